@@ -0,0 +1,27 @@
+#import bevy_sprite::mesh2d_view_bindings
+
+struct ScrollingMaterial {
+    // Min corner (xy) and size (zw) of the tile in the image, in UVs.
+    rect: vec4<f32>,
+    // Tiles per second.
+    velocity: vec2<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> material: ScrollingMaterial;
+@group(1) @binding(1)
+var texture: texture_2d<f32>;
+@group(1) @binding(2)
+var texture_sampler: sampler;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // Texture v runs downwards, so flip y to scroll the way the board does.
+    let offset = vec2<f32>(material.velocity.x, -material.velocity.y) * globals.time;
+    let uv = fract(in.uv - offset);
+    return textureSample(texture, texture_sampler, material.rect.xy + uv * material.rect.zw);
+}
@@ -0,0 +1,215 @@
+//! Board operations timed without the rendering stack, so they run anywhere.
+//! Save a baseline before a change with `cargo bench -- --save-baseline
+//! before` and compare after it with `cargo bench -- --baseline before`.
+
+use std::{collections::HashSet, hint::black_box};
+
+use bevy::{ecs::system::CommandQueue, prelude::*};
+use criterion::{criterion_group, criterion_main, Criterion};
+use map_test::{
+    fov,
+    pathfinding::{find_path, unit_cost},
+    procgen,
+    projection::GridProjection,
+    rebuild_board,
+    tile_names::TileRegistry,
+    vectors::{GridKind, GridRect, HexOrientation, Vector3Int, ORTHO_DIRECTIONS},
+    CurrentBoard, MapValidationReport, Scene, TILE_SIZE,
+};
+
+const SEED: u64 = 0x5eed;
+/// Cells along each side of the board built from a scene.
+const BOARD_SIZE: usize = 256;
+/// Cells along each side of the maze, odd so it has a wall all round.
+const MAZE_SIZE: usize = 129;
+/// Cells along each side of the square flooded, 10k in all.
+const FLOOD_SIZE: i32 = 100;
+/// Cells seen in every direction by the field of view benches.
+const FOV_RADIUS: i32 = 30;
+
+fn neighbours(v: Vector3Int) -> impl Iterator<Item = Vector3Int> {
+    ORTHO_DIRECTIONS.iter().map(move |dir| v + *dir)
+}
+
+/// Spawns `scene` into a fresh world, as loading a map does, sprites and
+/// all.
+fn build_board(scene: &Scene) -> (World, CurrentBoard) {
+    let mut world = World::new();
+    world.init_resource::<CurrentBoard>();
+    let mut queue = CommandQueue::default();
+    let mut current = CurrentBoard::default();
+    let mut report = MapValidationReport::default();
+    let mut commands = Commands::new(&mut queue, &world);
+    rebuild_board(
+        &mut commands,
+        scene,
+        GridKind::Square,
+        |_| Some((Handle::default(), 0)),
+        &TileRegistry::default(),
+        &GridProjection::default(),
+        &mut current,
+        &mut report,
+    );
+    queue.apply(&mut world);
+    // The tiles join the board in the world, the bounds straight away.
+    current.tiles = world.remove_resource::<CurrentBoard>().unwrap().tiles;
+    (world, current)
+}
+
+fn board_from_scene(c: &mut Criterion) {
+    let floor = vec![1; BOARD_SIZE * BOARD_SIZE];
+    let maze = procgen::maze(BOARD_SIZE, BOARD_SIZE, 2, SEED);
+    let scene: Scene = serde_json::from_value(serde_json::json!({
+        "width": BOARD_SIZE,
+        "layers": [floor, maze],
+    }))
+    .unwrap();
+
+    let (_, current) = build_board(&scene);
+    println!(
+        "{BOARD_SIZE}x{BOARD_SIZE} scene: {} tiles",
+        current.tiles.len()
+    );
+
+    c.bench_function("build board 256x256", |b| {
+        // Dropping the world afterwards is not part of loading a map.
+        b.iter_with_large_drop(|| build_board(&scene))
+    });
+}
+
+fn path_through_maze(c: &mut Criterion) {
+    let maze = procgen::maze(MAZE_SIZE, MAZE_SIZE, 1, SEED);
+    let open: HashSet<Vector3Int> = (maze.iter().enumerate())
+        .filter(|(_, i)| **i != 0)
+        .map(|(pos, _)| Vector3Int::new((pos % MAZE_SIZE) as i32, (pos / MAZE_SIZE) as i32, 0))
+        .collect();
+    let (from, goal) = (
+        Vector3Int::new(1, 1, 0),
+        Vector3Int::new(MAZE_SIZE as i32 - 2, MAZE_SIZE as i32 - 2, 0),
+    );
+    let search = || {
+        find_path(
+            from,
+            neighbours,
+            |v| v == goal,
+            |_, v| open.contains(&v),
+            unit_cost,
+            |_| 0.,
+            usize::MAX,
+        )
+    };
+
+    let steps = search().map(|path| path.cells.len());
+    println!("{MAZE_SIZE}x{MAZE_SIZE} maze: path of {steps:?} steps");
+
+    c.bench_function("path through maze 129x129", |b| {
+        b.iter(|| black_box(search()))
+    });
+}
+
+fn flood_fill(c: &mut Criterion) {
+    let rect = GridRect::new(0, 0, FLOOD_SIZE, FLOOD_SIZE);
+    let origin = Vector3Int::new(FLOOD_SIZE / 2, FLOOD_SIZE / 2, 0);
+    // With no goal the search visits every reachable cell, then gives up.
+    let flood = || {
+        find_path(
+            origin,
+            neighbours,
+            |_| false,
+            |_, v| rect.contains(v),
+            unit_cost,
+            |_| 0.,
+            usize::MAX,
+        )
+    };
+
+    assert!(flood().is_none());
+    c.bench_function("flood fill 10k cells", |b| b.iter(|| black_box(flood())));
+}
+
+fn field_of_view(c: &mut Criterion) {
+    // A maze around the origin, with its walls in sight all the way out.
+    let size = (2 * FOV_RADIUS + 3) as usize;
+    let maze = procgen::maze(size, size, 1, SEED);
+    let walls: HashSet<Vector3Int> = (maze.iter().enumerate())
+        .filter(|(_, i)| **i == 0)
+        .map(|(pos, _)| Vector3Int::new((pos % size) as i32, (pos / size) as i32, 0))
+        .collect();
+    let origin = Vector3Int::new(FOV_RADIUS + 1, FOV_RADIUS + 1, 0);
+    let blocks = |v: Vector3Int| walls.contains(&v);
+    // Each cell in range seen if a straight line to it is clear, as shots
+    // are.
+    let side = 2 * FOV_RADIUS + 1;
+    let range = GridRect::new(-FOV_RADIUS, -FOV_RADIUS, side, side);
+    let lines = || {
+        (range.cells(origin))
+            .filter(|v| {
+                let line = origin.line_to(*v);
+                (line.iter().skip(1).take(line.len().saturating_sub(2))).all(|v| !blocks(*v))
+            })
+            .count()
+    };
+    let shadowcasting = || fov::compute(origin, FOV_RADIUS as u32, blocks).len();
+    println!(
+        "radius {FOV_RADIUS} in a maze: {} cells seen by lines, {} by shadowcasting",
+        lines(),
+        shadowcasting()
+    );
+
+    let mut group = c.benchmark_group("field of view, radius 30");
+    group.bench_function("lines", |b| b.iter(|| black_box(lines())));
+    group.bench_function("shadowcasting", |b| b.iter(|| black_box(shadowcasting())));
+    group.finish();
+}
+
+fn grid_world_conversion(c: &mut Criterion) {
+    let cells: Vec<Vector3Int> = (GridRect::new(-128, -128, 256, 256))
+        .cells(Vector3Int::default())
+        .collect();
+    let projections = [
+        (
+            "orthogonal",
+            GridProjection::Orthogonal { pitch: TILE_SIZE },
+        ),
+        (
+            "isometric",
+            GridProjection::Isometric {
+                tile_width: 32.,
+                tile_height: 16.,
+            },
+        ),
+        (
+            "hex",
+            GridProjection::Hex {
+                orientation: HexOrientation::PointyTop,
+                pitch: TILE_SIZE,
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("grid to world and back, 64k cells");
+    for (name, projection) in projections {
+        let round_trip = || {
+            (cells.iter())
+                .filter(|v| projection.cell_at(projection.world(**v).truncate(), v.z) == **v)
+                .count()
+        };
+        println!(
+            "{name}: {} of {} cells round-trip",
+            round_trip(),
+            cells.len()
+        );
+        group.bench_function(name, |b| b.iter(|| black_box(round_trip())));
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    board_from_scene,
+    path_through_maze,
+    flood_fill,
+    field_of_view,
+    grid_world_conversion
+);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! A game built on the crate as another crate would, through the prelude
+//! alone: it plays the maps it is given with a rule of its own on top,
+//! where every tenth step a player takes costs them a point of health.
+//!
+//! `cargo run --example embed -- iso.json`
+//!
+//! It also stands as the check that the prelude is enough to build on, so
+//! anything it needs from elsewhere belongs in the prelude.
+
+use bevy::prelude::*;
+use map_test::prelude::*;
+
+const STEPS_PER_HIT: u32 = 10;
+
+/// Steps taken by each player since they were last worn down.
+#[derive(Default, Resource)]
+struct Fatigue {
+    steps: Vec<u32>,
+}
+
+struct FatiguePlugin;
+impl Plugin for FatiguePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Fatigue>()
+            .add_system(
+                wear_down
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(rest_on_load);
+    }
+}
+
+fn wear_down(
+    mut steps: EventReader<PlayerStepCompleted>,
+    players: Query<&Player>,
+    mut fatigue: ResMut<Fatigue>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for step in steps.iter() {
+        let Ok(player) = players.get(step.entity) else { continue };
+        if fatigue.steps.len() <= player.index {
+            fatigue.steps.resize(player.index + 1, 0);
+        }
+        let count = &mut fatigue.steps[player.index];
+        *count += 1;
+        if *count >= STEPS_PER_HIT {
+            *count = 0;
+            damage.send(DamageEvent {
+                entity: step.entity,
+                amount: 1,
+                element: Element::Physical,
+            });
+        }
+    }
+}
+
+/// Each map starts everyone fresh.
+fn rest_on_load(
+    mut loaded: EventReader<BoardLoadedEvent>,
+    board: Res<CurrentBoard>,
+    mut fatigue: ResMut<Fatigue>,
+) {
+    if loaded.iter().last().is_some() {
+        fatigue.steps.clear();
+        info!("Playing a board of {} tiles.", board.tiles.len());
+    }
+}
+
+fn main() {
+    let maps: Vec<String> = std::env::args().skip(1).collect();
+    let campaign = match maps.is_empty() {
+        true => Campaign::default(),
+        false => Campaign { maps, current: 0 },
+    };
+
+    App::new()
+        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .insert_resource(campaign)
+        .add_plugin(GamePlugin)
+        .add_plugin(FatiguePlugin)
+        .run();
+}
@@ -0,0 +1,101 @@
+//! Plays a generated N x N map and logs the average frame time over ten
+//! seconds, then exits.
+//!
+//! `cargo run --release --example stress -- 256 --headless`
+//!
+//! The map is written to `assets/stress.json`. Without a window or GPU
+//! (`--headless`) only the game's own systems are timed.
+
+use std::{fs, time::Duration};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use map_test::{procgen, AppState, Campaign, GamePlugin};
+
+const DEFAULT_SIZE: usize = 128;
+const MAP_NAME: &str = "stress.json";
+const SEED: u64 = 0x5eed;
+const MEASURE_TIME: Duration = Duration::from_secs(10);
+/// Grass everywhere, with cobble paths through it.
+const GROUND_TILE: usize = 1;
+const PATH_TILE: usize = 44;
+
+#[derive(Default)]
+struct FrameStats {
+    frames: u32,
+    elapsed: Duration,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let headless = args.iter().any(|arg| arg == "--headless");
+    let size = (args.iter())
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_SIZE);
+
+    let scene = serde_json::json!({
+        "width": size,
+        "layers": [
+            vec![GROUND_TILE; size * size],
+            procgen::maze(size, size, PATH_TILE, SEED),
+        ],
+    });
+    let path = format!("{}/assets/{MAP_NAME}", env!("CARGO_MANIFEST_DIR"));
+    fs::write(&path, scene.to_string()).expect("could not write the stress map");
+    println!("Wrote a {size}x{size} map to {path}.");
+
+    let plugins = DefaultPlugins.set(ImagePlugin::default_nearest());
+    let mut app = App::new();
+    if headless {
+        app.add_plugins(
+            plugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    wgpu_settings: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    },
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugin(ScheduleRunnerPlugin);
+    } else {
+        app.add_plugins(plugins);
+    }
+
+    app.insert_resource(Campaign {
+        maps: vec![MAP_NAME.to_string()],
+        current: 0,
+    })
+    .add_plugin(GamePlugin)
+    .add_system(measure_frames.in_set(OnUpdate(AppState::Game)))
+    .run();
+}
+
+fn measure_frames(time: Res<Time>, mut stats: Local<FrameStats>, mut exit: EventWriter<AppExit>) {
+    // The first frame in the game state includes spawning the board.
+    if stats.frames > 0 {
+        stats.elapsed += time.delta();
+    }
+    stats.frames += 1;
+
+    if stats.elapsed >= MEASURE_TIME {
+        let frames = stats.frames - 1;
+        info!(
+            "{} frames in {:.1}s: {:.2}ms per frame on average.",
+            frames,
+            stats.elapsed.as_secs_f32(),
+            stats.elapsed.as_secs_f32() * 1000. / frames as f32
+        );
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,287 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    elements::Element,
+    status::StatusKind,
+    storage::{platform_store, KeyValueStore},
+};
+
+/// Where settings are kept, as for save slots.
+pub const SETTINGS_DIR: &str = "settings";
+const ACCESSIBILITY_KEY: &str = "accessibility.ron";
+const MIN_UI_SCALE: f32 = 1.;
+const MAX_UI_SCALE: f32 = 2.;
+/// How far one press of the UI scale key grows the UI.
+const UI_SCALE_STEP: f32 = 0.25;
+
+/// The colour set every tint and UI colour is drawn from.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    #[default]
+    Normal,
+    /// Red and green are hard to tell apart.
+    Deuteranopia,
+    /// As deuteranopia, with reds also appearing dark.
+    Protanopia,
+    /// Blue and yellow are hard to tell apart.
+    Tritanopia,
+}
+
+impl Palette {
+    const ALL: [Palette; 4] = [
+        Palette::Normal,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+    ];
+
+    fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|p| *p == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// The accessibility section of the player's settings, kept between
+/// sessions. F6 cycles the palette and Shift+F6 the UI scale.
+#[derive(Resource, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accessibility {
+    pub palette: Palette,
+    /// Multiplies the size of every UI node and font, from 1 to 2.
+    pub ui_scale: f32,
+}
+
+impl Default for Accessibility {
+    fn default() -> Self {
+        Accessibility {
+            palette: Palette::Normal,
+            ui_scale: MIN_UI_SCALE,
+        }
+    }
+}
+
+/// What a colour is used for, looked up in the current palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PaletteColor {
+    /// Territory overlays, cycled through by team id.
+    Team(u8),
+    /// The editor's selection and other highlighted cells.
+    Highlight,
+    /// Tiles out of sight.
+    Fog,
+    HealthBar,
+    /// The health bar once below a third.
+    HealthLow,
+    DashReady,
+    DashCooling,
+    PathReachable,
+    PathUnreachable,
+    /// Steps of a reachable path past the points left this turn.
+    PathOverBudget,
+    Blast,
+    /// The dots of the breadcrumb trail, at full brightness.
+    Trail,
+    /// The most visited tiles of the heatmap, at full strength.
+    Heat,
+    /// Cells any enemy could reach or strike next turn.
+    Threat,
+    /// Cells the hovered enemy could reach or strike next turn.
+    ThreatFocus,
+    Status(StatusKind),
+    /// Hits of each element, as their target flashes. Physical hits are in
+    /// the low health colour.
+    Hit(Element),
+}
+
+/// The colours of the chosen palette. Systems drawing with these re-tint
+/// whatever they drew when it changes.
+#[derive(Resource, Default)]
+pub struct PaletteLookup {
+    palette: Palette,
+}
+
+impl PaletteLookup {
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn color(&self, key: PaletteColor) -> Color {
+        use Palette::*;
+        use PaletteColor::*;
+
+        // Red-green safe colours, from Okabe and Ito's set.
+        const BLUE: Color = Color::rgb(0., 0.45, 0.7);
+        const ORANGE: Color = Color::rgb(0.9, 0.62, 0.);
+        const SKY: Color = Color::rgb(0.34, 0.71, 0.91);
+        const YELLOW: Color = Color::rgb(0.94, 0.89, 0.26);
+        const VERMILLION: Color = Color::rgb(0.84, 0.37, 0.);
+        const TEAL: Color = Color::rgb(0., 0.62, 0.45);
+        const PURPLE: Color = Color::rgb(0.8, 0.47, 0.65);
+        const GREY: Color = Color::rgb(0.85, 0.85, 0.85);
+
+        match (key, self.palette) {
+            (Team(team), palette) => {
+                let teams = match palette {
+                    Normal => [
+                        Color::rgb(0.2, 0.4, 1.),
+                        Color::rgb(1., 0.25, 0.2),
+                        Color::rgb(0.2, 0.9, 0.3),
+                        Color::rgb(1., 0.85, 0.1),
+                    ],
+                    Deuteranopia => [BLUE, VERMILLION, SKY, YELLOW],
+                    Protanopia => [BLUE, ORANGE, SKY, YELLOW],
+                    Tritanopia => [VERMILLION, TEAL, PURPLE, GREY],
+                };
+                // Low alpha keeps the tiles readable under them.
+                teams[team as usize % teams.len()].with_a(0.35)
+            }
+            (Highlight, Tritanopia) => PURPLE.with_a(0.35),
+            (Highlight, _) => Color::rgba(0.3, 0.6, 1., 0.35),
+            (Fog, _) => Color::rgb(0.45, 0.45, 0.5),
+            (HealthBar, Normal) => Color::rgb(0.2, 0.85, 0.3),
+            (HealthBar, Deuteranopia | Protanopia) => SKY,
+            (HealthBar, Tritanopia) => TEAL,
+            (HealthLow, Normal) => Color::rgb(0.9, 0.2, 0.2),
+            (HealthLow, Deuteranopia | Protanopia) => ORANGE,
+            (HealthLow, Tritanopia) => VERMILLION,
+            (DashReady, Tritanopia) => PURPLE,
+            (DashReady, _) => Color::rgb(1., 0.85, 0.3),
+            (DashCooling, _) => Color::rgba(1., 1., 1., 0.3),
+            (PathReachable, _) => Color::rgba(1., 1., 1., 0.6),
+            (PathUnreachable, Normal | Tritanopia) => Color::rgba(1., 0.2, 0.2, 0.6),
+            (PathUnreachable, Deuteranopia) => VERMILLION.with_a(0.6),
+            (PathUnreachable, Protanopia) => ORANGE.with_a(0.6),
+            (PathOverBudget, Normal) => Color::rgba(1., 0.85, 0.3, 0.6),
+            (PathOverBudget, Deuteranopia | Protanopia) => SKY.with_a(0.6),
+            (PathOverBudget, Tritanopia) => PURPLE.with_a(0.6),
+            (Blast, Tritanopia) => VERMILLION,
+            (Blast, _) => Color::rgb(1., 0.6, 0.2),
+            (Trail, Tritanopia) => SKY,
+            (Trail, _) => Color::rgb(1., 0.95, 0.6),
+            (Heat, Normal) => Color::rgba(1., 0.15, 0.1, 0.6),
+            (Heat, Deuteranopia | Protanopia) => ORANGE.with_a(0.6),
+            (Heat, Tritanopia) => VERMILLION.with_a(0.6),
+            (Threat, Normal) => Color::rgba(1., 0.2, 0.2, 0.3),
+            (Threat, Deuteranopia | Tritanopia) => VERMILLION.with_a(0.3),
+            (Threat, Protanopia) => ORANGE.with_a(0.3),
+            (ThreatFocus, Normal) => Color::rgba(0.8, 0.3, 1., 0.4),
+            (ThreatFocus, Deuteranopia | Protanopia) => PURPLE.with_a(0.4),
+            (ThreatFocus, Tritanopia) => TEAL.with_a(0.4),
+            (Status(kind), Normal) => match kind {
+                StatusKind::Poison => Color::rgb(0.4, 0.85, 0.2),
+                StatusKind::Slow => Color::rgb(0.3, 0.5, 1.),
+                StatusKind::Haste => Color::rgb(1., 0.6, 0.1),
+                StatusKind::Rooted => Color::rgb(0.55, 0.35, 0.2),
+                StatusKind::Levitate => Color::rgb(0.8, 0.9, 1.),
+                StatusKind::Warded => Color::rgb(0.95, 0.85, 0.4),
+            },
+            (Status(kind), Deuteranopia | Protanopia) => match kind {
+                StatusKind::Poison => PURPLE,
+                StatusKind::Slow => BLUE,
+                StatusKind::Haste => YELLOW,
+                StatusKind::Rooted => GREY,
+                StatusKind::Levitate => SKY,
+                StatusKind::Warded => ORANGE,
+            },
+            (Status(kind), Tritanopia) => match kind {
+                StatusKind::Poison => TEAL,
+                StatusKind::Slow => GREY,
+                StatusKind::Haste => VERMILLION,
+                StatusKind::Rooted => PURPLE,
+                StatusKind::Levitate => SKY,
+                StatusKind::Warded => YELLOW,
+            },
+            (Hit(element), Normal) => match element {
+                Element::Physical => Color::rgb(0.9, 0.2, 0.2),
+                Element::Fire => Color::rgb(1., 0.55, 0.1),
+                Element::Ice => Color::rgb(0.45, 0.8, 1.),
+                Element::Poison => Color::rgb(0.4, 0.85, 0.2),
+            },
+            (Hit(element), Deuteranopia | Protanopia) => match element {
+                Element::Physical => ORANGE,
+                Element::Fire => YELLOW,
+                Element::Ice => SKY,
+                Element::Poison => PURPLE,
+            },
+            (Hit(element), Tritanopia) => match element {
+                Element::Physical => VERMILLION,
+                Element::Fire => YELLOW,
+                Element::Ice => GREY,
+                Element::Poison => TEAL,
+            },
+        }
+    }
+}
+
+pub struct AccessibilityPlugin;
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings())
+            .init_resource::<PaletteLookup>()
+            .add_system(cycle_settings)
+            .add_system(apply_settings.after(cycle_settings));
+    }
+}
+
+fn load_settings() -> Accessibility {
+    let text = match platform_store(SETTINGS_DIR).get(ACCESSIBILITY_KEY) {
+        Ok(Some(text)) => text,
+        Ok(None) => return Accessibility::default(),
+        Err(e) => {
+            warn!("Could not read accessibility settings: {}", e);
+            return Accessibility::default();
+        }
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring malformed accessibility settings: {}", e);
+        Accessibility::default()
+    })
+}
+
+fn cycle_settings(keys: Res<Input<KeyCode>>, mut settings: ResMut<Accessibility>) {
+    if !keys.just_pressed(KeyCode::F6) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+        let scale = settings.ui_scale + UI_SCALE_STEP;
+        settings.ui_scale = if scale > MAX_UI_SCALE + f32::EPSILON {
+            MIN_UI_SCALE
+        } else {
+            scale
+        };
+        info!("UI scale {:.2}.", settings.ui_scale);
+    } else {
+        settings.palette = settings.palette.next();
+        info!("Palette {:?}.", settings.palette);
+    }
+}
+
+/// Restyles the UI and switches palettes as the settings change, and keeps
+/// them for the next session.
+fn apply_settings(
+    settings: Res<Accessibility>,
+    mut lookup: ResMut<PaletteLookup>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let scale = settings.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE) as f64;
+    if ui_scale.scale != scale {
+        ui_scale.scale = scale;
+    }
+    if lookup.palette != settings.palette {
+        lookup.palette = settings.palette;
+    }
+    if settings.is_added() {
+        return;
+    }
+
+    let text = ron::ser::to_string_pretty(&*settings, ron::ser::PrettyConfig::default())
+        .expect("settings always serialize");
+    if let Err(e) = platform_store(SETTINGS_DIR).set(ACCESSIBILITY_KEY, &text) {
+        warn!("Could not save accessibility settings: {}", e);
+    }
+}
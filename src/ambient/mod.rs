@@ -0,0 +1,294 @@
+//! Creatures that liven up a map without taking part in it, such as
+//! butterflies over grass or rats in a cellar. A map lists them in its
+//! meta's `ambient_spawns`, and they come and go around the camera's view.
+//! They are only sprites: they take up no cell, block nothing, and have no
+//! `Position`, so saves, the world hash and everything else that reads the
+//! board pass them by.
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    camera::view_rect,
+    editor::EditorState,
+    layer_of, layer_z,
+    map_meta::MapMeta,
+    materials::{sprite_shape, TileMetadataRegistry},
+    player::Player,
+    prefabs::PrefabRegistry,
+    projection::GridProjection,
+    render_layers::{z_index, RenderLayerSlot},
+    rng::GameRng,
+    simulation::SimulationSet,
+    terrain::{TerrainKind, TerrainRegistry},
+    turns::TurnBased,
+    vectors::{GridKind, Vector3Int},
+    BoardLoadedEvent, CurrentBoard, GraphicsAssets, Position, Tile,
+};
+
+/// Ambient creatures there can be at once, across every entry.
+const MAX_AMBIENT: usize = 48;
+/// Cells tried for somewhere to spawn one before giving up until the next
+/// tick.
+const SPAWN_ATTEMPTS: usize = 8;
+/// Cells past the edge of the view at which one is despawned.
+const DESPAWN_MARGIN: f32 = 4.;
+/// Seconds between steps while wandering, at most.
+const WANDER_INTERVAL: f32 = 1.5;
+/// Cells a second one drifts toward its next cell as drawn.
+const DRIFT_SPEED: f32 = 2.;
+
+/// A kind of ambient creature a map spawns, as its meta's `ambient_spawns`
+/// lists them.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AmbientSpawnConfig {
+    /// The prefab whose sprite it shows. Nothing else of the prefab applies.
+    pub prefab: String,
+    /// Terrain it appears and wanders on.
+    #[serde(default)]
+    pub terrain: Vec<TerrainKind>,
+    /// Tiles it appears and wanders on too, by name. With neither these nor
+    /// `terrain`, any ground will do.
+    #[serde(default)]
+    pub tiles: Vec<String>,
+    /// How many to keep in view at once, however far the view is zoomed.
+    #[serde(default = "default_density")]
+    pub density: f32,
+    /// Seconds each lives, picked between the two.
+    #[serde(default = "default_lifetime")]
+    pub lifetime_s: [f32; 2],
+}
+
+fn default_density() -> f32 {
+    3.
+}
+
+fn default_lifetime() -> [f32; 2] {
+    [10., 30.]
+}
+
+/// An ambient creature, wandering its cell to cell until its time is up.
+#[derive(Component)]
+pub struct Ambient {
+    /// Which of the map's `ambient_spawns` it is.
+    entry: usize,
+    cell: Vector3Int,
+    lifetime: Timer,
+    wander: Timer,
+}
+
+/// Rolls for ambient creatures alone, so that however many come and go
+/// the gameplay `GameRng` draws the same numbers. Seeded afresh from its
+/// seed on each map.
+#[derive(Resource, Default)]
+struct AmbientRng(GameRng);
+
+impl AmbientRng {
+    /// A number between `min` and `max`.
+    fn between(&mut self, min: f32, max: f32) -> f32 {
+        let t = (self.0.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min).max(0.) * t
+    }
+}
+
+pub struct AmbientPlugin;
+impl Plugin for AmbientPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AmbientRng>()
+            .add_system(clear_ambient)
+            .add_systems(
+                (age_ambient, spawn_ambient.after(age_ambient))
+                    .distributive_run_if(ambient_running)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(draw_ambient);
+    }
+}
+
+/// Ambient creatures hold still in turn-based mode and in the editor, as
+/// they do whenever the simulation is paused.
+fn ambient_running(editor: Res<EditorState>, turn_based: Res<TurnBased>) -> bool {
+    !editor.active && !turn_based.0
+}
+
+/// Whether `entry`'s creatures may stand at `v`: on ground of its terrain
+/// or tiles, or on any ground if it names neither.
+fn suits(
+    entry: &AmbientSpawnConfig,
+    v: Vector3Int,
+    current: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    terrain: &TerrainRegistry,
+    assets: &GraphicsAssets,
+) -> bool {
+    let Some(tile) = current.floor(v).and_then(|e| tiles.get(e).ok()) else { return false };
+    if entry.terrain.is_empty() && entry.tiles.is_empty() {
+        return true;
+    }
+    let kind = terrain.kind(tile.i);
+    kind.is_some_and(|kind| entry.terrain.contains(&kind))
+        || (entry.tiles.iter()).any(|name| assets.tile_names.index(name) == Some(tile.i))
+}
+
+/// A map's creatures are gone when it is left, and its rolls start again
+/// from the seed.
+fn clear_ambient(
+    mut commands: Commands,
+    mut loaded: EventReader<BoardLoadedEvent>,
+    ambient: Query<Entity, With<Ambient>>,
+    game_rng: Res<GameRng>,
+    mut rng: ResMut<AmbientRng>,
+) {
+    if loaded.iter().count() == 0 {
+        return;
+    }
+    for entity in ambient.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    rng.0 = GameRng::new(game_rng.seed());
+}
+
+/// Spawns creatures on suitable cells in view until each entry has its
+/// density there, up to `MAX_AMBIENT` in all.
+#[allow(clippy::too_many_arguments)]
+fn spawn_ambient(
+    mut commands: Commands,
+    meta: Res<MapMeta>,
+    prefabs: Res<PrefabRegistry>,
+    camera: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    players: Query<&Position, With<Player>>,
+    ambient: Query<(&Ambient, &Transform)>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    terrain: Res<TerrainRegistry>,
+    assets: Res<GraphicsAssets>,
+    metadata: Res<TileMetadataRegistry>,
+    grid: Res<GridProjection>,
+    mut rng: ResMut<AmbientRng>,
+) {
+    let Ok((transform, projection)) = camera.get_single() else { return };
+    let Some(player) = players.iter().next() else { return };
+    let view = view_rect(transform, projection);
+    let mut total = ambient.iter().count();
+    // The cells under the view, as a box of cells around its corners.
+    let corners = [
+        view.min,
+        Vec2::new(view.max.x, view.min.y),
+        view.max,
+        Vec2::new(view.min.x, view.max.y),
+    ];
+    let (min, max) = corners.iter().map(|c| grid.world_to_cell(*c)).fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), c| (min.min(c), max.max(c)),
+    );
+    let (min, max) = (min.floor().as_ivec2(), max.ceil().as_ivec2());
+    let span = (max - min + 1).max(IVec2::ONE);
+    let z = layer_z(layer_of(player.v.z), z_index(RenderLayerSlot::Actors, 0));
+
+    for (i, entry) in meta.ambient_spawns.iter().enumerate() {
+        let Some(sprite) = prefabs.get(&entry.prefab).and_then(|p| p.sprite) else { continue };
+        let shown = (ambient.iter())
+            .filter(|(a, t)| a.entry == i && view.contains(t.translation.truncate()))
+            .count();
+        let wanted = entry.density.round().max(0.) as usize;
+        for _ in shown..wanted {
+            if total >= MAX_AMBIENT {
+                return;
+            }
+            let cell = (0..SPAWN_ATTEMPTS).find_map(|_| {
+                let x = min.x + rng.0.below(span.x as usize) as i32;
+                let y = min.y + rng.0.below(span.y as usize) as i32;
+                let v = Vector3Int::new(x, y, z);
+                let seen = view.contains(grid.world(v).truncate());
+                (seen && suits(entry, v, &current, &tiles, &terrain, &assets)).then_some(v)
+            });
+            let Some(cell) = cell else { break };
+            let [shortest, longest] = entry.lifetime_s;
+            let lifetime = rng.between(shortest, longest);
+            let wander = rng.between(0., WANDER_INTERVAL);
+            let mut atlas_sprite = TextureAtlasSprite::new(sprite);
+            let (size, anchor) = sprite_shape(&metadata.0, sprite, grid.sprite_size());
+            atlas_sprite.custom_size = Some(size);
+            atlas_sprite.anchor = anchor;
+            commands.spawn((
+                Ambient {
+                    entry: i,
+                    cell,
+                    lifetime: Timer::from_seconds(lifetime, TimerMode::Once),
+                    wander: Timer::from_seconds(wander, TimerMode::Once),
+                },
+                SpriteSheetBundle {
+                    sprite: atlas_sprite,
+                    texture_atlas: assets.sprite_texture.clone(),
+                    transform: Transform::from_translation(grid.world(cell)),
+                    ..default()
+                },
+            ));
+            total += 1;
+        }
+    }
+}
+
+/// Steps creatures to a suitable cell next to theirs now and then, and
+/// despawns them once their time is up or the view has left them behind.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn age_ambient(
+    mut commands: Commands,
+    mut ambient: Query<(Entity, &mut Ambient, &Transform)>,
+    camera: Query<(&Transform, &OrthographicProjection), (With<Camera2d>, Without<Ambient>)>,
+    meta: Res<MapMeta>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    terrain: Res<TerrainRegistry>,
+    assets: Res<GraphicsAssets>,
+    grid: Res<GridProjection>,
+    kind: Res<GridKind>,
+    fixed: Res<FixedTime>,
+    mut rng: ResMut<AmbientRng>,
+) {
+    let Ok((transform, projection)) = camera.get_single() else { return };
+    let margin = grid.tile_size() * DESPAWN_MARGIN;
+    let view = view_rect(transform, projection);
+    let kept = Rect::from_corners(view.min - margin, view.max + margin);
+    for (entity, mut creature, transform) in ambient.iter_mut() {
+        let Some(entry) = meta.ambient_spawns.get(creature.entry) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        creature.lifetime.tick(fixed.period);
+        if creature.lifetime.finished() || !kept.contains(transform.translation.truncate()) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        creature.wander.tick(fixed.period);
+        if !creature.wander.finished() {
+            continue;
+        }
+        let next = rng.between(0., WANDER_INTERVAL);
+        creature.wander = Timer::from_seconds(next, TimerMode::Once);
+        let cells: Vec<Vector3Int> = (current.neighbours(creature.cell, *kind))
+            .filter(|v| suits(entry, *v, &current, &tiles, &terrain, &assets))
+            .collect();
+        if !cells.is_empty() {
+            creature.cell = cells[rng.0.below(cells.len())];
+        }
+    }
+}
+
+/// Drifts creatures toward their cell, rather than snapping as actors do.
+fn draw_ambient(
+    mut ambient: Query<(&Ambient, &mut Transform)>,
+    grid: Res<GridProjection>,
+    time: Res<Time>,
+) {
+    let step = (grid.tile_size() * DRIFT_SPEED * time.delta_seconds()).max_element();
+    for (creature, mut transform) in ambient.iter_mut() {
+        let target = grid.world(creature.cell);
+        let offset = target - transform.translation;
+        if offset == Vec3::ZERO {
+            continue;
+        }
+        transform.translation += offset.clamp_length_max(step);
+    }
+}
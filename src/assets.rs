@@ -0,0 +1,104 @@
+use bevy::{asset::HandleId, prelude::*};
+
+use crate::{
+    streaming::{WorldManifest, WORLD_EXTENSION},
+    Scene, SceneHandle,
+};
+
+/// Assets for the whole session, such as the font and the sprite sheet.
+pub const CORE_GROUP: &str = "core";
+/// Campaign entries ending in this name a manifest rather than a scene.
+pub const MANIFEST_EXTENSION: &str = ".assets.json";
+
+/// The files a map needs, loaded as its asset group before it is played.
+#[derive(serde::Deserialize, bevy::reflect::TypeUuid, Debug)]
+#[uuid = "6c3e9a0e-52b1-4d8e-9c47-0d2f7a1b3e55"]
+pub struct AssetManifest {
+    /// The map's scene.
+    pub scene: String,
+    #[serde(default)]
+    pub textures: Vec<String>,
+    #[serde(default)]
+    pub audio: Vec<String>,
+    /// Other data files the map reads.
+    #[serde(default)]
+    pub data: Vec<String>,
+}
+
+impl AssetManifest {
+    /// Every file listed besides the scene.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        (self.textures.iter().chain(&self.audio).chain(&self.data)).map(|f| f.as_str())
+    }
+}
+
+/// Assets loaded together and released together.
+pub struct AssetGroup {
+    pub name: String,
+    pub handles: Vec<HandleUntyped>,
+}
+
+impl AssetGroup {
+    pub fn new(name: &str) -> Self {
+        AssetGroup {
+            name: name.to_string(),
+            handles: Vec::new(),
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = HandleId> + '_ {
+        self.handles.iter().map(|h| h.id())
+    }
+}
+
+/// The loaded asset groups, holding strong handles to their assets, and
+/// the groups still loading.
+#[derive(Default, Resource)]
+pub struct AssetGroups {
+    pub loaded: Vec<AssetGroup>,
+    /// Waited on while the state is `Loading`.
+    pub pending: Vec<AssetGroup>,
+    /// The pending map's manifest, until the files it lists are queued.
+    pub manifest: Option<Handle<AssetManifest>>,
+    /// The pending map's world manifest, until its start chunk is queued.
+    pub world: Option<Handle<WorldManifest>>,
+}
+
+impl AssetGroups {
+    /// Starts loading the group of map `entry`, a scene, a manifest naming
+    /// one or a chunked world, and points `scene` at it once known.
+    pub fn queue_map(&mut self, entry: &str, server: &AssetServer, scene: &mut SceneHandle) {
+        let mut group = AssetGroup::new(entry);
+        if entry.ends_with(MANIFEST_EXTENSION) {
+            let manifest: Handle<AssetManifest> = server.load(entry);
+            group.handles.push(manifest.clone_untyped());
+            self.manifest = Some(manifest);
+            scene.0 = Handle::default();
+        } else if entry.ends_with(WORLD_EXTENSION) {
+            let world: Handle<WorldManifest> = server.load(entry);
+            group.handles.push(world.clone_untyped());
+            self.world = Some(world);
+            scene.0 = Handle::default();
+        } else {
+            let handle: Handle<Scene> = server.load(entry);
+            group.handles.push(handle.clone_untyped());
+            scene.0 = handle;
+        }
+        self.pending.push(group);
+    }
+
+    /// The pending group of the map being loaded.
+    pub fn pending_map(&mut self) -> Option<&mut AssetGroup> {
+        self.pending.iter_mut().find(|g| g.name != CORE_GROUP)
+    }
+
+    /// Moves the pending groups to the loaded ones. A new map group
+    /// replaces the previous map's, releasing whatever only it held.
+    pub fn finish(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        if pending.iter().any(|g| g.name != CORE_GROUP) {
+            self.loaded.retain(|g| g.name == CORE_GROUP);
+        }
+        self.loaded.extend(pending);
+    }
+}
@@ -0,0 +1,630 @@
+//! One-call edits to the board for systems outside the map loader, such as
+//! the editor, undo, or a game embedding `GamePlugin`.
+//!
+//! The board is spread over several resources: `CurrentBoard` maps cells to
+//! tile entities, `Occupancy` maps cells to the actors standing on them, and
+//! `CollisionMap` holds what walls, pits, doors and the like stop. Each
+//! `BoardCommands` method keeps all of them in step, so that:
+//!
+//! - every tile entity is in `CurrentBoard::tiles` under its cell, wrapped
+//!   onto the board, and every entry there is a tile entity;
+//! - `CurrentBoard::bounds` covers every tile;
+//! - `Occupancy` holds the cells of every actor spawned, as of the call
+//!   rather than the next tick, so a second spawn onto the same cell fails;
+//! - `CollisionMap` holds what the tiles of every cell set or removed
+//!   bring, also as of the call;
+//! - a `TileChangedEvent` is sent for every cell whose tile changes.
+//!
+//! Entity spawns and despawns still go through `Commands`, so they land
+//! when the system's commands are applied. Reads through `BoardCommands`
+//! already see its own earlier edits.
+//!
+//! `BoardQuery` is the read-only side, for finding free cells to drop,
+//! spawn or move things onto.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use smallvec::SmallVec;
+
+use crate::{
+    collision::{
+        covered_cells, footprint_fits, footprint_step_cost, refresh_layer, step_allowed,
+        CollisionMap, Footprint, Occupancy,
+    },
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    player::Player,
+    prefabs::PrefabRegistry,
+    rng::GameRng,
+    terrain::{Capabilities, TerrainRegistry},
+    vectors::{GridKind, GridRect, Vector3Int},
+    CurrentBoard, Position, Tile,
+};
+
+/// Sent whenever a cell's tile is set, replaced or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileChangedEvent {
+    pub position: Vector3Int,
+    /// Atlas indices before and after, `None` where there is no tile.
+    pub before: Option<usize>,
+    pub after: Option<usize>,
+}
+
+/// Asks for the tile at `position` to be set to `index`, or removed for
+/// `None`, by systems that cannot hold `BoardCommands` themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SetTileEvent {
+    pub position: Vector3Int,
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BoardError {
+    UnknownPrefab(String),
+    /// Something already stands on, or walls off, one of the cells.
+    Blocked(Vector3Int),
+    /// The tiles given for a region do not fill it.
+    RegionSize {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardError::UnknownPrefab(name) => write!(f, "unknown prefab `{}`", name),
+            BoardError::Blocked(v) => write!(f, "no room at {:?}", v),
+            BoardError::RegionSize { expected, found } => {
+                write!(
+                    f,
+                    "region takes {} tiles but {} were given",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+pub struct BoardCommands<'w, 's> {
+    commands: Commands<'w, 's>,
+    current: ResMut<'w, CurrentBoard>,
+    collision: ResMut<'w, CollisionMap>,
+    occupancy: ResMut<'w, Occupancy>,
+    prefabs: Res<'w, PrefabRegistry>,
+    metadata: Res<'w, TileMetadataRegistry>,
+    terrain: Res<'w, TerrainRegistry>,
+    tiles: Query<'w, 's, &'static mut Tile>,
+    /// Everything but tiles and players.
+    objects: Query<'w, 's, Entity, (With<Position>, Without<Tile>, Without<Player>)>,
+    changed: EventWriter<'w, TileChangedEvent>,
+    /// Tiles spawned but not yet applied, which the tile query cannot see.
+    spawned: Local<'s, HashMap<Entity, usize>>,
+    /// Actors spawned but not yet applied, which the object query cannot
+    /// see.
+    actors: Local<'s, Vec<Entity>>,
+}
+
+impl<'w, 's> BoardCommands<'w, 's> {
+    pub fn current(&self) -> &CurrentBoard {
+        &self.current
+    }
+
+    /// The atlas index of the tile at `v`, wrapped onto the board.
+    pub fn tile(&self, v: Vector3Int) -> Option<usize> {
+        let entity = *self.current.tiles.get(&self.current.wrap(v))?;
+        match self.tiles.get(entity) {
+            Ok(tile) => Some(tile.i),
+            Err(_) => self.spawned.get(&entity).copied(),
+        }
+    }
+
+    /// Brings the collision of `v`'s layer in step with its tiles.
+    fn refresh_collision(&mut self, v: Vector3Int) {
+        let (tiles, spawned) = (&self.tiles, &self.spawned);
+        let index = |entity| match tiles.get(entity) {
+            Ok(tile) => Some(tile.i),
+            Err(_) => spawned.get(&entity).copied(),
+        };
+        refresh_layer(
+            Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), 0)),
+            index,
+            &self.current,
+            &self.metadata,
+            &self.terrain,
+            &mut self.collision,
+        );
+    }
+
+    /// Points `v` at atlas `index`, spawning a tile where there was none
+    /// and growing the board to cover it.
+    pub fn set_tile(&mut self, v: Vector3Int, index: usize) {
+        let v = self.current.wrap(v);
+        let before = self.tile(v);
+        if before == Some(index) {
+            return;
+        }
+        match self.current.tiles.get(&v).copied() {
+            // The scene renderer redraws tiles whose index changed.
+            Some(entity) => match self.tiles.get_mut(entity) {
+                Ok(mut tile) => tile.i = index,
+                Err(_) => {
+                    self.commands.entity(entity).insert(Tile { i: index });
+                    self.spawned.insert(entity, index);
+                }
+            },
+            None => {
+                // Those applied since are visible to the query again.
+                let tiles = &self.tiles;
+                self.spawned.retain(|entity, _| tiles.get(*entity).is_err());
+                let entity = self
+                    .commands
+                    .spawn((Position { v }, Tile { i: index }))
+                    .id();
+                self.current.tiles.insert(v, entity);
+                self.spawned.insert(entity, index);
+            }
+        }
+        if !self.current.bounds.rect.contains(v) {
+            let rect = self
+                .current
+                .bounds
+                .rect
+                .union(GridRect::new(v.x, v.y, 1, 1));
+            self.current.bounds.rect = rect;
+        }
+        self.refresh_collision(v);
+        self.changed.send(TileChangedEvent {
+            position: v,
+            before,
+            after: Some(index),
+        });
+    }
+
+    /// Despawns the tile at `v`, giving the index it had.
+    pub fn remove_tile(&mut self, v: Vector3Int) -> Option<usize> {
+        let v = self.current.wrap(v);
+        let before = self.tile(v)?;
+        if let Some(entity) = self.current.tiles.remove(&v) {
+            self.spawned.remove(&entity);
+            self.commands.entity(entity).despawn_recursive();
+        }
+        self.refresh_collision(v);
+        self.changed.send(TileChangedEvent {
+            position: v,
+            before: Some(before),
+            after: None,
+        });
+        Some(before)
+    }
+
+    /// Sets `v` to `index`, or removes its tile for `None`.
+    pub fn paint(&mut self, v: Vector3Int, index: Option<usize>) {
+        match index {
+            Some(index) => self.set_tile(v, index),
+            None => {
+                self.remove_tile(v);
+            }
+        }
+    }
+
+    /// Walls off `v`, as a closed door does.
+    pub fn block(&mut self, v: Vector3Int) {
+        self.collision.block(v);
+    }
+
+    /// Spawns prefab `name` at `v`, if every cell it covers has ground and
+    /// is free.
+    pub fn spawn_actor(&mut self, name: &str, v: Vector3Int) -> Result<Entity, BoardError> {
+        let Some(prefab) = self.prefabs.get(name) else {
+            return Err(BoardError::UnknownPrefab(name.to_string()));
+        };
+        let v = self.current.wrap(v);
+        let footprint = prefab.footprint();
+        let fits = footprint_fits(
+            Entity::PLACEHOLDER,
+            v,
+            footprint.as_ref(),
+            prefab.capabilities,
+            &self.current,
+            &self.collision,
+            &self.occupancy,
+        );
+        if prefab.is_occupier() && !fits {
+            return Err(BoardError::Blocked(v));
+        }
+
+        let mut entity = self.commands.spawn_empty();
+        prefab.insert(&mut entity, v);
+        let entity = entity.id();
+        // Those applied since are visible to the object query again.
+        let objects = &self.objects;
+        self.actors.retain(|actor| objects.get(*actor).is_err());
+        self.actors.push(entity);
+        if prefab.is_occupier() {
+            let cells = covered_cells(v, footprint.as_ref());
+            let cells: Vec<Vector3Int> = cells.into_iter().map(|c| self.current.wrap(c)).collect();
+            self.occupancy.insert(entity, cells);
+        }
+        Ok(entity)
+    }
+
+    /// Despawns every tile, object and actor other than players, keeping
+    /// the board's size. Actors spawned through these commands are despawned
+    /// too, even before they are applied.
+    pub fn clear(&mut self) {
+        let tiles: Vec<Vector3Int> = self.current.tiles.keys().copied().collect();
+        for v in tiles {
+            self.remove_tile(v);
+        }
+        let pending = (self.actors.drain(..)).filter(|actor| self.objects.get(*actor).is_err());
+        for entity in self.objects.iter().chain(pending) {
+            self.occupancy.remove(entity);
+            if let Some(entity) = self.commands.get_entity(entity) {
+                entity.despawn_recursive();
+            }
+        }
+        self.collision.clear();
+    }
+
+    /// Replaces the tiles of `rect` on layer `z` with `tiles`, given row by
+    /// row from the bottom-left cell, `None` for no tile. Gives back what
+    /// was there in the same order, so swapping that back undoes it.
+    pub fn swap_region(
+        &mut self,
+        rect: GridRect,
+        z: i32,
+        tiles: &[Option<usize>],
+    ) -> Result<Vec<Option<usize>>, BoardError> {
+        let expected = (rect.width.max(0) * rect.height.max(0)) as usize;
+        if tiles.len() != expected {
+            return Err(BoardError::RegionSize {
+                expected,
+                found: tiles.len(),
+            });
+        }
+        let cells: Vec<Vector3Int> = rect.cells(Vector3Int::new(0, 0, z)).collect();
+        let before = cells.iter().map(|v| self.tile(*v)).collect();
+        for (v, index) in cells.into_iter().zip(tiles) {
+            self.paint(v, *index);
+        }
+        Ok(before)
+    }
+}
+
+/// Carries out `SetTileEvent`s.
+pub(crate) fn set_tiles(mut events: EventReader<SetTileEvent>, mut board: BoardCommands) {
+    for event in events.iter() {
+        board.paint(event.position, event.index);
+    }
+}
+
+/// Where things can go. A cell is standable when it has ground, is not
+/// walled off, and no occupier stands on it. What counts as walled off
+/// depends on what the one standing there can do: water stops walkers but
+/// not swimmers, and fliers cross pits.
+#[derive(SystemParam)]
+pub struct BoardQuery<'w> {
+    current: Res<'w, CurrentBoard>,
+    collision: Res<'w, CollisionMap>,
+    occupancy: Res<'w, Occupancy>,
+    grid: Res<'w, GridKind>,
+}
+
+impl<'w> BoardQuery<'w> {
+    pub fn current(&self) -> &CurrentBoard {
+        &self.current
+    }
+
+    /// Whether `v` is standable by anything that walks.
+    pub fn is_standable(&self, v: Vector3Int) -> bool {
+        self.is_standable_by(v, None, Capabilities::NONE)
+    }
+
+    /// As `is_standable`, but for an actor able to do `capabilities`, with
+    /// `entity` free to stand where it already does.
+    pub fn is_standable_by(
+        &self,
+        v: Vector3Int,
+        entity: Option<Entity>,
+        capabilities: Capabilities,
+    ) -> bool {
+        let v = self.current.wrap(v);
+        self.current.has_ground(v)
+            && !self.collision.is_blocked_for(v, capabilities)
+            && self.occupancy.get(v).is_none_or(|e| Some(e) == entity)
+    }
+
+    /// Whether `entity`, able to do `capabilities`, would fit with its
+    /// anchor at `v`, over every cell of its footprint.
+    pub fn fits(
+        &self,
+        entity: Entity,
+        v: Vector3Int,
+        footprint: Option<&Footprint>,
+        capabilities: Capabilities,
+    ) -> bool {
+        footprint_fits(
+            entity,
+            v,
+            footprint,
+            capabilities,
+            &self.current,
+            &self.collision,
+            &self.occupancy,
+        )
+    }
+
+    /// What stepping an entity's anchor into `v` costs it, by the terrain
+    /// under it, for `find_path` to prefer roads to swamps.
+    pub fn step_cost(
+        &self,
+        v: Vector3Int,
+        footprint: Option<&Footprint>,
+        capabilities: Capabilities,
+    ) -> f32 {
+        footprint_step_cost(v, footprint, capabilities, &self.current, &self.collision)
+    }
+
+    /// The least any step costs an entity, to scale `find_path`'s guesses
+    /// of what a route has left to cost by.
+    pub fn min_step_cost(&self, capabilities: Capabilities) -> f32 {
+        self.collision.min_step_cost(capabilities)
+    }
+
+    /// Whether one-way cells let an entity with `footprint` step from `from`
+    /// to the neighbouring `to`.
+    pub fn allows_step(
+        &self,
+        from: Vector3Int,
+        to: Vector3Int,
+        footprint: Option<&Footprint>,
+    ) -> bool {
+        let dir = self.current.delta(from, to);
+        step_allowed(from, dir, footprint, &self.current, &self.collision)
+    }
+
+    /// Every cell within `max_radius` steps of `origin` on its layer,
+    /// wrapped onto the board, nearest first. Cells as near as each other
+    /// go lowest row first, then leftmost, counted from `origin` so that
+    /// wrapping does not change the order.
+    pub fn cells_near(&self, origin: Vector3Int, max_radius: u32) -> Vec<Vector3Int> {
+        let r = max_radius as i32;
+        let steps = |d: &Vector3Int| self.grid.distance(Vector3Int::default(), *d);
+        let mut offsets: Vec<Vector3Int> = (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| Vector3Int::new(dx, dy, 0)))
+            .filter(|d| steps(d) <= r)
+            .collect();
+        offsets.sort_by_key(|d| (steps(d), d.y, d.x));
+        // A radius wider than a wrapping board reaches cells twice.
+        let mut seen = HashSet::new();
+        (offsets.into_iter())
+            .map(|d| self.current.wrap(origin + d))
+            .filter(|v| seen.insert(*v))
+            .collect()
+    }
+
+    /// The nearest standable cell within `max_radius` steps of `origin`,
+    /// which may be `origin` itself. Ties break as in `cells_near`.
+    pub fn nearest_free(&self, origin: Vector3Int, max_radius: u32) -> Option<Vector3Int> {
+        (self.cells_near(origin, max_radius).into_iter()).find(|v| self.is_standable(*v))
+    }
+
+    /// The standable neighbours of `origin`, in the grid's direction order.
+    pub fn adjacent_free(&self, origin: Vector3Int) -> SmallVec<[Vector3Int; 6]> {
+        (self.current.neighbours(origin, *self.grid))
+            .filter(|v| self.is_standable(*v))
+            .collect()
+    }
+
+    /// A standable cell of `rect` on layer `z`, picked by `rng`. Cells are
+    /// counted row by row from the bottom-left, so the same seed picks the
+    /// same cell.
+    pub fn random_free_in(&self, rect: GridRect, z: i32, rng: &mut GameRng) -> Option<Vector3Int> {
+        let cells: Vec<Vector3Int> = (rect.cells(Vector3Int::new(0, 0, z)))
+            .map(|v| self.current.wrap(v))
+            .filter(|v| self.is_standable(*v))
+            .collect();
+        (!cells.is_empty()).then(|| cells[rng.below(cells.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        collision::Occupier,
+        replay::{advance_clock, LogicalClock},
+        simulation::SimulationSet,
+        testing::{headless_game, run_ticks},
+    };
+
+    /// An atlas index no map uses, made a wall for the tests.
+    const WALL: usize = 500;
+    /// Grass, which `data.json` is floored with.
+    const GRASS: usize = 0;
+
+    /// Cells of `data.json` with ground and nothing on them.
+    const A: Vector3Int = Vector3Int::new(-15, -10, 0);
+    const B: Vector3Int = Vector3Int::new(-14, -10, 0);
+    const C: Vector3Int = Vector3Int::new(-13, -10, 0);
+
+    type Edit = fn(&mut BoardCommands, &mut EventWriter<SetTileEvent>);
+
+    /// An edit to make on the next tick.
+    #[derive(Resource, Default)]
+    struct NextEdit(Option<Edit>);
+
+    fn edit(
+        mut next: ResMut<NextEdit>,
+        mut board: BoardCommands,
+        mut set: EventWriter<SetTileEvent>,
+    ) {
+        if let Some(edit) = next.0.take() {
+            edit(&mut board, &mut set);
+        }
+    }
+
+    /// `data.json` loaded, with `WALL` walling off whatever cell it is on.
+    fn game() -> App {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        app.init_resource::<NextEdit>().add_system(
+            edit.in_set(SimulationSet::Input)
+                .after(advance_clock)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        run_ticks(&mut app, 1);
+        let wall = serde_json::from_str(r#"{ "collision": ["block_walk"] }"#).unwrap();
+        let mut metadata = app.world.resource_mut::<TileMetadataRegistry>();
+        metadata.0.insert(WALL, wall);
+        let current = app.world.resource::<CurrentBoard>();
+        assert!([A, B, C].iter().all(|v| current.has_ground(*v)));
+        app
+    }
+
+    /// Runs `edit` on the next tick, and checks the board is in step after
+    /// it.
+    fn tick(app: &mut App, edit: Edit) {
+        app.world.resource_mut::<NextEdit>().0 = Some(edit);
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(app, tick + 1);
+        assert_in_step(&mut app.world);
+    }
+
+    /// Checks `CurrentBoard`, `CollisionMap` and `Occupancy` agree with the
+    /// tiles and occupiers there are.
+    fn assert_in_step(world: &mut World) {
+        let mut tiles = world.query::<(Entity, &Position, &Tile)>();
+        let tiles: HashMap<Entity, (Vector3Int, usize)> = (tiles.iter(world))
+            .map(|(entity, position, tile)| (entity, (position.v, tile.i)))
+            .collect();
+        let mut occupiers =
+            world.query_filtered::<(Entity, &Position, Option<&Footprint>), With<Occupier>>();
+        let occupiers: Vec<(Entity, Vec<Vector3Int>)> = (occupiers.iter(world))
+            .map(|(entity, position, footprint)| (entity, covered_cells(position.v, footprint)))
+            .collect();
+        // Occupancy gives cells with their layer for z.
+        let key = |v: &Vector3Int| Vector3Int::new(v.x, v.y, layer_of(v.z));
+        let current = world.resource::<CurrentBoard>();
+        let collision = world.resource::<CollisionMap>();
+        let occupancy = world.resource::<Occupancy>();
+
+        assert_eq!(
+            current.tiles.len(),
+            tiles.len(),
+            "tiles missing from the board"
+        );
+        for (entity, (v, _)) in &tiles {
+            assert_eq!(
+                current.tiles.get(&current.wrap(*v)),
+                Some(entity),
+                "{:?}",
+                v
+            );
+        }
+
+        let mut expected = CollisionMap::default();
+        let index = |entity| tiles.get(&entity).map(|(_, i)| *i);
+        let metadata = world.resource::<TileMetadataRegistry>();
+        let terrain = world.resource::<TerrainRegistry>();
+        for v in current.tiles.keys() {
+            let layer = Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), 0));
+            refresh_layer(layer, index, current, metadata, terrain, &mut expected);
+        }
+        for v in current.tiles.keys() {
+            // Doors and the like add flags of their own.
+            let flags = expected.flags(*v);
+            assert_eq!(collision.flags(*v) | flags, collision.flags(*v), "{:?}", v);
+            assert_eq!(collision.one_way(*v), expected.one_way(*v), "{:?}", v);
+            assert_eq!(collision.requires(*v), expected.requires(*v), "{:?}", v);
+            let cost = collision.step_cost(*v, Capabilities::NONE);
+            assert_eq!(cost, expected.step_cost(*v, Capabilities::NONE), "{:?}", v);
+        }
+
+        for (entity, cells) in &occupiers {
+            for v in cells {
+                assert_eq!(occupancy.get(*v), Some(*entity), "{:?}", v);
+            }
+        }
+        for v in occupancy.cells() {
+            let entity = occupancy.get(v).unwrap();
+            let cells = occupiers.iter().find(|(e, _)| *e == entity);
+            assert!(
+                cells.is_some_and(|(_, cells)| cells.iter().any(|c| key(c) == v)),
+                "{:?}",
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn commands_and_events_keep_the_board_in_step() {
+        let mut app = game();
+        assert_in_step(&mut app.world);
+        tick(&mut app, |board, set| {
+            board.set_tile(A, WALL);
+            // Walled off as of the call.
+            assert_eq!(board.spawn_actor("chaser", A), Err(BoardError::Blocked(A)));
+            set.send(SetTileEvent {
+                position: B,
+                index: Some(WALL),
+            });
+        });
+        assert!(app.world.resource::<CollisionMap>().is_blocked(B));
+        tick(&mut app, |board, set| {
+            board.set_tile(A, GRASS);
+            board.spawn_actor("chaser", A).unwrap();
+            board.set_tile(C, WALL);
+            set.send(SetTileEvent {
+                position: B,
+                index: None,
+            });
+        });
+        let collision = app.world.resource::<CollisionMap>();
+        assert!(!collision.is_blocked(A) && !collision.is_blocked(B));
+        assert!(!app.world.resource::<CurrentBoard>().has_ground(B));
+        tick(&mut app, |board, set| {
+            board.paint(C, None);
+            assert!(board.spawn_actor("chaser", C).is_err());
+            board.set_tile(C, GRASS);
+            board.spawn_actor("chaser", C).unwrap();
+            set.send(SetTileEvent {
+                position: B,
+                index: Some(GRASS),
+            });
+        });
+        for _ in 0..10 {
+            tick(&mut app, |_, _| {});
+        }
+    }
+
+    #[test]
+    fn clear_takes_actors_spawned_in_the_same_call() {
+        let mut app = game();
+        tick(&mut app, |board, _| {
+            board.spawn_actor("chaser", A).unwrap();
+            board.set_tile(B, WALL);
+            board.clear();
+            assert_eq!(board.tile(A), None);
+        });
+        assert!(app.world.resource::<CurrentBoard>().tiles.is_empty());
+        assert!(!app.world.resource::<CollisionMap>().is_blocked(B));
+        let mut objects = (app.world)
+            .query_filtered::<Entity, (With<Position>, Without<Tile>, Without<Player>)>();
+        assert_eq!(objects.iter(&app.world).count(), 0);
+        let mut players = app.world.query_filtered::<&Position, With<Player>>();
+        let cells: HashSet<Vector3Int> = (players.iter(&app.world))
+            .map(|p| Vector3Int::new(p.v.x, p.v.y, layer_of(p.v.z)))
+            .collect();
+        let occupancy = app.world.resource::<Occupancy>();
+        assert!(occupancy.cells().all(|v| cells.contains(&v)));
+    }
+}
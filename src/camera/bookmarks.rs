@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::SETTINGS_DIR,
+    editor::EditorState,
+    speed::speed_modifier,
+    storage::{platform_store, KeyValueStore},
+    Campaign,
+};
+
+use super::{CameraConfig, CameraZoom};
+
+const BOOKMARKS_KEY: &str = "camera_bookmarks.ron";
+/// Save a bookmark with Ctrl, fly to it alone.
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+/// Held with Ctrl, makes the next slot key delete its bookmark.
+const DELETE_KEY: KeyCode = KeyCode::Key0;
+
+/// Where the view was and how far it was zoomed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// The world point at the centre of the view.
+    pub center: [f32; 2],
+    /// The zoom level, as `CameraConfig` counts them.
+    pub zoom: f32,
+}
+
+/// Camera bookmarks saved in the editor, by map and then by slot from 1 to
+/// 9, kept between sessions.
+#[derive(Resource, Default, Debug, Serialize, Deserialize)]
+pub struct CameraBookmarks {
+    maps: HashMap<String, BTreeMap<usize, Bookmark>>,
+}
+
+impl CameraBookmarks {
+    pub fn get(&self, map: &str, slot: usize) -> Option<Bookmark> {
+        self.maps.get(map)?.get(&slot).copied()
+    }
+
+    /// Saves `bookmark` in `slot`, over whatever was there.
+    pub fn set(&mut self, map: &str, slot: usize, bookmark: Bookmark) {
+        let slots = self.maps.entry(map.to_string()).or_default();
+        slots.insert(slot, bookmark);
+    }
+
+    /// Whether there was a bookmark to delete.
+    pub fn remove(&mut self, map: &str, slot: usize) -> bool {
+        let Some(slots) = self.maps.get_mut(map) else { return false };
+        let removed = slots.remove(&slot).is_some();
+        if slots.is_empty() {
+            self.maps.remove(map);
+        }
+        removed
+    }
+}
+
+/// The view on its way to a bookmark, as offsets from the players and zoom
+/// levels.
+pub(super) struct Flight {
+    from: Vec2,
+    to: Vec2,
+    from_level: f32,
+    to_level: f32,
+    elapsed: f32,
+}
+
+pub(super) fn load_bookmarks() -> CameraBookmarks {
+    let text = match platform_store(SETTINGS_DIR).get(BOOKMARKS_KEY) {
+        Ok(Some(text)) => text,
+        Ok(None) => return CameraBookmarks::default(),
+        Err(e) => {
+            warn!("Could not read camera bookmarks: {}", e);
+            return CameraBookmarks::default();
+        }
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring malformed camera bookmarks: {}", e);
+        CameraBookmarks::default()
+    })
+}
+
+/// Saves, flies to and deletes bookmarks for the current map while the
+/// editor is open.
+pub(super) fn use_bookmarks(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    campaign: Res<Campaign>,
+    camera: Query<&Transform, With<Camera2d>>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    mut zoom: ResMut<CameraZoom>,
+    mut deleting: Local<bool>,
+) {
+    if !editor.active {
+        *deleting = false;
+        if zoom.flight.is_some() {
+            zoom.flight = None;
+        }
+        return;
+    }
+    let ctrl = speed_modifier(&keys);
+    if ctrl && keys.just_pressed(DELETE_KEY) {
+        *deleting = true;
+        info!("Press 1 to 9 to delete that camera bookmark.");
+        return;
+    }
+    let Some(i) = SLOT_KEYS.iter().position(|k| keys.just_pressed(*k)) else { return };
+    let (slot, map) = (i + 1, campaign.current_map());
+
+    if std::mem::take(&mut *deleting) {
+        match bookmarks.remove(map, slot) {
+            true => info!("Deleted camera bookmark {}.", slot),
+            false => info!("No camera bookmark {} to delete.", slot),
+        }
+    } else if ctrl {
+        let Ok(transform) = camera.get_single() else { return };
+        let center = transform.translation.truncate();
+        let bookmark = Bookmark {
+            center: center.to_array(),
+            zoom: zoom.level,
+        };
+        bookmarks.set(map, slot, bookmark);
+        info!("Saved camera bookmark {}.", slot);
+    } else if let Some(bookmark) = bookmarks.get(map, slot) {
+        let Ok(transform) = camera.get_single() else { return };
+        // The view is centred on the players plus the offset.
+        let players = transform.translation.truncate() - zoom.offset;
+        zoom.flight = Some(Flight {
+            from: zoom.offset,
+            to: Vec2::from(bookmark.center) - players,
+            from_level: zoom.level,
+            to_level: bookmark.zoom,
+            elapsed: 0.,
+        });
+    }
+}
+
+/// Moves the view along a flight to a bookmark, eased as zooms are. Where
+/// the bookmark is past the board's edge, the view stops at the clamp.
+pub(super) fn fly_camera(config: Res<CameraConfig>, time: Res<Time>, mut zoom: ResMut<CameraZoom>) {
+    let Some(mut flight) = zoom.flight.take() else { return };
+    flight.elapsed += time.delta_seconds();
+    let t = (flight.elapsed / config.bookmark_duration.max(f32::EPSILON)).min(1.);
+    let eased = t * t * (3. - 2. * t);
+    let level = flight.from_level + (flight.to_level - flight.from_level) * eased;
+    // Held at `level` without starting a zoom, which would end the flight.
+    zoom.level = level;
+    zoom.from = level;
+    zoom.to = level;
+    zoom.anchor = None;
+    zoom.offset = flight.from.lerp(flight.to, eased);
+    if t < 1. {
+        zoom.flight = Some(flight);
+    }
+}
+
+/// Keeps bookmarks for the next session whenever they change.
+pub(super) fn save_bookmarks(bookmarks: Res<CameraBookmarks>) {
+    if !bookmarks.is_changed() || bookmarks.is_added() {
+        return;
+    }
+    let text = ron::ser::to_string_pretty(&*bookmarks, ron::ser::PrettyConfig::default())
+        .expect("bookmarks always serialize");
+    if let Err(e) = platform_store(SETTINGS_DIR).set(BOOKMARKS_KEY, &text) {
+        warn!("Could not save camera bookmarks: {}", e);
+    }
+}
@@ -0,0 +1,154 @@
+//! Rooms the camera locks onto, one screen at a time. A region object with
+//! `camera_lock` set is such a room: while a player is in it the view
+//! stops following and pans to frame the room exactly, zoomed to fit it
+//! within the zoom limits, then pans back to the players once they have
+//! all left. Enemies outside the room hold still meanwhile. Where locked
+//! rooms overlap, the one entered last holds the view.
+//!
+//! Which room holds the view is worked out each tick from where players
+//! stand, not from region events, so a knockback across a room's edge
+//! counts as walking across it. The pan is only drawn: a change of room
+//! partway through starts the next pan from wherever the view has got to.
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{covered_cells, Footprint},
+    layer_of,
+    npc::{AiSuspended, Hostile},
+    player::Player,
+    regions::Region,
+    vectors::{GridRect, Vector3Int},
+    CurrentBoard, Position,
+};
+
+/// Marks a region as a room the camera locks onto.
+#[derive(Component)]
+pub struct CameraLock;
+
+/// A locked room: the cells of region `id` on one layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedRoom {
+    pub id: String,
+    pub rect: GridRect,
+    pub layer: i32,
+}
+
+impl LockedRoom {
+    fn new(region: &Region, position: &Position, footprint: Option<&Footprint>) -> Self {
+        let v = position.v;
+        let rect = footprint.map_or(GridRect::new(0, 0, 1, 1), |f| f.0);
+        LockedRoom {
+            id: region.id.clone(),
+            rect: GridRect::new(v.x + rect.x, v.y + rect.y, rect.width, rect.height),
+            layer: layer_of(v.z),
+        }
+    }
+
+    /// Whether any cell of an entity at `v` is in the room.
+    fn holds(&self, v: Vector3Int, footprint: Option<&Footprint>, current: &CurrentBoard) -> bool {
+        (covered_cells(v, footprint).into_iter())
+            .map(|c| current.wrap(c))
+            .any(|c| layer_of(c.z) == self.layer && self.rect.contains(c))
+    }
+}
+
+/// The locked rooms players are in, in the order they were entered.
+#[derive(Resource, Default)]
+pub struct LockedRooms {
+    entered: Vec<LockedRoom>,
+}
+
+impl LockedRooms {
+    /// The room holding the view, if any.
+    pub fn current(&self) -> Option<&LockedRoom> {
+        self.entered.last()
+    }
+}
+
+/// Where the view is panning: to a room, or back to the players.
+#[derive(Resource, Default)]
+pub(super) struct LockPan {
+    room: Option<LockedRoom>,
+    /// The view's centre and scale when the pan started, until it ends.
+    from: Option<(Vec2, f32)>,
+    elapsed: f32,
+}
+
+impl LockPan {
+    /// The view's centre and scale, given the one `shown` last frame, the
+    /// one that frames `room` and the one that follows the players. A pan
+    /// takes `duration` seconds, eased as zooms are.
+    pub(super) fn frame(
+        &mut self,
+        room: Option<(&LockedRoom, (Vec2, f32))>,
+        follow: (Vec2, f32),
+        shown: (Vec2, f32),
+        dt: f32,
+        duration: f32,
+    ) -> (Vec2, f32) {
+        let target = room.map(|(room, _)| room);
+        if self.room.as_ref() != target {
+            self.room = target.cloned();
+            self.from = Some(shown);
+            self.elapsed = 0.;
+        }
+        let (center, scale) = room.map_or(follow, |(_, frame)| frame);
+        let Some((from, from_scale)) = self.from else { return (center, scale) };
+        self.elapsed += dt;
+        let t = (self.elapsed / duration.max(f32::EPSILON)).min(1.);
+        if t >= 1. {
+            self.from = None;
+        }
+        let eased = t * t * (3. - 2. * t);
+        (
+            from.lerp(center, eased),
+            from_scale + (scale - from_scale) * eased,
+        )
+    }
+}
+
+/// Keeps track of the locked rooms players are in, and suspends enemies
+/// outside the one holding the view.
+#[allow(clippy::type_complexity)]
+pub(super) fn track_locked_rooms(
+    mut commands: Commands,
+    rooms: Query<(&Region, &Position, Option<&Footprint>), With<CameraLock>>,
+    players: Query<(&Position, Option<&Footprint>), With<Player>>,
+    enemies: Query<(Entity, &Position, Option<&Footprint>, Option<&AiSuspended>), Hostile>,
+    current: Res<CurrentBoard>,
+    mut locked: ResMut<LockedRooms>,
+) {
+    let occupied: Vec<LockedRoom> = (rooms.iter())
+        .map(|(region, position, footprint)| LockedRoom::new(region, position, footprint))
+        .filter(|room| (players.iter()).any(|(p, f)| room.holds(p.v, f, &current)))
+        .collect();
+    // Rooms still occupied keep their places, and newly entered ones go
+    // after them.
+    let mut entered: Vec<LockedRoom> = (locked.entered.iter())
+        .filter(|room| occupied.contains(room))
+        .cloned()
+        .collect();
+    for room in occupied {
+        if !entered.contains(&room) {
+            entered.push(room);
+        }
+    }
+    if locked.entered != entered {
+        locked.entered = entered;
+    }
+
+    let room = locked.current();
+    for (entity, position, footprint, suspended) in enemies.iter() {
+        let outside = room.is_some_and(|room| !room.holds(position.v, footprint, &current));
+        match (outside, suspended.is_some()) {
+            (true, false) => {
+                commands.entity(entity).insert(AiSuspended);
+            }
+            (false, true) => {
+                commands.entity(entity).remove::<AiSuspended>();
+            }
+            _ => {}
+        }
+    }
+}
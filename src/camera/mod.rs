@@ -0,0 +1,661 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::{
+    editor::EditorState,
+    hud::PointerOverUi,
+    player::Player,
+    projection::GridProjection,
+    simulation::{SimulationPaused, SimulationSet},
+    speed::speed_modifier,
+    vectors::GridRect,
+    Campaign, CurrentBoard, CAMERA_SCALE,
+};
+
+mod bookmarks;
+mod lock;
+mod photo;
+
+pub use bookmarks::{Bookmark, CameraBookmarks};
+pub use lock::{CameraLock, LockedRoom, LockedRooms};
+pub use photo::{PhotoConfig, PhotoMode, PHOTO_DIR};
+
+/// Distance between players, in tiles, beyond which the camera zooms out.
+pub const CO_OP_ZOOM_DISTANCE: f32 = 12.;
+pub const CAMERA_MAX_SCALE: f32 = 2.;
+pub const CAMERA_ZOOM_SPEED: f32 = 4.;
+/// Pixels the camera strays at full shake.
+pub const CAMERA_SHAKE_OFFSET: f32 = 6.;
+/// Shake lost per second.
+pub const CAMERA_SHAKE_DECAY: f32 = 1.5;
+/// Pixels of a touchpad scroll counted as one line of a wheel.
+const PIXELS_PER_LINE: f32 = 40.;
+const PRESET_KEYS: [KeyCode; 4] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+/// Held with the left button to drag the editor's view, as the middle
+/// button does alone.
+pub const GRAB_KEY: KeyCode = KeyCode::Space;
+/// Held with Ctrl, switches between the fixed viewport and filling the
+/// window.
+const FIXED_VIEWPORT_KEY: KeyCode = KeyCode::Key0;
+/// Filling the window around the fixed viewport, under the rest of the UI.
+const LETTERBOX_COLOR: Color = Color::BLACK;
+const LETTERBOX_Z: i32 = -1;
+
+/// A fixed resolution of art pixels, shown whatever the window's size by
+/// scaling it up by the largest whole factor that fits, so that pixel art
+/// stays crisp.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedViewport {
+    /// Off, the view fills the window at whatever zoom is picked.
+    pub enabled: bool,
+    /// Pixels of art across and down. 640 by 360 is 40 by 22.5 tiles.
+    pub size: UVec2,
+}
+
+/// How far the player can zoom. Zoom levels are screen pixels per pixel of
+/// art, so whole levels keep 16 px tiles crisp.
+#[derive(Resource, Clone, Debug)]
+pub struct CameraConfig {
+    /// Snapped to with keys 1 to 4.
+    pub presets: [f32; 4],
+    /// The furthest out and closest in the wheel zooms.
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// How much one line of the wheel multiplies the zoom level by.
+    pub wheel_step: f32,
+    /// Seconds a zoom takes to settle.
+    pub zoom_duration: f32,
+    /// Seconds the view takes to fly to a bookmark.
+    pub bookmark_duration: f32,
+    /// Seconds the view takes to pan to a locked room, and back.
+    pub lock_pan_duration: f32,
+    /// Keeps the view over the board, except along edges that wrap.
+    pub clamp_to_board: bool,
+    /// Logical pixels from a window edge within which the cursor pans the
+    /// editor's view, or 0 for none.
+    pub edge_scroll_margin: f32,
+    /// Logical pixels a second that edge scrolling pans, so it crosses the
+    /// screen as fast at any zoom.
+    pub edge_scroll_speed: f32,
+    /// Shown in place of the zoom levels while enabled, with Ctrl+0
+    /// switching it on and off.
+    pub fixed_viewport: FixedViewport,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            presets: [1., 2., 3., 4.],
+            min_zoom: 0.5,
+            max_zoom: 6.,
+            wheel_step: 1.25,
+            zoom_duration: 0.15,
+            bookmark_duration: 0.5,
+            lock_pan_duration: 0.4,
+            clamp_to_board: true,
+            edge_scroll_margin: 20.,
+            edge_scroll_speed: 600.,
+            fixed_viewport: FixedViewport {
+                enabled: false,
+                size: UVec2::new(640, 360),
+            },
+        }
+    }
+}
+
+/// How hard the camera is shaking, from 0 to 1. Explosions add to it and
+/// it dies down by itself.
+#[derive(Default, Resource)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+impl CameraShake {
+    pub fn add(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.);
+    }
+}
+
+/// The part of the window the fixed viewport takes up while it is on,
+/// with bars over the rest.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    /// In logical pixels from the top left, as UI is placed, or `None`
+    /// while the view fills the window.
+    pub view: Option<Rect>,
+    /// The projection scale that shows the fixed resolution in `view`.
+    pub scale: f32,
+}
+
+impl Letterbox {
+    /// Whether `cursor`, measured up from the bottom left as the window
+    /// measures it, is over the view rather than the bars.
+    pub fn shows(&self, window: &Window, cursor: Vec2) -> bool {
+        let flipped = Vec2::new(cursor.x, window.height() - cursor.y);
+        self.view.is_none_or(|view| view.contains(flipped))
+    }
+}
+
+/// One of the bars around the fixed viewport: above, below, left or right
+/// of it, in that order.
+#[derive(Component)]
+struct LetterboxBar(usize);
+
+/// The zoom level the player chose, and the zoom under way toward it.
+#[derive(Resource)]
+struct CameraZoom {
+    level: f32,
+    from: f32,
+    to: f32,
+    elapsed: f32,
+    /// Where the cursor was, from the centre of the window in logical
+    /// pixels, when the wheel started the zoom under way.
+    anchor: Option<Vec2>,
+    /// How far the view is off the players, so that the point under the
+    /// cursor held still, or where the editor panned to.
+    offset: Vec2,
+    /// How far the camera has pulled back to keep co-op players in view.
+    co_op: f32,
+    /// The editor's view on its way to a bookmark, until it arrives or the
+    /// view is zoomed or panned by hand.
+    flight: Option<bookmarks::Flight>,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        let level = 1. / CAMERA_SCALE;
+        CameraZoom {
+            level,
+            from: level,
+            to: level,
+            elapsed: 0.,
+            anchor: None,
+            offset: Vec2::ZERO,
+            co_op: 1.,
+            flight: None,
+        }
+    }
+}
+
+impl CameraZoom {
+    fn start(&mut self, to: f32, anchor: Option<Vec2>) {
+        self.from = self.level;
+        self.to = to;
+        self.elapsed = 0.;
+        self.anchor = anchor;
+        self.flight = None;
+    }
+}
+
+pub struct CameraPlugin;
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraConfig>()
+            .init_resource::<CameraZoom>()
+            .init_resource::<CameraShake>()
+            .init_resource::<Letterbox>()
+            .init_resource::<LockedRooms>()
+            .init_resource::<lock::LockPan>()
+            .insert_resource(bookmarks::load_bookmarks())
+            .add_plugin(photo::PhotoPlugin)
+            .add_startup_system(spawn_letterbox)
+            .add_system(toggle_fixed_viewport)
+            .add_system(fit_viewport.after(toggle_fixed_viewport))
+            .add_system(zoom_camera)
+            .add_system(pan_camera.after(zoom_camera))
+            .add_system(bookmarks::use_bookmarks.after(zoom_camera))
+            .add_system(
+                bookmarks::fly_camera
+                    .after(bookmarks::use_bookmarks)
+                    .after(pan_camera),
+            )
+            .add_system(bookmarks::save_bookmarks.after(bookmarks::use_bookmarks))
+            .add_system(
+                camera_follow_player
+                    .after(bookmarks::fly_camera)
+                    .after(fit_viewport),
+            )
+            .add_system(
+                lock::track_locked_rooms
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// How far to move the camera so that the world point under `cursor`,
+/// given from the centre of the view in logical pixels, stays under it as
+/// the projection scale goes `from` one `to` another. The scale is world
+/// units per logical pixel, and the view's axes point the same way as the
+/// world's, so the point is at `centre + cursor * scale` both before and
+/// after.
+pub fn zoom_anchor_shift(cursor: Vec2, from: f32, to: f32) -> Vec2 {
+    cursor * (from - to)
+}
+
+/// `center` moved as little as possible to keep a view reaching
+/// `half_size` either side of it inside `bounds`, or to the middle of
+/// `bounds` along an axis the view is wider than. Axes set in `free` are
+/// left alone.
+pub fn clamp_view(center: Vec2, half_size: Vec2, bounds: Rect, free: BVec2) -> Vec2 {
+    let axis = |c: f32, half: f32, min: f32, max: f32, free: bool| {
+        if free {
+            c
+        } else if 2. * half >= max - min {
+            (min + max) / 2.
+        } else {
+            c.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        axis(center.x, half_size.x, bounds.min.x, bounds.max.x, free.x),
+        axis(center.y, half_size.y, bounds.min.y, bounds.max.y, free.y),
+    )
+}
+
+/// The projection scale at which a view `view` logical pixels big just
+/// fits `size` world units, at the scale of its tighter axis.
+pub fn fit_scale(size: Vec2, view: Vec2) -> f32 {
+    (size / view.max(Vec2::ONE)).max_element()
+}
+
+/// Where a view of `size` art pixels goes in a window `physical` pixels
+/// big: its top left corner and size in physical pixels, centred and as
+/// large as whole physical pixels per art pixel allow, and that many. At
+/// least one, so a window too small for the view crops it.
+pub fn letterbox_rect(physical: UVec2, size: UVec2) -> (UVec2, UVec2, u32) {
+    let size = size.max(UVec2::ONE);
+    let factor = (physical / size).min_element().max(1);
+    let shown = (size * factor).min(physical);
+    ((physical - shown) / 2, shown, factor)
+}
+
+fn spawn_letterbox(mut commands: Commands) {
+    for i in 0..4 {
+        commands.spawn((
+            LetterboxBar(i),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                background_color: LETTERBOX_COLOR.into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(LETTERBOX_Z),
+                ..default()
+            },
+        ));
+    }
+}
+
+fn toggle_fixed_viewport(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    mut config: ResMut<CameraConfig>,
+) {
+    // In the editor Ctrl+0 deletes camera bookmarks instead.
+    if editor.active {
+        return;
+    }
+    if speed_modifier(&keys) && keys.just_pressed(FIXED_VIEWPORT_KEY) {
+        let fixed = &mut config.fixed_viewport;
+        fixed.enabled = !fixed.enabled;
+        info!("Fixed viewport {}.", if fixed.enabled { "on" } else { "off" });
+    }
+}
+
+/// Fits the fixed viewport to the window as it is resized or the viewport
+/// is switched on or off, and moves the bars around it. The bars are UI,
+/// as UI is laid out over the whole window whatever the camera's own
+/// viewport, and would be cut off by one.
+fn fit_viewport(
+    mut resized: EventReader<WindowResized>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    config: Res<CameraConfig>,
+    mut letterbox: ResMut<Letterbox>,
+    mut bars: Query<(&LetterboxBar, &mut Style, &mut Visibility)>,
+) {
+    if resized.iter().count() == 0 && !config.is_changed() {
+        return;
+    }
+    let Ok(window) = windows.get_single() else { return };
+    let fixed = config.fixed_viewport;
+    *letterbox = match fixed.enabled {
+        true => {
+            let physical = UVec2::new(window.physical_width(), window.physical_height());
+            let (corner, shown, factor) = letterbox_rect(physical, fixed.size);
+            let scale_factor = window.scale_factor() as f32;
+            let min = corner.as_vec2() / scale_factor;
+            Letterbox {
+                view: Some(Rect::from_corners(
+                    min,
+                    min + shown.as_vec2() / scale_factor,
+                )),
+                scale: scale_factor / factor as f32,
+            }
+        }
+        false => Letterbox::default(),
+    };
+
+    // In percentages of the window, which UI scaling leaves alone.
+    let (width, height) = (window.width(), window.height());
+    let view = letterbox.view.unwrap_or(Rect::new(0., 0., width, height));
+    let rects = [
+        Rect::new(0., 0., width, view.min.y),
+        Rect::new(0., view.max.y, width, height),
+        Rect::new(0., view.min.y, view.min.x, view.max.y),
+        Rect::new(view.max.x, view.min.y, width, view.max.y),
+    ];
+    let percent = |value: f32, of: f32| Val::Percent(100. * value / of.max(1.));
+    for (bar, mut style, mut visibility) in bars.iter_mut() {
+        let rect = rects[bar.0];
+        style.position.left = percent(rect.min.x, width);
+        style.position.top = percent(rect.min.y, height);
+        style.size = Size::new(percent(rect.width(), width), percent(rect.height(), height));
+        *visibility = match letterbox.view {
+            Some(_) => Visibility::Inherited,
+            None => Visibility::Hidden,
+        };
+    }
+}
+
+/// The world-space box around every cell of the board.
+fn board_rect(current: &CurrentBoard, grid: &GridProjection) -> Rect {
+    cells_rect(current.bounds.rect, grid)
+}
+
+/// The world-space box around every cell of `rect`.
+fn cells_rect(rect: GridRect, grid: &GridProjection) -> Rect {
+    // Cells are centred on their coordinates.
+    let min = Vec2::new(rect.x as f32, rect.y as f32) - 0.5;
+    let max = min + Vec2::new(rect.width as f32, rect.height as f32);
+    let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+    let (min, max) = corners.iter().map(|c| grid.cell_to_world(*c)).fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), c| (min.min(c), max.max(c)),
+    );
+    Rect::from_corners(min, max)
+}
+
+/// The world-space box the camera shows, from its projection's area as of
+/// the last frame drawn.
+pub fn view_rect(transform: &Transform, projection: &OrthographicProjection) -> Rect {
+    let center = transform.translation.truncate();
+    Rect::from_corners(projection.area.min + center, projection.area.max + center)
+}
+
+/// Snaps to presets on 1 to 4, and zooms toward the cursor with the wheel.
+#[allow(clippy::too_many_arguments)]
+fn zoom_camera(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    paused: Res<SimulationPaused>,
+    pointer: Res<PointerOverUi>,
+    config: Res<CameraConfig>,
+    editor: Res<EditorState>,
+    campaign: Res<Campaign>,
+    bookmarks: Res<CameraBookmarks>,
+    locked: Res<LockedRooms>,
+    mut zoom: ResMut<CameraZoom>,
+) {
+    let lines: f32 = (wheel.iter())
+        .map(|e| match e.unit {
+            MouseScrollUnit::Line => e.y,
+            MouseScrollUnit::Pixel => e.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    // Menus that pause the game use the number keys themselves, and with
+    // Ctrl they set the simulation speed. A locked room sets its own zoom.
+    let locked = locked.current().is_some() && !editor.active;
+    if paused.0 || speed_modifier(&keys) || locked {
+        return;
+    }
+
+    // In the editor a number flies to its bookmark instead, if it has one.
+    let map = campaign.current_map();
+    let bookmarked = |i: usize| editor.active && bookmarks.get(map, i + 1).is_some();
+    if let Some(i) = PRESET_KEYS.iter().position(|k| keys.just_pressed(*k)) {
+        if !bookmarked(i) {
+            zoom.start(config.presets[i], None);
+        }
+    } else if lines != 0. && !pointer.0 {
+        let to = zoom.to * config.wheel_step.powf(lines);
+        let cursor = windows.get_single().ok().and_then(|window| {
+            let size = Vec2::new(window.width(), window.height());
+            Some(window.cursor_position()? - size / 2.)
+        });
+        zoom.start(to.clamp(config.min_zoom, config.max_zoom), cursor);
+    }
+}
+
+/// Pans the editor's view while the cursor is near a window edge, or drags
+/// it with the middle button or `GRAB_KEY` and the left, keeping the world
+/// point under the cursor there.
+#[allow(clippy::too_many_arguments)]
+fn pan_camera(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    projections: Query<&OrthographicProjection, With<Camera2d>>,
+    editor: Res<EditorState>,
+    pointer: Res<PointerOverUi>,
+    config: Res<CameraConfig>,
+    time: Res<Time>,
+    mut zoom: ResMut<CameraZoom>,
+    mut grabbed_at: Local<Option<Vec2>>,
+) {
+    let window = windows.get_single().ok();
+    let cursor = window.and_then(|window| window.cursor_position());
+    let (Some(window), Some(cursor), Ok(projection)) = (window, cursor, projections.get_single())
+    else {
+        *grabbed_at = None;
+        return;
+    };
+    if !editor.active || pointer.0 {
+        *grabbed_at = None;
+        return;
+    }
+
+    // The view's axes point the same way as the world's, and the scale is
+    // world units per logical pixel.
+    let grabbing = buttons.pressed(MouseButton::Middle)
+        || (keys.pressed(GRAB_KEY) && buttons.pressed(MouseButton::Left));
+    if grabbing {
+        if let Some(last) = grabbed_at.filter(|last| *last != cursor) {
+            zoom.offset -= (cursor - last) * projection.scale;
+            zoom.flight = None;
+        }
+        *grabbed_at = Some(cursor);
+        return;
+    }
+    *grabbed_at = None;
+
+    let margin = config.edge_scroll_margin;
+    if margin <= 0. {
+        return;
+    }
+    let edge = |c: f32, size: f32| {
+        if c < margin {
+            -1.
+        } else if c > size - margin {
+            1.
+        } else {
+            0.
+        }
+    };
+    let dir = Vec2::new(
+        edge(cursor.x, window.width()),
+        edge(cursor.y, window.height()),
+    );
+    if dir == Vec2::ZERO {
+        return;
+    }
+    let speed = config.edge_scroll_speed * projection.scale;
+    zoom.offset += dir.normalize() * speed * time.delta_seconds();
+    zoom.flight = None;
+}
+
+/// Centers the camera between all players, zooming out once they are
+/// further apart than `CO_OP_ZOOM_DISTANCE`. A zoom toward the cursor
+/// moves the view off the players, and it drifts back once settled. In the
+/// editor it stays wherever it was panned to. The fixed viewport keeps its
+/// own scale, whatever the zoom. Outside the editor, a locked room holds
+/// the view instead, framed whole.
+#[allow(clippy::too_many_arguments)]
+fn camera_follow_player(
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+    players: Query<&Transform, (With<Player>, Without<Camera2d>)>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    grid: Res<GridProjection>,
+    current: Res<CurrentBoard>,
+    editor: Res<EditorState>,
+    config: Res<CameraConfig>,
+    letterbox: Res<Letterbox>,
+    locked: Res<LockedRooms>,
+    mut zoom: ResMut<CameraZoom>,
+    mut pan: ResMut<lock::LockPan>,
+    mut shake: ResMut<CameraShake>,
+    time: Res<Time>,
+) {
+    let Ok((mut c, mut projection)) = camera.get_single_mut() else { return };
+    if players.is_empty() {
+        return;
+    }
+    let shown = (c.translation.truncate(), projection.scale);
+
+    let (min, max) = players.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), p| {
+            (
+                min.min(p.translation.truncate()),
+                max.max(p.translation.truncate()),
+            )
+        },
+    );
+    let midpoint = (min + max) / 2.;
+    let dt = time.delta_seconds();
+
+    zoom.elapsed += dt;
+    let t = (zoom.elapsed / config.zoom_duration.max(f32::EPSILON)).min(1.);
+    zoom.level = zoom.from + (zoom.to - zoom.from) * t * t * (3. - 2. * t);
+    let spread = ((max - min) / grid.tile_size()).max_element();
+    let co_op = (spread / CO_OP_ZOOM_DISTANCE).max(1.);
+    zoom.co_op += (co_op - zoom.co_op) * (CAMERA_ZOOM_SPEED * dt).min(1.);
+    // Co-op never pulls back past the limit, but the player may.
+    let scale = match letterbox.view {
+        Some(_) => letterbox.scale,
+        None => (zoom.co_op / zoom.level).min(CAMERA_MAX_SCALE.max(1. / zoom.level)),
+    };
+    let before = projection.scale;
+    if projection.scale != scale {
+        projection.scale = scale;
+    }
+
+    match zoom.anchor {
+        Some(cursor) => zoom.offset += zoom_anchor_shift(cursor, before, scale),
+        None if !editor.active => zoom.offset *= 1. - (CAMERA_ZOOM_SPEED * dt).min(1.),
+        None => {}
+    }
+    if t >= 1. {
+        zoom.anchor = None;
+    }
+    let mut center = midpoint + zoom.offset;
+    let view_size = windows.get_single().ok().map(|window| {
+        let window_size = Vec2::new(window.width(), window.height());
+        (letterbox.view).map_or(window_size, |v| v.size())
+    });
+    if let Some(size) = view_size.filter(|_| config.clamp_to_board) {
+        let wrap = current.bounds.wrap;
+        let free = match *grid {
+            // Wrapping edges run diagonally across the screen.
+            GridProjection::Isometric { .. } => BVec2::splat(wrap.x || wrap.y),
+            _ => BVec2::new(wrap.x, wrap.y),
+        };
+        let half_size = size / 2. * scale;
+        center = clamp_view(center, half_size, board_rect(&current, &grid), free);
+        // The clamp wins over keeping the cursor's point still.
+        zoom.offset = center - midpoint;
+    }
+
+    // Fitted within the zoom limits, so a huge room is cropped rather
+    // than shrunk to nothing.
+    let room = (locked.current()).filter(|_| !editor.active).map(|room| {
+        let rect = cells_rect(room.rect, &grid);
+        let fit = match (letterbox.view, view_size) {
+            (Some(_), _) | (_, None) => scale,
+            (None, Some(size)) => {
+                fit_scale(rect.size(), size).clamp(1. / config.max_zoom, 1. / config.min_zoom)
+            }
+        };
+        (room, (rect.center(), fit))
+    });
+    let (center, scale) = pan.frame(room, (center, scale), shown, dt, config.lock_pan_duration);
+    if projection.scale != scale {
+        projection.scale = scale;
+    }
+
+    // Shake is cosmetic, so it wobbles with time rather than `GameRng`.
+    let t = time.elapsed_seconds();
+    let wobble = Vec2::new((t * 53.).sin(), (t * 41.).cos());
+    let offset = wobble * shake.trauma.powi(2) * CAMERA_SHAKE_OFFSET;
+    c.translation = (center + offset).extend(c.translation.z);
+    if shake.trauma > 0. {
+        shake.trauma = (shake.trauma - CAMERA_SHAKE_DECAY * dt).max(0.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let centre = Vec2::new(100., -40.);
+        for cursor in [Vec2::new(120., 80.), Vec2::new(-300., 15.), Vec2::ZERO] {
+            for (from, to) in [(0.5, 0.25), (0.25, 1.), (1., 1.)] {
+                let before = centre + cursor * from;
+                let after = centre + zoom_anchor_shift(cursor, from, to) + cursor * to;
+                assert!(
+                    before.distance(after) < 1e-4,
+                    "{} from {} to {}: {} became {}",
+                    cursor,
+                    from,
+                    to,
+                    before,
+                    after
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zooming_in_moves_toward_the_cursor() {
+        // Up and to the right of the centre, zooming in from 1x to 2x.
+        let shift = zoom_anchor_shift(Vec2::new(40., 20.), 0.5, 0.25);
+        assert_eq!(shift, Vec2::new(10., 5.));
+        // Zooming back out undoes it.
+        assert_eq!(zoom_anchor_shift(Vec2::new(40., 20.), 0.25, 0.5), -shift);
+    }
+
+    #[test]
+    fn the_bounds_win_over_the_cursor() {
+        let bounds = Rect::new(0., 0., 320., 240.);
+        let (cursor, from, to) = (Vec2::new(-150., 0.), 0.5, 0.25);
+        // Zooming in near the left edge toward a cursor off the board.
+        let centre = Vec2::new(80., 120.);
+        let anchored = centre + zoom_anchor_shift(cursor, from, to);
+        let half_size = Vec2::new(400., 300.) * to / 2.;
+        let clamped = clamp_view(anchored, half_size, bounds, BVec2::FALSE);
+        assert_eq!(clamped, Vec2::new(50., 120.));
+        // Wider than the bounds, the view is centred on them instead.
+        let clamped = clamp_view(anchored, half_size * 10., bounds, BVec2::FALSE);
+        assert_eq!(clamped, Vec2::new(160., 120.));
+        // Unless the axis wraps.
+        let clamped = clamp_view(anchored, half_size, bounds, BVec2::new(true, false));
+        assert_eq!(clamped, anchored);
+    }
+}
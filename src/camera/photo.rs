@@ -0,0 +1,605 @@
+//! Photo mode, opened with P from the save menu. The game stays paused,
+//! the HUD, menus and overlays are hidden, and the camera pans and zooms
+//! freely, past the limits play keeps it to. Actors and the fog can be
+//! hidden too. Space draws the view again off screen, at several times the
+//! window's resolution, and saves it as a PNG in `PHOTO_DIR`.
+//!
+//! Whatever is hidden goes back to the visibility it had when photo mode
+//! closes, including any its owner gave it in the meantime.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::{
+            BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, MapMode, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        view::VisibilitySystems,
+        RenderApp, RenderSet,
+    },
+    window::PrimaryWindow,
+};
+use wgpu::Maintain;
+
+use crate::{
+    combat::Health,
+    followers::Follower,
+    locale::Localization,
+    npc::{Chaser, RangedAi},
+    player::Player,
+    render_layers::RenderLayerSlot,
+    saves::SaveMenu,
+    shop::CLOSE_KEY,
+    simulation::SimulationPaused,
+    t,
+    vision::FogOfWar,
+    AppState, GraphicsAssets,
+};
+
+use super::{camera_follow_player, LetterboxBar, PIXELS_PER_LINE};
+
+/// Folder photos are saved in, beside the game.
+pub const PHOTO_DIR: &str = "photos";
+/// Opens photo mode from the save menu.
+const PHOTO_KEY: KeyCode = KeyCode::P;
+const CAPTURE_KEY: KeyCode = KeyCode::Space;
+const ACTORS_KEY: KeyCode = KeyCode::H;
+const FOG_KEY: KeyCode = KeyCode::G;
+const ZOOM_OUT_KEY: KeyCode = KeyCode::Q;
+const ZOOM_IN_KEY: KeyCode = KeyCode::E;
+/// Pick supersampling of two, three or four times the window.
+const SUPERSAMPLE_KEYS: [KeyCode; 3] = [KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+const HINT_FONT_SIZE: f32 = 16.;
+const HINT_COLOR: Color = Color::rgba(1., 1., 1., 0.6);
+/// Frames the camera draws into a capture before it is read back, so the
+/// image is ready on the GPU and the view drawn into it.
+const CAPTURE_FRAMES: u32 = 2;
+/// Frames after that a capture is waited on before it is given up.
+const CAPTURE_TIMEOUT: u32 = 30;
+/// The widest or tallest capture, within what any GPU draws to.
+const MAX_CAPTURE_SIZE: u32 = 8192;
+/// Rows copied off the GPU are padded to this many bytes, as wgpu's
+/// `COPY_BYTES_PER_ROW_ALIGNMENT`.
+const ROW_ALIGNMENT: u32 = 256;
+
+#[derive(Resource, Clone, Debug)]
+pub struct PhotoConfig {
+    /// Times the window's resolution a photo is drawn at, from 2 to 4.
+    pub supersample: u32,
+    /// The closest in and furthest out the camera zooms, as projection
+    /// scales, well past the limits of play.
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Logical pixels a second the view pans, so it crosses the screen as
+    /// fast at any zoom.
+    pub pan_speed: f32,
+    /// How much one line of the wheel multiplies the scale by. Finer than
+    /// in play.
+    pub wheel_step: f32,
+    /// How much Q and E multiply the scale by each second held.
+    pub zoom_speed: f32,
+}
+
+impl Default for PhotoConfig {
+    fn default() -> Self {
+        PhotoConfig {
+            supersample: 2,
+            min_scale: 0.05,
+            max_scale: 8.,
+            pan_speed: 600.,
+            wheel_step: 1.05,
+            zoom_speed: 2.,
+        }
+    }
+}
+
+/// Whether photo mode is open, its view, and what it hid to give back.
+#[derive(Resource, Default)]
+pub struct PhotoMode {
+    active: bool,
+    pub hide_actors: bool,
+    pub hide_fog: bool,
+    center: Vec2,
+    scale: f32,
+    /// Each entity hidden, and the visibility it goes back to.
+    hidden: HashMap<Entity, Visibility>,
+    /// Whether fog was on, while it is hidden.
+    fog: Option<bool>,
+    /// What the camera drew to before a capture took it over.
+    target: Option<RenderTarget>,
+}
+
+impl PhotoMode {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// A capture under way: the image the camera draws into, and the pixels
+/// read back from it.
+#[derive(Resource, Clone, Default, ExtractResource)]
+struct PhotoCapture {
+    image: Option<Handle<Image>>,
+    /// Times the window's resolution, and the window's scale factor, that
+    /// the view is drawn at.
+    factor: f32,
+    /// Frames the capture has been under way.
+    frames: u32,
+    /// Set once the image is drawn, for the render world to read it back.
+    take: bool,
+    pixels: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+/// The keys of photo mode, in a corner out of the way.
+#[derive(Component)]
+struct PhotoHint;
+
+pub struct PhotoPlugin;
+impl Plugin for PhotoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhotoConfig>()
+            .init_resource::<PhotoMode>()
+            .init_resource::<PhotoCapture>()
+            .add_plugin(ExtractResourcePlugin::<PhotoCapture>::default())
+            .add_systems(
+                (open_photo_mode, use_photo_mode, finish_capture, draw_hint)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(
+                aim_photo_camera
+                    .after(camera_follow_player)
+                    .after(use_photo_mode),
+            )
+            .add_system(
+                hide_for_photo
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(VisibilitySystems::VisibilityPropagate),
+            )
+            .add_system(close_photo_mode.in_schedule(OnExit(AppState::Game)));
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_system(read_back.in_set(RenderSet::Cleanup));
+        }
+    }
+}
+
+fn open_photo_mode(
+    keys: Res<Input<KeyCode>>,
+    mut saves: ResMut<SaveMenu>,
+    mut photo: ResMut<PhotoMode>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    if !keys.just_pressed(PHOTO_KEY) || photo.active || !saves.is_idle() {
+        return;
+    }
+    let Ok((transform, projection)) = cameras.get_single() else { return };
+    // Still paused, now for photo mode.
+    saves.close();
+    photo.active = true;
+    photo.center = transform.translation.truncate();
+    photo.scale = projection.scale;
+}
+
+/// Pans, zooms, toggles what is hidden, and starts captures.
+#[allow(clippy::too_many_arguments)]
+fn use_photo_mode(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    time: Res<Time>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut config: ResMut<PhotoConfig>,
+    mut photo: ResMut<PhotoMode>,
+    mut capture: ResMut<PhotoCapture>,
+    mut images: ResMut<Assets<Image>>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    let lines: f32 = wheel
+        .iter()
+        .map(|e| match e.unit {
+            MouseScrollUnit::Line => e.y,
+            MouseScrollUnit::Pixel => e.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    // Nothing changes under a capture, which would then show it.
+    if !photo.active || capture.image.is_some() {
+        return;
+    }
+    if keys.just_pressed(CLOSE_KEY) {
+        photo.active = false;
+        paused.0 = false;
+        return;
+    }
+
+    let dt = time.delta_seconds();
+    let mut scale = photo.scale / config.wheel_step.powf(lines);
+    if keys.pressed(ZOOM_OUT_KEY) {
+        scale *= config.zoom_speed.powf(dt);
+    }
+    if keys.pressed(ZOOM_IN_KEY) {
+        scale /= config.zoom_speed.powf(dt);
+    }
+    photo.scale = scale.clamp(config.min_scale, config.max_scale);
+
+    let pressed = |a: KeyCode, b: KeyCode| keys.pressed(a) || keys.pressed(b);
+    let mut direction = Vec2::ZERO;
+    if pressed(KeyCode::Left, KeyCode::A) {
+        direction.x -= 1.;
+    }
+    if pressed(KeyCode::Right, KeyCode::D) {
+        direction.x += 1.;
+    }
+    if pressed(KeyCode::Down, KeyCode::S) {
+        direction.y -= 1.;
+    }
+    if pressed(KeyCode::Up, KeyCode::W) {
+        direction.y += 1.;
+    }
+    let step = direction.normalize_or_zero() * config.pan_speed * photo.scale * dt;
+    photo.center += step;
+
+    if keys.just_pressed(ACTORS_KEY) {
+        photo.hide_actors = !photo.hide_actors;
+    }
+    if keys.just_pressed(FOG_KEY) {
+        photo.hide_fog = !photo.hide_fog;
+    }
+    if let Some(i) = SUPERSAMPLE_KEYS.iter().position(|k| keys.just_pressed(*k)) {
+        config.supersample = i as u32 + 2;
+    }
+
+    if !keys.just_pressed(CAPTURE_KEY) {
+        return;
+    }
+    let Ok(window) = windows.get_single() else { return };
+    let physical = UVec2::new(window.physical_width(), window.physical_height()).max(UVec2::ONE);
+    let largest = (MAX_CAPTURE_SIZE / physical.max_element()).max(1);
+    let factor = config.supersample.clamp(2, 4).min(largest);
+    let size = physical * factor;
+    *capture = PhotoCapture {
+        image: Some(images.add(capture_image(size))),
+        factor: factor as f32 * window.scale_factor() as f32,
+        ..default()
+    };
+    info!("Taking a {}x{} photo.", size.x, size.y);
+}
+
+/// An image `size` big for the camera to draw into and to copy back from.
+fn capture_image(size: UVec2) -> Image {
+    let extent = Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image {
+        data: vec![0; (size.x * size.y * 4) as usize],
+        ..default()
+    };
+    image.texture_descriptor.size = extent;
+    image.texture_descriptor.format = TextureFormat::Rgba8UnormSrgb;
+    image.texture_descriptor.usage = TextureUsages::RENDER_ATTACHMENT
+        | TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_SRC
+        | TextureUsages::COPY_DST;
+    image
+}
+
+/// What photo mode moves and points of the main camera.
+type CameraItem<'a> = (
+    Entity,
+    &'a mut Camera,
+    &'a mut Transform,
+    &'a mut OrthographicProjection,
+);
+
+/// Holds the camera where photo mode put it, in place of following the
+/// players, and points it at a capture while one is under way. The scale
+/// is divided by the capture's factor, so the image shows just what the
+/// window does.
+fn aim_photo_camera(
+    mut commands: Commands,
+    mut photo: ResMut<PhotoMode>,
+    capture: Res<PhotoCapture>,
+    mut cameras: Query<CameraItem, With<Camera2d>>,
+) {
+    let Ok((entity, mut camera, mut transform, mut projection)) = cameras.get_single_mut() else {
+        return;
+    };
+    let show_ui = match &capture.image {
+        Some(image) if photo.target.is_none() => {
+            photo.target = Some(camera.target.clone());
+            camera.target = RenderTarget::Image(image.clone());
+            Some(false)
+        }
+        None => photo.target.take().map(|target| {
+            camera.target = target;
+            true
+        }),
+        _ => None,
+    };
+    if let Some(show_ui) = show_ui {
+        commands.entity(entity).insert(UiCameraConfig { show_ui });
+    }
+    if !photo.active {
+        return;
+    }
+    transform.translation = photo.center.extend(transform.translation.z);
+    projection.scale = match capture.image {
+        Some(_) => photo.scale / capture.factor,
+        None => photo.scale,
+    };
+}
+
+/// Saves a capture once its pixels are back, or gives it up if they never
+/// come, and hands the camera back to the window.
+fn finish_capture(mut capture: ResMut<PhotoCapture>, mut images: ResMut<Assets<Image>>) {
+    let Some(image) = capture.image.clone() else { return };
+    capture.frames += 1;
+    capture.take = capture.frames >= CAPTURE_FRAMES;
+    let pixels = capture.pixels.lock().map_or(None, |mut p| p.take());
+    match pixels {
+        Some(pixels) => match images.get(&image) {
+            Some(drawn) => {
+                let size = drawn.texture_descriptor.size;
+                save_photo(UVec2::new(size.width, size.height), pixels);
+            }
+            None => warn!("The photo's image was dropped before it was saved."),
+        },
+        None if capture.frames > CAPTURE_FRAMES + CAPTURE_TIMEOUT => {
+            warn!("Gave up on a photo that was never drawn.");
+        }
+        None => return,
+    }
+    images.remove(&image);
+    *capture = PhotoCapture::default();
+}
+
+/// Writes `pixels`, rows of RGBA `size` across and down, to a new PNG in
+/// `PHOTO_DIR`, off the main thread.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_photo(size: UVec2, pixels: Vec<u8>) {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let stamp = (SystemTime::now().duration_since(UNIX_EPOCH)).map_or(0, |d| d.as_millis());
+    let path = std::path::Path::new(PHOTO_DIR).join(format!("photo_{}.png", stamp));
+    bevy::tasks::IoTaskPool::get()
+        .spawn(async move {
+            let extent = Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            };
+            let image = Image::new(
+                extent,
+                TextureDimension::D2,
+                pixels,
+                TextureFormat::Rgba8UnormSrgb,
+            );
+            let saved = (std::fs::create_dir_all(PHOTO_DIR).map_err(|e| e.to_string()))
+                .and_then(|_| image.try_into_dynamic().map_err(|e| format!("{:?}", e)))
+                .and_then(|image| image.save(&path).map_err(|e| e.to_string()));
+            match saved {
+                Ok(()) => info!("Saved a photo to {}.", path.display()),
+                Err(e) => warn!("Could not save a photo to {}: {}", path.display(), e),
+            }
+        })
+        .detach();
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_photo(_size: UVec2, _pixels: Vec<u8>) {
+    warn!("Photos cannot be saved in the browser.");
+}
+
+/// Copies a drawn capture off the GPU, without the padding rows are copied
+/// with.
+fn read_back(
+    capture: Res<PhotoCapture>,
+    images: Res<RenderAssets<Image>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    if !capture.take {
+        return;
+    }
+    let Some(image) = capture.image.as_ref().and_then(|i| images.get(i)) else { return };
+    let Ok(mut pixels) = capture.pixels.lock() else { return };
+    if pixels.is_some() {
+        return;
+    }
+    let (width, height) = (image.size.x as u32, image.size.y as u32);
+    let row = width * 4;
+    let padded = row.div_ceil(ROW_ALIGNMENT) * ROW_ALIGNMENT;
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("photo_read_back"),
+        size: (padded * height) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, |_| {});
+    device.wgpu_device().poll(Maintain::Wait);
+    let data = slice.get_mapped_range();
+    let rows = data.chunks(padded as usize);
+    *pixels = Some(rows.flat_map(|r| &r[..row as usize]).copied().collect());
+}
+
+/// The top of each panel of HUD or menu, bar the letterbox and the hint.
+type UiRoot = (
+    With<Node>,
+    Without<Parent>,
+    Without<LetterboxBar>,
+    Without<PhotoHint>,
+);
+/// Sprites on their own, overlays among them by their z.
+type SpriteRoot = (
+    Or<(With<Sprite>, With<TextureAtlasSprite>)>,
+    Without<Node>,
+    Without<Parent>,
+);
+type Actor = Or<(
+    With<Player>,
+    With<Health>,
+    With<Chaser>,
+    With<RangedAi>,
+    With<Follower>,
+)>;
+
+/// Hides the HUD, menus and overlays while photo mode is open, and actors
+/// too if asked, and gives back each its visibility as it stops needing to
+/// be hidden. Anything an owner shows again meanwhile is hidden again, and
+/// goes back to what the owner set.
+#[allow(clippy::too_many_arguments)]
+fn hide_for_photo(
+    mut photo: ResMut<PhotoMode>,
+    mut fog: ResMut<FogOfWar>,
+    ui: Query<Entity, UiRoot>,
+    sprites: Query<(Entity, &Transform), SpriteRoot>,
+    actors: Query<Entity, Actor>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    if !photo.active && photo.hidden.is_empty() && photo.fog.is_none() {
+        return;
+    }
+
+    let hide_fog = photo.active && photo.hide_fog;
+    match photo.fog {
+        None if hide_fog => {
+            photo.fog = Some(fog.0);
+            fog.0 = false;
+        }
+        Some(prior) if !hide_fog => {
+            fog.0 = prior;
+            photo.fog = None;
+        }
+        _ => {}
+    }
+
+    let mut wanted = HashSet::new();
+    if photo.active {
+        let overlay = RenderLayerSlot::Overlay.range().start as f32;
+        wanted.extend(ui.iter());
+        let overlays = sprites.iter().filter(|(_, t)| t.translation.z >= overlay);
+        wanted.extend(overlays.map(|(e, _)| e));
+        if photo.hide_actors {
+            wanted.extend(actors.iter());
+        }
+    }
+
+    let photo = &mut *photo;
+    photo.hidden.retain(|entity, prior| {
+        if wanted.contains(entity) {
+            return true;
+        }
+        if let Ok(mut visibility) = visibilities.get_mut(*entity) {
+            *visibility = *prior;
+        }
+        false
+    });
+    for entity in wanted {
+        let Ok(mut visibility) = visibilities.get_mut(entity) else { continue };
+        if *visibility != Visibility::Hidden {
+            photo.hidden.insert(entity, *visibility);
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// Shows the keys while photo mode is open, out of any capture.
+fn draw_hint(
+    mut commands: Commands,
+    photo: Res<PhotoMode>,
+    config: Res<PhotoConfig>,
+    capture: Res<PhotoCapture>,
+    locale: Res<Localization>,
+    assets: Res<GraphicsAssets>,
+    mut hints: Query<(Entity, &mut Text, &mut Visibility), With<PhotoHint>>,
+) {
+    if !photo.active {
+        for (entity, ..) in hints.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    let text = t!(locale, "photo.help", factor = config.supersample);
+    if let Some((_, mut shown, mut visibility)) = hints.iter_mut().next() {
+        if shown.sections[0].value != text {
+            shown.sections[0].value = text;
+        }
+        *visibility = match capture.image {
+            Some(_) => Visibility::Hidden,
+            None => Visibility::Inherited,
+        };
+        return;
+    }
+
+    commands.spawn((
+        PhotoHint,
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: HINT_FONT_SIZE,
+                color: HINT_COLOR,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(HINT_FONT_SIZE / 2.),
+                bottom: Val::Px(HINT_FONT_SIZE / 2.),
+                ..default()
+            },
+            ..default()
+        }),
+    ));
+}
+
+fn close_photo_mode(
+    mut commands: Commands,
+    mut photo: ResMut<PhotoMode>,
+    mut capture: ResMut<PhotoCapture>,
+    mut paused: ResMut<SimulationPaused>,
+    hints: Query<Entity, With<PhotoHint>>,
+) {
+    if photo.active {
+        photo.active = false;
+        paused.0 = false;
+    }
+    // Dropped unsaved, and the camera handed back to the window.
+    if capture.image.is_some() {
+        *capture = PhotoCapture::default();
+    }
+    for entity in hints.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,649 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    board::TileChangedEvent,
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    render_layers::RenderLayerSlot,
+    simulation::SimulationSet,
+    terrain::{Capabilities, TerrainRegistry},
+    vectors::{Direction, GridRect, Vector3Int},
+    CurrentBoard, Position, Tile, LAYER_Z_STRIDE,
+};
+
+/// What a cell stops, as flags that combine with `|`.
+///
+/// In map data, a list of flag names such as `["block_walk", "pit"]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(from = "Vec<CollisionFlag>")]
+pub struct CollisionFlags(u8);
+
+impl CollisionFlags {
+    pub const NONE: Self = CollisionFlags(0);
+    /// Nothing walks in, as a low fence stops walkers but not blasts.
+    pub const BLOCK_WALK: Self = CollisionFlags(1);
+    /// Nothing is seen or heard through, as a curtain.
+    pub const BLOCK_SIGHT: Self = CollisionFlags(1 << 1);
+    /// Nothing flies or blasts through.
+    pub const BLOCK_FLY: Self = CollisionFlags(1 << 2);
+    /// A hole: nothing walks in, but dashes cross it and pushed blocks
+    /// fall in.
+    pub const PIT: Self = CollisionFlags(1 << 3);
+    /// A wall, as a closed door or a 1 in a collision layer.
+    pub const SOLID: Self =
+        CollisionFlags(Self::BLOCK_WALK.0 | Self::BLOCK_SIGHT.0 | Self::BLOCK_FLY.0);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether any of `other`'s flags are set.
+    pub fn intersects(self, other: CollisionFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for CollisionFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        CollisionFlags(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for CollisionFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// A single flag by name, for map data.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CollisionFlag {
+    BlockWalk,
+    BlockSight,
+    BlockFly,
+    Pit,
+    Solid,
+}
+
+impl From<Vec<CollisionFlag>> for CollisionFlags {
+    fn from(flags: Vec<CollisionFlag>) -> Self {
+        (flags.into_iter())
+            .map(|flag| match flag {
+                CollisionFlag::BlockWalk => CollisionFlags::BLOCK_WALK,
+                CollisionFlag::BlockSight => CollisionFlags::BLOCK_SIGHT,
+                CollisionFlag::BlockFly => CollisionFlags::BLOCK_FLY,
+                CollisionFlag::Pit => CollisionFlags::PIT,
+                CollisionFlag::Solid => CollisionFlags::SOLID,
+            })
+            .fold(CollisionFlags::NONE, |a, b| a | b)
+    }
+}
+
+/// What each cell stops. Cells are kept per walkable layer, so everything
+/// within a layer's z band collides with the same cells.
+///
+/// Cells set from the map and by doors are kept apart from those of tiles,
+/// which follow the tile on the cell as it changes. One-way cells, what
+/// terrain requires and what it costs to cross come only from tiles.
+#[derive(Default, Resource)]
+pub struct CollisionMap {
+    cells: HashMap<Vector3Int, CollisionFlags>,
+    tiles: HashMap<Vector3Int, CollisionFlags>,
+    one_way: HashMap<Vector3Int, Direction>,
+    requires: HashMap<Vector3Int, Capabilities>,
+    /// Terrain costing other than 1 to step into.
+    move_costs: HashMap<Vector3Int, f32>,
+}
+
+impl CollisionMap {
+    fn key(v: Vector3Int) -> Vector3Int {
+        Vector3Int::new(v.x, v.y, layer_of(v.z))
+    }
+
+    /// Walls off `v` entirely.
+    pub fn block(&mut self, v: Vector3Int) {
+        self.set(v, CollisionFlags::SOLID);
+    }
+
+    pub fn unblock(&mut self, v: Vector3Int) {
+        self.cells.remove(&Self::key(v));
+    }
+
+    /// Adds `flags` to those of `v`.
+    pub fn set(&mut self, v: Vector3Int, flags: CollisionFlags) {
+        if !flags.is_empty() {
+            *self.cells.entry(Self::key(v)).or_default() |= flags;
+        }
+    }
+
+    /// Replaces the flags and the one-way direction the tile at `v`
+    /// brings.
+    pub fn set_tile(&mut self, v: Vector3Int, flags: CollisionFlags, one_way: Option<Direction>) {
+        if flags.is_empty() {
+            self.tiles.remove(&Self::key(v));
+        } else {
+            self.tiles.insert(Self::key(v), flags);
+        }
+        match one_way {
+            Some(way) => self.one_way.insert(Self::key(v), way),
+            None => self.one_way.remove(&Self::key(v)),
+        };
+    }
+
+    /// Replaces what the terrain of the floor at `v` requires to enter.
+    pub fn set_requires(&mut self, v: Vector3Int, requires: Capabilities) {
+        if requires.is_empty() {
+            self.requires.remove(&Self::key(v));
+        } else {
+            self.requires.insert(Self::key(v), requires);
+        }
+    }
+
+    /// What entering `v` requires of an actor that does not fly.
+    pub fn requires(&self, v: Vector3Int) -> Capabilities {
+        let key = Self::key(v);
+        self.requires.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Replaces what stepping into `v` costs, by its floor's terrain.
+    pub fn set_move_cost(&mut self, v: Vector3Int, cost: f32) {
+        if cost == 1. {
+            self.move_costs.remove(&Self::key(v));
+        } else {
+            self.move_costs.insert(Self::key(v), cost);
+        }
+    }
+
+    /// What stepping into `v` costs an actor with `capabilities`, for
+    /// routes to prefer cheaper terrain. Fliers pass over terrain as if it
+    /// were not there.
+    pub fn step_cost(&self, v: Vector3Int, capabilities: Capabilities) -> f32 {
+        if capabilities.contains(Capabilities::FLY) {
+            return 1.;
+        }
+        self.move_costs.get(&Self::key(v)).copied().unwrap_or(1.)
+    }
+
+    /// The least any step costs an actor with `capabilities`, for scaling
+    /// guesses of what a route has left to cost.
+    pub fn min_step_cost(&self, capabilities: Capabilities) -> f32 {
+        if capabilities.contains(Capabilities::FLY) {
+            return 1.;
+        }
+        self.move_costs
+            .values()
+            .fold(1f32, |a, b| a.min(*b))
+            .max(0.)
+    }
+
+    pub fn flags(&self, v: Vector3Int) -> CollisionFlags {
+        let key = Self::key(v);
+        let get = |map: &HashMap<Vector3Int, CollisionFlags>| map.get(&key).copied();
+        get(&self.cells).unwrap_or_default() | get(&self.tiles).unwrap_or_default()
+    }
+
+    /// Whether `v` stops anything `flags` names.
+    pub fn blocks(&self, v: Vector3Int, flags: CollisionFlags) -> bool {
+        self.flags(v).intersects(flags)
+    }
+
+    /// Whether `v` cannot be walked into: walled off, a pit, or terrain
+    /// that needs more than walking, such as water.
+    pub fn is_blocked(&self, v: Vector3Int) -> bool {
+        self.is_blocked_for(v, Capabilities::NONE)
+    }
+
+    /// Whether an actor with `capabilities` cannot enter `v`. Fliers are
+    /// stopped only by what stops flying; anything else by walls, pits,
+    /// and terrain requiring what it cannot do.
+    pub fn is_blocked_for(&self, v: Vector3Int, capabilities: Capabilities) -> bool {
+        if capabilities.contains(Capabilities::FLY) {
+            return self.blocks(v, CollisionFlags::BLOCK_FLY);
+        }
+        self.blocks(v, CollisionFlags::BLOCK_WALK | CollisionFlags::PIT)
+            || !capabilities.contains(self.requires(v))
+    }
+
+    pub fn is_pit(&self, v: Vector3Int) -> bool {
+        self.blocks(v, CollisionFlags::PIT)
+    }
+
+    /// The only way `v` may be crossed, if it is a one-way cell.
+    pub fn one_way(&self, v: Vector3Int) -> Option<Direction> {
+        self.one_way.get(&Self::key(v)).copied()
+    }
+
+    /// Every one-way cell and its direction, with the layer for z.
+    pub fn one_way_cells(&self) -> impl Iterator<Item = (Vector3Int, Direction)> + '_ {
+        self.one_way.iter().map(|(v, way)| (*v, *way))
+    }
+
+    /// Whether a step of `dir` from `from` into `to` goes the way of each
+    /// one-way cell it leaves or enters. Changes of layer are not counted.
+    pub fn allows_step(&self, from: Vector3Int, to: Vector3Int, dir: Vector3Int) -> bool {
+        let flat = Vector3Int::new(dir.x, dir.y, 0);
+        [from, to]
+            .into_iter()
+            .all(|v| self.one_way(v).is_none_or(|way| way.step() == flat))
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.tiles.clear();
+        self.one_way.clear();
+        self.requires.clear();
+        self.move_costs.clear();
+    }
+
+    /// Clears the cells `keep` rejects. Cells are given with the layer for
+    /// z.
+    pub fn retain(&mut self, mut keep: impl FnMut(Vector3Int) -> bool) {
+        self.cells.retain(|v, _| keep(*v));
+        self.tiles.retain(|v, _| keep(*v));
+        self.one_way.retain(|v, _| keep(*v));
+        self.requires.retain(|v, _| keep(*v));
+        self.move_costs.retain(|v, _| keep(*v));
+    }
+}
+
+/// An entity that takes up its cell; no two occupiers can share one.
+#[derive(Component)]
+pub struct Occupier;
+
+/// The cells an entity covers, relative to its `Position`.
+///
+/// The position is the anchor: cell (0, 0) of the rect, which for
+/// `Footprint::new` is the min (bottom-left) corner. `get_world_position`
+/// gives the centre of that cell and sprites are anchored to match.
+#[derive(Component, Clone, Copy)]
+pub struct Footprint(pub GridRect);
+
+impl Footprint {
+    pub fn new(width: i32, height: i32) -> Self {
+        Footprint(GridRect::new(0, 0, width, height))
+    }
+}
+
+/// Cells covered by an entity at `v`: just `v` unless it has a footprint.
+pub fn covered_cells(v: Vector3Int, footprint: Option<&Footprint>) -> Vec<Vector3Int> {
+    match footprint {
+        Some(Footprint(rect)) => rect.cells(v).collect(),
+        None => vec![v],
+    }
+}
+
+/// Whether an entity at `v` covers the cell `at`, as bumps into it are
+/// resolved.
+pub fn covers(v: Vector3Int, footprint: Option<&Footprint>, at: Vector3Int) -> bool {
+    (covered_cells(v, footprint).iter()).any(|c| c.manhattan(at) == 0)
+}
+
+/// Whether `entity`, able to do `capabilities`, could stand at `v`: every
+/// covered cell has ground and is neither blocked to it nor taken by
+/// another occupier.
+pub fn footprint_fits(
+    entity: Entity,
+    v: Vector3Int,
+    footprint: Option<&Footprint>,
+    capabilities: Capabilities,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+    occupancy: &Occupancy,
+) -> bool {
+    covered_cells(v, footprint).into_iter().all(|cell| {
+        let cell = current.wrap(cell);
+        current.has_ground(cell)
+            && !collision.is_blocked_for(cell, capabilities)
+            && occupancy.get(cell).is_none_or(|e| e == entity)
+    })
+}
+
+/// What stepping an entity's anchor into `v` costs: the dearest of the
+/// cells it would cover.
+pub fn footprint_step_cost(
+    v: Vector3Int,
+    footprint: Option<&Footprint>,
+    capabilities: Capabilities,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+) -> f32 {
+    (covered_cells(v, footprint).into_iter())
+        .map(|cell| collision.step_cost(current.wrap(cell), capabilities))
+        .fold(0., f32::max)
+}
+
+/// Whether an entity at `from` may step `dir` as far as one-way cells go,
+/// over every cell of its footprint.
+pub fn step_allowed(
+    from: Vector3Int,
+    dir: Vector3Int,
+    footprint: Option<&Footprint>,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+) -> bool {
+    covered_cells(from, footprint)
+        .into_iter()
+        .all(|cell| collision.allows_step(current.wrap(cell), current.wrap(cell + dir), dir))
+}
+
+/// Which occupier stands on each cell of each layer, kept in sync with
+/// their `Position`.
+#[derive(Default, Resource)]
+pub struct Occupancy {
+    cells: HashMap<Vector3Int, Entity>,
+    entities: HashMap<Entity, Vec<Vector3Int>>,
+}
+
+impl Occupancy {
+    fn key(v: Vector3Int) -> Vector3Int {
+        Vector3Int::new(v.x, v.y, layer_of(v.z))
+    }
+
+    pub fn get(&self, v: Vector3Int) -> Option<Entity> {
+        self.cells.get(&Self::key(v)).copied()
+    }
+
+    pub fn is_occupied(&self, v: Vector3Int) -> bool {
+        self.cells.contains_key(&Self::key(v))
+    }
+
+    /// Every occupied cell, with its layer in place of `z`, in no order.
+    pub fn cells(&self) -> impl Iterator<Item = Vector3Int> + '_ {
+        self.cells.keys().copied()
+    }
+
+    pub fn insert(&mut self, entity: Entity, cells: impl IntoIterator<Item = Vector3Int>) {
+        self.remove(entity);
+        let keys: Vec<Vector3Int> = cells.into_iter().map(Self::key).collect();
+        for key in keys.iter() {
+            self.cells.insert(*key, entity);
+        }
+        self.entities.insert(entity, keys);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        for key in self.entities.remove(&entity).unwrap_or_default() {
+            if self.cells.get(&key) == Some(&entity) {
+                self.cells.remove(&key);
+            }
+        }
+    }
+}
+
+pub struct CollisionPlugin;
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionMap>()
+            .init_resource::<Occupancy>()
+            // Once per tick for the simulation, and once per frame for
+            // anything moved or despawned outside of it.
+            .add_system(
+                update_occupancy
+                    .in_set(SimulationSet::Resolve)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(update_occupancy.in_base_set(CoreSet::PostUpdate))
+            .add_system(update_tile_collision.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_occupancy(
+    query: Query<
+        (Entity, &Position, Option<&Footprint>),
+        (With<Occupier>, Or<(Changed<Position>, Changed<Footprint>)>),
+    >,
+    mut removed: RemovedComponents<Occupier>,
+    mut occupancy: ResMut<Occupancy>,
+) {
+    for entity in removed.iter() {
+        occupancy.remove(entity);
+    }
+    for (entity, position, footprint) in query.iter() {
+        occupancy.insert(entity, covered_cells(position.v, footprint));
+    }
+}
+
+/// Keeps the flags and one-way directions tiles bring in step with their
+/// metadata, over every tile in a cell's layer, as tiles are spawned,
+/// changed and removed. The lowest one-way tile of a layer decides its
+/// direction, and the floor's terrain what entering it requires and
+/// costs.
+fn update_tile_collision(
+    changed: Query<&Position, Changed<Tile>>,
+    mut events: EventReader<TileChangedEvent>,
+    tiles: Query<&Tile>,
+    current: Res<CurrentBoard>,
+    metadata: Res<TileMetadataRegistry>,
+    terrain: Res<TerrainRegistry>,
+    mut collision: ResMut<CollisionMap>,
+) {
+    let removed = (events.iter())
+        .filter(|event| event.after.is_none())
+        .map(|event| event.position);
+    let cells: HashSet<Vector3Int> = (changed.iter().map(|p| p.v))
+        .chain(removed)
+        .map(|v| Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), 0)))
+        .collect();
+    for v in cells {
+        let index = |entity| tiles.get(entity).ok().map(|tile| tile.i);
+        refresh_layer(v, index, &current, &metadata, &terrain, &mut collision);
+    }
+}
+
+/// Sets what the tiles of the layer at `v`, given with the layer's base z,
+/// bring from their metadata and the floor's terrain, with `index` giving
+/// the atlas index of a tile entity.
+pub(crate) fn refresh_layer(
+    v: Vector3Int,
+    index: impl Fn(Entity) -> Option<usize>,
+    current: &CurrentBoard,
+    metadata: &TileMetadataRegistry,
+    terrain: &TerrainRegistry,
+    collision: &mut CollisionMap,
+) {
+    // Roofs are overhead, so stop nothing.
+    let layer: Vec<_> = (0..LAYER_Z_STRIDE)
+        .filter(|offset| !RenderLayerSlot::Roof.range().contains(offset))
+        .filter_map(|offset| {
+            let cell = Vector3Int::new(v.x, v.y, v.z + offset);
+            metadata.0.get(&index(*current.tiles.get(&cell)?)?)
+        })
+        .collect();
+    let flags = (layer.iter().map(|m| m.collision)).fold(CollisionFlags::NONE, |a, b| a | b);
+    let one_way = layer.iter().find_map(|m| m.one_way);
+    collision.set_tile(v, flags, one_way);
+    let floor = current.floor(v).and_then(index);
+    let requires = floor.map_or(Capabilities::NONE, |i| terrain.requires(i));
+    collision.set_requires(v, requires);
+    let hooks = floor.and_then(|i| terrain.hooks(i));
+    collision.set_move_cost(v, hooks.map_or(1., |h| h.move_cost));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pathfinding::{find_path, unit_cost, Path},
+        vectors::{GridRect, ORTHO_DIRECTIONS},
+    };
+
+    /// Cells along each side of the open ground paths are found over.
+    const SIZE: i32 = 10;
+
+    /// A ledge across the ground on row 5, which can only be dropped down.
+    fn ledge() -> CollisionMap {
+        let mut collision = CollisionMap::default();
+        for x in 0..SIZE {
+            let v = Vector3Int::new(x, 5, 0);
+            collision.set_tile(v, CollisionFlags::NONE, Some(Direction::Down));
+        }
+        collision
+    }
+
+    /// The cells of the shortest way over the ground from `from` to `goal`
+    /// for an entity with `footprint`, if there is one.
+    fn path(
+        collision: &CollisionMap,
+        footprint: Option<&Footprint>,
+        from: Vector3Int,
+        goal: Vector3Int,
+    ) -> Option<Vec<Vector3Int>> {
+        let current = CurrentBoard::default();
+        let ground = GridRect::new(0, 0, SIZE, SIZE);
+        let path = find_path(
+            from,
+            |v| ORTHO_DIRECTIONS.map(|dir| v + dir),
+            |v| v == goal,
+            |from, to| {
+                let dir = Vector3Int::new(to.x - from.x, to.y - from.y, 0);
+                (covered_cells(to, footprint).iter()).all(|cell| ground.contains(*cell))
+                    && step_allowed(from, dir, footprint, &current, collision)
+            },
+            unit_cost,
+            |_| 0.,
+            usize::MAX,
+        );
+        path.map(|path| path.cells)
+    }
+
+    #[test]
+    fn ledges_are_dropped_down_but_not_climbed() {
+        let collision = ledge();
+        let (top, bottom) = (Vector3Int::new(2, 8, 0), Vector3Int::new(7, 1, 0));
+
+        let down = path(&collision, None, top, bottom).unwrap();
+        assert_eq!(down.len(), 12);
+        // Across the ledge straight down, never along it.
+        let on_ledge: Vec<_> = down.iter().filter(|v| v.y == 5).collect();
+        assert_eq!(on_ledge.len(), 1);
+        assert!(path(&collision, None, bottom, top).is_none());
+
+        // Without the ledge the way up is open.
+        assert!(path(&CollisionMap::default(), None, bottom, top).is_some());
+    }
+
+    #[test]
+    fn every_cell_of_a_footprint_obeys_the_ledge() {
+        let collision = ledge();
+        let footprint = Footprint::new(2, 2);
+        let (top, bottom) = (Vector3Int::new(2, 7, 0), Vector3Int::new(6, 1, 0));
+
+        assert!(path(&collision, Some(&footprint), top, bottom).is_some());
+        assert!(path(&collision, Some(&footprint), bottom, top).is_none());
+    }
+
+    /// A river down columns 4 and 5, bridged on the top row, with pits on
+    /// its far bank in the way of the shortest crossing.
+    fn river() -> CollisionMap {
+        let mut collision = CollisionMap::default();
+        for y in 0..SIZE - 1 {
+            for x in 4..6 {
+                let v = Vector3Int::new(x, y, 0);
+                collision.set_requires(v, Capabilities::SWIM);
+                collision.set_move_cost(v, 2.);
+            }
+        }
+        for y in 0..3 {
+            collision.set(Vector3Int::new(7, y, 0), CollisionFlags::PIT);
+        }
+        collision
+    }
+
+    /// The cheapest way over the ground from `from` to `goal` for an actor
+    /// with `capabilities`, if there is one.
+    fn route(
+        collision: &CollisionMap,
+        capabilities: Capabilities,
+        from: Vector3Int,
+        goal: Vector3Int,
+    ) -> Option<Path> {
+        let ground = GridRect::new(0, 0, SIZE, SIZE);
+        find_path(
+            from,
+            |v| ORTHO_DIRECTIONS.map(|dir| v + dir),
+            |v| v == goal,
+            |_, to| ground.contains(to) && !collision.is_blocked_for(to, capabilities),
+            |_, to| collision.step_cost(to, capabilities),
+            |v| v.manhattan(goal) as f32 * collision.min_step_cost(capabilities),
+            usize::MAX,
+        )
+    }
+
+    #[test]
+    fn walkers_swimmers_and_fliers_take_their_own_ways() {
+        let collision = river();
+        let (from, goal) = (Vector3Int::new(1, 1, 0), Vector3Int::new(8, 1, 0));
+        let in_water = |v: &Vector3Int| (4..6).contains(&v.x) && v.y < SIZE - 1;
+
+        // Walkers keep out of the water and go round by the bridge.
+        let walker = route(&collision, Capabilities::NONE, from, goal).unwrap();
+        assert!(!walker.cells.iter().any(in_water));
+        assert!(walker.cells.iter().any(|v| v.y == SIZE - 1));
+        assert_eq!(walker.cost, 23.);
+
+        // Swimmers cross, at twice the cost of a step on land, then go
+        // round the pits.
+        let swimmer = route(&collision, Capabilities::SWIM, from, goal).unwrap();
+        assert_eq!(swimmer.cells.iter().filter(|v| in_water(v)).count(), 2);
+        assert!(!swimmer.cells.iter().any(|v| collision.is_pit(*v)));
+        assert_eq!(swimmer.cost, 13.);
+
+        // Fliers go straight over both.
+        let flier = route(&collision, Capabilities::FLY, from, goal).unwrap();
+        assert!(flier.cells.iter().all(|v| v.y == 1));
+        assert_eq!(flier.cost, 7.);
+
+        assert_ne!(walker.cells, swimmer.cells);
+        assert_ne!(swimmer.cells, flier.cells);
+    }
+
+    /// Swamp costing `swamp` along row 1 between columns 1 and 5, and road
+    /// costing `road` along row 2 from column 0 to 6, as a way round it.
+    fn swamp_and_road(swamp: f32, road: f32) -> CollisionMap {
+        let mut collision = CollisionMap::default();
+        for x in 1..6 {
+            collision.set_move_cost(Vector3Int::new(x, 1, 0), swamp);
+        }
+        for x in 0..7 {
+            collision.set_move_cost(Vector3Int::new(x, 2, 0), road);
+        }
+        collision
+    }
+
+    #[test]
+    fn roads_are_taken_round_swamp_when_cheaper() {
+        let (from, goal) = (Vector3Int::new(0, 1, 0), Vector3Int::new(6, 1, 0));
+        let in_swamp = |path: &Path| path.cells.iter().any(|v| v.y == 1 && v.x < 6);
+        let walk = |swamp, road| {
+            let collision = swamp_and_road(swamp, road);
+            route(&collision, Capabilities::NONE, from, goal).unwrap()
+        };
+
+        // Through five cells of swamp and onto the goal, or eight steps
+        // round by the road.
+        for (swamp, road, through) in [(3., 1., false), (1.5, 1., false), (1.25, 1., true)] {
+            let path = walk(swamp, road);
+            assert_eq!(in_swamp(&path), through, "swamp {}, road {}", swamp, road);
+            let cost = if through { 5. * swamp + 1. } else { 8. };
+            assert_eq!(path.cost, cost, "swamp {}, road {}", swamp, road);
+        }
+
+        // A road cheaper than open ground is still found, though the way
+        // across takes fewer steps, as guesses go by the cheapest step.
+        let path = walk(1., 0.5);
+        assert!(!in_swamp(&path));
+        assert_eq!(path.cost, 7. * 0.5 + 1.);
+
+        // Fliers pay no heed to either.
+        let collision = swamp_and_road(3., 1.);
+        let flier = route(&collision, Capabilities::FLY, from, goal).unwrap();
+        assert!(in_swamp(&flier));
+        assert_eq!(flier.cost, 6.);
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::Occupancy,
+    elements::{Element, ElementConfig, Resistances},
+    equipment::StatSheet,
+    followers::Follower,
+    knockback::{KnockbackEvent, HIT_KNOCKBACK},
+    player::{BumpEvent, Player},
+    simulation::{SimulationApp, SimulationSet},
+    status::StatusEffects,
+    CurrentBoard, Position,
+};
+
+/// Damage a player deals by bumping into something with `Health`, before
+/// equipment.
+pub const BUMP_ATTACK_DAMAGE: u32 = 1;
+
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Health { current: max, max }
+    }
+}
+
+/// What a player's bump attack would do to a creature, worked out without
+/// doing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttackPreview {
+    pub damage: u32,
+    /// The creature's health afterwards.
+    pub remaining: u32,
+}
+
+impl AttackPreview {
+    pub fn defeats(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// The damage a bump attack of `attack`, after resistances, deals to
+/// `target`, for anything that shows it beforehand.
+pub fn preview_attack(attack: u32, target: &Health) -> AttackPreview {
+    let damage = attack.min(target.current);
+    AttackPreview {
+        damage,
+        remaining: target.current - damage,
+    }
+}
+
+/// `amount` damage to `entity`, before its resistances to `element`.
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub amount: u32,
+    pub element: Element,
+}
+
+/// Sent when a creature is defeated, just before it is despawned.
+pub struct DiedEvent {
+    pub entity: Entity,
+}
+
+pub struct CombatPlugin;
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_event::<DamageEvent>()
+            .add_simulation_event::<DiedEvent>()
+            .add_systems(
+                (bump_attack, apply_damage, despawn_dead)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// Bumping into any cell covered by a creature attacks it, unless it is
+/// a follower.
+#[allow(clippy::type_complexity)]
+fn bump_attack(
+    mut bumps: EventReader<BumpEvent>,
+    players: Query<(&Position, Option<&StatSheet>), With<Player>>,
+    targets: Query<(), (With<Health>, Without<Player>, Without<Follower>)>,
+    occupancy: Res<Occupancy>,
+    current: Res<CurrentBoard>,
+    mut damage: EventWriter<DamageEvent>,
+    mut knockback: EventWriter<KnockbackEvent>,
+) {
+    for bump in bumps.iter() {
+        let Ok((attacker, sheet)) = players.get(bump.entity) else { continue };
+        let Some(target) = occupancy.get(bump.at).filter(|e| targets.contains(*e)) else {
+            continue;
+        };
+        damage.send(DamageEvent {
+            entity: target,
+            amount: sheet.map_or(BUMP_ATTACK_DAMAGE, |s| s.attack),
+            element: Element::Physical,
+        });
+        knockback.send(KnockbackEvent::away(
+            target,
+            attacker.v,
+            bump.at,
+            HIT_KNOCKBACK,
+            &current,
+        ));
+    }
+}
+
+/// Takes each hit off its target's health, less what the target resists.
+#[allow(clippy::type_complexity)]
+fn apply_damage(
+    mut events: EventReader<DamageEvent>,
+    config: Res<ElementConfig>,
+    mut query: Query<(
+        &mut Health,
+        Option<&StatSheet>,
+        Option<&Resistances>,
+        Option<&StatusEffects>,
+    )>,
+) {
+    for event in events.iter() {
+        let Ok((mut health, sheet, own, effects)) = query.get_mut(event.entity) else {
+            continue;
+        };
+        let resisted = Resistances::of(sheet, own, effects).get(event.element);
+        let amount = config.resist(event.amount, resisted);
+        health.current = health.current.saturating_sub(amount);
+        info!(
+            "{:?} took {} {} damage ({}/{}).",
+            event.entity,
+            amount,
+            event.element.name(),
+            health.current,
+            health.max
+        );
+    }
+}
+
+/// Removes defeated creatures. Players stay, whatever their health.
+#[allow(clippy::type_complexity)]
+pub fn despawn_dead(
+    mut commands: Commands,
+    query: Query<(Entity, &Health), (Changed<Health>, Without<Player>)>,
+    mut died: EventWriter<DiedEvent>,
+) {
+    for (entity, health) in query.iter() {
+        if health.current == 0 {
+            info!("{:?} was defeated.", entity);
+            died.send(DiedEvent { entity });
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fmt, fs, io, str::FromStr};
+
+use bevy::{ecs::system::SystemState, prelude::*};
+
+use crate::{
+    board::{BoardCommands, BoardError, BoardQuery},
+    editor::{ExportError, MapExport},
+    grid_to_position,
+    inventory::{Currency, Inventory, COIN_ITEM},
+    player::{place_player, MoveTween, MovementState, Player},
+    prefabs::PrefabRegistry,
+    projection::GridProjection,
+    render_layers::{z_index, RenderLayerSlot},
+    replay::{advance_clock, LogicalClock},
+    simulation::SimulationSet,
+    speed::SimulationSpeed,
+    vectors::GridKind,
+    world_hash::WorldHash,
+    AppState, Position,
+};
+
+/// How far from the cell asked for `spawn` may put each actor.
+const SPAWN_RADIUS: u32 = 8;
+
+/// Every command by name, with how it is written. Cells are the map's
+/// columns and rows, tiles are atlas indices and players count from 1.
+pub const COMMANDS: [(&str, &str); 9] = [
+    ("teleport", "teleport <col> <row> [player]"),
+    ("spawn", "spawn <prefab> <col> <row> [count]"),
+    ("settile", "settile <col> <row> <tile|none> [layer]"),
+    ("give", "give <item> [count] [player]"),
+    ("speed", "speed <factor>"),
+    ("hash", "hash"),
+    ("exporttiled", "exporttiled <file>"),
+    ("wait", "wait <ticks>"),
+    ("set", "set on_error abort|continue"),
+];
+
+/// What a script does when one of its commands fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnError {
+    #[default]
+    Abort,
+    Continue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConsoleCommand {
+    /// Puts player `player` down on a cell, on the layer they are on.
+    Teleport {
+        col: i32,
+        row: i32,
+        player: usize,
+    },
+    /// Spawns `count` of a prefab, each as near the cell as there is room.
+    Spawn {
+        prefab: String,
+        col: i32,
+        row: i32,
+        count: u32,
+    },
+    /// Sets a cell of ground layer `layer`, or clears it for `None`.
+    SetTile {
+        col: i32,
+        row: i32,
+        tile: Option<usize>,
+        layer: i32,
+    },
+    /// Adds to player `player`'s inventory, or their purse for coins.
+    Give {
+        item: String,
+        count: u32,
+        player: usize,
+    },
+    Speed(f32),
+    /// Logs the world hash, as F1 does.
+    Hash,
+    /// Writes the map as edited to the exports as a Tiled map.
+    ExportTiled(String),
+    /// Holds back the rest of a script for this many ticks.
+    Wait(u64),
+    SetOnError(OnError),
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Unknown(String),
+    /// Arguments missing, left over or malformed; holds how the command
+    /// is written.
+    Usage(&'static str),
+    NoPlayer(usize),
+    UnknownItem(String),
+    /// The player has no free slot for the item.
+    NoRoom(String),
+    /// Fewer actors fit near the cell than were asked for.
+    Crowded {
+        placed: u32,
+        count: u32,
+    },
+    Board(BoardError),
+    Export(ExportError),
+    /// Waiting and error handling only mean something to a script.
+    ScriptOnly(&'static str),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "unknown command `{}`", name),
+            CommandError::Usage(usage) => write!(f, "usage: {}", usage),
+            CommandError::NoPlayer(index) => write!(f, "no player {}", index + 1),
+            CommandError::UnknownItem(item) => write!(f, "unknown item `{}`", item),
+            CommandError::NoRoom(item) => write!(f, "no room for `{}`", item),
+            CommandError::Crowded { placed, count } => {
+                write!(f, "room for only {} of {}", placed, count)
+            }
+            CommandError::Board(e) => e.fmt(f),
+            CommandError::Export(e) => e.fmt(f),
+            CommandError::ScriptOnly(name) => write!(f, "`{}` only works in scripts", name),
+        }
+    }
+}
+
+fn parse<T: FromStr>(word: &str, usage: &'static str) -> Result<T, CommandError> {
+    word.parse().map_err(|_| CommandError::Usage(usage))
+}
+
+fn parse_or<T: FromStr>(
+    word: Option<&&str>,
+    default: T,
+    usage: &'static str,
+) -> Result<T, CommandError> {
+    word.map_or(Ok(default), |word| parse(word, usage))
+}
+
+/// A player's number, from 1, as their index.
+fn parse_player(word: Option<&&str>, usage: &'static str) -> Result<usize, CommandError> {
+    let number: usize = parse_or(word, 1, usage)?;
+    number.checked_sub(1).ok_or(CommandError::Usage(usage))
+}
+
+/// Reads one line of commands: a name and its arguments, split on
+/// whitespace. Blank lines and everything after a `#` are nothing.
+pub fn parse_command(line: &str) -> Result<Option<ConsoleCommand>, CommandError> {
+    let line = line.split('#').next().unwrap_or_default();
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, args)) = words.split_first() else { return Ok(None) };
+    let Some(&(_, usage)) = COMMANDS.iter().find(|(known, _)| known == name) else {
+        return Err(CommandError::Unknown(name.to_string()));
+    };
+    let command = match (*name, args) {
+        ("teleport", [col, row, rest @ ..]) if rest.len() <= 1 => ConsoleCommand::Teleport {
+            col: parse(col, usage)?,
+            row: parse(row, usage)?,
+            player: parse_player(rest.first(), usage)?,
+        },
+        ("spawn", [prefab, col, row, rest @ ..]) if rest.len() <= 1 => ConsoleCommand::Spawn {
+            prefab: prefab.to_string(),
+            col: parse(col, usage)?,
+            row: parse(row, usage)?,
+            count: parse_or(rest.first(), 1, usage)?,
+        },
+        ("settile", [col, row, tile, rest @ ..]) if rest.len() <= 1 => ConsoleCommand::SetTile {
+            col: parse(col, usage)?,
+            row: parse(row, usage)?,
+            tile: match *tile {
+                "none" => None,
+                tile => Some(parse(tile, usage)?),
+            },
+            layer: parse_or(rest.first(), 0, usage)?,
+        },
+        ("give", [item, rest @ ..]) if rest.len() <= 2 => ConsoleCommand::Give {
+            item: item.to_string(),
+            count: parse_or(rest.first(), 1, usage)?,
+            player: parse_player(rest.get(1), usage)?,
+        },
+        ("speed", [factor]) => ConsoleCommand::Speed(parse(factor, usage)?),
+        ("hash", []) => ConsoleCommand::Hash,
+        ("exporttiled", [file]) => ConsoleCommand::ExportTiled(file.to_string()),
+        ("wait", [ticks]) => ConsoleCommand::Wait(parse(ticks, usage)?),
+        ("set", ["on_error", "abort"]) => ConsoleCommand::SetOnError(OnError::Abort),
+        ("set", ["on_error", "continue"]) => ConsoleCommand::SetOnError(OnError::Continue),
+        _ => return Err(CommandError::Usage(usage)),
+    };
+    Ok(Some(command))
+}
+
+/// Carries out `command` on the world there and then, telling what it did.
+pub fn run_command(world: &mut World, command: &ConsoleCommand) -> Result<String, CommandError> {
+    let grid = *world.resource::<GridKind>();
+    match command {
+        ConsoleCommand::Teleport { col, row, player } => {
+            let projection = *world.resource::<GridProjection>();
+            let mut players = world.query::<(
+                &Player,
+                &mut Position,
+                &mut MovementState,
+                Option<&mut MoveTween>,
+                Option<&mut Transform>,
+            )>();
+            let found = (players.iter_mut(world)).find(|(p, ..)| p.index == *player);
+            let Some((_, position, state, tween, transform)) = found else {
+                return Err(CommandError::NoPlayer(*player));
+            };
+            let v = grid_to_position(grid, *col, *row, position.v.z);
+            place_player(v, position, state, tween, transform, &projection);
+            Ok(format!("Moved player {} to {:?}.", player + 1, v))
+        }
+        ConsoleCommand::Spawn {
+            prefab,
+            col,
+            row,
+            count,
+        } => {
+            let origin = grid_to_position(grid, *col, *row, 0);
+            let mut query = SystemState::<BoardQuery>::new(world);
+            let cells = query.get(world).cells_near(origin, SPAWN_RADIUS);
+            let mut state = SystemState::<BoardCommands>::new(world);
+            let mut board = state.get_mut(world);
+            let mut placed = 0;
+            for v in cells {
+                if placed >= *count {
+                    break;
+                }
+                match board.spawn_actor(prefab, v) {
+                    Ok(_) => placed += 1,
+                    Err(BoardError::Blocked(_)) => {}
+                    Err(e) => return Err(CommandError::Board(e)),
+                }
+            }
+            state.apply(world);
+            if placed < *count {
+                return Err(CommandError::Crowded {
+                    placed,
+                    count: *count,
+                });
+            }
+            Ok(format!("Spawned {} {} near {:?}.", count, prefab, origin))
+        }
+        ConsoleCommand::SetTile {
+            col,
+            row,
+            tile,
+            layer,
+        } => {
+            let z = z_index(RenderLayerSlot::Ground, *layer);
+            let v = grid_to_position(grid, *col, *row, z);
+            let mut state = SystemState::<BoardCommands>::new(world);
+            state.get_mut(world).paint(v, *tile);
+            state.apply(world);
+            Ok(format!("Set {:?} to {:?}.", v, tile))
+        }
+        ConsoleCommand::Give {
+            item,
+            count,
+            player,
+        } => {
+            let known = world.resource::<PrefabRegistry>().get(item).is_some();
+            if item != COIN_ITEM && !known {
+                return Err(CommandError::UnknownItem(item.clone()));
+            }
+            let mut players = world.query::<(&Player, &mut Inventory, &mut Currency)>();
+            let found = (players.iter_mut(world)).find(|(p, ..)| p.index == *player);
+            let Some((_, mut inventory, mut currency)) = found else {
+                return Err(CommandError::NoPlayer(*player));
+            };
+            if item == COIN_ITEM {
+                currency.0 += *count;
+            } else if !inventory.add(item, *count) {
+                return Err(CommandError::NoRoom(item.clone()));
+            }
+            Ok(format!("Gave player {} {} {}.", player + 1, count, item))
+        }
+        ConsoleCommand::Speed(factor) => {
+            let mut speed = world.resource_mut::<SimulationSpeed>();
+            speed.set(*factor);
+            Ok(format!("Running at {}x.", speed.factor()))
+        }
+        ConsoleCommand::Hash => {
+            let tick = world.resource::<LogicalClock>().tick;
+            let hash = SystemState::<WorldHash>::new(world).get(world).hash();
+            Ok(format!("World hash at tick {}: {:#018x}.", tick, hash))
+        }
+        ConsoleCommand::ExportTiled(file) => {
+            let mut export = SystemState::<MapExport>::new(world);
+            let path = export.get(world).write_tiled(file);
+            let path = path.map_err(CommandError::Export)?;
+            Ok(format!("Wrote {}.", path))
+        }
+        ConsoleCommand::Wait(_) => Err(CommandError::ScriptOnly("wait")),
+        ConsoleCommand::SetOnError(_) => Err(CommandError::ScriptOnly("set")),
+    }
+}
+
+/// A file of commands, one a line, run with `--exec` once the game has
+/// started. Lines run in order on the same tick until a `wait`, which
+/// carries on that many ticks later, so a script plays out the same way
+/// whatever the speed.
+#[derive(Resource, Debug)]
+pub struct ConsoleScript {
+    name: String,
+    lines: Vec<String>,
+    next: usize,
+    /// The tick to carry on from, after a wait.
+    resume_at: u64,
+    on_error: OnError,
+}
+
+impl ConsoleScript {
+    /// `name` is what errors are reported against, with their line.
+    pub fn new(name: &str, text: &str) -> Self {
+        ConsoleScript {
+            name: name.to_string(),
+            lines: text.lines().map(str::to_string).collect(),
+            next: 0,
+            resume_at: 0,
+            on_error: OnError::default(),
+        }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(ConsoleScript::new(path, &text))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.lines.len()
+    }
+}
+
+pub struct ConsolePlugin;
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            run_script
+                .after(advance_clock)
+                .run_if(in_state(AppState::Game))
+                .in_set(SimulationSet::Input)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// Runs the script's lines due this tick, logging each with its line
+/// number. A line that fails stops the script, unless it has been told
+/// to `set on_error continue`. The script goes once it has finished or
+/// stopped.
+fn run_script(world: &mut World) {
+    // Out of the world while it runs, as commands take the whole of it.
+    let Some(mut script) = world.remove_resource::<ConsoleScript>() else { return };
+    let tick = world.resource::<LogicalClock>().tick;
+    while script.resume_at <= tick && !script.is_finished() {
+        let (number, line) = (script.next + 1, script.lines[script.next].clone());
+        script.next += 1;
+        let result = match parse_command(&line) {
+            Ok(Some(ConsoleCommand::Wait(ticks))) => {
+                script.resume_at = tick + ticks;
+                continue;
+            }
+            Ok(Some(ConsoleCommand::SetOnError(on_error))) => {
+                script.on_error = on_error;
+                continue;
+            }
+            Ok(Some(command)) => run_command(world, &command),
+            Ok(None) => continue,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(done) => info!("{}:{}: {}", script.name, number, done),
+            Err(e) => {
+                warn!("{}:{}: {}", script.name, number, e);
+                if script.on_error == OnError::Abort {
+                    warn!("Stopped {} at line {}.", script.name, number);
+                    return;
+                }
+            }
+        }
+    }
+    if script.is_finished() {
+        info!("Finished {}.", script.name);
+    } else {
+        world.insert_resource(script);
+    }
+}
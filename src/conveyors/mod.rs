@@ -0,0 +1,402 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{covered_cells, CollisionMap, Footprint, Occupancy, Occupier},
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    render_layers::RenderLayerSlot,
+    simulation::SimulationSet,
+    turns::TurnQueue,
+    vectors::{Direction, GridKind, Vector3Int},
+    CurrentBoard, Position, Tile,
+};
+
+#[derive(Resource, Clone, Debug)]
+pub struct ConveyorConfig {
+    /// Seconds between belt moves in real time. In turn-based mode belts
+    /// move once a round instead.
+    pub interval: f32,
+}
+
+impl Default for ConveyorConfig {
+    fn default() -> Self {
+        ConveyorConfig { interval: 0.5 }
+    }
+}
+
+pub struct ConveyorPlugin;
+impl Plugin for ConveyorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConveyorConfig>().add_system(
+            move_belts
+                .in_set(SimulationSet::React)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// The way the belt at `v` runs, from the topmost tile of its layer that
+/// is a belt.
+pub fn conveyor_at(
+    v: Vector3Int,
+    current: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    metadata: &TileMetadataRegistry,
+) -> Option<Direction> {
+    let v = current.wrap(v);
+    RenderLayerSlot::Ground.range().rev().find_map(|offset| {
+        let cell = Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), offset));
+        let tile = tiles.get(*current.tiles.get(&cell)?).ok()?;
+        metadata.0.get(&tile.i)?.conveyor
+    })
+}
+
+/// Something standing on a belt, and the cell the belt carries it to.
+struct Rider {
+    entity: Entity,
+    to: Vector3Int,
+    footprint: Option<Footprint>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    Pending,
+    /// On the chain being worked down, waiting on whoever is ahead of it.
+    Waiting,
+    Moved,
+    Stayed,
+}
+
+/// One move of every belt, worked out downstream first: each rider waits
+/// for whoever stands where it is going, so a line of crates moves up
+/// together rather than only the front one.
+struct BeltMove<'a> {
+    riders: Vec<Rider>,
+    index: HashMap<Entity, usize>,
+    visits: Vec<Visit>,
+    /// The chain of riders waiting on each other, upstream first.
+    chain: Vec<usize>,
+    current: &'a CurrentBoard,
+    collision: &'a CollisionMap,
+    occupancy: &'a mut Occupancy,
+}
+
+impl<'a> BeltMove<'a> {
+    /// A move of `riders`, to be worked through in the order given.
+    fn new(
+        riders: Vec<Rider>,
+        current: &'a CurrentBoard,
+        collision: &'a CollisionMap,
+        occupancy: &'a mut Occupancy,
+    ) -> Self {
+        let count = riders.len();
+        BeltMove {
+            index: (riders.iter().enumerate())
+                .map(|(i, rider)| (rider.entity, i))
+                .collect(),
+            riders,
+            visits: vec![Visit::Pending; count],
+            chain: Vec::new(),
+            current,
+            collision,
+            occupancy,
+        }
+    }
+
+    /// Works out whether each rider moves, keeping occupancy up to date.
+    fn resolve_all(&mut self) {
+        for i in 0..self.riders.len() {
+            self.resolve(i);
+        }
+    }
+
+    /// Whether anything can stand on `cell`, ignoring occupiers.
+    fn walkable(&self, cell: Vector3Int) -> bool {
+        self.current.has_ground(cell) && !self.collision.is_blocked(cell)
+    }
+
+    fn target_cells(&self, i: usize) -> Vec<Vector3Int> {
+        let rider = &self.riders[i];
+        (covered_cells(rider.to, rider.footprint.as_ref()).into_iter())
+            .map(|cell| self.current.wrap(cell))
+            .collect()
+    }
+
+    fn resolve(&mut self, i: usize) {
+        if self.visits[i] != Visit::Pending {
+            return;
+        }
+        self.visits[i] = Visit::Waiting;
+        self.chain.push(i);
+        let clear = self.clear_ahead(i);
+        self.chain.pop();
+        // Settled already if it was part of a loop.
+        if self.visits[i] == Visit::Waiting {
+            if clear {
+                let rider = &self.riders[i];
+                let cells = covered_cells(rider.to, rider.footprint.as_ref());
+                self.occupancy.insert(rider.entity, cells);
+                self.visits[i] = Visit::Moved;
+            } else {
+                self.visits[i] = Visit::Stayed;
+            }
+        }
+    }
+
+    /// Whether the cells rider `i` is carried onto are free once everyone
+    /// ahead of it has moved.
+    fn clear_ahead(&mut self, i: usize) -> bool {
+        let entity = self.riders[i].entity;
+        for cell in self.target_cells(i) {
+            if !self.walkable(cell) {
+                return false;
+            }
+            let Some(other) = self.occupancy.get(cell).filter(|e| *e != entity) else { continue };
+            let Some(&j) = self.index.get(&other) else {
+                // Standing off the belts, so going nowhere.
+                return false;
+            };
+            match self.visits[j] {
+                Visit::Pending => self.resolve(j),
+                Visit::Waiting => self.close_loop(j),
+                Visit::Moved | Visit::Stayed => {}
+            }
+            if self.visits[i] != Visit::Waiting {
+                return false;
+            }
+            if self.occupancy.get(cell).is_some_and(|e| e != entity) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The chain from rider `j` on has come back round to `j`: a belt loop
+    /// full of riders. They all move round together if each one's cells
+    /// are left by the others, and all stay put otherwise.
+    fn close_loop(&mut self, j: usize) {
+        let start = self.chain.iter().position(|k| *k == j).unwrap_or(0);
+        let members: Vec<usize> = self.chain[start..].to_vec();
+        let entities: Vec<Entity> = members.iter().map(|k| self.riders[*k].entity).collect();
+
+        let mut taken = Vec::new();
+        let fits = members.iter().all(|k| {
+            self.target_cells(*k).into_iter().all(|cell| {
+                let free = self.walkable(cell)
+                    && (self.occupancy.get(cell)).is_none_or(|e| entities.contains(&e))
+                    && !taken.contains(&cell);
+                taken.push(cell);
+                free
+            })
+        });
+
+        if fits {
+            // All off first, so none is cleared from another's new cell.
+            for entity in entities {
+                self.occupancy.remove(entity);
+            }
+            for k in members.iter() {
+                let rider = &self.riders[*k];
+                let cells = covered_cells(rider.to, rider.footprint.as_ref());
+                self.occupancy.insert(rider.entity, cells);
+            }
+        }
+        for k in members {
+            self.visits[k] = if fits { Visit::Moved } else { Visit::Stayed };
+        }
+    }
+}
+
+/// Carries every occupier standing on a belt one cell along it. Those that
+/// would run into a wall, off the ground or into anything that is not
+/// itself moving off stay where they are.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn move_belts(
+    mut riders: Query<(Entity, &mut Position, Option<&Footprint>), (With<Occupier>, Without<Tile>)>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    mut occupancy: ResMut<Occupancy>,
+    grid: Res<GridKind>,
+    config: Res<ConveyorConfig>,
+    fixed: Res<FixedTime>,
+    queue: Res<TurnQueue>,
+    mut timer: Local<Timer>,
+    mut rounds_seen: Local<u32>,
+) {
+    let interval = Duration::from_secs_f32(config.interval.max(0.));
+    if timer.duration() != interval {
+        *timer = Timer::new(interval, TimerMode::Repeating);
+    }
+    // Rounds start over from 0 each time turn-based mode is switched on.
+    let round = queue.round();
+    let new_round = round != *rounds_seen;
+    *rounds_seen = round;
+    let due = if queue.is_active() {
+        new_round
+    } else {
+        timer.tick(fixed.period).just_finished()
+    };
+    if !due {
+        return;
+    }
+
+    let mut on_belts: Vec<(Vector3Int, Rider)> = (riders.iter())
+        .filter_map(|(entity, position, footprint)| {
+            let step = conveyor_at(position.v, &current, &tiles, &metadata)?.step();
+            if !grid.directions().contains(&step) {
+                return None;
+            }
+            let rider = Rider {
+                entity,
+                to: current.wrap(position.v + step),
+                footprint: footprint.copied(),
+            };
+            Some((position.v, rider))
+        })
+        .collect();
+    if on_belts.is_empty() {
+        return;
+    }
+    // Worked through in a fixed order, so that replays and peers agree.
+    on_belts.sort_by_key(|(v, rider)| (v.z, v.y, v.x, rider.entity));
+
+    let riders_on_belts: Vec<Rider> = on_belts.into_iter().map(|(_, rider)| rider).collect();
+    let mut belts = BeltMove::new(riders_on_belts, &current, &collision, &mut occupancy);
+    belts.resolve_all();
+
+    for (rider, visit) in belts.riders.iter().zip(belts.visits.iter()) {
+        if *visit != Visit::Moved {
+            continue;
+        }
+        if let Ok((_, mut position, _)) = riders.get_mut(rider.entity) {
+            position.v = rider.to;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where each of `riders`, standing on a cell on a belt running its
+    /// way, ends up after one move worked through in the order given.
+    /// There is ground under `ground`, walls on `walls` and occupiers off
+    /// the belts on `still`.
+    fn carry(
+        ground: impl IntoIterator<Item = Vector3Int>,
+        walls: &[Vector3Int],
+        still: &[Vector3Int],
+        riders: &[(Vector3Int, Direction)],
+    ) -> Vec<Vector3Int> {
+        let current = CurrentBoard::with_ground(ground);
+        let mut collision = CollisionMap::default();
+        for v in walls {
+            collision.block(*v);
+        }
+        let mut occupancy = Occupancy::default();
+        for (i, v) in still.iter().enumerate() {
+            occupancy.insert(Entity::from_raw(100 + i as u32), [*v]);
+        }
+        let on_belts: Vec<Rider> = (riders.iter().enumerate())
+            .map(|(i, (v, way))| {
+                let entity = Entity::from_raw(i as u32);
+                occupancy.insert(entity, [*v]);
+                let to = *v + way.step();
+                Rider {
+                    entity,
+                    to,
+                    footprint: None,
+                }
+            })
+            .collect();
+
+        let mut belts = BeltMove::new(on_belts, &current, &collision, &mut occupancy);
+        belts.resolve_all();
+        let moved: Vec<bool> = belts.visits.iter().map(|v| *v == Visit::Moved).collect();
+        let landed: Vec<Vector3Int> = (riders.iter().zip(moved))
+            .map(|((v, way), moved)| if moved { *v + way.step() } else { *v })
+            .collect();
+        for (i, v) in landed.iter().enumerate() {
+            assert_eq!(occupancy.get(*v), Some(Entity::from_raw(i as u32)));
+        }
+        landed
+    }
+
+    fn cell(x: i32, y: i32) -> Vector3Int {
+        Vector3Int::new(x, y, 0)
+    }
+
+    /// Ground along row 0 up to a wall in column 5.
+    fn belt_line() -> Vec<Vector3Int> {
+        (0..6).map(|x| cell(x, 0)).collect()
+    }
+
+    #[test]
+    fn crates_on_a_belt_move_together_and_stack_against_a_wall() {
+        let right = |x| (cell(x, 0), Direction::Right);
+        let wall = [cell(5, 0)];
+
+        // Upstream first or downstream first, the whole line moves up.
+        let moved = carry(belt_line(), &wall, &[], &[right(1), right(2), right(3)]);
+        assert_eq!(moved, vec![cell(2, 0), cell(3, 0), cell(4, 0)]);
+        let moved = carry(belt_line(), &wall, &[], &[right(3), right(2), right(1)]);
+        assert_eq!(moved, vec![cell(4, 0), cell(3, 0), cell(2, 0)]);
+
+        // Against the wall, none of them moves.
+        let stacked = [right(2), right(3), right(4)];
+        let stayed = carry(belt_line(), &wall, &[], &stacked);
+        assert_eq!(stayed, vec![cell(2, 0), cell(3, 0), cell(4, 0)]);
+    }
+
+    /// A belt loop round the square from (0, 0) to (1, 1), anticlockwise.
+    fn belt_loop() -> [(Vector3Int, Direction); 4] {
+        [
+            (cell(0, 0), Direction::Right),
+            (cell(1, 0), Direction::Up),
+            (cell(1, 1), Direction::Left),
+            (cell(0, 1), Direction::Down),
+        ]
+    }
+
+    #[test]
+    fn a_full_loop_turns_one_cell() {
+        let ground = belt_loop().map(|(v, _)| v);
+        let turned = [cell(1, 0), cell(1, 1), cell(0, 1), cell(0, 0)];
+        for start in 0..4 {
+            let mut riders = belt_loop();
+            riders.rotate_left(start);
+            let mut expected = turned;
+            expected.rotate_left(start);
+            assert_eq!(carry(ground, &[], &[], &riders), expected);
+        }
+    }
+
+    #[test]
+    fn a_rider_feeding_a_full_loop_waits() {
+        let feeder = (cell(-1, 0), Direction::Right);
+        let ground = belt_loop().map(|(v, _)| v).into_iter().chain([feeder.0]);
+        let turned = [cell(1, 0), cell(1, 1), cell(0, 1), cell(0, 0)];
+
+        // Whether the feeder is worked out before the loop or after it.
+        let riders: Vec<_> = [feeder].into_iter().chain(belt_loop()).collect();
+        let moved = carry(ground.clone(), &[], &[], &riders);
+        assert_eq!(moved[0], feeder.0);
+        assert_eq!(moved[1..], turned);
+
+        let riders: Vec<_> = belt_loop().into_iter().chain([feeder]).collect();
+        let moved = carry(ground, &[], &[], &riders);
+        assert_eq!(moved[..4], turned);
+        assert_eq!(moved[4], feeder.0);
+    }
+
+    #[test]
+    fn riders_stay_behind_occupiers_off_the_belts() {
+        let riders = [(cell(1, 0), Direction::Right), (cell(2, 0), Direction::Right)];
+        let moved = carry(belt_line(), &[], &[cell(3, 0)], &riders);
+        assert_eq!(moved, vec![cell(1, 0), cell(2, 0)]);
+    }
+}
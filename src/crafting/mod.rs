@@ -0,0 +1,491 @@
+//! Crafting items out of others. Recipes come from `game.recipes.json`,
+//! each turning a count of some items into a count of another, at a
+//! station such as an anvil or by hand. Bumping into a station opens the
+//! crafting panel for its recipes and those made by hand; C on the
+//! inventory screen opens it for the hand-made ones alone.
+
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    collision::{covers, Footprint},
+    equipment::InventoryScreen,
+    inventory::Inventory,
+    locale::Localization,
+    player::{BumpEvent, Player},
+    prefabs::PrefabRegistry,
+    shop::{CLOSE_KEY, TRADE_KEYS},
+    simulation::{SimulationPaused, SimulationSet},
+    t, AppState, GraphicsAssets, Position,
+};
+
+/// The recipes every map can use. Files ending in `.recipes.json` load as
+/// recipes rather than scenes.
+pub const RECIPES_FILE: &str = "game.recipes.json";
+const PANEL_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const PANEL_FONT_SIZE: f32 = 20.;
+const SELECTED_COLOR: Color = Color::YELLOW;
+const LACKING_COLOR: Color = Color::GRAY;
+/// Opens the panel from the inventory screen.
+const CRAFT_KEY: KeyCode = KeyCode::C;
+
+/// Recipes as written in a `.recipes.json` file. Entries are kept as raw
+/// JSON so that one bad entry does not fail the whole file.
+#[derive(Deserialize, bevy::reflect::TypeUuid, Debug)]
+#[uuid = "8c4e2a17-5b3f-4d90-a6e2-1f7b9c3d5e84"]
+pub struct RecipeFile(Vec<serde_json::Value>);
+
+/// Turns `inputs`, by item id and count, into `count` of `output`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Recipe {
+    pub inputs: BTreeMap<String, u32>,
+    pub output: String,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    /// The `station_id` of the station it is made at. Made by hand without.
+    #[serde(default)]
+    pub station: Option<String>,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+/// Why a recipe could not be made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CraftError {
+    /// Inputs there are too few of, and how many more are needed.
+    Missing(Vec<(String, u32)>),
+    /// No room for the output, even with the inputs gone.
+    NoRoom,
+}
+
+impl Recipe {
+    /// Each input `inventory` has too few of, and how many more it needs.
+    pub fn missing(&self, inventory: &Inventory) -> Vec<(String, u32)> {
+        (self.inputs.iter())
+            .map(|(id, count)| (id.clone(), count.saturating_sub(inventory.count(id))))
+            .filter(|(_, short)| *short > 0)
+            .collect()
+    }
+
+    /// `inventory` with the inputs taken out and the output put in, or why
+    /// it cannot be made. All or nothing, so the inventory is left as it
+    /// was on failure.
+    pub fn craft(&self, inventory: &Inventory) -> Result<Inventory, CraftError> {
+        let missing = self.missing(inventory);
+        if !missing.is_empty() {
+            return Err(CraftError::Missing(missing));
+        }
+        let mut crafted = inventory.clone();
+        for (id, count) in &self.inputs {
+            crafted.remove(id, *count);
+        }
+        // The last of an input frees its slot for the output.
+        match crafted.add(&self.output, self.count) {
+            true => Ok(crafted),
+            false => Err(CraftError::NoRoom),
+        }
+    }
+}
+
+/// The loaded recipes, checked against the prefabs whenever either
+/// changes.
+#[derive(Default, Resource)]
+pub struct RecipeBook {
+    pub handle: Handle<RecipeFile>,
+    /// Every recipe read from the file, before checking.
+    read: Vec<Recipe>,
+    /// Those whose items are all known.
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    /// The recipes made at `station`, then those made by hand, in file
+    /// order. By hand alone without a station.
+    pub fn at<'a>(&'a self, station: Option<&str>) -> impl Iterator<Item = &'a Recipe> {
+        let station = station.map(str::to_string);
+        let at = move |r: &&Recipe| station.is_some() && r.station == station;
+        let by_hand = |r: &&Recipe| r.station.is_none();
+        (self.recipes.iter().filter(at)).chain(self.recipes.iter().filter(by_hand))
+    }
+}
+
+/// Crafts the recipes that need its `id` for players who bump into it.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Station {
+    pub id: String,
+}
+
+/// Sent when a player crafts `count` of `item`.
+#[derive(Clone, Debug)]
+pub struct ItemCraftedEvent {
+    pub entity: Entity,
+    pub item: String,
+    pub count: u32,
+}
+
+/// The crafting panel, open over a paused game.
+#[derive(Default, Resource)]
+pub struct CraftingPanel {
+    /// The player crafting.
+    open: Option<Entity>,
+    /// The `id` of the station they are at, if any.
+    station: Option<String>,
+    selected: usize,
+    /// The outcome of the last craft, shown under the rows.
+    message: String,
+}
+
+#[derive(Component)]
+struct CraftingPanelRoot;
+
+/// A row of the panel, selected on hover and crafted on click.
+#[derive(Component)]
+struct CraftingRow(usize);
+
+pub struct CraftingPlugin;
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RecipeBook>()
+            .init_resource::<CraftingPanel>()
+            .add_event::<ItemCraftedEvent>()
+            .add_system(load_recipes.in_base_set(CoreSet::PreUpdate))
+            .add_system(
+                open_stations
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (open_from_inventory, navigate_crafting, draw_crafting)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(log_crafts.after(navigate_crafting))
+            .add_system(close_crafting.in_schedule(OnExit(AppState::Game)));
+    }
+}
+
+/// Reads the recipe file when it changes, and checks its recipes against
+/// the prefabs whenever either does, warning about and leaving out those
+/// naming items or stations no prefab is.
+fn load_recipes(
+    mut events: EventReader<AssetEvent<RecipeFile>>,
+    files: Res<Assets<RecipeFile>>,
+    prefabs: Res<PrefabRegistry>,
+    mut book: ResMut<RecipeBook>,
+) {
+    let mut read = false;
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(file) = files.get(handle).filter(|_| *handle == book.handle) else {
+            continue;
+        };
+        book.read = (file.0.iter().enumerate())
+            .filter_map(|(i, value)| match Recipe::deserialize(value) {
+                Ok(recipe) => Some(recipe),
+                Err(err) => {
+                    warn!("Skipping recipe {}: {}.", i, err);
+                    None
+                }
+            })
+            .collect();
+        read = true;
+    }
+    // Checked once both are in.
+    let names = prefabs.names();
+    if (!read && !prefabs.is_changed()) || book.read.is_empty() || names.is_empty() {
+        return;
+    }
+
+    let stations: HashSet<&str> = (names.iter())
+        .filter_map(|name| prefabs.get(name)?.station_id.as_deref())
+        .collect();
+    let mut recipes = Vec::new();
+    for recipe in &book.read {
+        let items = recipe.inputs.keys().chain([&recipe.output]);
+        let unknown: Vec<&String> = items.filter(|id| prefabs.get(id).is_none()).collect();
+        if !unknown.is_empty() {
+            warn!(
+                "Skipping the recipe for `{}`: unknown items {:?}.",
+                recipe.output, unknown
+            );
+            continue;
+        }
+        if let Some(station) = (recipe.station.as_deref()).filter(|s| !stations.contains(s)) {
+            warn!(
+                "The recipe for `{}` needs station `{}`, which no prefab is.",
+                recipe.output, station
+            );
+        }
+        recipes.push(recipe.clone());
+    }
+    info!("Loaded {} recipes.", recipes.len());
+    book.recipes = recipes;
+}
+
+/// Opens the panel for the station a player bumps into.
+fn open_stations(
+    mut bumps: EventReader<BumpEvent>,
+    players: Query<(), (With<Player>, With<Inventory>)>,
+    stations: Query<(&Station, &Position, Option<&Footprint>)>,
+    mut panel: ResMut<CraftingPanel>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    for bump in bumps.iter().filter(|b| players.contains(b.entity)) {
+        let station = (stations.iter()).find(|(_, p, footprint)| covers(p.v, *footprint, bump.at));
+        if let Some((station, ..)) = station.filter(|_| panel.open.is_none()) {
+            *panel = CraftingPanel {
+                open: Some(bump.entity),
+                station: Some(station.id.clone()),
+                ..default()
+            };
+            paused.0 = true;
+        }
+    }
+}
+
+/// Swaps the inventory screen for the panel, with the recipes made by
+/// hand, on C.
+fn open_from_inventory(
+    keys: Res<Input<KeyCode>>,
+    mut screen: ResMut<InventoryScreen>,
+    mut panel: ResMut<CraftingPanel>,
+) {
+    if !keys.just_pressed(CRAFT_KEY) || panel.open.is_some() {
+        return;
+    }
+    let Some(player) = screen.player() else { return };
+    // Still paused, now for the panel.
+    screen.close();
+    *panel = CraftingPanel {
+        open: Some(player),
+        ..default()
+    };
+}
+
+fn close_crafting(
+    mut commands: Commands,
+    mut panel: ResMut<CraftingPanel>,
+    mut paused: ResMut<SimulationPaused>,
+    panels: Query<Entity, With<CraftingPanelRoot>>,
+) {
+    if panel.open.take().is_some() {
+        paused.0 = false;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The recipes on offer, those that can be made first.
+fn rows<'a>(panel: &CraftingPanel, book: &'a RecipeBook, inventory: &Inventory) -> Vec<&'a Recipe> {
+    let mut rows: Vec<&Recipe> = book.at(panel.station.as_deref()).collect();
+    rows.sort_by_key(|recipe| !recipe.missing(inventory).is_empty());
+    rows
+}
+
+/// Counts of items, such as "potion x2, key x1".
+fn item_list(items: impl IntoIterator<Item = (String, u32)>, locale: &Localization) -> String {
+    (items.into_iter())
+        .map(|(id, count)| {
+            let item = t!(locale, &format!("item.{}", id));
+            t!(locale, "craft.ingredient", item = item, count = count)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Moves the selection, and on Enter or a click crafts the recipe
+/// selected.
+#[allow(clippy::too_many_arguments)]
+fn navigate_crafting(
+    keys: Res<Input<KeyCode>>,
+    mut panel: ResMut<CraftingPanel>,
+    mut paused: ResMut<SimulationPaused>,
+    clicks: Query<(&Interaction, &CraftingRow), Changed<Interaction>>,
+    mut players: Query<&mut Inventory>,
+    book: Res<RecipeBook>,
+    locale: Res<Localization>,
+    mut crafted: EventWriter<ItemCraftedEvent>,
+) {
+    let Some(player) = panel.open else { return };
+    let Ok(mut inventory) = players.get_mut(player) else {
+        // The player is gone.
+        panel.open = None;
+        paused.0 = false;
+        return;
+    };
+    if keys.just_pressed(CLOSE_KEY) {
+        panel.open = None;
+        paused.0 = false;
+        return;
+    }
+
+    let rows = rows(&panel, &book, &inventory);
+    let count = rows.len().max(1);
+    if keys.just_pressed(KeyCode::Up) {
+        panel.selected = (panel.selected + count - 1) % count;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        panel.selected = (panel.selected + 1) % count;
+    }
+    let mut craft = keys.any_just_pressed(TRADE_KEYS);
+    for (interaction, CraftingRow(row)) in clicks.iter() {
+        match interaction {
+            Interaction::Hovered if panel.selected != *row => panel.selected = *row,
+            Interaction::Clicked => {
+                panel.selected = *row;
+                craft = true;
+            }
+            _ => {}
+        }
+    }
+    if !craft {
+        return;
+    }
+
+    let Some(recipe) = rows.get(panel.selected).copied() else {
+        panel.message = t!(locale, "craft.empty");
+        return;
+    };
+    let name = t!(locale, &format!("item.{}", recipe.output));
+    panel.message = match recipe.craft(&inventory) {
+        Ok(after) => {
+            *inventory = after;
+            crafted.send(ItemCraftedEvent {
+                entity: player,
+                item: recipe.output.clone(),
+                count: recipe.count,
+            });
+            // What can still be made moves up, so follow the recipe.
+            let rows = self::rows(&panel, &book, &inventory);
+            panel.selected = (rows.iter().position(|r| *r == recipe)).unwrap_or(0);
+            t!(locale, "craft.crafted", item = name, count = recipe.count)
+        }
+        Err(CraftError::Missing(missing)) => {
+            let missing = item_list(missing, &locale);
+            t!(locale, "craft.missing", missing = missing)
+        }
+        Err(CraftError::NoRoom) => t!(locale, "craft.full", item = name),
+    };
+}
+
+fn log_crafts(mut crafted: EventReader<ItemCraftedEvent>, players: Query<&Player>) {
+    for event in crafted.iter() {
+        let Ok(player) = players.get(event.entity) else { continue };
+        info!(
+            "Player {} crafted {} {}.",
+            player.index + 1,
+            event.count,
+            event.item
+        );
+    }
+}
+
+/// Rebuilds the panel whenever it or what it shows changes.
+fn draw_crafting(
+    mut commands: Commands,
+    panel: Res<CraftingPanel>,
+    assets: Res<GraphicsAssets>,
+    book: Res<RecipeBook>,
+    players: Query<Ref<Inventory>>,
+    panels: Query<Entity, With<CraftingPanelRoot>>,
+    locale: Res<Localization>,
+) {
+    let open = panel.open.and_then(|player| players.get(player).ok());
+    let changed = (open.as_ref()).is_some_and(|inventory| inventory.is_changed());
+    if !panel.is_changed() && !locale.is_changed() && !book.is_changed() && !changed {
+        return;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(inventory) = open else { return };
+
+    let style = |color| TextStyle {
+        font: assets.font.clone(),
+        font_size: PANEL_FONT_SIZE,
+        color,
+    };
+    let mut footer = t!(locale, "craft.help");
+    if !panel.message.is_empty() {
+        footer += "\n\n";
+        footer += &panel.message;
+    }
+    let rows = rows(&panel, &book, &inventory);
+
+    commands
+        .spawn((
+            CraftingPanelRoot,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let title = t!(locale, "craft.title") + "\n";
+            parent.spawn(TextBundle::from_section(title, style(Color::WHITE)));
+            if rows.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    t!(locale, "craft.empty"),
+                    style(Color::WHITE),
+                ));
+            }
+            for (i, recipe) in rows.iter().enumerate() {
+                let item = t!(locale, &format!("item.{}", recipe.output));
+                let missing = recipe.missing(&inventory);
+                let can_make = missing.is_empty();
+                let text = match can_make {
+                    true => {
+                        let inputs = (recipe.inputs.iter()).map(|(id, n)| (id.clone(), *n));
+                        let inputs = item_list(inputs, &locale);
+                        t!(
+                            locale,
+                            "craft.recipe",
+                            item = item,
+                            count = recipe.count,
+                            inputs = inputs
+                        )
+                    }
+                    false => {
+                        let missing = item_list(missing, &locale);
+                        t!(
+                            locale,
+                            "craft.lacking",
+                            item = item,
+                            count = recipe.count,
+                            missing = missing
+                        )
+                    }
+                };
+                let (marker, color) = match (i == panel.selected, can_make) {
+                    (true, _) => (">", SELECTED_COLOR),
+                    (false, true) => (" ", Color::WHITE),
+                    (false, false) => (" ", LACKING_COLOR),
+                };
+                parent.spawn((
+                    CraftingRow(i),
+                    Interaction::default(),
+                    TextBundle::from_section(format!("{} {}", marker, text), style(color)),
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                "\n".to_string() + &footer,
+                style(Color::WHITE),
+            ));
+        });
+}
@@ -0,0 +1,256 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::Accessibility,
+    collision::{CollisionFlags, CollisionMap, Occupancy},
+    layer_of, layer_z,
+    locale::{LocaleSettings, Localization},
+    player::{MovementConfig, MovementRules, Player, PlayerSettings, PlayerStepStarted},
+    progression::Campaign,
+    rng::GameRng,
+    saves::format_timestamp,
+    simulation::SimulationSet,
+    speed::SimulationSpeed,
+    status::StatusEffects,
+    storage::{platform_store, unix_time, KeyValueStore},
+    timer::LevelTimerConfig,
+    turns::TurnBased,
+    vectors::{GridKind, Vector3Int},
+    AppState, CurrentBoard, Position, Tile, LAYER_Z_STRIDE,
+};
+
+/// Written to the working directory, or kept under this key in the browser.
+pub const REPORT_DIR: &str = ".";
+pub const REPORT_KEY: &str = "debug_report.txt";
+/// Player steps kept for the next report.
+pub const STEP_LOG_LIMIT: usize = 50;
+/// Cells either side of a player drawn in the report, for a 9×9 square.
+const SURROUNDINGS_RADIUS: i32 = 4;
+
+/// The latest player steps, oldest first, recorded whether or not a
+/// report is ever written.
+#[derive(Default, Resource)]
+pub struct StepLog {
+    steps: VecDeque<(f32, PlayerStepStarted)>,
+}
+
+impl StepLog {
+    fn push(&mut self, time: f32, step: PlayerStepStarted) {
+        if self.steps.len() == STEP_LOG_LIMIT {
+            self.steps.pop_front();
+        }
+        self.steps.push_back((time, step));
+    }
+
+    /// Each step with the seconds since startup it was taken at.
+    pub fn iter(&self) -> impl Iterator<Item = &(f32, PlayerStepStarted)> {
+        self.steps.iter()
+    }
+}
+
+/// A plain-text report of the game's state, for attaching to bug reports.
+/// Sections are written in the order they were added.
+#[derive(Default)]
+pub struct DebugReport {
+    sections: Vec<(String, String)>,
+}
+
+impl DebugReport {
+    pub fn new() -> Self {
+        DebugReport::default()
+    }
+
+    pub fn section(mut self, title: impl Into<String>, body: impl Into<String>) -> Self {
+        self.sections.push((title.into(), body.into()));
+        self
+    }
+
+    /// Everything F10 reports: the map and seed, each player's position,
+    /// surroundings and status effects, the recent steps, the settings in
+    /// effect, and the text keys the language has no string for.
+    pub fn gather(world: &mut World) -> Self {
+        let mut report = DebugReport::new().section("Game", game_summary(world));
+
+        let mut players =
+            world.query_filtered::<(Entity, &Position, Option<&StatusEffects>), With<Player>>();
+        let players: Vec<(Entity, Vector3Int, Option<StatusEffects>)> = players
+            .iter(world)
+            .map(|(entity, position, effects)| (entity, position.v, effects.cloned()))
+            .collect();
+        for (entity, v, effects) in players {
+            let mut body = format!("Position {:?}\n", v);
+            match effects.filter(|e| !e.0.is_empty()) {
+                Some(effects) => {
+                    for effect in effects.0 {
+                        let _ = writeln!(
+                            body,
+                            "{:?} x{} ({} ticks left)",
+                            effect.kind, effect.magnitude, effect.remaining
+                        );
+                    }
+                }
+                None => body.push_str("No status effects\n"),
+            }
+            body.push('\n');
+            body.push_str(&surroundings(world, v));
+            report = report.section(format!("Player {:?}", entity), body);
+        }
+
+        let mut steps = String::new();
+        for (time, step) in world.resource::<StepLog>().iter() {
+            let _ = writeln!(
+                steps,
+                "{:8.2}s {:?} {:?} -> {:?}",
+                time, step.entity, step.from, step.to
+            );
+        }
+        report
+            .section("Recent steps", steps)
+            .section("Settings", settings(world))
+            .section("Missing translations", missing_translations(world))
+    }
+}
+
+impl std::fmt::Display for DebugReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (title, body) in &self.sections {
+            writeln!(f, "== {} ==", title)?;
+            writeln!(f, "{}", body.trim_end())?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DebugReportPlugin;
+impl Plugin for DebugReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StepLog>()
+            // At the end of the tick, which runs even while paused.
+            .add_system(
+                record_steps
+                    .in_set(SimulationSet::End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                write_report
+                    .run_if(report_requested)
+                    .in_set(OnUpdate(AppState::Game)),
+            );
+    }
+}
+
+fn record_steps(
+    time: Res<Time>,
+    mut steps: EventReader<PlayerStepStarted>,
+    mut log: ResMut<StepLog>,
+) {
+    for step in steps.iter() {
+        log.push(time.elapsed_seconds(), *step);
+    }
+}
+
+fn report_requested(keys: Res<Input<KeyCode>>) -> bool {
+    keys.just_pressed(KeyCode::F10)
+}
+
+/// Gathers a report and writes it out, and to the log, for builds with
+/// nowhere to write files.
+fn write_report(world: &mut World) {
+    let text = DebugReport::gather(world).to_string();
+    info!("Debug report:\n{}", text);
+    match platform_store(REPORT_DIR).set(REPORT_KEY, &text) {
+        Ok(()) => info!("Wrote {}.", REPORT_KEY),
+        Err(e) => warn!("Could not write {}: {}", REPORT_KEY, e),
+    }
+}
+
+fn game_summary(world: &World) -> String {
+    format!(
+        "Written {}\nMap {}\nSeed {:#x}\nBuild {}\n",
+        format_timestamp(unix_time()),
+        world.resource::<Campaign>().current_map(),
+        world.resource::<GameRng>().seed(),
+        env!("CARGO_PKG_VERSION"),
+    )
+}
+
+/// The 9×9 cells around `center` on its layer, drawn twice: once as a
+/// map, and once as the index of each cell's top tile.
+fn surroundings(world: &mut World, center: Vector3Int) -> String {
+    let mut tiles = world.query::<&Tile>();
+    let current = world.resource::<CurrentBoard>();
+    let collision = world.resource::<CollisionMap>();
+    let occupancy = world.resource::<Occupancy>();
+    let layer = layer_of(center.z);
+
+    let mut map =
+        String::from("@ player  # blocked  _ pit  o occupied  . floor  (blank) no ground\n");
+    let mut indices = String::from("Top tile indices, - for none:\n");
+    // From the top row down, as on screen.
+    for dy in (-SURROUNDINGS_RADIUS..=SURROUNDINGS_RADIUS).rev() {
+        for dx in -SURROUNDINGS_RADIUS..=SURROUNDINGS_RADIUS {
+            let v = current.wrap(Vector3Int::new(center.x + dx, center.y + dy, center.z));
+            let glyph = if dx == 0 && dy == 0 {
+                '@'
+            } else if collision.blocks(v, CollisionFlags::BLOCK_WALK) {
+                '#'
+            } else if collision.is_pit(v) {
+                '_'
+            } else if occupancy.is_occupied(v) {
+                'o'
+            } else if current.has_ground(v) {
+                '.'
+            } else {
+                ' '
+            };
+            map.push(glyph);
+
+            let top = (0..LAYER_Z_STRIDE).rev().find_map(|offset| {
+                let cell = Vector3Int::new(v.x, v.y, layer_z(layer, offset));
+                let entity = current.tiles.get(&cell)?;
+                tiles.get(world, *entity).ok().map(|tile| tile.i)
+            });
+            let _ = match top {
+                Some(i) => write!(indices, "{:>5}", i),
+                None => write!(indices, "{:>5}", "-"),
+            };
+        }
+        map.push('\n');
+        indices.push('\n');
+    }
+    map + "\n" + &indices
+}
+
+fn settings(world: &World) -> String {
+    let mut body = String::new();
+    let _ = writeln!(body, "Grid {:?}", world.resource::<GridKind>());
+    let _ = writeln!(body, "{:?}", world.resource::<PlayerSettings>());
+    let _ = writeln!(body, "{:?}", world.resource::<MovementConfig>());
+    let _ = writeln!(body, "{:?}", world.resource::<MovementRules>());
+    let _ = writeln!(body, "{:?}", world.resource::<TurnBased>());
+    let _ = writeln!(
+        body,
+        "Speed x{}",
+        world.resource::<SimulationSpeed>().factor()
+    );
+    let _ = writeln!(body, "{:?}", world.resource::<LevelTimerConfig>());
+    let _ = writeln!(body, "{:?}", world.resource::<Accessibility>());
+    let _ = writeln!(
+        body,
+        "Language {}",
+        world.resource::<LocaleSettings>().language
+    );
+    let _ = writeln!(body, "Tick {:?}", world.resource::<FixedTime>().period);
+    body
+}
+
+fn missing_translations(world: &World) -> String {
+    let missing = world.resource::<Localization>().missing();
+    match missing.is_empty() {
+        true => "None\n".to_string(),
+        false => missing.join("\n"),
+    }
+}
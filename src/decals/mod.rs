@@ -0,0 +1,299 @@
+//! Tiles such as tall grass that stir as actors pass through them. A tile
+//! whose metadata has `decal_reactive` gets an overlay the first time an
+//! occupier steps into or out of its cell, drawn in the `Decals` band over
+//! the lower half of whoever stands there, and rustles through its frames
+//! with every step in or out after that. Cells nobody has walked through
+//! have no overlay at all. Overlays are pooled, and have no `Position` or
+//! `Tile`, so the board and everything that reads it pass them by.
+
+use std::collections::HashMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*, sprite::Anchor};
+use serde::Deserialize;
+
+use crate::{
+    board::TileChangedEvent,
+    collision::Occupier,
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    npc::NpcSteppedEvent,
+    player::PlayerStepStarted,
+    projection::GridProjection,
+    puzzles::BlockPushedEvent,
+    render_layers::{z_index, RenderLayerSlot},
+    vectors::Vector3Int,
+    BoardLoadedEvent, CurrentBoard, GraphicsAssets, Position, Tile,
+};
+
+/// Overlays there can be at once. Past this, the one left alone longest is
+/// taken for the next cell stepped through.
+const MAX_DECALS: usize = 256;
+
+/// How a tile stirs as occupiers pass through it, in its metadata's
+/// `decal_reactive`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReactiveDecal {
+    /// Tile indices the overlay shows: the first at rest, then the others in
+    /// turn as it rustles. Only the lower half of each is drawn. Empty for
+    /// the tile itself, which then covers whoever stands in it but does not
+    /// rustle.
+    #[serde(default)]
+    pub frames: Vec<usize>,
+    /// Seconds each frame of the rustle shows for.
+    #[serde(default = "default_frame_s")]
+    pub frame_s: f32,
+}
+
+fn default_frame_s() -> f32 {
+    0.08
+}
+
+/// The overlay on a stepped-through cell.
+#[derive(Component)]
+struct Decal {
+    /// Tile indices, as `ReactiveDecal` has them. Empty while pooled.
+    frames: Vec<usize>,
+    frame: usize,
+    timer: Timer,
+    /// The tile index drawn, once one is.
+    drawn: Option<usize>,
+    /// When it was last stepped through, in seconds since startup.
+    disturbed: f32,
+}
+
+impl Decal {
+    /// Starts the rustle over from its first frame.
+    fn disturb(&mut self, now: f32) {
+        self.frame = 1.min(self.frames.len().saturating_sub(1));
+        self.timer.reset();
+        self.disturbed = now;
+    }
+}
+
+/// Overlays by the cell they cover, and hidden ones waiting for a cell.
+#[derive(Resource, Default)]
+struct DecalPool {
+    live: HashMap<Vector3Int, Entity>,
+    free: Vec<Entity>,
+}
+
+pub struct DecalsPlugin;
+impl Plugin for DecalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DecalPool>()
+            .add_systems((release_decals, disturb_decals, animate_decals).chain());
+    }
+}
+
+/// The cells occupiers stepped out of and into since the last frame, by
+/// their anchor cell alone.
+#[derive(SystemParam)]
+struct Steps<'w, 's> {
+    players: EventReader<'w, 's, PlayerStepStarted>,
+    npcs: EventReader<'w, 's, NpcSteppedEvent>,
+    pushes: EventReader<'w, 's, BlockPushedEvent>,
+    occupiers: Query<'w, 's, &'static Position, With<Occupier>>,
+}
+
+impl<'w, 's> Steps<'w, 's> {
+    fn cells(&mut self) -> Vec<Vector3Int> {
+        let mut cells = Vec::new();
+        for step in self.players.iter() {
+            if self.occupiers.contains(step.entity) {
+                cells.extend([step.from, step.to]);
+            }
+        }
+        for step in self.npcs.iter() {
+            if let Ok(position) = self.occupiers.get(step.entity) {
+                cells.extend([step.from, position.v]);
+            }
+        }
+        for push in self.pushes.iter() {
+            if let Ok(position) = self.occupiers.get(push.entity) {
+                cells.extend([push.from, position.v]);
+            }
+        }
+        cells
+    }
+}
+
+/// The topmost ground tile at `v` that stirs, on the layer of `v.z`, with
+/// how it does.
+fn reactive_tile<'a>(
+    v: Vector3Int,
+    current: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    metadata: &'a TileMetadataRegistry,
+) -> Option<(usize, &'a ReactiveDecal)> {
+    let layer = layer_of(v.z);
+    (0..RenderLayerSlot::Ground.size()).rev().find_map(|i| {
+        let z = layer_z(layer, z_index(RenderLayerSlot::Ground, i));
+        let cell = current.wrap(Vector3Int::new(v.x, v.y, z));
+        let tile = tiles.get(*current.tiles.get(&cell)?).ok()?;
+        let decal = metadata.0.get(&tile.i)?.decal_reactive.as_ref()?;
+        Some((tile.i, decal))
+    })
+}
+
+/// The cell an overlay at `v` covers, at the z of the `Decals` band.
+fn decal_cell(v: Vector3Int, current: &CurrentBoard) -> Vector3Int {
+    let z = layer_z(layer_of(v.z), z_index(RenderLayerSlot::Decals, 0));
+    current.wrap(Vector3Int::new(v.x, v.y, z))
+}
+
+/// Hides an overlay and hands it back to the pool.
+fn release(
+    entity: Entity,
+    decals: &mut Query<(&mut Decal, &mut Visibility)>,
+    pool: &mut DecalPool,
+) {
+    if let Ok((mut decal, mut visibility)) = decals.get_mut(entity) {
+        decal.frames.clear();
+        decal.drawn = None;
+        *visibility = Visibility::Hidden;
+        pool.free.push(entity);
+    }
+}
+
+/// Every overlay goes back to the pool when a map loads, and an overlay
+/// whose cell no longer holds a tile that stirs when its tiles change.
+fn release_decals(
+    mut loaded: EventReader<BoardLoadedEvent>,
+    mut changed: EventReader<TileChangedEvent>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    mut decals: Query<(&mut Decal, &mut Visibility)>,
+    mut pool: ResMut<DecalPool>,
+) {
+    if loaded.iter().count() > 0 {
+        changed.clear();
+        let live: Vec<Entity> = pool.live.drain().map(|(_, entity)| entity).collect();
+        for entity in live {
+            release(entity, &mut decals, &mut pool);
+        }
+        return;
+    }
+    for change in changed.iter() {
+        if reactive_tile(change.position, &current, &tiles, &metadata).is_some() {
+            continue;
+        }
+        let cell = decal_cell(change.position, &current);
+        if let Some(entity) = pool.live.remove(&cell) {
+            release(entity, &mut decals, &mut pool);
+        }
+    }
+}
+
+/// Rustles the overlays of the cells occupiers step out of and into, giving
+/// cells stepped through for the first time one from the pool, a new one,
+/// or the one left alone longest once there are `MAX_DECALS`.
+#[allow(clippy::too_many_arguments)]
+fn disturb_decals(
+    mut commands: Commands,
+    mut steps: Steps,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    grid: Res<GridProjection>,
+    time: Res<Time>,
+    mut pool: ResMut<DecalPool>,
+    mut decals: Query<(&mut Decal, &mut Transform, &mut Visibility)>,
+) {
+    let now = time.elapsed_seconds();
+    for v in steps.cells() {
+        let Some((i, reactive)) = reactive_tile(v, &current, &tiles, &metadata) else { continue };
+        let cell = decal_cell(v, &current);
+        if let Some(entity) = pool.live.get(&cell) {
+            // One spawned this frame is already rustling.
+            if let Ok((mut decal, ..)) = decals.get_mut(*entity) {
+                decal.disturb(now);
+            }
+            continue;
+        }
+        let frames = match reactive.frames.is_empty() {
+            true => vec![i],
+            false => reactive.frames.clone(),
+        };
+        let frame_s = reactive.frame_s.max(f32::EPSILON);
+        let mut decal = Decal {
+            frames,
+            frame: 0,
+            timer: Timer::from_seconds(frame_s, TimerMode::Repeating),
+            drawn: None,
+            disturbed: now,
+        };
+        decal.disturb(now);
+        // Drawn from the bottom of the cell up to its middle.
+        let size = grid.tile_size();
+        let at = grid.world(cell) - Vec3::Y * size.y / 2.;
+        let transform = Transform::from_translation(at);
+
+        let evicted = match pool.free.pop() {
+            Some(entity) => Some(entity),
+            None if pool.live.len() >= MAX_DECALS => {
+                let oldest = (pool.live.iter())
+                    .filter_map(|(cell, e)| Some((*cell, decals.get(*e).ok()?.0.disturbed)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(cell, _)| cell);
+                oldest.and_then(|cell| pool.live.remove(&cell))
+            }
+            None => None,
+        };
+        let entity = match evicted.map(|e| (e, decals.get_mut(e))) {
+            Some((entity, Ok((mut old, mut old_transform, mut visibility)))) => {
+                *old = decal;
+                *old_transform = transform;
+                // Shown again once its first frame is drawn.
+                *visibility = Visibility::Hidden;
+                entity
+            }
+            _ => {
+                let sprite = Sprite {
+                    custom_size: Some(Vec2::new(size.x, size.y / 2.)),
+                    anchor: Anchor::BottomCenter,
+                    ..default()
+                };
+                let bundle = SpriteBundle {
+                    sprite,
+                    transform,
+                    visibility: Visibility::Hidden,
+                    ..default()
+                };
+                commands.spawn((decal, bundle)).id()
+            }
+        };
+        pool.live.insert(cell, entity);
+    }
+}
+
+/// Steps rustling overlays through their frames back to rest, drawing the
+/// lower half of each frame's tile.
+fn animate_decals(
+    mut decals: Query<(&mut Decal, &mut Sprite, &mut Handle<Image>, &mut Visibility)>,
+    graphics: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    time: Res<Time>,
+) {
+    for (mut decal, mut sprite, mut image, mut visibility) in decals.iter_mut() {
+        // Pooled, waiting for a cell.
+        if decal.frames.is_empty() {
+            continue;
+        }
+        if decal.frame > 0 && decal.timer.tick(time.delta()).just_finished() {
+            decal.frame = (decal.frame + 1) % decal.frames.len();
+        }
+        let i = decal.frames[decal.frame];
+        if decal.drawn == Some(i) {
+            continue;
+        }
+        let Some((handle, index)) = graphics.tile_atlas(i, &atlases) else { continue };
+        let Some(atlas) = atlases.get(handle) else { continue };
+        let Rect { min, max } = atlas.textures[index];
+        // Image rects run downward, so the lower half is the greater y.
+        sprite.rect = Some(Rect::new(min.x, (min.y + max.y) / 2., max.x, max.y));
+        *image = atlas.texture.clone();
+        *visibility = Visibility::Inherited;
+        decal.drawn = Some(i);
+    }
+}
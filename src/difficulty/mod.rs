@@ -0,0 +1,506 @@
+//! How hard the game plays. Each `Difficulty` stands for a set of
+//! `DifficultyModifiers`, multipliers on enemy damage and health, on how
+//! often and how many spawners spawn, on hazard damage, on how far players
+//! see and on how often loot drops. Systems keep their base values and
+//! scale them as they use them, so a change takes hold at once. Enemy
+//! health, the one such value kept on entities, is scaled from the
+//! `BaseHealth` noted when each enemy appears.
+//!
+//! The choice is kept with the other settings and in saves. D on the save
+//! menu swaps it for the difficulty menu, where a change mid-run is
+//! confirmed before it applies.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::SETTINGS_DIR,
+    combat::Health,
+    locale::Localization,
+    npc::Hostile,
+    saves::SaveMenu,
+    shop::{CLOSE_KEY, TRADE_KEYS},
+    simulation::{SimulationPaused, SimulationSet},
+    storage::{platform_store, KeyValueStore},
+    t, AppState, GraphicsAssets,
+};
+
+const DIFFICULTY_KEY: &str = "difficulty.ron";
+const MENU_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const MENU_FONT_SIZE: f32 = 20.;
+/// Opens the difficulty menu from the save menu.
+const MENU_KEY: KeyCode = KeyCode::D;
+/// How far one press moves a custom multiplier, and the range it moves in.
+const MODIFIER_STEP: f32 = 0.25;
+const MIN_MODIFIER: f32 = 0.25;
+const MAX_MODIFIER: f32 = 4.;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    /// Each multiplier as `DifficultySettings::custom` has it.
+    Custom,
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Custom,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            "custom" => Some(Difficulty::Custom),
+            _ => None,
+        }
+    }
+
+    /// The next difficulty along, or the one before.
+    fn cycle(self, forward: bool) -> Self {
+        let n = Self::ALL.len();
+        let i = Self::ALL.iter().position(|d| *d == self).unwrap_or(0);
+        let i = if forward { i + 1 } else { i + n - 1 };
+        Self::ALL[i % n]
+    }
+
+    fn locale_key(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "difficulty.easy",
+            Difficulty::Normal => "difficulty.normal",
+            Difficulty::Hard => "difficulty.hard",
+            Difficulty::Custom => "difficulty.custom",
+        }
+    }
+}
+
+/// Multipliers on the values systems start from, read where each value is
+/// used. All 1 on `Difficulty::Normal`.
+#[derive(Resource, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DifficultyModifiers {
+    /// On the damage enemies deal.
+    pub enemy_damage: f32,
+    /// On enemies' health.
+    pub enemy_health: f32,
+    /// On the time between a spawner's spawns.
+    pub spawn_interval: f32,
+    /// On how many of its spawns a spawner keeps alive.
+    pub max_alive: f32,
+    /// On the damage hazards deal.
+    pub hazard_damage: f32,
+    /// On how far players see.
+    pub vision: f32,
+    /// On the chance of each loot drop.
+    pub loot_chance: f32,
+}
+
+impl Default for DifficultyModifiers {
+    fn default() -> Self {
+        DifficultyModifiers {
+            enemy_damage: 1.,
+            enemy_health: 1.,
+            spawn_interval: 1.,
+            max_alive: 1.,
+            hazard_damage: 1.,
+            vision: 1.,
+            loot_chance: 1.,
+        }
+    }
+}
+
+/// `base` times `by` to the nearest whole number, and never below one
+/// unless `base` is nothing.
+fn scale(base: u32, by: f32) -> u32 {
+    if base == 0 {
+        return 0;
+    }
+    ((base as f32 * by).round() as u32).max(1)
+}
+
+impl DifficultyModifiers {
+    pub fn enemy_damage(&self, base: u32) -> u32 {
+        scale(base, self.enemy_damage)
+    }
+
+    pub fn enemy_health(&self, base: u32) -> u32 {
+        scale(base, self.enemy_health)
+    }
+
+    pub fn spawn_interval(&self, base_ms: u64) -> Duration {
+        let ms = (base_ms as f32 * self.spawn_interval).round() as u64;
+        Duration::from_millis(ms.max(1))
+    }
+
+    pub fn max_alive(&self, base: u32) -> u32 {
+        scale(base, self.max_alive)
+    }
+
+    pub fn hazard_damage(&self, base: u32) -> u32 {
+        scale(base, self.hazard_damage)
+    }
+
+    pub fn vision(&self, base: u32) -> u32 {
+        scale(base, self.vision)
+    }
+
+    /// Each multiplier with the name the menu shows it by.
+    fn fields(&mut self) -> [(&'static str, &mut f32); 7] {
+        [
+            ("enemy_damage", &mut self.enemy_damage),
+            ("enemy_health", &mut self.enemy_health),
+            ("spawn_interval", &mut self.spawn_interval),
+            ("max_alive", &mut self.max_alive),
+            ("hazard_damage", &mut self.hazard_damage),
+            ("vision", &mut self.vision),
+            ("loot_chance", &mut self.loot_chance),
+        ]
+    }
+
+    /// With every multiplier within what the menu allows.
+    fn clamped(mut self) -> Self {
+        for (_, value) in self.fields() {
+            *value = value.clamp(MIN_MODIFIER, MAX_MODIFIER);
+        }
+        self
+    }
+}
+
+/// The difficulty section of the player's settings, kept between sessions
+/// and in saves.
+#[derive(Resource, Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DifficultySettings {
+    pub difficulty: Difficulty,
+    /// The multipliers of `Difficulty::Custom`, kept while another is
+    /// chosen.
+    pub custom: DifficultyModifiers,
+}
+
+impl DifficultySettings {
+    /// The settings as last kept, or the defaults.
+    pub fn load() -> Self {
+        let text = match platform_store(SETTINGS_DIR).get(DIFFICULTY_KEY) {
+            Ok(Some(text)) => text,
+            Ok(None) => return DifficultySettings::default(),
+            Err(e) => {
+                warn!("Could not read difficulty settings: {}", e);
+                return DifficultySettings::default();
+            }
+        };
+        ron::from_str(&text).unwrap_or_else(|e| {
+            warn!("Ignoring malformed difficulty settings: {}", e);
+            DifficultySettings::default()
+        })
+    }
+
+    pub fn modifiers(&self) -> DifficultyModifiers {
+        match self.difficulty {
+            Difficulty::Easy => DifficultyModifiers {
+                enemy_damage: 0.5,
+                enemy_health: 0.75,
+                spawn_interval: 1.5,
+                max_alive: 0.75,
+                hazard_damage: 0.5,
+                vision: 1.25,
+                loot_chance: 1.5,
+            },
+            Difficulty::Normal => DifficultyModifiers::default(),
+            Difficulty::Hard => DifficultyModifiers {
+                enemy_damage: 1.5,
+                enemy_health: 1.5,
+                spawn_interval: 0.75,
+                max_alive: 1.5,
+                hazard_damage: 1.5,
+                vision: 0.75,
+                loot_chance: 0.75,
+            },
+            Difficulty::Custom => self.custom.clamped(),
+        }
+    }
+}
+
+/// An enemy's health before difficulty, noted when it appears.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BaseHealth(pub u32);
+
+/// The difficulty menu, open over a paused game.
+#[derive(Default, Resource)]
+struct DifficultyMenu {
+    open: bool,
+    /// The difficulty on the first row, then each multiplier.
+    selected: usize,
+    /// The settings as edited, applied once confirmed.
+    draft: DifficultySettings,
+    confirm: bool,
+}
+
+impl DifficultyMenu {
+    fn text(&self, locale: &Localization) -> String {
+        let mut text = t!(locale, "difficulty.title") + "\n\n";
+        let level = t!(locale, self.draft.difficulty.locale_key());
+        let mut rows = vec![t!(locale, "difficulty.level", level = level)];
+        for (name, value) in self.draft.modifiers().fields() {
+            let value = format!("{:.2}", value);
+            rows.push(t!(locale, &format!("difficulty.{}", name), value = value));
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            text += &format!("{} {}\n", marker, row);
+        }
+        text += "\n";
+        text += &match self.confirm {
+            true => t!(locale, "difficulty.confirm"),
+            false => t!(locale, "difficulty.help"),
+        };
+        text
+    }
+}
+
+#[derive(Component)]
+struct DifficultyMenuText;
+
+pub struct DifficultyPlugin;
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        // Unless the command line chose already.
+        if !app.world.contains_resource::<DifficultySettings>() {
+            app.insert_resource(DifficultySettings::load());
+        }
+        let modifiers = app.world.resource::<DifficultySettings>().modifiers();
+        app.insert_resource(modifiers)
+            .init_resource::<DifficultyMenu>()
+            .add_system(apply_settings)
+            .add_system(
+                scale_enemy_health
+                    .in_set(SimulationSet::Input)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (open_menu, navigate_menu, draw_menu)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(close_menu.in_schedule(OnExit(AppState::Game)));
+    }
+}
+
+/// Takes up the multipliers of the chosen difficulty, and keeps the choice
+/// for the next session.
+fn apply_settings(settings: Res<DifficultySettings>, mut modifiers: ResMut<DifficultyModifiers>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let chosen = settings.modifiers();
+    if *modifiers != chosen {
+        *modifiers = chosen;
+    }
+    if settings.is_added() {
+        return;
+    }
+
+    let text = ron::ser::to_string_pretty(&*settings, ron::ser::PrettyConfig::default())
+        .expect("settings always serialize");
+    if let Err(e) = platform_store(SETTINGS_DIR).set(DIFFICULTY_KEY, &text) {
+        warn!("Could not save difficulty settings: {}", e);
+    }
+}
+
+/// Sets `health` to a new `max`, keeping the share of it left.
+pub(crate) fn rescale(mut health: Mut<Health>, max: u32) {
+    if health.max == max {
+        return;
+    }
+    let share = health.current as f32 / health.max.max(1) as f32;
+    let current = match health.current {
+        0 => 0,
+        _ => ((share * max as f32).round() as u32).clamp(1, max),
+    };
+    *health = Health { current, max };
+}
+
+/// Notes the health of enemies as they appear and scales it by the
+/// difficulty, and scales every enemy's again from what was noted whenever
+/// the difficulty changes.
+fn scale_enemy_health(
+    mut commands: Commands,
+    modifiers: Res<DifficultyModifiers>,
+    mut appeared: Query<(Entity, &mut Health), (Hostile, Without<BaseHealth>)>,
+    mut enemies: Query<(&BaseHealth, &mut Health), Hostile>,
+) {
+    if modifiers.is_changed() {
+        for (base, health) in enemies.iter_mut() {
+            rescale(health, modifiers.enemy_health(base.0));
+        }
+    }
+    for (entity, health) in appeared.iter_mut() {
+        let base = health.max;
+        rescale(health, modifiers.enemy_health(base));
+        commands.entity(entity).insert(BaseHealth(base));
+    }
+}
+
+/// Swaps the save menu for the difficulty menu on D.
+fn open_menu(
+    keys: Res<Input<KeyCode>>,
+    settings: Res<DifficultySettings>,
+    mut saves: ResMut<SaveMenu>,
+    mut menu: ResMut<DifficultyMenu>,
+) {
+    if !keys.just_pressed(MENU_KEY) || menu.open || !saves.is_idle() {
+        return;
+    }
+    // Still paused, now for this menu.
+    saves.close();
+    *menu = DifficultyMenu {
+        open: true,
+        draft: *settings,
+        ..default()
+    };
+}
+
+fn close_menu(
+    mut commands: Commands,
+    mut menu: ResMut<DifficultyMenu>,
+    mut paused: ResMut<SimulationPaused>,
+    panels: Query<Entity, (With<DifficultyMenuText>, Without<Parent>)>,
+) {
+    if menu.open {
+        menu.open = false;
+        paused.0 = false;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Moves the selection and changes the row selected, and on Enter asks
+/// before applying the changes to the run.
+fn navigate_menu(
+    keys: Res<Input<KeyCode>>,
+    mut menu: ResMut<DifficultyMenu>,
+    mut settings: ResMut<DifficultySettings>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    if !menu.open {
+        return;
+    }
+    if menu.confirm {
+        if keys.any_just_pressed([KeyCode::N, CLOSE_KEY]) {
+            menu.confirm = false;
+        } else if keys.just_pressed(KeyCode::Y) {
+            info!("Difficulty {:?}.", menu.draft.difficulty);
+            *settings = menu.draft;
+            menu.open = false;
+            paused.0 = false;
+        }
+        return;
+    }
+    if keys.just_pressed(CLOSE_KEY) {
+        menu.open = false;
+        paused.0 = false;
+        return;
+    }
+
+    let menu = &mut *menu;
+    let rows = menu.draft.custom.fields().len() + 1;
+    if keys.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + rows - 1) % rows;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % rows;
+    }
+    let left = keys.just_pressed(KeyCode::Left);
+    let step = match (left, keys.just_pressed(KeyCode::Right)) {
+        (true, false) => -MODIFIER_STEP,
+        (false, true) => MODIFIER_STEP,
+        _ => 0.,
+    };
+    let draft = &mut menu.draft;
+    if step != 0. && menu.selected == 0 {
+        draft.difficulty = draft.difficulty.cycle(step > 0.);
+    } else if step != 0. {
+        // Changing any multiplier makes the difficulty a custom one,
+        // starting from what it was.
+        if draft.difficulty != Difficulty::Custom {
+            draft.custom = draft.modifiers();
+            draft.difficulty = Difficulty::Custom;
+        }
+        if let Some((_, value)) = draft.custom.fields().into_iter().nth(menu.selected - 1) {
+            *value = (*value + step).clamp(MIN_MODIFIER, MAX_MODIFIER);
+        }
+    }
+
+    if keys.any_just_pressed(TRADE_KEYS) {
+        if menu.draft == *settings {
+            menu.open = false;
+            paused.0 = false;
+        } else {
+            menu.confirm = true;
+        }
+    }
+}
+
+fn draw_menu(
+    mut commands: Commands,
+    menu: Res<DifficultyMenu>,
+    assets: Res<GraphicsAssets>,
+    mut texts: Query<&mut Text, With<DifficultyMenuText>>,
+    panels: Query<Entity, (With<DifficultyMenuText>, Without<Parent>)>,
+    locale: Res<Localization>,
+) {
+    if !menu.is_changed() && !locale.is_changed() {
+        return;
+    }
+    if !menu.open {
+        for entity in panels.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    let text = menu.text(&locale);
+    if let Some(mut shown) = texts.iter_mut().next() {
+        shown.sections[0].value = text;
+        return;
+    }
+
+    commands
+        .spawn((
+            DifficultyMenuText,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: MENU_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                DifficultyMenuText,
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: MENU_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
@@ -0,0 +1,159 @@
+use std::{fmt, path::Path};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde_json::{Map, Value};
+
+use crate::{
+    grid_to_position,
+    projection::GridProjection,
+    render_layers::{z_index, RenderLayerSlot},
+    storage::{platform_store, KeyValueStore, StorageError},
+    vectors::GridKind,
+    Campaign, CurrentBoard, Scene, SceneHandle, Tile,
+};
+
+use super::{objects::EditorObjects, tiled::tiled_map, EDIT_LAYERS};
+
+/// Where exported maps are written, by file name.
+pub const EXPORT_DIR: &str = "exports";
+/// Where the map being exported is read from, to keep what the editor does
+/// not change. Files on the desktop only.
+const ASSET_DIR: &str = "assets";
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// The map's scene has not loaded, so there is nothing to export.
+    NotLoaded,
+    /// Tiled maps are only written for the orthogonal projection.
+    Projection(GridProjection),
+    Storage(StorageError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::NotLoaded => write!(f, "the map has not loaded"),
+            ExportError::Projection(projection) => write!(
+                f,
+                "Tiled export needs an orthogonal map, not {:?}",
+                projection
+            ),
+            ExportError::Storage(e) => e.fmt(f),
+        }
+    }
+}
+
+/// The map as edited: the source file with the editor's ground layers and
+/// objects in place of its own, or just those where the source cannot be
+/// read. Loads as any other map, though tiles it named are numbered.
+fn export_scene(
+    source: Option<Map<String, Value>>,
+    scene: &Scene,
+    grid: GridKind,
+    current: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    objects: &EditorObjects,
+) -> Value {
+    let mut json = source.unwrap_or_default();
+    let (width, height) = scene.size();
+    let ground = RenderLayerSlot::Ground.size() as usize;
+    let count = scene.layers.len().min(ground).max(EDIT_LAYERS.len());
+    let mut layers = match json.remove("layers") {
+        Some(Value::Array(layers)) => layers,
+        _ => Vec::new(),
+    };
+    layers.resize(layers.len().max(count), Value::Array(Vec::new()));
+    for (layer, values) in layers.iter_mut().take(count).enumerate() {
+        let z = z_index(RenderLayerSlot::Ground, layer as i32);
+        let cells = (0..height).flat_map(|row| (0..width).map(move |col| (col, row)));
+        // Map values are tile indices plus one, with 0 for none.
+        *values = cells
+            .map(|(col, row)| {
+                let v = grid_to_position(grid, col as i32, row as i32, z);
+                let tile = current.tiles.get(&v).and_then(|e| tiles.get(*e).ok());
+                Value::from(tile.map_or(0, |tile| tile.i + 1))
+            })
+            .collect();
+    }
+    // Written as indices, which a palette would read as its own.
+    json.remove("palette");
+    json.insert("width".to_string(), width.into());
+    json.insert("layers".to_string(), layers.into());
+    let objects = serde_json::to_value(&objects.objects).unwrap_or_default();
+    json.insert("objects".to_string(), objects);
+    Value::Object(json)
+}
+
+/// Everything exporting the map as edited reads, for the editor's Ctrl+S
+/// and the console alike.
+#[derive(SystemParam)]
+pub struct MapExport<'w, 's> {
+    campaign: Res<'w, Campaign>,
+    scene: Res<'w, SceneHandle>,
+    scenes: Res<'w, Assets<Scene>>,
+    grid: Res<'w, GridKind>,
+    projection: Res<'w, GridProjection>,
+    current: Res<'w, CurrentBoard>,
+    tiles: Query<'w, 's, &'static Tile>,
+    objects: Res<'w, EditorObjects>,
+}
+
+impl<'w, 's> MapExport<'w, 's> {
+    /// The map's scene, and the map as edited.
+    fn export(&self) -> Result<(&Scene, Value), ExportError> {
+        let scene = (self.scenes.get(&self.scene.0)).ok_or(ExportError::NotLoaded)?;
+        let map = self.campaign.current_map();
+        let source = match platform_store(ASSET_DIR).get(map) {
+            Ok(Some(text)) => match serde_json::from_str(&text) {
+                Ok(Value::Object(source)) => Some(source),
+                _ => None,
+            },
+            _ => None,
+        };
+        if source.is_none() {
+            warn!("Could not read {}; exporting what was edited.", map);
+        }
+        let (grid, current, objects) = (*self.grid, &self.current, &self.objects);
+        let json = export_scene(source, scene, grid, current, &self.tiles, objects);
+        Ok((scene, json))
+    }
+
+    /// Writes the map as edited to `EXPORT_DIR`, under the map's file name,
+    /// giving where it went.
+    pub fn write_map(&self) -> Result<String, ExportError> {
+        let (_, json) = self.export()?;
+        let map = self.campaign.current_map();
+        let name = (Path::new(map).file_name()).map_or(map.into(), |n| n.to_string_lossy());
+        write(&name, &json)
+    }
+
+    /// Writes the map as edited to `EXPORT_DIR` as a Tiled map named `name`,
+    /// giving where it went. Its images are found from there in the assets.
+    pub fn write_tiled(&self, name: &str) -> Result<String, ExportError> {
+        if !matches!(*self.projection, GridProjection::Orthogonal { .. }) {
+            return Err(ExportError::Projection(*self.projection));
+        }
+        let (scene, json) = self.export()?;
+        write(name, &tiled_map(&json, scene, &format!("../{}", ASSET_DIR)))
+    }
+}
+
+fn write(name: &str, json: &Value) -> Result<String, ExportError> {
+    let text = serde_json::to_string_pretty(json).unwrap_or_default();
+    (platform_store(EXPORT_DIR).set(name, &text)).map_err(ExportError::Storage)?;
+    Ok(format!("{}/{}", EXPORT_DIR, name))
+}
+
+/// Ctrl+S in the editor writes the map as edited to `EXPORT_DIR`, under
+/// the map's file name.
+pub fn export_map(keys: Res<Input<KeyCode>>, campaign: Res<Campaign>, export: MapExport) {
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if !ctrl || !keys.just_pressed(KeyCode::S) {
+        return;
+    }
+    let map = campaign.current_map();
+    match export.write_map() {
+        Ok(path) => info!("Exported {} to {}.", map, path),
+        Err(e) => warn!("Could not export {}: {}", map, e),
+    }
+}
@@ -0,0 +1,518 @@
+use std::collections::{HashSet, VecDeque};
+
+mod export;
+mod objects;
+mod tiled;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    board::BoardCommands,
+    camera::{Letterbox, GRAB_KEY},
+    collision::CollisionMap,
+    flash::Outline,
+    hud::PointerOverUi,
+    projection::GridProjection,
+    render_layers::{z_for, z_index, RenderLayerSlot},
+    undo::{UndoHistory, UndoRecord},
+    vectors::{Direction, GridKind, GridRect, Vector3Int},
+    AppState, CurrentBoard, GraphicsAssets, MapValidationReport, Position, Tile, TILE_SIZE,
+};
+
+/// Tiles in the packed atlas (12 x 11).
+const PALETTE_SIZE: usize = 132;
+/// The tile layers the editor paints onto: ground and decoration.
+const EDIT_LAYERS: [i32; 2] = [
+    z_index(RenderLayerSlot::Ground, 0),
+    z_index(RenderLayerSlot::Ground, 1),
+];
+pub const DEFAULT_FILL_LIMIT: usize = 10_000;
+/// Over labels, which one-way arrows would otherwise hide under.
+const ARROW_Z: f32 = 11.;
+const ARROW_COLOR: Color = Color::rgba(1., 1., 1., 0.5);
+
+/// The board cell under the mouse cursor, on the layer being edited.
+#[derive(Default, Resource)]
+pub struct HoveredTile(pub Option<Vector3Int>);
+
+pub use export::{ExportError, MapExport};
+pub use objects::EditorObjects;
+
+/// What clicking on the board does, switched with B.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Drags out a selection rect.
+    #[default]
+    Select,
+    /// Replaces the clicked cell and every connected cell with the same
+    /// tile by the palette tile.
+    Fill,
+    /// Places map objects and edits their properties.
+    Object,
+}
+
+#[derive(Default, Resource)]
+pub struct EditorState {
+    pub active: bool,
+    pub tool: EditorTool,
+    /// Atlas index painted by fills, or `None` to erase.
+    pub palette: Option<usize>,
+    /// The tile layer's z-index that every tool reads and writes.
+    pub z: i32,
+}
+
+#[derive(Resource)]
+pub struct EditorConfig {
+    /// Most cells a bucket fill changes; larger fills are abandoned.
+    pub fill_limit: usize,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        EditorConfig {
+            fill_limit: DEFAULT_FILL_LIMIT,
+        }
+    }
+}
+
+/// The last rect dragged out with the mouse, clamped to the board.
+#[derive(Default, Resource)]
+pub struct EditorSelection {
+    pub rect: Option<GridRect>,
+    anchor: Option<Vector3Int>,
+    /// Copied tiles by offset from the rect's min corner, keeping their z.
+    clipboard: Vec<(Vector3Int, usize)>,
+}
+
+#[derive(Component)]
+struct SelectionOutline;
+
+/// Points the way a one-way cell may be crossed, while editing.
+#[derive(Component)]
+struct OneWayArrow;
+
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HoveredTile>()
+            .init_resource::<EditorState>()
+            .init_resource::<EditorConfig>()
+            .init_resource::<EditorSelection>()
+            .init_resource::<EditorObjects>()
+            .add_startup_system(spawn_selection_outline)
+            .add_system(toggle_editor)
+            .add_system(update_hovered_tile)
+            .add_system(objects::load_objects)
+            .add_systems(
+                (
+                    select_tiles,
+                    pick_or_fill,
+                    edit_selection,
+                    objects::place_or_select,
+                    export::export_map,
+                )
+                    .chain()
+                    .distributive_run_if(editor_active)
+                    // Keys typed into the object form are only text.
+                    .distributive_run_if(not_typing)
+                    .before(objects::edit_objects)
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(
+                objects::edit_objects
+                    .run_if(editor_active)
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(draw_selection)
+            .add_system(outline_selection)
+            .add_system(draw_one_way_arrows)
+            .add_system(objects::draw_object_icons)
+            .add_system(objects::draw_object_form);
+    }
+}
+
+fn editor_active(state: Res<EditorState>) -> bool {
+    state.active
+}
+
+fn not_typing(objects: Res<EditorObjects>) -> bool {
+    !objects.typing()
+}
+
+fn toggle_editor(
+    keys: Res<Input<KeyCode>>,
+    report: Res<MapValidationReport>,
+    mut state: ResMut<EditorState>,
+) {
+    if keys.just_pressed(KeyCode::F2) {
+        state.active = !state.active;
+        info!("Editor {}.", if state.active { "on" } else { "off" });
+        // Remind whoever is editing of cells that failed to load.
+        if state.active && !report.is_empty() {
+            warn!("Scene has {}", *report);
+        }
+    }
+}
+
+fn update_hovered_tile(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    letterbox: Res<Letterbox>,
+    state: Res<EditorState>,
+    projection: Res<GridProjection>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    let cell = windows
+        .get_single()
+        .ok()
+        .and_then(|window| {
+            let cursor = window.cursor_position()?;
+            letterbox.shows(window, cursor).then_some(cursor)
+        })
+        .and_then(|cursor| {
+            let (camera, transform) = cameras.get_single().ok()?;
+            camera.viewport_to_world(transform, cursor)
+        })
+        // Tiles are centred on their cell, so this rounds to the nearest one.
+        .map(|ray| projection.cell_at(ray.origin.truncate(), state.z));
+
+    if hovered.0 != cell {
+        hovered.0 = cell;
+    }
+}
+
+fn alt_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::LAlt, KeyCode::RAlt])
+}
+
+/// Whether the left button is free for editing, rather than over a menu or
+/// dragging the view with `GRAB_KEY`.
+fn left_for_editing(keys: &Input<KeyCode>, pointer: &PointerOverUi) -> bool {
+    !pointer.0 && !keys.pressed(GRAB_KEY)
+}
+
+fn log_palette(palette: Option<usize>) {
+    match palette {
+        Some(index) => info!("Palette tile {}.", index),
+        None => info!("Palette eraser."),
+    }
+}
+
+fn select_tiles(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    pointer: Res<PointerOverUi>,
+    state: Res<EditorState>,
+    hovered: Res<HoveredTile>,
+    current: Res<CurrentBoard>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    if state.tool != EditorTool::Select || alt_pressed(&keys) || !left_for_editing(&keys, &pointer)
+    {
+        selection.anchor = None;
+        return;
+    }
+    if buttons.just_pressed(MouseButton::Left) {
+        selection.anchor = hovered.0;
+    }
+    if buttons.just_released(MouseButton::Left) {
+        let Some(anchor) = selection.anchor.take() else { return };
+        let end = hovered.0.unwrap_or(anchor);
+        selection.rect = GridRect::from_corners(anchor, end).intersection(current.bounds.rect);
+    }
+}
+
+/// The cells joined to `start` through neighbours holding the same tile,
+/// or the same lack of one, on its layer and within the board. `None` if
+/// there are more than `limit`.
+fn connected_region(
+    start: Vector3Int,
+    current: &CurrentBoard,
+    grid: GridKind,
+    limit: usize,
+    index_at: impl Fn(Vector3Int) -> Option<usize>,
+) -> Option<Vec<Vector3Int>> {
+    let target = index_at(start);
+    let mut queue = VecDeque::from([start]);
+    let mut seen = HashSet::from([start]);
+    let mut region = Vec::new();
+    while let Some(v) = queue.pop_front() {
+        region.push(v);
+        if region.len() > limit {
+            return None;
+        }
+        for next in current.neighbours(v, grid) {
+            if current.bounds.rect.contains(next) && index_at(next) == target && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    Some(region)
+}
+
+/// Alt-clicking a cell picks up its tile, or the eraser if it has none.
+/// Otherwise, with the fill tool, a click fills the connected cells of the
+/// clicked kind with the palette tile, undone in one go.
+#[allow(clippy::too_many_arguments)]
+fn pick_or_fill(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    pointer: Res<PointerOverUi>,
+    hovered: Res<HoveredTile>,
+    config: Res<EditorConfig>,
+    grid: Res<GridKind>,
+    mut state: ResMut<EditorState>,
+    mut board: BoardCommands,
+    mut history: ResMut<UndoHistory>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) || !left_for_editing(&keys, &pointer) {
+        return;
+    }
+    let Some(v) = hovered.0.map(|v| board.current().wrap(v)) else { return };
+    if !board.current().bounds.rect.contains(v) {
+        return;
+    }
+    let index_at = |v: Vector3Int| board.tile(v);
+
+    if alt_pressed(&keys) {
+        state.palette = index_at(v);
+        log_palette(state.palette);
+        return;
+    }
+    let target = index_at(v);
+    if state.tool != EditorTool::Fill || target == state.palette {
+        return;
+    }
+    let Some(region) = connected_region(v, board.current(), *grid, config.fill_limit, index_at)
+    else {
+        warn!(
+            "Fill abandoned: more than {} cells are connected.",
+            config.fill_limit
+        );
+        return;
+    };
+
+    history.begin();
+    history.record(UndoRecord::TilesPainted {
+        before: region.iter().map(|v| (*v, target)).collect(),
+    });
+    for v in region.iter() {
+        board.paint(*v, state.palette);
+    }
+    info!("Filled {} cells.", region.len());
+}
+
+fn edit_selection(
+    keys: Res<Input<KeyCode>>,
+    hovered: Res<HoveredTile>,
+    mut state: ResMut<EditorState>,
+    mut selection: ResMut<EditorSelection>,
+    mut board: BoardCommands,
+) {
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+
+    // The eraser sits before the first tile and after the last.
+    if keys.just_pressed(KeyCode::RBracket) {
+        state.palette = match state.palette {
+            None => Some(0),
+            Some(i) => Some(i + 1).filter(|i| *i < PALETTE_SIZE),
+        };
+        log_palette(state.palette);
+    }
+    if keys.just_pressed(KeyCode::LBracket) {
+        state.palette = match state.palette {
+            None => Some(PALETTE_SIZE - 1),
+            Some(i) => i.checked_sub(1),
+        };
+        log_palette(state.palette);
+    }
+    if keys.just_pressed(KeyCode::B) {
+        state.tool = match state.tool {
+            EditorTool::Select => EditorTool::Fill,
+            EditorTool::Fill => EditorTool::Object,
+            EditorTool::Object => EditorTool::Select,
+        };
+        info!("Editor tool {:?}.", state.tool);
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        let next = EDIT_LAYERS
+            .iter()
+            .position(|z| *z == state.z)
+            .map_or(0, |i| i + 1);
+        state.z = EDIT_LAYERS[next % EDIT_LAYERS.len()];
+        info!("Editing tile layer {}.", state.z);
+    }
+
+    // Pasting only needs a hovered cell, not a selection.
+    if ctrl && keys.just_pressed(KeyCode::V) {
+        let Some(origin) = hovered.0 else { return };
+        for (offset, index) in selection.clipboard.iter() {
+            // Wrapping edges carry the paste round instead of growing the board.
+            let v = Vector3Int::new(origin.x + offset.x, origin.y + offset.y, offset.z);
+            board.set_tile(v, *index);
+        }
+        return;
+    }
+
+    let Some(rect) = selection.rect else { return };
+    let in_rect: Vec<Vector3Int> = (board.current().tiles.keys())
+        .filter(|v| rect.contains(**v))
+        .copied()
+        .collect();
+
+    // The object tool deletes objects instead.
+    if keys.just_pressed(KeyCode::Delete) && state.tool != EditorTool::Object {
+        for v in in_rect.into_iter().filter(|v| v.z == state.z) {
+            board.remove_tile(v);
+        }
+    } else if ctrl && keys.just_pressed(KeyCode::F) {
+        for v in rect.cells(Vector3Int::new(0, 0, state.z)) {
+            board.paint(v, state.palette);
+        }
+    } else if ctrl && keys.just_pressed(KeyCode::C) {
+        let min = Vector3Int::new(rect.x, rect.y, 0);
+        selection.clipboard = (in_rect.into_iter())
+            .filter_map(|v| Some((v - min, board.tile(v)?)))
+            .collect();
+        info!("Copied {} tiles.", selection.clipboard.len());
+    }
+}
+
+impl EditorSelection {
+    /// The rect being dragged out, or failing that the stored one.
+    fn shown(&self, hovered: &HoveredTile, current: &CurrentBoard) -> Option<GridRect> {
+        let dragging = (self.anchor.zip(hovered.0))
+            .map(|(anchor, end)| GridRect::from_corners(anchor, end))
+            .and_then(|rect| rect.intersection(current.bounds.rect));
+        dragging.or(self.rect)
+    }
+}
+
+fn spawn_selection_outline(mut commands: Commands, palette: Res<PaletteLookup>) {
+    commands.spawn((
+        SelectionOutline,
+        SpriteBundle {
+            sprite: Sprite {
+                color: palette.color(PaletteColor::Highlight),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Stretches the outline over the rect being dragged, or the stored one.
+fn draw_selection(
+    state: Res<EditorState>,
+    hovered: Res<HoveredTile>,
+    current: Res<CurrentBoard>,
+    selection: Res<EditorSelection>,
+    projection: Res<GridProjection>,
+    palette: Res<PaletteLookup>,
+    mut outline: Query<(&mut Sprite, &mut Transform, &mut Visibility), With<SelectionOutline>>,
+) {
+    let Ok((mut sprite, mut transform, mut visibility)) = outline.get_single_mut() else { return };
+    if palette.is_changed() {
+        sprite.color = palette.color(PaletteColor::Highlight);
+    }
+
+    let shown = selection.shown(&hovered, &current);
+    let Some(rect) = shown.filter(|_| state.active) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    // Cell centres sit on whole cell coordinates, so the rect's edges are
+    // half a cell out from its corner cells. Isometric rects are diamonds,
+    // so the outline covers their bounding box.
+    let (left, bottom) = (rect.x as f32 - 0.5, rect.y as f32 - 0.5);
+    let (right, top) = (left + rect.width as f32, bottom + rect.height as f32);
+    let corners = [(left, bottom), (right, bottom), (left, top), (right, top)]
+        .map(|(x, y)| projection.cell_to_world(Vec2::new(x, y)));
+    let min = corners.iter().fold(Vec2::splat(f32::MAX), |a, b| a.min(*b));
+    let max = corners.iter().fold(Vec2::splat(f32::MIN), |a, b| a.max(*b));
+    sprite.custom_size = Some(max - min);
+    transform.translation = ((min + max) / 2.).extend(z_for(RenderLayerSlot::Ui2d, 0.));
+    *visibility = Visibility::Visible;
+}
+
+/// Outlines the objects and actors inside the selection while editing.
+#[allow(clippy::type_complexity)]
+fn outline_selection(
+    mut commands: Commands,
+    state: Res<EditorState>,
+    hovered: Res<HoveredTile>,
+    current: Res<CurrentBoard>,
+    selection: Res<EditorSelection>,
+    palette: Res<PaletteLookup>,
+    entities: Query<
+        (Entity, &Position, Option<&Outline>),
+        (With<TextureAtlasSprite>, Without<Tile>),
+    >,
+) {
+    let rect = selection.shown(&hovered, &current).filter(|_| state.active);
+    let outline = Outline {
+        color: palette.color(PaletteColor::Highlight).with_a(1.),
+    };
+    for (entity, position, outlined) in entities.iter() {
+        let selected = rect.is_some_and(|rect| rect.contains(position.v));
+        match (selected, outlined) {
+            (true, Some(outlined)) if *outlined == outline => {}
+            (true, _) => {
+                commands.entity(entity).insert(outline);
+            }
+            (false, Some(_)) => {
+                commands.entity(entity).remove::<Outline>();
+            }
+            (false, None) => {}
+        }
+    }
+}
+
+/// Puts an arrow over each one-way cell while editing, again whenever the
+/// cells change.
+fn draw_one_way_arrows(
+    mut commands: Commands,
+    state: Res<EditorState>,
+    collision: Res<CollisionMap>,
+    projection: Res<GridProjection>,
+    assets: Res<GraphicsAssets>,
+    arrows: Query<Entity, With<OneWayArrow>>,
+) {
+    if !state.is_changed() && !collision.is_changed() {
+        return;
+    }
+    for entity in arrows.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !state.active {
+        return;
+    }
+
+    let style = TextStyle {
+        font: assets.font.clone(),
+        font_size: TILE_SIZE * 0.75,
+        color: ARROW_COLOR,
+    };
+    for (v, way) in collision.one_way_cells() {
+        let arrow = match way {
+            Direction::Up => "↑",
+            Direction::Down => "↓",
+            Direction::Left => "←",
+            Direction::Right => "→",
+        };
+        let at = projection.world(Vector3Int::new(v.x, v.y, 0)).truncate();
+        commands.spawn((
+            OneWayArrow,
+            Text2dBundle {
+                text: Text::from_section(arrow, style.clone()),
+                transform: Transform::from_translation(
+                    at.extend(z_for(RenderLayerSlot::Overlay, ARROW_Z)),
+                ),
+                ..default()
+            },
+        ));
+    }
+}
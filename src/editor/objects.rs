@@ -0,0 +1,468 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    grid_to_position,
+    hud::PointerOverUi,
+    objects::{spawn_map_object, MapObject, MapObjectIndex},
+    position_to_grid,
+    prefabs::PrefabRegistry,
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    simulation::SimulationPaused,
+    vectors::{GridKind, Vector3Int},
+    BoardLoadedEvent, CurrentBoard, GraphicsAssets, Scene, SceneHandle, TILE_SIZE,
+};
+
+use super::{left_for_editing, EditorState, EditorTool, HoveredTile};
+
+/// The kinds `spawn_map_objects` builds itself, offered before the
+/// prefabs.
+const BUILT_IN_KINDS: [&str; 15] = [
+    "trigger",
+    "door",
+    "hazard",
+    "pushable",
+    "pressure_plate",
+    "moving_tile",
+    "npc",
+    "spawner",
+    "region",
+    "exit",
+    "bomb",
+    "shop",
+    "item",
+    "label",
+    "layer_link",
+];
+/// Over one-way arrows, so that an object on a one-way cell is still named.
+const ICON_Z: f32 = 12.;
+const ICON_COLOR: Color = Color::rgba(1., 1., 1., 0.8);
+const FORM_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const FORM_FONT_SIZE: f32 = 16.;
+
+/// The current map's objects as edited, in map order, which is also their
+/// `MapObjectIndex`. Taken from the map whenever one loads, and written
+/// out with it on export.
+#[derive(Default, Resource)]
+pub struct EditorObjects {
+    pub objects: Vec<MapObject>,
+    /// The kind placed on empty cells, by index into `object_kinds`.
+    kind: usize,
+    /// The object whose properties the form shows.
+    selected: Option<usize>,
+    /// The form row picked: the column, the row, each property by key, and
+    /// last a blank one for adding a property.
+    row: usize,
+    /// The text being typed into the picked row, once Enter starts it.
+    typing: Option<String>,
+    /// Why the last entry was refused, shown under the form.
+    message: String,
+}
+
+impl EditorObjects {
+    pub fn typing(&self) -> bool {
+        self.typing.is_some()
+    }
+
+    /// The topmost object covering the cell at `col` and `row`.
+    fn at(&self, col: i32, row: i32) -> Option<usize> {
+        self.objects.iter().rposition(|object| {
+            let (width, height) = (object.footprint()).map_or((1, 1), |f| (f.0.width, f.0.height));
+            let (cols, rows) = (object.x..object.x + width, object.y..object.y + height);
+            cols.contains(&col) && rows.contains(&row)
+        })
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+        self.row = 0;
+        self.typing = None;
+        self.message.clear();
+    }
+}
+
+/// The label and text of each row of the form for `object`.
+fn form_rows(object: &MapObject) -> Vec<(String, String)> {
+    let mut properties: Vec<(&String, &serde_json::Value)> = object.properties.iter().collect();
+    properties.sort_unstable_by_key(|(key, _)| *key);
+    let mut rows = vec![
+        ("x".to_string(), object.x.to_string()),
+        ("y".to_string(), object.y.to_string()),
+    ];
+    rows.extend((properties.into_iter()).map(|(key, value)| (key.clone(), value_text(value))));
+    rows.push(("+".to_string(), String::new()));
+    rows
+}
+
+/// Strings as typed, without their quotes; anything else as JSON.
+fn value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Typed text as a property value: JSON where it reads as JSON, otherwise
+/// a string. Replacing a number only takes another number.
+fn parse_value(
+    text: &str,
+    previous: Option<&serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    let value = serde_json::from_str(text.trim())
+        .unwrap_or_else(|_| serde_json::Value::String(text.trim().to_string()));
+    match previous {
+        Some(serde_json::Value::Number(_)) if !value.is_number() => {
+            Err(format!("`{}` is not a number.", text.trim()))
+        }
+        _ => Ok(value),
+    }
+}
+
+/// Applies the text typed into `row` to `object`: a new column or row, on
+/// the board, a property's new value, empty to remove it, or a new
+/// `key=value` property.
+fn apply_row(
+    object: &mut MapObject,
+    row: usize,
+    text: &str,
+    grid: GridKind,
+    current: &CurrentBoard,
+) -> Result<(), String> {
+    let rows = form_rows(object);
+    let Some((key, _)) = rows.get(row) else { return Ok(()) };
+    match (row, key.as_str()) {
+        (0 | 1, _) => {
+            let Ok(value) = text.trim().parse::<i32>() else {
+                return Err(format!("`{}` is not a whole number.", text.trim()));
+            };
+            let (col, row) = match row {
+                0 => (value, object.y),
+                _ => (object.x, value),
+            };
+            let v = grid_to_position(grid, col, row, 0);
+            if !current.bounds.rect.contains(v) {
+                return Err(format!("({}, {}) is off the board.", col, row));
+            }
+            (object.x, object.y) = (col, row);
+        }
+        (_, "+") => {
+            let Some((key, value)) = text.split_once('=') else {
+                return Err("New properties are typed as key=value.".to_string());
+            };
+            let key = key.trim();
+            if key.is_empty() {
+                return Err("A property needs a key.".to_string());
+            }
+            let value = parse_value(value, object.properties.get(key))?;
+            object.properties.insert(key.to_string(), value);
+        }
+        (_, key) if text.trim().is_empty() => {
+            object.properties.remove(key);
+        }
+        (_, key) => {
+            let value = parse_value(text, object.properties.get(key))?;
+            object.properties.insert(key.to_string(), value);
+        }
+    }
+    Ok(())
+}
+
+/// The kinds the object tool places: the built-in ones, then each prefab.
+fn object_kinds(prefabs: &PrefabRegistry) -> Vec<&str> {
+    BUILT_IN_KINDS.into_iter().chain(prefabs.names()).collect()
+}
+
+/// Despawns the object spawned for `index`, if it is still about.
+fn despawn_object(
+    commands: &mut Commands,
+    spawned: &Query<(Entity, &mut MapObjectIndex)>,
+    index: usize,
+) {
+    for (entity, MapObjectIndex(i)) in spawned.iter() {
+        if *i == index {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Takes the objects of each map loaded, before any are edited.
+pub fn load_objects(
+    mut loaded: EventReader<BoardLoadedEvent>,
+    scene: Res<SceneHandle>,
+    scenes: Res<Assets<Scene>>,
+    mut objects: ResMut<EditorObjects>,
+) {
+    if loaded.iter().last().is_none() {
+        return;
+    }
+    let Some(scene) = scenes.get(&scene.0) else { return };
+    objects.objects = scene.objects.clone();
+    objects.select(None);
+}
+
+/// With the object tool, clicking an object selects it for the form, and
+/// clicking an empty cell places the chosen kind there.
+#[allow(clippy::too_many_arguments)]
+pub fn place_or_select(
+    mut commands: Commands,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    pointer: Res<PointerOverUi>,
+    state: Res<EditorState>,
+    hovered: Res<HoveredTile>,
+    grid: Res<GridKind>,
+    current: Res<CurrentBoard>,
+    prefabs: Res<PrefabRegistry>,
+    mut objects: ResMut<EditorObjects>,
+) {
+    if state.tool != EditorTool::Object
+        || !buttons.just_pressed(MouseButton::Left)
+        || !left_for_editing(&keys, &pointer)
+    {
+        return;
+    }
+    let Some(v) = hovered.0.map(|v| current.wrap(v)) else { return };
+    if !current.bounds.rect.contains(v) {
+        return;
+    }
+    let (col, row) = position_to_grid(*grid, v);
+    if let Some(index) = objects.at(col, row) {
+        objects.select(Some(index));
+        return;
+    }
+
+    let kinds = object_kinds(&prefabs);
+    let kind = kinds[objects.kind % kinds.len()];
+    let object = MapObject {
+        kind: kind.to_string(),
+        x: col,
+        y: row,
+        properties: default(),
+    };
+    let index = objects.objects.len();
+    spawn_map_object(&mut commands, &object, *grid, &prefabs, Some(index));
+    objects.objects.push(object);
+    objects.select(Some(index));
+    info!("Placed {} at ({}, {}).", kind, col, row);
+}
+
+/// Keys for the object tool: comma and period pick the kind placed, Delete
+/// removes the selected object, and Up, Down and Enter pick a row of its
+/// form to type into. While typing, Enter applies the row and Escape
+/// drops it; the game is paused meanwhile so that keys only type.
+#[allow(clippy::too_many_arguments)]
+pub fn edit_objects(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut chars: EventReader<ReceivedCharacter>,
+    state: Res<EditorState>,
+    grid: Res<GridKind>,
+    current: Res<CurrentBoard>,
+    prefabs: Res<PrefabRegistry>,
+    mut objects: ResMut<EditorObjects>,
+    mut spawned: Query<(Entity, &mut MapObjectIndex)>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    let objects = &mut *objects;
+    if let Some(text) = &mut objects.typing {
+        text.extend(chars.iter().map(|c| c.char).filter(|c| !c.is_control()));
+        if keys.just_pressed(KeyCode::Back) {
+            text.pop();
+        }
+        if keys.just_pressed(KeyCode::Escape) {
+            objects.typing = None;
+            paused.0 = false;
+            return;
+        }
+        if !keys.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]) {
+            return;
+        }
+        let text = objects.typing.take().unwrap_or_default();
+        paused.0 = false;
+        let Some(index) = objects.selected else { return };
+        let mut object = objects.objects[index].clone();
+        match apply_row(&mut object, objects.row, &text, *grid, &current) {
+            Ok(()) => {
+                // Spawned afresh, as the map would spawn it.
+                despawn_object(&mut commands, &spawned, index);
+                spawn_map_object(&mut commands, &object, *grid, &prefabs, Some(index));
+                objects.objects[index] = object;
+                objects.message.clear();
+            }
+            Err(reason) => objects.message = reason,
+        }
+        return;
+    }
+    chars.clear();
+    if state.tool != EditorTool::Object {
+        return;
+    }
+
+    let kinds = object_kinds(&prefabs);
+    if keys.just_pressed(KeyCode::Period) {
+        objects.kind = (objects.kind + 1) % kinds.len();
+        info!("Placing {}.", kinds[objects.kind]);
+    }
+    if keys.just_pressed(KeyCode::Comma) {
+        objects.kind = (objects.kind + kinds.len() - 1) % kinds.len();
+        info!("Placing {}.", kinds[objects.kind]);
+    }
+
+    let Some(index) = objects.selected else { return };
+    if keys.just_pressed(KeyCode::Delete) {
+        despawn_object(&mut commands, &spawned, index);
+        // Later objects move up the list.
+        for (_, mut i) in spawned.iter_mut().filter(|(_, i)| i.0 > index) {
+            i.0 -= 1;
+        }
+        let object = objects.objects.remove(index);
+        info!("Removed {} at ({}, {}).", object.kind, object.x, object.y);
+        objects.select(None);
+        return;
+    }
+    let count = form_rows(&objects.objects[index]).len();
+    if keys.just_pressed(KeyCode::Up) {
+        objects.row = (objects.row + count - 1) % count;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        objects.row = (objects.row + 1) % count;
+    }
+    if keys.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]) {
+        let rows = form_rows(&objects.objects[index]);
+        objects.typing = rows.get(objects.row).map(|(_, text)| text.clone());
+        objects.message.clear();
+        paused.0 = true;
+    }
+}
+
+/// Marks where each object is, named by kind, while editing. Hidden
+/// objects such as triggers and regions show too.
+#[derive(Component)]
+pub struct ObjectIcon;
+
+#[allow(clippy::too_many_arguments)]
+pub fn draw_object_icons(
+    mut commands: Commands,
+    state: Res<EditorState>,
+    objects: Res<EditorObjects>,
+    grid: Res<GridKind>,
+    projection: Res<GridProjection>,
+    palette: Res<PaletteLookup>,
+    assets: Res<GraphicsAssets>,
+    icons: Query<Entity, With<ObjectIcon>>,
+) {
+    if !state.is_changed() && !objects.is_changed() && !palette.is_changed() {
+        return;
+    }
+    for entity in icons.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !state.active {
+        return;
+    }
+
+    for (i, object) in objects.objects.iter().enumerate() {
+        let color = match objects.selected == Some(i) {
+            true => palette.color(PaletteColor::Highlight).with_a(1.),
+            false => ICON_COLOR,
+        };
+        let style = TextStyle {
+            font: assets.font.clone(),
+            font_size: TILE_SIZE * 0.3,
+            color,
+        };
+        let v = object.position(*grid);
+        let at = projection.world(Vector3Int::new(v.x, v.y, 0)).truncate();
+        commands.spawn((
+            ObjectIcon,
+            Text2dBundle {
+                text: Text::from_section(format!("◆\n{}", object.kind), style),
+                transform: Transform::from_translation(
+                    at.extend(z_for(RenderLayerSlot::Overlay, ICON_Z)),
+                ),
+                ..default()
+            },
+        ));
+    }
+}
+
+#[derive(Component)]
+pub struct ObjectForm;
+
+/// Shows the object tool's kind, and the selected object's form, in a
+/// panel at the left while the tool is in use.
+pub fn draw_object_form(
+    mut commands: Commands,
+    state: Res<EditorState>,
+    objects: Res<EditorObjects>,
+    prefabs: Res<PrefabRegistry>,
+    assets: Res<GraphicsAssets>,
+    forms: Query<Entity, With<ObjectForm>>,
+) {
+    if !state.is_changed() && !objects.is_changed() {
+        return;
+    }
+    for entity in forms.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !state.active || state.tool != EditorTool::Object {
+        return;
+    }
+
+    let kinds = object_kinds(&prefabs);
+    let mut text = format!(
+        "Placing {} (, and . to change)\n",
+        kinds[objects.kind % kinds.len()]
+    );
+    if let Some(object) = objects.selected.and_then(|i| objects.objects.get(i)) {
+        text += &format!("\n{}\n", object.kind);
+        for (i, (key, value)) in form_rows(object).into_iter().enumerate() {
+            let picked = i == objects.row;
+            let value = match &objects.typing {
+                Some(typed) if picked => format!("{}_", typed),
+                _ => value,
+            };
+            let marker = if picked { ">" } else { " " };
+            text += &match key.as_str() {
+                "+" => format!("{} + key=value {}\n", marker, value),
+                _ => format!("{} {} = {}\n", marker, key, value),
+            };
+        }
+        text += "\nEnter to edit, empty to remove, Delete to remove the object";
+    }
+    if !objects.message.is_empty() {
+        text += "\n\n";
+        text += &objects.message;
+    }
+
+    commands
+        .spawn((
+            ObjectForm,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(10.),
+                        top: Val::Px(40.),
+                        ..default()
+                    },
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: FORM_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: FORM_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
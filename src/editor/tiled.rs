@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::{
+    collision::Footprint, objects::MapObject, Scene, Tileset, DEFAULT_TILE_COUNT, SPRITE_SHEET,
+    TILE_SIZE,
+};
+
+/// Columns of the built-in sheet, as `load_assets` cuts it up.
+const BUILT_IN_COLUMNS: usize = 12;
+/// The version of Tiled's JSON format written.
+const TILED_VERSION: &str = "1.10";
+
+/// `json`, a map as `export_scene` writes it, as an orthogonal Tiled map:
+/// its ground layers as tile layers, its objects as an object layer, and
+/// its tilesets, or else the built-in sheet, embedded with their images
+/// found under `image_dir`. Tiled numbers tiles from each sheet's
+/// `firstgid` as map values do, and counts rows down from the top as
+/// scenes do, so layers carry over as they are. Only objects change,
+/// from cells to pixels, which also count down from the top.
+pub fn tiled_map(json: &Value, scene: &Scene, image_dir: &str) -> Value {
+    let (width, height) = scene.size();
+    let tile = scene.meta.tile_size.unwrap_or(TILE_SIZE);
+    let ground = json["layers"].as_array().into_iter().flatten();
+    let mut layers: Vec<Value> = (ground.enumerate())
+        .map(|(i, data)| {
+            json!({
+                "id": i + 1,
+                "name": format!("Ground {}", i),
+                "type": "tilelayer",
+                "x": 0,
+                "y": 0,
+                "width": width,
+                "height": height,
+                "opacity": 1,
+                "visible": true,
+                "data": data,
+            })
+        })
+        .collect();
+    let objects: Vec<MapObject> =
+        serde_json::from_value(json["objects"].clone()).unwrap_or_default();
+    let objects: Vec<Value> = (objects.iter().enumerate())
+        .map(|(i, object)| tiled_object(i + 1, object, tile))
+        .collect();
+    let next_object = objects.len() + 1;
+    layers.push(json!({
+        "id": layers.len() + 1,
+        "name": "Objects",
+        "type": "objectgroup",
+        "draworder": "topdown",
+        "x": 0,
+        "y": 0,
+        "opacity": 1,
+        "visible": true,
+        "objects": objects,
+    }));
+
+    let built_in = Tileset {
+        image: SPRITE_SHEET.to_string(),
+        firstgid: 1,
+        columns: BUILT_IN_COLUMNS,
+        tilecount: DEFAULT_TILE_COUNT,
+        spacing: 0.,
+        tilewidth: Some(TILE_SIZE),
+        tileheight: Some(TILE_SIZE),
+        names: Default::default(),
+    };
+    let tilesets: Vec<Value> = match scene.tilesets.is_empty() {
+        true => vec![tiled_tileset(&built_in, tile, image_dir)],
+        false => (scene.tilesets.iter())
+            .map(|tileset| tiled_tileset(tileset, tile, image_dir))
+            .collect(),
+    };
+    json!({
+        "type": "map",
+        "version": TILED_VERSION,
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "infinite": false,
+        "compressionlevel": -1,
+        "width": width,
+        "height": height,
+        "tilewidth": tile as u32,
+        "tileheight": tile as u32,
+        "nextlayerid": layers.len() + 1,
+        "nextobjectid": next_object,
+        "layers": layers,
+        "tilesets": tilesets,
+    })
+}
+
+/// `object` as a rectangle over the cells it covers, with its kind as its
+/// class. Tiled has no lists or maps for properties, so those are kept as
+/// JSON text.
+fn tiled_object(id: usize, object: &MapObject, tile: f32) -> Value {
+    let (width, height) = (object.footprint()).map_or((1, 1), |Footprint(r)| (r.width, r.height));
+    let mut keys: Vec<&String> = object.properties.keys().collect();
+    keys.sort();
+    let properties: Vec<Value> = (keys.into_iter())
+        .map(|key| {
+            let value = &object.properties[key];
+            let (kind, value) = match value {
+                Value::Bool(_) => ("bool", value.clone()),
+                Value::Number(n) if n.is_f64() => ("float", value.clone()),
+                Value::Number(_) => ("int", value.clone()),
+                Value::String(_) => ("string", value.clone()),
+                _ => ("string", Value::from(value.to_string())),
+            };
+            json!({ "name": key, "type": kind, "value": value })
+        })
+        .collect();
+    json!({
+        "id": id,
+        "name": "",
+        "type": object.kind,
+        "x": object.x as f32 * tile,
+        "y": object.y as f32 * tile,
+        "width": width as f32 * tile,
+        "height": height as f32 * tile,
+        "rotation": 0,
+        "visible": true,
+        "properties": properties,
+    })
+}
+
+fn tiled_tileset(tileset: &Tileset, tile: f32, image_dir: &str) -> Value {
+    let tile_width = tileset.tilewidth.unwrap_or(tile);
+    let tile_height = tileset.tileheight.unwrap_or(tile);
+    let columns = tileset.columns.max(1);
+    let rows = tileset.tilecount.div_ceil(columns);
+    // Tiles with `spacing` between them, and none around the edge.
+    let extent = |count: usize, size: f32| {
+        count as f32 * size + count.saturating_sub(1) as f32 * tileset.spacing
+    };
+    let name = (Path::new(&tileset.image).file_stem())
+        .map_or(tileset.image.clone(), |stem| stem.to_string_lossy().into());
+    json!({
+        "firstgid": tileset.firstgid,
+        "name": name,
+        "image": format!("{}/{}", image_dir, tileset.image),
+        "imagewidth": extent(columns, tile_width) as u32,
+        "imageheight": extent(rows, tile_height) as u32,
+        "columns": columns,
+        "tilecount": tileset.tilecount,
+        "tilewidth": tile_width as u32,
+        "tileheight": tile_height as u32,
+        "spacing": tileset.spacing as u32,
+        "margin": 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid_to_position, vectors::GridKind};
+
+    /// Three columns by four rows, with a key in the top-left corner, a
+    /// crate in the bottom-right one and a gate two wide and three high
+    /// down to the bottom row.
+    fn map() -> Value {
+        json!({
+            "width": 3,
+            "layers": [(1..=12).collect::<Vec<usize>>()],
+            "objects": [
+                { "kind": "key", "x": 0, "y": 0 },
+                { "kind": "crate", "x": 2, "y": 3 },
+                { "kind": "gate", "x": 1, "y": 1, "properties": { "width": 2, "height": 3 } },
+            ],
+        })
+    }
+
+    #[test]
+    fn rows_and_objects_count_down_from_the_top() {
+        let json = map();
+        let scene: Scene = serde_json::from_value(json.clone()).unwrap();
+        let tiled = tiled_map(&json, &scene, "assets");
+        let size = (tiled["width"].as_u64(), tiled["height"].as_u64());
+        assert_eq!(size, (Some(3), Some(4)));
+
+        // The top row first, as in the scene.
+        let ground = &tiled["layers"][0];
+        assert_eq!(ground["data"], json["layers"][0]);
+        assert_eq!(ground["data"][0], 1);
+        assert_eq!(ground["data"][11], 12);
+
+        let objects = tiled["layers"][1]["objects"].as_array().unwrap();
+        let rects: Vec<[f32; 4]> = (objects.iter())
+            .map(|o| ["x", "y", "width", "height"].map(|k| o[k].as_f64().unwrap() as f32))
+            .collect();
+        let t = TILE_SIZE;
+        assert_eq!(rects[0], [0., 0., t, t]);
+        assert_eq!(rects[1], [2. * t, 3. * t, t, t]);
+        assert_eq!(rects[2], [t, t, 2. * t, 3. * t]);
+    }
+
+    #[test]
+    fn objects_land_back_on_their_cells() {
+        let json = map();
+        let scene: Scene = serde_json::from_value(json.clone()).unwrap();
+        let tiled = tiled_map(&json, &scene, "assets");
+        let objects: Vec<MapObject> = serde_json::from_value(json["objects"].clone()).unwrap();
+        let exported = tiled["layers"][1]["objects"].as_array().unwrap();
+        let square = |object: &MapObject| object.position(GridKind::Square);
+
+        // Tiled's y goes down the map and the board's up it, so the row of a
+        // rectangle's anchor is the bottom one, counted from the top.
+        for (object, exported) in objects.iter().zip(exported) {
+            let cells = |k: &str| (exported[k].as_f64().unwrap() as f32 / TILE_SIZE) as i32;
+            let bottom = cells("y") + cells("height") - 1;
+            let anchor = grid_to_position(GridKind::Square, cells("x"), bottom, 0);
+            let expected = square(object);
+            let cell = (anchor.x, anchor.y);
+            assert_eq!(cell, (expected.x, expected.y), "{}", object.kind);
+        }
+
+        // The key, on the top row, is the highest on the board.
+        let key = square(&objects[0]);
+        assert!(objects.iter().all(|o| square(o).y <= key.y));
+        assert!(square(&objects[1]).y < key.y);
+    }
+}
@@ -0,0 +1,280 @@
+//! What damage is made of. Every hit carries an `Element`, and whoever
+//! takes it shrugs off a share by their `Resistances` to it, or takes more
+//! where those are below 0. Fire sets light to what its target stands on,
+//! and ice may slow it.
+
+use std::ops::Add;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{covered_cells, Footprint},
+    combat::DamageEvent,
+    equipment::StatSheet,
+    fire::IgniteEvent,
+    rng::GameRng,
+    simulation::SimulationSet,
+    status::{ApplyStatusEvent, StatusEffect, StatusEffects, StatusKind},
+    Position,
+};
+
+/// What a hit is made of. On an entity, the element of the damage it
+/// deals, by attack or as a projectile.
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    #[default]
+    Physical,
+    Fire,
+    Ice,
+    Poison,
+}
+
+impl Element {
+    pub const ALL: [Element; 4] = [
+        Element::Physical,
+        Element::Fire,
+        Element::Ice,
+        Element::Poison,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Element::Physical => "physical",
+            Element::Fire => "fire",
+            Element::Ice => "ice",
+            Element::Poison => "poison",
+        }
+    }
+
+    /// The element called `name` in map properties.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.name() == name)
+    }
+}
+
+/// Percent of each element's damage shrugged off: 50 for half, 100 for
+/// none at all, and below 0 for more than dealt. Resistances from several
+/// sources add up.
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub physical: f32,
+    pub fire: f32,
+    pub ice: f32,
+    pub poison: f32,
+}
+
+impl Resistances {
+    /// `percent` against every element.
+    pub fn all(percent: f32) -> Self {
+        Resistances {
+            physical: percent,
+            fire: percent,
+            ice: percent,
+            poison: percent,
+        }
+    }
+
+    pub fn get(&self, element: Element) -> f32 {
+        match element {
+            Element::Physical => self.physical,
+            Element::Fire => self.fire,
+            Element::Ice => self.ice,
+            Element::Poison => self.poison,
+        }
+    }
+
+    /// What an entity resists in all: its stat sheet if a player, which
+    /// counts everything in already, or else its own resistances and what
+    /// its status effects add.
+    pub fn of(
+        sheet: Option<&StatSheet>,
+        own: Option<&Resistances>,
+        effects: Option<&StatusEffects>,
+    ) -> Self {
+        match sheet {
+            Some(sheet) => sheet.resistances,
+            None => {
+                let own = own.copied().unwrap_or_default();
+                own + effects.map_or_else(Resistances::default, |e| e.resistances())
+            }
+        }
+    }
+}
+
+impl Add for Resistances {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Resistances {
+            physical: self.physical + other.physical,
+            fire: self.fire + other.fire,
+            ice: self.ice + other.ice,
+            poison: self.poison + other.poison,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ElementConfig {
+    /// The least a hit of any damage at all deals, however resisted. 0
+    /// lets full resistance make its bearer immune.
+    pub min_damage: u32,
+    /// Chance that an ice hit slows its target.
+    pub chill_chance: f32,
+    /// The slow an ice hit puts on.
+    pub chill: StatusEffect,
+}
+
+impl Default for ElementConfig {
+    fn default() -> Self {
+        ElementConfig {
+            min_damage: 1,
+            chill_chance: 0.25,
+            chill: StatusEffect {
+                kind: StatusKind::Slow,
+                magnitude: 1.5,
+                remaining: 3,
+            },
+        }
+    }
+}
+
+impl ElementConfig {
+    /// `amount` less `percent` of it, rounded, and no less than
+    /// `min_damage` where there was any. Resistance past 100 counts as 100.
+    pub fn resist(&self, amount: u32, percent: f32) -> u32 {
+        if amount == 0 {
+            return 0;
+        }
+        let scaled = amount as f32 * (1. - percent.min(100.) / 100.);
+        (scaled.round() as u32).max(self.min_damage)
+    }
+}
+
+pub struct ElementsPlugin;
+impl Plugin for ElementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ElementConfig>().add_system(
+            element_effects
+                .in_set(SimulationSet::React)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// Fire hits set light to whatever burns under their target, and ice hits
+/// may slow it.
+fn element_effects(
+    mut events: EventReader<DamageEvent>,
+    targets: Query<(&Position, Option<&Footprint>)>,
+    config: Res<ElementConfig>,
+    mut rng: ResMut<GameRng>,
+    mut ignite: EventWriter<IgniteEvent>,
+    mut statuses: EventWriter<ApplyStatusEvent>,
+) {
+    for event in events.iter() {
+        match event.element {
+            Element::Fire => {
+                let Ok((position, footprint)) = targets.get(event.entity) else { continue };
+                for position in covered_cells(position.v, footprint) {
+                    ignite.send(IgniteEvent { position });
+                }
+            }
+            Element::Ice if rng.chance(config.chill_chance) => {
+                statuses.send(ApplyStatusEvent {
+                    entity: event.entity,
+                    effect: config.chill,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        equipment::StatModifiers,
+        status::{StackRule, StatusKind},
+    };
+
+    fn warded(percent: f32) -> StatusEffect {
+        StatusEffect {
+            kind: StatusKind::Warded,
+            magnitude: percent,
+            remaining: 3,
+        }
+    }
+
+    #[test]
+    fn resistances_add_up_from_every_source() {
+        let own = Resistances {
+            fire: 20.,
+            ..default()
+        };
+        let boots = StatModifiers {
+            resistances: Resistances {
+                fire: 30.,
+                ice: -25.,
+                ..default()
+            },
+            ..default()
+        };
+        let ring = StatModifiers {
+            resistances: Resistances::all(5.),
+            ..default()
+        };
+        let effects = StatusEffects(vec![warded(10.)]);
+
+        let sheet = StatSheet::build(Some(&own), [&boots, &ring], Some(&effects));
+        let expected = Resistances {
+            physical: 15.,
+            fire: 65.,
+            ice: -10.,
+            poison: 15.,
+        };
+        assert_eq!(sheet.resistances, expected);
+
+        // Players go by their sheet alone, which has the rest counted in.
+        let of = Resistances::of(Some(&sheet), Some(&own), Some(&effects));
+        assert_eq!(of, expected);
+        // Anyone else by their own and their effects'.
+        let of = Resistances::of(None, Some(&own), Some(&effects));
+        assert_eq!(of.fire, 30.);
+        assert_eq!(of.ice, 10.);
+        assert_eq!(Resistances::of(None, None, None), Resistances::default());
+    }
+
+    #[test]
+    fn wards_refresh_rather_than_add() {
+        let mut effects = StatusEffects::default();
+        effects.apply(warded(10.), StackRule::Refresh);
+        effects.apply(warded(25.), StackRule::Refresh);
+        effects.apply(warded(15.), StackRule::Refresh);
+        assert_eq!(effects.resistances(), Resistances::all(25.));
+    }
+
+    #[test]
+    fn resisted_damage_is_clamped() {
+        let config = ElementConfig::default();
+        assert_eq!(config.resist(10, 0.), 10);
+        assert_eq!(config.resist(10, 65.), 4);
+        // Vulnerability deals more.
+        assert_eq!(config.resist(10, -50.), 15);
+        // Full resistance, or more, still lets the least through.
+        assert_eq!(config.resist(10, 100.), 1);
+        assert_eq!(config.resist(10, 250.), 1);
+        assert_eq!(config.resist(0, -50.), 0);
+
+        let immune = ElementConfig {
+            min_damage: 0,
+            ..default()
+        };
+        assert_eq!(immune.resist(10, 100.), 0);
+        assert_eq!(immune.resist(10, 96.), 0);
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{Health, BUMP_ATTACK_DAMAGE},
+    elements::Resistances,
+    inventory::Inventory,
+    locale::Localization,
+    player::{Player, PLAYER_HEALTH},
+    prefabs::PrefabRegistry,
+    simulation::{SimulationPaused, SimulationSet},
+    status::StatusEffects,
+    t,
+    terrain::Capabilities,
+    vision::PLAYER_VISION,
+    AppState, GraphicsAssets,
+};
+
+const SCREEN_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const SCREEN_FONT_SIZE: f32 = 20.;
+const SELECTED_COLOR: Color = Color::YELLOW;
+
+/// Where an item is worn. A player has one of each.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+    Accessory,
+}
+
+impl EquipSlot {
+    pub const ALL: [EquipSlot; 3] = [EquipSlot::Weapon, EquipSlot::Armor, EquipSlot::Accessory];
+
+    /// The key of its name in the locale files.
+    pub fn key(self) -> &'static str {
+        match self {
+            EquipSlot::Weapon => "equipment.weapon",
+            EquipSlot::Armor => "equipment.armor",
+            EquipSlot::Accessory => "equipment.accessory",
+        }
+    }
+}
+
+/// What an item adds to the stats of whoever wears it.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct StatModifiers {
+    pub attack: i32,
+    pub max_health: i32,
+    /// Cells further seen.
+    pub vision: i32,
+    /// How much faster steps are taken: 0.25 for a quarter faster.
+    pub speed: f32,
+    /// What the wearer can do, such as `["swim"]` for flippers.
+    pub capabilities: Capabilities,
+    /// Percent more of each element's damage shrugged off.
+    pub resistances: Resistances,
+}
+
+/// The `equip` entry of an item's prefab: the slot it is worn in and what
+/// it does there.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Equippable {
+    pub slot: EquipSlot,
+    #[serde(flatten)]
+    pub modifiers: StatModifiers,
+}
+
+/// The items a player wears, by slot. Each is the id of an item prefab.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Equipment {
+    slots: BTreeMap<EquipSlot, String>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipSlot) -> Option<&str> {
+        self.slots.get(&slot).map(|item| item.as_str())
+    }
+
+    /// Puts `item` on in `slot`, returning whatever it replaces.
+    pub fn equip(&mut self, slot: EquipSlot, item: &str) -> Option<String> {
+        self.slots.insert(slot, item.to_string())
+    }
+
+    pub fn unequip(&mut self, slot: EquipSlot) -> Option<String> {
+        self.slots.remove(&slot)
+    }
+
+    /// Each item worn and its slot.
+    pub fn iter(&self) -> impl Iterator<Item = (EquipSlot, &str)> {
+        self.slots.iter().map(|(slot, item)| (*slot, item.as_str()))
+    }
+}
+
+/// A player's stats with their equipment and status effects counted in,
+/// read by combat, vision and movement in place of the base constants.
+/// Rebuilt whenever either changes.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct StatSheet {
+    /// Damage dealt by a bump attack.
+    pub attack: u32,
+    pub max_health: u32,
+    /// Cells seen in every direction.
+    pub vision: u32,
+    /// Multiplier on the time between steps.
+    pub interval_scale: f32,
+    /// What terrain allows, from equipment and status effects.
+    pub capabilities: Capabilities,
+    /// Percent of each element's damage shrugged off, from the player's
+    /// own resistances, equipment and status effects added up.
+    pub resistances: Resistances,
+}
+
+impl Default for StatSheet {
+    fn default() -> Self {
+        StatSheet {
+            attack: BUMP_ATTACK_DAMAGE,
+            max_health: PLAYER_HEALTH,
+            vision: PLAYER_VISION,
+            interval_scale: 1.,
+            capabilities: Capabilities::NONE,
+            resistances: Resistances::default(),
+        }
+    }
+}
+
+impl StatSheet {
+    /// The base stats with each of `modifiers` added, stepping as fast as
+    /// `effects` allow. Resistances are `own` with those of `modifiers` and
+    /// `effects` added.
+    pub fn build<'a>(
+        own: Option<&Resistances>,
+        modifiers: impl IntoIterator<Item = &'a StatModifiers>,
+        effects: Option<&StatusEffects>,
+    ) -> Self {
+        let base = StatSheet::default();
+        let mut total = StatModifiers::default();
+        for m in modifiers {
+            total.attack += m.attack;
+            total.max_health += m.max_health;
+            total.vision += m.vision;
+            total.speed += m.speed;
+            total.capabilities |= m.capabilities;
+            total.resistances = total.resistances + m.resistances;
+        }
+        let add = |base: u32, bonus: i32| (base as i32 + bonus).max(0) as u32;
+        StatSheet {
+            attack: add(base.attack, total.attack),
+            max_health: add(base.max_health, total.max_health).max(1),
+            vision: add(base.vision, total.vision),
+            interval_scale: effects.map_or(1., |e| e.interval_scale())
+                / (1. + total.speed).max(f32::EPSILON),
+            capabilities: total.capabilities
+                | effects.map_or(Capabilities::NONE, |e| e.capabilities()),
+            resistances: Resistances::of(None, own, effects) + total.resistances,
+        }
+    }
+}
+
+/// The inventory screen, open over a paused game for the first player.
+#[derive(Default, Resource)]
+pub struct InventoryScreen {
+    open: Option<Entity>,
+    selected: usize,
+    /// The outcome of the last action, shown under the rows.
+    message: String,
+}
+
+impl InventoryScreen {
+    /// The player whose inventory is showing, if any.
+    pub fn player(&self) -> Option<Entity> {
+        self.open
+    }
+
+    /// Closes the screen, leaving the game paused for whatever replaces it.
+    pub fn close(&mut self) {
+        *self = InventoryScreen::default();
+    }
+}
+
+#[derive(Component)]
+struct InventoryScreenRoot;
+
+/// A row of the screen, selected on hover and used on click.
+#[derive(Component)]
+struct InventoryRow(usize);
+
+/// What a row of the screen holds: a slot, worn or not, then each item
+/// carried.
+enum Row {
+    Slot(EquipSlot, Option<String>),
+    Item(String, u32),
+}
+
+fn rows(equipment: &Equipment, inventory: &Inventory) -> Vec<Row> {
+    let worn = |slot: EquipSlot| equipment.get(slot).map(str::to_string);
+    (EquipSlot::ALL.iter())
+        .map(|slot| Row::Slot(*slot, worn(*slot)))
+        .chain((inventory.iter()).map(|(id, count)| Row::Item(id.to_string(), count)))
+        .collect()
+}
+
+pub struct EquipmentPlugin;
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InventoryScreen>()
+            // Paused or not, so that changes made on the screen count.
+            .add_system(
+                rebuild_stat_sheets
+                    .in_set(SimulationSet::End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (toggle_screen, use_screen, draw_screen)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(close_screen.in_schedule(OnExit(AppState::Game)));
+    }
+}
+
+/// Recounts the stats of players whose equipment, status effects or own
+/// resistances changed, and of every player when the prefabs do. Health is
+/// kept within the new maximum.
+#[allow(clippy::type_complexity)]
+fn rebuild_stat_sheets(
+    prefabs: Res<PrefabRegistry>,
+    mut players: Query<
+        (
+            Ref<Equipment>,
+            Option<Ref<StatusEffects>>,
+            Option<Ref<Resistances>>,
+            &mut StatSheet,
+            Option<&mut Health>,
+        ),
+        With<Player>,
+    >,
+) {
+    for (equipment, effects, own, mut sheet, health) in players.iter_mut() {
+        let effects_changed = effects.as_ref().is_some_and(|e| e.is_changed());
+        let own_changed = own.as_ref().is_some_and(|r| r.is_changed());
+        if !equipment.is_changed() && !effects_changed && !own_changed && !prefabs.is_changed() {
+            continue;
+        }
+        let modifiers: Vec<StatModifiers> = (equipment.iter())
+            .filter_map(|(_, item)| prefabs.get(item)?.equip)
+            .map(|equip| equip.modifiers)
+            .collect();
+        let rebuilt = StatSheet::build(own.as_deref(), &modifiers, effects.as_deref());
+        if *sheet != rebuilt {
+            *sheet = rebuilt;
+        }
+        let Some(mut health) = health.filter(|h| h.max != rebuilt.max_health) else {
+            continue;
+        };
+        health.max = rebuilt.max_health;
+        health.current = health.current.min(health.max);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn toggle_screen(
+    keys: Res<Input<KeyCode>>,
+    mut screen: ResMut<InventoryScreen>,
+    mut paused: ResMut<SimulationPaused>,
+    players: Query<(Entity, &Player), (With<Inventory>, With<Equipment>)>,
+) {
+    let close = screen.open.is_some() && keys.just_pressed(KeyCode::Escape);
+    if !keys.just_pressed(KeyCode::I) && !close {
+        return;
+    }
+    let first = players.iter().min_by_key(|(_, p)| p.index).map(|(e, _)| e);
+    *screen = InventoryScreen {
+        open: screen.open.xor(first),
+        ..default()
+    };
+    paused.0 = screen.open.is_some();
+}
+
+fn close_screen(
+    mut commands: Commands,
+    mut screen: ResMut<InventoryScreen>,
+    mut paused: ResMut<SimulationPaused>,
+    roots: Query<Entity, With<InventoryScreenRoot>>,
+) {
+    if screen.open.take().is_some() {
+        paused.0 = false;
+    }
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Moves the selection, and on Enter or a click puts on the item selected
+/// or takes off the one in the slot selected.
+fn use_screen(
+    keys: Res<Input<KeyCode>>,
+    mut screen: ResMut<InventoryScreen>,
+    clicks: Query<(&Interaction, &InventoryRow), Changed<Interaction>>,
+    mut players: Query<(&mut Inventory, &mut Equipment)>,
+    prefabs: Res<PrefabRegistry>,
+    locale: Res<Localization>,
+) {
+    let Some(Ok((mut inventory, mut equipment))) = screen.open.map(|p| players.get_mut(p)) else {
+        return;
+    };
+    let count = EquipSlot::ALL.len() + inventory.iter().count();
+    if keys.just_pressed(KeyCode::Up) {
+        screen.selected = (screen.selected + count - 1) % count;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        screen.selected = (screen.selected + 1) % count;
+    }
+    let mut act = keys.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]);
+    for (interaction, InventoryRow(row)) in clicks.iter() {
+        match interaction {
+            Interaction::Hovered if screen.selected != *row => screen.selected = *row,
+            Interaction::Clicked => {
+                screen.selected = *row;
+                act = true;
+            }
+            _ => {}
+        }
+    }
+    if !act {
+        return;
+    }
+
+    let rows = rows(&equipment, &inventory);
+    let Some(row) = rows.into_iter().nth(screen.selected) else { return };
+    let name = |item: &str| t!(locale, &format!("item.{}", item));
+    screen.message = match row {
+        Row::Slot(_, None) => return,
+        Row::Slot(slot, Some(item)) => {
+            if inventory.add(&item, 1) {
+                equipment.unequip(slot);
+                t!(locale, "inventory.unequipped", item = name(&item))
+            } else {
+                t!(locale, "inventory.full", item = name(&item))
+            }
+        }
+        Row::Item(item, _) => match prefabs.get(&item).and_then(|p| p.equip) {
+            None => t!(locale, "inventory.not_equippable", item = name(&item)),
+            Some(equip) => {
+                inventory.remove(&item, 1);
+                // What comes off takes the place of what goes on.
+                match equipment.get(equip.slot).map(str::to_string) {
+                    Some(worn) if !inventory.add(&worn, 1) => {
+                        inventory.add(&item, 1);
+                        t!(locale, "inventory.full", item = name(&worn))
+                    }
+                    _ => {
+                        equipment.equip(equip.slot, &item);
+                        let last = EquipSlot::ALL.len() + inventory.iter().count();
+                        screen.selected = screen.selected.min(last - 1);
+                        t!(locale, "inventory.equipped", item = name(&item))
+                    }
+                }
+            }
+        },
+    };
+}
+
+/// Rebuilds the screen whenever it or what it shows changes.
+fn draw_screen(
+    mut commands: Commands,
+    screen: Res<InventoryScreen>,
+    assets: Res<GraphicsAssets>,
+    players: Query<(Ref<Inventory>, Ref<Equipment>, Ref<StatSheet>)>,
+    roots: Query<Entity, With<InventoryScreenRoot>>,
+    locale: Res<Localization>,
+) {
+    let open = screen.open.and_then(|player| players.get(player).ok());
+    let changed = (open.as_ref()).is_some_and(|(inventory, equipment, sheet)| {
+        inventory.is_changed() || equipment.is_changed() || sheet.is_changed()
+    });
+    if !screen.is_changed() && !locale.is_changed() && !changed {
+        return;
+    }
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some((inventory, equipment, sheet)) = open else { return };
+
+    let style = |color| TextStyle {
+        font: assets.font.clone(),
+        font_size: SCREEN_FONT_SIZE,
+        color,
+    };
+    let stats = t!(
+        locale,
+        "inventory.stats",
+        attack = sheet.attack,
+        health = sheet.max_health,
+        vision = sheet.vision
+    );
+    let mut footer = t!(locale, "inventory.help");
+    if !screen.message.is_empty() {
+        footer += "\n\n";
+        footer += &screen.message;
+    }
+
+    commands
+        .spawn((
+            InventoryScreenRoot,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: SCREEN_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let title = t!(locale, "inventory.title") + "\n\n" + &stats + "\n";
+            parent.spawn(TextBundle::from_section(title, style(Color::WHITE)));
+            for (i, row) in rows(&equipment, &inventory).into_iter().enumerate() {
+                let text = match row {
+                    Row::Slot(slot, item) => {
+                        let item = match item {
+                            Some(item) => t!(locale, &format!("item.{}", item)),
+                            None => t!(locale, "equipment.empty"),
+                        };
+                        format!("{}: {}", t!(locale, slot.key()), item)
+                    }
+                    Row::Item(item, count) => {
+                        format!("{} x{}", t!(locale, &format!("item.{}", item)), count)
+                    }
+                };
+                let (marker, color) = match i == screen.selected {
+                    true => (">", SELECTED_COLOR),
+                    false => (" ", Color::WHITE),
+                };
+                // A gap between what is worn and what is carried.
+                let gap = if i == EquipSlot::ALL.len() { "\n" } else { "" };
+                parent.spawn((
+                    InventoryRow(i),
+                    Interaction::default(),
+                    TextBundle::from_section(format!("{}{} {}", gap, marker, text), style(color)),
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                "\n".to_string() + &footer,
+                style(Color::WHITE),
+            ));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        testing::{headless_game, run_ticks},
+        tint::TileTint,
+        vectors::{GridKind, Vector3Int},
+        CurrentBoard, Position,
+    };
+
+    /// Cells from the player to the furthest tile the fog leaves lit.
+    fn fog_radius(app: &mut App) -> i32 {
+        let mut players = app.world.query_filtered::<&Position, With<Player>>();
+        let mut tints = app.world.query::<&TileTint>();
+        let from = players.single(&app.world).v;
+        let current = app.world.resource::<CurrentBoard>();
+        let grid = *app.world.resource::<GridKind>();
+        let flat = |v: Vector3Int| Vector3Int::new(v.x, v.y, 0);
+        (current.tiles.iter())
+            .filter(|(_, entity)| {
+                let tint = tints.get(&app.world, **entity);
+                tint.map_or(true, |tint| tint.fog == Color::WHITE)
+            })
+            .map(|(v, _)| current.distance(grid, flat(from), flat(*v)))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn a_vision_amulet_pushes_back_the_fog() {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 1);
+        app.update();
+        assert_eq!(fog_radius(&mut app), PLAYER_VISION as i32);
+
+        let mut players = app.world.query_filtered::<&mut Equipment, With<Player>>();
+        let mut equipment = players.single_mut(&mut app.world);
+        equipment.equip(EquipSlot::Accessory, "amulet");
+        run_ticks(&mut app, 2);
+        app.update();
+        assert_eq!(fog_radius(&mut app), PLAYER_VISION as i32 + 2);
+    }
+}
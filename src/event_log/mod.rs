@@ -0,0 +1,410 @@
+//! A side panel of what has just happened, for following emergent
+//! behaviour: damage, steps, tile changes, doors, regions, spawns and
+//! deaths as they come in, newest last, coloured by category. Shift+F7
+//! shows it, since F7 alone has the stats page. Nothing is read while it is
+//! hidden or paused, so it costs the game nothing until wanted.
+//!
+//! Games log their own events on the same panel:
+//!
+//! ```ignore
+//! app.log_event::<QuestEvent>("quest", Color::GOLD, |quest, out| {
+//!     let _ = write!(out, "{} done", quest.id);
+//! });
+//! ```
+
+use std::{collections::VecDeque, fmt::Write};
+
+use bevy::{ecs::event::Event, prelude::*};
+
+use crate::{
+    board::TileChangedEvent,
+    collision::Occupier,
+    combat::{DamageEvent, DiedEvent},
+    npc::NpcSteppedEvent,
+    objects::Door,
+    player::PlayerStepStarted,
+    regions::{RegionEntered, RegionExited},
+    GraphicsAssets,
+};
+
+/// Entries kept. Past this, each new one takes the place of the oldest.
+pub const EVENT_LOG_LIMIT: usize = 200;
+/// Pressed with Shift.
+const TOGGLE_KEY: KeyCode = KeyCode::F7;
+const PANEL_COLOR: Color = Color::rgba(0., 0., 0., 0.7);
+const PANEL_FONT_SIZE: f32 = 12.;
+const HIDDEN_COLOR: Color = Color::DARK_GRAY;
+
+/// One line of the log.
+struct Entry {
+    /// Seconds since startup.
+    seconds: f32,
+    category: usize,
+    text: String,
+}
+
+/// A kind of entry, shown in its own colour and hidden as one.
+struct Category {
+    name: &'static str,
+    color: Color,
+    shown: bool,
+}
+
+/// The latest entries, oldest first, and the categories they fall in.
+/// Entries only come in while the panel is open and not paused.
+#[derive(Default, Resource)]
+pub struct EventLogger {
+    pub open: bool,
+    pub paused: bool,
+    categories: Vec<Category>,
+    entries: VecDeque<Entry>,
+    /// Text of entries gone, to write new ones into.
+    spare: Vec<String>,
+}
+
+impl EventLogger {
+    /// The index of category `name`, added in `color` if new.
+    pub fn category(&mut self, name: &'static str, color: Color) -> usize {
+        if let Some(i) = self.categories.iter().position(|c| c.name == name) {
+            return i;
+        }
+        self.categories.push(Category {
+            name,
+            color,
+            shown: true,
+        });
+        self.categories.len() - 1
+    }
+
+    /// Whether entries are being taken.
+    pub fn recording(&self) -> bool {
+        self.open && !self.paused
+    }
+
+    /// Adds an entry in `category` as `write` writes it, into the text of
+    /// one gone where there is one. Entries left empty are dropped.
+    pub fn push(&mut self, seconds: f32, category: usize, write: impl FnOnce(&mut String)) {
+        let mut text = self.spare.pop().unwrap_or_default();
+        text.clear();
+        write(&mut text);
+        if text.is_empty() {
+            self.spare.push(text);
+            return;
+        }
+        if self.entries.len() >= EVENT_LOG_LIMIT {
+            if let Some(oldest) = self.entries.pop_front() {
+                self.spare.push(oldest.text);
+            }
+        }
+        self.entries.push_back(Entry {
+            seconds,
+            category,
+            text,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        let texts = self.entries.drain(..).map(|entry| entry.text);
+        self.spare.extend(texts);
+    }
+
+    /// Shows or hides the entries of category `i`.
+    pub fn toggle_category(&mut self, i: usize) {
+        if let Some(category) = self.categories.get_mut(i) {
+            category.shown = !category.shown;
+        }
+    }
+
+    /// The entries of shown categories, oldest first, with their colours.
+    fn shown(&self) -> impl Iterator<Item = (&Entry, Color)> {
+        (self.entries.iter()).filter_map(|entry| {
+            let category = self.categories.get(entry.category)?;
+            category.shown.then_some((entry, category.color))
+        })
+    }
+}
+
+/// Where the systems that take entries run, only while recording.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EventLogSet;
+
+pub trait EventLogApp {
+    /// Logs each `E` sent, in `category`, as `format` writes it. Categories
+    /// are shared by name, keeping the colour first given.
+    fn log_event<E: Event>(
+        &mut self,
+        category: &'static str,
+        color: Color,
+        format: impl Fn(&E, &mut String) + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    /// Logs each entity whose `C` is added or changed, in `category`, as
+    /// `format` writes it. The `Ref` tells which.
+    fn log_changes<C: Component>(
+        &mut self,
+        category: &'static str,
+        color: Color,
+        format: impl Fn(Entity, Ref<C>, &mut String) + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl EventLogApp for App {
+    fn log_event<E: Event>(
+        &mut self,
+        category: &'static str,
+        color: Color,
+        format: impl Fn(&E, &mut String) + Send + Sync + 'static,
+    ) -> &mut Self {
+        let mut logger = self.world.get_resource_or_insert_with(EventLogger::default);
+        let category = logger.category(category, color);
+        let log =
+            move |time: Res<Time>, mut events: EventReader<E>, mut logger: ResMut<EventLogger>| {
+                let seconds = time.elapsed_seconds();
+                for event in events.iter() {
+                    logger.push(seconds, category, |out| format(event, out));
+                }
+            };
+        self.add_system(log.in_set(EventLogSet))
+    }
+
+    fn log_changes<C: Component>(
+        &mut self,
+        category: &'static str,
+        color: Color,
+        format: impl Fn(Entity, Ref<C>, &mut String) + Send + Sync + 'static,
+    ) -> &mut Self {
+        let mut logger = self.world.get_resource_or_insert_with(EventLogger::default);
+        let category = logger.category(category, color);
+        let log = move |time: Res<Time>,
+                        changed: Query<(Entity, Ref<C>), Changed<C>>,
+                        mut logger: ResMut<EventLogger>| {
+            let seconds = time.elapsed_seconds();
+            for (entity, component) in changed.iter() {
+                logger.push(seconds, category, |out| format(entity, component, out));
+            }
+        };
+        self.add_system(log.in_set(EventLogSet))
+    }
+}
+
+/// What clicking part of the panel's header does.
+#[derive(Component, Clone, Copy)]
+enum LogButton {
+    Category(usize),
+    Pause,
+    Clear,
+}
+
+#[derive(Component)]
+struct EventLogRoot;
+
+/// The text the entries are written into, a section each.
+#[derive(Component)]
+struct EventLogBody;
+
+pub struct EventLogPlugin;
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EventLogger>()
+            .configure_set(EventLogSet.run_if(|logger: Res<EventLogger>| logger.recording()))
+            .add_systems((toggle_log, click_log).before(EventLogSet))
+            .add_system(draw_log.after(EventLogSet))
+            .log_event::<DamageEvent>("damage", Color::TOMATO, |e, out| {
+                let element = e.element.name();
+                let _ = write!(out, "{:?} took {} {} damage", e.entity, e.amount, element);
+            })
+            .log_event::<DiedEvent>("death", Color::CRIMSON, |e, out| {
+                let _ = write!(out, "{:?} died", e.entity);
+            })
+            .log_event::<PlayerStepStarted>("step", Color::SILVER, |e, out| {
+                let _ = write!(out, "{:?} stepped {:?} -> {:?}", e.entity, e.from, e.to);
+            })
+            .log_event::<NpcSteppedEvent>("step", Color::SILVER, |e, out| {
+                let _ = write!(out, "{:?} stepped out of {:?}", e.entity, e.from);
+            })
+            .log_event::<TileChangedEvent>("tile", Color::CYAN, |e, out| {
+                let _ = write!(out, "{:?} tile {:?} -> {:?}", e.position, e.before, e.after);
+            })
+            .log_event::<RegionEntered>("region", Color::LIME_GREEN, |e, out| {
+                let _ = write!(out, "{:?} entered `{}`", e.entity, e.region_id);
+            })
+            .log_event::<RegionExited>("region", Color::LIME_GREEN, |e, out| {
+                let _ = write!(out, "{:?} left `{}`", e.entity, e.region_id);
+            })
+            // Doors placed with the map are not news.
+            .log_changes::<Door>("door", Color::ORANGE, |entity, door, out| {
+                if !door.is_added() {
+                    let state = if door.open { "opened" } else { "shut" };
+                    let _ = write!(out, "{:?} {}", entity, state);
+                }
+            })
+            .log_changes::<Occupier>("spawn", Color::YELLOW, |entity, occupier, out| {
+                if occupier.is_added() {
+                    let _ = write!(out, "{:?} spawned", entity);
+                }
+            });
+    }
+}
+
+fn toggle_log(keys: Res<Input<KeyCode>>, mut logger: ResMut<EventLogger>) {
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if shift && keys.just_pressed(TOGGLE_KEY) {
+        logger.open = !logger.open;
+        info!("Event log {}.", if logger.open { "shown" } else { "hidden" });
+    }
+}
+
+/// Hides and shows categories, and pauses and clears the log, from the
+/// panel's header.
+fn click_log(
+    clicks: Query<(&Interaction, &LogButton), Changed<Interaction>>,
+    mut logger: ResMut<EventLogger>,
+) {
+    for (interaction, button) in clicks.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match *button {
+            LogButton::Category(i) => logger.toggle_category(i),
+            LogButton::Pause => logger.paused = !logger.paused,
+            LogButton::Clear => logger.clear(),
+        }
+    }
+}
+
+/// Spawns the panel when it opens and despawns it when it closes, and
+/// in between writes the entries shown into the sections they had.
+fn draw_log(
+    mut commands: Commands,
+    logger: Res<EventLogger>,
+    assets: Res<GraphicsAssets>,
+    roots: Query<Entity, With<EventLogRoot>>,
+    spawned: Query<(), Added<EventLogBody>>,
+    mut body: Query<&mut Text, With<EventLogBody>>,
+    mut buttons: Query<(&LogButton, &mut Text), Without<EventLogBody>>,
+) {
+    if !logger.is_changed() && spawned.is_empty() {
+        return;
+    }
+    if !logger.open {
+        for entity in roots.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if roots.is_empty() {
+        // Filled in once spawned.
+        spawn_panel(&mut commands, &logger, &assets);
+        return;
+    }
+
+    for (button, mut text) in buttons.iter_mut() {
+        let section = &mut text.sections[0];
+        match *button {
+            LogButton::Category(i) => {
+                let Some(category) = logger.categories.get(i) else { continue };
+                section.style.color = match category.shown {
+                    true => category.color,
+                    false => HIDDEN_COLOR,
+                };
+            }
+            LogButton::Pause => {
+                let label = if logger.paused { "[resume]" } else { "[pause]" };
+                if section.value != label {
+                    section.value.clear();
+                    section.value.push_str(label);
+                }
+            }
+            LogButton::Clear => {}
+        }
+    }
+
+    let Ok(mut text) = body.get_single_mut() else { return };
+    let sections = &mut text.sections;
+    let mut count = 0;
+    for (entry, color) in logger.shown() {
+        if count == sections.len() {
+            // There is always one, kept for its style.
+            let style = sections[0].style.clone();
+            sections.push(TextSection::new(String::new(), style));
+        }
+        let section = &mut sections[count];
+        section.value.clear();
+        let _ = writeln!(section.value, "{:8.2} {}", entry.seconds, entry.text);
+        section.style.color = color;
+        count += 1;
+    }
+    sections.truncate(count.max(1));
+    if count == 0 {
+        sections[0].value.clear();
+    }
+}
+
+fn spawn_panel(commands: &mut Commands, logger: &EventLogger, assets: &GraphicsAssets) {
+    let style = |color| TextStyle {
+        font: assets.font.clone(),
+        font_size: PANEL_FONT_SIZE,
+        color,
+    };
+    let button = |label: String, color, button| {
+        let mut text = TextBundle::from_section(label, style(color));
+        text.style.margin = UiRect::right(Val::Px(8.));
+        (button, Interaction::default(), text)
+    };
+    commands
+        .spawn((
+            EventLogRoot,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(0.),
+                        top: Val::Px(0.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(40.), Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(6.)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_wrap: FlexWrap::Wrap,
+                        margin: UiRect::bottom(Val::Px(6.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|header| {
+                    for (i, category) in logger.categories.iter().enumerate() {
+                        let label = category.name.to_string();
+                        header.spawn(button(label, category.color, LogButton::Category(i)));
+                    }
+                    let pause = if logger.paused { "[resume]" } else { "[pause]" };
+                    header.spawn(button(pause.into(), Color::WHITE, LogButton::Pause));
+                    header.spawn(button("[clear]".into(), Color::WHITE, LogButton::Clear));
+                });
+            // The newest entries at the bottom, the oldest cut off at the top.
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_grow: 1.,
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::FlexEnd,
+                        overflow: Overflow::Hidden,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|entries| {
+                    let text = TextBundle::from_section(String::new(), style(Color::WHITE));
+                    entries.spawn((EventLogBody, text));
+                });
+        });
+}
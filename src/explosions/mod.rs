@@ -0,0 +1,367 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    board::BoardCommands,
+    camera::CameraShake,
+    collision::{CollisionFlags, CollisionMap, Occupancy},
+    combat::DamageEvent,
+    elements::Element,
+    knockback::KnockbackEvent,
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    pathfinding::flood_fill,
+    player::Player,
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    simulation::{SimulationApp, SimulationSet},
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position, LAYER_Z_STRIDE,
+};
+
+/// Dots making up a blast's ring.
+const RING_DOTS: usize = 16;
+const RING_DOT_SIZE: f32 = 3.;
+/// Seconds a ring takes to expand to the blast's radius and fade.
+const RING_DURATION: f32 = 0.35;
+/// Cells from a blast beyond which the camera no longer shakes.
+const SHAKE_RANGE: f32 = 12.;
+
+/// How a blast finds the cells it reaches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlastShape {
+    /// Every cell within `radius` on both axes, walls or not.
+    #[default]
+    Square,
+    /// Cells within `radius` steps without passing a wall, so blasts do not
+    /// reach through solid rock or closed doors.
+    Flood,
+}
+
+/// Damages occupiers, and optionally tiles, around `center`.
+#[derive(Clone, Copy, Debug)]
+pub struct ExplosionEvent {
+    pub center: Vector3Int,
+    pub radius: u32,
+    pub damage: u32,
+    pub destroys_tiles: bool,
+    pub shape: BlastShape,
+    /// Cells the occupiers hit are thrown away from the centre.
+    pub knockback: u32,
+    pub element: Element,
+}
+
+/// Breaks the destructible tiles on the cell of `position`, on its layer.
+pub struct DamageTileEvent {
+    pub position: Vector3Int,
+}
+
+/// Sent for each tile an explosion breaks, before it is despawned.
+pub struct TileDestroyedEvent {
+    pub position: Vector3Int,
+    /// The tile's atlas index, for looking up its metadata.
+    pub index: usize,
+}
+
+/// Explodes when its timer runs out, such as a lit bomb.
+#[derive(Component)]
+pub struct Fuse {
+    pub timer: Timer,
+    pub radius: u32,
+    pub damage: u32,
+    pub destroys_tiles: bool,
+    pub shape: BlastShape,
+    pub knockback: u32,
+    pub element: Element,
+}
+
+impl Fuse {
+    pub fn new(duration: Duration, radius: u32, damage: u32) -> Self {
+        Fuse {
+            timer: Timer::new(duration, TimerMode::Once),
+            radius,
+            damage,
+            destroys_tiles: false,
+            shape: BlastShape::default(),
+            knockback: 0,
+            element: Element::Physical,
+        }
+    }
+}
+
+/// One dot of an expanding ring, hidden and back in the pool once
+/// `timer` finishes.
+#[derive(Component)]
+struct RingDot {
+    center: Vec2,
+    angle: f32,
+    /// Distance in pixels the dot travels out to.
+    reach: f32,
+    timer: Timer,
+}
+
+pub struct ExplosionsPlugin;
+impl Plugin for ExplosionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_event::<ExplosionEvent>()
+            .add_simulation_event::<DamageTileEvent>()
+            .add_simulation_event::<TileDestroyedEvent>()
+            .add_systems(
+                (burn_fuses, explode, damage_tiles)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(show_blasts)
+            .add_system(expand_rings);
+    }
+}
+
+fn burn_fuses(
+    mut commands: Commands,
+    fixed: Res<FixedTime>,
+    mut fuses: Query<(Entity, &mut Fuse, &Position)>,
+    mut explosions: EventWriter<ExplosionEvent>,
+) {
+    for (entity, mut fuse, position) in fuses.iter_mut() {
+        if fuse.timer.tick(fixed.period).just_finished() {
+            explosions.send(ExplosionEvent {
+                center: position.v,
+                radius: fuse.radius,
+                damage: fuse.damage,
+                destroys_tiles: fuse.destroys_tiles,
+                shape: fuse.shape,
+                knockback: fuse.knockback,
+                element: fuse.element,
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// The cells `blast` reaches, on the layer of its centre.
+pub fn blast_cells(
+    blast: &ExplosionEvent,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+    grid: GridKind,
+) -> Vec<Vector3Int> {
+    let center = current.wrap(blast.center);
+    match blast.shape {
+        BlastShape::Square => {
+            let r = blast.radius as i32;
+            (-r..=r)
+                .flat_map(|dy| (-r..=r).map(move |dx| Vector3Int::new(dx, dy, 0)))
+                .map(|d| current.wrap(center + d))
+                .map(|v| Vector3Int::new(v.x, v.y, center.z))
+                .collect()
+        }
+        BlastShape::Flood => flood_fill(
+            center,
+            |v| current.neighbours(v, grid),
+            |v| current.has_ground(v) && !collision.blocks(v, CollisionFlags::BLOCK_FLY),
+            blast.radius,
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn explode(
+    mut explosions: EventReader<ExplosionEvent>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    occupancy: Res<Occupancy>,
+    grid: Res<GridKind>,
+    positions: Query<&Position>,
+    mut damage: EventWriter<DamageEvent>,
+    mut knockback: EventWriter<KnockbackEvent>,
+    mut tile_damage: EventWriter<DamageTileEvent>,
+) {
+    for blast in explosions.iter() {
+        let cells = blast_cells(blast, &current, &collision, *grid);
+        // Occupiers covering several cells are hit once.
+        let mut hit: Vec<Entity> = Vec::new();
+        for cell in cells.iter() {
+            if let Some(entity) = occupancy.get(*cell).filter(|e| !hit.contains(e)) {
+                hit.push(entity);
+                damage.send(DamageEvent {
+                    entity,
+                    amount: blast.damage,
+                    element: blast.element,
+                });
+                let push = (positions.get(entity).ok()).and_then(|position| {
+                    KnockbackEvent::from_blast(
+                        entity,
+                        blast.center,
+                        position.v,
+                        blast.knockback,
+                        &current,
+                        *grid,
+                    )
+                });
+                if let Some(push) = push.filter(|_| blast.knockback > 0) {
+                    knockback.send(push);
+                }
+            }
+            if blast.destroys_tiles {
+                tile_damage.send(DamageTileEvent { position: *cell });
+            }
+        }
+    }
+}
+
+/// Removes destructible tiles from the board, leaving a gap where a
+/// destructible floor was, and the cell's collision to what is left.
+pub fn damage_tiles(
+    mut events: EventReader<DamageTileEvent>,
+    mut destroyed: EventWriter<TileDestroyedEvent>,
+    mut board: BoardCommands,
+    metadata: Res<TileMetadataRegistry>,
+) {
+    for event in events.iter() {
+        let v = board.current().wrap(event.position);
+        let layer = layer_of(v.z);
+        let broken: Vec<Vector3Int> = (0..LAYER_Z_STRIDE)
+            .map(|offset| Vector3Int::new(v.x, v.y, layer_z(layer, offset)))
+            .filter(|cell| {
+                (board.tile(*cell))
+                    .and_then(|i| metadata.0.get(&i))
+                    .is_some_and(|m| m.destructible)
+            })
+            .collect();
+        for cell in broken {
+            if let Some(index) = board.remove_tile(cell) {
+                destroyed.send(TileDestroyedEvent {
+                    position: cell,
+                    index,
+                });
+            }
+        }
+    }
+}
+
+/// Shakes the camera by how close the nearest player is to each blast, and
+/// sends out a ring of dots reused from earlier blasts.
+#[allow(clippy::too_many_arguments)]
+fn show_blasts(
+    mut commands: Commands,
+    mut explosions: EventReader<ExplosionEvent>,
+    current: Res<CurrentBoard>,
+    players: Query<&Position, With<Player>>,
+    projection: Res<GridProjection>,
+    grid: Res<GridKind>,
+    mut shake: ResMut<CameraShake>,
+    palette: Res<PaletteLookup>,
+    mut dots: Query<(&mut RingDot, &mut Visibility)>,
+) {
+    let mut idle = dots.iter_mut().filter(|(dot, _)| dot.timer.finished());
+    for blast in explosions.iter() {
+        let nearest = (players.iter())
+            .map(|p| current.distance(*grid, p.v, blast.center))
+            .min();
+        if let Some(distance) = nearest {
+            shake.add((1. - distance as f32 / SHAKE_RANGE).max(0.));
+        }
+
+        let center = projection.world(blast.center).truncate();
+        let reach = (blast.radius as f32 + 0.5) * projection.tile_size().x;
+        for i in 0..RING_DOTS {
+            let dot = RingDot {
+                center,
+                angle: i as f32 / RING_DOTS as f32 * std::f32::consts::TAU,
+                reach,
+                timer: Timer::from_seconds(RING_DURATION, TimerMode::Once),
+            };
+            match idle.next() {
+                Some((mut pooled, mut visibility)) => {
+                    *pooled = dot;
+                    *visibility = Visibility::Visible;
+                }
+                None => {
+                    commands.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: palette.color(PaletteColor::Blast),
+                                custom_size: Some(Vec2::splat(RING_DOT_SIZE)),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(
+                                center.extend(z_for(RenderLayerSlot::Overlay, 0.)),
+                            ),
+                            ..default()
+                        },
+                        dot,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn expand_rings(
+    time: Res<Time>,
+    palette: Res<PaletteLookup>,
+    mut dots: Query<(&mut RingDot, &mut Sprite, &mut Transform, &mut Visibility)>,
+) {
+    for (mut dot, mut sprite, mut transform, mut visibility) in dots.iter_mut() {
+        if dot.timer.finished() {
+            continue;
+        }
+        if dot.timer.tick(time.delta()).finished() {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        let t = dot.timer.percent();
+        let offset = Vec2::from_angle(dot.angle) * dot.reach * t;
+        transform.translation = (dot.center + offset).extend(z_for(RenderLayerSlot::Overlay, 0.));
+        sprite.color = palette.color(PaletteColor::Blast).with_a(1. - t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        board::SetTileEvent,
+        replay::LogicalClock,
+        testing::{headless_game, run_ticks},
+    };
+
+    /// An atlas index no map uses, made a breakable wall for the test.
+    const WALL: usize = 500;
+    /// A cell of `data.json` with grass and nothing on it.
+    const A: Vector3Int = Vector3Int::new(-15, -10, 0);
+
+    fn next_tick(app: &mut App) {
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(app, tick + 1);
+    }
+
+    #[test]
+    fn walls_blown_up_can_be_walked_through() {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 1);
+        let wall = r#"{ "collision": ["block_walk"], "destructible": true }"#;
+        let wall = serde_json::from_str(wall).unwrap();
+        (app.world.resource_mut::<TileMetadataRegistry>().0).insert(WALL, wall);
+        // Stood on the grass rather than replacing it.
+        let position = Vector3Int::new(A.x, A.y, 1);
+        app.world.send_event(SetTileEvent {
+            position,
+            index: Some(WALL),
+        });
+        next_tick(&mut app);
+        assert!(app.world.resource::<CollisionMap>().is_blocked(A));
+
+        app.world.send_event(DamageTileEvent { position: A });
+        next_tick(&mut app);
+        assert!(!app.world.resource::<CollisionMap>().is_blocked(A));
+        let current = app.world.resource::<CurrentBoard>();
+        assert!(current.has_ground(A));
+        assert!(!current.tiles.contains_key(&position));
+    }
+}
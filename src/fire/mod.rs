@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::SetTileEvent,
+    collision::{CollisionMap, Occupancy},
+    combat::DamageEvent,
+    elements::Element,
+    explosions::{self, blast_cells, ExplosionEvent},
+    layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    rng::GameRng,
+    simulation::{SimulationApp, SimulationSet},
+    tint::TileTint,
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position, Tile, LAYER_Z_STRIDE,
+};
+
+const FIRE_COLOR: Color = Color::rgba(1., 0.4, 0.05, 0.65);
+/// Flickers a second.
+const FLICKER_RATE: f32 = 6.;
+
+/// How a tile burns, from its metadata.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flammable {
+    /// Ticks it burns for once lit.
+    #[serde(default = "default_burn_ticks")]
+    pub burn_ticks: u32,
+    /// The tile it leaves behind, or a gap for none.
+    #[serde(default)]
+    pub scorched: Option<usize>,
+}
+
+fn default_burn_ticks() -> u32 {
+    40
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct FireConfig {
+    /// Chance each tick that a burning tile lights each flammable
+    /// neighbour.
+    pub spread_chance: f32,
+    /// Damage to whoever stands in the flames, every `damage_ticks`.
+    pub damage: u32,
+    pub damage_ticks: u32,
+    /// Burning tiles moved on each tick, at most. Past that, tiles take
+    /// turns, so a forest fire burns slower rather than stalling a frame.
+    pub max_per_tick: usize,
+}
+
+impl Default for FireConfig {
+    fn default() -> Self {
+        FireConfig {
+            spread_chance: 0.1,
+            damage: 1,
+            damage_ticks: 10,
+            max_per_tick: 256,
+        }
+    }
+}
+
+/// Sets light to the flammable tiles on the cell of `position`, on its
+/// layer.
+pub struct IgniteEvent {
+    pub position: Vector3Int,
+}
+
+/// A tile on fire, until `remaining_ticks` runs out.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Burning {
+    pub remaining_ticks: u32,
+}
+
+/// Sets light to flammable tiles on and next to its cell, as a torch does.
+#[derive(Component)]
+pub struct Ignites;
+
+pub struct FirePlugin;
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FireConfig>()
+            .add_simulation_event::<IgniteEvent>()
+            .add_systems(
+                (light_fires, burn_tiles)
+                    .chain()
+                    .after(explosions::damage_tiles)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(tint_fires);
+    }
+}
+
+/// The flammable, unlit tiles on the cell of `v`, on its layer. `tile`
+/// gives a tile entity's atlas index and whether it is burning.
+fn flammable_at(
+    v: Vector3Int,
+    current: &CurrentBoard,
+    metadata: &TileMetadataRegistry,
+    tile: impl Fn(Entity) -> Option<(usize, bool)>,
+) -> Vec<(Entity, Flammable)> {
+    let v = current.wrap(v);
+    (0..LAYER_Z_STRIDE)
+        .map(|offset| Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), offset)))
+        .filter_map(|cell| current.tiles.get(&cell).copied())
+        .filter_map(|entity| {
+            let (index, burning) = tile(entity)?;
+            let flammable = metadata.0.get(&index)?.flammable?;
+            (!burning).then_some((entity, flammable))
+        })
+        .collect()
+}
+
+/// Lights the tiles asked for, those by torches and those blasts reach.
+/// Tiles a blast broke are already off the board.
+#[allow(clippy::too_many_arguments)]
+fn light_fires(
+    mut commands: Commands,
+    mut ignite: EventReader<IgniteEvent>,
+    mut explosions: EventReader<ExplosionEvent>,
+    torches: Query<&Position, With<Ignites>>,
+    tiles: Query<(&Tile, Option<&Burning>)>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    metadata: Res<TileMetadataRegistry>,
+    grid: Res<GridKind>,
+) {
+    let mut cells: Vec<Vector3Int> = ignite.iter().map(|e| e.position).collect();
+    for blast in explosions.iter() {
+        cells.extend(blast_cells(blast, &current, &collision, *grid));
+    }
+    for torch in torches.iter() {
+        cells.push(torch.v);
+        cells.extend(current.neighbours(torch.v, *grid));
+    }
+
+    let mut lit = HashSet::new();
+    for v in cells {
+        let tile = |entity| (tiles.get(entity).ok()).map(|(t, b)| (t.i, b.is_some()));
+        for (entity, flammable) in flammable_at(v, &current, &metadata, tile) {
+            if lit.insert(entity) {
+                commands.entity(entity).insert(Burning {
+                    remaining_ticks: flammable.burn_ticks,
+                });
+            }
+        }
+    }
+}
+
+/// Moves burning tiles on by a tick, at most `max_per_tick` of them, in
+/// order of position from where the last tick stopped: each hurts whoever
+/// stands in it, may light its neighbours, and once burnt out becomes its
+/// scorched tile.
+#[allow(clippy::too_many_arguments)]
+fn burn_tiles(
+    mut commands: Commands,
+    config: Res<FireConfig>,
+    fires: Query<(Entity, &Position), With<Burning>>,
+    mut tiles: Query<(&Tile, Option<&mut Burning>)>,
+    current: Res<CurrentBoard>,
+    occupancy: Res<Occupancy>,
+    metadata: Res<TileMetadataRegistry>,
+    grid: Res<GridKind>,
+    mut rng: ResMut<GameRng>,
+    mut damage: EventWriter<DamageEvent>,
+    mut set_tiles: EventWriter<SetTileEvent>,
+    mut next: Local<usize>,
+) {
+    let mut fires: Vec<(Entity, Vector3Int)> = (fires.iter()).map(|(e, p)| (e, p.v)).collect();
+    if fires.is_empty() {
+        return;
+    }
+    fires.sort_by_key(|(_, v)| (v.z, v.y, v.x));
+    let start = *next % fires.len();
+    let count = fires.len().min(config.max_per_tick);
+    *next = start + count;
+
+    let mut lit = HashSet::new();
+    for &(entity, v) in fires.iter().cycle().skip(start).take(count) {
+        let Ok((&Tile { i: index }, Some(fire))) = tiles.get(entity) else { continue };
+        let damage_tick =
+            config.damage_ticks > 0 && fire.remaining_ticks % config.damage_ticks == 0;
+        if let Some(occupier) = occupancy.get(v).filter(|_| damage_tick) {
+            damage.send(DamageEvent {
+                entity: occupier,
+                amount: config.damage,
+                element: Element::Fire,
+            });
+        }
+
+        for neighbour in current.neighbours(v, *grid) {
+            let tile = |entity| (tiles.get(entity).ok()).map(|(t, b)| (t.i, b.is_some()));
+            for (lighting, flammable) in flammable_at(neighbour, &current, &metadata, tile) {
+                if !lit.contains(&lighting) && rng.chance(config.spread_chance) {
+                    lit.insert(lighting);
+                    commands.entity(lighting).insert(Burning {
+                        remaining_ticks: flammable.burn_ticks,
+                    });
+                }
+            }
+        }
+
+        let Ok((_, Some(mut fire))) = tiles.get_mut(entity) else { continue };
+        fire.remaining_ticks = fire.remaining_ticks.saturating_sub(1);
+        if fire.remaining_ticks == 0 {
+            let scorched = (metadata.0.get(&index))
+                .and_then(|m| m.flammable)
+                .and_then(|f| f.scorched);
+            commands.entity(entity).remove::<Burning>();
+            set_tiles.send(SetTileEvent {
+                position: v,
+                index: scorched,
+            });
+        }
+    }
+}
+
+/// Flickers burning tiles, and puts out the tint of those burnt out.
+fn tint_fires(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut burning: Query<(Entity, Option<&mut TileTint>), With<Burning>>,
+    mut tints: Query<&mut TileTint, Without<Burning>>,
+    mut out: RemovedComponents<Burning>,
+) {
+    for entity in out.iter() {
+        if let Ok(mut tint) = tints.get_mut(entity) {
+            tint.fire = None;
+        }
+    }
+    let flicker = 0.8 + 0.2 * (time.elapsed_seconds() * FLICKER_RATE).sin();
+    let fire = Some(FIRE_COLOR.with_a(FIRE_COLOR.a() * flicker));
+    for (entity, tint) in burning.iter_mut() {
+        match tint {
+            Some(mut tint) => tint.fire = fire,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(TileTint { fire, ..default() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        grid_to_position,
+        replay::LogicalClock,
+        rng::DEFAULT_SEED,
+        testing::{headless_game, run_ticks},
+    };
+
+    /// The columns and rows of the field in `assets/fixtures/grass.json`.
+    /// Water runs down column 5.
+    const COLUMNS: std::ops::Range<i32> = 2..9;
+    const ROWS: std::ops::Range<i32> = 2..7;
+
+    /// Lights the grass at the west edge of the field and lets it burn out,
+    /// then draws the field a row a line: `"` for grass, `*` for a bush,
+    /// `~` for water, `.` for scorched grass and a space where a tile
+    /// burnt away.
+    fn burn_field(seed: u64, config: FireConfig) -> Vec<String> {
+        let mut app = headless_game("fixtures/grass.json", Duration::from_secs_f64(1. / 60.));
+        app.insert_resource(GameRng::new(seed)).insert_resource(config);
+        run_ticks(&mut app, 0);
+        let position = grid_to_position(GridKind::Square, COLUMNS.start, 4, 0);
+        app.world.send_event(IgniteEvent { position });
+
+        let mut burning = app.world.query_filtered::<(), With<Burning>>();
+        let mut tick = app.world.resource::<LogicalClock>().tick;
+        loop {
+            tick += 1;
+            run_ticks(&mut app, tick);
+            if burning.iter(&app.world).next().is_none() {
+                break;
+            }
+            assert!(tick < 1000, "the fire never went out");
+        }
+        // For the last tiles burnt out to be replaced.
+        run_ticks(&mut app, tick + 1);
+
+        let current = app.world.resource::<CurrentBoard>();
+        (ROWS.map(|row| {
+            (COLUMNS.map(|col| {
+                let v = grid_to_position(GridKind::Square, col, row, 0);
+                let floor = current.floor(v).and_then(|e| app.world.get::<Tile>(e));
+                match floor.map(|tile| tile.i) {
+                    None => ' ',
+                    Some(1) => '"',
+                    Some(2) => '.',
+                    Some(3) => '*',
+                    Some(4) => '~',
+                    Some(i) => panic!("tile {} is not in the fixture", i),
+                }
+            }))
+            .collect()
+        }))
+        .collect()
+    }
+
+    #[test]
+    fn a_seeded_fire_burns_the_same_pattern() {
+        let config = FireConfig {
+            spread_chance: 0.3,
+            ..default()
+        };
+        let field = burn_field(7, config.clone());
+        assert_eq!(
+            field,
+            vec![
+                "\"\"\"~\"\"\"",
+                "\"*\"~\"\"\"",
+                "..\"~*\"\"",
+                " .\"~\"\"\"",
+                "...~\"\"\"",
+            ]
+        );
+        assert_eq!(burn_field(7, config), field);
+    }
+
+    #[test]
+    fn capped_fires_burn_as_far_as_uncapped_ones() {
+        // Every flammable neighbour catches, so the order tiles take turns
+        // in cannot change what burns.
+        let config = |max_per_tick| FireConfig {
+            spread_chance: 1.,
+            max_per_tick,
+            ..default()
+        };
+        let uncapped = burn_field(DEFAULT_SEED, config(usize::MAX));
+        assert_eq!(
+            uncapped,
+            vec![
+                "...~\"\"\"",
+                ". .~\"\"\"",
+                "...~*\"\"",
+                " ..~\"\"\"",
+                "...~\"\"\"",
+            ]
+        );
+        assert_eq!(burn_field(DEFAULT_SEED, config(2)), uncapped);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::{SimulationApp, SimulationSet};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FlagValue {
+    Bool(bool),
+    Int(i32),
+}
+
+impl FlagValue {
+    /// A flag counts as set when it is `true` or a non-zero integer.
+    pub fn is_set(&self) -> bool {
+        match self {
+            FlagValue::Bool(b) => *b,
+            FlagValue::Int(i) => *i != 0,
+        }
+    }
+}
+
+impl From<bool> for FlagValue {
+    fn from(b: bool) -> Self {
+        FlagValue::Bool(b)
+    }
+}
+
+impl From<i32> for FlagValue {
+    fn from(i: i32) -> Self {
+        FlagValue::Int(i)
+    }
+}
+
+/// A single, possibly negated, flag name within an expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlagTerm {
+    pub name: String,
+    pub negated: bool,
+}
+
+/// A parsed flag condition: every term must hold (`a && !b`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlagExpr {
+    pub terms: Vec<FlagTerm>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlagExprError {
+    Empty,
+    InvalidName(String),
+}
+
+impl fmt::Display for FlagExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagExprError::Empty => write!(f, "empty flag expression"),
+            FlagExprError::InvalidName(name) => write!(f, "invalid flag name `{}`", name),
+        }
+    }
+}
+
+impl FlagExpr {
+    pub fn parse(source: &str) -> Result<FlagExpr, FlagExprError> {
+        let mut terms = Vec::new();
+        for part in source.split("&&") {
+            let mut name = part.trim();
+            let mut negated = false;
+            while let Some(rest) = name.strip_prefix('!') {
+                negated = !negated;
+                name = rest.trim_start();
+            }
+
+            if name.is_empty() {
+                return Err(FlagExprError::Empty);
+            }
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(FlagExprError::InvalidName(name.to_string()));
+            }
+
+            terms.push(FlagTerm {
+                name: name.to_string(),
+                negated,
+            });
+        }
+
+        Ok(FlagExpr { terms })
+    }
+
+    pub fn eval(&self, flags: &GameFlags) -> bool {
+        self.terms
+            .iter()
+            .all(|term| flags.is_set(&term.name) != term.negated)
+    }
+}
+
+#[derive(Default, Resource, Serialize, Deserialize)]
+pub struct GameFlags {
+    values: HashMap<String, FlagValue>,
+    // Parsed expressions keyed by their source text.
+    #[serde(skip)]
+    cache: Mutex<HashMap<String, Option<FlagExpr>>>,
+}
+
+impl GameFlags {
+    pub fn set(&mut self, name: &str, value: impl Into<FlagValue>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<FlagValue> {
+        self.values.get(name).copied()
+    }
+
+    /// Every flag, by name, in no order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, FlagValue)> + '_ {
+        (self.values.iter()).map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// A copy of the flags, without the parsed expressions.
+    pub fn snapshot(&self) -> Self {
+        GameFlags {
+            values: self.values.clone(),
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Forgets every flag, as when a new map is loaded. The map's memory
+    /// puts back those it had when it was last left.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|v| v.is_set())
+    }
+
+    /// Evaluates a condition such as `has_key && !door_open`.
+    /// Malformed expressions are logged once and always evaluate to false.
+    pub fn check(&self, expr: &str) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        let parsed = cache.entry(expr.to_string()).or_insert_with(|| {
+            FlagExpr::parse(expr)
+                .map_err(|e| warn!("Could not parse flag expression `{}`: {}.", expr, e))
+                .ok()
+        });
+
+        parsed.as_ref().is_some_and(|e| e.eval(self))
+    }
+}
+
+/// Sent by anything that wants to mutate a flag (triggers, dialogues, deaths).
+pub struct SetFlagEvent {
+    pub name: String,
+    pub value: FlagValue,
+}
+
+impl SetFlagEvent {
+    pub fn new(name: &str, value: impl Into<FlagValue>) -> Self {
+        SetFlagEvent {
+            name: name.to_string(),
+            value: value.into(),
+        }
+    }
+}
+
+pub struct FlagsPlugin;
+impl Plugin for FlagsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameFlags>()
+            .add_simulation_event::<SetFlagEvent>()
+            .add_system(
+                apply_flag_events
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+fn apply_flag_events(mut events: EventReader<SetFlagEvent>, mut flags: ResMut<GameFlags>) {
+    for event in events.iter() {
+        info!("Flag `{}` set to {:?}.", event.name, event.value);
+        flags.set(&event.name, event.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(name: &str, negated: bool) -> FlagTerm {
+        FlagTerm {
+            name: name.to_string(),
+            negated,
+        }
+    }
+
+    #[test]
+    fn parses_terms_and_negations() {
+        let expr = FlagExpr::parse(" has_key && !door_open && !!lever_2 ").unwrap();
+        let terms = vec![
+            term("has_key", false),
+            term("door_open", true),
+            term("lever_2", false),
+        ];
+        assert_eq!(expr, FlagExpr { terms });
+    }
+
+    #[test]
+    fn rejects_empty_terms_and_bad_names() {
+        assert_eq!(FlagExpr::parse(""), Err(FlagExprError::Empty));
+        assert_eq!(FlagExpr::parse("a && "), Err(FlagExprError::Empty));
+        assert_eq!(FlagExpr::parse("!"), Err(FlagExprError::Empty));
+        let bad = FlagExprError::InvalidName("a || b".to_string());
+        assert_eq!(FlagExpr::parse("a || b"), Err(bad));
+    }
+
+    #[test]
+    fn evaluates_against_flags() {
+        let mut flags = GameFlags::default();
+        flags.set("has_key", true);
+        flags.set("coins", 3);
+        flags.set("door_open", 0);
+        assert!(flags.check("has_key && coins && !door_open"));
+        assert!(!flags.check("has_key && door_open"));
+        assert!(!flags.check("!has_key"));
+        // Flags never set count as unset.
+        assert!(flags.check("!unknown"));
+        assert!(!flags.check("has_key && ?"));
+    }
+}
@@ -0,0 +1,215 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    combat::DamageEvent,
+    tint,
+    tint::TileTint,
+};
+
+/// How long a hit flashes its target.
+const DAMAGE_FLASH_SECS: f32 = 0.2;
+/// How much larger than its entity an outline is drawn.
+const OUTLINE_SCALE: f32 = 1.25;
+/// How far behind its entity an outline is drawn, within its z band.
+const OUTLINE_DEPTH: f32 = 0.01;
+
+/// The colour an entity's sprite is drawn in when nothing is flashing it.
+/// Flashes return sprites to this, or to their `TileTint` when they have
+/// one, rather than to whatever colour they had when the flash began,
+/// which may itself be partway through another flash.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct BaseColor(pub Color);
+
+/// A brief wash of `color` over an entity's sprite, which fades in and
+/// back out over `duration` seconds and then removes itself.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FlashEffect {
+    pub color: Color,
+    pub duration: f32,
+    elapsed: f32,
+}
+
+impl FlashEffect {
+    pub fn new(color: Color, duration: f32) -> Self {
+        FlashEffect {
+            color,
+            duration,
+            elapsed: 0.,
+        }
+    }
+}
+
+/// A solid `color` border drawn around an entity's sprite for as long as
+/// the component stays on.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub color: Color,
+}
+
+/// The enlarged copy of `source`'s sprite that draws its outline.
+#[derive(Component)]
+struct OutlineSprite {
+    source: Entity,
+}
+
+pub struct FlashPlugin;
+impl Plugin for FlashPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(flash_on_damage)
+            .add_system(
+                flash_sprites
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(tint::apply_tile_tints),
+            )
+            .add_systems(
+                (spawn_outlines, sync_outlines)
+                    .chain()
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Flashes whatever was hurt in the palette's colour for the element that
+/// hurt it. Sprite colours multiply their texture, so most sprites, drawn
+/// in white, cannot flash any brighter.
+fn flash_on_damage(
+    mut commands: Commands,
+    mut events: EventReader<DamageEvent>,
+    palette: Res<PaletteLookup>,
+    sprites: Query<(), With<TextureAtlasSprite>>,
+) {
+    // The defeated are despawned on the tick that hurts them, so are gone
+    // from the query by now.
+    for event in events.iter().filter(|e| sprites.contains(e.entity)) {
+        let color = palette.color(PaletteColor::Hit(event.element));
+        commands
+            .entity(event.entity)
+            .insert(FlashEffect::new(color, DAMAGE_FLASH_SECS));
+    }
+}
+
+/// Blends flashing sprites from their base colour toward the flash colour
+/// and back. Only red, green and blue are touched, so fades done through
+/// alpha carry on underneath.
+#[allow(clippy::type_complexity)]
+pub fn flash_sprites(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashing: Query<(
+        Entity,
+        &mut FlashEffect,
+        &mut TextureAtlasSprite,
+        Option<&BaseColor>,
+        Option<&TileTint>,
+    )>,
+) {
+    for (entity, mut flash, mut sprite, base, tint) in flashing.iter_mut() {
+        let base = match (tint, base) {
+            (Some(tint), _) => tint.color(),
+            (None, Some(base)) => base.0,
+            // The first flash on an untinted sprite records what it was.
+            (None, None) => {
+                commands.entity(entity).insert(BaseColor(sprite.color));
+                sprite.color
+            }
+        };
+
+        flash.elapsed += time.delta_seconds();
+        let t = (flash.elapsed / flash.duration.max(f32::EPSILON)).min(1.);
+        // Up to full strength halfway through, then back down.
+        let strength = (1. - (2. * t - 1.).abs()) * flash.color.a();
+        let from = Vec4::from(base.as_rgba_f32()).truncate();
+        let to = Vec4::from(flash.color.as_rgba_f32()).truncate();
+        let color = Color::from(from.lerp(to, strength).extend(sprite.color.a()));
+        if sprite.color != color {
+            sprite.color = color;
+        }
+        if t >= 1. {
+            commands.entity(entity).remove::<FlashEffect>();
+        }
+    }
+}
+
+/// Gives newly outlined entities the sprite that draws their outline.
+fn spawn_outlines(
+    mut commands: Commands,
+    added: Query<
+        (
+            Entity,
+            &TextureAtlasSprite,
+            &Handle<TextureAtlas>,
+            &Transform,
+        ),
+        Added<Outline>,
+    >,
+    outlines: Query<&OutlineSprite>,
+) {
+    for (source, sprite, atlas, transform) in added.iter() {
+        // Removing and re-adding within a frame would otherwise leave two.
+        if outlines.iter().any(|o| o.source == source) {
+            continue;
+        }
+        commands.spawn((
+            OutlineSprite { source },
+            SpriteSheetBundle {
+                sprite: sprite.clone(),
+                texture_atlas: atlas.clone(),
+                transform: *transform,
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Keeps outlines behind their entity and in step with its sprite, and
+/// despawns those whose entity is gone or no longer outlined.
+fn sync_outlines(
+    mut commands: Commands,
+    sources: Query<
+        (&Outline, &TextureAtlasSprite, &Transform, &Visibility),
+        Without<OutlineSprite>,
+    >,
+    mut outlines: Query<(
+        Entity,
+        &OutlineSprite,
+        &mut TextureAtlasSprite,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    for (entity, outline, mut sprite, mut transform, mut visibility) in outlines.iter_mut() {
+        let Ok((source, source_sprite, source_transform, source_visibility)) =
+            sources.get(outline.source)
+        else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+
+        // Multiplying by the colour leaves the sprite's shape, so a
+        // bright sprite's outline is close to solid.
+        let drawn = TextureAtlasSprite {
+            color: source.color,
+            ..source_sprite.clone()
+        };
+        if sprite.index != drawn.index
+            || sprite.flip_x != drawn.flip_x
+            || sprite.flip_y != drawn.flip_y
+            || sprite.color != drawn.color
+        {
+            *sprite = drawn;
+        }
+        let behind = Transform {
+            translation: source_transform.translation - Vec3::Z * OUTLINE_DEPTH,
+            scale: source_transform.scale * OUTLINE_SCALE,
+            ..*source_transform
+        };
+        if *transform != behind {
+            *transform = behind;
+        }
+        if *visibility != *source_visibility {
+            *visibility = *source_visibility;
+        }
+    }
+}
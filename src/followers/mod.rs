@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+
+use crate::{
+    collision::{
+        covered_cells, footprint_fits, footprint_step_cost, step_allowed, CollisionMap, Footprint,
+        Occupancy,
+    },
+    debug_report::StepLog,
+    layer_of, layer_z,
+    npc::{NpcSteppedEvent, NPC_Z},
+    pathfinding::find_path,
+    player::{Player, PlayerStepCompleted},
+    simulation::SimulationSet,
+    terrain::{actor_capabilities, CapabilitySources},
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position,
+};
+
+/// How many cells a catch-up path search may visit before giving up.
+const PATH_SEARCH_LIMIT: usize = 512;
+
+/// Walks `spacing` cells behind `target`, one step for each of its steps,
+/// over the cells it walked rather than any shorter way round.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Follower {
+    /// The player followed. Prefabs leave this as `Entity::PLACEHOLDER`,
+    /// for the first player to be picked once there is one.
+    pub target: Entity,
+    pub spacing: u32,
+    /// Steps of the target in a row that the follower could not keep up
+    /// with.
+    cut_off: u32,
+}
+
+impl Follower {
+    pub fn new(target: Entity, spacing: u32) -> Self {
+        Follower {
+            target,
+            spacing,
+            cut_off: 0,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct FollowerConfig {
+    /// Target steps a follower stays cut off for before it is moved next
+    /// to its target.
+    pub teleport_after: u32,
+    /// Whether chasers go after followers as well as players.
+    pub targeted_by_enemies: bool,
+}
+
+impl Default for FollowerConfig {
+    fn default() -> Self {
+        FollowerConfig {
+            teleport_after: 3,
+            targeted_by_enemies: false,
+        }
+    }
+}
+
+pub struct FollowersPlugin;
+impl Plugin for FollowersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FollowerConfig>().add_systems(
+            (pick_targets, follow_targets)
+                .chain()
+                .in_set(SimulationSet::React)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// Points followers without a player at the first one.
+fn pick_targets(
+    players: Query<(Entity, &Player)>,
+    mut followers: Query<&mut Follower, Without<Player>>,
+) {
+    let Some((first, _)) = players.iter().min_by_key(|(_, player)| player.index) else { return };
+    for mut follower in followers.iter_mut() {
+        if !players.contains(follower.target) {
+            follower.target = first;
+        }
+    }
+}
+
+/// The cells `target` walked through in the step log, oldest first and
+/// ending at `at`, placed at a follower's z-index.
+fn walked_cells(log: &StepLog, target: Entity, at: Vector3Int) -> Vec<Vector3Int> {
+    let on_follower_z = |v: Vector3Int| Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), NPC_Z));
+    (log.iter())
+        .filter(|(_, step)| step.entity == target)
+        .map(|(_, step)| on_follower_z(step.from))
+        .chain([on_follower_z(at)])
+        .collect()
+}
+
+/// Each time a player finishes a step, moves their followers one cell
+/// along the player's path. A follower that is not on the path, or cannot
+/// take its next cell, looks for a way to catch up instead, and one that
+/// stays cut off is put next to the player.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn follow_targets(
+    mut completed: EventReader<PlayerStepCompleted>,
+    log: Res<StepLog>,
+    players: Query<&Position, With<Player>>,
+    mut followers: Query<
+        (
+            Entity,
+            &mut Follower,
+            &mut Position,
+            Option<&Footprint>,
+            CapabilitySources,
+        ),
+        Without<Player>,
+    >,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    mut occupancy: ResMut<Occupancy>,
+    grid: Res<GridKind>,
+    config: Res<FollowerConfig>,
+    mut steps: EventWriter<NpcSteppedEvent>,
+) {
+    let grid = *grid;
+    for step in completed.iter() {
+        let Ok(target) = players.get(step.entity) else { continue };
+        for (entity, mut follower, mut position, footprint, sources) in followers.iter_mut() {
+            let (_, _, effects) = sources;
+            if follower.target != step.entity || effects.is_some_and(|e| e.is_rooted()) {
+                continue;
+            }
+            let capabilities = actor_capabilities(sources);
+            let spacing = follower.spacing.max(1) as usize;
+            let walked = walked_cells(&log, step.entity, target.v);
+            let at = walked[walked.len() - 1];
+            let fits = |v: Vector3Int| {
+                footprint_fits(
+                    entity,
+                    v,
+                    footprint,
+                    capabilities,
+                    &current,
+                    &collision,
+                    &occupancy,
+                )
+            };
+            let may_step = |from: Vector3Int, v: Vector3Int| {
+                let dir = current.delta(from, v);
+                fits(v) && step_allowed(from, dir, footprint, &current, &collision)
+            };
+
+            // How far behind along the path, if on it at all.
+            let on_path = walked[..walked.len() - 1]
+                .iter()
+                .rposition(|v| *v == position.v);
+            let close = match on_path {
+                Some(i) => walked.len() - 1 - i <= spacing,
+                None => current.distance(grid, position.v, at) <= spacing as i32,
+            };
+            if close {
+                follower.cut_off = 0;
+                continue;
+            }
+
+            let retrace = on_path.map(|i| walked[i + 1]).filter(|v| {
+                current.distance(grid, position.v, *v) == 1 && may_step(position.v, *v)
+            });
+            let next = retrace.or_else(|| {
+                let behind = walked.len().checked_sub(spacing + 1).map(|i| walked[i]);
+                let min_cost = collision.min_step_cost(capabilities);
+                // Cells to whichever goal is nearer, the cell behind or any
+                // close enough to the player.
+                let cells_left = |v| {
+                    let near = (current.distance(grid, v, at) - spacing as i32).max(0);
+                    behind.map_or(near, |b| near.min(current.distance(grid, v, b)))
+                };
+                let path = find_path(
+                    position.v,
+                    |v| current.neighbours(v, grid),
+                    |v| Some(v) == behind || current.distance(grid, v, at) <= spacing as i32,
+                    may_step,
+                    |_, v| footprint_step_cost(v, footprint, capabilities, &current, &collision),
+                    |v| cells_left(v) as f32 * min_cost,
+                    PATH_SEARCH_LIMIT,
+                );
+                path.and_then(|path| path.first())
+            });
+
+            let next = match next {
+                Some(next) => {
+                    follower.cut_off = 0;
+                    next
+                }
+                None => {
+                    follower.cut_off += 1;
+                    if follower.cut_off < config.teleport_after {
+                        continue;
+                    }
+                    let Some(beside) = current.neighbours(at, grid).find(|v| fits(*v)) else {
+                        continue;
+                    };
+                    info!(
+                        "{:?} was cut off; moved it next to {:?}.",
+                        entity, step.entity
+                    );
+                    follower.cut_off = 0;
+                    beside
+                }
+            };
+            let from = std::mem::replace(&mut position.v, next);
+            // Claimed now, so the next follower does not take it too.
+            occupancy.insert(entity, covered_cells(next, footprint));
+            steps.send(NpcSteppedEvent { entity, from });
+        }
+    }
+}
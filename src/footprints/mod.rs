@@ -0,0 +1,170 @@
+//! Footprints left in snow, mud and the like. Whoever walks out of a cell
+//! whose top ground tile has `imprintable` in its metadata leaves a print
+//! there, turned the way they went, over the ground and under objects and
+//! actors. Prints fade out, or with no fade stay until `capacity` newer
+//! ones have taken their places. Fliers and ambient creatures leave none.
+
+use bevy::prelude::*;
+
+use crate::{
+    ambient::Ambient,
+    layer_of, layer_z,
+    marks::{self, Mark, MarkSprites, Marks},
+    materials::TileMetadataRegistry,
+    npc::NpcSteppedEvent,
+    player::PlayerStepStarted,
+    projection::GridProjection,
+    render_layers::{z_for, z_index, RenderLayerSlot},
+    terrain::{actor_capabilities, Capabilities, CapabilitySources},
+    vectors::Vector3Int,
+    BoardLoadedEvent, CurrentBoard, Position, Tile,
+};
+
+/// Over the top ground tile, under objects.
+const FOOTPRINT_Z: f32 = 1.5;
+
+#[derive(Resource, Clone, Debug)]
+pub struct FootprintConfig {
+    /// Prints there can be at once. Past this, the oldest is taken for the
+    /// next.
+    pub capacity: usize,
+    /// Seconds a print takes to fade away, or `None` to keep each until it
+    /// is taken for a newer one.
+    pub fade_seconds: Option<f32>,
+    pub color: Color,
+    /// Print size as a fraction of a tile, across and along the way walked.
+    pub size: Vec2,
+}
+
+impl Default for FootprintConfig {
+    fn default() -> Self {
+        FootprintConfig {
+            capacity: 256,
+            fade_seconds: Some(20.),
+            color: Color::rgba(0.2, 0.15, 0.1, 0.45),
+            size: Vec2::new(0.25, 0.35),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct Footprints(Marks);
+
+/// A sprite drawing one print.
+#[derive(Component)]
+struct Footprint;
+
+pub struct FootprintsPlugin;
+impl Plugin for FootprintsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FootprintConfig>()
+            .init_resource::<Footprints>()
+            .add_systems((clear_footprints, leave_footprints, draw_footprints).chain());
+    }
+}
+
+/// Whether the topmost ground tile at `v`, on the layer of `v.z`, keeps
+/// footprints.
+fn is_imprintable(
+    v: Vector3Int,
+    current: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    metadata: &TileMetadataRegistry,
+) -> bool {
+    let layer = layer_of(v.z);
+    let top = (0..RenderLayerSlot::Ground.size()).rev().find_map(|i| {
+        let z = layer_z(layer, z_index(RenderLayerSlot::Ground, i));
+        let cell = current.wrap(Vector3Int::new(v.x, v.y, z));
+        tiles.get(*current.tiles.get(&cell)?).ok()
+    });
+    top.and_then(|tile| metadata.0.get(&tile.i))
+        .is_some_and(|m| m.imprintable)
+}
+
+/// Prints do not carry over to another map.
+fn clear_footprints(mut loaded: EventReader<BoardLoadedEvent>, mut prints: ResMut<Footprints>) {
+    if loaded.iter().count() > 0 {
+        prints.0.clear();
+    }
+}
+
+/// Leaves a print in each imprintable cell players and creatures step out
+/// of, turned the way they went.
+#[allow(clippy::too_many_arguments)]
+fn leave_footprints(
+    mut players: EventReader<PlayerStepStarted>,
+    mut npcs: EventReader<NpcSteppedEvent>,
+    walkers: Query<(Option<&Position>, CapabilitySources), Without<Ambient>>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+    config: Res<FootprintConfig>,
+    time: Res<Time>,
+    mut prints: ResMut<Footprints>,
+) {
+    let mut steps: Vec<(Entity, Vector3Int, Vector3Int)> = (players.iter())
+        .map(|step| (step.entity, step.from, step.dir))
+        .collect();
+    for step in npcs.iter() {
+        let Ok((Some(position), _)) = walkers.get(step.entity) else { continue };
+        steps.push((step.entity, step.from, position.v - step.from));
+    }
+
+    prints.0.capacity = config.capacity;
+    prints.0.trim();
+    for (entity, from, dir) in steps {
+        let Ok((_, sources)) = walkers.get(entity) else { continue };
+        if actor_capabilities(sources).contains(Capabilities::FLY) {
+            continue;
+        }
+        if !is_imprintable(from, &current, &tiles, &metadata) {
+            continue;
+        }
+        let z = layer_z(layer_of(from.z), 0) as f32 + z_for(RenderLayerSlot::Ground, FOOTPRINT_Z);
+        prints.0.stamp(Mark {
+            at: projection.world(current.wrap(from)).truncate().extend(z),
+            rotation: marks::facing(dir, &projection),
+            stamped: time.elapsed_seconds(),
+        });
+    }
+}
+
+/// Draws each print, faded by its age if prints fade.
+fn draw_footprints(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<FootprintConfig>,
+    projection: Res<GridProjection>,
+    mut prints: ResMut<Footprints>,
+    mut sprites: MarkSprites<Footprint>,
+) {
+    let now = time.elapsed_seconds();
+    if let Some(fade) = config.fade_seconds {
+        prints.0.expire(now, fade);
+    }
+    let size = projection.tile_size() * config.size;
+    marks::draw_marks(
+        &mut commands,
+        &prints.0,
+        now,
+        config.fade_seconds,
+        config.color,
+        &mut sprites,
+        |color, transform| {
+            let sprite = Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            };
+            (
+                SpriteBundle {
+                    sprite,
+                    transform,
+                    ..default()
+                },
+                Footprint,
+            )
+        },
+    );
+}
@@ -0,0 +1,279 @@
+//! Field of view by shadowcasting. Each quadrant is scanned a row at a
+//! time outward from the origin. Every wall met casts a shadow: the range
+//! of slopes its whole square covers, seen from the centre of the origin.
+//! A floor cell is seen if its centre is out of every shadow cast by walls
+//! nearer the origin, which makes sight symmetric: the same straight line
+//! joins the two centres either way, and what blocks it does not depend on
+//! which end it is looked along from. Walls are seen if any of them is out
+//! of shadow, so rooms show their edges.
+//!
+//! Shadows that meet are joined, so sight does not squeeze between walls
+//! that touch only at a corner, as the cells of a diagonal wall do; nor
+//! can anyone walk between them. A line grazing the edge or corner of a
+//! single wall is clear.
+//!
+//! Slopes are kept as exact fractions, so no cell is in or out by
+//! rounding. For square grids only.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::vectors::Vector3Int;
+
+/// `num / den`, with `den` above 0.
+#[derive(Clone, Copy, Debug)]
+struct Slope {
+    num: i64,
+    den: i64,
+}
+
+impl Slope {
+    const fn new(num: i64, den: i64) -> Self {
+        Slope { num, den }
+    }
+}
+
+impl Ord for Slope {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.num * other.den).cmp(&(other.num * self.den))
+    }
+}
+
+impl PartialOrd for Slope {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Slope {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Slope {}
+
+/// The edges of a quadrant, along the diagonals.
+const QUADRANT_START: Slope = Slope::new(-1, 1);
+const QUADRANT_END: Slope = Slope::new(1, 1);
+
+/// The slopes the square of the cell `depth` rows out and `col` across
+/// covers, from its lowest corner to its highest.
+fn square(depth: i64, col: i64) -> (Slope, Slope) {
+    // Corners are half a cell either way, doubled to stay whole.
+    let (near, far) = (2 * depth - 1, 2 * depth + 1);
+    let (low, high) = (2 * col - 1, 2 * col + 1);
+    let start = Slope::new(low, if low < 0 { near } else { far });
+    let end = Slope::new(high, if high < 0 { far } else { near });
+    (start, end)
+}
+
+/// The shadows cast in a quadrant so far, joined where they meet, in
+/// order.
+#[derive(Default)]
+struct Shadows(Vec<(Slope, Slope)>);
+
+impl Shadows {
+    /// Whether `slope` is inside a shadow, rather than out of all of them
+    /// or on the edge of one.
+    fn hides(&self, slope: Slope) -> bool {
+        (self.0.iter()).any(|(start, end)| *start < slope && slope < *end)
+    }
+
+    /// Whether every slope from `start` to `end` is in the one shadow.
+    fn covers(&self, start: Slope, end: Slope) -> bool {
+        (self.0.iter()).any(|(from, to)| *from <= start && end <= *to)
+    }
+
+    /// Whether the whole quadrant is inside a shadow, and nothing further
+    /// out can be seen.
+    fn is_dark(&self) -> bool {
+        (self.0.iter()).any(|(start, end)| *start < QUADRANT_START && QUADRANT_END < *end)
+    }
+
+    fn cast(&mut self, mut start: Slope, mut end: Slope) {
+        // Taking in every shadow it meets, even at a point.
+        self.0.retain(|(from, to)| {
+            let meets = *from <= end && start <= *to;
+            if meets {
+                start = start.min(*from);
+                end = end.max(*to);
+            }
+            !meets
+        });
+        let at = self.0.partition_point(|(from, _)| *from < start);
+        self.0.insert(at, (start, end));
+    }
+}
+
+/// The quadrants, as the cell `depth` rows out and `col` across in each.
+const QUADRANTS: [fn(Vector3Int, i32, i32) -> Vector3Int; 4] = [
+    |o, depth, col| Vector3Int::new(o.x + col, o.y + depth, o.z),
+    |o, depth, col| Vector3Int::new(o.x + depth, o.y + col, o.z),
+    |o, depth, col| Vector3Int::new(o.x + col, o.y - depth, o.z),
+    |o, depth, col| Vector3Int::new(o.x - depth, o.y + col, o.z),
+];
+
+/// Every cell `origin` sees within `radius` cells along either axis, on
+/// its own layer, where `blocks_sight` says which cells stop sight. The
+/// origin sees itself. Cells are as the scan reaches them, so on boards
+/// that wrap `blocks_sight` should wrap them, and so should the caller.
+pub fn compute(
+    origin: Vector3Int,
+    radius: u32,
+    blocks_sight: impl Fn(Vector3Int) -> bool,
+) -> HashSet<Vector3Int> {
+    let mut seen = HashSet::from([origin]);
+    for cell in QUADRANTS {
+        let mut shadows = Shadows::default();
+        for depth in 1..=radius as i64 {
+            if shadows.is_dark() {
+                break;
+            }
+            // Shadows fall on the rows beyond, not on this one. A wall just
+            // past the quadrant's edge casts one that can meet another
+            // along the edge.
+            let mut cast = Vec::new();
+            for col in -(depth + 1)..=depth + 1 {
+                let v = cell(origin, depth as i32, col as i32);
+                let wall = blocks_sight(v);
+                let (start, end) = square(depth, col);
+                if wall {
+                    cast.push((start, end));
+                }
+                let shown = match wall {
+                    _ if col.abs() > depth => false,
+                    true => !shadows.covers(start.max(QUADRANT_START), end.min(QUADRANT_END)),
+                    false => !shadows.hides(Slope::new(col, depth)),
+                };
+                if shown {
+                    seen.insert(v);
+                }
+            }
+            for (start, end) in cast {
+                shadows.cast(start, end);
+            }
+        }
+    }
+    seen
+}
+
+/// Whether `from` sees `to`, as `compute` would have it. Where both are
+/// floor, the answer is the same whichever is looked from.
+pub fn sees(from: Vector3Int, to: Vector3Int, blocks_sight: impl Fn(Vector3Int) -> bool) -> bool {
+    let to = Vector3Int::new(to.x, to.y, from.z);
+    let radius = (to.x - from.x).abs().max((to.y - from.y).abs());
+    compute(from, radius as u32, blocks_sight).contains(&to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map drawn as what `@` should see of it, from the top row down:
+    /// `.` and `,` floor seen and not, `#` and `%` walls seen and not.
+    /// Beyond its edges is wall, which is left out of what is checked.
+    struct Fixture {
+        rows: &'static [&'static str],
+    }
+
+    impl Fixture {
+        fn cells(&self) -> impl Iterator<Item = (Vector3Int, char)> + '_ {
+            (self.rows.iter().enumerate()).flat_map(|(y, row)| {
+                (row.chars().enumerate())
+                    .map(move |(x, c)| (Vector3Int::new(x as i32, -(y as i32), 0), c))
+            })
+        }
+
+        fn origin(&self) -> Vector3Int {
+            self.cells().find(|(_, c)| *c == '@').unwrap().0
+        }
+
+        fn blocks_sight(&self, v: Vector3Int) -> bool {
+            let c = self.cells().find(|(cell, _)| *cell == v).map(|(_, c)| c);
+            c.is_none_or(|c| c == '#' || c == '%')
+        }
+
+        /// Checks what the origin sees against the drawing, and that each
+        /// floor cell and the one it sees see each other.
+        fn check(&self) {
+            let radius = self.rows.len().max(self.rows[0].len()) as u32;
+            let mut seen = compute(self.origin(), radius, |v| self.blocks_sight(v));
+            seen.retain(|v| self.cells().any(|(cell, _)| cell == *v));
+            let expected: HashSet<Vector3Int> = (self.cells())
+                .filter(|(_, c)| matches!(c, '@' | '.' | '#'))
+                .map(|(v, _)| v)
+                .collect();
+            assert_eq!(seen, expected);
+
+            let floor: Vec<Vector3Int> = (self.cells())
+                .filter(|(v, _)| !self.blocks_sight(*v))
+                .map(|(v, _)| v)
+                .collect();
+            for a in &floor {
+                for b in &floor {
+                    let there = sees(*a, *b, |v| self.blocks_sight(v));
+                    let back = sees(*b, *a, |v| self.blocks_sight(v));
+                    assert_eq!(there, back, "{:?} and {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_pillar_casts_a_widening_shadow() {
+        // Cells whose line only grazes the pillar's corner are seen.
+        Fixture {
+            rows: &[
+                "...........",
+                "...........",
+                "...........",
+                "........,,,",
+                "..@..#,,,,,",
+                "........,,,",
+                "...........",
+                "...........",
+                "...........",
+            ],
+        }
+        .check();
+    }
+
+    #[test]
+    fn a_corridor_hides_what_is_round_its_corner() {
+        // Not even the first cell round the corner, which the corner of the
+        // wall is in the way of.
+        Fixture {
+            rows: &[
+                "###########",
+                "#@........#",
+                "#########,#",
+                "%%%%%%%%%,%",
+                "%%%%%%%%%,%",
+                "%%%%%%%%%,%",
+                "%%%%%%%%%%%",
+            ],
+        }
+        .check();
+    }
+
+    #[test]
+    fn a_diagonal_wall_lets_no_sight_between_its_corners() {
+        let fixture = Fixture {
+            rows: &[
+                ",,,,,,,,..",
+                ",,,,,,#...",
+                ",,,,,#....",
+                ",,,,#.....",
+                ".,,#......",
+                "..#.......",
+                "..........",
+                "..........",
+                "....@.....",
+            ],
+        };
+        fixture.check();
+        // Not even straight through where two of its cells meet.
+        let (near, far) = (Vector3Int::new(6, -4, 0), Vector3Int::new(3, -1, 0));
+        assert!(!sees(near, far, |v| fixture.blocks_sight(v)));
+    }
+}
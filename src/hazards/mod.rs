@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+
+use crate::{
+    combat::{DamageEvent, Health},
+    difficulty::DifficultyModifiers,
+    elements::Element,
+    layer_of,
+    materials::TileMetadataRegistry,
+    npc::NpcSteppedEvent,
+    player::{DashedEvent, MovementConfig, PlayerStepCompleted},
+    simulation::SimulationSet,
+    status::ApplyStatusEvent,
+    vectors::Vector3Int,
+    CurrentBoard, Position, Tile,
+};
+
+/// Damages players that arrive on its cell.
+#[derive(Component)]
+pub struct Hazard {
+    pub damage: u32,
+    pub element: Element,
+}
+
+pub struct HazardsPlugin;
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (hazard_on_step, hazard_on_dash, status_on_step)
+                .in_set(SimulationSet::React)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// Total damage of each element dealt by the hazards on `v`, before
+/// difficulty, leaving out elements that deal none.
+pub fn damage_at<'a>(
+    hazards: impl IntoIterator<Item = (&'a Hazard, &'a Position)>,
+    v: Vector3Int,
+) -> Vec<(Element, u32)> {
+    let on = (hazards.into_iter()).filter(|(_, position)| {
+        position.v.manhattan(v) == 0 && layer_of(position.v.z) == layer_of(v.z)
+    });
+    let mut totals: Vec<(Element, u32)> = Vec::new();
+    for (hazard, _) in on.filter(|(hazard, _)| hazard.damage > 0) {
+        match totals.iter().position(|(e, _)| *e == hazard.element) {
+            Some(i) => totals[i].1 += hazard.damage,
+            None => totals.push((hazard.element, hazard.damage)),
+        }
+    }
+    totals
+}
+
+#[allow(clippy::type_complexity)]
+fn hazard_on_step(
+    mut steps: EventReader<PlayerStepCompleted>,
+    movers: Query<(), With<Health>>,
+    hazards: Query<(&Hazard, &Position)>,
+    modifiers: Res<DifficultyModifiers>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for step in steps.iter() {
+        if !movers.contains(step.entity) {
+            continue;
+        }
+        for (element, amount) in damage_at(hazards.iter(), step.at) {
+            damage.send(DamageEvent {
+                entity: step.entity,
+                amount: modifiers.hazard_damage(amount),
+                element,
+            });
+        }
+    }
+}
+
+/// A dash skips the cells between its ends; they still hurt. The landing
+/// cell is handled by `hazard_on_step` like any other move.
+fn hazard_on_dash(
+    mut dashes: EventReader<DashedEvent>,
+    hazards: Query<(&Hazard, &Position)>,
+    config: Res<MovementConfig>,
+    modifiers: Res<DifficultyModifiers>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for dash in dashes.iter() {
+        if !config.dash_triggers_hazards {
+            continue;
+        }
+
+        let step = Vector3Int::new(
+            (dash.to.x - dash.from.x).signum(),
+            (dash.to.y - dash.from.y).signum(),
+            0,
+        );
+        let mut v = dash.from + step;
+        while v.manhattan(dash.to) != 0 {
+            for (element, amount) in damage_at(hazards.iter(), v) {
+                damage.send(DamageEvent {
+                    entity: dash.entity,
+                    amount: modifiers.hazard_damage(amount),
+                    element,
+                });
+            }
+            v += step;
+        }
+    }
+}
+
+/// Tiles whose metadata names a status effect put it on players and NPCs
+/// arriving on them.
+fn status_on_step(
+    mut player_steps: EventReader<PlayerStepCompleted>,
+    mut npc_steps: EventReader<NpcSteppedEvent>,
+    npcs: Query<&Position>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    mut statuses: EventWriter<ApplyStatusEvent>,
+) {
+    let arrivals = (player_steps.iter().map(|s| (s.entity, s.at)))
+        .chain((npc_steps.iter()).filter_map(|s| npcs.get(s.entity).ok().map(|p| (s.entity, p.v))));
+    for (entity, at) in arrivals {
+        let effect = (current.floor(at))
+            .and_then(|tile| tiles.get(tile).ok())
+            .and_then(|tile| metadata.0.get(&tile.i))
+            .and_then(|m| m.status);
+        if let Some(effect) = effect {
+            statuses.send(ApplyStatusEvent { entity, effect });
+        }
+    }
+}
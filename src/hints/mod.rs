@@ -0,0 +1,183 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    collision::{covers, Footprint, Occupancy},
+    combat::Health,
+    editor::EditorState,
+    flags::GameFlags,
+    followers::Follower,
+    input::{move_actions, Binding, InputMap},
+    locale::Localization,
+    objects::{Door, LayerLink, RequiresFlag},
+    player::{step_target, MovementState, Player},
+    puzzles::Pushable,
+    shop::{Shop, ShopPanel, Tab, CLOSE_KEY, TRADE_KEYS},
+    simulation::SimulationPaused,
+    t,
+    vectors::{GridKind, Vector3Int},
+    AppState, CurrentBoard, GraphicsAssets, Position,
+};
+
+const HINT_FONT_SIZE: f32 = 16.;
+/// Between hints on the bar.
+const HINT_GAP: &str = "     ";
+
+/// The line of hints along the bottom of the screen.
+#[derive(Component)]
+struct HintBar;
+
+pub struct HintsPlugin;
+impl Plugin for HintsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_hint_bar.in_schedule(OnEnter(AppState::Game)))
+            .add_system(update_hint_bar.in_set(OnUpdate(AppState::Game)));
+    }
+}
+
+fn spawn_hint_bar(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    bars: Query<(), With<HintBar>>,
+) {
+    // Kept between maps, as the rest of the HUD is.
+    if !bars.is_empty() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(HINT_FONT_SIZE / 2.),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                HintBar,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: HINT_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
+
+/// How a binding is written in hints.
+fn binding_name(binding: Binding) -> String {
+    match binding {
+        Binding::Key(KeyCode::Return) => "Enter".to_string(),
+        Binding::Key(KeyCode::Escape) => "Esc".to_string(),
+        Binding::Key(KeyCode::Back) => "Backspace".to_string(),
+        Binding::Key(key) => format!("{:?}", key),
+        Binding::Pad(button) => format!("{:?}", button),
+    }
+}
+
+/// What stepping into a cell would do, for the cells next to a player.
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+struct BumpTargets<'w, 's> {
+    current: Res<'w, CurrentBoard>,
+    occupancy: Res<'w, Occupancy>,
+    flags: Res<'w, GameFlags>,
+    links: Query<'w, 's, (&'static LayerLink, &'static Position), Without<Player>>,
+    creatures: Query<'w, 's, (), (With<Health>, Without<Player>, Without<Follower>)>,
+    pushables: Query<'w, 's, (), With<Pushable>>,
+    shops: Query<'w, 's, (&'static Position, Option<&'static Footprint>), With<Shop>>,
+    doors: Query<
+        'w,
+        's,
+        (
+            &'static Door,
+            &'static Position,
+            Option<&'static Footprint>,
+            Option<&'static RequiresFlag>,
+        ),
+    >,
+}
+
+impl<'w, 's> BumpTargets<'w, 's> {
+    /// The text key for bumping into what a step of `dir` from `from` lands
+    /// on, as the bump systems would take it: attacking a creature, pushing
+    /// a block, trading with a shop, or opening a door that is not locked.
+    fn hint(&self, from: Vector3Int, dir: Vector3Int) -> Option<&'static str> {
+        let at = step_target(&self.links, &self.current, from, dir);
+        if let Some(occupier) = self.occupancy.get(at) {
+            if self.creatures.contains(occupier) {
+                return Some("hints.attack");
+            }
+            if self.pushables.contains(occupier) {
+                return Some("hints.push");
+            }
+        }
+        if (self.shops.iter()).any(|(position, footprint)| covers(position.v, footprint, at)) {
+            return Some("hints.trade");
+        }
+        let door = (self.doors.iter()).find(|(door, position, footprint, requires)| {
+            !door.open
+                && covers(position.v, *footprint, at)
+                && requires.is_none_or(|RequiresFlag(expr)| self.flags.check(expr))
+        });
+        door.map(|_| "hints.open")
+    }
+}
+
+/// Lists what the first player can do next to them, with the keys bound
+/// to it, or the shop's keys while trading. Hidden in the editor and
+/// behind menus that pause the game.
+#[allow(clippy::too_many_arguments)]
+fn update_hint_bar(
+    locale: Res<Localization>,
+    map: Res<InputMap>,
+    grid: Res<GridKind>,
+    editor: Res<EditorState>,
+    paused: Res<SimulationPaused>,
+    panel: Res<ShopPanel>,
+    players: Query<(&Player, &Position, &MovementState)>,
+    targets: BumpTargets,
+    mut bars: Query<&mut Text, With<HintBar>>,
+) {
+    let mut hints = Vec::new();
+    if let Some(tab) = panel.tab() {
+        let trade = match tab {
+            Tab::Buy => "hints.buy",
+            Tab::Sell => "hints.sell",
+        };
+        let (enter, close) = (Binding::Key(TRADE_KEYS[0]), Binding::Key(CLOSE_KEY));
+        hints.push(t!(locale, trade, key = binding_name(enter)));
+        hints.push(t!(locale, "hints.leave", key = binding_name(close)));
+    } else if !editor.active && !paused.0 {
+        let first = players.iter().min_by_key(|(player, ..)| player.index);
+        if let Some((player, position, state)) = first {
+            let mut moves = move_actions(*grid);
+            // What the player faces first.
+            moves.sort_by_key(|(_, dir)| *dir != state.facing);
+            let set = map.players.get(player.index);
+            for (action, dir) in moves {
+                let Some(binding) = set.and_then(|set| set.bindings(action).first()) else {
+                    continue;
+                };
+                if let Some(hint) = targets.hint(position.v, dir) {
+                    hints.push(t!(locale, hint, key = binding_name(*binding)));
+                }
+            }
+        }
+    }
+
+    let value = hints.join(HINT_GAP);
+    for mut text in bars.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
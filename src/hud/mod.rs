@@ -0,0 +1,390 @@
+use bevy::{prelude::*, ui::UiSystem};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    combat::Health,
+    equipment::{EquipSlot, Equipment},
+    locale::Localization,
+    player::{MovementState, Player, PlayerSettings},
+    status::StatusEffects,
+    t,
+    timer::{LevelTimer, TIMER_WARNING},
+    turns::{ActionPoints, TurnQueue},
+    AppState, GraphicsAssets,
+};
+
+const PIP_SIZE: f32 = 8.;
+/// The width of a full health bar.
+const HEALTH_BAR_WIDTH: f32 = PIP_SIZE * 6.;
+const TIMER_FONT_SIZE: f32 = 24.;
+const EQUIPMENT_FONT_SIZE: f32 = 16.;
+/// Times a second the countdown flashes once it is running out.
+const TIMER_FLASH_RATE: f32 = 2.;
+
+/// Shows whether a player's dash is ready, one per player.
+#[derive(Component)]
+struct DashPip(usize);
+
+/// A row of icons, one for each status effect on a player.
+#[derive(Component)]
+struct StatusIcons(usize);
+
+/// A player's remaining health, as the width of a bar.
+#[derive(Component)]
+struct HealthBar(usize);
+
+/// What a player wears in each slot, in the top right.
+#[derive(Component)]
+struct EquipmentText(usize);
+
+/// The points a player has left this turn, beside their health bar in
+/// turn-based mode.
+#[derive(Component)]
+struct PointsText(usize);
+
+/// The level's countdown, at the top of the screen.
+#[derive(Component)]
+struct TimerText;
+
+/// Whether the cursor is over a menu or overlay, so that clicks, drags and
+/// the wheel there are not taken as meant for the world. Overlays that take
+/// the pointer are given an `Interaction`.
+#[derive(Default, Resource)]
+pub struct PointerOverUi(pub bool);
+
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PointerOverUi>()
+            // Before anything reads the mouse this frame.
+            .add_system(
+                track_pointer_over_ui
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(UiSystem::Focus),
+            )
+            .add_system(spawn_hud.in_schedule(OnEnter(AppState::Game)))
+            .add_system(update_dash_pip)
+            .add_system(update_health_bar)
+            .add_system(update_status_icons)
+            .add_system(update_equipment_text)
+            .add_system(update_points_text)
+            .add_system(update_timer_text);
+    }
+}
+
+fn track_pointer_over_ui(nodes: Query<&Interaction>, mut pointer: ResMut<PointerOverUi>) {
+    let over = nodes
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    if pointer.0 != over {
+        pointer.0 = over;
+    }
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    settings: Res<PlayerSettings>,
+    palette: Res<PaletteLookup>,
+    assets: Res<GraphicsAssets>,
+    pips: Query<(), With<DashPip>>,
+) {
+    // Players carry over between maps, and so does their HUD.
+    if !pips.is_empty() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(PIP_SIZE),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TimerText,
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: assets.font.clone(),
+                            font_size: TIMER_FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                    )
+                },
+            ));
+        });
+    for index in 0..settings.player_count {
+        commands.spawn((
+            DashPip(index),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(PIP_SIZE * (1 + 2 * index) as f32),
+                        bottom: Val::Px(PIP_SIZE),
+                        ..default()
+                    },
+                    size: Size::all(Val::Px(PIP_SIZE)),
+                    ..default()
+                },
+                background_color: palette.color(PaletteColor::DashReady).into(),
+                ..default()
+            },
+        ));
+        // The pips and icons fill the bottom left, so the bars stack up
+        // from the bottom right.
+        commands.spawn((
+            HealthBar(index),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(PIP_SIZE),
+                        bottom: Val::Px(PIP_SIZE * (1 + 2 * index) as f32),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(HEALTH_BAR_WIDTH), Val::Px(PIP_SIZE)),
+                    ..default()
+                },
+                background_color: palette.color(PaletteColor::HealthBar).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ));
+        commands.spawn((
+            StatusIcons(index),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(PIP_SIZE * (1 + 2 * index) as f32),
+                        bottom: Val::Px(PIP_SIZE * 3.),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    gap: Size::all(Val::Px(PIP_SIZE / 4.)),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+        commands.spawn((
+            PointsText(index),
+            TextBundle {
+                visibility: Visibility::Hidden,
+                ..TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: EQUIPMENT_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(HEALTH_BAR_WIDTH + PIP_SIZE * 2.),
+                        bottom: Val::Px(PIP_SIZE * (1 + 2 * index) as f32),
+                        ..default()
+                    },
+                    ..default()
+                })
+            },
+        ));
+        // Filled in once the locale is known.
+        commands.spawn((
+            EquipmentText(index),
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: EQUIPMENT_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(PIP_SIZE),
+                    top: Val::Px(PIP_SIZE + index as f32 * EQUIPMENT_FONT_SIZE * 4.),
+                    ..default()
+                },
+                ..default()
+            }),
+        ));
+    }
+}
+
+fn update_dash_pip(
+    palette: Res<PaletteLookup>,
+    players: Query<(&Player, &MovementState)>,
+    mut pips: Query<(&DashPip, &mut Style, &mut BackgroundColor)>,
+) {
+    for (player, state) in players.iter() {
+        for (_, mut style, mut color) in pips.iter_mut().filter(|(p, ..)| p.0 == player.index) {
+            // The pip grows back to full size as the cooldown recovers.
+            let ready = state.dash_cooldown.finished();
+            let size = PIP_SIZE * state.dash_cooldown.percent().max(0.25);
+            style.size = Size::all(Val::Px(size));
+            *color = palette
+                .color(if ready {
+                    PaletteColor::DashReady
+                } else {
+                    PaletteColor::DashCooling
+                })
+                .into();
+        }
+    }
+}
+
+fn update_health_bar(
+    palette: Res<PaletteLookup>,
+    players: Query<(&Player, Option<&Health>)>,
+    mut bars: Query<(
+        &HealthBar,
+        &mut Style,
+        &mut BackgroundColor,
+        &mut Visibility,
+    )>,
+) {
+    for (bar, mut style, mut color, mut visibility) in bars.iter_mut() {
+        // Players without health cannot be hurt, so they get no bar.
+        let health = (players.iter())
+            .find(|(player, _)| player.index == bar.0)
+            .and_then(|(_, health)| health);
+        let Some(health) = health else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        let fraction = health.current as f32 / health.max.max(1) as f32;
+        style.size.width = Val::Px(HEALTH_BAR_WIDTH * fraction);
+        *color = palette
+            .color(if fraction < 1. / 3. {
+                PaletteColor::HealthLow
+            } else {
+                PaletteColor::HealthBar
+            })
+            .into();
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn update_status_icons(
+    mut commands: Commands,
+    palette: Res<PaletteLookup>,
+    players: Query<(&Player, Ref<StatusEffects>)>,
+    rows: Query<(Entity, &StatusIcons)>,
+) {
+    // A new palette recolours every row, not just the changed ones.
+    let players =
+        (players.iter()).filter(|(_, effects)| palette.is_changed() || effects.is_changed());
+    for (player, effects) in players {
+        for (row, _) in rows.iter().filter(|(_, r)| r.0 == player.index) {
+            commands.entity(row).despawn_descendants();
+            commands.entity(row).with_children(|row| {
+                for effect in effects.0.iter() {
+                    row.spawn(NodeBundle {
+                        style: Style {
+                            size: Size::all(Val::Px(PIP_SIZE)),
+                            ..default()
+                        },
+                        background_color: palette.color(PaletteColor::Status(effect.kind)).into(),
+                        ..default()
+                    });
+                }
+            });
+        }
+    }
+}
+
+fn update_equipment_text(
+    locale: Res<Localization>,
+    players: Query<(&Player, Ref<Equipment>)>,
+    mut texts: Query<(&EquipmentText, &mut Text)>,
+) {
+    let players = (players.iter()).filter(|(_, e)| locale.is_changed() || e.is_changed());
+    for (player, equipment) in players {
+        let lines: Vec<String> = (EquipSlot::ALL.iter())
+            .map(|slot| {
+                let item = match equipment.get(*slot) {
+                    Some(item) => t!(locale, &format!("item.{}", item)),
+                    None => t!(locale, "equipment.empty"),
+                };
+                format!("{}: {}", t!(locale, slot.key()), item)
+            })
+            .collect();
+        for (_, mut text) in texts.iter_mut().filter(|(t, _)| t.0 == player.index) {
+            text.sections[0].value = lines.join("\n");
+        }
+    }
+}
+
+fn update_points_text(
+    locale: Res<Localization>,
+    queue: Res<TurnQueue>,
+    players: Query<(&Player, &ActionPoints)>,
+    mut texts: Query<(&PointsText, &mut Text, &mut Visibility)>,
+) {
+    // To tenths, so that sums of move costs show without float error.
+    let tenths = |points: f32| (points * 10.).round() / 10.;
+    for (text, mut label, mut visibility) in texts.iter_mut() {
+        let points = (players.iter())
+            .find(|(player, _)| player.index == text.0)
+            .map(|(_, points)| points)
+            .filter(|_| queue.is_active());
+        let Some(points) = points else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let shown = t!(
+            locale,
+            "hud.action_points",
+            current = tenths(points.current),
+            max = tenths(points.max)
+        );
+        let section = &mut label.sections[0];
+        if section.value != shown {
+            section.value = shown;
+        }
+    }
+}
+
+fn update_timer_text(
+    time: Res<Time>,
+    timer: Res<LevelTimer>,
+    palette: Res<PaletteLookup>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<TimerText>>,
+) {
+    for (mut text, mut visibility) in texts.iter_mut() {
+        let Some(remaining) = timer.remaining() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        // Whole seconds, rounded up so that 0:00 is only shown once out.
+        let seconds = remaining.ceil() as u32;
+        let value = format!("{}:{:02}", seconds / 60, seconds % 60);
+        let section = &mut text.sections[0];
+        if section.value != value {
+            section.value = value;
+        }
+        let flash = remaining < TIMER_WARNING
+            && !timer.is_frozen()
+            && (time.elapsed_seconds() * TIMER_FLASH_RATE).fract() < 0.5;
+        section.style.color = if flash {
+            palette.color(PaletteColor::HealthLow)
+        } else {
+            Color::WHITE
+        };
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    replay::ReplayPlayback,
+    vectors::{GridKind, Vector3Int},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Sprint,
+    Dash,
+    Undo,
+    /// Steps the world back while held.
+    Rewind,
+    /// The n-th hex neighbour clockwise, see `HexOrientation::clockwise`.
+    HexStep(usize),
+    /// Dismisses overlays such as the level summary.
+    Confirm,
+    /// Ends the player's turn in turn-based mode, whatever points are left.
+    Pass,
+}
+
+pub const MOVE_ACTIONS: [(Action, Vector3Int); 4] = [
+    (Action::MoveUp, Vector3Int::UP),
+    (Action::MoveDown, Vector3Int::DOWN),
+    (Action::MoveLeft, Vector3Int::LEFT),
+    (Action::MoveRight, Vector3Int::RIGHT),
+];
+
+/// Keys for `Action::HexStep`, clockwise round the block they sit in.
+const HEX_KEYS: [KeyCode; 6] = [
+    KeyCode::Q,
+    KeyCode::W,
+    KeyCode::E,
+    KeyCode::D,
+    KeyCode::S,
+    KeyCode::A,
+];
+const HEX_NUMPAD_KEYS: [KeyCode; 6] = [
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::Numpad3,
+    KeyCode::Numpad2,
+    KeyCode::Numpad1,
+];
+
+/// The movement actions on `grid` and the step each one takes.
+pub fn move_actions(grid: GridKind) -> Vec<(Action, Vector3Int)> {
+    match grid {
+        GridKind::Square => MOVE_ACTIONS.to_vec(),
+        GridKind::Hex { orientation } => (orientation.clockwise().into_iter().enumerate())
+            .map(|(i, dir)| (Action::HexStep(i), dir))
+            .collect(),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A button on the gamepad assigned to the same player.
+    Pad(GamepadButtonType),
+}
+
+/// Bindings for one player's actions. Any bound input triggers the action.
+#[derive(Default)]
+pub struct ActionSet {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl ActionSet {
+    pub fn bind(&mut self, action: Action, binding: Binding) -> &mut Self {
+        self.bindings.entry(action).or_default().push(binding);
+        self
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    /// Every action with at least one binding.
+    pub fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.bindings.keys().copied()
+    }
+
+    fn with_pad_defaults(mut self) -> Self {
+        self.bind(Action::MoveUp, Binding::Pad(GamepadButtonType::DPadUp))
+            .bind(Action::MoveDown, Binding::Pad(GamepadButtonType::DPadDown))
+            .bind(Action::MoveLeft, Binding::Pad(GamepadButtonType::DPadLeft))
+            .bind(
+                Action::MoveRight,
+                Binding::Pad(GamepadButtonType::DPadRight),
+            )
+            .bind(Action::Sprint, Binding::Pad(GamepadButtonType::LeftTrigger))
+            .bind(Action::Dash, Binding::Pad(GamepadButtonType::South))
+            .bind(Action::Undo, Binding::Pad(GamepadButtonType::Select))
+            .bind(Action::Rewind, Binding::Pad(GamepadButtonType::West))
+            .bind(Action::Confirm, Binding::Pad(GamepadButtonType::Start))
+            .bind(Action::Pass, Binding::Pad(GamepadButtonType::North));
+        self
+    }
+}
+
+/// One action set per local player, indexed by `Player::index`.
+#[derive(Resource)]
+pub struct InputMap {
+    pub players: Vec<ActionSet>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut one = ActionSet::default();
+        one.bind(Action::MoveUp, Binding::Key(KeyCode::W))
+            .bind(Action::MoveDown, Binding::Key(KeyCode::S))
+            .bind(Action::MoveLeft, Binding::Key(KeyCode::A))
+            .bind(Action::MoveRight, Binding::Key(KeyCode::D))
+            .bind(Action::Sprint, Binding::Key(KeyCode::LShift))
+            .bind(Action::Dash, Binding::Key(KeyCode::F))
+            .bind(Action::Undo, Binding::Key(KeyCode::U))
+            .bind(Action::Rewind, Binding::Key(KeyCode::R))
+            .bind(Action::Confirm, Binding::Key(KeyCode::Return))
+            .bind(Action::Pass, Binding::Key(KeyCode::Z));
+        for (i, key) in HEX_KEYS.into_iter().enumerate() {
+            one.bind(Action::HexStep(i), Binding::Key(key));
+        }
+
+        let mut two = ActionSet::default();
+        two.bind(Action::MoveUp, Binding::Key(KeyCode::Up))
+            .bind(Action::MoveDown, Binding::Key(KeyCode::Down))
+            .bind(Action::MoveLeft, Binding::Key(KeyCode::Left))
+            .bind(Action::MoveRight, Binding::Key(KeyCode::Right))
+            .bind(Action::Sprint, Binding::Key(KeyCode::RShift))
+            .bind(Action::Dash, Binding::Key(KeyCode::RControl))
+            .bind(Action::Undo, Binding::Key(KeyCode::Back))
+            .bind(Action::Rewind, Binding::Key(KeyCode::Numpad0))
+            .bind(Action::Confirm, Binding::Key(KeyCode::NumpadEnter))
+            .bind(Action::Pass, Binding::Key(KeyCode::Numpad5));
+        for (i, key) in HEX_NUMPAD_KEYS.into_iter().enumerate() {
+            two.bind(Action::HexStep(i), Binding::Key(key));
+        }
+
+        InputMap {
+            players: vec![one.with_pad_defaults(), two.with_pad_defaults()],
+        }
+    }
+}
+
+/// Actions pressed since the last simulation tick, so that a press and
+/// release between two ticks still counts.
+#[derive(Default, Resource)]
+pub struct PendingPresses(HashSet<(usize, Action)>);
+
+/// Set while players' own input is ignored, as during a screen transition.
+/// Replays play back what was read at the time regardless.
+#[derive(Default, Resource)]
+pub struct InputSuppressed(pub bool);
+
+/// Actions held and pressed by players on another machine, such as a
+/// client playing over the network.
+#[derive(Default, Resource)]
+pub struct RemoteInput {
+    held: HashSet<(usize, Action)>,
+    pressed: HashSet<(usize, Action)>,
+}
+
+// Only the network session feeds remote input for now.
+#[cfg_attr(not(feature = "net"), allow(dead_code))]
+impl RemoteInput {
+    pub fn set(&mut self, player: usize, action: Action, down: bool) {
+        if down {
+            self.held.insert((player, action));
+            self.pressed.insert((player, action));
+        } else {
+            self.held.remove(&(player, action));
+        }
+    }
+
+    /// Lets go of everything `player` holds, as when they disconnect.
+    pub fn release(&mut self, player: usize) {
+        self.held.retain(|(p, _)| *p != player);
+    }
+}
+
+/// Reads actions for a player straight from the keyboard and their gamepad.
+#[derive(SystemParam)]
+pub struct DeviceInput<'w> {
+    map: Res<'w, InputMap>,
+    keys: Res<'w, Input<KeyCode>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+impl<'w> DeviceInput<'w> {
+    fn check(
+        &self,
+        player: usize,
+        action: Action,
+        key: impl Fn(KeyCode) -> bool,
+        button: impl Fn(GamepadButton) -> bool,
+    ) -> bool {
+        let Some(set) = self.map.players.get(player) else { return false };
+        // Gamepads are handed out to players in connection order.
+        let gamepad = self.gamepads.iter().nth(player);
+
+        set.bindings(action).iter().any(|binding| match *binding {
+            Binding::Key(code) => key(code),
+            Binding::Pad(button_type) => {
+                gamepad.is_some_and(|gamepad| button(GamepadButton::new(gamepad, button_type)))
+            }
+        })
+    }
+
+    pub fn pressed(&self, player: usize, action: Action) -> bool {
+        self.check(
+            player,
+            action,
+            |k| self.keys.pressed(k),
+            |b| self.buttons.pressed(b),
+        )
+    }
+
+    /// Whether `action` was pressed this frame.
+    pub fn just_pressed(&self, player: usize, action: Action) -> bool {
+        self.check(
+            player,
+            action,
+            |k| self.keys.just_pressed(k),
+            |b| self.buttons.just_pressed(b),
+        )
+    }
+}
+
+/// Reads actions for a player on a simulation tick, from the keyboard,
+/// their gamepad and remote input, or from the replay being played back.
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    devices: DeviceInput<'w>,
+    pending: Res<'w, PendingPresses>,
+    suppressed: Res<'w, InputSuppressed>,
+    remote: Option<Res<'w, RemoteInput>>,
+    replay: Option<Res<'w, ReplayPlayback>>,
+}
+
+impl<'w> ActionInput<'w> {
+    pub fn pressed(&self, player: usize, action: Action) -> bool {
+        if let Some(replay) = &self.replay {
+            return replay.pressed(player, action);
+        }
+        if self.suppressed.0 {
+            return false;
+        }
+        self.devices.pressed(player, action)
+            || (self.remote)
+                .as_ref()
+                .is_some_and(|r| r.held.contains(&(player, action)))
+    }
+
+    /// Whether `action` was pressed since the last tick.
+    pub fn just_pressed(&self, player: usize, action: Action) -> bool {
+        if let Some(replay) = &self.replay {
+            return replay.just_pressed(player, action);
+        }
+        if self.suppressed.0 {
+            return false;
+        }
+        self.pending.0.contains(&(player, action))
+            || (self.remote)
+                .as_ref()
+                .is_some_and(|r| r.pressed.contains(&(player, action)))
+    }
+
+    /// Whether players' own input is being ignored, as during a screen
+    /// transition. Never while a replay plays back.
+    pub fn is_suppressed(&self) -> bool {
+        self.replay.is_none() && self.suppressed.0
+    }
+
+    /// Whether any player holds `action`, for shared actions.
+    pub fn any_pressed(&self, action: Action) -> bool {
+        (0..self.devices.map.players.len()).any(|player| self.pressed(player, action))
+    }
+
+    /// Whether any player just pressed `action`, for shared actions.
+    pub fn any_just_pressed(&self, action: Action) -> bool {
+        (0..self.devices.map.players.len()).any(|player| self.just_pressed(player, action))
+    }
+}
+
+/// Holds on to this frame's presses until a tick reads them. Presses while
+/// input is suppressed are dropped rather than kept for later.
+pub fn latch_presses(
+    devices: DeviceInput,
+    suppressed: Res<InputSuppressed>,
+    mut pending: ResMut<PendingPresses>,
+) {
+    if suppressed.0 {
+        return;
+    }
+    for (player, set) in devices.map.players.iter().enumerate() {
+        for action in set.actions() {
+            if devices.just_pressed(player, action) {
+                pending.0.insert((player, action));
+            }
+        }
+    }
+}
+
+pub fn clear_presses(mut pending: ResMut<PendingPresses>, remote: Option<ResMut<RemoteInput>>) {
+    pending.0.clear();
+    if let Some(mut remote) = remote {
+        remote.pressed.clear();
+    }
+}
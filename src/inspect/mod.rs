@@ -0,0 +1,359 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    camera::Letterbox,
+    collision::{covered_cells, Footprint},
+    combat::{preview_attack, Health, BUMP_ATTACK_DAMAGE},
+    editor::EditorState,
+    elements::{Element, ElementConfig, Resistances},
+    equipment::StatSheet,
+    hud::PointerOverUi,
+    layer_of, layer_z,
+    locale::Localization,
+    materials::TileMetadataRegistry,
+    player::Player,
+    projection::GridProjection,
+    status::StatusEffects,
+    t,
+    terrain::TerrainRegistry,
+    turns::{Initiative, TurnBased},
+    vectors::Vector3Int,
+    AppState, CurrentBoard, GraphicsAssets, Position, Tile, LAYER_Z_STRIDE,
+};
+
+/// Seconds the cursor must rest on a new target before the tooltip
+/// switches to it, so sweeping across the board does not flicker.
+const HOVER_DELAY: f32 = 0.1;
+/// Pixels between the cursor and the tooltip's corner.
+const TOOLTIP_OFFSET: f32 = 16.;
+const TOOLTIP_PADDING: f32 = 4.;
+const TOOLTIP_FONT_SIZE: f32 = 14.;
+const TOOLTIP_COLOR: Color = Color::rgba(0., 0., 0., 0.8);
+
+/// The name shown when inspecting an entity. Only entities with one can be
+/// inspected; tiles are named from their metadata or terrain.
+#[derive(Component, Clone, Debug)]
+pub struct Inspectable {
+    pub name: String,
+}
+
+impl Inspectable {
+    pub fn new(name: impl Into<String>) -> Self {
+        Inspectable { name: name.into() }
+    }
+}
+
+/// What the cursor is over in inspect mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InspectTarget {
+    Entity(Entity),
+    /// The top tile of a cell.
+    Tile(Entity),
+}
+
+/// The target the tooltip describes, and the one the cursor has moved to
+/// since, once it has rested there for `HOVER_DELAY`.
+#[derive(Default, Resource)]
+struct Inspection {
+    shown: Option<InspectTarget>,
+    pending: Option<InspectTarget>,
+    /// Seconds the cursor has rested on `pending`.
+    resting: f32,
+}
+
+#[derive(Component)]
+struct Tooltip;
+
+#[derive(Component)]
+struct TooltipText;
+
+pub struct InspectPlugin;
+impl Plugin for InspectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspection>()
+            .add_system(spawn_tooltip.in_schedule(OnEnter(AppState::Game)))
+            .add_systems(
+                (pick_target, describe_target, place_tooltip)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(hide_tooltip.in_schedule(OnExit(AppState::Game)));
+    }
+}
+
+/// Inspect mode is on while Tab is held, except in the editor, which has
+/// Tab for switching layers.
+fn inspecting(keys: &Input<KeyCode>, editor: &EditorState) -> bool {
+    keys.pressed(KeyCode::Tab) && !editor.active
+}
+
+fn spawn_tooltip(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    tooltips: Query<(), With<Tooltip>>,
+) {
+    // Kept between maps.
+    if !tooltips.is_empty() {
+        return;
+    }
+    commands
+        .spawn((
+            Tooltip,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    padding: UiRect::all(Val::Px(TOOLTIP_PADDING)),
+                    ..default()
+                },
+                background_color: TOOLTIP_COLOR.into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TooltipText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: TOOLTIP_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
+
+/// Finds what is under the cursor on the first player's layer: the
+/// inspectable entity on top, or else the top tile.
+#[allow(clippy::too_many_arguments)]
+fn pick_target(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    pointer: Res<PointerOverUi>,
+    time: Res<Time>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    letterbox: Res<Letterbox>,
+    projection: Res<GridProjection>,
+    current: Res<CurrentBoard>,
+    players: Query<&Position, With<Player>>,
+    inspectables: Query<(Entity, &Position, Option<&Footprint>), With<Inspectable>>,
+    mut inspection: ResMut<Inspection>,
+) {
+    if !inspecting(&keys, &editor) || pointer.0 {
+        *inspection = Inspection::default();
+        return;
+    }
+    let layer = players.iter().next().map_or(0, |p| layer_of(p.v.z));
+    let cell = windows
+        .get_single()
+        .ok()
+        .and_then(|window| {
+            let cursor = window.cursor_position()?;
+            letterbox.shows(window, cursor).then_some(cursor)
+        })
+        .and_then(|cursor| {
+            let (camera, transform) = cameras.get_single().ok()?;
+            camera.viewport_to_world(transform, cursor)
+        })
+        .map(|ray| projection.cell_at(ray.origin.truncate(), layer_z(layer, 0)))
+        .map(|v| current.wrap(v));
+
+    let target = cell.and_then(|cell| {
+        let on_cell = |position: &Position, footprint: Option<&Footprint>| {
+            layer_of(position.v.z) == layer
+                && (covered_cells(position.v, footprint).into_iter())
+                    .any(|c| current.wrap(c).x == cell.x && current.wrap(c).y == cell.y)
+        };
+        let entity = (inspectables.iter())
+            .filter(|(_, position, footprint)| on_cell(position, *footprint))
+            .max_by_key(|(_, position, _)| position.v.z)
+            .map(|(entity, ..)| InspectTarget::Entity(entity));
+        entity.or_else(|| {
+            (0..LAYER_Z_STRIDE)
+                .rev()
+                .find_map(|offset| {
+                    let v = Vector3Int::new(cell.x, cell.y, layer_z(layer, offset));
+                    current.tiles.get(&v)
+                })
+                .map(|tile| InspectTarget::Tile(*tile))
+        })
+    });
+
+    if target != inspection.pending {
+        inspection.pending = target;
+        inspection.resting = 0.;
+    }
+    inspection.resting += time.delta_seconds();
+    // Showing the first target waits too, so a quick tap of Tab shows
+    // nothing.
+    if inspection.resting >= HOVER_DELAY {
+        inspection.shown = inspection.pending;
+    }
+}
+
+/// Writes what is known about the shown target into the tooltip: its
+/// name, health, status effects and terrain, and in turn-based mode its
+/// initiative and what a bump attack would do to it.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn describe_target(
+    inspection: Res<Inspection>,
+    turn_based: Res<TurnBased>,
+    entities: Query<(
+        &Inspectable,
+        Option<&Health>,
+        Option<&StatusEffects>,
+        Option<&Initiative>,
+        Option<&Player>,
+        Option<&Resistances>,
+    )>,
+    sheets: Query<(&Player, &StatSheet)>,
+    elements: Res<ElementConfig>,
+    tiles: Query<&Tile>,
+    metadata: Res<TileMetadataRegistry>,
+    terrain: Res<TerrainRegistry>,
+    mut texts: Query<&mut Text, With<TooltipText>>,
+    locale: Res<Localization>,
+) {
+    let lines = match inspection.shown {
+        Some(InspectTarget::Entity(entity)) => match entities.get(entity) {
+            Ok((inspectable, health, effects, initiative, player, own)) => {
+                let mut lines = vec![locale.data_text(&inspectable.name)];
+                if let Some(health) = health {
+                    lines.push(t!(
+                        locale,
+                        "inspect.health",
+                        current = health.current,
+                        max = health.max
+                    ));
+                }
+                for effect in effects.into_iter().flat_map(|e| e.0.iter()) {
+                    lines.push(format!(
+                        "{:?} x{} ({}s)",
+                        effect.kind, effect.magnitude, effect.remaining
+                    ));
+                }
+                if turn_based.0 {
+                    if let Some(initiative) = initiative {
+                        lines.push(t!(locale, "inspect.initiative", value = initiative.value));
+                    }
+                    if let Some(health) = health.filter(|_| player.is_none()) {
+                        // As the first player would strike.
+                        let strength = (sheets.iter().min_by_key(|(p, _)| p.index))
+                            .map_or(BUMP_ATTACK_DAMAGE, |(_, sheet)| sheet.attack);
+                        let resisted = Resistances::of(None, own, effects).get(Element::Physical);
+                        let attack = preview_attack(elements.resist(strength, resisted), health);
+                        lines.push(if attack.defeats() {
+                            t!(locale, "inspect.attack.defeats", damage = attack.damage)
+                        } else {
+                            t!(
+                                locale,
+                                "inspect.attack",
+                                damage = attack.damage,
+                                left = attack.remaining
+                            )
+                        });
+                    }
+                }
+                lines
+            }
+            // Gone since it was hovered.
+            Err(_) => Vec::new(),
+        },
+        Some(InspectTarget::Tile(entity)) => match tiles.get(entity) {
+            Ok(tile) => {
+                let data = metadata.0.get(&tile.i);
+                let kind = terrain.kind(tile.i);
+                let name = match (data.and_then(|d| d.name.clone()), kind) {
+                    (Some(name), _) => locale.data_text(&name),
+                    (None, Some(kind)) => format!("{:?}", kind),
+                    (None, None) => t!(locale, "inspect.tile", index = tile.i),
+                };
+                let mut lines = vec![name];
+                if let Some(hooks) = terrain.hooks(tile.i) {
+                    if hooks.move_cost != 1. {
+                        lines.push(t!(locale, "inspect.move_cost", cost = hooks.move_cost));
+                    }
+                    if hooks.slide {
+                        lines.push(t!(locale, "inspect.slippery"));
+                    }
+                }
+                if let Some(effect) = data.and_then(|d| d.status) {
+                    lines.push(t!(
+                        locale,
+                        "inspect.inflicts",
+                        effect = format!("{:?}", effect.kind),
+                        time = effect.remaining
+                    ));
+                }
+                if data.is_some_and(|d| d.destructible) {
+                    lines.push(t!(locale, "inspect.destructible"));
+                }
+                lines
+            }
+            Err(_) => Vec::new(),
+        },
+        None => Vec::new(),
+    };
+
+    let text = lines.join("\n");
+    for mut shown in texts.iter_mut() {
+        // Only touched when it changes, so the layout is not redone every
+        // frame.
+        if shown.sections[0].value != text {
+            shown.sections[0].value = text.clone();
+        }
+    }
+}
+
+/// Keeps the tooltip beside the cursor, and inside the window.
+fn place_tooltip(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    texts: Query<&Text, With<TooltipText>>,
+    mut tooltips: Query<(&mut Style, &mut Visibility, &Node), With<Tooltip>>,
+) {
+    let empty = texts.iter().all(|text| text.sections[0].value.is_empty());
+    let window = windows.get_single().ok();
+    let cursor = window.and_then(|window| window.cursor_position());
+    for (mut style, mut visibility, node) in tooltips.iter_mut() {
+        let (Some(window), Some(cursor), false) = (window, cursor, empty) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        // The cursor is measured up from the bottom, and UI down from the
+        // top. Past the right or bottom edge, the tooltip flips to the
+        // cursor's other side, and is then kept on screen.
+        let size = node.size();
+        let (width, height) = (window.width(), window.height());
+        let (x, y) = (cursor.x, height - cursor.y);
+        let mut left = x + TOOLTIP_OFFSET;
+        if left + size.x > width {
+            left = x - TOOLTIP_OFFSET - size.x;
+        }
+        let mut top = y + TOOLTIP_OFFSET;
+        if top + size.y > height {
+            top = y - TOOLTIP_OFFSET - size.y;
+        }
+        let left = left.clamp(0., (width - size.x).max(0.));
+        let top = top.clamp(0., (height - size.y).max(0.));
+        style.position = UiRect {
+            left: Val::Px(left),
+            top: Val::Px(top),
+            ..default()
+        };
+    }
+}
+
+fn hide_tooltip(
+    mut inspection: ResMut<Inspection>,
+    mut tooltips: Query<&mut Visibility, With<Tooltip>>,
+) {
+    *inspection = Inspection::default();
+    for mut visibility in tooltips.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
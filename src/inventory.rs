@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Picked up into a player's purse rather than their inventory.
+pub const COIN_ITEM: &str = "coin";
+/// Different items a player can carry at once, any number of each.
+pub const INVENTORY_SLOTS: usize = 8;
+
+/// Coins a player has to spend.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Currency(pub u32);
+
+/// What a player carries: how many of each item, by id.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Inventory {
+    items: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn count(&self, id: &str) -> u32 {
+        self.items.get(id).copied().unwrap_or(0)
+    }
+
+    /// Whether `id` would fit, either with others of its kind or in a
+    /// free slot.
+    pub fn has_room_for(&self, id: &str) -> bool {
+        self.items.contains_key(id) || self.items.len() < INVENTORY_SLOTS
+    }
+
+    /// Adds `count` of `id`, or nothing and returns false if there is no
+    /// room for it.
+    pub fn add(&mut self, id: &str, count: u32) -> bool {
+        if !self.has_room_for(id) {
+            return false;
+        }
+        *self.items.entry(id.to_string()).or_default() += count;
+        true
+    }
+
+    /// Takes away `count` of `id`, or nothing and returns false if there
+    /// are fewer. The last one taken frees its slot.
+    pub fn remove(&mut self, id: &str, count: u32) -> bool {
+        let Some(held) = self.items.get_mut(id).filter(|held| **held >= count) else {
+            return false;
+        };
+        *held -= count;
+        if *held == 0 {
+            self.items.remove(id);
+        }
+        true
+    }
+
+    /// Each item carried and how many, by id.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.items.iter().map(|(id, count)| (id.as_str(), *count))
+    }
+}
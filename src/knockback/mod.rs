@@ -0,0 +1,359 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    collision::{
+        covered_cells, footprint_fits, step_allowed, CollisionFlags, CollisionMap, Footprint,
+        Occupancy,
+    },
+    combat::{self, DamageEvent, Health},
+    difficulty::DifficultyModifiers,
+    elements::Element,
+    explosions, get_world_position,
+    hazards::{damage_at, Hazard},
+    player::{MoveTween, MovementState},
+    projection::GridProjection,
+    puzzles::Pushable,
+    simulation::{SimulationApp, SimulationSet},
+    terrain::{actor_capabilities, CapabilitySources},
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position,
+};
+
+/// Cells a melee hit pushes its victim.
+pub const HIT_KNOCKBACK: u32 = 1;
+/// Seconds a knocked-back sprite takes to reach its new cell, well under a
+/// walking step.
+const KNOCKBACK_DURATION: f32 = 0.08;
+
+/// Pushes `entity` up to `strength` cells in direction `dir`, one cell at
+/// a time, stopping at the first cell it cannot enter.
+///
+/// - Off the board, or into another occupier, it just stops.
+/// - Into a wall, closed door or pushable block it stops and takes
+///   `KnockbackConfig::slam_damage`. Blocks are not pushed along.
+/// - Hazards where it lands hurt it at once.
+pub struct KnockbackEvent {
+    pub entity: Entity,
+    pub dir: Vector3Int,
+    pub strength: u32,
+}
+
+impl KnockbackEvent {
+    /// A push away from `from` on a neighbouring cell of `to`, worked out
+    /// across wrapping edges.
+    pub fn away(
+        entity: Entity,
+        from: Vector3Int,
+        to: Vector3Int,
+        strength: u32,
+        current: &CurrentBoard,
+    ) -> Self {
+        let delta = current.delta(from, to);
+        KnockbackEvent {
+            entity,
+            dir: Vector3Int::new(delta.x, delta.y, 0),
+            strength,
+        }
+    }
+
+    /// A push from a blast at `center` on something at `v`, along whichever
+    /// direction leads furthest from it. `None` at the centre itself.
+    pub fn from_blast(
+        entity: Entity,
+        center: Vector3Int,
+        v: Vector3Int,
+        strength: u32,
+        current: &CurrentBoard,
+        grid: GridKind,
+    ) -> Option<Self> {
+        if current.distance(grid, center, v) == 0 {
+            return None;
+        }
+        // Ties go to the direction most in line with the blast, so that
+        // square grids push straight out rather than sideways.
+        let away = current.delta(center, v);
+        let dir = grid.directions().iter().max_by_key(|dir| {
+            (
+                current.distance(grid, center, v + **dir),
+                dir.x * away.x + dir.y * away.y,
+            )
+        })?;
+        Some(KnockbackEvent {
+            entity,
+            dir: *dir,
+            strength,
+        })
+    }
+}
+
+#[derive(Resource)]
+pub struct KnockbackConfig {
+    /// Damage taken when knocked into a wall or block.
+    pub slam_damage: u32,
+}
+
+impl Default for KnockbackConfig {
+    fn default() -> Self {
+        KnockbackConfig { slam_damage: 1 }
+    }
+}
+
+/// The quick slide of a knocked-back sprite onto its new cell.
+#[derive(Component)]
+struct KnockbackTween {
+    start: Vec3,
+    end: Vec3,
+    elapsed: f32,
+}
+
+pub struct KnockbackPlugin;
+impl Plugin for KnockbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KnockbackConfig>()
+            .add_simulation_event::<KnockbackEvent>()
+            // Once the tick's hits have landed, so that the defeated stay
+            // where they fell.
+            .add_system(
+                knock_back
+                    .after(combat::despawn_dead)
+                    .after(explosions::damage_tiles)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            // After walking and object sprites have been placed.
+            .add_system(
+                animate_knockback
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn knock_back(
+    mut commands: Commands,
+    mut events: EventReader<KnockbackEvent>,
+    mut victims: Query<(
+        &mut Position,
+        &Health,
+        Option<&Footprint>,
+        Option<&Transform>,
+        Option<&mut MovementState>,
+        CapabilitySources,
+    )>,
+    pushables: Query<(), With<Pushable>>,
+    hazards: Query<(&Hazard, &Position), Without<Health>>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    mut occupancy: ResMut<Occupancy>,
+    grid: Res<GridKind>,
+    projection: Res<GridProjection>,
+    config: Res<KnockbackConfig>,
+    modifiers: Res<DifficultyModifiers>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for event in events.iter() {
+        let Ok((mut position, health, footprint, transform, state, sources)) =
+            victims.get_mut(event.entity)
+        else {
+            continue;
+        };
+        let capabilities = actor_capabilities(sources);
+        // Only straight pushes onto a neighbouring cell.
+        if health.current == 0 || !grid.directions().contains(&event.dir) {
+            continue;
+        }
+
+        let mut v = position.v;
+        for _ in 0..event.strength {
+            let next = current.wrap(v + event.dir);
+            // A ledge is no wall to slam into, only a way not to go.
+            if !step_allowed(v, event.dir, footprint, &current, &collision) {
+                break;
+            }
+            if footprint_fits(
+                event.entity,
+                next,
+                footprint,
+                capabilities,
+                &current,
+                &collision,
+                &occupancy,
+            ) {
+                v = next;
+                continue;
+            }
+            let slammed = covered_cells(next, footprint).into_iter().any(|cell| {
+                let cell = current.wrap(cell);
+                collision.blocks(cell, CollisionFlags::BLOCK_WALK)
+                    || (occupancy.get(cell))
+                        .is_some_and(|e| e != event.entity && pushables.contains(e))
+            });
+            if slammed && config.slam_damage > 0 {
+                damage.send(DamageEvent {
+                    entity: event.entity,
+                    amount: config.slam_damage,
+                    element: Element::Physical,
+                });
+            }
+            break;
+        }
+        if v == position.v {
+            continue;
+        }
+
+        position.v = v;
+        occupancy.insert(event.entity, covered_cells(v, footprint));
+        if let Some(mut state) = state {
+            state.cancel_step();
+            state.slide = None;
+        }
+        for (element, amount) in damage_at(hazards.iter(), v) {
+            damage.send(DamageEvent {
+                entity: event.entity,
+                amount: modifiers.hazard_damage(amount),
+                element,
+            });
+        }
+        if let Some(transform) = transform {
+            commands.entity(event.entity).insert(KnockbackTween {
+                start: transform.translation,
+                end: get_world_position(&position, &projection),
+                elapsed: 0.,
+            });
+        }
+    }
+}
+
+fn animate_knockback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(
+        Entity,
+        &mut KnockbackTween,
+        &mut Transform,
+        Option<&mut MoveTween>,
+    )>,
+) {
+    for (entity, mut knockback, mut transform, tween) in query.iter_mut() {
+        knockback.elapsed += time.delta_seconds();
+        let t = (knockback.elapsed / KNOCKBACK_DURATION).min(1.);
+        transform.translation = knockback.start.lerp(knockback.end, t);
+        // Walking picks up from the new cell rather than easing over.
+        if let Some(mut tween) = tween {
+            *tween = MoveTween::at(knockback.end);
+        }
+        if t >= 1. {
+            commands.entity(entity).remove::<KnockbackTween>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the victim stands at the start, on a row of ground from
+    /// column 0 to 5.
+    const START: Vector3Int = Vector3Int::new(1, 0, 0);
+
+    fn cell(x: i32) -> Vector3Int {
+        Vector3Int::new(x, 0, 0)
+    }
+
+    /// Knockback alone on the row, with a victim on `START`.
+    fn row() -> (App, Entity) {
+        let mut app = App::new();
+        app.insert_resource(CurrentBoard::with_ground((0..6).map(cell)))
+            .insert_resource(GridKind::Square)
+            .init_resource::<CollisionMap>()
+            .init_resource::<Occupancy>()
+            .init_resource::<GridProjection>()
+            .init_resource::<KnockbackConfig>()
+            .init_resource::<DifficultyModifiers>()
+            .add_event::<KnockbackEvent>()
+            .add_event::<DamageEvent>()
+            .add_system(knock_back);
+        let victim = occupy(&mut app, START, Health::new(5));
+        (app, victim)
+    }
+
+    /// Puts an occupier with `bundle` on `v`.
+    fn occupy(app: &mut App, v: Vector3Int, bundle: impl Bundle) -> Entity {
+        let entity = app.world.spawn((Position { v }, bundle)).id();
+        app.world.resource_mut::<Occupancy>().insert(entity, [v]);
+        entity
+    }
+
+    /// Knocks `victim` `strength` cells right, giving where it ends up and
+    /// the damage it takes.
+    fn knock(app: &mut App, victim: Entity, strength: u32) -> (Vector3Int, Vec<(u32, Element)>) {
+        app.world.send_event(KnockbackEvent {
+            entity: victim,
+            dir: Vector3Int::RIGHT,
+            strength,
+        });
+        app.update();
+        let damage = (app.world.resource::<Events<DamageEvent>>())
+            .iter_current_update_events()
+            .filter(|event| event.entity == victim)
+            .map(|event| (event.amount, event.element))
+            .collect();
+        (app.world.get::<Position>(victim).unwrap().v, damage)
+    }
+
+    #[test]
+    fn blocks_are_slammed_into_not_pushed() {
+        let (mut app, victim) = row();
+        let block = occupy(&mut app, cell(2), Pushable);
+
+        let slam = KnockbackConfig::default().slam_damage;
+        assert_eq!(knock(&mut app, victim, 1), (START, vec![(slam, Element::Physical)]));
+        assert_eq!(app.world.get::<Position>(block).unwrap().v, cell(2));
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.get(cell(2)), Some(block));
+        assert_eq!(occupancy.get(START), Some(victim));
+    }
+
+    #[test]
+    fn strong_pushes_stop_at_the_first_wall() {
+        let (mut app, victim) = row();
+        app.world.resource_mut::<CollisionMap>().block(cell(3));
+
+        let slam = KnockbackConfig::default().slam_damage;
+        assert_eq!(knock(&mut app, victim, 2), (cell(2), vec![(slam, Element::Physical)]));
+    }
+
+    #[test]
+    fn occupancy_follows_the_victim() {
+        let (mut app, victim) = row();
+
+        assert_eq!(knock(&mut app, victim, 2), (cell(3), vec![]));
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.get(cell(3)), Some(victim));
+        assert_eq!(occupancy.get(START), None);
+    }
+
+    #[test]
+    fn hazards_hurt_on_landing() {
+        let (mut app, victim) = row();
+        let fire = Hazard {
+            damage: 3,
+            element: Element::Fire,
+        };
+        app.world.spawn((fire, Position { v: cell(2) }));
+
+        assert_eq!(knock(&mut app, victim, 1), (cell(2), vec![(3, Element::Fire)]));
+    }
+
+    #[test]
+    fn nothing_is_pushed_off_the_board_or_onto_others() {
+        let (mut app, victim) = row();
+        occupy(&mut app, cell(2), Health::new(5));
+        assert_eq!(knock(&mut app, victim, 1), (START, vec![]));
+
+        let edge = occupy(&mut app, cell(5), Health::new(5));
+        assert_eq!(knock(&mut app, edge, 3), (cell(5), vec![]));
+        assert_eq!(app.world.resource::<Occupancy>().get(cell(5)), Some(edge));
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    flags::{GameFlags, SetFlagEvent},
+    locale::Localization,
+    objects::{MapObject, MapObjectIndex},
+    player::Player,
+    projection::GridProjection,
+    regions::{self, Region, RegionEntered},
+    render_layers::{z_for, RenderLayerSlot},
+    simulation::SimulationSet,
+    vectors::GridKind,
+    AppState, CurrentBoard, GraphicsAssets, Position, Scene, SceneHandle,
+};
+
+/// Over the breadcrumb trail and path preview, within the overlay band.
+const LABEL_Z: f32 = 10.;
+const LABEL_FONT_SIZE: f32 = 12.;
+/// Cells from a player within which a label is seen, and stays seen.
+const REVEAL_RADIUS: i32 = 4;
+/// Under screen transitions and menus.
+const TITLE_Z: i32 = 10;
+const TITLE_FONT_SIZE: f32 = 48.;
+/// Seconds an area's title is shown, the last of them fading out.
+const TITLE_SECONDS: f32 = 2.5;
+const TITLE_FADE_SECONDS: f32 = 0.5;
+
+/// Text placed on the map, such as a signpost, drawn over the board once a
+/// player has come near it.
+#[derive(Component, Clone, Debug)]
+pub struct MapLabel {
+    pub text: String,
+    pub font_size: f32,
+    pub color: Color,
+    /// A font other than the default, loaded with the map.
+    pub font: Option<String>,
+}
+
+impl MapLabel {
+    /// Reads a `label` object's `text`, `font_size`, `color` (as hex, such
+    /// as `"#ffd080"`) and `font`.
+    pub fn from_object(object: &MapObject) -> Self {
+        let color = object.str_prop("color").map(|hex| {
+            Color::hex(hex.trim_start_matches('#')).unwrap_or_else(|_| {
+                warn!("Label has malformed color `{}`.", hex);
+                Color::WHITE
+            })
+        });
+        MapLabel {
+            text: object.str_prop("text").unwrap_or_default().to_string(),
+            font_size: object.f32_prop("font_size").unwrap_or(LABEL_FONT_SIZE),
+            color: color.unwrap_or(Color::WHITE),
+            font: object.str_prop("font").map(str::to_string),
+        }
+    }
+}
+
+/// A label a player has come near.
+#[derive(Component)]
+struct Revealed;
+
+/// The name a region announces the first time a player enters it.
+#[derive(Component)]
+pub struct AreaName(pub String);
+
+/// The area name on screen, and for how much longer.
+#[derive(Resource, Default)]
+struct AreaTitle {
+    text: String,
+    remaining: f32,
+}
+
+#[derive(Component)]
+struct AreaTitleText;
+
+pub struct LabelsPlugin;
+impl Plugin for LabelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AreaTitle>()
+            .add_system(spawn_area_title.in_schedule(OnEnter(AppState::Game)))
+            .add_systems((respawn_labels, spawn_label_text, reveal_labels).chain())
+            .add_system(translate_labels.after(spawn_label_text))
+            .add_system(draw_area_title)
+            .add_system(
+                announce_areas
+                    .after(regions::update_membership)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// The label's text, hidden until revealed.
+fn spawn_label_text(
+    mut commands: Commands,
+    labels: Query<(Entity, &MapLabel, &Position, Option<&Revealed>), Added<MapLabel>>,
+    server: Res<AssetServer>,
+    graphics: Res<GraphicsAssets>,
+    projection: Res<GridProjection>,
+    locale: Res<Localization>,
+) {
+    for (entity, label, position, revealed) in labels.iter() {
+        // Map fonts were loaded with the map's group, so this looks it up.
+        let font = match &label.font {
+            Some(path) => server.load(path.as_str()),
+            None => graphics.font.clone(),
+        };
+        let style = TextStyle {
+            font,
+            font_size: label.font_size,
+            color: label.color,
+        };
+        let v = projection.world(position.v).truncate();
+        commands.entity(entity).insert(Text2dBundle {
+            text: Text::from_section(locale.data_text(&label.text), style)
+                .with_alignment(TextAlignment::Center),
+            transform: Transform::from_translation(
+                v.extend(z_for(RenderLayerSlot::Overlay, LABEL_Z)),
+            ),
+            visibility: match revealed {
+                Some(_) => Visibility::Inherited,
+                None => Visibility::Hidden,
+            },
+            ..default()
+        });
+    }
+}
+
+/// Rewrites placed labels in a newly chosen language.
+fn translate_labels(locale: Res<Localization>, mut labels: Query<(&MapLabel, &mut Text)>) {
+    if !locale.is_changed() {
+        return;
+    }
+    for (label, mut text) in labels.iter_mut() {
+        text.sections[0].value = locale.data_text(&label.text);
+    }
+}
+
+/// Shows labels once a player comes within `REVEAL_RADIUS` of them.
+#[allow(clippy::type_complexity)]
+fn reveal_labels(
+    mut commands: Commands,
+    mut labels: Query<(Entity, &Position, &mut Visibility), (With<MapLabel>, Without<Revealed>)>,
+    players: Query<&Position, With<Player>>,
+    board: Res<CurrentBoard>,
+    grid: Res<GridKind>,
+) {
+    for (entity, position, mut visibility) in labels.iter_mut() {
+        let near = (players.iter())
+            .any(|player| board.distance(*grid, player.v, position.v) <= REVEAL_RADIUS);
+        if near {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).insert(Revealed);
+        }
+    }
+}
+
+/// Puts the labels back from the scene when its file changes, keeping
+/// those already revealed in sight.
+fn respawn_labels(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Scene>>,
+    scene: Res<SceneHandle>,
+    scenes: Res<Assets<Scene>>,
+    labels: Query<(Entity, &Position, Option<&Revealed>), With<MapLabel>>,
+    grid: Res<GridKind>,
+) {
+    let modified = events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { handle } if *handle == scene.0));
+    let Some(scene) = scenes.get(&scene.0).filter(|_| modified) else { return };
+
+    let mut revealed = HashSet::new();
+    for (entity, position, seen) in labels.iter() {
+        if seen.is_some() {
+            revealed.insert(position.v);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+    let objects = scene.objects.iter().enumerate();
+    for (index, object) in objects.filter(|(_, object)| object.kind == "label") {
+        let v = object.position(*grid);
+        let mut entity = commands.spawn((
+            Position { v },
+            MapObjectIndex(index),
+            MapLabel::from_object(object),
+        ));
+        if revealed.contains(&v) {
+            entity.insert(Revealed);
+        }
+    }
+}
+
+/// Titles an area the first time a player enters it. Seen areas are kept
+/// as `area_seen_<id>` flags, remembered with the map's other flags when
+/// it is left.
+fn announce_areas(
+    mut entered: EventReader<RegionEntered>,
+    players: Query<(), With<Player>>,
+    areas: Query<(&Region, &AreaName)>,
+    flags: Res<GameFlags>,
+    mut set_flags: EventWriter<SetFlagEvent>,
+    mut title: ResMut<AreaTitle>,
+) {
+    // Flags set this tick are not applied until the next.
+    let mut seen = HashSet::new();
+    for event in entered.iter().filter(|e| players.contains(e.entity)) {
+        let flag = format!("area_seen_{}", event.region_id);
+        if flags.is_set(&flag) || !seen.insert(flag.clone()) {
+            continue;
+        }
+        let Some((_, AreaName(name))) = areas.iter().find(|(r, _)| r.id == event.region_id) else {
+            continue;
+        };
+        info!("Entered {}.", name);
+        set_flags.send(SetFlagEvent::new(&flag, true));
+        title.text = name.clone();
+        title.remaining = TITLE_SECONDS;
+    }
+}
+
+fn spawn_area_title(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    titles: Query<(), With<AreaTitleText>>,
+) {
+    if !titles.is_empty() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::all(Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            z_index: ZIndex::Global(TITLE_Z),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                AreaTitleText,
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: assets.font.clone(),
+                            font_size: TITLE_FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                    )
+                    .with_text_alignment(TextAlignment::Center)
+                },
+            ));
+        });
+}
+
+/// Shows the area title while it lasts, fading it out at the end.
+fn draw_area_title(
+    time: Res<Time>,
+    mut title: ResMut<AreaTitle>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<AreaTitleText>>,
+    locale: Res<Localization>,
+) {
+    let alpha = (title.remaining / TITLE_FADE_SECONDS).min(1.);
+    for (mut text, mut visibility) in texts.iter_mut() {
+        let shown = match alpha > 0. {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+        if alpha > 0. {
+            let section = &mut text.sections[0];
+            let shown = locale.data_text(&title.text);
+            if section.value != shown {
+                section.value = shown;
+            }
+            section.style.color.set_a(alpha);
+        }
+    }
+    if title.remaining > 0. {
+        title.remaining = (title.remaining - time.delta_seconds()).max(0.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        replay::LogicalClock,
+        testing::{headless_game, run_ticks, travel},
+    };
+
+    /// Sends the first player into the frozen pond of `data.json`, giving
+    /// the title shown for it, if any.
+    fn enter_pond(app: &mut App) -> Option<String> {
+        let mut players = app.world.query_filtered::<Entity, With<Player>>();
+        let entity = players.single(&app.world);
+        *app.world.resource_mut::<AreaTitle>() = AreaTitle::default();
+        app.world.send_event(RegionEntered {
+            entity,
+            region_id: "ice".to_string(),
+        });
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(app, tick + 2);
+        let title = app.world.resource::<AreaTitle>();
+        Some(title.text.clone()).filter(|text| !text.is_empty())
+    }
+
+    #[test]
+    fn areas_are_titled_once_even_after_leaving_the_map() {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 0);
+        assert_eq!(enter_pond(&mut app).as_deref(), Some("The Frozen Pond"));
+        assert_eq!(enter_pond(&mut app), None);
+
+        travel(&mut app, "level2.json");
+        travel(&mut app, "data.json");
+        assert_eq!(enter_pond(&mut app), None);
+    }
+}
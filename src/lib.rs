@@ -0,0 +1,1510 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+};
+
+use accessibility::AccessibilityPlugin;
+use ambient::AmbientPlugin;
+use assets::{AssetGroup, AssetGroups, AssetManifest, CORE_GROUP};
+use bevy::{
+    asset::LoadState,
+    ecs::system::SystemParam,
+    prelude::*,
+    sprite::{Anchor, Mesh2dHandle},
+};
+use bevy_common_assets::json::JsonAssetPlugin;
+use board::{SetTileEvent, TileChangedEvent};
+use camera::CameraPlugin;
+use collision::{CollisionFlags, CollisionMap, CollisionPlugin};
+use combat::CombatPlugin;
+use console::ConsolePlugin;
+use conveyors::ConveyorPlugin;
+use crafting::{CraftingPlugin, RecipeBook, RecipeFile, RECIPES_FILE};
+use debug_report::DebugReportPlugin;
+use decals::DecalsPlugin;
+use difficulty::DifficultyPlugin;
+use editor::EditorPlugin;
+use elements::ElementsPlugin;
+use equipment::EquipmentPlugin;
+use event_log::EventLogPlugin;
+use explosions::ExplosionsPlugin;
+use fire::FirePlugin;
+use flags::{FlagsPlugin, GameFlags};
+use flash::FlashPlugin;
+use followers::FollowersPlugin;
+use footprints::FootprintsPlugin;
+use hazards::HazardsPlugin;
+use hints::HintsPlugin;
+use hud::HudPlugin;
+use input::InputMap;
+use inspect::InspectPlugin;
+use knockback::KnockbackPlugin;
+use labels::LabelsPlugin;
+use locale::{LocalePlugin, LocaleStrings, Localization};
+use loot::LootPlugin;
+use map_meta::{MapMeta, MapMetaPlugin};
+use materials::{
+    sprite_shape, MaterialsPlugin, ScrollingMaterial, TileMetadata, TileMetadataRegistry,
+};
+use music::{MusicLibrary, MusicPlugin, RegionAudio};
+use npc::NpcPlugin;
+use objects::{MapObject, ObjectsPlugin};
+use path_preview::PathPreviewPlugin;
+use persistence::{PersistencePlugin, Persistent};
+use platforms::PlatformsPlugin;
+use player::{Player, PlayerPlugin};
+use prefabs::{PrefabFile, PrefabRegistry, PrefabsPlugin, PREFABS_FILE};
+use progression::ProgressionPlugin;
+use projectiles::ProjectilesPlugin;
+use projection::GridProjection;
+use puzzles::PuzzlesPlugin;
+use regions::RegionsPlugin;
+use render_layers::{z_index, RenderLayerSlot};
+use replay::ReplayPlugin;
+use rewind::RewindPlugin;
+use rng::GameRng;
+use roofs::RoofsPlugin;
+use saves::SavesPlugin;
+use sfx::{SfxLibrary, SfxPlugin};
+use shop::ShopPlugin;
+use simulation::{SimulationApp, SimulationPlugin, SimulationSet};
+use speed::SpeedPlugin;
+use stats::StatsPlugin;
+use status::{StackRule, StatusKind, StatusPlugin, StatusRules};
+use streaming::{ChunkStreamer, StreamingPlugin, WorldManifest};
+use terrain::{TerrainPlugin, TerrainRegistry, TerrainTable};
+use territory::{Territory, TerritoryPlugin};
+use threats::ThreatsPlugin;
+use tile_names::{MapTile, TileRegistry};
+use tileset_swap::TilesetSwapPlugin;
+use timer::TimerPlugin;
+use tint::TintPlugin;
+use trail::TrailPlugin;
+use transitions::{TransitionCause, TransitionCovered, TransitionsPlugin};
+use turns::TurnsPlugin;
+use undo::UndoPlugin;
+use vectors::{GridKind, GridRect, Vector3Int};
+use vision::VisionPlugin;
+use world_hash::WorldHashPlugin;
+use wrap::WrapPlugin;
+
+pub use progression::Campaign;
+
+pub mod accessibility;
+mod ambient;
+mod assets;
+pub mod board;
+mod camera;
+mod collision;
+mod combat;
+pub mod console;
+mod conveyors;
+mod crafting;
+mod debug_report;
+mod decals;
+mod difficulty;
+mod editor;
+mod elements;
+mod equipment;
+pub mod event_log;
+mod explosions;
+mod fire;
+mod flags;
+mod flash;
+mod followers;
+mod footprints;
+pub mod fov;
+mod hazards;
+mod hints;
+mod hud;
+mod input;
+mod inspect;
+mod inventory;
+mod knockback;
+mod labels;
+mod locale;
+pub mod loot;
+mod map_meta;
+mod marks;
+mod materials;
+mod music;
+#[cfg(feature = "net")]
+pub mod net;
+mod npc;
+mod objects;
+mod path_preview;
+pub mod pathfinding;
+mod persistence;
+mod platforms;
+mod player;
+mod prefabs;
+pub mod prelude;
+pub mod procgen;
+mod progression;
+mod projectiles;
+pub mod projection;
+mod puzzles;
+mod regions;
+mod render_layers;
+pub mod replay;
+mod rewind;
+mod rng;
+mod roofs;
+mod saves;
+mod sfx;
+mod shop;
+pub mod simulation;
+mod speed;
+mod stats;
+mod status;
+pub mod storage;
+mod streaming;
+mod terrain;
+pub mod territory;
+#[cfg(test)]
+mod testing;
+mod threats;
+pub mod tile_names;
+#[cfg(feature = "ecs_tilemap")]
+mod tilemap;
+pub mod tileset_swap;
+mod timer;
+mod tint;
+mod trail;
+pub mod transitions;
+mod turns;
+mod undo;
+pub mod vectors;
+mod vision;
+mod world_hash;
+mod wrap;
+
+pub const TILE_SIZE: f32 = 16.;
+const TILE_Z: f32 = 0.;
+const CAMERA_SCALE: f32 = 0.5;
+/// The image of the built-in sheet, under `assets/`.
+pub const SPRITE_SHEET: &str = "tilemap_packed.png";
+/// Tiles in the built-in sheet (12 x 11).
+const DEFAULT_TILE_COUNT: usize = 132;
+/// Drawn in place of tiles no tileset covers.
+const MISSING_TILE_COLOR: Color = Color::FUCHSIA;
+/// Stands in for out-of-range indices found while loading a scene. No
+/// tileset covers it, so those cells draw as missing tiles.
+const PLACEHOLDER_TILE: usize = usize::MAX;
+/// Each walkable layer (ground, then any overlays such as bridges) gets its
+/// own band of z-indices for tiles, objects and actors.
+pub const LAYER_Z_STRIDE: i32 = 10;
+
+#[derive(serde::Deserialize, bevy::reflect::TypeUuid, Debug)]
+#[uuid = "413be529-bfeb-41b3-9db0-4b8b380a2c46"] // <-- keep me unique
+pub struct Scene {
+    /// Cells per row of each layer.
+    #[serde(default = "default_scene_width")]
+    width: usize,
+    layers: Vec<Vec<MapTile>>, // Corresponds to width * height.
+    /// Walkable layers above the ground, such as bridge decks.
+    #[serde(default)]
+    overlays: Vec<Vec<MapTile>>,
+    /// Roofs, laid out as a layer and drawn over everything on the ground.
+    /// Region objects with a `roof` fade them out while someone is inside.
+    #[serde(default)]
+    roof: Vec<MapTile>,
+    /// Tile names that numbers in the layers stand for, from 1, so that
+    /// a map can name its tiles without spelling each name out.
+    #[serde(default)]
+    palette: Vec<String>,
+    /// What each cell of the ground stops, laid out as a layer: 0 for
+    /// nothing, 1 for a wall, and other values as `collision_flags` says.
+    #[serde(default)]
+    collision: Vec<u8>,
+    /// Collision layer values and the flags they stand for, such as
+    /// `"2": ["block_walk"]` for low fences.
+    #[serde(default)]
+    collision_flags: HashMap<u8, CollisionFlags>,
+    #[serde(default)]
+    objects: Vec<MapObject>,
+    #[serde(default)]
+    terrain: TerrainTable,
+    /// How status effects of each kind stack, overriding the defaults.
+    #[serde(default)]
+    status_rules: HashMap<StatusKind, StackRule>,
+    /// Sound effect keys and the audio files they play.
+    #[serde(default)]
+    sounds: HashMap<String, String>,
+    /// The music track played outside any region with its own.
+    #[serde(default)]
+    music: Option<String>,
+    /// Tile sheets, each covering the indices from its `firstgid`. Without
+    /// any, tiles index into the built-in sheet.
+    #[serde(default)]
+    tilesets: Vec<Tileset>,
+    /// The shape of cells: square (the default) or hex.
+    #[serde(default)]
+    grid: GridKind,
+    /// How cells are laid out on screen. Hex grids always use the hex
+    /// layout.
+    #[serde(default)]
+    projection: GridProjection,
+    /// Rendering options and effects by atlas index, such as scrolling
+    /// water.
+    #[serde(default)]
+    tile_metadata: HashMap<usize, TileMetadata>,
+    /// Board axes that wrap around, joining opposite edges. Square grids
+    /// only.
+    #[serde(default)]
+    wrap: Wrap,
+    /// Seconds to finish the map in, for escapes against the clock.
+    #[serde(default)]
+    time_limit_s: Option<f32>,
+    /// An image to draw the built-in sheet from instead, laid out the same,
+    /// such as a winter version.
+    #[serde(default)]
+    tileset_variant: Option<String>,
+    /// The map's name and author, and settings that override the keys
+    /// above. See `MapMeta`.
+    #[serde(default)]
+    meta: MapMeta,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Tileset {
+    image: String,
+    /// The map value of the sheet's first tile, as in Tiled.
+    firstgid: usize,
+    columns: usize,
+    tilecount: usize,
+    /// Pixels between neighbouring tiles in the image.
+    #[serde(default)]
+    spacing: f32,
+    /// Pixels, by default the map's `tile_size` or else `TILE_SIZE`.
+    #[serde(default)]
+    tilewidth: Option<f32>,
+    #[serde(default)]
+    tileheight: Option<f32>,
+    /// Tile names and their indices in the sheet, for maps to name tiles
+    /// by rather than by where the sheet puts them.
+    #[serde(default)]
+    names: HashMap<String, usize>,
+}
+
+fn default_scene_width() -> usize {
+    32
+}
+
+impl Scene {
+    /// Columns and rows, going by the longest layer. At least one of each.
+    fn size(&self) -> (usize, usize) {
+        let width = self.width.max(1);
+        let height = (self.layers.iter().chain(self.overlays.iter()))
+            .chain([&self.roof])
+            .map(|layer| layer.len().div_ceil(width))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        (width, height)
+    }
+
+    /// The map's meta block, with the older top-level `music`, `wrap` and
+    /// `time_limit_s` filling in what it leaves out.
+    pub fn meta(&self) -> MapMeta {
+        let mut meta = self.meta.clone();
+        meta.default_music = meta.default_music.or_else(|| self.music.clone());
+        meta.wrap = meta.wrap.or(Some(self.wrap));
+        meta.time_limit_s = meta.time_limit_s.or(self.time_limit_s);
+        meta
+    }
+
+    /// The map's track and those of its music regions.
+    fn music_tracks(&self) -> impl Iterator<Item = String> + '_ {
+        let regions = (self.objects.iter())
+            .filter(|object| object.kind == "region")
+            .filter_map(|object| object.json_prop::<RegionAudio>("audio")?.ok())
+            .map(|audio| audio.track);
+        self.meta().default_music.into_iter().chain(regions)
+    }
+
+    /// Fonts the map's labels are drawn in, other than the default.
+    fn label_fonts(&self) -> impl Iterator<Item = String> + '_ {
+        (self.objects.iter())
+            .filter(|object| object.kind == "label")
+            .filter_map(|object| object.str_prop("font").map(str::to_string))
+    }
+}
+
+impl Tileset {
+    /// The range of `Tile::i` (map values less one) the sheet covers.
+    fn range(&self) -> Range<usize> {
+        let start = self.firstgid.saturating_sub(1);
+        start..start + self.tilecount
+    }
+}
+
+#[derive(Resource)]
+pub struct GraphicsAssets {
+    /// The built-in sheet that object and actor sprites index into.
+    pub sprite_texture: Handle<TextureAtlas>,
+    /// Tile atlases and the range of tile indices each covers.
+    pub atlases: Vec<(Range<usize>, Handle<TextureAtlas>)>,
+    /// The names the atlases give their tiles.
+    pub tile_names: TileRegistry,
+    pub font: Handle<Font>,
+}
+
+impl GraphicsAssets {
+    /// The atlas holding tile index `i`, and the index within it, if that
+    /// lies inside the atlas.
+    pub fn tile_atlas(
+        &self,
+        i: usize,
+        atlases: &Assets<TextureAtlas>,
+    ) -> Option<(&Handle<TextureAtlas>, usize)> {
+        (self.atlases.iter())
+            .find(|(range, _)| range.contains(&i))
+            .map(|(range, atlas)| (atlas, i - range.start))
+            .filter(|(atlas, index)| atlases.get(atlas).is_some_and(|a| *index < a.len()))
+    }
+}
+
+/// A scene cell whose tile no atlas covers: an index out of range, or a
+/// name no tileset gives.
+#[derive(Debug, Clone)]
+pub struct TileIssue {
+    /// The tile z-index, as on the board.
+    pub layer: i32,
+    /// Column and row in the scene.
+    pub x: i32,
+    pub y: i32,
+    /// The cell as the map gives it, with palette numbers looked up.
+    pub tile: MapTile,
+}
+
+/// Problems found while loading the current scene, replaced on each load.
+#[derive(Default, Resource)]
+pub struct MapValidationReport {
+    pub issues: Vec<TileIssue>,
+}
+
+impl MapValidationReport {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for MapValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} tiles no tileset covers:", self.issues.len())?;
+        for issue in self.issues.iter() {
+            write!(
+                f,
+                "\n  {} at ({}, {}) on layer {}",
+                issue.tile, issue.x, issue.y, issue.layer
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Resource)]
+pub struct CurrentBoard {
+    pub tiles: HashMap<Vector3Int, Entity>,
+    pub bounds: BoardBounds,
+}
+
+impl CurrentBoard {
+    /// `v` moved onto the board across any wrapping edges.
+    pub fn wrap(&self, v: Vector3Int) -> Vector3Int {
+        wrap_position(v, &self.bounds)
+    }
+
+    /// The tile to stand on at `v`, on the layer of `v.z`.
+    pub fn floor(&self, v: Vector3Int) -> Option<Entity> {
+        let v = self.wrap(v);
+        self.tiles
+            .get(&Vector3Int::new(
+                v.x,
+                v.y,
+                layer_z(layer_of(v.z), z_index(RenderLayerSlot::Ground, 0)),
+            ))
+            .copied()
+    }
+
+    pub fn has_ground(&self, v: Vector3Int) -> bool {
+        self.floor(v).is_some()
+    }
+
+    /// The cells next to `v`, wrapped onto the board.
+    pub fn neighbours(
+        &self,
+        v: Vector3Int,
+        grid: GridKind,
+    ) -> impl Iterator<Item = Vector3Int> + '_ {
+        (grid.directions().iter()).map(move |dir| self.wrap(v + *dir))
+    }
+
+    /// The shortest offset from `from` to `to`, which may cross an edge.
+    pub fn delta(&self, from: Vector3Int, to: Vector3Int) -> Vector3Int {
+        let BoardBounds { rect, wrap } = self.bounds;
+        let shortest = |d: i32, size: i32, wraps: bool| {
+            if wraps && size > 0 {
+                (d + size / 2).rem_euclid(size) - size / 2
+            } else {
+                d
+            }
+        };
+        let d = to - from;
+        Vector3Int::new(
+            shortest(d.x, rect.width, wrap.x),
+            shortest(d.y, rect.height, wrap.y),
+            d.z,
+        )
+    }
+
+    /// Steps between two cells, ignoring z, taking the way across an edge
+    /// when it is shorter.
+    pub fn distance(&self, grid: GridKind, a: Vector3Int, b: Vector3Int) -> i32 {
+        grid.distance(Vector3Int::default(), self.delta(a, b))
+    }
+}
+
+#[cfg(test)]
+impl CurrentBoard {
+    /// A board with ground under each of `cells`, for tests that need no
+    /// world. The tile entities stand for tiles and are never spawned.
+    pub(crate) fn with_ground(cells: impl IntoIterator<Item = Vector3Int>) -> Self {
+        let ground = z_index(RenderLayerSlot::Ground, 0);
+        let tiles = (cells.into_iter().enumerate())
+            .map(|(i, v)| {
+                let floor = Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), ground));
+                (floor, Entity::from_raw(i as u32))
+            })
+            .collect();
+        CurrentBoard { tiles, ..default() }
+    }
+}
+
+/// Which board axes wrap around, joining opposite edges.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Wrap {
+    #[serde(default)]
+    pub x: bool,
+    #[serde(default)]
+    pub y: bool,
+}
+
+impl Wrap {
+    pub fn any(&self) -> bool {
+        self.x || self.y
+    }
+}
+
+/// The cells the board spans. The editor grows it when pasting past an edge.
+#[derive(Clone, Copy, Debug)]
+pub struct BoardBounds {
+    pub rect: GridRect,
+    pub wrap: Wrap,
+}
+
+impl Default for BoardBounds {
+    fn default() -> Self {
+        BoardBounds {
+            rect: GridRect::from_corners(
+                grid_to_position(GridKind::Square, 0, 31, 0),
+                grid_to_position(GridKind::Square, 31, 0, 0),
+            ),
+            wrap: Wrap::default(),
+        }
+    }
+}
+
+/// `v` with each wrapping coordinate brought inside `bounds`.
+pub fn wrap_position(v: Vector3Int, bounds: &BoardBounds) -> Vector3Int {
+    let BoardBounds { rect, wrap } = *bounds;
+    let wrapped = |c: i32, min: i32, size: i32, wraps: bool| {
+        if wraps && size > 0 {
+            min + (c - min).rem_euclid(size)
+        } else {
+            c
+        }
+    };
+    Vector3Int::new(
+        wrapped(v.x, rect.x, rect.width, wrap.x),
+        wrapped(v.y, rect.y, rect.height, wrap.y),
+        v.z,
+    )
+}
+
+#[derive(Default, Resource)]
+struct SceneHandle(Handle<Scene>);
+
+/// Replaces the current board with the map at `name`, behind a screen
+/// transition. Players keep their state; tiles, objects, NPCs and flags
+/// start over.
+pub struct LoadMapEvent {
+    pub name: String,
+}
+
+/// A map's tiles and objects have been spawned.
+pub struct BoardLoadedEvent;
+
+#[derive(Component)]
+pub struct Position {
+    pub v: Vector3Int,
+}
+
+#[derive(Component)]
+pub struct Tile {
+    pub i: usize,
+}
+
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    Loading,
+    Game,
+    /// The summary shown after reaching an exit, until it is dismissed.
+    LevelComplete,
+    Victory,
+    /// A map that cannot be played, with `MapError` saying why.
+    Error,
+}
+
+/// Why the map on the board cannot be played.
+#[derive(Resource, Default, Debug)]
+pub struct MapError(pub String);
+
+/// Everything the game adds on top of Bevy's default plugins. A
+/// `Campaign` inserted beforehand picks the maps played.
+pub struct GamePlugin;
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .init_resource::<AssetGroups>()
+            .init_resource::<SceneHandle>()
+            .init_resource::<CurrentBoard>()
+            .init_resource::<MapValidationReport>()
+            .init_resource::<GridProjection>()
+            .init_resource::<GridKind>()
+            .init_resource::<InputMap>()
+            .init_resource::<GameRng>()
+            .add_plugin(JsonAssetPlugin::<Scene>::new(&["json"]))
+            .add_plugin(JsonAssetPlugin::<AssetManifest>::new(&["assets.json"]))
+            .add_plugin(JsonAssetPlugin::<PrefabFile>::new(&["prefabs.json"]))
+            .add_plugin(JsonAssetPlugin::<RecipeFile>::new(&["recipes.json"]))
+            .add_plugin(JsonAssetPlugin::<WorldManifest>::new(&["world.json"]))
+            .add_plugin(JsonAssetPlugin::<LocaleStrings>::new(&["locale.json"]))
+            // Fixed ticks that gameplay systems run on.
+            .add_plugin(SimulationPlugin::default())
+            // Walls and the actors standing on each cell.
+            .add_plugin(CollisionPlugin)
+            // Player plugin.
+            .add_plugin(PlayerPlugin::default())
+            // Following players, and zoom presets on 1 to 4.
+            .add_plugin(CameraPlugin)
+            .add_plugin(CombatPlugin)
+            // Entities defined in data rather than code.
+            .add_plugin(PrefabsPlugin)
+            // Items left behind by the defeated and by broken tiles.
+            .add_plugin(LootPlugin)
+            // Shops that trade items for coins, opened by bumping into them.
+            .add_plugin(ShopPlugin)
+            // Items worn for their stats, and the inventory screen on I.
+            .add_plugin(EquipmentPlugin)
+            // Recipes made at stations, or by hand from the inventory screen.
+            .add_plugin(CraftingPlugin)
+            .add_plugin(StatusPlugin)
+            .add_plugin(ExplosionsPlugin)
+            .add_plugin(HazardsPlugin)
+            // Hits and blasts shove their victims back.
+            .add_plugin(KnockbackPlugin)
+            .add_plugin(HudPlugin)
+            // What the first player can do next to them, along the bottom.
+            .add_plugin(HintsPlugin)
+            // Quest flags and the map objects that read and write them.
+            .add_plugin(FlagsPlugin)
+            .add_plugin(ObjectsPlugin)
+            .add_plugin(PlatformsPlugin)
+            // Belts that carry whatever stands on them.
+            .add_plugin(ConveyorPlugin)
+            .add_plugin(PuzzlesPlugin)
+            .add_plugin(UndoPlugin)
+            // Steps the whole world back while R is held.
+            .add_plugin(RewindPlugin)
+            .add_plugin(SfxPlugin)
+            .add_plugin(MusicPlugin)
+            .add_plugin(TerrainPlugin)
+            // Butterflies and the like, around the view for atmosphere.
+            .add_plugin(AmbientPlugin)
+            // Tall grass and the like, stirred by whoever walks through.
+            .add_plugin(DecalsPlugin)
+            .add_plugin(NpcPlugin)
+            // Arrows and the like, a cell a tick.
+            .add_plugin(ProjectilesPlugin)
+            // Pets that trail a player.
+            .add_plugin(FollowersPlugin)
+            // Turn-based mode, on F4.
+            .add_plugin(TurnsPlugin)
+            // Slow motion and fast forward, on Ctrl+1 to Ctrl+4.
+            .add_plugin(SpeedPlugin)
+            .add_plugin(RegionsPlugin)
+            // Roofs that fade out while a player is inside.
+            .add_plugin(RoofsPlugin)
+            .add_plugin(ProgressionPlugin)
+            // Countdowns on maps with a time limit.
+            .add_plugin(TimerPlugin)
+            .add_plugin(EditorPlugin)
+            // Tooltips for whatever is under the cursor, while Tab is held.
+            .add_plugin(InspectPlugin)
+            // The route to the hovered cell.
+            .add_plugin(PathPreviewPlugin)
+            // Where enemies could strike next turn, on T in turn-based mode.
+            .add_plugin(ThreatsPlugin)
+            // Where players have walked, as breadcrumbs or a heatmap, on F11.
+            .add_plugin(TrailPlugin)
+            // Footprints left in snow and mud.
+            .add_plugin(FootprintsPlugin)
+            .add_plugin(MaterialsPlugin)
+            // Seasonal and other variants of the built-in sheet.
+            .add_plugin(TilesetSwapPlugin)
+            // Per-tile colours, and the teams owning tiles.
+            .add_plugin(TintPlugin)
+            // The map's name and author, its overrides and its ambient light.
+            .add_plugin(MapMetaPlugin)
+            // Fog over whatever no player can see.
+            .add_plugin(VisionPlugin)
+            .add_plugin(TerritoryPlugin)
+            // Hit flashes and selection outlines.
+            .add_plugin(FlashPlugin)
+            .add_plugin(WrapPlugin)
+            .add_plugin(ReplayPlugin)
+            // Chunked worlds, loaded around the players.
+            .add_plugin(StreamingPlugin)
+            // Save slots, on F5.
+            .add_plugin(SavesPlugin)
+            // Difficulty, kept in settings and saves, changed from the save menu.
+            .add_plugin(DifficultyPlugin)
+            // Run statistics, and past runs on F7.
+            .add_plugin(StatsPlugin)
+            // Colour-blind palettes and UI scale, on F6 and Shift+F6.
+            .add_plugin(AccessibilityPlugin)
+            // Player-facing text in each language, switched on F3.
+            .add_plugin(LocalePlugin)
+            // A report of where the players are stuck, on F10.
+            .add_plugin(DebugReportPlugin)
+            // Recent events in a side panel, on Shift+F7.
+            .add_plugin(EventLogPlugin)
+            // Console commands, and scripts of them run with `--exec`.
+            .add_plugin(ConsolePlugin)
+            // Fades between maps and screens.
+            .add_plugin(TransitionsPlugin)
+            // Entities kept between maps, and maps as they were left.
+            .add_plugin(PersistencePlugin)
+            // Signposts and area names placed on maps.
+            .add_plugin(LabelsPlugin)
+            // Flammable tiles catching fire and burning down.
+            .add_plugin(FirePlugin)
+            // Elements and resistances to them, and what fire and ice hits do.
+            .add_plugin(ElementsPlugin)
+            // A hash of the board for desync checks, logged on F1.
+            .add_plugin(WorldHashPlugin)
+            .add_event::<LoadMapEvent>()
+            .add_event::<BoardLoadedEvent>()
+            .init_resource::<MapError>()
+            .add_event::<TileChangedEvent>()
+            .add_simulation_event::<SetTileEvent>()
+            .add_system(
+                board::set_tiles
+                    .in_set(SimulationSet::End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            // Load assets.
+            .add_startup_system(load_assets)
+            // Load camera.
+            .add_startup_system(spawn_camera)
+            .add_system(check_asset_loading.in_set(OnUpdate(AppState::Loading)))
+            // Load scene once assets are done loading.
+            .add_system(load_scene.in_schedule(OnEnter(AppState::Game)))
+            .add_system(spawn_scene_renderer)
+            .add_system(load_map);
+        // Boards drawn as tilemaps, for maps too large for sprites.
+        #[cfg(feature = "ecs_tilemap")]
+        app.add_plugin(tilemap::TilemapRenderPlugin);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_assets(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut atlas: ResMut<Assets<TextureAtlas>>,
+    mut groups: ResMut<AssetGroups>,
+    mut scene: ResMut<SceneHandle>,
+    mut prefabs: ResMut<PrefabRegistry>,
+    mut recipes: ResMut<RecipeBook>,
+    mut locale: ResMut<Localization>,
+    campaign: Res<Campaign>,
+) {
+    let texture = server.load(SPRITE_SHEET);
+    let font = server.load("fonts/DejaVuSans.ttf");
+    prefabs.handle = server.load(PREFABS_FILE);
+    recipes.handle = server.load(RECIPES_FILE);
+
+    let mut core = AssetGroup::new(CORE_GROUP);
+    core.handles.push(texture.clone_untyped());
+    core.handles.push(font.clone_untyped());
+    core.handles.push(prefabs.handle.clone_untyped());
+    core.handles.push(recipes.handle.clone_untyped());
+    core.handles.extend(locale.load(&server));
+    groups.pending.push(core);
+    groups.queue_map(campaign.current_map(), &server, &mut scene);
+
+    let map = TextureAtlas::from_grid(texture, Vec2::splat(16.), 12, 11, None, None);
+    let handle = atlas.add(map);
+
+    // Add the graphic asset.
+    commands.insert_resource(GraphicsAssets {
+        sprite_texture: handle,
+        atlases: Vec::new(),
+        tile_names: TileRegistry::default(),
+        font,
+    });
+}
+
+/// Waits on the pending asset groups. A map's manifest names its scene and
+/// other files, and the scene its tilesets and sounds, so each adds to the
+/// map's group once loaded.
+#[allow(clippy::too_many_arguments)]
+fn check_asset_loading(
+    server: Res<AssetServer>,
+    mut groups: ResMut<AssetGroups>,
+    manifests: Res<Assets<AssetManifest>>,
+    worlds: Res<Assets<WorldManifest>>,
+    mut streamer: ResMut<ChunkStreamer>,
+    mut scene: ResMut<SceneHandle>,
+    scenes: Res<Assets<Scene>>,
+    mut graphics: ResMut<GraphicsAssets>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut grid: ResMut<GridKind>,
+    mut projection: ResMut<GridProjection>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut reported: Local<HashSet<bevy::asset::HandleId>>,
+) {
+    let mut loaded = true;
+    for group in groups.pending.iter() {
+        match server.get_group_load_state(group.ids()) {
+            LoadState::Loaded => {}
+            LoadState::Failed => {
+                loaded = false;
+                for handle in group.handles.iter() {
+                    if server.get_load_state(handle) == LoadState::Failed
+                        && reported.insert(handle.id())
+                    {
+                        let path = server.get_handle_path(handle);
+                        let file = path
+                            .as_ref()
+                            .map_or("?".into(), |p| p.path().display().to_string());
+                        error!("Failed to load `{}` in asset group `{}`.", file, group.name);
+                    }
+                }
+            }
+            _ => loaded = false,
+        }
+    }
+    if !loaded {
+        return;
+    }
+
+    if let Some(manifest) = groups.manifest.take() {
+        let Some(map) = groups.pending_map() else { return };
+        let Some(manifest) = manifests.get(&manifest) else {
+            error!("Asset group `{}` has no manifest.", map.name);
+            return;
+        };
+        scene.0 = server.load(manifest.scene.as_str());
+        map.handles.push(scene.0.clone_untyped());
+        for file in manifest.files() {
+            map.handles.push(server.load_untyped(file));
+        }
+        return;
+    }
+
+    // A world loads its start chunk as the map, and the rest as played.
+    if let Some(world) = groups.world.take() {
+        let Some(map) = groups.pending_map() else { return };
+        let Some(world) = worlds.get(&world) else {
+            error!("Asset group `{}` has no world manifest.", map.name);
+            return;
+        };
+        let Some(start) = world.start() else {
+            error!("World `{}` has no chunk \"0,0\" to start in.", map.name);
+            return;
+        };
+        scene.0 = server.load(start);
+        map.handles.push(scene.0.clone_untyped());
+        streamer.begin(world.clone());
+        return;
+    }
+
+    // Tileset images are only known once the scene itself has loaded, so
+    // queue them and keep waiting.
+    if graphics.atlases.is_empty() {
+        let scene = scenes.get(&scene.0);
+        // Set before anything is drawn on the new board.
+        *grid = scene.map_or_else(GridKind::default, |s| s.grid);
+        let pitch = scene.and_then(|s| s.meta.grid_pitch.filter(|p| *p > 0.));
+        let pitch = pitch.unwrap_or(TILE_SIZE);
+        *projection = match *grid {
+            GridKind::Hex { orientation } => GridProjection::Hex { orientation, pitch },
+            GridKind::Square => scene.map_or_else(GridProjection::default, |s| s.projection),
+        }
+        .with_pitch(pitch);
+        let Some(map) = groups.pending_map() else { return };
+        let sounds = scene.into_iter().flat_map(|s| s.sounds.values().cloned());
+        let music = scene.into_iter().flat_map(|s| s.music_tracks());
+        let fonts = scene.into_iter().flat_map(|s| s.label_fonts());
+        map.handles.extend(
+            (sounds.chain(music).chain(fonts)).map(|path| server.load_untyped(path.as_str())),
+        );
+        let tilesets = scene.map_or(&[][..], |s| &s.tilesets);
+        let tile_size = scene.and_then(|s| s.meta.tile_size);
+        if tilesets.is_empty() {
+            let default = (0..DEFAULT_TILE_COUNT, graphics.sprite_texture.clone());
+            graphics.atlases.push(default);
+        } else {
+            for tileset in tilesets {
+                let texture: Handle<Image> = server.load(tileset.image.as_str());
+                map.handles.push(texture.clone_untyped());
+                let extent = |size: Option<f32>| size.or(tile_size).unwrap_or(TILE_SIZE);
+                let columns = tileset.columns.max(1);
+                let rows = tileset.tilecount.div_ceil(columns);
+                let padding = (tileset.spacing > 0.).then(|| Vec2::splat(tileset.spacing));
+                let map = TextureAtlas::from_grid(
+                    texture,
+                    Vec2::new(extent(tileset.tilewidth), extent(tileset.tileheight)),
+                    columns,
+                    rows,
+                    padding,
+                    None,
+                );
+                graphics.atlases.push((tileset.range(), atlases.add(map)));
+                let first = tileset.range().start;
+                graphics.tile_names.add_sheet(first, &tileset.names);
+            }
+        }
+        return;
+    }
+
+    for group in groups.pending.iter() {
+        info!(
+            "Loaded {} assets in group `{}`.",
+            group.handles.len(),
+            group.name
+        );
+    }
+    groups.finish();
+    next_state.set(AppState::Game);
+}
+
+fn get_world_position(position: &Position, projection: &GridProjection) -> Vec3 {
+    projection.world(position.v)
+}
+
+/// `from` moved by whole boards along the wrapping axes to the copy of the
+/// board nearest `to`, so moving between them never crosses the board.
+pub fn nearest_copy(
+    from: Vec3,
+    to: Vec3,
+    bounds: &BoardBounds,
+    projection: &GridProjection,
+) -> Vec3 {
+    let BoardBounds { rect, wrap } = *bounds;
+    let d = projection.world_to_cell((to - from).truncate());
+    let boards = |d: f32, size: i32, wraps: bool| {
+        if wraps && size > 0 {
+            (d / size as f32).round() * size as f32
+        } else {
+            0.
+        }
+    };
+    let shift = Vec2::new(
+        boards(d.x, rect.width, wrap.x),
+        boards(d.y, rect.height, wrap.y),
+    );
+    from + projection.cell_to_world(shift).extend(0.)
+}
+
+/// The walkable layer a z-index belongs to.
+pub fn layer_of(z: i32) -> i32 {
+    z.div_euclid(LAYER_Z_STRIDE)
+}
+
+/// The z-index `offset` places above the floor of `layer`.
+pub fn layer_z(layer: i32, offset: i32) -> i32 {
+    layer * LAYER_Z_STRIDE + offset
+}
+
+/// Converts a column and row of the scene into a board position.
+/// Note: (0, 0) is the centre of a 32 x 32 scene, so the top-left cell is
+/// (-16, 16) whatever the scene's size.
+/// On hex grids the centred column and row are offset coordinates, which
+/// become axial ones.
+pub fn grid_to_position(grid: GridKind, col: i32, row: i32, z: i32) -> Vector3Int {
+    let (x, y) = (col - 16, 16 - row);
+    match grid {
+        GridKind::Square => Vector3Int::new(x, y, z),
+        GridKind::Hex { orientation } => {
+            let (q, r) = orientation.offset_to_axial(x, y);
+            Vector3Int::new(q, r, z)
+        }
+    }
+}
+
+/// Converts a board position back into a column and row of the scene,
+/// undoing `grid_to_position`.
+pub fn position_to_grid(grid: GridKind, v: Vector3Int) -> (i32, i32) {
+    let (x, y) = match grid {
+        GridKind::Square => (v.x, v.y),
+        GridKind::Hex { orientation } => orientation.axial_to_offset(v.x, v.y),
+    };
+    (x + 16, 16 - y)
+}
+
+/// Spawns the tiles of `scene` already drawn, in one batch, onto
+/// `current`, replacing its bounds. The tiles join the board together once
+/// the commands are applied. `atlas` gives the atlas and index within it
+/// that draw a tile index; indices it has none for are drawn as
+/// placeholders and listed in `report`, which starts over.
+#[allow(clippy::too_many_arguments)]
+pub fn rebuild_board(
+    commands: &mut Commands,
+    scene: &Scene,
+    grid: GridKind,
+    atlas: impl Fn(usize) -> Option<(Handle<TextureAtlas>, usize)>,
+    names: &TileRegistry,
+    projection: &GridProjection,
+    current: &mut CurrentBoard,
+    report: &mut MapValidationReport,
+) {
+    report.issues.clear();
+
+    let (width, height) = scene.size();
+    current.bounds.rect = GridRect::from_corners(
+        grid_to_position(GridKind::Square, 0, height as i32 - 1, 0),
+        grid_to_position(GridKind::Square, width as i32 - 1, 0, 0),
+    );
+    // Hex rows are offset, so the board does not tile as a rectangle.
+    let wrap = scene.meta().wrap.unwrap_or_default();
+    current.bounds.wrap = match grid {
+        GridKind::Hex { .. } if wrap.any() => {
+            warn!("Wrap-around is not supported on hex grids.");
+            Wrap::default()
+        }
+        _ => wrap,
+    };
+
+    let is_valid = |i| atlas(i).is_some();
+    let tiles = scene_tiles(scene, grid, IVec2::ZERO, is_valid, names, report);
+    spawn_tile_batch(commands, tiles, atlas, &scene.tile_metadata, projection);
+}
+
+/// The tiles of `scene` by cell and index, moved `origin.x` columns and
+/// `origin.y` rows, with tile names looked up in `names`. Names it does not
+/// have and indices `is_valid` rejects become placeholders and are added
+/// to `report`.
+fn scene_tiles(
+    scene: &Scene,
+    grid: GridKind,
+    origin: IVec2,
+    is_valid: impl Fn(usize) -> bool,
+    names: &TileRegistry,
+    report: &mut MapValidationReport,
+) -> Vec<(Vector3Int, usize)> {
+    let width = scene.width.max(1);
+    // Load scene layer by layer, increasing the z-index as we do. Layers
+    // past the ground band would draw among objects, so they are left out.
+    let ground = RenderLayerSlot::Ground.size() as usize;
+    if scene.layers.len() > ground {
+        warn!(
+            "Scene has {} layers; only the first {} are drawn.",
+            scene.layers.len(),
+            ground
+        );
+    }
+    let layers = (scene.layers.iter().take(ground).enumerate())
+        .map(|(z, l)| (z_index(RenderLayerSlot::Ground, z as i32), l));
+    // Overlays start the band of the walkable layer above the last.
+    let overlays = (scene.overlays.iter().enumerate()).map(|(i, l)| {
+        (
+            layer_z(i as i32 + 1, z_index(RenderLayerSlot::Ground, 0)),
+            l,
+        )
+    });
+    let roof = layer_z(0, z_index(RenderLayerSlot::Roof, 0));
+    let mut tiles = Vec::new();
+    for (z, layer) in layers.chain(overlays).chain([(roof, &scene.roof)]) {
+        for (pos, cell) in layer.iter().enumerate() {
+            // Map values are indices plus one, or palette entries from 1.
+            let palette = |value: usize| scene.palette.get(value - 1);
+            let index = match cell {
+                MapTile::Value(0) => continue,
+                MapTile::Value(value) if scene.palette.is_empty() => Some(value - 1),
+                MapTile::Value(value) => palette(*value).and_then(|name| names.index(name)),
+                MapTile::Name(name) => names.index(name),
+            };
+            // Calculate y from width.
+            let (x, y) = ((pos % width) as i32, (pos / width) as i32);
+            let (x, y) = (x + origin.x, y + origin.y);
+            let v = grid_to_position(grid, x, y, z);
+            let index = index.filter(|i| is_valid(*i)).unwrap_or_else(|| {
+                let tile = match cell {
+                    MapTile::Value(value) if !scene.palette.is_empty() => {
+                        palette(*value).map_or(cell.clone(), |name| MapTile::Name(name.clone()))
+                    }
+                    _ => cell.clone(),
+                };
+                report.issues.push(TileIssue {
+                    layer: z,
+                    x,
+                    y,
+                    tile,
+                });
+                PLACEHOLDER_TILE
+            });
+            tiles.push((v, index));
+        }
+    }
+    tiles
+}
+
+/// Spawns `tiles` with their sprites in a single batch, so that they are
+/// drawn from their first frame, and adds them to the board in one go.
+/// Tiles `metadata` gives a material are left for the materials plugin to
+/// draw, and the rest are drawn at the size and anchor it gives them.
+fn spawn_tile_batch(
+    commands: &mut Commands,
+    tiles: Vec<(Vector3Int, usize)>,
+    atlas: impl Fn(usize) -> Option<(Handle<TextureAtlas>, usize)>,
+    metadata: &HashMap<usize, TileMetadata>,
+    projection: &GridProjection,
+) {
+    let scrolling = |i| (metadata.get(&i)).is_some_and(TileMetadata::is_scrolling);
+    let mut drawn = Vec::new();
+    let mut missing = Vec::new();
+    let mut plain = Vec::new();
+    let cell = projection.tile_size();
+    for (v, i) in tiles {
+        let position = Position { v };
+        let transform = Transform::from_translation(get_world_position(&position, projection));
+        match atlas(i) {
+            // With tilemaps, tiles drawn from an atlas are left for them,
+            // or for the renderer to draw those they cannot.
+            Some(_) if scrolling(i) || cfg!(feature = "ecs_tilemap") => {
+                plain.push((position, Tile { i }))
+            }
+            Some((sheet, index)) => drawn.push((
+                position,
+                Tile { i },
+                tile_sheet_bundle(sheet, index, transform, sprite_shape(metadata, i, cell)),
+            )),
+            None => missing.push((
+                position,
+                Tile { i },
+                missing_tile_bundle(transform, projection),
+            )),
+        }
+    }
+
+    commands.add(move |world: &mut World| {
+        let cells: Vec<Vector3Int> = (drawn.iter().map(|(p, ..)| p.v))
+            .chain(missing.iter().map(|(p, ..)| p.v))
+            .chain(plain.iter().map(|(p, _)| p.v))
+            .collect();
+        let mut entities: Vec<Entity> = world.spawn_batch(drawn).collect();
+        entities.extend(world.spawn_batch(missing));
+        entities.extend(world.spawn_batch(plain));
+
+        let mut current = world.resource_mut::<CurrentBoard>();
+        current.tiles.extend(cells.into_iter().zip(entities));
+    });
+}
+
+/// Sets the flags of `scene`'s collision layer onto `collision`, moved
+/// as `scene_tiles` moves tiles.
+fn spawn_collision(scene: &Scene, grid: GridKind, origin: IVec2, collision: &mut CollisionMap) {
+    let width = scene.width.max(1);
+    let mut unknown = HashSet::new();
+    for (pos, value) in scene.collision.iter().enumerate() {
+        let flags = match (*value, scene.collision_flags.get(value)) {
+            (_, Some(flags)) => *flags,
+            (0, None) => continue,
+            (1, None) => CollisionFlags::SOLID,
+            (value, None) => {
+                // Walled off, so that a typo errs on the side of a wall.
+                unknown.insert(value);
+                CollisionFlags::SOLID
+            }
+        };
+        let (x, y) = ((pos % width) as i32, (pos / width) as i32);
+        let v = grid_to_position(grid, x + origin.x, y + origin.y, 0);
+        collision.set(v, flags);
+    }
+    if !unknown.is_empty() {
+        warn!(
+            "Collision values {:?} are not in `collision_flags`; treating them as walls.",
+            unknown
+        );
+    }
+}
+
+/// What a map's scene sets up besides its board: its terrain, rules,
+/// sounds, music and meta block.
+#[derive(SystemParam)]
+struct SceneSettings<'w> {
+    terrain: ResMut<'w, TerrainRegistry>,
+    status_rules: ResMut<'w, StatusRules>,
+    sounds: ResMut<'w, SfxLibrary>,
+    music: ResMut<'w, MusicLibrary>,
+    metadata: ResMut<'w, TileMetadataRegistry>,
+    meta: ResMut<'w, MapMeta>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl<'w> SceneSettings<'w> {
+    fn apply(&mut self, scene: &Scene) {
+        // Already loaded with the map's group, so these only look it up.
+        for path in scene.music_tracks() {
+            let track = self.asset_server.load(path.as_str());
+            self.music.insert(path.clone(), track);
+        }
+        *self.meta = scene.meta();
+        self.meta.log_unknown();
+        self.music.map_track = self.meta.default_music.clone();
+        // Tiles one map gives a terrain are plain on the next.
+        *self.terrain = TerrainRegistry::default();
+        self.terrain.extend(scene.terrain.clone());
+        // Rules one map overrides go back to the defaults on the next.
+        *self.status_rules = StatusRules::default();
+        self.status_rules.0.extend(scene.status_rules.clone());
+        self.metadata.0 = scene.tile_metadata.clone();
+        for (key, path) in scene.sounds.iter() {
+            let sound = self.asset_server.load(path);
+            self.sounds.insert(key.clone(), sound);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_scene(
+    mut commands: Commands,
+    scene: Res<SceneHandle>,
+    scenes: Res<Assets<Scene>>,
+    mut current: ResMut<CurrentBoard>,
+    mut collision: ResMut<CollisionMap>,
+    mut report: ResMut<MapValidationReport>,
+    mut settings: SceneSettings,
+    prefabs: Res<PrefabRegistry>,
+    grid: Res<GridKind>,
+    graphics: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    projection: Res<GridProjection>,
+    mut loaded: EventWriter<BoardLoadedEvent>,
+) {
+    // Left in its asset, so that loading the same map again finds it.
+    if let Some(scene) = scenes.get(&scene.0) {
+        rebuild_board(
+            &mut commands,
+            scene,
+            *grid,
+            |index| (graphics.tile_atlas(index, &atlases)).map(|(atlas, i)| (atlas.clone(), i)),
+            &graphics.tile_names,
+            &projection,
+            &mut current,
+            &mut report,
+        );
+        if !report.is_empty() {
+            warn!("Scene has {}", *report);
+        }
+        spawn_collision(scene, *grid, IVec2::ZERO, &mut collision);
+
+        objects::spawn_map_objects(&mut commands, &scene.objects, *grid, &prefabs, true);
+        settings.apply(scene);
+    }
+    loaded.send(BoardLoadedEvent);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn load_map(
+    mut commands: Commands,
+    mut covered: EventReader<TransitionCovered>,
+    server: Res<AssetServer>,
+    board: Query<Entity, (With<Position>, Without<Player>, Without<Persistent>)>,
+    mut current: ResMut<CurrentBoard>,
+    mut collision: ResMut<CollisionMap>,
+    mut flags: ResMut<GameFlags>,
+    mut territory: ResMut<Territory>,
+    mut groups: ResMut<AssetGroups>,
+    mut scene: ResMut<SceneHandle>,
+    mut sounds: ResMut<SfxLibrary>,
+    mut music: ResMut<MusicLibrary>,
+    mut graphics: ResMut<GraphicsAssets>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // Torn down once the screen is covered, out of sight.
+    let Some(name) = (covered.iter())
+        .filter_map(|event| match &event.cause {
+            TransitionCause::LoadMap(name) => Some(name),
+            TransitionCause::State(_) => None,
+        })
+        .last()
+    else {
+        return;
+    };
+    info!("Loading map `{}`.", name);
+
+    for entity in board.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    current.tiles.clear();
+    current.bounds = BoardBounds::default();
+    collision.clear();
+    flags.clear();
+    territory.clear();
+
+    // The previous map's group stays loaded until the new one is, so assets
+    // the maps share are not loaded twice.
+    groups.queue_map(name, &server, &mut scene);
+    // The new scene may bring its own tilesets, sounds and music.
+    graphics.atlases.clear();
+    graphics.tile_names.clear();
+    sounds.clear();
+    music.clear();
+    next_state.set(AppState::Loading);
+}
+
+/// Draws tiles from the atlas covering their index, again whenever the
+/// index changes. Indices outside every tileset get a placeholder. Tiles
+/// spawned already drawn, as a whole board is, are left as they are, and
+/// so are tiles a tilemap draws, with the `ecs_tilemap` feature.
+#[allow(clippy::type_complexity)]
+fn spawn_scene_renderer(
+    mut commands: Commands,
+    query: Query<
+        (
+            Entity,
+            Ref<Tile>,
+            &Position,
+            Option<&TextureAtlasSprite>,
+            Option<&Sprite>,
+        ),
+        Changed<Tile>,
+    >,
+    assets: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+    mut missing: Local<HashSet<usize>>,
+) {
+    for (entity, tile, position, sheet, sprite) in query.iter() {
+        if tile.is_added() && (sheet.is_some() || sprite.is_some()) {
+            continue;
+        }
+        let found = assets.tile_atlas(tile.i, &atlases);
+        // Tiles with a material are drawn by the materials plugin.
+        if found.is_some() && metadata.scrolling(tile.i).is_some() {
+            continue;
+        }
+        #[cfg(feature = "ecs_tilemap")]
+        if tilemap::draws(tile.i, found.is_some(), &metadata, &projection) {
+            continue;
+        }
+
+        let transform = Transform::from_translation(get_world_position(position, &projection));
+        let mut entity = commands.entity(entity);
+        entity.remove::<(Mesh2dHandle, Handle<ScrollingMaterial>)>();
+        if let Some((atlas, index)) = found {
+            let shape = sprite_shape(&metadata.0, tile.i, projection.tile_size());
+            entity.remove::<Sprite>().insert(tile_sheet_bundle(
+                atlas.clone(),
+                index,
+                transform,
+                shape,
+            ));
+        } else {
+            // Placeholders were already reported when the scene loaded.
+            if tile.i != PLACEHOLDER_TILE && missing.insert(tile.i) {
+                warn!("No tileset covers tile index {}.", tile.i);
+            }
+            entity
+                .remove::<(TextureAtlasSprite, Handle<TextureAtlas>)>()
+                .insert(missing_tile_bundle(transform, &projection));
+        }
+    }
+}
+
+/// A tile drawn `size` in size, with `anchor` on its cell's centre.
+fn tile_sheet_bundle(
+    atlas: Handle<TextureAtlas>,
+    index: usize,
+    transform: Transform,
+    (size, anchor): (Vec2, Anchor),
+) -> SpriteSheetBundle {
+    let mut sprite = TextureAtlasSprite::new(index);
+    sprite.custom_size = Some(size);
+    sprite.anchor = anchor;
+    SpriteSheetBundle {
+        sprite,
+        texture_atlas: atlas,
+        transform,
+        ..Default::default()
+    }
+}
+
+/// The placeholder drawn for tiles no tileset covers.
+fn missing_tile_bundle(transform: Transform, projection: &GridProjection) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: MISSING_TILE_COLOR,
+            custom_size: Some(projection.tile_size()),
+            ..default()
+        },
+        transform,
+        ..Default::default()
+    }
+}
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scale = CAMERA_SCALE;
+    commands.spawn(camera);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    /// Two tiles in the sheet, and a map naming and numbering others.
+    const BROKEN_SCENE: &str = r#"{
+        "width": 3,
+        "layers": [
+            [1, 2, 9, 0, 1, 1],
+            [0, "lava", 0, 0, 0, 3]
+        ]
+    }"#;
+
+    #[test]
+    fn broken_tiles_become_placeholders_and_are_reported() {
+        let scene: Scene = serde_json::from_str(BROKEN_SCENE).unwrap();
+        let mut world = World::new();
+        world.init_resource::<CurrentBoard>();
+        let mut queue = CommandQueue::default();
+        let mut current = CurrentBoard::default();
+        let mut report = MapValidationReport::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        rebuild_board(
+            &mut commands,
+            &scene,
+            GridKind::Square,
+            |i| (i < 2).then(|| (Handle::default(), i)),
+            &TileRegistry::default(),
+            &GridProjection::default(),
+            &mut current,
+            &mut report,
+        );
+        queue.apply(&mut world);
+
+        let found: Vec<(i32, i32, i32, String)> = (report.issues.iter())
+            .map(|issue| (issue.layer, issue.x, issue.y, issue.tile.to_string()))
+            .collect();
+        let upper = z_index(RenderLayerSlot::Ground, 1);
+        let expected = vec![
+            (0, 2, 0, "9".to_string()),
+            (upper, 1, 0, "`lava`".to_string()),
+            (upper, 2, 1, "3".to_string()),
+        ];
+        assert_eq!(found, expected);
+        let text = report.to_string();
+        assert!(text.starts_with("3 tiles no tileset covers:"));
+        assert!(text.contains("`lava` at (1, 0) on layer"));
+
+        // Every tile is on the board, the broken ones as placeholders.
+        let board = world.resource::<CurrentBoard>();
+        assert_eq!(board.tiles.len(), 7);
+        let placeholders = (board.tiles.values())
+            .filter(|e| world.get::<Tile>(**e).unwrap().i == PLACEHOLDER_TILE)
+            .count();
+        assert_eq!(placeholders, 3);
+    }
+
+    /// Ground with a fence, a curtain, pits and walls in its collision
+    /// layer. See `assets/fixtures/collision.json`.
+    const COLLISION_SCENE: &str = include_str!("../assets/fixtures/collision.json");
+
+    /// What the fixture's collision layer stops, as loaded.
+    fn fixture_collision() -> CollisionMap {
+        let scene: Scene = serde_json::from_str(COLLISION_SCENE).unwrap();
+        let mut collision = CollisionMap::default();
+        spawn_collision(&scene, GridKind::Square, IVec2::ZERO, &mut collision);
+        collision
+    }
+
+    /// How many cells block sound, and whether shots and sight by each
+    /// method get through, between cells either side of `v` along its row.
+    fn across(collision: &CollisionMap, v: Vector3Int) -> (u32, bool, [bool; 2]) {
+        let current = CurrentBoard::default();
+        let (west, east) = (v + Vector3Int::LEFT * 2, v + Vector3Int::RIGHT * 2);
+        let blockers = sfx::count_blockers(west, east, &current, collision);
+        let shot = projectiles::clear_shot(west, east, &current, collision);
+        let sight = [vision::SightMethod::Lines, vision::SightMethod::Shadowcasting].map(|method| {
+            let config = vision::VisionConfig { method };
+            vision::sees(&config, GridKind::Square, &current, collision, west, east)
+        });
+        (blockers, shot, sight)
+    }
+
+    #[test]
+    fn fences_stop_walkers_but_not_sound_sight_or_shots() {
+        let collision = fixture_collision();
+        let fence = Vector3Int::new(0, 6, 0);
+
+        assert_eq!(collision.flags(fence), CollisionFlags::BLOCK_WALK);
+        assert!(collision.is_blocked(fence));
+        assert!(!collision.is_pit(fence));
+        assert_eq!(across(&collision, fence), (0, true, [true, true]));
+    }
+
+    #[test]
+    fn curtains_stop_sound_and_sight_but_not_walkers() {
+        let collision = fixture_collision();
+        let curtain = Vector3Int::new(0, 8, 0);
+
+        assert_eq!(collision.flags(curtain), CollisionFlags::BLOCK_SIGHT);
+        assert!(!collision.is_blocked(curtain));
+        assert_eq!(across(&collision, curtain), (1, false, [false, false]));
+    }
+
+    #[test]
+    fn pits_stop_walkers_only() {
+        let collision = fixture_collision();
+        let pit = Vector3Int::new(2, 0, 0);
+
+        assert!(collision.is_pit(pit));
+        assert!(collision.is_blocked(pit));
+        assert!(!collision.blocks(pit, CollisionFlags::BLOCK_WALK));
+        assert!(!collision.is_blocked_for(pit, terrain::Capabilities::FLY));
+        assert_eq!(across(&collision, pit), (0, true, [true, true]));
+    }
+
+    #[test]
+    fn collision_values_are_legacy_walls_or_looked_up() {
+        let collision = fixture_collision();
+        let at = |x| collision.flags(Vector3Int::new(x, 12, 0));
+
+        assert_eq!(at(-4), CollisionFlags::NONE);
+        assert_eq!(at(-3), CollisionFlags::SOLID);
+        // 9 is not in the table, so it walls the cell off.
+        assert_eq!(at(-2), CollisionFlags::SOLID);
+        assert_eq!(collision.flags(Vector3Int::new(0, -1, 0)), CollisionFlags::PIT);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    sync::Mutex,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::SETTINGS_DIR,
+    storage::{platform_store, KeyValueStore},
+};
+
+/// Languages with a strings file, in the order F3 goes through them.
+pub const LANGUAGES: [&str; 2] = ["en", "fr"];
+const LOCALE_KEY: &str = "locale.ron";
+
+/// The file holding `language`'s strings.
+fn strings_path(language: &str) -> String {
+    format!("locale/{}.locale.json", language)
+}
+
+/// One language's player-facing strings, by key.
+#[derive(Deserialize, bevy::reflect::TypeUuid, Debug)]
+#[uuid = "9a4c1e7b-3d52-4f86-a0b9-6e2d8c5f1a37"]
+pub struct LocaleStrings(HashMap<String, String>);
+
+/// The language section of the player's settings, kept between sessions.
+/// F3 switches to the next language.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocaleSettings {
+    /// One of `LANGUAGES`.
+    pub language: String,
+}
+
+impl Default for LocaleSettings {
+    fn default() -> Self {
+        LocaleSettings {
+            language: LANGUAGES[0].to_string(),
+        }
+    }
+}
+
+/// Player-facing text in the chosen language, looked up by key with `t!`.
+/// Changes whenever the language does, for UI to be rebuilt.
+#[derive(Default, Resource)]
+pub struct Localization {
+    /// Every language's strings file, loaded with the core assets.
+    files: HashMap<String, Handle<LocaleStrings>>,
+    language: String,
+    strings: HashMap<String, String>,
+    /// Keys looked up this session that the language had no string for.
+    missing: Mutex<BTreeSet<String>>,
+}
+
+impl Localization {
+    /// Starts loading every language's strings, giving their handles.
+    pub fn load(&mut self, server: &AssetServer) -> Vec<HandleUntyped> {
+        self.files = (LANGUAGES.iter())
+            .map(|language| (language.to_string(), server.load(strings_path(language))))
+            .collect();
+        self.files.values().map(|h| h.clone_untyped()).collect()
+    }
+
+    /// `key`'s string, or the key itself if the language has none.
+    pub fn get(&self, key: &str) -> String {
+        if let Some(text) = self.strings.get(key) {
+            return text.clone();
+        }
+        // Nothing is missing before the strings have loaded.
+        if self.language.is_empty() {
+            return key.to_string();
+        }
+        let mut missing = self.missing.lock().unwrap();
+        if missing.insert(key.to_string()) {
+            warn!("No `{}` string for `{}`.", self.language, key);
+        }
+        key.to_string()
+    }
+
+    /// `key`'s string with each `{name}` in it replaced by that value in
+    /// `args`.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let mut text = self.get(key);
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+
+    /// Text written in map or prefab data, translated where the language
+    /// has it as a key, and shown as written otherwise.
+    pub fn data_text(&self, text: &str) -> String {
+        (self.strings.get(text)).map_or_else(|| text.to_string(), |s| s.clone())
+    }
+
+    /// Keys looked up without a string so far, for the debug report.
+    pub fn missing(&self) -> Vec<String> {
+        self.missing.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Looks `key` up in a `Localization`, filling in any `{name}`
+/// placeholders: `t!(locale, "saves.saved", slot = 2)`.
+#[macro_export]
+macro_rules! t {
+    ($locale:expr, $key:expr) => {
+        $locale.get($key)
+    };
+    ($locale:expr, $key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $locale.format(
+            $key,
+            &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),+],
+        )
+    };
+}
+
+pub struct LocalePlugin;
+impl Plugin for LocalePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings())
+            .init_resource::<Localization>()
+            .add_system(cycle_language)
+            .add_system(apply_language.after(cycle_language));
+    }
+}
+
+fn load_settings() -> LocaleSettings {
+    let text = match platform_store(SETTINGS_DIR).get(LOCALE_KEY) {
+        Ok(Some(text)) => text,
+        Ok(None) => return LocaleSettings::default(),
+        Err(e) => {
+            warn!("Could not read language settings: {}", e);
+            return LocaleSettings::default();
+        }
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        warn!("Ignoring malformed language settings: {}", e);
+        LocaleSettings::default()
+    })
+}
+
+fn cycle_language(keys: Res<Input<KeyCode>>, mut settings: ResMut<LocaleSettings>) {
+    if !keys.just_pressed(KeyCode::F3) {
+        return;
+    }
+    let i = (LANGUAGES.iter()).position(|l| *l == settings.language);
+    settings.language = LANGUAGES[i.map_or(0, |i| (i + 1) % LANGUAGES.len())].to_string();
+    info!("Language {}.", settings.language);
+}
+
+/// Takes up the chosen language's strings once loaded, again when they
+/// change on disk, and keeps the choice for the next session.
+fn apply_language(
+    settings: Res<LocaleSettings>,
+    mut events: EventReader<AssetEvent<LocaleStrings>>,
+    files: Res<Assets<LocaleStrings>>,
+    mut locale: ResMut<Localization>,
+) {
+    let Some(handle) = locale.files.get(&settings.language).cloned() else {
+        if settings.is_changed() {
+            warn!("There are no strings for language `{}`.", settings.language);
+        }
+        return;
+    };
+    let reloaded = events.iter().any(|event| match event {
+        AssetEvent::Created { handle: h } | AssetEvent::Modified { handle: h } => *h == handle,
+        AssetEvent::Removed { .. } => false,
+    });
+    if !settings.is_changed() && !reloaded {
+        return;
+    }
+
+    if let Some(file) = files.get(&handle) {
+        locale.language = settings.language.clone();
+        locale.strings = file.0.clone();
+    }
+    if settings.is_added() || !settings.is_changed() {
+        return;
+    }
+    let text = ron::ser::to_string_pretty(&*settings, ron::ser::PrettyConfig::default())
+        .expect("settings always serialize");
+    if let Err(e) = platform_store(SETTINGS_DIR).set(LOCALE_KEY, &text) {
+        warn!("Could not save language settings: {}", e);
+    }
+}
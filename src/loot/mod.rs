@@ -0,0 +1,343 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    board::BoardQuery,
+    combat::{self, DiedEvent},
+    difficulty::DifficultyModifiers,
+    explosions::{self, TileDestroyedEvent},
+    inventory::{Currency, Inventory, COIN_ITEM},
+    layer_of,
+    materials::TileMetadataRegistry,
+    player::PlayerStepCompleted,
+    prefabs::{spawn_prefab, Prefab, PrefabRegistry, PREFABS_FILE},
+    rng::{GameRng, DEFAULT_SEED},
+    simulation::{SimulationApp, SimulationSet},
+    terrain::Capabilities,
+    vectors::Vector3Int,
+    CurrentBoard, Position,
+};
+
+/// Cells from where loot fell that it scatters over before giving up on
+/// room for the rest.
+const SCATTER_STEPS: u32 = 4;
+
+fn one() -> u32 {
+    1
+}
+
+fn certain() -> f32 {
+    1.
+}
+
+fn single() -> [u32; 2] {
+    [1, 1]
+}
+
+/// One line of a loot table: between `count[0]` and `count[1]` of `item`,
+/// or nothing when `item` is left out, dropped with probability `chance`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct LootEntry {
+    #[serde(default)]
+    pub item: Option<String>,
+    /// How often it is picked relative to the other entries. Unused in
+    /// `always`.
+    #[serde(default = "one")]
+    pub weight: u32,
+    #[serde(default = "single")]
+    pub count: [u32; 2],
+    #[serde(default = "certain")]
+    pub chance: f32,
+}
+
+impl LootEntry {
+    fn roll(&self, rng: &mut GameRng, chance_scale: f32) -> Option<(&str, u32)> {
+        let item = self.item.as_deref()?;
+        if !rng.chance(self.chance * chance_scale) {
+            return None;
+        }
+        let [min, max] = self.count;
+        let count = min + rng.below((max.max(min) - min + 1) as usize) as u32;
+        (count > 0).then_some((item, count))
+    }
+}
+
+/// What something leaves behind: each entry of `always`, subject only to
+/// its chance, then `rolls` picks from `entries` by weight.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct LootTable {
+    #[serde(default)]
+    pub always: Vec<LootEntry>,
+    #[serde(default = "one")]
+    pub rolls: u32,
+    #[serde(default)]
+    pub entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    /// Each item dropped and how many, in the order first rolled, with the
+    /// chance of each multiplied by `chance_scale`.
+    pub fn roll(&self, rng: &mut GameRng, chance_scale: f32) -> Vec<(String, u32)> {
+        let mut drops: Vec<(String, u32)> = Vec::new();
+        let mut add = |item: &str, count: u32| match drops.iter_mut().find(|(i, _)| i == item) {
+            Some((_, total)) => *total += count,
+            None => drops.push((item.to_string(), count)),
+        };
+
+        for entry in self.always.iter() {
+            if let Some((item, count)) = entry.roll(rng, chance_scale) {
+                add(item, count);
+            }
+        }
+        let total: u32 = self.entries.iter().map(|e| e.weight).sum();
+        for _ in (0..self.rolls).take_while(|_| total > 0) {
+            let mut pick = rng.below(total as usize) as u32;
+            let entry = self.entries.iter().find(|e| {
+                if pick < e.weight {
+                    return true;
+                }
+                pick -= e.weight;
+                false
+            });
+            if let Some((item, count)) = entry.and_then(|e| e.roll(rng, chance_scale)) {
+                add(item, count);
+            }
+        }
+        drops
+    }
+}
+
+/// The loot table rolled when an entity is defeated.
+#[derive(Component, Clone, Debug)]
+pub struct Loot(pub LootTable);
+
+/// A dropped stack of `count` of the item `id`, which is drawn as the
+/// prefab of the same name.
+#[derive(Component, Clone, Debug)]
+pub struct Item {
+    pub id: String,
+    pub count: u32,
+}
+
+/// Sent when a player steps onto a dropped stack and picks it up. Coins go
+/// to their `Currency`, anything else to their `Inventory`.
+pub struct ItemCollectedEvent {
+    pub entity: Entity,
+    pub id: String,
+    pub count: u32,
+}
+
+#[derive(Debug)]
+pub enum LootError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// No prefab of this name has a loot table.
+    UnknownTable(String),
+}
+
+impl fmt::Display for LootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LootError::Io(e) => write!(f, "{}", e),
+            LootError::Format(e) => write!(f, "malformed prefabs: {}", e),
+            LootError::UnknownTable(name) => write!(f, "no prefab `{}` with loot", name),
+        }
+    }
+}
+
+/// The loot table of prefab `name`, read straight from the game's
+/// prefab file rather than through the asset server.
+pub fn load_table(name: &str) -> Result<LootTable, LootError> {
+    let text = fs::read_to_string(format!("assets/{}", PREFABS_FILE)).map_err(LootError::Io)?;
+    let prefabs: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&text).map_err(LootError::Format)?;
+    let Some(value) = prefabs.get(name) else {
+        return Err(LootError::UnknownTable(name.to_string()));
+    };
+    let prefab = Prefab::deserialize(value).map_err(LootError::Format)?;
+    prefab
+        .loot
+        .ok_or_else(|| LootError::UnknownTable(name.to_string()))
+}
+
+/// How often each item dropped over many rolls of one table.
+pub struct LootDistribution {
+    rolls: u32,
+    /// Rolls each item dropped in, and how many it dropped in all.
+    items: BTreeMap<String, (u32, u64)>,
+    /// Rolls that dropped nothing.
+    empty: u32,
+}
+
+impl LootDistribution {
+    /// Rolls `table` `rolls` times from the default seed, so the same
+    /// table always gives the same figures.
+    pub fn simulate(table: &LootTable, rolls: u32) -> Self {
+        let mut rng = GameRng::new(DEFAULT_SEED);
+        let mut distribution = LootDistribution {
+            rolls,
+            items: BTreeMap::new(),
+            empty: 0,
+        };
+        for _ in 0..rolls {
+            let drops = table.roll(&mut rng, 1.);
+            if drops.is_empty() {
+                distribution.empty += 1;
+            }
+            for (item, count) in drops {
+                let (times, total) = distribution.items.entry(item).or_default();
+                *times += 1;
+                *total += count as u64;
+            }
+        }
+        distribution
+    }
+}
+
+impl fmt::Display for LootDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rolls = self.rolls.max(1) as f64;
+        writeln!(f, "{} rolls:", self.rolls)?;
+        for (item, (times, total)) in self.items.iter() {
+            writeln!(
+                f,
+                "  {}: in {:.1}% of rolls, {:.3} per roll",
+                item,
+                *times as f64 / rolls * 100.,
+                *total as f64 / rolls
+            )?;
+        }
+        write!(f, "  nothing: {:.1}%", self.empty as f64 / rolls * 100.)
+    }
+}
+
+pub struct LootPlugin;
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_event::<ItemCollectedEvent>()
+            .add_system(
+                pick_up_items
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                drop_loot
+                    .after(combat::despawn_dead)
+                    .after(explosions::damage_tiles)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// Items and the occupiers in their way are kept per layer, as collision
+/// is.
+fn cell_key(v: Vector3Int) -> Vector3Int {
+    Vector3Int::new(v.x, v.y, layer_of(v.z))
+}
+
+/// Picks up every stack on the cells players arrive on. Stacks a player has
+/// no room for are left where they lie.
+fn pick_up_items(
+    mut commands: Commands,
+    mut steps: EventReader<PlayerStepCompleted>,
+    items: Query<(Entity, &Item, &Position)>,
+    mut holders: Query<(&mut Inventory, &mut Currency)>,
+    current: Res<CurrentBoard>,
+    mut collected: EventWriter<ItemCollectedEvent>,
+) {
+    // Despawns land later, so two players arriving together share nothing.
+    let mut taken = HashSet::new();
+    for step in steps.iter() {
+        let at = cell_key(current.wrap(step.at));
+        let mut holder = holders.get_mut(step.entity).ok();
+        for (entity, item, position) in items.iter() {
+            if cell_key(current.wrap(position.v)) != at || taken.contains(&entity) {
+                continue;
+            }
+            match holder.as_mut() {
+                Some((_, currency)) if item.id == COIN_ITEM => currency.0 += item.count,
+                Some((inventory, _)) if !inventory.has_room_for(&item.id) => {
+                    info!("No room to carry {}.", item.id);
+                    continue;
+                }
+                Some((inventory, _)) => {
+                    inventory.add(&item.id, item.count);
+                }
+                None => {}
+            }
+            taken.insert(entity);
+            info!("Picked up {} {}.", item.count, item.id);
+            collected.send(ItemCollectedEvent {
+                entity: step.entity,
+                id: item.id.clone(),
+                count: item.count,
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Rolls the loot of whatever was defeated or broken this tick, and
+/// scatters it one stack to a cell over the nearest free cells, starting
+/// with the one it fell on.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn drop_loot(
+    mut commands: Commands,
+    mut died: EventReader<DiedEvent>,
+    mut destroyed: EventReader<TileDestroyedEvent>,
+    dying: Query<(&Loot, &Position)>,
+    items: Query<&Position, With<Item>>,
+    metadata: Res<TileMetadataRegistry>,
+    registry: Res<PrefabRegistry>,
+    board: BoardQuery,
+    modifiers: Res<DifficultyModifiers>,
+    mut rng: ResMut<GameRng>,
+) {
+    let chance = modifiers.loot_chance;
+    // Where each drop fell, and the defeated occupier that no longer
+    // stands in the way there.
+    let mut falls: Vec<(Vector3Int, Option<Entity>, Vec<(String, u32)>)> = Vec::new();
+    for event in died.iter() {
+        if let Ok((Loot(table), position)) = dying.get(event.entity) {
+            falls.push((position.v, Some(event.entity), table.roll(&mut rng, chance)));
+        }
+    }
+    for event in destroyed.iter() {
+        let table = metadata.0.get(&event.index).and_then(|m| m.loot.as_ref());
+        if let Some(table) = table {
+            falls.push((event.position, None, table.roll(&mut rng, chance)));
+        }
+    }
+    if falls.iter().all(|(_, _, drops)| drops.is_empty()) {
+        return;
+    }
+
+    let current = board.current();
+    let mut taken: HashSet<Vector3Int> =
+        items.iter().map(|p| cell_key(current.wrap(p.v))).collect();
+    for (origin, vacated, drops) in falls {
+        let origin = current.wrap(origin);
+        // A broken floor leaves nothing to stand on, but loot still
+        // scatters from it.
+        let mut cells = (board.cells_near(origin, SCATTER_STEPS).into_iter())
+            .filter(|v| board.is_standable_by(*v, vacated, Capabilities::NONE));
+
+        for (item, count) in drops {
+            let Some(cell) = cells.by_ref().find(|v| !taken.contains(&cell_key(*v))) else {
+                warn!("No room near {:?} to drop {} {}.", origin, count, item);
+                break;
+            };
+            taken.insert(cell_key(cell));
+            if let Some(entity) = spawn_prefab(&mut commands, &registry, &item, cell) {
+                info!("Dropped {} {} at {:?}.", count, item, cell);
+                commands.entity(entity).insert(Item { id: item, count });
+            }
+        }
+    }
+}
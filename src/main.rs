@@ -1,199 +1,192 @@
-use std::collections::HashMap;
-
-use bevy::{asset::LoadState, prelude::*};
-use bevy_common_assets::json::JsonAssetPlugin;
-use player::PlayerPlugin;
-use vectors::Vector3Int;
-
-mod player;
-pub mod vectors;
-
-const TILE_SIZE: f32 = 16.;
-const TILE_Z: f32 = 0.;
-
-#[derive(serde::Deserialize, bevy::reflect::TypeUuid, Debug)]
-#[uuid = "413be529-bfeb-41b3-9db0-4b8b380a2c46"] // <-- keep me unique
-struct Scene {
-    layers: Vec<Vec<usize>>, // Corresponds to width * height.
-}
-
-#[derive(Default, Resource)]
-struct AssetList(pub Vec<HandleUntyped>);
-
-#[derive(Resource)]
-pub struct GraphicsAssets {
-    pub sprite_texture: Handle<TextureAtlas>,
+use bevy::prelude::*;
+#[cfg(feature = "net")]
+use map_test::net;
+use map_test::{
+    console::ConsoleScript,
+    loot::{self, LootDistribution},
+    prelude::{Difficulty, DifficultySettings, SimulationSpeed},
+    replay::{ReplayPlayback, ReplayRecorder},
+    tile_names,
+    tileset_swap::TilesetVariant,
+    transitions::{TransitionConfig, TransitionStyle},
+    Campaign, GamePlugin,
+};
+
+/// The command line, or on the web the page's query string read as one:
+/// `?map=iso.json&replay=file` is `iso.json --replay file`.
+#[cfg(not(target_arch = "wasm32"))]
+fn cli_args() -> Vec<String> {
+    std::env::args().skip(1).collect()
 }
 
-#[derive(Default, Resource)]
-pub struct CurrentBoard {
-    pub tiles: HashMap<Vector3Int, Entity>,
-}
-
-#[derive(Resource)]
-struct SceneHandle(Handle<Scene>);
-
-#[derive(Component)]
-struct Position {
-    pub v: Vector3Int,
+#[cfg(target_arch = "wasm32")]
+fn cli_args() -> Vec<String> {
+    let query = (web_sys::window())
+        .and_then(|window| window.location().search().ok())
+        .unwrap_or_default();
+    let Ok(params) = web_sys::UrlSearchParams::new_with_str(&query) else { return Vec::new() };
+    let mut args = Vec::new();
+    for flag in [
+        "record",
+        "replay",
+        "tileset",
+        "transition",
+        "speed",
+        "difficulty",
+    ] {
+        if let Some(value) = params.get(flag) {
+            args.push(format!("--{}", flag));
+            args.push(value);
+        }
+    }
+    args.extend(params.get("map"));
+    args
 }
 
-#[derive(Component)]
-struct Tile {
-    pub i: usize,
+/// `loot simulate <table> <n>` rolls the loot of the prefab named `table`
+/// `n` times and prints how often each item dropped, without starting
+/// the game.
+fn simulate_loot(args: &[String]) {
+    let [action, table, rolls] = args else {
+        panic!("Usage: loot simulate <table> <n>");
+    };
+    if action != "simulate" {
+        panic!("Unknown loot command `{}`.", action);
+    }
+    let rolls: u32 =
+        (rolls.parse()).unwrap_or_else(|e| panic!("Bad roll count `{}`: {}", rolls, e));
+    let table = loot::load_table(table)
+        .unwrap_or_else(|e| panic!("Could not read loot table `{}`: {}", table, e));
+    println!("{}", LootDistribution::simulate(&table, rolls));
 }
 
-#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
-pub enum AppState {
-    #[default]
-    Loading,
-    Game,
+/// `remap <map> <names>` prints `map` with its tiles named by the table
+/// in `names`, laid out as a tileset's `names`, for moving a map off raw
+/// indices before its sheet is laid out afresh.
+fn remap_map(args: &[String]) {
+    let [map, names] = args else {
+        panic!("Usage: remap <map> <names>");
+    };
+    let table = tile_names::load_table(names)
+        .unwrap_or_else(|e| panic!("Could not read tile names `{}`: {}", names, e));
+    let text =
+        std::fs::read_to_string(map).unwrap_or_else(|e| panic!("Could not read `{}`: {}", map, e));
+    let mut json: serde_json::Value =
+        (serde_json::from_str(&text)).unwrap_or_else(|e| panic!("Bad map `{}`: {}", map, e));
+    let report = tile_names::remap(&mut json, &table);
+    if !report.unnamed.is_empty() {
+        eprintln!("No names for tile indices {:?}.", report.unnamed);
+    }
+    eprintln!("Named {} tiles.", report.named);
+    let text = serde_json::to_string_pretty(&json).unwrap_or_default();
+    println!("{}", text);
 }
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
-        .add_state::<AppState>()
-        .init_resource::<AssetList>()
-        .init_resource::<CurrentBoard>()
-        .add_plugin(JsonAssetPlugin::<Scene>::new(&["json"]))
-        // Player plugin.
-        .add_plugin(PlayerPlugin)
-        // Load assets.
-        .add_startup_system(load_assets)
-        // Load camera.
-        .add_startup_system(spawn_camera)
-        .add_system(check_asset_loading.in_set(OnUpdate(AppState::Loading)))
-        // Load scene once assets are done loading.
-        .add_system(load_scene.in_schedule(OnEnter(AppState::Game)))
-        .add_system(spawn_scene_renderer)
-        .add_system(zoom_2d)
-        .run();
-}
-
-fn load_assets(
-    mut commands: Commands,
-    server: Res<AssetServer>,
-    mut atlas: ResMut<Assets<TextureAtlas>>,
-    mut assets: ResMut<AssetList>,
-) {
-    let scene = server.load("data.json");
-    let texture = server.load("tilemap_packed.png");
-
-    assets.0.push(scene.clone_untyped());
-    assets.0.push(texture.clone_untyped());
-
-    let map = TextureAtlas::from_grid(texture, Vec2::splat(16.), 12, 11, None, None);
-    let handle = atlas.add(map);
-
-    // Add the graphic asset.
-    commands.insert_resource(GraphicsAssets {
-        sprite_texture: handle,
-    });
-
-    // Add the data asset.
-    commands.insert_resource(SceneHandle(scene));
-}
-
-fn check_asset_loading(
-    server: Res<AssetServer>,
-    assets: Res<AssetList>,
-    mut next_state: ResMut<NextState<AppState>>,
-) {
-    match server.get_group_load_state(assets.0.iter().map(|a| a.id())) {
-        LoadState::Loaded => {
-            info!("Loaded {} assets.", assets.0.len());
-            next_state.set(AppState::Game);
-        }
-        LoadState::Failed => {
-            error!("Failed to load assets.");
-        }
+    // A map named on the command line, such as `iso.json`, is played alone.
+    // `--record file` saves the session's inputs on exit and `--replay file`
+    // plays them back. `--tileset image` draws the built-in sheet from a
+    // variant of it, whatever the maps ask for. `--transition fade|iris|wipe`
+    // picks how the screen changes between maps. `--speed 0.5` runs the
+    // simulation at half speed, or any other. `--difficulty easy|normal|hard`
+    // plays at that difficulty, whatever the settings say. With the `net`
+    // feature, `--host addr` waits for a second player to `--join addr`.
+    // `--exec file` runs the console commands in it, a line at a time, once
+    // the game starts.
+    let args = cli_args();
+    match args.split_first().map(|(a, rest)| (a.as_str(), rest)) {
+        Some(("loot", rest)) => return simulate_loot(rest),
+        Some(("remap", rest)) => return remap_map(rest),
         _ => {}
-    };
-}
-
-fn get_world_position(position: &Position) -> Vec3 {
-    Vec3::new(
-        TILE_SIZE * position.v.x as f32,
-        TILE_SIZE * position.v.y as f32,
-        position.v.z as f32,
-    )
-}
-
-fn load_scene(
-    mut commands: Commands,
-    scene: Res<SceneHandle>,
-    mut scenes: ResMut<Assets<Scene>>,
-    mut current: ResMut<CurrentBoard>,
-) {
-    if let Some(scene) = scenes.remove(scene.0.id()) {
-        // Load scene layer by layer, increasing the z-index as we do.
-        let mut z: i32 = 0;
-        for layer in scene.layers.iter() {
-            for (pos, i) in layer.iter().enumerate() {
-                // Calculate y from width.
-                // Note: (0, 0) is actually centered.
-                // In order to center the map which is 32 x 32
-                // we have to start at (-16, -16).
-                let index: i32 = (*i as i32) - 1;
-                if index >= 0 {
-                    let x = (pos as i32 % 32) - 16;
-                    let y = 16 - (pos as i32) / 32;
-
-                    let v = Vector3Int::new(x, y, z);
-                    let tile = commands
-                        .spawn((Position { v }, Tile { i: index as usize }))
-                        .id(); // Offset by 1.
-                    current.tiles.insert(v, tile);
-                }
-            }
+    }
+    let mut args = args.into_iter();
+    let (mut map, mut record, mut replay, mut tileset, mut transition, mut speed) =
+        (None, None, None, None, None, None);
+    let (mut exec, mut difficulty) = (None, None);
+    #[cfg(feature = "net")]
+    let (mut host, mut join) = (None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record = args.next(),
+            "--replay" => replay = args.next(),
+            "--tileset" => tileset = args.next(),
+            "--transition" => transition = args.next(),
+            "--speed" => speed = args.next(),
+            "--exec" => exec = args.next(),
+            "--difficulty" => difficulty = args.next(),
+            #[cfg(feature = "net")]
+            "--host" => host = args.next(),
+            #[cfg(feature = "net")]
+            "--join" => join = args.next(),
+            _ => map = Some(arg),
         }
     }
-}
-
-fn spawn_scene_renderer(
-    mut commands: Commands,
-    query: Query<(Entity, &Tile, &Position), Added<Tile>>,
-    assets: Res<GraphicsAssets>,
-) {
-    for (entity, tile, position) in query.iter() {
-        let mut sprite = TextureAtlasSprite::new(tile.i);
-        sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
-
-        let v = get_world_position(&position);
+    let mut campaign = map.map_or_else(Campaign::default, |map| Campaign {
+        maps: vec![map],
+        current: 0,
+    });
 
-        commands.entity(entity).insert(SpriteSheetBundle {
-            sprite,
-            texture_atlas: assets.sprite_texture.clone(),
-            transform: Transform::from_translation(v),
-            ..Default::default()
+    let mut app = App::new();
+    // On the web, fill the page's canvas rather than keeping a fixed size.
+    let window = WindowPlugin {
+        primary_window: Some(Window {
+            fit_canvas_to_parent: true,
+            ..default()
+        }),
+        ..default()
+    };
+    app.add_plugins(
+        DefaultPlugins
+            .set(ImagePlugin::default_nearest())
+            .set(window),
+    );
+    if let Some(path) = replay {
+        let playback = ReplayPlayback::load(&path)
+            .unwrap_or_else(|e| panic!("Could not play back `{}`: {}", path, e));
+        campaign = playback.campaign();
+        app.insert_resource(playback);
+    } else if let Some(path) = record {
+        app.insert_resource(ReplayRecorder::new(path, &campaign));
+    }
+    #[cfg(feature = "net")]
+    if let Some(addr) = host {
+        let (accept, port) =
+            net::listen(&addr).unwrap_or_else(|e| panic!("Could not host on `{}`: {}", addr, e));
+        println!("Hosting on port {}.", port);
+        app.insert_resource(net::NetHost::new(accept));
+    } else if let Some(addr) = join {
+        let link = net::ClientLink::connect(&addr)
+            .unwrap_or_else(|e| panic!("Could not join `{}`: {}", addr, e));
+        app.insert_resource(net::NetClient::new(link));
+    }
+    if let Some(path) = tileset {
+        app.insert_resource(TilesetVariant::preferring(&path));
+    }
+    if let Some(name) = transition {
+        let style = TransitionStyle::from_name(&name)
+            .unwrap_or_else(|| panic!("Unknown transition `{}`.", name));
+        app.insert_resource(TransitionConfig { style, ..default() });
+    }
+    if let Some(factor) = speed {
+        let factor =
+            (factor.parse()).unwrap_or_else(|e| panic!("Bad simulation speed `{}`: {}", factor, e));
+        app.insert_resource(SimulationSpeed::new(factor));
+    }
+    if let Some(name) = difficulty {
+        let difficulty = Difficulty::from_name(&name)
+            .unwrap_or_else(|| panic!("Unknown difficulty `{}`.", name));
+        app.insert_resource(DifficultySettings {
+            difficulty,
+            ..DifficultySettings::load()
         });
     }
-}
-
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle { ..default() });
-}
-
-fn setup_camera(mut commands: Commands) {
-    let mut camera = Camera2dBundle::default();
-    camera.transform.translation = Vec3::new(
-        4. * TILE_SIZE,
-        4. * TILE_SIZE,
-        camera.transform.translation.z,
-    );
-    commands.spawn(camera);
-}
-
-fn zoom_2d(mut q: Query<&mut OrthographicProjection, With<Camera>>) {
-    let mut projection = q.single_mut();
-
-    // example: zoom out
-    // projection.scale *= 5.;
-    // example: zoom in
-    projection.scale *= 0.9;
-
-    // always ensure you end up with sane values
-    // (pick an upper and lower bound for your application)
-    projection.scale = projection.scale.clamp(0.5, 5.0);
+    if let Some(path) = exec {
+        let script = ConsoleScript::load(&path)
+            .unwrap_or_else(|e| panic!("Could not read script `{}`: {}", path, e));
+        app.insert_resource(script);
+    }
+    app.insert_resource(campaign).add_plugin(GamePlugin);
+    #[cfg(feature = "net")]
+    app.add_plugin(net::NetPlugin);
+    app.run();
 }
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{ambient::AmbientSpawnConfig, tint::TileTint, CurrentBoard, Wrap};
+
+/// A map's `meta` block: what it is called and who made it, and settings
+/// that hold for as long as it is played. Every key is optional, and a map
+/// without the block keeps the defaults.
+#[derive(Resource, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MapMeta {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// The track played outside any region with its own.
+    pub default_music: Option<String>,
+    /// Multiplied into every tile's colour, as hex such as `"#8090c0"`.
+    pub ambient_color: Option<String>,
+    pub wrap: Option<Wrap>,
+    /// Seconds to finish the map in.
+    pub time_limit_s: Option<f32>,
+    /// Pixels a side of the tiles in the map's tilesets that give no size.
+    pub tile_size: Option<f32>,
+    /// World units between neighbouring cells on square and hex grids,
+    /// `TILE_SIZE` if not given. Tiles are drawn this size too unless their
+    /// metadata gives a `render_size`.
+    pub grid_pitch: Option<f32>,
+    /// Whether holding two directions at once steps diagonally. Square
+    /// grids only.
+    pub diagonals_allowed: bool,
+    /// Creatures that come and go around the view for atmosphere alone.
+    pub ambient_spawns: Vec<AmbientSpawnConfig>,
+    /// Keys this version does not know, logged once loaded.
+    #[serde(flatten)]
+    unknown: HashMap<String, serde_json::Value>,
+}
+
+impl MapMeta {
+    /// The map's name, or its asset path when it has none.
+    pub fn display_name(&self, path: &str) -> String {
+        self.name.clone().unwrap_or_else(|| path.to_string())
+    }
+
+    /// The ambient colour, white when unset or malformed.
+    pub fn ambient(&self) -> Color {
+        let Some(hex) = &self.ambient_color else { return Color::WHITE };
+        Color::hex(hex.trim_start_matches('#')).unwrap_or_else(|_| {
+            warn!("Map has malformed ambient color `{}`.", hex);
+            Color::WHITE
+        })
+    }
+
+    /// Lists the keys not understood, so that typos can be found.
+    pub fn log_unknown(&self) {
+        if !self.unknown.is_empty() {
+            let mut keys: Vec<&str> = self.unknown.keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            debug!("Map meta has unknown keys: {}.", keys.join(", "));
+        }
+    }
+}
+
+pub struct MapMetaPlugin;
+impl Plugin for MapMetaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapMeta>().add_system(apply_ambient);
+    }
+}
+
+/// Lights every tile with the map's ambient colour, again whenever the map
+/// or the board changes.
+pub fn apply_ambient(
+    mut commands: Commands,
+    meta: Res<MapMeta>,
+    current: Res<CurrentBoard>,
+    mut tints: Query<&mut TileTint>,
+) {
+    if !meta.is_changed() && !current.is_changed() {
+        return;
+    }
+    let light = meta.ambient();
+    for entity in current.tiles.values() {
+        match tints.get_mut(*entity) {
+            Ok(mut tint) => {
+                if tint.light != light {
+                    tint.light = light;
+                }
+            }
+            Err(_) if light != Color::WHITE => {
+                commands
+                    .entity(*entity)
+                    .insert(TileTint { light, ..default() });
+            }
+            Err(_) => {}
+        }
+    }
+}
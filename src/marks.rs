@@ -0,0 +1,112 @@
+//! Marks left on the board that fade away, such as breadcrumbs and
+//! footprints: a bounded queue of where each was stamped and which way it
+//! faces, drawn by a pool of sprites that are hidden and reused rather
+//! than despawned. Marks have no `Position`, so saves, the world hash and
+//! everything else reading the board pass them by.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{projection::GridProjection, vectors::Vector3Int};
+
+/// A mark at `at` in the world, turned by `rotation`, stamped `stamped`
+/// seconds after startup.
+#[derive(Clone, Copy, Debug)]
+pub struct Mark {
+    pub at: Vec3,
+    pub rotation: Quat,
+    pub stamped: f32,
+}
+
+/// The latest marks, oldest first. Past `capacity`, each new one takes the
+/// place of the oldest; by default none are kept until it is set.
+#[derive(Debug, Default)]
+pub struct Marks {
+    marks: VecDeque<Mark>,
+    pub capacity: usize,
+}
+
+impl Marks {
+    pub fn stamp(&mut self, mark: Mark) {
+        self.marks.push_back(mark);
+        self.trim();
+    }
+
+    /// Drops the oldest marks past `capacity`, as after it is lowered.
+    pub fn trim(&mut self) {
+        while self.marks.len() > self.capacity {
+            self.marks.pop_front();
+        }
+    }
+
+    /// Drops the marks `fade` seconds old or more by `now`.
+    pub fn expire(&mut self, now: f32, fade: f32) {
+        while (self.marks.front()).is_some_and(|mark| now - mark.stamped >= fade) {
+            self.marks.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.marks.clear();
+    }
+}
+
+/// The rotation turning a mark drawn facing up the screen to face along
+/// `dir` on the board, as `projection` draws it. None for no way at all.
+pub fn facing(dir: Vector3Int, projection: &GridProjection) -> Quat {
+    let way = projection.cell_to_world(Vec2::new(dir.x as f32, dir.y as f32));
+    match way == Vec2::ZERO {
+        true => Quat::IDENTITY,
+        false => Quat::from_rotation_z(Vec2::Y.angle_between(way)),
+    }
+}
+
+/// The sprites drawing one kind of mark, told apart by a marker `M`.
+pub type MarkSprites<'w, 's, M> = Query<
+    'w,
+    's,
+    (
+        &'static mut Sprite,
+        &'static mut Transform,
+        &'static mut Visibility,
+    ),
+    With<M>,
+>;
+
+/// Draws `marks` with `sprites`, each `color` faded out by its age over
+/// `fade` seconds, or never faded if `None`. Sprites left over are hidden,
+/// and more are spawned with `spawn`, from the sprite's colour and place,
+/// when they run short.
+pub fn draw_marks<M: Component, B: Bundle>(
+    commands: &mut Commands,
+    marks: &Marks,
+    now: f32,
+    fade: Option<f32>,
+    color: Color,
+    sprites: &mut MarkSprites<M>,
+    mut spawn: impl FnMut(Color, Transform) -> B,
+) {
+    let mut places = marks.marks.iter().map(|mark| {
+        let age = fade.map_or(0., |fade| (now - mark.stamped) / fade.max(f32::EPSILON));
+        let transform = Transform::from_translation(mark.at).with_rotation(mark.rotation);
+        (color.with_a(color.a() * (1. - age).max(0.)), transform)
+    });
+    for (mut sprite, mut transform, mut visibility) in sprites.iter_mut() {
+        match places.next() {
+            Some((color, place)) => {
+                sprite.color = color;
+                *transform = place;
+                *visibility = Visibility::Visible;
+            }
+            None => {
+                if *visibility != Visibility::Hidden {
+                    *visibility = Visibility::Hidden;
+                }
+            }
+        }
+    }
+    for (color, place) in places {
+        commands.spawn(spawn(color, place));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{AsBindGroup, ShaderRef},
+    sprite::{Anchor, Material2d, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{
+    collision::CollisionFlags, decals::ReactiveDecal, fire::Flammable, get_world_position,
+    loot::LootTable, projection::GridProjection, status::StatusEffect, vectors::Direction,
+    GraphicsAssets, Position, Tile, TILE_SIZE,
+};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TileMaterial {
+    /// The tile's image scrolls over time, as for flowing water.
+    Scrolling,
+}
+
+/// Rendering options and effects for the tiles with one atlas index.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TileMetadata {
+    /// Shown when the tile is inspected.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub material: Option<TileMaterial>,
+    /// Direction the image scrolls in, on the board.
+    #[serde(default)]
+    pub direction: [f32; 2],
+    /// Tiles per second.
+    #[serde(default)]
+    pub speed: f32,
+    /// Pixel rect (x, y, width, height) in the tileset image to draw,
+    /// instead of the tile's own cell.
+    #[serde(default)]
+    pub rect: Option<[f32; 4]>,
+    /// Put on whatever steps onto the tile, as a swamp slows.
+    #[serde(default)]
+    pub status: Option<StatusEffect>,
+    /// Explosions that destroy tiles remove it from the board.
+    #[serde(default)]
+    pub destructible: bool,
+    /// Rolled for items to leave behind once destroyed.
+    #[serde(default)]
+    pub loot: Option<LootTable>,
+    /// Belts carry whatever stands on the tile one cell this way.
+    #[serde(default)]
+    pub conveyor: Option<Direction>,
+    /// Catches fire from flames next to it, and burns down to another tile.
+    #[serde(default)]
+    pub flammable: Option<Flammable>,
+    /// What the tile stops, on top of anything the map's collision layer
+    /// sets for its cell.
+    #[serde(default)]
+    pub collision: CollisionFlags,
+    /// Only stepped onto, off or across going this way, as a ledge is
+    /// dropped down but not climbed.
+    #[serde(default)]
+    pub one_way: Option<Direction>,
+    /// Drawn over the lower half of whoever stands in the tile, and rustles
+    /// as they come and go, once the cell has been stepped through.
+    #[serde(default)]
+    pub decal_reactive: Option<ReactiveDecal>,
+    /// Keeps the footprints of whoever walks across it, as snow and mud do.
+    #[serde(default)]
+    pub imprintable: bool,
+    /// World units the tile is drawn at, for art larger than a cell, such
+    /// as a tree that reaches into the cell above. The cell's size if not
+    /// given. It still takes up its one cell.
+    #[serde(default)]
+    pub render_size: Option<[f32; 2]>,
+    /// World units the drawn tile's centre is moved from its cell's.
+    #[serde(default)]
+    pub anchor: [f32; 2],
+}
+
+impl TileMetadata {
+    /// Whether the tile is drawn with the scrolling material.
+    pub fn is_scrolling(&self) -> bool {
+        self.material == Some(TileMaterial::Scrolling)
+    }
+
+    /// The size to draw the tile at in a cell `cell` in size, and where on
+    /// it to anchor it to the cell's centre.
+    pub fn sprite_shape(&self, cell: Vec2) -> (Vec2, Anchor) {
+        let size = self.render_size.map_or(cell, Vec2::from);
+        let offset = Vec2::from(self.anchor);
+        let anchor = match offset == Vec2::ZERO {
+            true => Anchor::Center,
+            false => Anchor::Custom(-offset / size.max(Vec2::splat(f32::EPSILON))),
+        };
+        (size, anchor)
+    }
+}
+
+/// How tile `i` of `metadata` is drawn in a cell `cell` in size, filling it
+/// unless its metadata says otherwise.
+pub fn sprite_shape(
+    metadata: &HashMap<usize, TileMetadata>,
+    i: usize,
+    cell: Vec2,
+) -> (Vec2, Anchor) {
+    (metadata.get(&i)).map_or((cell, Anchor::Center), |m| m.sprite_shape(cell))
+}
+
+/// Tile metadata for the current scene, by atlas index.
+#[derive(Default, Resource)]
+pub struct TileMetadataRegistry(pub HashMap<usize, TileMetadata>);
+
+impl TileMetadataRegistry {
+    /// Metadata for tile `i`, if it is drawn with the scrolling material.
+    pub fn scrolling(&self, i: usize) -> Option<&TileMetadata> {
+        (self.0.get(&i)).filter(|m| m.is_scrolling())
+    }
+}
+
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "fdada9eb-f519-43fe-97bd-3097b005c086"]
+pub struct ScrollingMaterial {
+    /// Min corner and size of the drawn rect, in UVs of the image.
+    #[uniform(0)]
+    rect: Vec4,
+    #[uniform(0)]
+    velocity: Vec2,
+    #[texture(1)]
+    #[sampler(2)]
+    texture: Handle<Image>,
+}
+
+impl ScrollingMaterial {
+    fn new(atlas: &TextureAtlas, index: usize, metadata: &TileMetadata) -> Self {
+        let (min, size) = match metadata.rect {
+            Some([x, y, width, height]) => (Vec2::new(x, y), Vec2::new(width, height)),
+            None => {
+                let rect = atlas.textures[index];
+                (rect.min, rect.size())
+            }
+        };
+        let (min, size) = (min / atlas.size, size / atlas.size);
+        ScrollingMaterial {
+            rect: Vec4::new(min.x, min.y, size.x, size.y),
+            velocity: Vec2::from(metadata.direction).normalize_or_zero() * metadata.speed,
+            texture: atlas.texture.clone(),
+        }
+    }
+}
+
+/// Points the materials drawn from image `from` at `to` instead, as when
+/// the sheet they come from is swapped for a variant.
+pub fn retexture(
+    materials: &mut Assets<ScrollingMaterial>,
+    from: &Handle<Image>,
+    to: &Handle<Image>,
+) {
+    let ids: Vec<HandleId> = (materials.iter())
+        .filter(|(_, material)| material.texture == *from)
+        .map(|(id, _)| id)
+        .collect();
+    for id in ids {
+        if let Some(material) = materials.get_mut(&Handle::weak(id)) {
+            material.texture = to.clone();
+        }
+    }
+}
+
+impl Material2d for ScrollingMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/scrolling.wgsl".into()
+    }
+}
+
+/// The quad every material tile is drawn on.
+#[derive(Resource)]
+struct TileQuad(Mesh2dHandle);
+
+impl FromWorld for TileQuad {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let quad = shape::Quad::new(Vec2::splat(TILE_SIZE));
+        TileQuad(meshes.add(quad.into()).into())
+    }
+}
+
+pub struct MaterialsPlugin;
+impl Plugin for MaterialsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(Material2dPlugin::<ScrollingMaterial>::default())
+            .init_resource::<TileMetadataRegistry>()
+            .init_resource::<TileQuad>()
+            .add_system(render_material_tiles);
+    }
+}
+
+/// Draws tiles with a material in their metadata as meshes, sharing one
+/// material between the tiles of each index. Other tiles are left to the
+/// scene renderer.
+#[allow(clippy::too_many_arguments)]
+fn render_material_tiles(
+    mut commands: Commands,
+    query: Query<(Entity, &Tile, &Position), Changed<Tile>>,
+    registry: Res<TileMetadataRegistry>,
+    graphics: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    quad: Res<TileQuad>,
+    mut materials: ResMut<Assets<ScrollingMaterial>>,
+    mut cache: Local<HashMap<usize, Handle<ScrollingMaterial>>>,
+    projection: Res<GridProjection>,
+) {
+    // A new scene can change what each index means.
+    if registry.is_changed() {
+        cache.clear();
+    }
+
+    for (entity, tile, position) in query.iter() {
+        let Some(metadata) = registry.scrolling(tile.i) else { continue };
+        let Some((handle, index)) = graphics.tile_atlas(tile.i, &atlases) else { continue };
+        let Some(atlas) = atlases.get(handle) else { continue };
+
+        let material = cache
+            .entry(tile.i)
+            .or_insert_with(|| materials.add(ScrollingMaterial::new(atlas, index, metadata)))
+            .clone();
+        commands
+            .entity(entity)
+            .remove::<(TextureAtlasSprite, Handle<TextureAtlas>, Sprite)>()
+            .insert(MaterialMesh2dBundle {
+                mesh: quad.0.clone(),
+                material,
+                // The shared quad is one orthogonal tile; stretch it to fit.
+                transform: Transform::from_translation(get_world_position(position, &projection))
+                    .with_scale((projection.tile_size() / TILE_SIZE).extend(1.)),
+                ..default()
+            });
+    }
+}
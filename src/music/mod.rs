@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    player::Player,
+    regions::{Region, RegionEntered, RegionExited},
+};
+
+/// How long the map's own track takes to fade in when nothing says.
+const MAP_FADE_MS: u64 = 1000;
+
+fn default_fade_ms() -> u64 {
+    MAP_FADE_MS
+}
+
+/// Music played while a player is inside a region, in place of the map's
+/// own track, crossfading over `fade_ms` either way.
+#[derive(Component, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegionAudio {
+    pub track: String,
+    #[serde(default = "default_fade_ms")]
+    pub fade_ms: u64,
+}
+
+/// The map's music tracks by path, loaded with its asset group so that
+/// switching tracks never waits on the disk.
+#[derive(Default, Resource)]
+pub struct MusicLibrary {
+    tracks: HashMap<String, Handle<AudioSource>>,
+    /// Played outside any music region.
+    pub map_track: Option<String>,
+}
+
+impl MusicLibrary {
+    pub fn insert(&mut self, path: String, track: Handle<AudioSource>) {
+        self.tracks.insert(path, track);
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.map_track = None;
+    }
+}
+
+/// A playing track, at `volume` of full.
+struct Voice {
+    track: String,
+    sink: Handle<AudioSink>,
+    volume: f32,
+}
+
+#[derive(Default, Resource)]
+struct MusicPlayer {
+    /// The track fading in, or playing at full volume.
+    current: Option<Voice>,
+    /// Tracks fading out, stopped once silent.
+    fading: Vec<Voice>,
+    /// Seconds a full fade takes.
+    fade: f32,
+    /// Music regions players are in, the latest entered last.
+    inside: Vec<(Entity, String)>,
+}
+
+impl MusicPlayer {
+    /// Crossfades to `track`, or to silence. A fade already under way is
+    /// turned around rather than stacked: the track fading in starts to
+    /// fade out from where it got to, and one fading out comes back.
+    fn retarget(
+        &mut self,
+        track: Option<&str>,
+        fade_ms: u64,
+        library: &MusicLibrary,
+        audio: &Audio,
+    ) {
+        if self.current.as_ref().map(|v| v.track.as_str()) == track {
+            return;
+        }
+        self.fade = fade_ms as f32 / 1000.;
+        self.fading.extend(self.current.take());
+        let Some(track) = track else { return };
+
+        if let Some(i) = self.fading.iter().position(|v| v.track == track) {
+            self.current = Some(self.fading.remove(i));
+            return;
+        }
+        let Some(source) = library.tracks.get(track) else {
+            warn!("Music `{}` is not in the map's asset group.", track);
+            return;
+        };
+        let sink = audio.play_with_settings(source.clone(), PlaybackSettings::LOOP.with_volume(0.));
+        self.current = Some(Voice {
+            track: track.to_string(),
+            sink,
+            volume: 0.,
+        });
+    }
+}
+
+pub struct MusicPlugin;
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicLibrary>()
+            .init_resource::<MusicPlayer>()
+            .add_system(choose_music)
+            .add_system(fade_music.after(choose_music));
+    }
+}
+
+/// Follows players into and out of music regions, and onto new maps.
+fn choose_music(
+    mut entered: EventReader<RegionEntered>,
+    mut exited: EventReader<RegionExited>,
+    players: Query<(), With<Player>>,
+    regions: Query<(&Region, &RegionAudio)>,
+    library: Res<MusicLibrary>,
+    audio: Res<Audio>,
+    mut player: ResMut<MusicPlayer>,
+) {
+    let audio_of = |id: &str| (regions.iter()).find_map(|(r, a)| (r.id == id).then_some(a));
+    let mut changed = library.is_changed();
+    // Leaving the last music region fades back at the speed it came in.
+    let mut fade_ms = MAP_FADE_MS;
+
+    for event in exited.iter().filter(|e| players.contains(e.entity)) {
+        let before = player.inside.len();
+        (player.inside).retain(|(entity, id)| (*entity, id) != (event.entity, &event.region_id));
+        changed |= player.inside.len() != before;
+        if let Some(audio) = audio_of(&event.region_id) {
+            fade_ms = audio.fade_ms;
+        }
+    }
+    for event in entered.iter().filter(|e| players.contains(e.entity)) {
+        if audio_of(&event.region_id).is_some() {
+            player.inside.push((event.entity, event.region_id.clone()));
+            changed = true;
+        }
+    }
+    if !changed {
+        return;
+    }
+
+    // The region entered last wins while regions overlap.
+    let region = (player.inside.iter().rev()).find_map(|(_, id)| audio_of(id));
+    match region {
+        Some(region) => {
+            let region = region.clone();
+            player.retarget(Some(&region.track), region.fade_ms, &library, &audio);
+        }
+        None => {
+            let track = library.map_track.clone();
+            player.retarget(track.as_deref(), fade_ms, &library, &audio);
+        }
+    }
+}
+
+/// Steps every fade along, and stops tracks once faded out.
+fn fade_music(time: Res<Time>, sinks: Res<Assets<AudioSink>>, mut player: ResMut<MusicPlayer>) {
+    if player.current.is_none() && player.fading.is_empty() {
+        return;
+    }
+    let step = if player.fade > 0. {
+        time.delta_seconds() / player.fade
+    } else {
+        1.
+    };
+    let player = &mut *player;
+
+    if let Some(voice) = player.current.as_mut() {
+        voice.volume = (voice.volume + step).min(1.);
+        if let Some(sink) = sinks.get(&voice.sink) {
+            sink.set_volume(voice.volume);
+        }
+    }
+    player.fading.retain_mut(|voice| {
+        voice.volume = (voice.volume - step).max(0.);
+        let sink = sinks.get(&voice.sink);
+        if let Some(sink) = sink {
+            sink.set_volume(voice.volume);
+        }
+        if voice.volume > 0. {
+            return true;
+        }
+        if let Some(sink) = sink {
+            sink.stop();
+        }
+        false
+    });
+}
@@ -0,0 +1,562 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Mutex,
+    },
+    thread,
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    get_world_position,
+    input::{Action, DeviceInput, InputMap, RemoteInput},
+    player::{MoveTween, MovementState, Player, PlayerSettings},
+    progression::Campaign,
+    projection::GridProjection,
+    replay::LogicalClock,
+    saves::SavedPlayer,
+    simulation::{SimulationPaused, SimulationSet},
+    vectors::Vector3Int,
+    world_hash::{self, DesyncCheckEvent, WorldHash, DESYNC_CHECK_INTERVAL},
+    AppState, CurrentBoard, LoadMapEvent, Position, Tile,
+};
+
+pub use crate::world_hash::SyncStatus;
+
+/// The player a joining client controls on the host.
+pub const CLIENT_PLAYER: usize = 1;
+
+/// A board cell, as written in messages.
+pub type Cell = [i32; 3];
+
+/// What the authoritative host tells its client.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum HostMessage {
+    /// Every tile of the board, sent when a client joins and whenever the
+    /// host loads a map.
+    Board {
+        map: String,
+        tiles: Vec<(Cell, usize)>,
+    },
+    /// One cell's tile changed, or was removed with `None`.
+    TileChanged { cell: Cell, tile: Option<usize> },
+    /// Where the players stand after a simulation tick.
+    Tick {
+        tick: u64,
+        players: Vec<SavedPlayer>,
+    },
+    /// The host's world hash, every `DESYNC_CHECK_INTERVAL` ticks, sent
+    /// after that tick's `Tick`.
+    DesyncCheck(DesyncCheckEvent),
+}
+
+/// What a client tells its host.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ClientMessage {
+    /// The client's player pressed or released `action`.
+    Action { action: Action, down: bool },
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// One end of a connection. Messages are JSON, one per line, read and
+/// written on background threads so the game never blocks on the socket.
+pub struct Link<In, Out> {
+    incoming: Mutex<Receiver<In>>,
+    outgoing: Sender<Out>,
+}
+
+impl<In, Out> Link<In, Out>
+where
+    In: DeserializeOwned + Send + 'static,
+    Out: Serialize + Send + 'static,
+{
+    pub fn new(stream: TcpStream) -> Result<Self, NetError> {
+        stream.set_nodelay(true).map_err(NetError::Io)?;
+        let reader = stream.try_clone().map_err(NetError::Io)?;
+        let (incoming_tx, incoming) = mpsc::channel();
+        let (outgoing, outgoing_rx) = mpsc::channel::<Out>();
+
+        // Ends on a closed socket or a bad message, which the other side
+        // sees as a disconnect.
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                let Ok(message) = serde_json::from_str(&line) else { break };
+                if incoming_tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || {
+            let mut stream = stream;
+            for message in outgoing_rx {
+                let Ok(mut line) = serde_json::to_string(&message) else { continue };
+                line.push('\n');
+                if stream.write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+            }
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        });
+
+        Ok(Link {
+            incoming: Mutex::new(incoming),
+            outgoing,
+        })
+    }
+
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, NetError> {
+        Self::new(TcpStream::connect(addr).map_err(NetError::Io)?)
+    }
+
+    pub fn send(&self, message: Out) {
+        // A closed link shows up on the next `receive`.
+        let _ = self.outgoing.send(message);
+    }
+
+    /// Everything received since the last call, or `None` once the other
+    /// side has gone.
+    pub fn receive(&self) -> Option<Vec<In>> {
+        let incoming = self.incoming.lock().unwrap();
+        let mut messages = Vec::new();
+        loop {
+            match incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => return Some(messages),
+                Err(TryRecvError::Disconnected) => {
+                    return (!messages.is_empty()).then_some(messages)
+                }
+            }
+        }
+    }
+
+    /// Waits for the next message, or `None` once the other side has gone.
+    pub fn receive_blocking(&self) -> Option<In> {
+        self.incoming.lock().unwrap().recv().ok()
+    }
+}
+
+pub type HostLink = Link<ClientMessage, HostMessage>;
+pub type ClientLink = Link<HostMessage, ClientMessage>;
+
+/// Accepts clients on a background thread.
+pub fn listen(addr: impl ToSocketAddrs) -> Result<(Receiver<HostLink>, u16), NetError> {
+    let listener = TcpListener::bind(addr).map_err(NetError::Io)?;
+    let port = listener.local_addr().map_err(NetError::Io)?.port();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.map_err(NetError::Io).and_then(Link::new) {
+                Ok(link) => {
+                    if tx.send(link).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Could not accept a client: {}", e),
+            }
+        }
+    });
+    Ok((rx, port))
+}
+
+/// The tiles of a board by cell, as the host sent them.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct BoardSnapshot {
+    pub tiles: BTreeMap<Cell, usize>,
+}
+
+impl BoardSnapshot {
+    pub fn from_board(current: &CurrentBoard, tiles: &Query<&Tile>) -> Self {
+        let tiles = (current.tiles.iter())
+            .filter_map(|(v, entity)| Some(([v.x, v.y, v.z], tiles.get(*entity).ok()?.i)))
+            .collect();
+        BoardSnapshot { tiles }
+    }
+
+    /// Applies a board or tile message. Ticks leave the board alone.
+    pub fn apply(&mut self, message: &HostMessage) {
+        match message {
+            HostMessage::Board { tiles, .. } => self.tiles = tiles.iter().copied().collect(),
+            HostMessage::TileChanged {
+                cell,
+                tile: Some(i),
+            } => {
+                self.tiles.insert(*cell, *i);
+            }
+            HostMessage::TileChanged { cell, tile: None } => {
+                self.tiles.remove(cell);
+            }
+            HostMessage::Tick { .. } | HostMessage::DesyncCheck(_) => {}
+        }
+    }
+
+    /// A hash of every cell and tile, equal on both ends once in sync.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.tiles.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Hosting: the client's link once one joins, and the tiles it was last
+/// told about.
+#[derive(Resource)]
+pub struct NetHost {
+    accept: Mutex<Receiver<HostLink>>,
+    client: Option<HostLink>,
+    sent: HashMap<Entity, (Cell, usize)>,
+    /// Set when the whole board needs sending again.
+    resend: bool,
+}
+
+impl NetHost {
+    pub fn new(accept: Receiver<HostLink>) -> Self {
+        NetHost {
+            accept: Mutex::new(accept),
+            client: None,
+            sent: HashMap::new(),
+            resend: false,
+        }
+    }
+}
+
+/// Joined to a host, which runs the simulation for both players.
+#[derive(Resource)]
+pub struct NetClient {
+    link: ClientLink,
+    /// The host's board, until this side's copy of the map is in play.
+    pending: Option<BoardSnapshot>,
+    /// Set on entering the game, once the map here has loaded.
+    ready: bool,
+    held: HashSet<Action>,
+}
+
+impl NetClient {
+    pub fn new(link: ClientLink) -> Self {
+        NetClient {
+            link,
+            pending: None,
+            ready: false,
+            held: HashSet::new(),
+        }
+    }
+}
+
+/// Hosts or joins when a `NetHost` or `NetClient` is inserted before the
+/// plugin is added. Sessions are for two players.
+pub struct NetPlugin;
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        if app.world.contains_resource::<NetHost>() {
+            app.insert_resource(PlayerSettings { player_count: 2 })
+                .init_resource::<RemoteInput>()
+                .add_system(resend_board.in_schedule(OnEnter(AppState::Game)))
+                .add_systems(
+                    (accept_client, receive_actions, send_tiles)
+                        .chain()
+                        .in_set(OnUpdate(AppState::Game)),
+                )
+                .add_system(
+                    send_tick
+                        .in_set(SimulationSet::End)
+                        .in_schedule(CoreSchedule::FixedUpdate),
+                );
+        }
+        if app.world.contains_resource::<NetClient>() {
+            app.insert_resource(PlayerSettings { player_count: 2 })
+                .insert_resource(SimulationPaused(true))
+                .init_resource::<SyncStatus>()
+                .add_system(arm_client.in_schedule(OnEnter(AppState::Game)))
+                .add_systems(
+                    // After new tiles get their sprites, so that tiles the
+                    // host no longer has are not despawned beforehand.
+                    (
+                        receive_host.after(crate::spawn_scene_renderer),
+                        send_actions,
+                    )
+                        .in_set(OnUpdate(AppState::Game)),
+                )
+                // Once the tiles received are spawned.
+                .add_systems(
+                    (apply_system_buffers, world_hash::check_desync)
+                        .chain()
+                        .after(receive_host)
+                        .in_set(OnUpdate(AppState::Game)),
+                );
+        }
+    }
+}
+
+fn resend_board(mut host: ResMut<NetHost>) {
+    host.resend = true;
+}
+
+fn accept_client(mut host: ResMut<NetHost>, mut remote: ResMut<RemoteInput>) {
+    let joined = host.accept.lock().unwrap().try_iter().last();
+    if let Some(link) = joined {
+        info!("A client joined as player {}.", CLIENT_PLAYER + 1);
+        remote.release(CLIENT_PLAYER);
+        host.client = Some(link);
+        host.resend = true;
+    }
+}
+
+fn receive_actions(mut host: ResMut<NetHost>, mut remote: ResMut<RemoteInput>) {
+    let Some(link) = &host.client else { return };
+    let Some(messages) = link.receive() else {
+        info!("The client left.");
+        remote.release(CLIENT_PLAYER);
+        host.client = None;
+        return;
+    };
+    for ClientMessage::Action { action, down } in messages {
+        remote.set(CLIENT_PLAYER, action, down);
+    }
+}
+
+/// Sends the whole board when needed, otherwise the tiles that changed
+/// or moved since.
+#[allow(clippy::type_complexity)]
+fn send_tiles(
+    mut host: ResMut<NetHost>,
+    campaign: Res<Campaign>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    changed: Query<(Entity, &Tile, &Position), Or<(Changed<Tile>, Changed<Position>)>>,
+    mut removed: RemovedComponents<Tile>,
+) {
+    let host = &mut *host;
+    let Some(link) = &host.client else {
+        removed.clear();
+        return;
+    };
+
+    if host.resend {
+        host.resend = false;
+        removed.clear();
+        host.sent = (current.tiles.iter())
+            .filter_map(|(v, e)| Some((*e, ([v.x, v.y, v.z], tiles.get(*e).ok()?.i))))
+            .collect();
+        let snapshot = BoardSnapshot::from_board(&current, &tiles);
+        link.send(HostMessage::Board {
+            map: campaign.current_map().to_string(),
+            tiles: snapshot.tiles.into_iter().collect(),
+        });
+        return;
+    }
+
+    for entity in removed.iter() {
+        if let Some((cell, _)) = host.sent.remove(&entity) {
+            link.send(HostMessage::TileChanged { cell, tile: None });
+        }
+    }
+    for (entity, tile, position) in changed.iter() {
+        let cell = [position.v.x, position.v.y, position.v.z];
+        let previous = host.sent.insert(entity, (cell, tile.i));
+        if previous == Some((cell, tile.i)) {
+            continue;
+        }
+        // Moving platforms leave their old cell empty.
+        if let Some((from, _)) = previous.filter(|(from, _)| *from != cell) {
+            link.send(HostMessage::TileChanged {
+                cell: from,
+                tile: None,
+            });
+        }
+        link.send(HostMessage::TileChanged {
+            cell,
+            tile: Some(tile.i),
+        });
+    }
+}
+
+fn send_tick(
+    host: Res<NetHost>,
+    clock: Res<LogicalClock>,
+    players: Query<(&Player, &Position)>,
+    hash: WorldHash,
+) {
+    let Some(link) = &host.client else { return };
+    let players = (players.iter())
+        .map(|(player, position)| SavedPlayer {
+            index: player.index,
+            position: [position.v.x, position.v.y, position.v.z],
+            health: None,
+            effects: default(),
+            currency: default(),
+            inventory: default(),
+            equipment: default(),
+            action_points: None,
+        })
+        .collect();
+    link.send(HostMessage::Tick {
+        tick: clock.tick,
+        players,
+    });
+    if clock.tick.is_multiple_of(DESYNC_CHECK_INTERVAL) {
+        link.send(HostMessage::DesyncCheck(DesyncCheckEvent {
+            tick: clock.tick,
+            hash: hash.replicated(),
+        }));
+    }
+}
+
+fn arm_client(mut client: ResMut<NetClient>) {
+    client.ready = true;
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn receive_host(
+    mut commands: Commands,
+    client: Option<ResMut<NetClient>>,
+    mut campaign: ResMut<Campaign>,
+    mut loads: EventWriter<LoadMapEvent>,
+    mut checks: EventWriter<DesyncCheckEvent>,
+    mut paused: ResMut<SimulationPaused>,
+    mut current: ResMut<CurrentBoard>,
+    mut tiles: Query<&mut Tile>,
+    projection: Res<GridProjection>,
+    mut players: Query<(
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        Option<&mut MoveTween>,
+        Option<&mut Transform>,
+    )>,
+) {
+    let Some(mut client) = client else { return };
+    let Some(messages) = client.link.receive() else {
+        // Without a menu to return to, carry on alone from where the host
+        // left off.
+        warn!("Lost the connection to the host.");
+        commands.remove_resource::<NetClient>();
+        paused.0 = false;
+        return;
+    };
+
+    for message in messages {
+        match &message {
+            HostMessage::Board { map, .. } => {
+                let mut snapshot = BoardSnapshot::default();
+                snapshot.apply(&message);
+                client.pending = Some(snapshot);
+                if campaign.current_map() != map {
+                    *campaign = Campaign {
+                        maps: vec![map.clone()],
+                        current: 0,
+                    };
+                    loads.send(LoadMapEvent { name: map.clone() });
+                    client.ready = false;
+                }
+            }
+            HostMessage::TileChanged { cell, tile } => match &mut client.pending {
+                Some(pending) => pending.apply(&message),
+                None => set_tile(&mut commands, &mut current, &mut tiles, *cell, *tile),
+            },
+            HostMessage::Tick { players: saved, .. } => {
+                for (player, mut position, mut state, tween, transform) in players.iter_mut() {
+                    let Some(saved) = saved.iter().find(|p| p.index == player.index) else {
+                        continue;
+                    };
+                    let [x, y, z] = saved.position;
+                    let v = Vector3Int::new(x, y, z);
+                    if position.v == v {
+                        continue;
+                    }
+                    position.v = v;
+                    state.cancel_step();
+                    let world = get_world_position(&position, &projection);
+                    if let Some(mut tween) = tween {
+                        *tween = MoveTween::at(world);
+                    }
+                    if let Some(mut transform) = transform {
+                        transform.translation = world;
+                    }
+                }
+            }
+            HostMessage::DesyncCheck(check) => checks.send(*check),
+        }
+    }
+
+    if !client.ready {
+        return;
+    }
+    let Some(snapshot) = client.pending.take() else { return };
+    let stale: Vec<Cell> = (current.tiles.keys())
+        .map(|v| [v.x, v.y, v.z])
+        .filter(|cell| !snapshot.tiles.contains_key(cell))
+        .collect();
+    for cell in stale {
+        set_tile(&mut commands, &mut current, &mut tiles, cell, None);
+    }
+    for (cell, i) in snapshot.tiles {
+        set_tile(&mut commands, &mut current, &mut tiles, cell, Some(i));
+    }
+}
+
+/// Puts tile `tile` on `cell`, or removes the tile there for `None`.
+fn set_tile(
+    commands: &mut Commands,
+    current: &mut CurrentBoard,
+    tiles: &mut Query<&mut Tile>,
+    cell: Cell,
+    tile: Option<usize>,
+) {
+    let [x, y, z] = cell;
+    let v = Vector3Int::new(x, y, z);
+    match (tile, current.tiles.get(&v).copied()) {
+        (None, Some(entity)) => {
+            current.tiles.remove(&v);
+            commands.entity(entity).despawn_recursive();
+        }
+        (None, None) => {}
+        (Some(i), Some(entity)) => {
+            if let Ok(mut existing) = tiles.get_mut(entity) {
+                if existing.i != i {
+                    existing.i = i;
+                }
+            }
+        }
+        (Some(i), None) => {
+            let entity = commands.spawn((Position { v }, Tile { i })).id();
+            current.tiles.insert(v, entity);
+        }
+    }
+}
+
+/// Sends the local first player's presses and releases, which the host
+/// applies to `CLIENT_PLAYER`.
+fn send_actions(client: Option<ResMut<NetClient>>, devices: DeviceInput, map: Res<InputMap>) {
+    let Some(mut client) = client else { return };
+    let Some(set) = map.players.first() else { return };
+    for action in set.actions() {
+        let down = devices.pressed(0, action);
+        if down != client.held.contains(&action) {
+            if down {
+                client.held.insert(action);
+            } else {
+                client.held.remove(&action);
+            }
+            client.link.send(ClientMessage::Action { action, down });
+        }
+    }
+}
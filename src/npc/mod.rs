@@ -0,0 +1,526 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::BoardQuery,
+    collision::{covered_cells, CollisionMap, Footprint},
+    combat::{DamageEvent, DiedEvent},
+    difficulty::DifficultyModifiers,
+    elements::Element,
+    followers::{Follower, FollowerConfig},
+    knockback::{KnockbackEvent, HIT_KNOCKBACK},
+    layer_of, layer_z,
+    pathfinding::find_path,
+    player::Player,
+    prefabs::{spawn_prefab, PrefabRegistry},
+    projectiles::{clear_shot, fire_projectile, flight_path},
+    projection::GridProjection,
+    render_layers::{z_for, z_index, RenderLayerSlot},
+    rng::GameRng,
+    simulation::{SimulationApp, SimulationSet},
+    terrain::{actor_capabilities, CapabilitySources},
+    turns::{pay_points, ActionPoints, Turns},
+    vectors::{GridKind, Vector3Int},
+    vision::{sees, VisionConfig},
+    Position, TILE_SIZE,
+};
+
+/// Z-index of NPCs within their layer's band.
+pub const NPC_Z: i32 = z_index(RenderLayerSlot::Actors, 0);
+/// Seconds between an NPC's steps or attacks.
+pub const NPC_STEP_INTERVAL: f32 = 0.6;
+pub const NPC_ATTACK_DAMAGE: u32 = 1;
+/// How many cells a path search may visit before giving up.
+const PATH_SEARCH_LIMIT: usize = 512;
+pub const NPC_SPRITE: usize = 104;
+/// Spawners pause while every player is further away than this, in cells.
+pub const SPAWNER_ACTIVATION_DISTANCE: u32 = 16;
+/// Actions a ranged NPC spends between one shot and aiming the next.
+pub const RANGED_COOLDOWN_ACTIONS: u32 = 2;
+/// Cells a ranged NPC looks through for somewhere to back off to.
+const KITE_SEARCH_RADIUS: u32 = 4;
+/// Within the overlay band, under labels.
+const AIM_Z: f32 = 5.;
+/// Aim dot size as a fraction of a tile.
+const AIM_DOT_SCALE: f32 = 0.25;
+const AIM_COLOR: Color = Color::rgba(1., 0.2, 0.2, 0.7);
+
+/// Enemies: NPCs that chase or shoot at players.
+pub type Hostile = (
+    Or<(With<Chaser>, With<RangedAi>)>,
+    Without<Player>,
+    Without<Follower>,
+);
+
+/// Keeps an enemy from chasing, shooting or aiming until removed, as while
+/// a locked room the camera holds leaves it outside.
+#[derive(Component)]
+pub struct AiSuspended;
+
+/// Keeps up to `max_alive` of the prefab named `kind` around, spawning one
+/// every `interval_ms` on a free cell within `radius` cells.
+#[derive(Component)]
+pub struct Spawner {
+    pub kind: String,
+    pub max_alive: u32,
+    pub interval_ms: u64,
+    pub radius: u32,
+    pub activation_distance: u32,
+    timer: Timer,
+    alive: Vec<Entity>,
+}
+
+impl Spawner {
+    pub fn new(kind: &str, max_alive: u32, interval_ms: u64, radius: u32) -> Self {
+        Spawner {
+            kind: kind.to_string(),
+            max_alive,
+            interval_ms,
+            radius,
+            activation_distance: SPAWNER_ACTIVATION_DISTANCE,
+            timer: Timer::new(Duration::from_millis(interval_ms), TimerMode::Repeating),
+            alive: Vec::new(),
+        }
+    }
+}
+
+/// Sent when an NPC moves to a new cell, out of `from`.
+pub struct NpcSteppedEvent {
+    pub entity: Entity,
+    pub from: Vector3Int,
+}
+
+/// Walks towards the nearest player within `range` cells and attacks them
+/// once adjacent. Followers are fair game too if `FollowerConfig` says so.
+#[derive(Component)]
+pub struct Chaser {
+    pub range: i32,
+    pub timer: Timer,
+}
+
+impl Chaser {
+    pub fn new(range: i32) -> Self {
+        Chaser {
+            range,
+            timer: Timer::from_seconds(NPC_STEP_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Keeps between `min_range` and `max_range` cells from the nearest
+/// player it can see and shoots `projectile` prefabs at them, backing off
+/// from players who come too close and closing in on those too far or out
+/// of sight. Each shot is aimed one action ahead, shown as a line to the
+/// cell aimed at, and flies at that cell, so a player who moves in time is
+/// missed.
+#[derive(Component)]
+pub struct RangedAi {
+    pub min_range: i32,
+    pub max_range: i32,
+    pub projectile: String,
+    pub timer: Timer,
+    /// Actions left before it may aim again.
+    cooldown: u32,
+    /// The cell it aimed at on its last action, to shoot at on this one.
+    aim: Option<Vector3Int>,
+}
+
+impl RangedAi {
+    pub fn new(min_range: i32, max_range: i32, projectile: &str) -> Self {
+        RangedAi {
+            min_range,
+            max_range,
+            projectile: projectile.to_string(),
+            timer: Timer::from_seconds(NPC_STEP_INTERVAL, TimerMode::Repeating),
+            cooldown: 0,
+            aim: None,
+        }
+    }
+}
+
+/// One dot of the line a ranged NPC is aiming along.
+#[derive(Component)]
+struct AimDot {
+    owner: Entity,
+}
+
+pub struct NpcPlugin;
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_event::<NpcSteppedEvent>().add_systems(
+            (chase_players, shoot_players, run_spawners)
+                .chain()
+                .in_set(SimulationSet::Act)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn chase_players(
+    fixed: Res<FixedTime>,
+    turns: Turns,
+    board: BoardQuery,
+    players: Query<(Entity, &Position), With<Player>>,
+    followers: Query<(Entity, &Position), (With<Follower>, Without<Player>)>,
+    follower_config: Res<FollowerConfig>,
+    mut chasers: Query<
+        (
+            Entity,
+            &mut Chaser,
+            &mut Position,
+            Option<&Footprint>,
+            CapabilitySources,
+            Option<&Element>,
+            Option<&mut ActionPoints>,
+        ),
+        (Without<Player>, Without<Follower>, Without<AiSuspended>),
+    >,
+    mut damage: EventWriter<DamageEvent>,
+    mut knockback: EventWriter<KnockbackEvent>,
+    mut steps: EventWriter<NpcSteppedEvent>,
+    grid: Res<GridKind>,
+    modifiers: Res<DifficultyModifiers>,
+) {
+    let current = board.current();
+    for (entity, mut chaser, mut position, footprint, sources, element, points) in
+        chasers.iter_mut()
+    {
+        let (_, _, effects) = sources;
+        let capabilities = actor_capabilities(sources);
+        let interval = NPC_STEP_INTERVAL * effects.map_or(1., |e| e.interval_scale());
+        chaser.timer.set_duration(Duration::from_secs_f32(interval));
+        // Taking turns, each NPC acts once a tick on its turn, however slow,
+        // for as long as it can pay for what it does. Doing nothing passes.
+        let mut points = points.filter(|_| turns.queue.is_active());
+        if turns.queue.is_active() {
+            if !turns.queue.may_act(entity) {
+                continue;
+            }
+        } else if !chaser.timer.tick(fixed.period).just_finished() {
+            continue;
+        }
+
+        let distance = |anchor, target: &Position| {
+            covered_cells(anchor, footprint)
+                .iter()
+                .filter(|c| layer_of(c.z) == layer_of(target.v.z))
+                .map(|c| current.distance(*grid, *c, target.v))
+                .min()
+                .unwrap_or(i32::MAX)
+        };
+        let followers = followers
+            .iter()
+            .filter(|_| follower_config.targeted_by_enemies);
+        let Some((player, target)) = players
+            .iter()
+            .chain(followers)
+            .filter(|(_, p)| distance(position.v, p) <= chaser.range)
+            .min_by_key(|(_, p)| distance(position.v, p))
+        else {
+            continue;
+        };
+
+        if distance(position.v, target) == 1 {
+            if !pay_points(&mut points, turns.config.attack_cost) {
+                continue;
+            }
+            damage.send(DamageEvent {
+                entity: player,
+                amount: modifiers.enemy_damage(NPC_ATTACK_DAMAGE),
+                element: element.copied().unwrap_or_default(),
+            });
+            // Away from whichever of its cells the NPC struck from.
+            if let Some(from) = covered_cells(position.v, footprint)
+                .into_iter()
+                .find(|c| current.distance(*grid, *c, target.v) == 1)
+            {
+                knockback.send(KnockbackEvent::away(
+                    player,
+                    from,
+                    target.v,
+                    HIT_KNOCKBACK,
+                    current,
+                ));
+            }
+            continue;
+        }
+        if effects.is_some_and(|e| e.is_rooted()) {
+            continue;
+        }
+
+        // Every cell of the footprint has to fit at each step of the path,
+        // and it keeps to cheaper ground where that is no further.
+        let min_cost = board.min_step_cost(capabilities);
+        let path = find_path(
+            position.v,
+            |v| current.neighbours(v, *grid),
+            |v| distance(v, target) == 1,
+            |from, v| {
+                board.fits(entity, v, footprint, capabilities)
+                    && board.allows_step(from, v, footprint)
+            },
+            |_, v| board.step_cost(v, footprint, capabilities),
+            |v| cells_left(distance(v, target), 1) * min_cost,
+            PATH_SEARCH_LIMIT,
+        );
+        let next = path.and_then(|path| path.first());
+        let cost = |v| board.step_cost(v, footprint, capabilities);
+        if let Some(next) = next.filter(|v| pay_points(&mut points, cost(*v))) {
+            let from = std::mem::replace(&mut position.v, next);
+            steps.send(NpcSteppedEvent { entity, from });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn shoot_players(
+    mut commands: Commands,
+    fixed: Res<FixedTime>,
+    turns: Turns,
+    board: BoardQuery,
+    collision: Res<CollisionMap>,
+    prefabs: Res<PrefabRegistry>,
+    projection: Res<GridProjection>,
+    players: Query<(Entity, &Position), With<Player>>,
+    followers: Query<(Entity, &Position), (With<Follower>, Without<Player>)>,
+    follower_config: Res<FollowerConfig>,
+    mut archers: Query<
+        (
+            Entity,
+            &mut RangedAi,
+            &mut Position,
+            Option<&Footprint>,
+            CapabilitySources,
+            Option<&mut ActionPoints>,
+        ),
+        (Without<Player>, Without<Follower>, Without<AiSuspended>),
+    >,
+    dots: Query<(Entity, &AimDot)>,
+    mut steps: EventWriter<NpcSteppedEvent>,
+    grid: Res<GridKind>,
+    modifiers: Res<DifficultyModifiers>,
+    vision: Res<VisionConfig>,
+) {
+    let current = board.current();
+    // Lines aimed by archers that have since died.
+    for (dot, aim) in dots.iter() {
+        if !archers.contains(aim.owner) {
+            commands.entity(dot).despawn();
+        }
+    }
+    for (entity, mut archer, mut position, footprint, sources, points) in archers.iter_mut() {
+        let (_, _, effects) = sources;
+        let capabilities = actor_capabilities(sources);
+        let interval = NPC_STEP_INTERVAL * effects.map_or(1., |e| e.interval_scale());
+        archer.timer.set_duration(Duration::from_secs_f32(interval));
+        let mut points = points.filter(|_| turns.queue.is_active());
+        if turns.queue.is_active() {
+            if !turns.queue.may_act(entity) {
+                continue;
+            }
+        } else if !archer.timer.tick(fixed.period).just_finished() {
+            continue;
+        }
+
+        // Last action's aim is shot now, wherever its target has gone. On
+        // a turn, a shot it cannot pay for waits for the next.
+        if archer.aim.is_some() && !pay_points(&mut points, turns.config.attack_cost) {
+            continue;
+        }
+        if let Some(aim) = archer.aim.take() {
+            for (dot, _) in dots.iter().filter(|(_, d)| d.owner == entity) {
+                commands.entity(dot).despawn();
+            }
+            if clear_shot(position.v, aim, current, &collision) {
+                let path = flight_path(position.v, aim, archer.max_range, current);
+                fire_projectile(
+                    &mut commands,
+                    &prefabs,
+                    &archer.projectile,
+                    entity,
+                    modifiers.enemy_damage(NPC_ATTACK_DAMAGE),
+                    position.v,
+                    path,
+                );
+            }
+            archer.cooldown = RANGED_COOLDOWN_ACTIONS;
+            continue;
+        }
+        archer.cooldown = archer.cooldown.saturating_sub(1);
+
+        let distance = |from: Vector3Int, target: &Position| {
+            if layer_of(from.z) != layer_of(target.v.z) {
+                return i32::MAX;
+            }
+            current.distance(*grid, from, target.v)
+        };
+        let seen = |from: Vector3Int, target: &Position| {
+            sees(&vision, *grid, current, &collision, from, target.v)
+        };
+        // Anyone within twice its range is noticed.
+        let followers = followers
+            .iter()
+            .filter(|_| follower_config.targeted_by_enemies);
+        let Some((_, target)) = players
+            .iter()
+            .chain(followers)
+            .filter(|(_, p)| distance(position.v, p) <= archer.max_range * 2)
+            .min_by_key(|(_, p)| distance(position.v, p))
+        else {
+            continue;
+        };
+
+        let d = distance(position.v, target);
+        let in_band = (archer.min_range..=archer.max_range).contains(&d);
+        if in_band && seen(position.v, target) {
+            if archer.cooldown == 0 {
+                archer.aim = Some(target.v);
+                let line = flight_path(position.v, target.v, d, current);
+                for v in line {
+                    let place = (projection.world(v).truncate())
+                        .extend(z_for(RenderLayerSlot::Overlay, AIM_Z));
+                    commands.spawn((
+                        AimDot { owner: entity },
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: AIM_COLOR,
+                                custom_size: Some(Vec2::splat(TILE_SIZE * AIM_DOT_SCALE)),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(place),
+                            ..default()
+                        },
+                    ));
+                }
+                // The aim is shown for a whole turn before the shot.
+                if let Some(points) = points.as_mut() {
+                    points.pass();
+                }
+            }
+            continue;
+        }
+        if effects.is_some_and(|e| e.is_rooted()) {
+            continue;
+        }
+
+        // Too close, it backs off to the furthest cell nearby, up to its
+        // range, that still has a shot, the nearest of those if several.
+        let retreat = (d < archer.min_range).then(|| {
+            board
+                .cells_near(position.v, KITE_SEARCH_RADIUS)
+                .into_iter()
+                .rev()
+                .filter(|v| board.fits(entity, *v, footprint, capabilities) && seen(*v, target))
+                .map(|v| (distance(v, target).min(archer.max_range), v))
+                .max_by_key(|(score, _)| *score)
+        });
+        let retreat = match retreat {
+            Some(Some((score, v))) if score > d => Some(v),
+            Some(_) => continue,
+            None => None,
+        };
+        // Otherwise it closes in until it has a shot.
+        let min_cost = board.min_step_cost(capabilities);
+        let path = find_path(
+            position.v,
+            |v| current.neighbours(v, *grid),
+            |v| match retreat {
+                Some(to) => v == to,
+                None => distance(v, target) <= archer.max_range && seen(v, target),
+            },
+            |from, v| {
+                board.fits(entity, v, footprint, capabilities)
+                    && board.allows_step(from, v, footprint)
+            },
+            |_, v| board.step_cost(v, footprint, capabilities),
+            |v| {
+                let left = match retreat {
+                    Some(to) => cells_left(current.distance(*grid, v, to), 0),
+                    None => cells_left(distance(v, target), archer.max_range),
+                };
+                left * min_cost
+            },
+            PATH_SEARCH_LIMIT,
+        );
+        let next = path.and_then(|path| path.first());
+        let cost = |v| board.step_cost(v, footprint, capabilities);
+        if let Some(next) = next.filter(|v| pay_points(&mut points, cost(*v))) {
+            let from = std::mem::replace(&mut position.v, next);
+            steps.send(NpcSteppedEvent { entity, from });
+        }
+    }
+}
+
+/// Cells still to go from `distance` to within `reach`, for guessing what
+/// the rest of a path costs. Nothing where the distance is unknown, as on
+/// another layer, so that the guess never overshoots.
+fn cells_left(distance: i32, reach: i32) -> f32 {
+    match distance {
+        i32::MAX => 0.,
+        d => (d - reach).max(0) as f32,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_spawners(
+    mut commands: Commands,
+    fixed: Res<FixedTime>,
+    mut rng: ResMut<GameRng>,
+    mut died: EventReader<DiedEvent>,
+    board: BoardQuery,
+    players: Query<&Position, With<Player>>,
+    mut spawners: Query<(Entity, &mut Spawner, &Position)>,
+    grid: Res<GridKind>,
+    prefabs: Res<PrefabRegistry>,
+    modifiers: Res<DifficultyModifiers>,
+) {
+    let dead: Vec<Entity> = died.iter().map(|d| d.entity).collect();
+    // Cells claimed this tick, before occupancy catches up.
+    let mut taken: Vec<Vector3Int> = Vec::new();
+
+    for (entity, mut spawner, position) in spawners.iter_mut() {
+        spawner.alive.retain(|e| !dead.contains(e));
+
+        let near = players.iter().any(|p| {
+            board.current().distance(*grid, p.v, position.v) <= spawner.activation_distance as i32
+        });
+        if !near {
+            continue;
+        }
+
+        let interval = modifiers.spawn_interval(spawner.interval_ms);
+        spawner.timer.set_duration(interval);
+        if !spawner.timer.tick(fixed.period).just_finished()
+            || spawner.alive.len() >= modifiers.max_alive(spawner.max_alive) as usize
+        {
+            continue;
+        }
+        let Some(prefab) = prefabs.get(&spawner.kind) else {
+            warn!("Spawner has unknown prefab `{}`.", spawner.kind);
+            continue;
+        };
+
+        // Candidates are listed in a fixed order so `GameRng` picks the
+        // same cell for the same seed.
+        let footprint = prefab.footprint();
+        let z = layer_z(layer_of(position.v.z), NPC_Z);
+        let origin = Vector3Int::new(position.v.x, position.v.y, z);
+        let candidates: Vec<Vector3Int> = (board.cells_near(origin, spawner.radius).into_iter())
+            .filter(|v| {
+                board.fits(entity, *v, footprint.as_ref(), prefab.capabilities)
+                    && !(covered_cells(*v, footprint.as_ref()).iter())
+                        .any(|c| taken.iter().any(|t| t.manhattan(*c) == 0))
+            })
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+
+        let v = candidates[rng.below(candidates.len())];
+        taken.extend(covered_cells(v, footprint.as_ref()));
+        let actor = spawn_prefab(&mut commands, &prefabs, &spawner.kind, v);
+        spawner.alive.extend(actor);
+    }
+}
@@ -0,0 +1,638 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    camera::CameraLock,
+    collision::{covered_cells, covers, CollisionMap, Footprint, Occupier},
+    combat::{self, DiedEvent, Health},
+    elements::Element,
+    explosions::{BlastShape, Fuse},
+    fire::Ignites,
+    flags::{GameFlags, SetFlagEvent},
+    get_world_position, grid_to_position,
+    hazards::Hazard,
+    inspect::Inspectable,
+    labels::{AreaName, MapLabel},
+    layer_of, layer_z,
+    loot::Item,
+    materials::{sprite_shape, TileMetadataRegistry},
+    music::RegionAudio,
+    npc::{Chaser, Spawner, NPC_SPRITE, NPC_Z},
+    persistence::Persistent,
+    platforms::MovingTile,
+    player::{BumpEvent, Player, PlayerStepCompleted},
+    prefabs::PrefabRegistry,
+    progression::Exit,
+    projection::GridProjection,
+    puzzles::{PressurePlate, Pushable, PUSHABLE_Z},
+    regions::{Region, RegionMessage},
+    render_layers::{z_index, RenderLayerSlot},
+    roofs::RoofGroup,
+    shop::Shop,
+    simulation::SimulationSet,
+    timer::{ModifiesTimer, ModifyTimerEvent},
+    turns::Initiative,
+    vectors::{GridKind, Vector3Int},
+    GraphicsAssets, Position, Tile,
+};
+
+/// Z-index of map objects within their layer's band.
+pub const OBJECT_Z: i32 = z_index(RenderLayerSlot::Objects, 0);
+const DOOR_SPRITE: usize = 85;
+pub const DOOR_OPEN_SPRITE: usize = 74;
+const PUSHABLE_SPRITE: usize = 130;
+const PLATE_SPRITE: usize = 108;
+const EXIT_SPRITE: usize = 103;
+const BOMB_SPRITE: usize = 118;
+const SHOP_SPRITE: usize = 121;
+
+/// An object placed in the scene, positioned by column and row like the layers.
+/// Objects with a `width` and `height` cover that many cells, with `x` and
+/// `y` naming the top-left one.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct MapObject {
+    pub kind: String,
+    pub x: i32,
+    pub y: i32,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, serde_json::Value>,
+}
+
+impl MapObject {
+    /// The anchor cell, which is the bottom-left one for larger objects.
+    pub fn position(&self, grid: GridKind) -> Vector3Int {
+        let height = self.footprint().map_or(1, |Footprint(rect)| rect.height);
+        grid_to_position(grid, self.x, self.y + height - 1, OBJECT_Z)
+    }
+
+    /// A copy moved `col` columns and `row` rows, waypoints and all.
+    pub fn offset(&self, col: i32, row: i32) -> MapObject {
+        let mut object = self.clone();
+        object.x += col;
+        object.y += row;
+        let points = object.properties.get_mut("waypoints");
+        for point in points.and_then(|p| p.as_array_mut()).into_iter().flatten() {
+            let Some(point) = point.as_array_mut() else { continue };
+            for (value, by) in point.iter_mut().zip([col, row]) {
+                if let Some(c) = value.as_i64() {
+                    *value = (c + by as i64).into();
+                }
+            }
+        }
+        object
+    }
+
+    pub fn footprint(&self) -> Option<Footprint> {
+        let width = self.usize_prop("width").unwrap_or(1) as i32;
+        let height = self.usize_prop("height").unwrap_or(1) as i32;
+        (width > 1 || height > 1).then(|| Footprint::new(width, height))
+    }
+
+    pub fn str_prop(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).and_then(|v| v.as_str())
+    }
+
+    pub fn f32_prop(&self, key: &str) -> Option<f32> {
+        self.properties
+            .get(key)
+            .and_then(|v| v.as_f64())
+            .map(|v| v as f32)
+    }
+
+    pub fn bool_prop(&self, key: &str) -> Option<bool> {
+        self.properties.get(key).and_then(|v| v.as_bool())
+    }
+
+    pub fn usize_prop(&self, key: &str) -> Option<usize> {
+        self.properties
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+    }
+
+    /// The `element` property, physical where there is none. Unknown
+    /// elements are warned about and count as physical.
+    pub fn element(&self) -> Element {
+        let Some(name) = self.str_prop("element") else { return Element::Physical };
+        Element::from_name(name).unwrap_or_else(|| {
+            warn!("Object `{}` has unknown element `{}`.", self.kind, name);
+            Element::Physical
+        })
+    }
+
+    /// A property holding a JSON object, read as `T`.
+    pub fn json_prop<T: serde::de::DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Option<Result<T, serde_json::Error>> {
+        self.properties.get(key).map(T::deserialize)
+    }
+}
+
+/// Condition (a flag expression) that must hold for the object to react.
+#[derive(Component)]
+pub struct RequiresFlag(pub String);
+
+/// Flag set to true when the object fires.
+#[derive(Component)]
+pub struct SetsFlag(pub String);
+
+/// Fires when the player steps onto its cell.
+#[derive(Component)]
+pub struct Trigger {
+    pub once: bool,
+    pub consume: bool,
+}
+
+/// Blocks its cell until opened by bumping into it.
+#[derive(Component)]
+pub struct Door {
+    pub open: bool,
+    pub open_sprite: usize,
+}
+
+/// Moves whoever steps onto its cell onto another walkable layer, such as
+/// the deck at either end of a bridge.
+#[derive(Component)]
+pub struct LayerLink(pub i32);
+
+#[derive(Component)]
+pub struct ObjectSprite(pub usize);
+
+/// The object's place in its map's object list, which the map's memory
+/// refers to it by.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapObjectIndex(pub usize);
+
+pub struct ObjectsPlugin;
+impl Plugin for ObjectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_object_renderer)
+            .add_system(block_doors)
+            .add_system(sync_object_transforms)
+            .add_systems(
+                (step_triggers, open_doors)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                flag_kills
+                    .after(combat::despawn_dead)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// Spawns `objects`. Kinds other than the built-in ones name prefabs.
+/// `indexed` tags each with its `MapObjectIndex`, for whole maps; streamed
+/// chunks come and go, so are left out.
+pub fn spawn_map_objects(
+    commands: &mut Commands,
+    objects: &[MapObject],
+    grid: GridKind,
+    prefabs: &PrefabRegistry,
+    indexed: bool,
+) {
+    for (index, object) in objects.iter().enumerate() {
+        spawn_map_object(commands, object, grid, prefabs, indexed.then_some(index));
+    }
+}
+
+/// Spawns one map object, tagged with `index` as its `MapObjectIndex` if
+/// given.
+pub fn spawn_map_object(
+    commands: &mut Commands,
+    object: &MapObject,
+    grid: GridKind,
+    prefabs: &PrefabRegistry,
+    index: Option<usize>,
+) -> Entity {
+    let mut entity = commands.spawn(Position {
+        v: object.position(grid),
+    });
+    if let Some(index) = index {
+        entity.insert(MapObjectIndex(index));
+    }
+    if object.bool_prop("persistent").unwrap_or(false) {
+        entity.insert(Persistent);
+    }
+
+    if let Some(expr) = object.str_prop("requires_flag") {
+        entity.insert(RequiresFlag(expr.to_string()));
+    }
+    if let Some(flag) = object.str_prop("sets_flag") {
+        entity.insert(SetsFlag(flag.to_string()));
+    }
+    if let Some(footprint) = object.footprint() {
+        entity.insert(footprint);
+    }
+    let (delta, freeze) = (
+        object.f32_prop("add_time_s"),
+        object.f32_prop("freeze_time_s"),
+    );
+    if delta.is_some() || freeze.is_some() {
+        entity.insert(ModifiesTimer {
+            delta: delta.unwrap_or(0.),
+            freeze: freeze.unwrap_or(0.),
+        });
+    }
+    // Prefabs name themselves, and hidden objects are not given away.
+    let shown = match object.kind.as_str() {
+        "door" | "pushable" | "pressure_plate" | "npc" | "exit" | "bomb" | "shop" => true,
+        "trigger" | "hazard" | "spawner" => object.usize_prop("sprite").is_some(),
+        _ => false,
+    };
+    if shown {
+        let name = match object.str_prop("name") {
+            Some(name) => name.to_string(),
+            None => object.kind.replace('_', " "),
+        };
+        entity.insert(Inspectable::new(name));
+    }
+
+    match object.kind.as_str() {
+        "trigger" => {
+            entity.insert(Trigger {
+                once: object.bool_prop("once").unwrap_or(true),
+                consume: object.bool_prop("consume").unwrap_or(false),
+            });
+            if let Some(sprite) = object.usize_prop("sprite") {
+                entity.insert(ObjectSprite(sprite));
+            }
+        }
+        "door" => {
+            let sprite = object.usize_prop("sprite").unwrap_or(DOOR_SPRITE);
+            entity.insert((
+                Door {
+                    open: false,
+                    open_sprite: object.usize_prop("open_sprite").unwrap_or(DOOR_OPEN_SPRITE),
+                },
+                ObjectSprite(sprite),
+            ));
+        }
+        "hazard" => {
+            entity.insert(Hazard {
+                damage: object.usize_prop("damage").unwrap_or(1) as u32,
+                element: object.element(),
+            });
+            if object.bool_prop("ignites").unwrap_or(false) {
+                entity.insert(Ignites);
+            }
+            if let Some(sprite) = object.usize_prop("sprite") {
+                entity.insert(ObjectSprite(sprite));
+            }
+        }
+        "pushable" => {
+            entity.insert((
+                Position {
+                    v: grid_to_position(grid, object.x, object.y, PUSHABLE_Z),
+                },
+                Pushable,
+                Occupier,
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(PUSHABLE_SPRITE)),
+            ));
+        }
+        "pressure_plate" => {
+            entity.insert((
+                PressurePlate::default(),
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(PLATE_SPRITE)),
+            ));
+        }
+        "moving_tile" => {
+            // Moving tiles are part of the board rather than objects on it.
+            entity.insert((
+                Position {
+                    v: grid_to_position(grid, object.x, object.y, 0),
+                },
+                Tile {
+                    i: object.usize_prop("sprite").unwrap_or(0),
+                },
+                MovingTile::from_object(object, grid),
+            ));
+        }
+        "npc" => {
+            let v = object.position(grid);
+            entity.insert((
+                Position {
+                    v: Vector3Int::new(v.x, v.y, layer_z(0, NPC_Z)),
+                },
+                Occupier,
+                Health::new(object.usize_prop("health").unwrap_or(1) as u32),
+                Chaser::new(object.usize_prop("range").unwrap_or(8) as i32),
+                Initiative {
+                    value: object.usize_prop("initiative").unwrap_or(0) as i32,
+                },
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(NPC_SPRITE)),
+            ));
+        }
+        "spawner" => {
+            let mut spawner = Spawner::new(
+                object.str_prop("actor").unwrap_or_default(),
+                object.usize_prop("max_alive").unwrap_or(1) as u32,
+                object.usize_prop("interval_ms").unwrap_or(5000) as u64,
+                object.usize_prop("radius").unwrap_or(2) as u32,
+            );
+            if let Some(distance) = object.usize_prop("activation_distance") {
+                spawner.activation_distance = distance as u32;
+            }
+            entity.insert(spawner);
+            if let Some(sprite) = object.usize_prop("sprite") {
+                entity.insert(ObjectSprite(sprite));
+            }
+        }
+        "region" => {
+            let id = object.str_prop("id").unwrap_or_default();
+            entity.insert(Region {
+                id: id.to_string(),
+                players_only: object.bool_prop("players_only").unwrap_or(false),
+            });
+            if let Some(message) = object.str_prop("message") {
+                entity.insert(RegionMessage(message.to_string()));
+            }
+            if let Some(name) = object.str_prop("area_name") {
+                entity.insert(AreaName(name.to_string()));
+            }
+            if object.bool_prop("camera_lock").unwrap_or(false) {
+                entity.insert(CameraLock);
+            }
+            match object.json_prop::<RegionAudio>("audio") {
+                Some(Ok(audio)) => {
+                    entity.insert(audio);
+                }
+                Some(Err(e)) => warn!("Region `{}` has malformed audio: {}.", id, e),
+                None => {}
+            }
+            match object.json_prop::<RoofGroup>("roof") {
+                Some(Ok(roof)) => {
+                    entity.insert(roof);
+                }
+                Some(Err(e)) => warn!("Region `{}` has a malformed roof: {}.", id, e),
+                None => {}
+            }
+        }
+        "exit" => {
+            entity.insert((
+                Exit,
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(EXIT_SPRITE)),
+            ));
+        }
+        "bomb" => {
+            let fuse_ms = object.usize_prop("fuse_ms").unwrap_or(3000) as u64;
+            let mut fuse = Fuse::new(
+                Duration::from_millis(fuse_ms),
+                object.usize_prop("radius").unwrap_or(2) as u32,
+                object.usize_prop("damage").unwrap_or(2) as u32,
+            );
+            fuse.destroys_tiles = object.bool_prop("destroys_tiles").unwrap_or(true);
+            fuse.knockback = object.usize_prop("knockback").unwrap_or(2) as u32;
+            fuse.element = object.element();
+            if object.bool_prop("walls_block").unwrap_or(true) {
+                fuse.shape = BlastShape::Flood;
+            }
+            entity.insert((
+                fuse,
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(BOMB_SPRITE)),
+            ));
+        }
+        "shop" => {
+            let stock = match object.json_prop("stock") {
+                Some(Ok(stock)) => stock,
+                Some(Err(e)) => {
+                    warn!(
+                        "Shop at ({}, {}) has malformed stock: {}.",
+                        object.x, object.y, e
+                    );
+                    Vec::new()
+                }
+                None => Vec::new(),
+            };
+            entity.insert((
+                Shop { stock },
+                Occupier,
+                ObjectSprite(object.usize_prop("sprite").unwrap_or(SHOP_SPRITE)),
+            ));
+        }
+        "item" => {
+            // A stack lying on the map from the start, picked up as dropped
+            // loot is.
+            let id = object.str_prop("item").unwrap_or_default();
+            match prefabs.get(id) {
+                Some(prefab) => {
+                    prefab.insert(&mut entity, object.position(grid));
+                    entity.insert(Item {
+                        id: id.to_string(),
+                        count: object.usize_prop("count").unwrap_or(1) as u32,
+                    });
+                }
+                None => warn!("Item object has unknown item `{}`.", id),
+            }
+        }
+        "label" => {
+            entity.insert(MapLabel::from_object(object));
+        }
+        "layer_link" => {
+            entity.insert(LayerLink(object.usize_prop("layer").unwrap_or(0) as i32));
+        }
+        kind => match prefabs.get(kind) {
+            Some(prefab) => prefab.insert(&mut entity, object.position(grid)),
+            None => warn!("Unknown map object kind `{}`.", kind),
+        },
+    }
+    entity.id()
+}
+
+pub fn spawn_object_renderer(
+    mut commands: Commands,
+    query: Query<(Entity, &ObjectSprite, &Position, Option<&Footprint>), Added<ObjectSprite>>,
+    assets: Res<GraphicsAssets>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+) {
+    let cell = projection.sprite_size();
+    for (entity, object, position, footprint) in query.iter() {
+        let mut sprite = TextureAtlasSprite::new(object.0);
+        let (size, anchor) = sprite_shape(&metadata.0, object.0, cell);
+        sprite.custom_size = Some(size);
+        sprite.anchor = anchor;
+        if let Some(Footprint(rect)) = footprint {
+            // One sprite over the whole rect, anchored on the centre of the
+            // anchor cell so it lines up with `get_world_position`.
+            let size = Vec2::new(rect.width as f32, rect.height as f32);
+            let anchor = (Vec2::new(-rect.x as f32, -rect.y as f32) + 0.5) / size - 0.5;
+            sprite.custom_size = Some(size * cell);
+            sprite.anchor = Anchor::Custom(anchor);
+        }
+
+        let v = get_world_position(position, &projection);
+
+        commands.entity(entity).insert(SpriteSheetBundle {
+            sprite,
+            texture_atlas: assets.sprite_texture.clone(),
+            transform: Transform::from_translation(v),
+            ..Default::default()
+        });
+    }
+}
+
+/// Snaps objects moved by pushes or undo onto their new cell.
+#[allow(clippy::type_complexity)]
+fn sync_object_transforms(
+    mut query: Query<(&Position, &mut Transform), (With<ObjectSprite>, Changed<Position>)>,
+    projection: Res<GridProjection>,
+) {
+    for (position, mut transform) in query.iter_mut() {
+        transform.translation = get_world_position(position, &projection);
+    }
+}
+
+fn block_doors(
+    query: Query<(&Door, &Position, Option<&Footprint>), Added<Door>>,
+    mut collision: ResMut<CollisionMap>,
+) {
+    for (door, position, footprint) in query.iter() {
+        if !door.open {
+            for cell in covered_cells(position.v, footprint) {
+                collision.block(cell);
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn step_triggers(
+    mut commands: Commands,
+    mut steps: EventReader<PlayerStepCompleted>,
+    triggers: Query<(
+        Entity,
+        &Trigger,
+        &Position,
+        Option<&RequiresFlag>,
+        Option<&SetsFlag>,
+        Option<&ModifiesTimer>,
+    )>,
+    flags: Res<GameFlags>,
+    mut events: EventWriter<SetFlagEvent>,
+    mut timer: EventWriter<ModifyTimerEvent>,
+) {
+    let arrivals: Vec<Vector3Int> = steps.iter().map(|step| step.at).collect();
+    for (entity, trigger, position, requires, sets, modifies) in triggers.iter() {
+        if !arrivals
+            .iter()
+            .any(|at| at.manhattan(position.v) == 0 && layer_of(at.z) == layer_of(position.v.z))
+        {
+            continue;
+        }
+        if let Some(RequiresFlag(expr)) = requires {
+            if !flags.check(expr) {
+                continue;
+            }
+        }
+
+        if let Some(SetsFlag(flag)) = sets {
+            events.send(SetFlagEvent::new(flag, true));
+        }
+        if let Some(modifies) = modifies {
+            timer.send(ModifyTimerEvent {
+                delta: modifies.delta,
+                freeze: modifies.freeze,
+            });
+        }
+
+        if trigger.consume {
+            commands.entity(entity).despawn();
+        } else if trigger.once {
+            commands.entity(entity).remove::<Trigger>();
+        }
+    }
+}
+
+/// Sets the flags of tagged creatures as they are defeated. Runs after
+/// `despawn_dead` sends the `DiedEvent`, relying on its despawn being
+/// deferred so the creature's `SetsFlag` can still be read.
+fn flag_kills(
+    mut died: EventReader<DiedEvent>,
+    tagged: Query<&SetsFlag>,
+    mut events: EventWriter<SetFlagEvent>,
+) {
+    for event in died.iter() {
+        if let Ok(SetsFlag(flag)) = tagged.get(event.entity) {
+            events.send(SetFlagEvent::new(flag, true));
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn open_doors(
+    mut bumps: EventReader<BumpEvent>,
+    players: Query<(), With<Player>>,
+    mut doors: Query<(
+        &mut Door,
+        &Position,
+        Option<&Footprint>,
+        &mut TextureAtlasSprite,
+        Option<&RequiresFlag>,
+        Option<&SetsFlag>,
+    )>,
+    flags: Res<GameFlags>,
+    mut collision: ResMut<CollisionMap>,
+    mut events: EventWriter<SetFlagEvent>,
+) {
+    for bump in bumps.iter().filter(|b| players.contains(b.entity)) {
+        for (mut door, position, footprint, mut sprite, requires, sets) in doors.iter_mut() {
+            if door.open || !covers(position.v, footprint, bump.at) {
+                continue;
+            }
+            if let Some(RequiresFlag(expr)) = requires {
+                if !flags.check(expr) {
+                    info!("The door is locked.");
+                    continue;
+                }
+            }
+
+            door.open = true;
+            sprite.index = door.open_sprite;
+            for cell in covered_cells(position.v, footprint) {
+                collision.unblock(cell);
+            }
+
+            if let Some(SetsFlag(flag)) = sets {
+                events.send(SetFlagEvent::new(flag, true));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        combat::DamageEvent,
+        replay::LogicalClock,
+        testing::{headless_game, run_ticks},
+    };
+
+    #[test]
+    fn defeating_a_tagged_creature_sets_its_flag() {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 1);
+        let mut creatures = (app.world).query_filtered::<Entity, (With<Health>, Without<Player>)>();
+        let creature = creatures.iter(&app.world).next().unwrap();
+        let flag = SetsFlag("creature_defeated".to_string());
+        app.world.entity_mut(creature).insert(flag);
+        app.world.send_event(DamageEvent {
+            entity: creature,
+            amount: 100,
+            element: Element::Physical,
+        });
+
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(&mut app, tick + 2);
+        assert!(app.world.get_entity(creature).is_none());
+        assert!(app
+            .world
+            .resource::<GameFlags>()
+            .is_set("creature_defeated"));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    collision::{CollisionMap, Occupancy},
+    editor::{EditorState, HoveredTile},
+    nearest_copy,
+    pathfinding::{find_path, find_path_budgeted},
+    player::Player,
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    terrain::{actor_capabilities, CapabilitySources},
+    turns::{ActionPoints, Turns},
+    vectors::{GridKind, Vector3Int},
+    AppState, CurrentBoard, Position,
+};
+
+/// Over blast rings and particles, within the overlay band.
+const PREVIEW_Z: f32 = 5.;
+/// How many cells a preview search may visit before giving up.
+const PATH_SEARCH_LIMIT: usize = 1024;
+/// Dot size as a fraction of a tile.
+const DOT_SCALE: f32 = 0.25;
+
+#[derive(Resource)]
+pub struct PathPreviewConfig {
+    /// Paths costing more than this are drawn as out of reach, a step on
+    /// plain ground costing 1.
+    pub max_length: usize,
+}
+
+impl Default for PathPreviewConfig {
+    fn default() -> Self {
+        PathPreviewConfig { max_length: 24 }
+    }
+}
+
+/// The cheapest route from the first player to the hovered cell, if the
+/// cursor is over the board.
+#[derive(Default, Resource)]
+pub struct PathPreview {
+    /// The cells stepped through, excluding the player's own. Just the
+    /// hovered cell when it cannot be reached.
+    pub path: Vec<Vector3Int>,
+    pub reachable: bool,
+    /// How many of the steps the player can pay for this turn in
+    /// turn-based mode, or on their next turn while others act. Every
+    /// step otherwise.
+    pub affordable: usize,
+}
+
+/// A sprite drawing one step of the preview. Kept around hidden when not
+/// needed and reused for later previews.
+#[derive(Component)]
+struct PreviewDot;
+
+pub struct PathPreviewPlugin;
+impl Plugin for PathPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PathPreviewConfig>()
+            .init_resource::<PathPreview>()
+            .add_systems(
+                (update_path_preview, draw_path_preview)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            );
+    }
+}
+
+/// Searches for a new route whenever the hovered cell, the board, the
+/// player's position or their points change.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_path_preview(
+    hovered: Res<HoveredTile>,
+    editor: Res<EditorState>,
+    config: Res<PathPreviewConfig>,
+    turns: Turns,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    occupancy: Res<Occupancy>,
+    players: Query<(
+        Entity,
+        &Player,
+        &Position,
+        CapabilitySources,
+        Option<&ActionPoints>,
+    )>,
+    acted: Query<(), (With<Player>, Or<(Changed<Position>, Changed<ActionPoints>)>)>,
+    grid: Res<GridKind>,
+    mut preview: ResMut<PathPreview>,
+) {
+    let changed = hovered.is_changed() || editor.is_changed() || current.is_changed();
+    if !changed && !turns.queue.is_changed() && acted.is_empty() {
+        return;
+    }
+
+    let player = players.iter().find(|(_, player, ..)| player.index == 0);
+    let target = (hovered.0)
+        .filter(|_| !editor.active)
+        .zip(player)
+        .map(|(cell, (_, _, position, ..))| {
+            current.wrap(Vector3Int::new(cell.x, cell.y, position.v.z))
+        })
+        .filter(|v| current.has_ground(*v));
+    let (Some(target), Some((entity, _, position, sources, points))) = (target, player) else {
+        if !preview.path.is_empty() {
+            *preview = PathPreview::default();
+        }
+        return;
+    };
+
+    let capabilities = actor_capabilities(sources);
+    let min_cost = collision.min_step_cost(capabilities);
+    let neighbours = |v: Vector3Int| current.neighbours(v, *grid);
+    let passable = |from: Vector3Int, v: Vector3Int| {
+        current.has_ground(v)
+            && !collision.is_blocked_for(v, capabilities)
+            && occupancy.get(v).is_none_or(|e| e == entity)
+            && collision.allows_step(from, v, current.delta(from, v))
+    };
+    let cost = |_: Vector3Int, v: Vector3Int| collision.step_cost(v, capabilities);
+    let path = find_path(
+        position.v,
+        neighbours,
+        |v| v == target,
+        passable,
+        cost,
+        |v| current.distance(*grid, v, target) as f32 * min_cost,
+        PATH_SEARCH_LIMIT,
+    );
+    let Some(path) = path else {
+        *preview = PathPreview {
+            path: vec![target],
+            reachable: false,
+            affordable: 0,
+        };
+        return;
+    };
+
+    // The cheapest way to each cell of the cheapest route is along it, so
+    // the steps within budget are those to cells reached within it.
+    let (_, sheet, effects) = sources;
+    let budget = (turns.queue.is_active()).then(|| match points {
+        Some(points) if turns.queue.may_act(entity) => points.current,
+        _ => turns.config.max_points(sheet, effects),
+    });
+    let affordable = match budget {
+        Some(budget) => {
+            let reached = find_path_budgeted(position.v, neighbours, passable, cost, budget);
+            let within: HashSet<Vector3Int> = reached.into_iter().map(|(v, _)| v).collect();
+            let steps = path.cells.iter();
+            steps.take_while(|v| within.contains(v)).count()
+        }
+        None => path.cells.len(),
+    };
+    *preview = PathPreview {
+        reachable: path.cost <= config.max_length as f32,
+        path: path.cells,
+        affordable,
+    };
+}
+
+/// Places a dot on each cell of the preview, taking dots from the pool and
+/// adding to it when it runs short.
+fn draw_path_preview(
+    mut commands: Commands,
+    preview: Res<PathPreview>,
+    palette: Res<PaletteLookup>,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+    players: Query<(&Player, &Transform), Without<PreviewDot>>,
+    mut dots: Query<(&mut Sprite, &mut Transform, &mut Visibility), With<PreviewDot>>,
+) {
+    if !preview.is_changed() && !palette.is_changed() {
+        return;
+    }
+
+    let color = |i: usize| {
+        palette.color(if !preview.reachable {
+            PaletteColor::PathUnreachable
+        } else if i >= preview.affordable {
+            PaletteColor::PathOverBudget
+        } else {
+            PaletteColor::PathReachable
+        })
+    };
+    // Each dot goes on the copy of its cell nearest the last, so the route
+    // stays in one piece across wrapping edges.
+    let start = (players.iter())
+        .find(|(player, _)| player.index == 0)
+        .map_or(Vec3::ZERO, |(_, transform)| transform.translation);
+    let places: Vec<Vec3> = (preview.path.iter())
+        .scan(start, |last, v| {
+            *last = nearest_copy(projection.world(*v), *last, &current.bounds, &projection);
+            Some(
+                last.truncate()
+                    .extend(z_for(RenderLayerSlot::Overlay, PREVIEW_Z)),
+            )
+        })
+        .collect();
+
+    let mut places = places.into_iter().enumerate();
+    for (mut sprite, mut transform, mut visibility) in dots.iter_mut() {
+        match places.next() {
+            Some((i, place)) => {
+                sprite.color = color(i);
+                transform.translation = place;
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    for (i, place) in places {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: color(i),
+                    custom_size: Some(projection.tile_size() * DOT_SCALE),
+                    ..default()
+                },
+                transform: Transform::from_translation(place),
+                ..default()
+            },
+            PreviewDot,
+        ));
+    }
+}
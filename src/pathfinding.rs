@@ -0,0 +1,210 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+};
+
+use crate::vectors::Vector3Int;
+
+/// A route found by `find_path`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    /// The cells stepped through, excluding the one started from.
+    pub cells: Vec<Vector3Int>,
+    /// What taking every step costs in all.
+    pub cost: f32,
+}
+
+impl Path {
+    pub fn first(&self) -> Option<Vector3Int> {
+        self.cells.first().copied()
+    }
+}
+
+/// A cell waiting to be spread from. The cheapest estimate goes first, and
+/// of those the one found first, so that on even ground the search goes as
+/// breadth-first search would.
+struct Open {
+    estimate: f32,
+    order: usize,
+    cost: f32,
+    v: Vector3Int,
+}
+
+impl Ord for Open {
+    // Reversed, as `BinaryHeap` takes the greatest first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.estimate.total_cmp(&self.estimate)).then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+/// A step costing 1 whatever the cells, for searches that count steps.
+pub fn unit_cost(_: Vector3Int, _: Vector3Int) -> f32 {
+    1.
+}
+
+/// A* search over `neighbours` from `from` for the cheapest route to any
+/// cell where `is_goal` holds, only taking steps `passable` allows. Steps
+/// are directed, as one-way cells make them, so `passable` and `cost` are
+/// asked about the cell stepped from as well as the one entered. For
+/// entities with a footprint, all are anchor positions, so `passable` and
+/// `cost` should look at every covered cell.
+///
+/// `heuristic` guesses what is left to pay from a cell. It must never
+/// guess more than the cheapest way to the nearest goal, or the route
+/// found may not be the cheapest: cells from a goal times the least any
+/// step costs is safe, and always guessing 0 searches as Dijkstra's
+/// algorithm does. Costs below 0 count as 0.
+///
+/// Returns `None` if no goal is reached within `limit` visited cells.
+pub fn find_path<I: IntoIterator<Item = Vector3Int>>(
+    from: Vector3Int,
+    neighbours: impl Fn(Vector3Int) -> I,
+    is_goal: impl Fn(Vector3Int) -> bool,
+    passable: impl Fn(Vector3Int, Vector3Int) -> bool,
+    cost: impl Fn(Vector3Int, Vector3Int) -> f32,
+    heuristic: impl Fn(Vector3Int) -> f32,
+    limit: usize,
+) -> Option<Path> {
+    let mut open = BinaryHeap::from([Open {
+        estimate: heuristic(from),
+        order: 0,
+        cost: 0.,
+        v: from,
+    }]);
+    // The cheapest way found to each cell, and the cell it came from.
+    let mut best: HashMap<Vector3Int, (f32, Vector3Int)> = HashMap::from([(from, (0., from))]);
+    let mut closed = HashSet::new();
+    let mut order = 0;
+
+    while let Some(Open { cost: paid, v, .. }) = open.pop() {
+        // A cell is queued again each time a cheaper way to it is found.
+        if !closed.insert(v) {
+            continue;
+        }
+        if is_goal(v) {
+            let mut cells = Vec::new();
+            let mut step = v;
+            while step != from {
+                cells.push(step);
+                step = best[&step].1;
+            }
+            cells.reverse();
+            return Some(Path { cells, cost: paid });
+        }
+        if best.len() > limit {
+            return None;
+        }
+
+        for next in neighbours(v) {
+            if closed.contains(&next) || !passable(v, next) {
+                continue;
+            }
+            let total = paid + cost(v, next).max(0.);
+            if best.get(&next).is_some_and(|(known, _)| *known <= total) {
+                continue;
+            }
+            best.insert(next, (total, v));
+            order += 1;
+            open.push(Open {
+                estimate: total + heuristic(next),
+                order,
+                cost: total,
+                v: next,
+            });
+        }
+    }
+
+    None
+}
+
+/// Every cell reachable from `from` for at most `budget` in all, with the
+/// least it costs to reach, cheapest first and `from` first of all at no
+/// cost, as for showing how far an actor could go on its movement points.
+/// Steps are taken and costed as in `find_path`.
+pub fn find_path_budgeted<I: IntoIterator<Item = Vector3Int>>(
+    from: Vector3Int,
+    neighbours: impl Fn(Vector3Int) -> I,
+    passable: impl Fn(Vector3Int, Vector3Int) -> bool,
+    cost: impl Fn(Vector3Int, Vector3Int) -> f32,
+    budget: f32,
+) -> Vec<(Vector3Int, f32)> {
+    let mut open = BinaryHeap::from([Open {
+        estimate: 0.,
+        order: 0,
+        cost: 0.,
+        v: from,
+    }]);
+    let mut best: HashMap<Vector3Int, f32> = HashMap::from([(from, 0.)]);
+    let mut reached = Vec::new();
+    let mut closed = HashSet::new();
+    let mut order = 0;
+
+    while let Some(Open { cost: paid, v, .. }) = open.pop() {
+        if !closed.insert(v) {
+            continue;
+        }
+        reached.push((v, paid));
+        for next in neighbours(v) {
+            if closed.contains(&next) || !passable(v, next) {
+                continue;
+            }
+            let total = paid + cost(v, next).max(0.);
+            if total > budget || best.get(&next).is_some_and(|known| *known <= total) {
+                continue;
+            }
+            best.insert(next, total);
+            order += 1;
+            open.push(Open {
+                estimate: total,
+                order,
+                cost: total,
+                v: next,
+            });
+        }
+    }
+
+    reached
+}
+
+/// The cells reachable from `from` in at most `steps` moves over
+/// `neighbours`, including `from`. Cells where `passable` fails are
+/// reached but not spread from, so a flood stops at walls without
+/// skipping them.
+pub fn flood_fill<I: IntoIterator<Item = Vector3Int>>(
+    from: Vector3Int,
+    neighbours: impl Fn(Vector3Int) -> I,
+    passable: impl Fn(Vector3Int) -> bool,
+    steps: u32,
+) -> Vec<Vector3Int> {
+    let mut queue = VecDeque::from([(from, 0)]);
+    let mut reached = HashSet::from([from]);
+    let mut cells = vec![from];
+
+    while let Some((v, depth)) = queue.pop_front() {
+        if depth >= steps || !passable(v) {
+            continue;
+        }
+        for next in neighbours(v) {
+            if reached.insert(next) {
+                cells.push(next);
+                queue.push_back((next, depth + 1));
+            }
+        }
+    }
+
+    cells
+}
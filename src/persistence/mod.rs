@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    board::BoardQuery,
+    collision::{covered_cells, Footprint, Occupier},
+    flags::{FlagValue, GameFlags},
+    layer_of, layer_z, load_map, load_scene,
+    objects::{Door, MapObjectIndex, ObjectSprite},
+    player::{self, Player},
+    progression::Campaign,
+    shop::Shop,
+    terrain::Capabilities,
+    transitions::{TransitionCause, TransitionCovered},
+    vectors::Vector3Int,
+    AppState, Position,
+};
+
+/// How far from the first player entities brought from another map may be
+/// put down.
+const ARRIVAL_RADIUS: u32 = 4;
+
+/// Kept when the board is cleared for another map, as players are. Those
+/// that came from a map's objects go with the map again if it is reloaded.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Persistent;
+
+/// How a map was left: its objects gone from it, its doors opened and what
+/// its shops have left, by index in the map's object list, and its flags.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct MapDiff {
+    pub removed: BTreeSet<usize>,
+    pub opened_doors: BTreeSet<usize>,
+    /// Quantities left of each line of stock.
+    pub stock: BTreeMap<usize, Vec<u32>>,
+    /// Put back after loading the map clears them.
+    pub flags: BTreeMap<String, FlagValue>,
+}
+
+/// What a map's memory records of one of its objects.
+pub type MemorableObject<'a> = (&'a MapObjectIndex, Option<&'a Door>, Option<&'a Shop>);
+
+/// Maps as they were left, by asset path, put back on coming back to them.
+/// Objects spawned after loading, such as loot and spawned NPCs, are not
+/// remembered.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MapMemory {
+    maps: BTreeMap<String, MapDiff>,
+    /// The map on the board, once known.
+    #[serde(skip)]
+    current: Option<String>,
+    /// The objects the current map had once its memory was put back.
+    #[serde(skip)]
+    entered_with: BTreeSet<usize>,
+    /// Persistent entities brought over from the last map, to be put down
+    /// on this one.
+    #[serde(skip)]
+    arriving: Vec<Entity>,
+}
+
+impl MapMemory {
+    /// The current map's objects gone since it was entered, its doors
+    /// opened and its shops' stock, added to what was remembered of it.
+    fn diff<'a>(&self, objects: impl Iterator<Item = MemorableObject<'a>>) -> MapDiff {
+        let mut diff = (self.current.as_ref())
+            .and_then(|map| self.maps.get(map))
+            .cloned()
+            .unwrap_or_default();
+        let mut present = BTreeSet::new();
+        for (MapObjectIndex(index), door, shop) in objects {
+            present.insert(*index);
+            if door.is_some_and(|door| door.open) {
+                diff.opened_doors.insert(*index);
+            }
+            if let Some(shop) = shop {
+                diff.stock.insert(*index, shop.quantities());
+            }
+        }
+        diff.removed
+            .extend(self.entered_with.difference(&present).copied());
+        diff
+    }
+
+    /// A copy with the current map as it is now, for saving.
+    pub fn snapshot<'a>(&self, objects: impl Iterator<Item = MemorableObject<'a>>) -> MapMemory {
+        let mut memory = self.clone();
+        if let Some(map) = &self.current {
+            memory.maps.insert(map.clone(), self.diff(objects));
+        }
+        memory
+    }
+
+    /// Takes on the maps remembered in a save. The board about to be
+    /// replaced is not recorded.
+    pub fn restore(&mut self, saved: &MapMemory) {
+        *self = MapMemory {
+            maps: saved.maps.clone(),
+            ..default()
+        };
+    }
+}
+
+pub struct PersistencePlugin;
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapMemory>()
+            // Before the flags are cleared for the next map.
+            .add_system(record_map.before(load_map))
+            // Once the map's objects are spawned and the players are back at
+            // the start, and before anything draws or blocks for them.
+            .add_systems(
+                (apply_system_buffers, restore_map)
+                    .chain()
+                    .after(load_scene)
+                    .after(player::load_player)
+                    .in_schedule(OnEnter(AppState::Game)),
+            );
+    }
+}
+
+/// Records the map being left once the screen is covered, before it is
+/// torn down. Reloading the map on the board, as a restart does, leaves it
+/// to come back as it was entered.
+#[allow(clippy::type_complexity)]
+fn record_map(
+    mut commands: Commands,
+    mut covered: EventReader<TransitionCovered>,
+    mut memory: ResMut<MapMemory>,
+    flags: Res<GameFlags>,
+    objects: Query<(&MapObjectIndex, Option<&Door>, Option<&Shop>), Without<Persistent>>,
+    persistent: Query<(Entity, Option<&MapObjectIndex>), (With<Persistent>, Without<Player>)>,
+) {
+    let Some(name) = (covered.iter())
+        .filter_map(|event| match &event.cause {
+            TransitionCause::LoadMap(name) => Some(name.clone()),
+            TransitionCause::State(_) => None,
+        })
+        .last()
+    else {
+        return;
+    };
+
+    let reload = memory.current.as_ref() == Some(&name);
+    if let Some(leaving) = memory.current.clone().filter(|_| !reload) {
+        let mut diff = memory.diff(objects.iter());
+        // Taken along, so no longer the map's to spawn.
+        diff.removed.extend(
+            persistent
+                .iter()
+                .filter_map(|(_, index)| index.map(|i| i.0)),
+        );
+        diff.flags = (flags.iter())
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        memory.maps.insert(leaving, diff);
+    }
+
+    memory.arriving.clear();
+    for (entity, index) in persistent.iter() {
+        if reload && index.is_some() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        // Indices belong to the map left behind.
+        commands.entity(entity).remove::<MapObjectIndex>();
+        memory.arriving.push(entity);
+    }
+    memory.current = Some(name);
+    memory.entered_with.clear();
+}
+
+/// Puts the remembered state back on a map just loaded: despawns objects
+/// gone from it, opens its doors, restocks its shops and sets its flags as
+/// they were left.
+/// Persistent entities brought along are put down near the first player.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn restore_map(
+    mut commands: Commands,
+    mut memory: ResMut<MapMemory>,
+    campaign: Res<Campaign>,
+    mut flags: ResMut<GameFlags>,
+    mut objects: Query<(
+        Entity,
+        &MapObjectIndex,
+        Option<&mut Door>,
+        Option<&mut ObjectSprite>,
+        Option<&mut Shop>,
+    )>,
+    players: Query<(&Player, &Position)>,
+    occupiers: Query<&Position, (With<Occupier>, Without<Persistent>, Without<Player>)>,
+    mut persistent: Query<
+        (&mut Position, Option<&Footprint>, Option<&Capabilities>),
+        (With<Persistent>, Without<Player>, Without<MapObjectIndex>),
+    >,
+    board: BoardQuery,
+) {
+    let memory = &mut *memory;
+    let map = (memory.current)
+        .get_or_insert_with(|| campaign.current_map().to_string())
+        .clone();
+    let diff = memory.maps.get(&map).cloned().unwrap_or_default();
+    for (name, value) in diff.flags.iter() {
+        flags.set(name, *value);
+    }
+
+    memory.entered_with.clear();
+    for (entity, MapObjectIndex(index), door, sprite, shop) in objects.iter_mut() {
+        if diff.removed.contains(index) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        memory.entered_with.insert(*index);
+        if let (Some(mut shop), Some(quantities)) = (shop, diff.stock.get(index)) {
+            shop.restock(quantities);
+        }
+        let Some(mut door) = door.filter(|_| diff.opened_doors.contains(index)) else { continue };
+        door.open = true;
+        if let Some(mut sprite) = sprite {
+            sprite.0 = door.open_sprite;
+        }
+    }
+
+    let Some((_, origin)) = players.iter().min_by_key(|(player, _)| player.index) else {
+        memory.arriving.clear();
+        return;
+    };
+    let mut taken: Vec<Vector3Int> = (players.iter().map(|(_, p)| p.v))
+        .chain(occupiers.iter().map(|p| p.v))
+        .collect();
+    let free = |v: &Vector3Int, taken: &[Vector3Int]| {
+        !taken
+            .iter()
+            .any(|t| t.manhattan(*v) == 0 && layer_of(t.z) == layer_of(v.z))
+    };
+    for entity in memory.arriving.drain(..) {
+        let Ok((mut position, footprint, capabilities)) = persistent.get_mut(entity) else {
+            continue;
+        };
+        let capabilities = capabilities.copied().unwrap_or_default();
+        let offset = position.v.z - layer_z(layer_of(position.v.z), 0);
+        let found = (board.cells_near(origin.v, ARRIVAL_RADIUS).into_iter())
+            .map(|v| Vector3Int::new(v.x, v.y, layer_z(layer_of(origin.v.z), offset)))
+            .find(|v| {
+                board.fits(entity, *v, footprint, capabilities)
+                    && covered_cells(*v, footprint).iter().all(|c| free(c, &taken))
+            });
+        match found {
+            Some(v) => {
+                position.v = v;
+                taken.extend(covered_cells(v, footprint));
+            }
+            None => warn!("No room near the players for {:?}.", entity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        objects::{RequiresFlag, SetsFlag, Trigger},
+        player::{BumpEvent, PlayerStepCompleted},
+        replay::LogicalClock,
+        testing::{headless_game, run_ticks, travel},
+    };
+
+    #[test]
+    fn keys_taken_still_open_doors_after_coming_back() {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 0);
+        let player = (app.world.query_filtered::<Entity, With<Player>>()).single(&app.world);
+        let mut keys = app
+            .world
+            .query_filtered::<(&SetsFlag, &Position), With<Trigger>>();
+        let (_, key) = (keys.iter(&app.world))
+            .find(|(SetsFlag(flag), _)| flag == "has_key")
+            .unwrap();
+        let step = PlayerStepCompleted {
+            entity: player,
+            at: key.v,
+        };
+        app.world.send_event(step);
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(&mut app, tick + 2);
+        assert!(app.world.resource::<GameFlags>().is_set("has_key"));
+
+        travel(&mut app, "level2.json");
+        assert!(!app.world.resource::<GameFlags>().is_set("has_key"));
+        travel(&mut app, "data.json");
+        assert!(app.world.resource::<GameFlags>().is_set("has_key"));
+        let key_left = (keys.iter(&app.world)).any(|(SetsFlag(flag), _)| flag == "has_key");
+        assert!(!key_left);
+
+        let mut doors = app.world.query::<(&Door, &RequiresFlag, &Position)>();
+        let (_, _, door) = (doors.iter(&app.world))
+            .find(|(_, RequiresFlag(expr), _)| expr == "has_key")
+            .unwrap();
+        let at = door.v;
+        app.world.send_event(BumpEvent { entity: player, at });
+        let tick = app.world.resource::<LogicalClock>().tick;
+        run_ticks(&mut app, tick + 2);
+        let (door, ..) = (doors.iter(&app.world))
+            .find(|(_, RequiresFlag(expr), _)| expr == "has_key")
+            .unwrap();
+        assert!(door.open);
+    }
+}
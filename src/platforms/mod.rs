@@ -0,0 +1,322 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{refresh_layer, CollisionFlags, CollisionMap, Occupancy, Occupier},
+    get_world_position, grid_to_position, layer_of, layer_z,
+    materials::TileMetadataRegistry,
+    objects::MapObject,
+    player::{PLAYER_SPEED, POSITION_TOLERANCE},
+    projection::GridProjection,
+    simulation::SimulationSet,
+    terrain::TerrainRegistry,
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position, Tile, LAYER_Z_STRIDE,
+};
+
+const DEFAULT_PERIOD_MS: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlatformMode {
+    /// Returns to the first waypoint after the last one.
+    Loop,
+    /// Reverses direction at either end.
+    PingPong,
+}
+
+/// A tile that moves one cell per `period_ms` towards its next waypoint,
+/// carrying any occupier standing on it.
+#[derive(Component)]
+pub struct MovingTile {
+    pub waypoints: Vec<Vector3Int>,
+    pub period_ms: u64,
+    pub mode: PlatformMode,
+    timer: Timer,
+    next: usize,
+    forward: bool,
+}
+
+impl MovingTile {
+    pub fn new(waypoints: Vec<Vector3Int>, period_ms: u64, mode: PlatformMode) -> Self {
+        MovingTile {
+            waypoints,
+            period_ms,
+            mode,
+            timer: Timer::new(Duration::from_millis(period_ms), TimerMode::Repeating),
+            next: 0,
+            forward: true,
+        }
+    }
+
+    /// Builds a moving tile from a `moving_tile` map object. Waypoints are
+    /// `[column, row]` or `[column, row, z]` arrays like the object position.
+    pub fn from_object(object: &MapObject, grid: GridKind) -> Self {
+        let waypoints = object
+            .properties
+            .get("waypoints")
+            .and_then(|v| v.as_array())
+            .map(|points| {
+                points
+                    .iter()
+                    .filter_map(|p| {
+                        let p = p.as_array()?;
+                        let col = p.first()?.as_i64()? as i32;
+                        let row = p.get(1)?.as_i64()? as i32;
+                        let z = p.get(2).and_then(|z| z.as_i64()).unwrap_or(0) as i32;
+                        Some(grid_to_position(grid, col, row, z))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let period_ms = object
+            .properties
+            .get("period_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_PERIOD_MS);
+
+        let mode = match object.str_prop("mode") {
+            Some("loop") => PlatformMode::Loop,
+            _ => PlatformMode::PingPong,
+        };
+
+        MovingTile::new(waypoints, period_ms, mode)
+    }
+
+    fn advance(&mut self) {
+        let len = self.waypoints.len();
+        match self.mode {
+            PlatformMode::Loop => self.next = (self.next + 1) % len,
+            PlatformMode::PingPong => {
+                if self.forward && self.next + 1 == len {
+                    self.forward = false;
+                } else if !self.forward && self.next == 0 {
+                    self.forward = true;
+                }
+                self.next = if self.forward {
+                    self.next + 1
+                } else {
+                    self.next - 1
+                };
+            }
+        }
+    }
+
+    /// The single-cell step from `at` towards the next waypoint, moving
+    /// along x, then y, then z. Returns `None` when there is nowhere to go.
+    fn next_step(&mut self, at: Vector3Int) -> Option<Vector3Int> {
+        if self.waypoints.len() < 2 {
+            return None;
+        }
+
+        for _ in 0..self.waypoints.len() {
+            let target = self.waypoints[self.next];
+            let step = if target.x != at.x {
+                Vector3Int::new((target.x - at.x).signum(), 0, 0)
+            } else if target.y != at.y {
+                Vector3Int::new(0, (target.y - at.y).signum(), 0)
+            } else if target.z != at.z {
+                Vector3Int::new(0, 0, (target.z - at.z).signum())
+            } else {
+                self.advance();
+                continue;
+            };
+            return Some(step);
+        }
+
+        None
+    }
+}
+
+pub struct PlatformsPlugin;
+impl Plugin for PlatformsPlugin {
+    fn build(&self, app: &mut App) {
+        // Once per tick, so that a tick run on the frame a map is entered
+        // already finds them, and once per frame for any spawned between.
+        app.add_system(
+            register_moving_tiles
+                .in_set(SimulationSet::Input)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(register_moving_tiles)
+        .add_system(
+            move_platforms
+                .in_set(SimulationSet::Act)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(update_platform_transforms);
+    }
+}
+
+fn register_moving_tiles(
+    query: Query<(Entity, &Position), Added<MovingTile>>,
+    mut current: ResMut<CurrentBoard>,
+) {
+    for (entity, position) in query.iter() {
+        current.tiles.insert(position.v, entity);
+    }
+}
+
+/// Moves each platform a cell once its period is up, bringing the
+/// collision of the cells left and entered in step with their tiles, and
+/// carries whoever stands on it along.
+#[allow(clippy::too_many_arguments)]
+fn move_platforms(
+    mut platforms: Query<(Entity, &mut MovingTile, &mut Position), With<Tile>>,
+    mut riders: Query<&mut Position, (With<Occupier>, Without<Tile>)>,
+    tiles: Query<&Tile>,
+    fixed: Res<FixedTime>,
+    occupancy: Res<Occupancy>,
+    metadata: Res<TileMetadataRegistry>,
+    terrain: Res<TerrainRegistry>,
+    mut collision: ResMut<CollisionMap>,
+    mut current: ResMut<CurrentBoard>,
+) {
+    for (entity, mut platform, mut position) in platforms.iter_mut() {
+        // Pick up changes to `period_ms` made after spawning.
+        let period = Duration::from_millis(platform.period_ms);
+        if platform.timer.duration() != period {
+            platform.timer.set_duration(period);
+        }
+        if !platform.timer.tick(fixed.period).just_finished() {
+            continue;
+        }
+
+        let from = position.v;
+        let Some(step) = platform.next_step(from) else { continue };
+        let to = Vector3Int::new(from.x + step.x, from.y + step.y, from.z + step.z);
+
+        // Pause rather than overlap a wall, an actor or another tile.
+        // Moving purely along z keeps the cell, so only tiles matter.
+        let same_cell = step.x == 0 && step.y == 0;
+        // Platforms carry actors over water rather than wading in.
+        let walled = collision.blocks(to, CollisionFlags::BLOCK_WALK | CollisionFlags::PIT);
+        if !same_cell && (walled || occupancy.is_occupied(to)) {
+            continue;
+        }
+        if current.tiles.contains_key(&to) {
+            continue;
+        }
+
+        current.tiles.remove(&from);
+        current.tiles.insert(to, entity);
+        position.v = to;
+        for v in [from, to] {
+            let layer = Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), 0));
+            let index = |entity| tiles.get(entity).ok().map(|tile| tile.i);
+            refresh_layer(layer, index, &current, &metadata, &terrain, &mut collision);
+        }
+
+        // Whoever stands within a layer's height above the tile, which part
+        // way up an elevator reaches into the layer above.
+        let rider = [0, LAYER_Z_STRIDE - 1]
+            .into_iter()
+            .filter_map(|dz| occupancy.get(Vector3Int::new(from.x, from.y, from.z + dz)))
+            .find(|rider| {
+                (riders.get(*rider)).is_ok_and(|r| (0..LAYER_Z_STRIDE).contains(&(r.v.z - from.z)))
+            });
+        if let Some(mut rider) = rider.and_then(|rider| riders.get_mut(rider).ok()) {
+            let v = rider.v;
+            rider.v = Vector3Int::new(v.x + step.x, v.y + step.y, v.z + step.z);
+        }
+    }
+}
+
+fn update_platform_transforms(
+    mut query: Query<(&Position, &mut Transform), With<MovingTile>>,
+    time: Res<Time>,
+    projection: Res<GridProjection>,
+) {
+    for (position, mut transform) in query.iter_mut() {
+        let target = get_world_position(position, &projection);
+        let d = (target - transform.translation).length();
+        if d > POSITION_TOLERANCE {
+            transform.translation = transform
+                .translation
+                .lerp(target, PLAYER_SPEED * time.delta_seconds());
+        } else {
+            transform.translation = target;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        collision::update_occupancy,
+        render_layers::{z_index, RenderLayerSlot},
+    };
+
+    /// The platform's atlas index, a glass pane that blocks sight so its
+    /// collision can be followed.
+    const GLASS: usize = 7;
+
+    /// Platforms alone on ground at columns -1 and 3 of row 0, with a gap
+    /// between. A platform at column 0 carries a rider over the gap to
+    /// column 2, then up a floor.
+    fn gap() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        let glass = serde_json::json!({ "collision": ["block_sight"] });
+        let metadata = HashMap::from([(GLASS, serde_json::from_value(glass).unwrap())]);
+        app.insert_resource(FixedTime::new_from_secs(0.1))
+            .insert_resource(CurrentBoard::with_ground([cell(-1, 0), cell(3, 0)]))
+            .insert_resource(TileMetadataRegistry(metadata))
+            .init_resource::<TerrainRegistry>()
+            .init_resource::<CollisionMap>()
+            .init_resource::<Occupancy>()
+            .add_systems((move_platforms, update_occupancy).chain());
+        let waypoints = vec![cell(0, 0), cell(2, 0), cell(2, LAYER_Z_STRIDE)];
+        let platform = app
+            .world
+            .spawn((
+                Position { v: cell(0, 0) },
+                Tile { i: GLASS },
+                MovingTile::new(waypoints, 100, PlatformMode::PingPong),
+            ))
+            .id();
+        app.world
+            .resource_mut::<CurrentBoard>()
+            .tiles
+            .insert(cell(0, 0), platform);
+        let actor = z_index(RenderLayerSlot::Actors, 0);
+        let v = cell(0, actor);
+        let rider = app.world.spawn((Position { v }, Occupier)).id();
+        app.world.resource_mut::<Occupancy>().insert(rider, [v]);
+        (app, platform, rider)
+    }
+
+    fn cell(x: i32, z: i32) -> Vector3Int {
+        Vector3Int::new(x, 0, z)
+    }
+
+    #[test]
+    fn riders_are_carried_over_gaps_and_up_floors() {
+        let (mut app, platform, rider) = gap();
+        let actor = z_index(RenderLayerSlot::Actors, 0);
+        let at = |app: &App, entity| app.world.get::<Position>(entity).unwrap().v;
+        let sight = |app: &App, v| {
+            (app.world.resource::<CollisionMap>()).blocks(v, CollisionFlags::BLOCK_SIGHT)
+        };
+
+        for x in 1..=2 {
+            app.update();
+            assert_eq!(at(&app, platform), cell(x, 0));
+            assert_eq!(at(&app, rider), cell(x, actor));
+            assert!(!sight(&app, cell(x - 1, 0)));
+            assert!(sight(&app, cell(x, 0)));
+        }
+        for _ in 0..LAYER_Z_STRIDE {
+            app.update();
+        }
+        assert_eq!(at(&app, platform), cell(2, LAYER_Z_STRIDE));
+        assert_eq!(at(&app, rider), cell(2, LAYER_Z_STRIDE + actor));
+        let occupancy = app.world.resource::<Occupancy>();
+        assert_eq!(occupancy.get(cell(2, LAYER_Z_STRIDE)), Some(rider));
+        assert!(!sight(&app, cell(2, 0)));
+        assert!(sight(&app, cell(2, LAYER_Z_STRIDE)));
+    }
+}
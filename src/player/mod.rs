@@ -1,93 +1,1101 @@
-use bevy::prelude::*;
+use std::time::Duration;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
 
 use crate::{
-    get_world_position, vectors::Vector3Int, AppState, GraphicsAssets, Position, TILE_SIZE,
+    board::BoardQuery,
+    collision::{self, CollisionFlags, CollisionMap, Occupancy, Occupier},
+    combat::Health,
+    equipment::{Equipment, StatSheet},
+    get_world_position,
+    input::{move_actions, Action, ActionInput, MOVE_ACTIONS},
+    inspect::Inspectable,
+    inventory::{Currency, Inventory},
+    layer_of, layer_z,
+    map_meta::MapMeta,
+    materials::{sprite_shape, TileMetadataRegistry},
+    nearest_copy,
+    objects::LayerLink,
+    projection::GridProjection,
+    puzzles::{self, BlockPushedEvent, Pushable},
+    render_layers::{z_index, RenderLayerSlot},
+    simulation::{tick_alpha, SimulationApp, SimulationSet},
+    status::StatusEffects,
+    terrain::{actor_capabilities, Capabilities, CapabilitySources},
+    turns::{pay_points, ActionPoints, Initiative, Turns, PLAYER_INITIATIVE},
+    vectors::{GridKind, Vector3Int},
+    AppState, BoardLoadedEvent, CurrentBoard, GraphicsAssets, MapError, Position, SceneHandle,
 };
 
 pub const POSITION_TOLERANCE: f32 = 0.1;
+/// Z-index of players within their layer's band.
+pub const PLAYER_Z: i32 = z_index(RenderLayerSlot::Actors, 1);
 pub const PLAYER_SPEED: f32 = 10.;
+/// How far, in tiles, a bump nudges the sprite towards the blocked cell.
+pub const BUMP_OFFSET: f32 = 0.25;
+
+/// Sprite used by each local player, in `Player::index` order.
+const PLAYER_SPRITES: [usize; 2] = [95, 104]; // Temporary values.
+/// Sprite alpha for players that ignore collision.
+pub const NOCLIP_ALPHA: f32 = 0.6;
+/// How many cells away to look for a free tile when leaving noclip.
+const UNSTICK_SEARCH_RADIUS: u32 = 16;
+/// How many cells from a blocked spawn point to look for a free tile.
+const SPAWN_SEARCH_RADIUS: u32 = 8;
+
+#[derive(Component)]
+pub struct Player {
+    pub index: usize,
+}
+
+/// Sent when an entity tries to move into a blocked cell.
+pub struct BumpEvent {
+    pub entity: Entity,
+    pub at: Vector3Int,
+}
+
+pub const PLAYER_HEALTH: u32 = 10;
+
+/// Sent when a player's logical position moves to a new cell.
+#[derive(Clone, Copy, Debug)]
+pub struct PlayerStepStarted {
+    pub entity: Entity,
+    pub from: Vector3Int,
+    pub to: Vector3Int,
+    pub dir: Vector3Int,
+}
+
+/// Sent when a player's sprite arrives on the cell it stepped to.
+pub struct PlayerStepCompleted {
+    pub entity: Entity,
+    pub at: Vector3Int,
+}
+
+/// Sent when an entity dashes, for effects and for hazards along the way.
+pub struct DashedEvent {
+    pub entity: Entity,
+    pub from: Vector3Int,
+    pub to: Vector3Int,
+}
+
+#[derive(Resource, Debug)]
+pub struct MovementConfig {
+    /// Seconds between steps while a direction is held.
+    pub repeat_interval: f32,
+    /// Multiplier applied to `repeat_interval` while sprinting.
+    pub sprint_interval_scale: f32,
+    /// Maximum number of tiles covered by a dash.
+    pub dash_distance: u32,
+    /// Seconds before another dash is allowed.
+    pub dash_cooldown: f32,
+    /// Whether hazards between the ends of a dash still deal damage.
+    pub dash_triggers_hazards: bool,
+    /// Seconds a step takes, and takes to animate for every easing but
+    /// `Exponential`.
+    pub step_duration: f32,
+    pub easing: Easing,
+    /// Seconds before a step completes within which a fresh direction press
+    /// waits for it to, rather than cutting it short.
+    pub input_buffer: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            repeat_interval: 0.2,
+            sprint_interval_scale: 0.5,
+            dash_distance: 3,
+            dash_cooldown: 2.,
+            dash_triggers_hazards: true,
+            step_duration: 0.15,
+            easing: Easing::EaseOutQuad,
+            input_buffer: 0.15,
+        }
+    }
+}
+
+/// Rules that bend normal movement, such as the debug noclip mode.
+#[derive(Resource, Debug)]
+pub struct MovementRules {
+    /// Walk through the collision map and other occupiers.
+    pub noclip: bool,
+    /// While in noclip, move freely instead of from tile to tile.
+    pub free_movement: bool,
+    /// Speed in tiles per second for free movement.
+    pub free_speed: f32,
+}
+
+impl Default for MovementRules {
+    fn default() -> Self {
+        MovementRules {
+            noclip: false,
+            free_movement: false,
+            free_speed: 8.,
+        }
+    }
+}
+
+impl MovementRules {
+    pub fn ignores_collision(&self) -> bool {
+        self.noclip
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.noclip && self.free_movement
+    }
+}
+
+/// How a player's sprite moves between cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseOutQuad,
+    EaseInOutCubic,
+    /// The original frame-rate-dependent lerp at `PLAYER_SPEED`.
+    Exponential,
+}
+
+impl Easing {
+    /// Maps progress `t` in [0, 1] to an interpolation factor.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear | Easing::Exponential => t,
+            Easing::EaseOutQuad => 1. - (1. - t) * (1. - t),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+        }
+    }
+
+    /// The next easing in the list, for cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            Easing::Linear => Easing::EaseOutQuad,
+            Easing::EaseOutQuad => Easing::EaseInOutCubic,
+            Easing::EaseInOutCubic => Easing::Exponential,
+            Easing::Exponential => Easing::Linear,
+        }
+    }
+}
+
+/// A sprite's move between two world positions.
+#[derive(Component)]
+pub struct MoveTween {
+    pub start: Vec3,
+    pub end: Vec3,
+    /// Seconds since the tween started.
+    pub elapsed: f32,
+}
+
+impl MoveTween {
+    /// A tween already resting at `v`.
+    pub fn at(v: Vec3) -> Self {
+        MoveTween {
+            start: v,
+            end: v,
+            elapsed: f32::INFINITY,
+        }
+    }
+
+    /// Restarts the tween towards `end` from wherever the sprite is now.
+    pub fn retarget(&mut self, from: Vec3, end: Vec3) {
+        self.start = from;
+        self.end = end;
+        self.elapsed = 0.;
+    }
+}
 
 #[derive(Component)]
-pub struct Player;
+pub struct MovementState {
+    pub facing: Vector3Int,
+    pub repeat: Timer,
+    pub dash_cooldown: Timer,
+    /// Time until the current step completes.
+    pub step: Timer,
+    /// Multiplier on the time the next step takes, set by terrain.
+    pub move_cost: f32,
+    /// Direction the next step is forced in, ignoring input (e.g. on ice).
+    pub slide: Option<Vector3Int>,
+    /// Set between `PlayerStepStarted` and `PlayerStepCompleted`.
+    stepping: bool,
+    /// Direction pressed late in the current step, taken once it completes.
+    buffered: Option<Vector3Int>,
+}
+
+impl MovementState {
+    pub fn new(config: &MovementConfig) -> Self {
+        let mut repeat = Timer::from_seconds(config.repeat_interval, TimerMode::Once);
+        let mut dash_cooldown = Timer::from_seconds(config.dash_cooldown, TimerMode::Once);
+        // Start ready to move and dash.
+        repeat.tick(repeat.duration());
+        dash_cooldown.tick(dash_cooldown.duration());
+
+        MovementState {
+            facing: Vector3Int::DOWN,
+            repeat,
+            dash_cooldown,
+            step: Timer::default(),
+            move_cost: 1.,
+            slide: None,
+            stepping: false,
+            buffered: None,
+        }
+    }
+
+    /// Starts a step, which completes after `step_duration` scaled by the
+    /// cost of moving off the current terrain.
+    pub fn start_step(&mut self, config: &MovementConfig) {
+        let duration = config.step_duration * self.move_cost;
+        self.step = Timer::from_seconds(duration, TimerMode::Once);
+        self.stepping = true;
+    }
+
+    /// Drops an unfinished step, so no `PlayerStepCompleted` follows it,
+    /// and any press waiting for it.
+    pub fn cancel_step(&mut self) {
+        self.stepping = false;
+        self.buffered = None;
+    }
+}
 
-const DIR_KEY_MAPPING: [(KeyCode, Vector3Int); 4] = [
-    (KeyCode::W, Vector3Int::UP),
-    (KeyCode::S, Vector3Int::DOWN),
-    (KeyCode::A, Vector3Int::LEFT),
-    (KeyCode::D, Vector3Int::RIGHT),
-];
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlayerSettings {
+    /// Number of local players sharing the keyboard (1 or 2).
+    pub player_count: usize,
+}
+
+pub struct PlayerPlugin {
+    pub player_count: usize,
+}
+
+impl Default for PlayerPlugin {
+    fn default() -> Self {
+        PlayerPlugin { player_count: 1 }
+    }
+}
 
-pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(load_player.in_schedule(OnEnter(AppState::Game)))
-            .add_system(spawn_player_renderer)
-            .add_system(player_position)
-            .add_system(update_player_position)
-            .add_system(camera_follow_player);
+        app.insert_resource(PlayerSettings {
+            player_count: self.player_count.clamp(1, PLAYER_SPRITES.len()),
+        })
+        .add_simulation_event::<BumpEvent>()
+        .add_simulation_event::<DashedEvent>()
+        .add_simulation_event::<PlayerStepStarted>()
+        .add_simulation_event::<PlayerStepCompleted>()
+        .init_resource::<MovementConfig>()
+        .init_resource::<MovementRules>()
+        .add_system(load_player.in_schedule(OnEnter(AppState::Game)))
+        // Once the new board's doors are shut and its occupiers counted.
+        .add_system(
+            settle_players
+                .after(collision::update_occupancy)
+                .in_base_set(CoreSet::PostUpdate),
+        )
+        .add_system(spawn_player_renderer)
+        .add_systems(
+            (player_position, follow_free_movement, unstick_players)
+                .chain()
+                .in_set(SimulationSet::Act)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            finish_steps
+                .in_set(SimulationSet::Resolve)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        // Free movement is drawn every frame, and followed on the next tick.
+        .add_system(free_move.in_set(OnUpdate(AppState::Game)))
+        .add_system(update_player_position)
+        .add_system(bump_feedback)
+        .add_system(noclip_indicator)
+        .add_system(log_steps);
+
+        if cfg!(debug_assertions) {
+            app.add_system(toggle_noclip).add_system(cycle_easing);
+        }
     }
 }
 
-fn load_player(mut commands: Commands) {
-    commands.spawn((
-        Player,
-        Position {
-            v: Vector3Int::new(0, 0, 5), // Temp z-index.
-        },
-    ));
+fn spawn_point(index: usize) -> Vector3Int {
+    // Later players line up to the right of the first.
+    Vector3Int::new(0, 0, layer_z(0, PLAYER_Z)) + Vector3Int::RIGHT * index as i32
+}
+
+#[allow(clippy::type_complexity)]
+pub fn load_player(
+    mut commands: Commands,
+    config: Res<MovementConfig>,
+    settings: Res<PlayerSettings>,
+    mut players: Query<(
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        Option<&mut MoveTween>,
+        Option<&mut Transform>,
+    )>,
+    projection: Res<GridProjection>,
+) {
+    // Players from the previous map keep their health and only move back to
+    // the start.
+    if !players.is_empty() {
+        for (player, position, state, tween, transform) in players.iter_mut() {
+            let v = spawn_point(player.index);
+            place_player(v, position, state, tween, transform, &projection);
+        }
+        return;
+    }
+
+    for index in 0..settings.player_count {
+        commands.spawn((
+            Player { index },
+            Inspectable::new(format!("Player {}", index + 1)),
+            Occupier,
+            Health::new(PLAYER_HEALTH),
+            Inventory::default(),
+            Currency::default(),
+            Equipment::default(),
+            StatSheet::default(),
+            Initiative {
+                value: PLAYER_INITIATIVE,
+            },
+            MovementState::new(&config),
+            Position {
+                v: spawn_point(index),
+            },
+        ));
+    }
+}
+
+/// Puts a player down on `v` at rest, sprite and all.
+pub fn place_player(
+    v: Vector3Int,
+    mut position: Mut<Position>,
+    mut state: Mut<MovementState>,
+    tween: Option<Mut<MoveTween>>,
+    transform: Option<Mut<Transform>>,
+    projection: &GridProjection,
+) {
+    position.v = v;
+    state.cancel_step();
+    state.slide = None;
+    let v = get_world_position(&position, projection);
+    if let Some(mut tween) = tween {
+        *tween = MoveTween::at(v);
+    }
+    if let Some(mut transform) = transform {
+        transform.translation = v;
+    }
+}
+
+/// Moves players whose spawn point has no ground, is walled off or is
+/// taken to the nearest free tile, once a map has loaded. A map with no
+/// free tile near its spawn point cannot be played.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn settle_players(
+    mut loaded: EventReader<BoardLoadedEvent>,
+    board: BoardQuery,
+    server: Res<AssetServer>,
+    scene: Res<SceneHandle>,
+    projection: Res<GridProjection>,
+    mut error: ResMut<MapError>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut players: Query<(
+        Entity,
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        Option<&mut MoveTween>,
+        Option<&mut Transform>,
+    )>,
+) {
+    if loaded.iter().count() == 0 {
+        return;
+    }
+    let map = server
+        .get_handle_path(&scene.0)
+        .map_or("?".into(), |p| p.path().display().to_string());
+
+    let mut players: Vec<_> = players.iter_mut().collect();
+    players.sort_by_key(|(_, player, ..)| player.index);
+    let mut taken: Vec<Vector3Int> = Vec::new();
+    for (entity, player, position, mut state, tween, transform) in players {
+        // Presses from the last map are not carried into this one.
+        state.buffered = None;
+        let spawn = position.v;
+        // Players start on ground anyone could stand on, whatever they wear.
+        let found = (board.cells_near(spawn, SPAWN_SEARCH_RADIUS).into_iter()).find(|v| {
+            board.is_standable_by(*v, Some(entity), Capabilities::NONE) && !taken.contains(v)
+        });
+        let Some(v) = found else {
+            error.0 = format!(
+                "Map `{}` has nowhere for player {} to stand within {} tiles of {:?}.",
+                map,
+                player.index + 1,
+                SPAWN_SEARCH_RADIUS,
+                spawn
+            );
+            next_state.set(AppState::Error);
+            return;
+        };
+        if v != spawn {
+            warn!(
+                "Spawn point {:?} of player {} in `{}` is not free; using {:?}.",
+                spawn,
+                player.index + 1,
+                map,
+                v
+            );
+            place_player(v, position, state, tween, transform, &projection);
+        }
+        taken.push(v);
+    }
 }
 
 fn spawn_player_renderer(
     mut commands: Commands,
-    query: Query<(Entity, &Position), Added<Player>>,
+    query: Query<(Entity, &Player, &Position), Added<Player>>,
     assets: Res<GraphicsAssets>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+) {
+    for (entity, player, position) in query.iter() {
+        let index = PLAYER_SPRITES[player.index % PLAYER_SPRITES.len()];
+        let mut sprite = TextureAtlasSprite::new(index);
+        let (size, anchor) = sprite_shape(&metadata.0, index, projection.sprite_size());
+        sprite.custom_size = Some(size);
+        sprite.anchor = anchor;
+
+        let v = get_world_position(position, &projection);
+        commands.entity(entity).insert((
+            MoveTween::at(v),
+            SpriteSheetBundle {
+                sprite,
+                texture_atlas: assets.sprite_texture.clone(),
+                transform: Transform::from_translation(v),
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+/// What players' steps, bumps, dashes and pushes tell the rest of the game.
+#[derive(SystemParam)]
+struct MoveEvents<'w> {
+    bumps: EventWriter<'w, BumpEvent>,
+    dashes: EventWriter<'w, DashedEvent>,
+    steps: EventWriter<'w, PlayerStepStarted>,
+    pushes: EventWriter<'w, BlockPushedEvent>,
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn player_position(
+    input: ActionInput,
+    config: Res<MovementConfig>,
+    rules: Res<MovementRules>,
+    fixed: Res<FixedTime>,
+    turns: Turns,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    occupancy: Res<Occupancy>,
+    mut events: MoveEvents,
+    mut query: Query<(
+        Entity,
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        Option<&StatusEffects>,
+        Option<&StatSheet>,
+        Option<&Capabilities>,
+        Option<&mut ActionPoints>,
+    )>,
+    mut pushables: Query<&mut Position, (With<Pushable>, Without<Player>)>,
+    links: Query<(&LayerLink, &Position), (Without<Player>, Without<Pushable>)>,
+    grid: Res<GridKind>,
+    meta: Res<MapMeta>,
 ) {
-    let Ok((entity, position)) = query.get_single() else { return };
+    let moves = move_actions(*grid);
+    let diagonals = meta.diagonals_allowed && *grid == GridKind::Square;
+    // Occupancy only catches up after this system, so cells entered by an
+    // earlier player this tick are tracked here.
+    let mut entered: Vec<Vector3Int> = Vec::new();
+    let free = |v: Vector3Int, entity: Entity, capabilities, entered: &Vec<Vector3Int>| {
+        rules.ignores_collision()
+            || !collision.is_blocked_for(v, capabilities)
+                && occupancy.get(v).is_none_or(|e| e == entity)
+                && !entered.iter().any(|e| e.manhattan(v) == 0)
+    };
 
-    let mut sprite = TextureAtlasSprite::new(95); // Temporary value.
-    sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
+    if rules.is_free() {
+        return;
+    }
+
+    for (entity, player, mut position, mut state, effects, sheet, own, points) in query.iter_mut() {
+        let index = player.index;
+        let capabilities = actor_capabilities((own, sheet, effects));
+
+        let mut interval = config.repeat_interval;
+        if input.pressed(index, Action::Sprint) {
+            interval *= config.sprint_interval_scale;
+        }
+        interval *= state.move_cost;
+        // The stat sheet counts status effects in with equipment.
+        interval *= sheet.map_or_else(
+            || effects.map_or(1., |e| e.interval_scale()),
+            |s| s.interval_scale,
+        );
+        state.repeat.set_duration(Duration::from_secs_f32(interval));
+        state
+            .dash_cooldown
+            .set_duration(Duration::from_secs_f32(config.dash_cooldown));
+        state.repeat.tick(fixed.period);
+        state.dash_cooldown.tick(fixed.period);
+        // Rooted players neither step nor dash, but keep any slide for later.
+        if effects.is_some_and(|e| e.is_rooted()) || !turns.queue.may_act(entity) {
+            continue;
+        }
+        // Points only count on a turn.
+        let mut points = points.filter(|_| turns.queue.is_active());
+        let pass = input.just_pressed(index, Action::Pass);
+        if let Some(points) = points.as_mut().filter(|_| pass) {
+            points.pass();
+            continue;
+        }
+
+        // A fresh press moves straight away, holding repeats on the interval.
+        let slide = state.slide.take();
+        let fresh = (moves.iter()).find(|(action, _)| input.just_pressed(index, *action));
+        let mut pressed = slide.or_else(|| {
+            fresh
+                .or_else(|| {
+                    moves
+                        .iter()
+                        .filter(|_| state.repeat.finished())
+                        .find(|(action, _)| input.pressed(index, *action))
+                })
+                .map(|(_, dir)| *dir)
+        });
+        // Another direction held across the first makes the step diagonal.
+        if let Some(dir) = pressed.as_mut().filter(|_| diagonals && slide.is_none()) {
+            let across = moves.iter().find(|(action, d)| {
+                d.x * dir.x + d.y * dir.y == 0 && input.pressed(index, *action)
+            });
+            if let Some((_, d)) = across {
+                *dir += *d;
+            }
+        }
+        // A fresh press late in a step waits for the step to complete, and
+        // then goes ahead of whatever is held. Slides and transitions drop
+        // it, as they do presses.
+        let ending = state.step.remaining_secs() <= config.input_buffer;
+        if slide.is_some() || input.is_suppressed() {
+            state.buffered = None;
+        } else if state.stepping && ending && fresh.is_some() {
+            state.buffered = pressed.take();
+        } else if !state.stepping {
+            pressed = state.buffered.take().or(pressed);
+        }
+
+        if let Some(dir) = pressed {
+            state.facing = dir;
+            state.repeat.reset();
+
+            let target = step_target(&links, &current, position.v, dir);
+            // There is nothing to stand on outside the board or over a gap.
+            if current.has_ground(target) {
+                // One-way cells stop steps the other way as walls do.
+                let against =
+                    !rules.ignores_collision() && !collision.allows_step(position.v, target, dir);
+                let mut blocked = against || !free(target, entity, capabilities, &entered);
+                let other = occupancy.get(target).filter(|e| *e != entity);
+                let shoves = other.is_some() && !rules.ignores_collision() && !against;
+                // On a turn, whatever is in the way costs an attack to strike
+                // or shove, and a step what its cell costs to enter. Walls
+                // and doors are bumped for nothing.
+                let cost = match (shoves, blocked) {
+                    (true, _) => turns.config.attack_cost,
+                    (false, true) => 0.,
+                    (false, false) => collision.step_cost(target, capabilities),
+                };
+                let paid = pay_points(&mut points, cost);
+                if let Some(other) = other.filter(|_| shoves && paid) {
+                    // Walking into a block pushes it, otherwise occupiers block.
+                    blocked = true;
+                    if let Ok(mut block) = pushables.get_mut(other) {
+                        let from = block.v;
+                        if !entered
+                            .iter()
+                            .any(|e| e.manhattan(current.wrap(from + dir)) == 0)
+                            && puzzles::try_push(&mut block, dir, &current, &collision, &occupancy)
+                        {
+                            blocked = false;
+                            events.pushes.send(BlockPushedEvent {
+                                entity: other,
+                                from,
+                            });
+                        }
+                    }
+                }
+                if !paid {
+                    state.buffered = None;
+                } else if blocked {
+                    state.buffered = None;
+                    events.bumps.send(BumpEvent { entity, at: target });
+                } else {
+                    events.steps.send(PlayerStepStarted {
+                        entity,
+                        from: position.v,
+                        to: target,
+                        dir,
+                    });
+                    position.v = target;
+                    state.start_step(&config);
+                    entered.push(target);
+                }
+            }
+        }
 
-    let v = get_world_position(position);
-    commands.entity(entity).insert(SpriteSheetBundle {
-        sprite,
-        texture_atlas: assets.sprite_texture.clone(),
-        transform: Transform::from_translation(v),
-        ..Default::default()
-    });
+        if input.just_pressed(index, Action::Dash) && state.dash_cooldown.finished() {
+            let from = position.v;
+            let mut to = from;
+            // Dashes cross pits, but only land past them.
+            let mut over = from;
+            for _ in 0..config.dash_distance {
+                let next = step_target(&links, &current, over, state.facing);
+                let against = !collision.allows_step(over, next, state.facing);
+                if !current.has_ground(next) || (against && !rules.ignores_collision()) {
+                    break;
+                }
+                over = next;
+                let pit =
+                    collision.is_pit(next) && !collision.blocks(next, CollisionFlags::BLOCK_WALK);
+                if pit && !rules.ignores_collision() {
+                    continue;
+                }
+                if !free(next, entity, capabilities, &entered) {
+                    break;
+                }
+                to = next;
+            }
+
+            // On a turn, a dash costs what its landing cell does to enter,
+            // and ends the turn.
+            if to != from && pay_points(&mut points, collision.step_cost(to, capabilities)) {
+                events.steps.send(PlayerStepStarted {
+                    entity,
+                    from,
+                    to,
+                    dir: state.facing,
+                });
+                position.v = to;
+                state.start_step(&config);
+                entered.push(to);
+                state.dash_cooldown.reset();
+                events.dashes.send(DashedEvent { entity, from, to });
+                if let Some(points) = points.as_mut() {
+                    points.pass();
+                }
+            }
+        }
+    }
+}
+
+/// Where a step of `dir` from `from` lands, and so what it bumps into:
+/// wrapped onto the board and moved onto the layer of any link there.
+pub fn step_target<F: bevy::ecs::query::ReadOnlyWorldQuery>(
+    links: &Query<(&LayerLink, &Position), F>,
+    current: &CurrentBoard,
+    from: Vector3Int,
+    dir: Vector3Int,
+) -> Vector3Int {
+    enter_layer(links, current.wrap(from + dir))
+}
+
+/// `v` moved onto the layer of any link on its cell, keeping its z-index
+/// within the layer. Movement into the cell is checked on that layer, so a
+/// link is only reachable from where its layer has ground.
+fn enter_layer<F: bevy::ecs::query::ReadOnlyWorldQuery>(
+    links: &Query<(&LayerLink, &Position), F>,
+    v: Vector3Int,
+) -> Vector3Int {
+    let Some((LayerLink(layer), _)) = links.iter().find(|(_, p)| p.v.manhattan(v) == 0) else {
+        return v;
+    };
+    let offset = v.z - layer_z(layer_of(v.z), 0);
+    Vector3Int::new(v.x, v.y, layer_z(*layer, offset))
 }
 
-fn player_position(keys: ResMut<Input<KeyCode>>, mut query: Query<&mut Position, With<Player>>) {
-    let Ok(mut position) = query.get_single_mut() else { return };
+/// Moves players' sprites continuously in free movement.
+fn free_move(
+    input: ActionInput,
+    rules: Res<MovementRules>,
+    time: Res<Time>,
+    current: Res<CurrentBoard>,
+    links: Query<(&LayerLink, &Position), Without<Player>>,
+    mut query: Query<(&Player, &Position, &mut Transform)>,
+    projection: Res<GridProjection>,
+) {
+    if !rules.is_free() {
+        return;
+    }
 
-    for (key, dir) in DIR_KEY_MAPPING {
-        if keys.just_pressed(key) {
-            position.v += dir;
+    for (player, position, mut transform) in query.iter_mut() {
+        let mut dir = Vec2::ZERO;
+        for (action, v) in MOVE_ACTIONS {
+            if input.pressed(player.index, action) {
+                dir += Vec2::new(v.x as f32, v.y as f32);
+            }
+        }
+        if dir == Vec2::ZERO {
+            continue;
+        }
+
+        let step = dir.normalize() * rules.free_speed * time.delta_seconds();
+        let moved = transform.translation + projection.cell_to_world(step).extend(0.);
+        let cell = projection.cell_at(moved.truncate(), position.v.z);
+        let v = enter_layer(&links, current.wrap(cell));
+        // Free movement still stays on the board.
+        if current.has_ground(v) {
+            // Crossing a wrapping edge carries the sprite to the far side.
+            let (dx, dy) = (v.x - cell.x, v.y - cell.y);
+            let seam = projection.cell_to_world(Vec2::new(dx as f32, dy as f32));
+            transform.translation = moved + seam.extend(0.);
+        }
+    }
+}
+
+/// Keeps players' `Position` on the tile under their sprite in free
+/// movement.
+#[allow(clippy::too_many_arguments)]
+fn follow_free_movement(
+    input: ActionInput,
+    rules: Res<MovementRules>,
+    current: Res<CurrentBoard>,
+    links: Query<(&LayerLink, &Position), Without<Player>>,
+    mut started: EventWriter<PlayerStepStarted>,
+    mut completed: EventWriter<PlayerStepCompleted>,
+    mut query: Query<(
+        Entity,
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        &Transform,
+    )>,
+    projection: Res<GridProjection>,
+) {
+    if !rules.is_free() {
+        return;
+    }
+
+    for (entity, player, mut position, mut state, transform) in query.iter_mut() {
+        for (action, v) in MOVE_ACTIONS {
+            if input.pressed(player.index, action) {
+                state.facing = v;
+            }
+        }
+
+        let cell = projection.cell_at(transform.translation.truncate(), position.v.z);
+        let v = enter_layer(&links, current.wrap(cell));
+        // Crossing into a new cell starts and completes a step at once.
+        if v != position.v && current.has_ground(v) {
+            started.send(PlayerStepStarted {
+                entity,
+                from: position.v,
+                to: v,
+                dir: state.facing,
+            });
+            completed.send(PlayerStepCompleted { entity, at: v });
+            position.v = v;
+        }
+    }
+}
+
+/// Completes steps once their time is up, whenever the sprite arrives.
+fn finish_steps(
+    fixed: Res<FixedTime>,
+    mut query: Query<(Entity, &Position, &mut MovementState), With<Player>>,
+    mut steps: EventWriter<PlayerStepCompleted>,
+) {
+    for (entity, position, mut state) in query.iter_mut() {
+        if state.stepping && state.step.tick(fixed.period).finished() {
+            state.stepping = false;
+            steps.send(PlayerStepCompleted {
+                entity,
+                at: position.v,
+            });
         }
     }
 }
 
 fn update_player_position(
-    mut query: Query<(&Position, &mut Transform), With<Player>>,
+    mut query: Query<(&Position, &MovementState, &mut MoveTween, &mut Transform), With<Player>>,
+    config: Res<MovementConfig>,
+    rules: Res<MovementRules>,
     time: Res<Time>,
+    fixed: Res<FixedTime>,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
 ) {
-    let Ok((position, mut transform)) = query.get_single_mut() else { return };
-    let target = get_world_position(position);
-    let d = (target - transform.translation).length();
-    if d > POSITION_TOLERANCE {
-        transform.translation = transform
-            .translation
-            .lerp(target, PLAYER_SPEED * time.delta_seconds());
-    } else {
-        transform.translation = target;
+    if rules.is_free() {
+        return;
+    }
+
+    for (position, state, mut tween, mut transform) in query.iter_mut() {
+        let target = get_world_position(position, &projection);
+        // A new target, even mid-step, starts from where the sprite is drawn.
+        // Steps across a wrapping edge start from beyond the opposite edge,
+        // where ghosts show the same cells, so the camera never jumps.
+        if target != tween.end {
+            let from = nearest_copy(transform.translation, target, &current.bounds, &projection);
+            transform.translation = from;
+            tween.retarget(from, target);
+            // The move happened on the last tick, which is already behind
+            // the frame being drawn.
+            tween.elapsed = tick_alpha(&fixed) * fixed.period.as_secs_f32();
+        }
+
+        // Arriving is only drawn; the step completes on its own tick.
+        let arrived = if config.easing == Easing::Exponential {
+            let d = (target - transform.translation).length();
+            if d > POSITION_TOLERANCE {
+                transform.translation = transform
+                    .translation
+                    .lerp(target, PLAYER_SPEED * time.delta_seconds());
+            }
+            d <= POSITION_TOLERANCE
+        } else {
+            tween.elapsed += time.delta_seconds();
+            let duration = config.step_duration * state.move_cost;
+            let t = (tween.elapsed / duration.max(f32::EPSILON)).min(1.);
+            transform.translation = tween.start.lerp(tween.end, config.easing.apply(t));
+            t >= 1.
+        };
+
+        if arrived {
+            transform.translation = target;
+        }
+    }
+}
+
+fn bump_feedback(
+    mut bumps: EventReader<BumpEvent>,
+    mut query: Query<(&Position, &mut Transform, Option<&mut MoveTween>)>,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+) {
+    for bump in bumps.iter() {
+        let Ok((position, mut transform, tween)) = query.get_mut(bump.entity) else { continue };
+        let delta = current.delta(position.v, bump.at);
+        let dir = Vec2::new(delta.x as f32, delta.y as f32);
+        // The movement tween eases the sprite back onto its cell.
+        transform.translation += projection.cell_to_world(dir * BUMP_OFFSET).extend(0.);
+        if let Some(mut tween) = tween {
+            let end = tween.end;
+            tween.retarget(transform.translation, end);
+        }
+    }
+}
+
+fn log_steps(mut steps: EventReader<PlayerStepStarted>) {
+    for step in steps.iter() {
+        debug!(
+            "{:?} stepping {:?} from {:?} to {:?}.",
+            step.entity, step.dir, step.from, step.to
+        );
+    }
+}
+
+/// Debug keys: F8 toggles noclip, F9 toggles free movement within it.
+fn toggle_noclip(keys: Res<Input<KeyCode>>, mut rules: ResMut<MovementRules>) {
+    if keys.just_pressed(KeyCode::F8) {
+        rules.noclip = !rules.noclip;
+        info!("Noclip {}.", if rules.noclip { "on" } else { "off" });
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        rules.free_movement = !rules.free_movement;
+        info!(
+            "Free movement {}.",
+            if rules.free_movement { "on" } else { "off" }
+        );
+    }
+}
+
+/// Debug key: F7 cycles the movement easing. Shift+F7 is the event log's.
+fn cycle_easing(keys: Res<Input<KeyCode>>, mut config: ResMut<MovementConfig>) {
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if keys.just_pressed(KeyCode::F7) && !shift {
+        config.easing = config.easing.next();
+        info!("Movement easing: {:?}.", config.easing);
     }
 }
 
-fn camera_follow_player(
-    mut camera: Query<&mut Transform, With<Camera2d>>,
-    player: Query<&Transform, (With<Player>, Without<Camera2d>)>,
+/// When collision comes back on, moves any player left inside a wall or
+/// another occupier to the nearest free tile.
+#[allow(clippy::too_many_arguments)]
+fn unstick_players(
+    rules: Res<MovementRules>,
+    config: Res<MovementConfig>,
+    board: BoardQuery,
+    mut steps: EventWriter<PlayerStepStarted>,
+    mut query: Query<(Entity, &mut Position, &mut MovementState, CapabilitySources), With<Player>>,
 ) {
-    let Ok(mut c) = camera.get_single_mut() else { return };
-    let Ok(p) = player.get_single() else { return };
+    if !rules.is_changed() || rules.ignores_collision() {
+        return;
+    }
+
+    let mut taken: Vec<Vector3Int> = Vec::new();
+    for (entity, mut position, mut state, sources) in query.iter_mut() {
+        let capabilities = actor_capabilities(sources);
+        let found = (board
+            .cells_near(position.v, UNSTICK_SEARCH_RADIUS)
+            .into_iter())
+        .find(|v| board.is_standable_by(*v, Some(entity), capabilities) && !taken.contains(v));
+
+        match found {
+            Some(v) => {
+                if v != position.v {
+                    info!("Moved {:?} out of a wall to {:?}.", entity, v);
+                    steps.send(PlayerStepStarted {
+                        entity,
+                        from: position.v,
+                        to: v,
+                        dir: Vector3Int::default(),
+                    });
+                    position.v = v;
+                    state.start_step(&config);
+                }
+                taken.push(v);
+            }
+            None => warn!("No free tile near {:?} for {:?}.", position.v, entity),
+        }
+    }
+}
 
-    c.translation = p.translation;
+/// Fades players while they ignore collision.
+fn noclip_indicator(
+    rules: Res<MovementRules>,
+    mut query: Query<&mut TextureAtlasSprite, With<Player>>,
+) {
+    let alpha = if rules.ignores_collision() {
+        NOCLIP_ALPHA
+    } else {
+        1.
+    };
+    for mut sprite in query.iter_mut() {
+        // Only write on change, so sprite change detection stays quiet.
+        if sprite.color.a() != alpha {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{headless_game, run_ticks, Script, LOAD_FRAMES};
+
+    const TICKS: u64 = 12;
+
+    /// Where the first player stands once the map has loaded and after
+    /// each tick up to `TICKS`, drawing `fps` frames a second. Right is held from tick 1
+    /// and down tapped on tick 2, early in the step right.
+    fn trace(fps: f64) -> Vec<Vector3Int> {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / fps));
+        app.insert_resource(Script(vec![
+            (1, 0, Action::MoveRight, true),
+            (2, 0, Action::MoveDown, true),
+            (3, 0, Action::MoveDown, false),
+        ]));
+        let mut players = app.world.query_filtered::<&Position, With<Player>>();
+        (0..=TICKS)
+            .map(|tick| {
+                run_ticks(&mut app, tick);
+                players.single(&app.world).v
+            })
+            .collect()
+    }
+
+    #[test]
+    fn presses_late_in_a_step_wait_for_it_then_win() {
+        let at_60 = trace(60.);
+        let start = at_60[0];
+        let (right, down) = (Vector3Int::new(1, 0, 0), Vector3Int::new(0, -1, 0));
+
+        // The step right is not cut short by the press down...
+        assert_eq!(at_60[1], start + right);
+        assert!(at_60[2..5].iter().all(|v| *v == start + right));
+        // ...which goes next, though right is still held and was first.
+        let next = (at_60.iter()).find(|v| **v != start + right && **v != start);
+        assert_eq!(next, Some(&(start + right + down)));
+
+        // Slower frames may run the first tick on the one the map loads on,
+        // so only the ticks input was given on are compared.
+        for fps in [30., 144., 240.] {
+            let same = trace(fps)[1..] == at_60[1..];
+            assert!(same, "input went differently at {} fps", fps);
+        }
+    }
+
+    #[test]
+    fn dashes_cross_pits_that_stop_walking() {
+        let mut app = headless_game("fixtures/collision.json", Duration::from_secs_f64(1. / 60.));
+        // The pit is the cell below the start, with ground past it.
+        app.insert_resource(Script(vec![
+            (1, 0, Action::MoveDown, true),
+            (2, 0, Action::MoveDown, false),
+            (10, 0, Action::Dash, true),
+            (11, 0, Action::Dash, false),
+        ]));
+        let mut players = app.world.query_filtered::<&Position, With<Player>>();
+        run_ticks(&mut app, 0);
+        let start = players.single(&app.world).v;
+
+        run_ticks(&mut app, 9);
+        assert_eq!(players.single(&app.world).v, start);
+        run_ticks(&mut app, 12);
+        assert_eq!(players.single(&app.world).v, start + Vector3Int::DOWN * 3);
+    }
+
+    /// Where the first player stands once `map` has loaded.
+    fn settled_on(map: &str) -> Vector3Int {
+        let mut app = headless_game(map, Duration::from_secs_f64(1. / 60.));
+        run_ticks(&mut app, 0);
+        let mut players = app.world.query_filtered::<&Position, With<Player>>();
+        players.single(&app.world).v
+    }
+
+    #[test]
+    fn players_move_off_walled_spawn_points() {
+        // Walls on the spawn point and round it, but for the cell right.
+        let v = settled_on("fixtures/blocked_spawn.json");
+        assert_eq!(v, spawn_point(0) + Vector3Int::RIGHT);
+    }
+
+    #[test]
+    fn players_move_off_spawn_points_with_no_ground() {
+        // No ground on the spawn point or round it, but for the cell up.
+        let v = settled_on("fixtures/void_spawn.json");
+        assert_eq!(v, spawn_point(0) + Vector3Int::UP);
+    }
+
+    #[test]
+    fn maps_with_nowhere_to_stand_fail_naming_the_map() {
+        let map = "fixtures/walled_in.json";
+        let mut app = headless_game(map, Duration::from_secs_f64(1. / 60.));
+        for _ in 0..LOAD_FRAMES {
+            if app.world.resource::<State<AppState>>().0 == AppState::Error {
+                break;
+            }
+            app.update();
+        }
+
+        assert_eq!(app.world.resource::<State<AppState>>().0, AppState::Error);
+        let error = &app.world.resource::<MapError>().0;
+        assert!(error.contains(map), "{}", error);
+        assert!(error.contains("nowhere for player 1 to stand"), "{}", error);
+    }
 }
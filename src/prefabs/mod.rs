@@ -0,0 +1,794 @@
+use std::collections::HashMap;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    collision::{Footprint, Occupier},
+    combat::Health,
+    crafting::Station,
+    difficulty::{rescale, BaseHealth, DifficultyModifiers},
+    elements::{Element, Resistances},
+    equipment::Equippable,
+    followers::Follower,
+    inspect::Inspectable,
+    layer_of, layer_z,
+    loot::{Loot, LootTable},
+    npc::{Chaser, RangedAi, NPC_Z},
+    objects::{Door, ObjectSprite, Trigger, DOOR_OPEN_SPRITE, OBJECT_Z},
+    persistence::Persistent,
+    progression::Exit,
+    puzzles::{Pushable, PUSHABLE_Z},
+    shop::{Shop, StockEntry},
+    terrain::Capabilities,
+    turns::Initiative,
+    vectors::Vector3Int,
+    Position,
+};
+
+/// The prefabs every map can use. Files ending in `.prefabs.json` load as
+/// prefab definitions rather than scenes.
+pub const PREFABS_FILE: &str = "game.prefabs.json";
+
+/// Prefab definitions by name, as written in a `.prefabs.json` file.
+/// Entries are kept as raw JSON so that one bad entry does not fail the
+/// whole file.
+#[derive(Deserialize, bevy::reflect::TypeUuid, Debug)]
+#[uuid = "2f0b8c7e-91d4-4a36-b5e1-7c2d9a4e6f18"]
+pub struct PrefabFile(HashMap<String, serde_json::Value>);
+
+/// How a prefab moves and fights, if at all.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AiKind {
+    /// Chases players within `range` cells.
+    Chaser {
+        #[serde(default = "default_range")]
+        range: i32,
+    },
+    /// Follows the first player, `spacing` cells behind.
+    Follower {
+        #[serde(default = "default_spacing")]
+        spacing: u32,
+    },
+    /// Shoots `projectile` prefabs at players from between `min_range` and
+    /// `max_range` cells away.
+    Ranged {
+        #[serde(default = "default_min_range")]
+        min_range: i32,
+        #[serde(default = "default_max_range")]
+        max_range: i32,
+        projectile: String,
+    },
+}
+
+impl AiKind {
+    /// Adds the component that runs this AI to `entity`.
+    fn insert(&self, entity: &mut EntityCommands) {
+        match self {
+            AiKind::Chaser { range } => {
+                entity.insert(Chaser::new(*range));
+            }
+            AiKind::Follower { spacing } => {
+                entity.insert(Follower::new(Entity::PLACEHOLDER, *spacing));
+            }
+            AiKind::Ranged {
+                min_range,
+                max_range,
+                projectile,
+            } => {
+                entity.insert(RangedAi::new(*min_range, *max_range, projectile));
+            }
+        }
+    }
+}
+
+fn default_range() -> i32 {
+    8
+}
+
+fn default_spacing() -> u32 {
+    1
+}
+
+fn default_min_range() -> i32 {
+    2
+}
+
+fn default_max_range() -> i32 {
+    6
+}
+
+/// What happens when a player meets the prefab.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interactable {
+    Door,
+    Trigger,
+    Exit,
+    Pushable,
+    /// Trades its `stock` with players who bump into it.
+    Shop,
+    /// Crafts the recipes that need its `station_id` for players who bump
+    /// into it.
+    Station,
+}
+
+/// A named bundle of components, spawned by map objects, spawners and
+/// anything else that creates entities from data.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Prefab {
+    /// Shown when inspected. The prefab's key once loaded, if not given.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub sprite: Option<usize>,
+    #[serde(default)]
+    pub health: Option<u32>,
+    #[serde(default)]
+    pub ai: Option<AiKind>,
+    /// Where it comes in each round of turn-based mode, if an actor.
+    #[serde(default)]
+    pub initiative: i32,
+    /// Width and height in cells, for prefabs larger than one.
+    #[serde(default)]
+    pub footprint: Option<[i32; 2]>,
+    /// What terrain it can cross, such as `["fly"]`.
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    /// What the damage it deals is made of, by attack or as a projectile.
+    #[serde(default)]
+    pub element: Option<Element>,
+    /// Percent of each element's damage it shrugs off, such as
+    /// `{"fire": 50, "ice": -25}`.
+    #[serde(default)]
+    pub resistances: Option<Resistances>,
+    #[serde(default)]
+    pub interactable: Option<Interactable>,
+    /// What a shop sells, if it is one.
+    #[serde(default)]
+    pub stock: Vec<StockEntry>,
+    /// Which recipes a crafting station makes, if it is one.
+    #[serde(default)]
+    pub station_id: Option<String>,
+    /// Worn in a slot for its stat modifiers, if an item that can be.
+    #[serde(default)]
+    pub equip: Option<Equippable>,
+    /// Rolled for items to leave behind when defeated.
+    #[serde(default)]
+    pub loot: Option<LootTable>,
+    /// Kept when the players move on to another map, as a pet is.
+    #[serde(default)]
+    pub persistent: bool,
+    /// Keys this version does not know, warned about once loaded.
+    #[serde(flatten)]
+    unknown: HashMap<String, serde_json::Value>,
+    /// Its key in the prefab file.
+    #[serde(skip)]
+    id: String,
+    /// Times its definition has changed since the file first loaded.
+    #[serde(skip)]
+    revision: u32,
+    /// The JSON it was read from, to tell whether a reload changed it.
+    #[serde(skip)]
+    source: serde_json::Value,
+}
+
+impl Prefab {
+    pub fn footprint(&self) -> Option<Footprint> {
+        let [width, height] = self.footprint?;
+        (width > 1 || height > 1).then(|| Footprint::new(width, height))
+    }
+
+    /// Whether it is a creature, which takes up its cells and can be hurt.
+    pub fn is_actor(&self) -> bool {
+        self.ai.is_some() || self.health.is_some()
+    }
+
+    /// Whether it takes up its cells, so that no other occupier can share
+    /// them.
+    pub fn is_occupier(&self) -> bool {
+        self.is_actor()
+            || matches!(
+                self.interactable,
+                Some(Interactable::Pushable | Interactable::Shop | Interactable::Station)
+            )
+    }
+
+    /// The z-index within a layer's band it is placed at.
+    fn z_offset(&self) -> i32 {
+        match self.interactable {
+            _ if self.is_actor() => NPC_Z,
+            Some(Interactable::Pushable) => PUSHABLE_Z,
+            _ => OBJECT_Z,
+        }
+    }
+
+    /// Adds the prefab's components to `entity`, placing it on the cell of
+    /// `v` at the prefab's z-index within the layer of `v`.
+    pub fn insert(&self, entity: &mut EntityCommands, v: Vector3Int) {
+        let z = layer_z(layer_of(v.z), self.z_offset());
+        entity.insert(Position {
+            v: Vector3Int::new(v.x, v.y, z),
+        });
+        entity.insert(SpawnedFrom {
+            prefab: self.id.clone(),
+            revision: self.revision,
+        });
+        if let Some(name) = &self.name {
+            entity.insert(Inspectable::new(name));
+        }
+        if let Some(sprite) = self.sprite {
+            entity.insert(ObjectSprite(sprite));
+        }
+        if let Some(health) = self.health {
+            entity.insert(Health::new(health));
+        }
+        // Followers move with their player rather than taking turns.
+        if self.is_actor() && !matches!(self.ai, Some(AiKind::Follower { .. })) {
+            entity.insert(Initiative {
+                value: self.initiative,
+            });
+        }
+        if let Some(ai) = &self.ai {
+            ai.insert(entity);
+        }
+        if let Some(footprint) = self.footprint() {
+            entity.insert(footprint);
+        }
+        if !self.capabilities.is_empty() {
+            entity.insert(self.capabilities);
+        }
+        if let Some(element) = self.element {
+            entity.insert(element);
+        }
+        if let Some(resistances) = self.resistances {
+            entity.insert(resistances);
+        }
+        if self.is_occupier() {
+            entity.insert(Occupier);
+        }
+        match self.interactable {
+            Some(Interactable::Door) => {
+                entity.insert(Door {
+                    open: false,
+                    open_sprite: DOOR_OPEN_SPRITE,
+                });
+            }
+            Some(Interactable::Trigger) => {
+                entity.insert(Trigger {
+                    once: true,
+                    consume: false,
+                });
+            }
+            Some(Interactable::Exit) => {
+                entity.insert(Exit);
+            }
+            Some(Interactable::Pushable) => {
+                entity.insert(Pushable);
+            }
+            Some(Interactable::Shop) => {
+                entity.insert(Shop {
+                    stock: self.stock.clone(),
+                });
+            }
+            Some(Interactable::Station) => {
+                entity.insert(Station {
+                    id: self.station_id.clone().unwrap_or_default(),
+                });
+            }
+            None => {}
+        }
+        if let Some(table) = &self.loot {
+            entity.insert(Loot(table.clone()));
+        }
+        if self.persistent {
+            entity.insert(Persistent);
+        }
+    }
+}
+
+/// The prefab an entity was spawned from, and the revision of it that it
+/// has, so that edits to the prefab file reach entities already spawned.
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct SpawnedFrom {
+    pub prefab: String,
+    pub revision: u32,
+}
+
+/// Sent when a reload of the prefab file changes or removes a prefab, with
+/// its definition from before.
+pub struct PrefabChangedEvent {
+    pub name: String,
+    pub previous: Prefab,
+}
+
+/// The loaded prefabs, replaced whenever their file changes.
+#[derive(Default, Resource)]
+pub struct PrefabRegistry {
+    pub handle: Handle<PrefabFile>,
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl PrefabRegistry {
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    /// Every prefab's name, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.prefabs.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Spawns prefab `name` on the cell of `position`, or warns and returns
+/// `None` if there is no such prefab.
+pub fn spawn_prefab(
+    commands: &mut Commands,
+    registry: &PrefabRegistry,
+    name: &str,
+    position: Vector3Int,
+) -> Option<Entity> {
+    let Some(prefab) = registry.get(name) else {
+        warn!("Unknown prefab `{}`.", name);
+        return None;
+    };
+    let mut entity = commands.spawn_empty();
+    prefab.insert(&mut entity, position);
+    Some(entity.id())
+}
+
+pub struct PrefabsPlugin;
+impl Plugin for PrefabsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PrefabRegistry>()
+            .add_event::<PrefabChangedEvent>()
+            // Before the state changes, so maps entered on the frame after
+            // loading finishes find their prefabs.
+            .add_system(load_prefabs.in_base_set(CoreSet::PreUpdate))
+            .add_system(
+                patch_spawned
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(load_prefabs),
+            );
+    }
+}
+
+/// Reads the prefab file whenever it loads or changes. A prefab whose JSON
+/// changed moves on a revision, and the change is sent on for entities
+/// already spawned from it.
+fn load_prefabs(
+    mut events: EventReader<AssetEvent<PrefabFile>>,
+    files: Res<Assets<PrefabFile>>,
+    mut registry: ResMut<PrefabRegistry>,
+    mut changed: EventWriter<PrefabChangedEvent>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(file) = files.get(handle).filter(|_| *handle == registry.handle) else {
+            continue;
+        };
+
+        let mut prefabs = HashMap::new();
+        for (name, value) in file.0.iter() {
+            match Prefab::deserialize(value) {
+                Ok(mut prefab) => {
+                    prefab.name.get_or_insert_with(|| name.clone());
+                    prefab.id = name.clone();
+                    prefab.source = value.clone();
+                    if let Some(previous) = registry.prefabs.get(name) {
+                        prefab.revision = previous.revision;
+                        if previous.source != prefab.source {
+                            prefab.revision += 1;
+                        }
+                    }
+                    for key in prefab.unknown.keys() {
+                        warn!("Prefab `{}` has unknown key `{}`.", name, key);
+                    }
+                    prefabs.insert(name.clone(), prefab);
+                }
+                Err(err) => warn!("Skipping prefab `{}`: {}.", name, err),
+            }
+        }
+        info!("Loaded {} prefabs.", prefabs.len());
+        let previous = std::mem::replace(&mut registry.prefabs, prefabs);
+        for (name, previous) in previous {
+            let revision = registry.get(&name).map(|p| p.revision);
+            if revision != Some(previous.revision) {
+                changed.send(PrefabChangedEvent { name, previous });
+            }
+        }
+    }
+}
+
+/// What patching an entity for a changed prefab reads and changes of it.
+type PatchedItem<'a> = (
+    Entity,
+    &'a mut SpawnedFrom,
+    Option<&'a mut ObjectSprite>,
+    Option<&'a mut TextureAtlasSprite>,
+    Option<&'a mut Health>,
+    Option<&'a mut BaseHealth>,
+    Option<&'a mut Chaser>,
+    Option<&'a mut RangedAi>,
+    Option<&'a mut Follower>,
+);
+
+/// Brings entities spawned from a changed prefab up to its new revision,
+/// patching in place just what changed of their sprite, health, AI and
+/// resistances. Health keeps the share of it left, and enemies' health is
+/// scaled by the difficulty again. Where they are and what they carry or
+/// suffer is theirs and left alone. Entities whose prefab was removed keep
+/// what they have.
+fn patch_spawned(
+    mut commands: Commands,
+    mut events: EventReader<PrefabChangedEvent>,
+    registry: Res<PrefabRegistry>,
+    modifiers: Res<DifficultyModifiers>,
+    mut spawned: Query<PatchedItem>,
+) {
+    for PrefabChangedEvent { name, previous } in events.iter() {
+        let Some(prefab) = registry.get(name) else {
+            let orphans = spawned.iter().filter(|(_, from, ..)| from.prefab == *name);
+            let count = orphans.count();
+            if count > 0 {
+                warn!(
+                    "Prefab `{}` was removed; {} entities spawned from it keep their last values.",
+                    name, count
+                );
+            }
+            continue;
+        };
+        for item in spawned.iter_mut() {
+            let (entity, mut from, object, atlas, health, base, chaser, ranged, follower) = item;
+            // Spawned since the change, from the new revision already.
+            if from.prefab != *name || from.revision >= prefab.revision {
+                continue;
+            }
+            from.revision = prefab.revision;
+
+            if let Some(index) = prefab.sprite.filter(|_| prefab.sprite != previous.sprite) {
+                if let Some(mut object) = object {
+                    object.0 = index;
+                }
+                if let Some(mut atlas) = atlas {
+                    atlas.index = index;
+                }
+            }
+            if let Some(max) = prefab.health.filter(|_| prefab.health != previous.health) {
+                match (health, base) {
+                    (Some(health), Some(mut base)) => {
+                        base.0 = max;
+                        rescale(health, modifiers.enemy_health(max));
+                    }
+                    (Some(health), None) => rescale(health, max),
+                    _ => {}
+                }
+            }
+            if prefab.ai != previous.ai {
+                let mut entity = commands.entity(entity);
+                match (&prefab.ai, chaser, ranged, follower) {
+                    (Some(AiKind::Chaser { range }), Some(mut chaser), ..) => {
+                        chaser.range = *range;
+                    }
+                    (
+                        Some(AiKind::Ranged {
+                            min_range,
+                            max_range,
+                            projectile,
+                        }),
+                        _,
+                        Some(mut ranged),
+                        _,
+                    ) => {
+                        ranged.min_range = *min_range;
+                        ranged.max_range = *max_range;
+                        ranged.projectile = projectile.clone();
+                    }
+                    (Some(AiKind::Follower { spacing }), .., Some(mut follower)) => {
+                        follower.spacing = *spacing;
+                    }
+                    // A different kind of AI altogether, or none.
+                    (ai, ..) => {
+                        entity.remove::<(Chaser, RangedAi, Follower)>();
+                        if let Some(ai) = ai {
+                            ai.insert(&mut entity);
+                        }
+                    }
+                }
+            }
+            if prefab.resistances != previous.resistances {
+                match prefab.resistances {
+                    Some(resistances) => commands.entity(entity).insert(resistances),
+                    None => commands.entity(entity).remove::<Resistances>(),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{asset::AssetPlugin, ecs::system::CommandQueue};
+
+    use super::*;
+    use crate::{
+        equipment::{EquipSlot, StatModifiers},
+        loot::LootEntry,
+        status::{StatusEffect, StatusEffects, StatusKind},
+        vectors::GridRect,
+    };
+
+    /// Every field a prefab can have, each kind of AI and of interactable,
+    /// a key no version knows and an entry that does not parse.
+    const FIXTURE: &str = r#"{
+        "ogre": {
+            "name": "Old Ogre",
+            "sprite": 7,
+            "health": 12,
+            "ai": { "kind": "ranged", "min_range": 3, "max_range": 5, "projectile": "rock" },
+            "initiative": 9,
+            "footprint": [2, 3],
+            "capabilities": ["swim", "fly"],
+            "element": "ice",
+            "resistances": { "fire": 50, "poison": -25 },
+            "loot": {
+                "always": [{ "item": "coin", "count": [1, 3] }],
+                "rolls": 2,
+                "entries": [{ "item": "gem", "weight": 2, "chance": 0.5 }, {}]
+            },
+            "persistent": true,
+            "mood": "grumpy"
+        },
+        "guard": { "ai": { "kind": "chaser", "range": 4 } },
+        "pet": { "ai": { "kind": "follower", "spacing": 2 } },
+        "boots": { "equip": { "slot": "accessory", "speed": 0.25, "vision": 1 } },
+        "door": { "interactable": "door" },
+        "lever": { "interactable": "trigger" },
+        "stairs": { "interactable": "exit" },
+        "crate": { "interactable": "pushable" },
+        "stall": {
+            "interactable": "shop",
+            "stock": [{ "item": "potion", "price": 5, "quantity": 2 }]
+        },
+        "anvil": { "interactable": "station", "station_id": "forge" },
+        "broken": { "health": "lots" }
+    }"#;
+
+    /// The prefab plugin alone, with `json` loaded as the prefab file.
+    fn load(json: &str) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<PrefabFile>()
+            .init_resource::<DifficultyModifiers>()
+            .add_plugin(PrefabsPlugin);
+        let file = serde_json::from_str(json).unwrap();
+        let handle = app.world.resource_mut::<Assets<PrefabFile>>().add(file);
+        app.world.resource_mut::<PrefabRegistry>().handle = handle;
+        // The asset's event is sent at the end of the first frame and
+        // read at the start of the second.
+        app.update();
+        app.update();
+        app
+    }
+
+    fn spawn(app: &mut App, name: &str) -> Entity {
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let registry = app.world.resource::<PrefabRegistry>();
+        let entity = spawn_prefab(&mut commands, registry, name, Vector3Int::new(4, 5, 0));
+        queue.apply(&mut app.world);
+        entity.unwrap()
+    }
+
+    #[test]
+    fn every_field_reaches_what_is_spawned() {
+        let mut app = load(FIXTURE);
+
+        let ogre = spawn(&mut app, "ogre");
+        let ogre = app.world.entity(ogre);
+        assert_eq!(
+            ogre.get::<Position>().unwrap().v,
+            Vector3Int::new(4, 5, layer_z(0, NPC_Z))
+        );
+        assert_eq!(
+            ogre.get::<SpawnedFrom>(),
+            Some(&SpawnedFrom {
+                prefab: "ogre".to_string(),
+                revision: 0,
+            })
+        );
+        assert_eq!(ogre.get::<Inspectable>().unwrap().name, "Old Ogre");
+        assert_eq!(ogre.get::<ObjectSprite>().unwrap().0, 7);
+        let health = ogre.get::<Health>().unwrap();
+        assert_eq!((health.current, health.max), (12, 12));
+        assert_eq!(ogre.get::<Initiative>(), Some(&Initiative { value: 9 }));
+        let ranged = ogre.get::<RangedAi>().unwrap();
+        assert_eq!((ranged.min_range, ranged.max_range), (3, 5));
+        assert_eq!(ranged.projectile, "rock");
+        let footprint = ogre.get::<Footprint>().unwrap();
+        assert_eq!(footprint.0, GridRect::new(0, 0, 2, 3));
+        assert_eq!(
+            ogre.get::<Capabilities>(),
+            Some(&(Capabilities::SWIM | Capabilities::FLY))
+        );
+        assert_eq!(ogre.get::<Element>(), Some(&Element::Ice));
+        assert_eq!(
+            ogre.get::<Resistances>(),
+            Some(&Resistances {
+                fire: 50.,
+                poison: -25.,
+                ..default()
+            })
+        );
+        assert!(ogre.contains::<Occupier>());
+        let loot = &ogre.get::<Loot>().unwrap().0;
+        let always = LootEntry {
+            item: Some("coin".to_string()),
+            weight: 1,
+            count: [1, 3],
+            chance: 1.,
+        };
+        let gem = LootEntry {
+            item: Some("gem".to_string()),
+            weight: 2,
+            count: [1, 1],
+            chance: 0.5,
+        };
+        let nothing = LootEntry {
+            item: None,
+            weight: 1,
+            count: [1, 1],
+            chance: 1.,
+        };
+        assert_eq!(
+            *loot,
+            LootTable {
+                always: vec![always],
+                rolls: 2,
+                entries: vec![gem, nothing],
+            }
+        );
+        assert!(ogre.contains::<Persistent>());
+
+        let guard = spawn(&mut app, "guard");
+        assert_eq!(app.world.get::<Chaser>(guard).unwrap().range, 4);
+        let pet = spawn(&mut app, "pet");
+        let follower = app.world.get::<Follower>(pet).unwrap();
+        assert_eq!(follower.spacing, 2);
+        assert_eq!(follower.target, Entity::PLACEHOLDER);
+        // Followers move with their player rather than taking turns.
+        assert!(app.world.get::<Initiative>(pet).is_none());
+
+        let registry = app.world.resource::<PrefabRegistry>();
+        assert_eq!(
+            registry.get("boots").unwrap().equip,
+            Some(Equippable {
+                slot: EquipSlot::Accessory,
+                modifiers: StatModifiers {
+                    speed: 0.25,
+                    vision: 1,
+                    ..default()
+                },
+            })
+        );
+
+        let door = spawn(&mut app, "door");
+        assert!(!app.world.get::<Door>(door).unwrap().open);
+        let lever = spawn(&mut app, "lever");
+        assert!(app.world.get::<Trigger>(lever).unwrap().once);
+        let stairs = spawn(&mut app, "stairs");
+        assert!(app.world.get::<Exit>(stairs).is_some());
+        let crate_ = spawn(&mut app, "crate");
+        assert!(app.world.get::<Pushable>(crate_).is_some());
+        assert!(app.world.get::<Occupier>(crate_).is_some());
+        let stall = spawn(&mut app, "stall");
+        assert_eq!(
+            app.world.get::<Shop>(stall).unwrap().stock,
+            [StockEntry {
+                item: "potion".to_string(),
+                price: 5,
+                quantity: 2,
+            }]
+        );
+        let anvil = spawn(&mut app, "anvil");
+        assert_eq!(app.world.get::<Station>(anvil).unwrap().id, "forge");
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_entries_spare_the_rest() {
+        let app = load(FIXTURE);
+        let registry = app.world.resource::<PrefabRegistry>();
+
+        assert!(registry.get("broken").is_none());
+        assert_eq!(registry.names().len(), 10);
+        let ogre = registry.get("ogre").unwrap();
+        let unknown: Vec<&str> = ogre.unknown.keys().map(String::as_str).collect();
+        assert_eq!(unknown, ["mood"]);
+        // Named after their key when the file gives no name.
+        let guard = registry.get("guard").unwrap();
+        assert_eq!(guard.name.as_deref(), Some("guard"));
+    }
+
+    #[test]
+    fn edits_to_the_loaded_file_reach_live_entities() {
+        let mut app = load(
+            r#"{
+                "guard": {
+                    "sprite": 3,
+                    "health": 10,
+                    "ai": { "kind": "chaser", "range": 4 },
+                    "resistances": { "fire": 10 }
+                },
+                "archer": {
+                    "health": 6,
+                    "ai": { "kind": "ranged", "min_range": 2, "max_range": 4, "projectile": "arrow" }
+                },
+                "pet": { "ai": { "kind": "follower", "spacing": 2 } }
+            }"#,
+        );
+        let guard = spawn(&mut app, "guard");
+        let archer = spawn(&mut app, "archer");
+        let pet = spawn(&mut app, "pet");
+        // What the guard has been through since it was spawned.
+        app.world.get_mut::<Health>(guard).unwrap().current = 5;
+        app.world.get_mut::<Position>(guard).unwrap().v.x = 9;
+        let effects = StatusEffects(vec![StatusEffect {
+            kind: StatusKind::Poison,
+            magnitude: 1.,
+            remaining: 4,
+        }]);
+        app.world.entity_mut(guard).insert(effects);
+
+        let handle = app.world.resource::<PrefabRegistry>().handle.clone();
+        let mut files = app.world.resource_mut::<Assets<PrefabFile>>();
+        let file = &mut files.get_mut(&handle).unwrap().0;
+        file.insert(
+            "guard".to_string(),
+            serde_json::json!({
+                "sprite": 9,
+                "health": 20,
+                "ai": { "kind": "chaser", "range": 6 },
+                "resistances": { "fire": 30, "ice": -10 }
+            }),
+        );
+        file.insert(
+            "archer".to_string(),
+            serde_json::json!({ "health": 6, "ai": { "kind": "chaser", "range": 2 } }),
+        );
+        file.remove("pet");
+        app.update();
+        app.update();
+
+        let guard = app.world.entity(guard);
+        assert_eq!(guard.get::<SpawnedFrom>().unwrap().revision, 1);
+        assert_eq!(guard.get::<ObjectSprite>().unwrap().0, 9);
+        // Half its health left, of twice as much.
+        let health = guard.get::<Health>().unwrap();
+        assert_eq!((health.current, health.max), (10, 20));
+        assert_eq!(guard.get::<Chaser>().unwrap().range, 6);
+        let resistances = guard.get::<Resistances>().unwrap();
+        assert_eq!((resistances.fire, resistances.ice), (30., -10.));
+        // Its own state stays as it was.
+        assert_eq!(guard.get::<Position>().unwrap().v.x, 9);
+        let effects = guard.get::<StatusEffects>().unwrap();
+        assert_eq!(effects.get(StatusKind::Poison).unwrap().remaining, 4);
+
+        // A new kind of AI replaces the old, and health left as it was.
+        let archer = app.world.entity(archer);
+        assert!(archer.get::<RangedAi>().is_none());
+        assert_eq!(archer.get::<Chaser>().unwrap().range, 2);
+        assert_eq!(archer.get::<Health>().unwrap().max, 6);
+
+        // With its prefab gone, the pet carries on as it was.
+        assert!(app.world.resource::<PrefabRegistry>().get("pet").is_none());
+        assert_eq!(app.world.get::<Follower>(pet).unwrap().spacing, 2);
+        let from = app.world.get::<SpawnedFrom>(pet).unwrap();
+        assert_eq!(from.revision, 0);
+    }
+}
@@ -0,0 +1,67 @@
+//! What a game built on `GamePlugin` works with, for a glob import:
+//! `use map_test::prelude::*;`. Names here keep their meaning between
+//! minor versions; anything reached only through the modules, such as the
+//! systems themselves, may change.
+
+// The app and the maps it plays.
+pub use crate::{
+    map_meta::MapMeta, progression::LevelCompletedEvent, AppState, BoardLoadedEvent, Campaign,
+    GamePlugin, LoadMapEvent, MapError, TILE_SIZE,
+};
+
+// Ticks, and the order systems run in within one.
+pub use crate::{
+    rng::GameRng,
+    simulation::{Rewinding, SimulationApp, SimulationPaused, SimulationPlugin, SimulationSet},
+    speed::SimulationSpeed,
+    world_hash::{world_hash, DesyncCheckEvent, WorldHash},
+};
+
+// The board and what stands on it.
+pub use crate::{
+    board::{BoardCommands, BoardError, BoardQuery, SetTileEvent, TileChangedEvent},
+    collision::{CollisionFlags, CollisionMap, Footprint, Occupancy},
+    projection::GridProjection,
+    vectors::{Direction, GridKind, GridRect, Vector3Int},
+    CurrentBoard, Position, Scene, Tile,
+};
+
+// Players and their input.
+pub use crate::{
+    input::{Action, ActionSet, Binding, InputMap},
+    player::{
+        MovementConfig, MovementRules, Player, PlayerSettings, PlayerStepCompleted,
+        PlayerStepStarted,
+    },
+};
+
+// Rules the game plays by.
+pub use crate::{
+    combat::{DamageEvent, DiedEvent, Health},
+    difficulty::{BaseHealth, Difficulty, DifficultyModifiers, DifficultySettings},
+    elements::{Element, ElementConfig, Resistances},
+    equipment::{EquipSlot, Equipment, StatModifiers, StatSheet},
+    flags::{GameFlags, SetFlagEvent},
+    footprints::FootprintConfig,
+    inventory::{Currency, Inventory},
+    prefabs::{PrefabChangedEvent, PrefabRegistry, SpawnedFrom},
+    shop::{Shop, ShopConfig, StockEntry},
+    status::{ApplyStatusEvent, StatusEffect, StatusEffects, StatusKind},
+    terrain::Capabilities,
+    timer::{LevelTimer, LevelTimerConfig},
+    turns::{ActionPoints, TurnBased, TurnConfig},
+    vision::{FogOfWar, SightMethod, VisionConfig},
+};
+
+// How it looks and reads.
+pub use crate::{
+    accessibility::Accessibility,
+    camera::{
+        Bookmark, CameraBookmarks, CameraConfig, CameraLock, FixedViewport, LockedRoom,
+        LockedRooms, PhotoConfig, PhotoMode, PHOTO_DIR,
+    },
+    event_log::{EventLogApp, EventLogSet, EventLogger},
+    locale::LocaleSettings,
+    trail::TrailConfig,
+    transitions::{TransitionConfig, TransitionStyle},
+};
@@ -0,0 +1,38 @@
+use crate::rng::GameRng;
+
+/// A maze `width` by `height` cells as one scene layer, row by row: `floor`
+/// on the paths and 0 (no tile) in the walls, which nothing can walk over.
+/// Paths join the cells with odd coordinates, so the outer ring is wall and
+/// every path cell is reachable from every other.
+pub fn maze(width: usize, height: usize, floor: usize, seed: u64) -> Vec<usize> {
+    let mut rng = GameRng::new(seed);
+    let mut layer = vec![0; width * height];
+    if width < 3 || height < 3 {
+        return layer;
+    }
+
+    // Depth-first, knocking through to a random unvisited cell two away.
+    let mut stack = vec![(1, 1)];
+    layer[width + 1] = floor;
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited: Vec<(usize, usize)> = [(2, 0), (0, 2), (-2, 0), (0, -2)]
+            .iter()
+            .map(|(dx, dy)| (x as i32 + dx, y as i32 + dy))
+            .filter(|(nx, ny)| {
+                (1..width as i32 - 1).contains(nx) && (1..height as i32 - 1).contains(ny)
+            })
+            .map(|(nx, ny)| (nx as usize, ny as usize))
+            .filter(|(nx, ny)| layer[ny * width + nx] == 0)
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+
+        let (nx, ny) = unvisited[rng.below(unvisited.len())];
+        layer[(y + ny) / 2 * width + (x + nx) / 2] = floor;
+        layer[ny * width + nx] = floor;
+        stack.push((nx, ny));
+    }
+    layer
+}
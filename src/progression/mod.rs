@@ -0,0 +1,277 @@
+use bevy::{prelude::*, time::Stopwatch};
+
+use crate::{
+    flags::GameFlags,
+    input::{Action, ActionInput},
+    layer_of,
+    locale::Localization,
+    objects::RequiresFlag,
+    player::PlayerStepCompleted,
+    simulation::{SimulationApp, SimulationSet},
+    stats::CurrentRun,
+    t,
+    transitions::{ScreenTransition, TransitionCause},
+    AppState, GraphicsAssets, LoadMapEvent, MapError, Position,
+};
+
+const OVERLAY_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const OVERLAY_FONT_SIZE: f32 = 24.;
+
+/// Ends the level when a player steps onto its cell.
+#[derive(Component)]
+pub struct Exit;
+
+/// Sent once when a player reaches an open exit.
+pub struct LevelCompletedEvent;
+
+/// The maps played in order, by asset path.
+#[derive(Resource)]
+pub struct Campaign {
+    pub maps: Vec<String>,
+    pub current: usize,
+}
+
+impl Default for Campaign {
+    fn default() -> Self {
+        Campaign {
+            maps: vec!["data.json".to_string(), "level2.json".to_string()],
+            current: 0,
+        }
+    }
+}
+
+impl Campaign {
+    pub fn current_map(&self) -> &str {
+        &self.maps[self.current.min(self.maps.len() - 1)]
+    }
+
+    /// Moves on to the next map, if there is one.
+    pub fn advance(&mut self) -> Option<&str> {
+        if self.current + 1 >= self.maps.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.current_map())
+    }
+}
+
+/// Progress through the current level, reset whenever one starts.
+#[derive(Default, Resource)]
+pub struct GameStats {
+    pub steps: u32,
+    pub time: Stopwatch,
+}
+
+/// The full-screen panel shown between levels and on victory.
+#[derive(Component)]
+struct Overlay;
+
+#[derive(Component)]
+struct OverlayText;
+
+pub struct ProgressionPlugin;
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Campaign>()
+            .init_resource::<GameStats>()
+            .add_simulation_event::<LevelCompletedEvent>()
+            .add_system(reset_stats.in_schedule(OnEnter(AppState::Game)))
+            .add_systems(
+                (count_stats, reach_exit, complete_level)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(show_summary.in_schedule(OnEnter(AppState::LevelComplete)))
+            .add_system(
+                dismiss_summary
+                    .after(SimulationSet::Input)
+                    .before(SimulationSet::End)
+                    .run_if(in_state(AppState::LevelComplete))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(despawn_overlay.in_schedule(OnExit(AppState::LevelComplete)))
+            .add_system(announce_victory.in_schedule(OnEnter(AppState::Victory)))
+            .add_system(show_error.in_schedule(OnEnter(AppState::Error)))
+            .add_system(translate_overlay);
+    }
+}
+
+fn reset_stats(mut stats: ResMut<GameStats>) {
+    *stats = GameStats::default();
+}
+
+fn count_stats(
+    mut steps: EventReader<PlayerStepCompleted>,
+    fixed: Res<FixedTime>,
+    mut stats: ResMut<GameStats>,
+) {
+    stats.steps += steps.iter().count() as u32;
+    stats.time.tick(fixed.period);
+}
+
+fn reach_exit(
+    mut steps: EventReader<PlayerStepCompleted>,
+    exits: Query<(&Position, Option<&RequiresFlag>), With<Exit>>,
+    flags: Res<GameFlags>,
+    mut completed: EventWriter<LevelCompletedEvent>,
+) {
+    let reached = steps.iter().any(|step| {
+        exits.iter().any(|(position, requires)| {
+            step.at.manhattan(position.v) == 0
+                && layer_of(step.at.z) == layer_of(position.v.z)
+                && requires.is_none_or(|RequiresFlag(expr)| flags.check(expr))
+        })
+    });
+    if reached {
+        completed.send(LevelCompletedEvent);
+    }
+}
+
+fn complete_level(
+    mut events: EventReader<LevelCompletedEvent>,
+    mut transition: ResMut<ScreenTransition>,
+) {
+    if events.iter().count() > 0 {
+        transition.request(TransitionCause::State(AppState::LevelComplete));
+    }
+}
+
+fn spawn_overlay(commands: &mut Commands, assets: &GraphicsAssets, text: String) {
+    commands
+        .spawn((
+            Overlay,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: OVERLAY_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                OverlayText,
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: OVERLAY_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+            ));
+        });
+}
+
+/// What the overlay says in `state`, if it shows one.
+fn overlay_text(
+    state: AppState,
+    stats: &GameStats,
+    run: &CurrentRun,
+    error: &MapError,
+    locale: &Localization,
+) -> Option<String> {
+    match state {
+        AppState::LevelComplete => Some(t!(
+            locale,
+            "level.complete",
+            steps = stats.steps,
+            time = format!("{:.1}", stats.time.elapsed_secs()),
+        )),
+        AppState::Victory => Some(t!(locale, "victory", record = run.record.summary(locale))),
+        AppState::Error => Some(t!(locale, "map.error", error = error.0)),
+        _ => None,
+    }
+}
+
+fn show_summary(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    stats: Res<GameStats>,
+    run: Res<CurrentRun>,
+    error: Res<MapError>,
+    locale: Res<Localization>,
+) {
+    info!(
+        "Level complete in {} steps and {:.1}s.",
+        stats.steps,
+        stats.time.elapsed_secs()
+    );
+    let text = overlay_text(AppState::LevelComplete, &stats, &run, &error, &locale);
+    spawn_overlay(&mut commands, &assets, text.unwrap_or_default());
+}
+
+/// Waits for a confirm press, then loads the next map or ends the campaign.
+fn dismiss_summary(
+    input: ActionInput,
+    mut campaign: ResMut<Campaign>,
+    mut loads: EventWriter<LoadMapEvent>,
+    mut transition: ResMut<ScreenTransition>,
+) {
+    if !input.any_just_pressed(Action::Confirm) {
+        return;
+    }
+    match campaign.advance() {
+        Some(name) => loads.send(LoadMapEvent {
+            name: name.to_string(),
+        }),
+        None => transition.request(TransitionCause::State(AppState::Victory)),
+    }
+}
+
+fn despawn_overlay(mut commands: Commands, overlays: Query<Entity, With<Overlay>>) {
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn announce_victory(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    stats: Res<GameStats>,
+    run: Res<CurrentRun>,
+    error: Res<MapError>,
+    locale: Res<Localization>,
+) {
+    info!("You win!\n{}", run.record.summary(&locale));
+    let text = overlay_text(AppState::Victory, &stats, &run, &error, &locale);
+    spawn_overlay(&mut commands, &assets, text.unwrap_or_default());
+}
+
+fn show_error(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    stats: Res<GameStats>,
+    run: Res<CurrentRun>,
+    error: Res<MapError>,
+    locale: Res<Localization>,
+) {
+    error!("{}", error.0);
+    let text = overlay_text(AppState::Error, &stats, &run, &error, &locale);
+    spawn_overlay(&mut commands, &assets, text.unwrap_or_default());
+}
+
+/// Rewrites the overlay in the new language when it changes.
+fn translate_overlay(
+    state: Res<State<AppState>>,
+    stats: Res<GameStats>,
+    run: Res<CurrentRun>,
+    error: Res<MapError>,
+    locale: Res<Localization>,
+    mut texts: Query<&mut Text, With<OverlayText>>,
+) {
+    if !locale.is_changed() {
+        return;
+    }
+    let Some(value) = overlay_text(state.0, &stats, &run, &error, &locale) else { return };
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
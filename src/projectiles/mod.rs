@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{CollisionFlags, CollisionMap, Occupancy},
+    combat::DamageEvent,
+    elements::Element,
+    prefabs::{spawn_prefab, PrefabRegistry},
+    simulation::SimulationSet,
+    vectors::Vector3Int,
+    CurrentBoard, Position,
+};
+
+/// A shot flying one cell a tick along `path`, until it hits a wall or an
+/// occupier other than whoever fired it, or runs out of path. In
+/// turn-based mode it still flies on ticks, between turns.
+#[derive(Component)]
+pub struct Projectile {
+    pub owner: Entity,
+    pub damage: u32,
+    /// Cells still to fly through, next first.
+    path: VecDeque<Vector3Int>,
+}
+
+pub struct ProjectilesPlugin;
+impl Plugin for ProjectilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            fly_projectiles
+                .in_set(SimulationSet::Act)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// The cells a straight shot from `from` towards `to` passes through
+/// after leaving `from`, `range` of them, across wrapping edges and on the
+/// layer of `from`. The line carries on past `to` if `range` reaches it.
+pub fn flight_path(
+    from: Vector3Int,
+    to: Vector3Int,
+    range: i32,
+    current: &CurrentBoard,
+) -> Vec<Vector3Int> {
+    let delta = current.delta(from, Vector3Int::new(to.x, to.y, from.z));
+    let steps = delta.x.abs().max(delta.y.abs());
+    if steps == 0 {
+        return Vec::new();
+    }
+    // Far enough along the same line for `range` cells.
+    let scale = (range + steps - 1) / steps;
+    let end = Vector3Int::new(
+        from.x + delta.x * scale.max(1),
+        from.y + delta.y * scale.max(1),
+        from.z,
+    );
+    (from.line_to(end).into_iter())
+        .skip(1)
+        .take(range.max(0) as usize)
+        .map(|v| current.wrap(v))
+        .collect()
+}
+
+/// Whether nothing between `from` and `to` blocks sight or stops a shot.
+pub fn clear_shot(
+    from: Vector3Int,
+    to: Vector3Int,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+) -> bool {
+    let delta = current.delta(from, Vector3Int::new(to.x, to.y, from.z));
+    let steps = delta.x.abs().max(delta.y.abs());
+    let path = flight_path(from, to, steps, current);
+    let blockers = CollisionFlags::BLOCK_SIGHT | CollisionFlags::BLOCK_FLY;
+    (path.iter().take(path.len().saturating_sub(1))).all(|v| !collision.blocks(*v, blockers))
+}
+
+/// Spawns the prefab named `name` as a shot by `owner` from `from` along
+/// `path`, unless there is no path.
+pub fn fire_projectile(
+    commands: &mut Commands,
+    prefabs: &PrefabRegistry,
+    name: &str,
+    owner: Entity,
+    damage: u32,
+    from: Vector3Int,
+    path: Vec<Vector3Int>,
+) -> Option<Entity> {
+    if path.is_empty() {
+        return None;
+    }
+    let entity = spawn_prefab(commands, prefabs, name, from)?;
+    commands.entity(entity).insert(Projectile {
+        owner,
+        damage,
+        path: path.into(),
+    });
+    Some(entity)
+}
+
+fn fly_projectiles(
+    mut commands: Commands,
+    collision: Res<CollisionMap>,
+    occupancy: Res<Occupancy>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Position, Option<&Element>)>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for (entity, mut projectile, mut position, element) in projectiles.iter_mut() {
+        let Some(next) = projectile.path.pop_front() else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        if collision.blocks(next, CollisionFlags::BLOCK_FLY) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        position.v = Vector3Int::new(next.x, next.y, position.v.z);
+        let hit = occupancy.get(position.v).filter(|e| *e != projectile.owner);
+        if let Some(target) = hit {
+            damage.send(DamageEvent {
+                entity: target,
+                amount: projectile.damage,
+                element: element.copied().unwrap_or_default(),
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+use crate::{
+    vectors::{hex_round, HexOrientation, Vector3Int},
+    TILE_SIZE,
+};
+
+/// Difference in z between cells one step apart in depth. Small enough that
+/// depth sorting never crosses into the next z-index.
+const DEPTH_STEP: f32 = 1. / 4096.;
+
+/// How board cells are laid out in the world. Only drawing and picking go
+/// through this; the board itself is the same grid either way.
+#[derive(serde::Deserialize, Resource, Clone, Copy, Debug, PartialEq)]
+#[serde(from = "ProjectionKind")]
+pub enum GridProjection {
+    /// Square cells `pitch` world units a side.
+    Orthogonal { pitch: f32 },
+    /// Cells drawn as diamonds `tile_width` by `tile_height` pixels, with
+    /// the board's x axis running up-right and y up-left.
+    Isometric { tile_width: f32, tile_height: f32 },
+    /// Hex cells in axial coordinates, `pitch` world units across the
+    /// flats.
+    Hex {
+        orientation: HexOrientation,
+        pitch: f32,
+    },
+}
+
+impl Default for GridProjection {
+    fn default() -> Self {
+        GridProjection::Orthogonal { pitch: TILE_SIZE }
+    }
+}
+
+/// A projection as maps write it. The pitch of square and hex cells is the
+/// map's `grid_pitch` rather than part of it.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionKind {
+    Orthogonal,
+    Isometric { tile_width: f32, tile_height: f32 },
+    Hex { orientation: HexOrientation },
+}
+
+impl From<ProjectionKind> for GridProjection {
+    fn from(kind: ProjectionKind) -> Self {
+        match kind {
+            ProjectionKind::Orthogonal => GridProjection::default(),
+            ProjectionKind::Isometric {
+                tile_width,
+                tile_height,
+            } => GridProjection::Isometric {
+                tile_width,
+                tile_height,
+            },
+            ProjectionKind::Hex { orientation } => GridProjection::Hex {
+                orientation,
+                pitch: TILE_SIZE,
+            },
+        }
+    }
+}
+
+/// Circumradius of a hex `pitch` across the flats.
+fn hex_size(pitch: f32) -> f32 {
+    pitch / 3f32.sqrt()
+}
+
+impl GridProjection {
+    /// The same projection with square and hex cells `pitch` world units
+    /// apart. Isometric cells keep their own size.
+    pub fn with_pitch(self, pitch: f32) -> Self {
+        match self {
+            GridProjection::Orthogonal { .. } => GridProjection::Orthogonal { pitch },
+            GridProjection::Hex { orientation, .. } => GridProjection::Hex { orientation, pitch },
+            isometric => isometric,
+        }
+    }
+
+    /// World position of a point on the board, in cells.
+    pub fn cell_to_world(&self, cell: Vec2) -> Vec2 {
+        match *self {
+            GridProjection::Orthogonal { pitch } => cell * pitch,
+            GridProjection::Isometric {
+                tile_width,
+                tile_height,
+            } => Vec2::new(
+                (cell.x - cell.y) * tile_width / 2.,
+                (cell.x + cell.y) * tile_height / 2.,
+            ),
+            GridProjection::Hex { orientation, pitch } => {
+                orientation.hex_to_world(cell, hex_size(pitch))
+            }
+        }
+    }
+
+    /// The point on the board, in cells, drawn at a world position.
+    pub fn world_to_cell(&self, world: Vec2) -> Vec2 {
+        match *self {
+            GridProjection::Orthogonal { pitch } => world / pitch,
+            GridProjection::Isometric {
+                tile_width,
+                tile_height,
+            } => {
+                let (a, b) = (world.x * 2. / tile_width, world.y * 2. / tile_height);
+                Vec2::new((a + b) / 2., (b - a) / 2.)
+            }
+            GridProjection::Hex { orientation, pitch } => {
+                orientation.world_to_hex(world, hex_size(pitch))
+            }
+        }
+    }
+
+    /// The cell drawn at a world position, on z-index `z`.
+    pub fn cell_at(&self, world: Vec2, z: i32) -> Vector3Int {
+        let cell = self.world_to_cell(world);
+        let (x, y) = match self {
+            // Hexes are not squares in axial space, so round in cube space.
+            GridProjection::Hex { .. } => hex_round(cell),
+            _ => (cell.x.round() as i32, cell.y.round() as i32),
+        };
+        Vector3Int::new(x, y, z)
+    }
+
+    /// Where to draw something in cell `v`. In isometric mode cells further
+    /// back (higher x + y) sort behind others on the same z-index.
+    pub fn world(&self, v: Vector3Int) -> Vec3 {
+        let depth = match self {
+            GridProjection::Orthogonal { .. } | GridProjection::Hex { .. } => 0.,
+            GridProjection::Isometric { .. } => 0.5 - (v.x + v.y) as f32 * DEPTH_STEP,
+        };
+        let cell = Vec2::new(v.x as f32, v.y as f32);
+        self.cell_to_world(cell).extend(v.z as f32 + depth)
+    }
+
+    /// The size of a cell, which tiles are drawn at unless their metadata
+    /// gives a `render_size`.
+    pub fn tile_size(&self) -> Vec2 {
+        match *self {
+            GridProjection::Orthogonal { pitch } | GridProjection::Hex { pitch, .. } => {
+                Vec2::splat(pitch)
+            }
+            GridProjection::Isometric {
+                tile_width,
+                tile_height,
+            } => Vec2::new(tile_width, tile_height),
+        }
+    }
+
+    /// The size an actor or object from the built-in sheet is drawn at
+    /// unless its metadata gives a `render_size`: a cell on square and hex
+    /// grids, and a `TILE_SIZE` square on isometric ones.
+    pub fn sprite_size(&self) -> Vec2 {
+        match *self {
+            GridProjection::Isometric { .. } => Vec2::splat(TILE_SIZE),
+            _ => self.tile_size(),
+        }
+    }
+}
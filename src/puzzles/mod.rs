@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+
+use crate::{
+    collision::{CollisionFlags, CollisionMap, Occupancy},
+    flags::SetFlagEvent,
+    objects::SetsFlag,
+    render_layers::{z_index, RenderLayerSlot},
+    simulation::{SimulationApp, SimulationSet},
+    vectors::Vector3Int,
+    CurrentBoard, Position,
+};
+
+/// Z-index for pushed blocks, above plates and other objects.
+pub const PUSHABLE_Z: i32 = z_index(RenderLayerSlot::Objects, 1);
+
+/// A block that moves one cell when walked into, if the cell behind it is free.
+#[derive(Component)]
+pub struct Pushable;
+
+/// Sent when a player pushes a block out of `from`.
+pub struct BlockPushedEvent {
+    pub entity: Entity,
+    pub from: Vector3Int,
+}
+
+/// Holds its `SetsFlag` flag true for as long as something rests on it.
+#[derive(Component, Default)]
+pub struct PressurePlate {
+    pub active: bool,
+}
+
+pub struct PuzzlesPlugin;
+impl Plugin for PuzzlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_event::<BlockPushedEvent>().add_systems(
+            (drop_into_pits, update_pressure_plates)
+                .in_set(SimulationSet::React)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+/// Attempts to push `block` one cell in `dir`. Fails on walls, gaps and
+/// other occupiers (including other blocks, so only one block moves).
+/// Blocks pushed into a pit fall in.
+pub fn try_push(
+    block: &mut Position,
+    dir: Vector3Int,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+    occupancy: &Occupancy,
+) -> bool {
+    let to = current.wrap(block.v + dir);
+    let walled = collision.blocks(to, CollisionFlags::BLOCK_WALK);
+    if !current.has_ground(to) || walled || occupancy.is_occupied(to) {
+        return false;
+    }
+
+    block.v = to;
+    true
+}
+
+/// Destroys blocks pushed into a pit.
+fn drop_into_pits(
+    mut commands: Commands,
+    mut pushes: EventReader<BlockPushedEvent>,
+    blocks: Query<&Position, With<Pushable>>,
+    collision: Res<CollisionMap>,
+) {
+    for push in pushes.iter() {
+        let Ok(position) = blocks.get(push.entity) else { continue };
+        if collision.is_pit(position.v) {
+            commands.entity(push.entity).despawn_recursive();
+        }
+    }
+}
+
+fn update_pressure_plates(
+    mut plates: Query<(&mut PressurePlate, &Position, &SetsFlag)>,
+    occupancy: Res<Occupancy>,
+    mut events: EventWriter<SetFlagEvent>,
+) {
+    for (mut plate, position, SetsFlag(flag)) in plates.iter_mut() {
+        let pressed = occupancy.is_occupied(position.v);
+        if pressed != plate.active {
+            plate.active = pressed;
+            events.send(SetFlagEvent::new(flag, pressed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        input::Action,
+        testing::{headless_game, run_ticks, Script},
+    };
+
+    #[test]
+    fn push_into_a_wall_is_refused() {
+        let cells = (0..3).map(|x| Vector3Int::new(x, 0, 0));
+        let current = CurrentBoard::with_ground(cells);
+        let mut collision = CollisionMap::default();
+        collision.block(Vector3Int::new(2, 0, 0));
+        let occupancy = Occupancy::default();
+        let right = Vector3Int::new(1, 0, 0);
+
+        let mut block = Position {
+            v: Vector3Int::new(1, 0, 0),
+        };
+        let pushed = try_push(&mut block, right, &current, &collision, &occupancy);
+        assert!(!pushed);
+        assert_eq!(block.v, Vector3Int::new(1, 0, 0));
+
+        // The same push goes ahead once the wall is gone.
+        collision.unblock(Vector3Int::new(2, 0, 0));
+        let pushed = try_push(&mut block, right, &current, &collision, &occupancy);
+        assert!(pushed);
+        assert_eq!(block.v, Vector3Int::new(2, 0, 0));
+    }
+
+    #[test]
+    fn push_into_another_block_or_off_the_ground_is_refused() {
+        let cells = (0..3).map(|x| Vector3Int::new(x, 0, 0));
+        let current = CurrentBoard::with_ground(cells);
+        let collision = CollisionMap::default();
+        let mut occupancy = Occupancy::default();
+        occupancy.insert(Entity::from_raw(1), [Vector3Int::new(1, 0, 0)]);
+        let right = Vector3Int::new(1, 0, 0);
+
+        let mut block = Position {
+            v: Vector3Int::new(0, 0, 0),
+        };
+        let pushed = try_push(&mut block, right, &current, &collision, &occupancy);
+        assert!(!pushed);
+        let mut edge = Position {
+            v: Vector3Int::new(2, 0, 0),
+        };
+        let pushed = try_push(&mut edge, right, &current, &collision, &occupancy);
+        assert!(!pushed);
+        assert_eq!(edge.v, Vector3Int::new(2, 0, 0));
+    }
+
+    #[test]
+    fn blocks_pushed_into_a_pit_fall_in() {
+        let mut app = headless_game("fixtures/collision.json", Duration::from_secs_f64(1. / 60.));
+        // The block is right of the start, with a pit right of it.
+        app.insert_resource(Script(vec![
+            (1, 0, Action::MoveRight, true),
+            (2, 0, Action::MoveRight, false),
+        ]));
+        let mut blocks = app.world.query_filtered::<(), With<Pushable>>();
+        run_ticks(&mut app, 0);
+        assert_eq!(blocks.iter(&app.world).count(), 1);
+
+        run_ticks(&mut app, 20);
+        assert_eq!(blocks.iter(&app.world).count(), 0);
+        let pit = Vector3Int::new(2, 0, 0);
+        assert!(app.world.resource::<CollisionMap>().is_pit(pit));
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{covered_cells, Footprint, Occupier},
+    flags::SetFlagEvent,
+    layer_of,
+    npc::NpcSteppedEvent,
+    objects::SetsFlag,
+    player::{Player, PlayerStepCompleted},
+    puzzles::BlockPushedEvent,
+    simulation::{SimulationApp, SimulationSet},
+    vectors::Vector3Int,
+    Position,
+};
+
+/// An area, usually given a `Footprint`, that reports occupiers crossing
+/// its boundary. Overlapping regions each report on their own.
+#[derive(Component)]
+pub struct Region {
+    pub id: String,
+    /// Ignore everything but players.
+    pub players_only: bool,
+}
+
+/// Text shown the first time a player enters the region, e.g. a tutorial hint.
+#[derive(Component)]
+pub struct RegionMessage(pub String);
+
+pub struct RegionEntered {
+    pub entity: Entity,
+    pub region_id: String,
+}
+
+pub struct RegionExited {
+    pub entity: Entity,
+    pub region_id: String,
+}
+
+/// Regions each occupier is in, by id, so membership outlives the region
+/// entities themselves when a map is reloaded.
+#[derive(Default, Resource)]
+pub struct RegionMembership {
+    members: HashMap<Entity, HashSet<String>>,
+}
+
+impl RegionMembership {
+    /// The occupiers in the region `region_id`.
+    pub fn members_of<'a>(&'a self, region_id: &'a str) -> impl Iterator<Item = Entity> + 'a {
+        (self.members.iter())
+            .filter(move |(_, ids)| ids.contains(region_id))
+            .map(|(entity, _)| *entity)
+    }
+}
+
+pub struct RegionsPlugin;
+impl Plugin for RegionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RegionMembership>()
+            .add_simulation_event::<RegionEntered>()
+            .add_simulation_event::<RegionExited>()
+            .add_systems(
+                (update_membership, region_effects)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// Recomputes membership for occupiers that just finished moving, or for
+/// every occupier when regions come or go.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn update_membership(
+    mut player_steps: EventReader<PlayerStepCompleted>,
+    mut npc_steps: EventReader<NpcSteppedEvent>,
+    mut pushes: EventReader<BlockPushedEvent>,
+    added: Query<(), Added<Region>>,
+    mut removed: RemovedComponents<Region>,
+    mut gone: RemovedComponents<Occupier>,
+    regions: Query<(&Region, &Position, Option<&Footprint>)>,
+    occupiers: Query<(Entity, &Position, Option<&Footprint>, Option<&Player>), With<Occupier>>,
+    mut membership: ResMut<RegionMembership>,
+    mut entered: EventWriter<RegionEntered>,
+    mut exited: EventWriter<RegionExited>,
+) {
+    for entity in gone.iter() {
+        for region_id in membership.members.remove(&entity).unwrap_or_default() {
+            exited.send(RegionExited { entity, region_id });
+        }
+    }
+
+    let mut moved: Vec<Entity> = player_steps.iter().map(|s| s.entity).collect();
+    moved.extend(npc_steps.iter().map(|s| s.entity));
+    moved.extend(pushes.iter().map(|p| p.entity));
+    let regions_changed = !added.is_empty() || removed.iter().count() > 0;
+    if regions_changed {
+        moved = occupiers.iter().map(|(entity, ..)| entity).collect();
+    }
+
+    let areas: Vec<(&Region, HashSet<Vector3Int>)> = regions
+        .iter()
+        .map(|(region, position, footprint)| {
+            let cells = covered_cells(position.v, footprint)
+                .into_iter()
+                .map(cell_key);
+            (region, cells.collect())
+        })
+        .collect();
+
+    for entity in moved {
+        let Ok((_, position, footprint, player)) = occupiers.get(entity) else { continue };
+        let cells: Vec<Vector3Int> = covered_cells(position.v, footprint)
+            .into_iter()
+            .map(cell_key)
+            .collect();
+        let inside: HashSet<String> = areas
+            .iter()
+            .filter(|(region, _)| player.is_some() || !region.players_only)
+            .filter(|(_, area)| cells.iter().any(|c| area.contains(c)))
+            .map(|(region, _)| region.id.clone())
+            .collect();
+
+        let previous = membership.members.remove(&entity).unwrap_or_default();
+        for region_id in inside.difference(&previous) {
+            entered.send(RegionEntered {
+                entity,
+                region_id: region_id.clone(),
+            });
+        }
+        for region_id in previous.difference(&inside) {
+            exited.send(RegionExited {
+                entity,
+                region_id: region_id.clone(),
+            });
+        }
+        if !inside.is_empty() {
+            membership.members.insert(entity, inside);
+        }
+    }
+}
+
+/// Compares cells by x, y and layer, whatever their z within the layer.
+fn cell_key(v: Vector3Int) -> Vector3Int {
+    Vector3Int::new(v.x, v.y, layer_of(v.z))
+}
+
+fn region_effects(
+    mut entered: EventReader<RegionEntered>,
+    mut exited: EventReader<RegionExited>,
+    players: Query<(), With<Player>>,
+    mut regions: Query<(Entity, &Region, Option<&SetsFlag>, Option<&RegionMessage>)>,
+    mut commands: Commands,
+    mut flags: EventWriter<SetFlagEvent>,
+) {
+    for event in entered.iter() {
+        debug!("{:?} entered region `{}`.", event.entity, event.region_id);
+        let is_player = players.contains(event.entity);
+        for (entity, _, sets, message) in regions
+            .iter_mut()
+            .filter(|(_, r, ..)| r.id == event.region_id)
+        {
+            if let Some(SetsFlag(flag)) = sets {
+                flags.send(SetFlagEvent::new(flag, true));
+            }
+            if let Some(RegionMessage(text)) = message.filter(|_| is_player) {
+                info!("{}", text);
+                commands.entity(entity).remove::<RegionMessage>();
+            }
+        }
+    }
+    for event in exited.iter() {
+        debug!("{:?} left region `{}`.", event.entity, event.region_id);
+    }
+}
@@ -0,0 +1,127 @@
+//! The bands of z everything is drawn in, kept in one place so that new
+//! sprites never have to guess at a z that clears the map.
+//!
+//! The map bands are z-indices within each walkable layer, repeated every
+//! `LAYER_Z_STRIDE`, and go into a `Position` through `layer_z`. The
+//! overlay bands are world z above every layer a map can have.
+
+use std::ops::Range;
+
+use crate::LAYER_Z_STRIDE;
+
+/// A band of z that one kind of sprite is drawn in, lowest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderLayerSlot {
+    /// Tiles, one z-index for each layer of the scene.
+    Ground,
+    /// Map objects, then pushable blocks.
+    Objects,
+    /// Creatures, then players.
+    Actors,
+    /// Tile decals drawn over the lower half of whoever stands in them, as
+    /// tall grass is.
+    Decals,
+    /// Roofs over everything else in the layer, faded out while someone
+    /// is inside.
+    Roof,
+    /// Effects and previews drawn over the whole board.
+    Overlay,
+    /// Markers for whoever is at the keyboard, such as the editor's
+    /// selection, over everything else.
+    Ui2d,
+}
+
+impl RenderLayerSlot {
+    pub const ALL: [RenderLayerSlot; 7] = [
+        RenderLayerSlot::Ground,
+        RenderLayerSlot::Objects,
+        RenderLayerSlot::Actors,
+        RenderLayerSlot::Decals,
+        RenderLayerSlot::Roof,
+        RenderLayerSlot::Overlay,
+        RenderLayerSlot::Ui2d,
+    ];
+
+    /// The z the band covers. The 2D camera sees up to just under 1000.
+    pub const fn range(self) -> Range<i32> {
+        match self {
+            RenderLayerSlot::Ground => 0..2,
+            RenderLayerSlot::Objects => 2..4,
+            RenderLayerSlot::Actors => 4..6,
+            RenderLayerSlot::Decals => 6..7,
+            RenderLayerSlot::Roof => 7..8,
+            RenderLayerSlot::Overlay => 900..950,
+            RenderLayerSlot::Ui2d => 950..990,
+        }
+    }
+
+    /// Whether the band repeats in every walkable layer.
+    pub const fn is_map(self) -> bool {
+        matches!(
+            self,
+            RenderLayerSlot::Ground
+                | RenderLayerSlot::Objects
+                | RenderLayerSlot::Actors
+                | RenderLayerSlot::Decals
+                | RenderLayerSlot::Roof
+        )
+    }
+
+    /// How many z-indices the band holds.
+    pub const fn size(self) -> i32 {
+        let range = self.range();
+        range.end - range.start
+    }
+}
+
+/// The z-index `within` places above the start of a map band, for use with
+/// `layer_z`. Panics if it falls outside the band, at compile time for
+/// constants.
+pub const fn z_index(slot: RenderLayerSlot, within: i32) -> i32 {
+    assert!(slot.is_map(), "only map bands have z-indices");
+    assert!(
+        within >= 0 && within < slot.size(),
+        "z-index outside its band"
+    );
+    slot.range().start + within
+}
+
+/// The world z `within` places above the start of a band. Map bands give
+/// the z within a layer's stride.
+pub fn z_for(slot: RenderLayerSlot, within: f32) -> f32 {
+    debug_assert!(
+        (0. ..slot.size() as f32).contains(&within),
+        "{} is outside {:?}",
+        within,
+        slot
+    );
+    slot.range().start as f32 + within
+}
+
+/// Whether every band is clear of the others, the map bands fit in a
+/// layer's stride and the overlay bands sit above the map ones.
+const fn bands_are_sound() -> bool {
+    let mut i = 0;
+    while i < RenderLayerSlot::ALL.len() {
+        let a = RenderLayerSlot::ALL[i].range();
+        if a.start >= a.end {
+            return false;
+        }
+        if RenderLayerSlot::ALL[i].is_map() && a.end > LAYER_Z_STRIDE {
+            return false;
+        }
+        let mut j = i + 1;
+        while j < RenderLayerSlot::ALL.len() {
+            let b = RenderLayerSlot::ALL[j].range();
+            // `ALL` is lowest first.
+            if b.start < a.end {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const _: () = assert!(bands_are_sound(), "render layer bands overlap");
@@ -0,0 +1,382 @@
+use std::{collections::HashSet, fmt, fs, time::Duration};
+
+use bevy::{app::AppExit, input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    editor::EditorState,
+    input::{Action, ActionInput, InputMap},
+    player::{MovementConfig, MovementRules, Player},
+    progression::{Campaign, GameStats},
+    rng::{GameRng, DEFAULT_SEED},
+    simulation::{SimulationPaused, SimulationSet},
+    turns::TurnBased,
+    AppState, Position,
+};
+
+/// How far the replayed level time may drift from the recorded one.
+const TIME_TOLERANCE: f32 = 1e-3;
+
+/// Simulation ticks run outside of loading, which takes a different number
+/// of ticks from run to run.
+#[derive(Default, Resource)]
+pub struct LogicalClock {
+    pub tick: u64,
+}
+
+/// A press (or release) of one player's action on a tick of the clock.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ReplayEvent(pub u64, pub usize, pub Action, pub bool);
+
+/// How the recorded session started and ended. Playback checks that it
+/// ends the same way.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayHeader {
+    pub seed: u64,
+    pub maps: Vec<String>,
+    /// The length of a simulation tick, which playback runs at.
+    pub period: Duration,
+    pub ticks: u64,
+    pub steps: u32,
+    pub time: f32,
+    /// Player positions by `Player::index`.
+    pub players: Vec<[i32; 3]>,
+    /// Why the session cannot be replayed, such as a debug toggle that is
+    /// not recorded.
+    #[serde(default)]
+    pub nondeterministic: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReplayFile {
+    header: ReplayHeader,
+    events: Vec<ReplayEvent>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The recording used something playback cannot reproduce.
+    Nondeterministic(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::Format(e) => write!(f, "malformed replay: {}", e),
+            ReplayError::Nondeterministic(reason) => {
+                write!(f, "the session cannot be replayed: {}", reason)
+            }
+        }
+    }
+}
+
+/// Logs every action press and release to `path`, written on exit.
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    path: String,
+    maps: Vec<String>,
+    events: Vec<ReplayEvent>,
+    held: HashSet<(usize, Action)>,
+    nondeterministic: Option<String>,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: impl Into<String>, campaign: &Campaign) -> Self {
+        ReplayRecorder {
+            path: path.into(),
+            maps: campaign.maps.clone(),
+            events: Vec::new(),
+            held: HashSet::new(),
+            nondeterministic: None,
+        }
+    }
+
+    /// Marks the recording as impossible to replay, keeping the first reason.
+    fn taint(&mut self, reason: &str) {
+        if self.nondeterministic.is_none() {
+            warn!("Replay recording will not play back: {}.", reason);
+            self.nondeterministic = Some(reason.to_string());
+        }
+    }
+}
+
+/// A recorded session fed into `ActionInput` in place of the keyboard and
+/// gamepads.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    header: ReplayHeader,
+    events: Vec<ReplayEvent>,
+    next: usize,
+    held: HashSet<(usize, Action)>,
+    /// Pressed on the current tick.
+    pressed: HashSet<(usize, Action)>,
+}
+
+impl ReplayPlayback {
+    pub fn load(path: &str) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let file: ReplayFile = serde_json::from_str(&text).map_err(ReplayError::Format)?;
+        if let Some(reason) = file.header.nondeterministic {
+            return Err(ReplayError::Nondeterministic(reason));
+        }
+        Ok(ReplayPlayback {
+            header: file.header,
+            events: file.events,
+            next: 0,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+        })
+    }
+
+    /// The maps the session was recorded on.
+    pub fn campaign(&self) -> Campaign {
+        Campaign {
+            maps: self.header.maps.clone(),
+            current: 0,
+        }
+    }
+
+    pub fn pressed(&self, player: usize, action: Action) -> bool {
+        self.held.contains(&(player, action))
+    }
+
+    pub fn just_pressed(&self, player: usize, action: Action) -> bool {
+        self.pressed.contains(&(player, action))
+    }
+}
+
+/// Records with `ReplayRecorder` or plays back with `ReplayPlayback` when
+/// either is inserted before the plugin is added.
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LogicalClock>().add_system(
+            advance_clock
+                .run_if(simulating)
+                .in_set(SimulationSet::Input)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+
+        let (seed, period) = match app.world.get_resource::<ReplayPlayback>() {
+            Some(playback) => (playback.header.seed, Some(playback.header.period)),
+            None if app.world.contains_resource::<ReplayRecorder>() => (DEFAULT_SEED, None),
+            None => return,
+        };
+        if let Some(period) = period {
+            app.insert_resource(FixedTime::new(period));
+        }
+        app.insert_resource(GameRng::new(seed))
+            .add_systems(
+                (record_actions, play_actions)
+                    .after(advance_clock)
+                    .in_set(SimulationSet::Input)
+                    .distributive_run_if(simulating)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                ignore_live_input
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(detect_debug_toggles)
+            .add_system(
+                check_playback
+                    .in_set(SimulationSet::End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            // Late enough to see an exit sent on the last frame.
+            .add_system(save_recording.in_base_set(CoreSet::Last));
+    }
+}
+
+fn simulating(state: Res<State<AppState>>) -> bool {
+    state.0 != AppState::Loading
+}
+
+pub(crate) fn advance_clock(mut clock: ResMut<LogicalClock>) {
+    clock.tick += 1;
+}
+
+fn record_actions(
+    input: ActionInput,
+    map: Res<InputMap>,
+    clock: Res<LogicalClock>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let Some(mut recorder) = recorder else { return };
+    let tick = clock.tick;
+    for (player, set) in map.players.iter().enumerate() {
+        for action in set.actions() {
+            let mut was = recorder.held.contains(&(player, action));
+            let mut push = |pressed| {
+                (recorder.events).push(ReplayEvent(tick, player, action, pressed));
+            };
+            // A press since the last tick counts even if it has already
+            // been let go, or let go and pressed again.
+            if input.just_pressed(player, action) {
+                if was {
+                    push(false);
+                }
+                push(true);
+                was = true;
+            }
+            let pressed = input.pressed(player, action);
+            if pressed != was {
+                push(pressed);
+            }
+            if pressed {
+                recorder.held.insert((player, action));
+            } else {
+                recorder.held.remove(&(player, action));
+            }
+        }
+    }
+}
+
+fn play_actions(clock: Res<LogicalClock>, playback: Option<ResMut<ReplayPlayback>>) {
+    let Some(mut playback) = playback else { return };
+    let playback = &mut *playback;
+    playback.pressed.clear();
+    while let Some(ReplayEvent(tick, player, action, pressed)) =
+        playback.events.get(playback.next).copied()
+    {
+        if tick > clock.tick {
+            break;
+        }
+        if pressed {
+            playback.held.insert((player, action));
+            playback.pressed.insert((player, action));
+        } else {
+            playback.held.remove(&(player, action));
+        }
+        playback.next += 1;
+    }
+}
+
+/// Drops keyboard and gamepad input during playback, so nothing reading
+/// them directly (such as debug toggles) interferes with the replay.
+fn ignore_live_input(
+    playback: Option<Res<ReplayPlayback>>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut buttons: ResMut<Input<GamepadButton>>,
+) {
+    if playback.is_some() {
+        keys.reset_all();
+        buttons.reset_all();
+    }
+}
+
+/// Debug toggles read the keyboard directly, so replays cannot repeat them.
+fn detect_debug_toggles(
+    rules: Res<MovementRules>,
+    config: Res<MovementConfig>,
+    editor: Res<EditorState>,
+    paused: Res<SimulationPaused>,
+    turn_based: Res<TurnBased>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    let reason = if rules.is_changed() && !rules.is_added() {
+        "noclip was toggled"
+    } else if config.is_changed() && !config.is_added() {
+        "the movement easing was changed"
+    } else if turn_based.is_changed() && !turn_based.is_added() {
+        "turn-based mode was toggled"
+    } else if editor.active {
+        "the editor was used"
+    } else if paused.0 {
+        "the game was paused"
+    } else {
+        return;
+    };
+    if let Some(mut recorder) = recorder {
+        recorder.taint(reason);
+    }
+    if playback.is_some() {
+        panic!("Replay diverged: {} during playback.", reason);
+    }
+}
+
+fn player_positions(players: &Query<(&Player, &Position)>) -> Vec<[i32; 3]> {
+    let mut players: Vec<(usize, [i32; 3])> = players
+        .iter()
+        .map(|(player, position)| {
+            let v = position.v;
+            (player.index, [v.x, v.y, v.z])
+        })
+        .collect();
+    players.sort_by_key(|(index, _)| *index);
+    players.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Ends playback on the recorded final tick, failing loudly if the session
+/// did not end the way it was recorded.
+fn check_playback(
+    clock: Res<LogicalClock>,
+    stats: Res<GameStats>,
+    players: Query<(&Player, &Position)>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(playback) = playback else { return };
+    let header = &playback.header;
+    if clock.tick < header.ticks {
+        return;
+    }
+
+    let positions = player_positions(&players);
+    let time = stats.time.elapsed_secs();
+    if stats.steps != header.steps
+        || (time - header.time).abs() > TIME_TOLERANCE
+        || positions != header.players
+    {
+        panic!(
+            "Replay diverged at tick {}: {} steps in {:.3}s at {:?}, recorded {} steps in {:.3}s at {:?}.",
+            clock.tick, stats.steps, time, positions, header.steps, header.time, header.players
+        );
+    }
+    info!("Replay matched after {} ticks.", clock.tick);
+    exit.send(AppExit);
+}
+
+fn save_recording(
+    mut exits: EventReader<AppExit>,
+    clock: Res<LogicalClock>,
+    fixed: Res<FixedTime>,
+    stats: Res<GameStats>,
+    players: Query<(&Player, &Position)>,
+    recorder: Option<Res<ReplayRecorder>>,
+) {
+    let Some(recorder) = recorder else { return };
+    if exits.iter().count() == 0 {
+        return;
+    }
+
+    let file = ReplayFile {
+        header: ReplayHeader {
+            seed: DEFAULT_SEED,
+            maps: recorder.maps.clone(),
+            period: fixed.period,
+            ticks: clock.tick,
+            steps: stats.steps,
+            time: stats.time.elapsed_secs(),
+            players: player_positions(&players),
+            nondeterministic: recorder.nondeterministic.clone(),
+        },
+        events: recorder.events.clone(),
+    };
+    let result = serde_json::to_string(&file)
+        .map_err(ReplayError::Format)
+        .and_then(|text| fs::write(&recorder.path, text).map_err(ReplayError::Io));
+    match result {
+        Ok(()) => info!(
+            "Saved {} replay events to `{}`.",
+            file.events.len(),
+            recorder.path
+        ),
+        Err(e) => error!("Could not save the replay to `{}`: {}", recorder.path, e),
+    }
+}
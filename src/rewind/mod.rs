@@ -0,0 +1,258 @@
+//! Holding rewind steps the whole world back a tick at a time: every
+//! actor's position and health, doors and tiles, as undo does for a single
+//! move. Each tick's changes are kept as `UndoRecord`s along with the
+//! random number state the tick started from, so that letting go and
+//! playing on from a rewound tick draws the same numbers it did before.
+//!
+//! What was defeated stays gone, and what was spawned stays, as neither is
+//! a change undo can revert.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    board::TileChangedEvent,
+    combat::Health,
+    input::{Action, ActionInput},
+    objects::Door,
+    rng::GameRng,
+    simulation::{Rewinding, SimulationPaused, SimulationSet},
+    undo::{UndoHistory, UndoRecord, UndoTargets},
+    vectors::Vector3Int,
+    AppState, Position, Tile,
+};
+
+/// Ticks kept for rewinding, half a minute at the default tick rate.
+pub const DEFAULT_REWIND_TICKS: usize = 600;
+/// Seconds a ghost left behind by rewinding takes to fade away.
+const GHOST_SECONDS: f32 = 0.6;
+const GHOST_ALPHA: f32 = 0.5;
+/// Under the actor it was left by.
+const GHOST_Z_OFFSET: f32 = 0.01;
+
+/// What one tick changed, oldest first.
+struct TickRecord {
+    /// `GameRng`'s state as the tick started.
+    rng: u64,
+    records: Vec<UndoRecord>,
+}
+
+/// The latest ticks, oldest first, up to `capacity` of them.
+#[derive(Resource)]
+pub struct RewindHistory {
+    pub capacity: usize,
+    ticks: VecDeque<TickRecord>,
+    /// `GameRng`'s state as the tick being recorded started.
+    rng: Option<u64>,
+    /// Tile changes since the last tick was recorded.
+    tiles: Vec<(Vector3Int, Option<usize>)>,
+}
+
+impl Default for RewindHistory {
+    fn default() -> Self {
+        RewindHistory {
+            capacity: DEFAULT_REWIND_TICKS,
+            ticks: VecDeque::new(),
+            rng: None,
+            tiles: Vec::new(),
+        }
+    }
+}
+
+impl RewindHistory {
+    fn push(&mut self, tick: TickRecord) {
+        self.ticks.push_back(tick);
+        while self.ticks.len() > self.capacity.max(1) {
+            self.ticks.pop_front();
+        }
+    }
+}
+
+/// Where each actor stood, how healthy it was and which doors were open
+/// as of the last recorded tick, to tell what the next one changed.
+#[derive(Default)]
+struct LastSeen {
+    positions: HashMap<Entity, Vector3Int>,
+    health: HashMap<Entity, u32>,
+    open_doors: HashSet<Entity>,
+}
+
+/// A fading copy of an actor's sprite, left where it was rewound to.
+#[derive(Component)]
+struct Ghost {
+    remaining: f32,
+}
+
+pub struct RewindPlugin;
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RewindHistory>()
+            .add_system(clear_history.in_schedule(OnEnter(AppState::Game)))
+            .add_system(
+                rewind
+                    .in_set(SimulationSet::Input)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                record_tick
+                    .in_set(SimulationSet::End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(collect_tile_changes)
+            .add_systems((spawn_ghosts, fade_ghosts).in_set(OnUpdate(AppState::Game)));
+    }
+}
+
+fn clear_history(mut history: ResMut<RewindHistory>, rng: Res<GameRng>) {
+    history.ticks.clear();
+    history.tiles.clear();
+    history.rng = Some(rng.state());
+}
+
+/// Tile changes go out as events, which ticks may not see every frame of.
+fn collect_tile_changes(
+    mut events: EventReader<TileChangedEvent>,
+    rewinding: Res<Rewinding>,
+    mut history: ResMut<RewindHistory>,
+) {
+    // Those are the rewind's own changes.
+    if rewinding.0 {
+        events.clear();
+        return;
+    }
+    (history.tiles).extend(events.iter().map(|e| (e.position, e.before)));
+}
+
+/// Keeps what the tick changed. While paused or rewinding only the last
+/// seen state catches up, so that the next tick records from there.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn record_tick(
+    paused: Res<SimulationPaused>,
+    rewinding: Res<Rewinding>,
+    state: Res<State<AppState>>,
+    rng: Res<GameRng>,
+    positions: Query<(Entity, &Position), (Changed<Position>, Without<Tile>)>,
+    healths: Query<(Entity, &Health), Changed<Health>>,
+    doors: Query<(Entity, &Door), Changed<Door>>,
+    mut last: Local<LastSeen>,
+    mut history: ResMut<RewindHistory>,
+) {
+    let mut records = Vec::new();
+    for (entity, position) in positions.iter() {
+        match last.positions.insert(entity, position.v) {
+            Some(from) if from != position.v => {
+                records.push(UndoRecord::Moved { entity, from });
+            }
+            _ => {}
+        }
+    }
+    for (entity, health) in healths.iter() {
+        match last.health.insert(entity, health.current) {
+            Some(before) if before != health.current => {
+                records.push(UndoRecord::HealthChanged { entity, before });
+            }
+            _ => {}
+        }
+    }
+    for (entity, door) in doors.iter() {
+        if !door.open {
+            last.open_doors.remove(&entity);
+        } else if last.open_doors.insert(entity) {
+            records.push(UndoRecord::DoorOpened { entity });
+        }
+    }
+    if !history.tiles.is_empty() {
+        // Painted back newest first, so a cell changed twice ends up as it
+        // was before either.
+        let before = history.tiles.drain(..).rev().collect();
+        records.push(UndoRecord::TilesPainted { before });
+    }
+
+    let start = history.rng.replace(rng.state());
+    if paused.0 || rewinding.0 || state.0 != AppState::Game {
+        return;
+    }
+    history.push(TickRecord {
+        rng: start.unwrap_or(rng.state()),
+        records,
+    });
+}
+
+/// While rewind is held, reverts the newest recorded tick on each tick and
+/// forgets it, so that letting go plays on from there. Once the history
+/// runs out the world stays where it is.
+#[allow(clippy::too_many_arguments)]
+fn rewind(
+    input: ActionInput,
+    paused: Res<SimulationPaused>,
+    state: Res<State<AppState>>,
+    mut rewinding: ResMut<Rewinding>,
+    mut history: ResMut<RewindHistory>,
+    mut undo: ResMut<UndoHistory>,
+    mut rng: ResMut<GameRng>,
+    mut targets: UndoTargets,
+) {
+    let held = !paused.0 && state.0 == AppState::Game && input.any_pressed(Action::Rewind);
+    if rewinding.0 != held {
+        rewinding.0 = held;
+    }
+    if !held {
+        return;
+    }
+    let Some(tick) = history.ticks.pop_back() else { return };
+    for record in tick.records.into_iter().rev() {
+        targets.revert(record);
+    }
+    rng.restore(tick.rng);
+    history.rng = Some(tick.rng);
+    // Undo's groups belong to the future just thrown away.
+    undo.clear();
+}
+
+/// Leaves a ghost wherever an actor is rewound to.
+#[allow(clippy::type_complexity)]
+fn spawn_ghosts(
+    mut commands: Commands,
+    rewinding: Res<Rewinding>,
+    actors: Query<
+        (&Transform, &TextureAtlasSprite, &Handle<TextureAtlas>),
+        (Changed<Position>, Without<Tile>, Without<Ghost>),
+    >,
+) {
+    if !rewinding.0 {
+        return;
+    }
+    for (transform, sprite, atlas) in actors.iter() {
+        let mut sprite = sprite.clone();
+        sprite.color.set_a(GHOST_ALPHA);
+        commands.spawn((
+            Ghost {
+                remaining: GHOST_SECONDS,
+            },
+            SpriteSheetBundle {
+                sprite,
+                texture_atlas: atlas.clone(),
+                transform: transform
+                    .with_translation(transform.translation - Vec3::Z * GHOST_Z_OFFSET),
+                ..default()
+            },
+        ));
+    }
+}
+
+fn fade_ghosts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut ghosts: Query<(Entity, &mut Ghost, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut ghost, mut sprite) in ghosts.iter_mut() {
+        ghost.remaining -= time.delta_seconds();
+        if ghost.remaining <= 0. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let alpha = GHOST_ALPHA * ghost.remaining / GHOST_SECONDS;
+        sprite.color.set_a(alpha);
+    }
+}
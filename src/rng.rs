@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+
+pub const DEFAULT_SEED: u64 = 0x5eed;
+
+/// Seeded random numbers for gameplay, so the same seed and inputs always
+/// play out the same way. Uses SplitMix64.
+#[derive(Resource)]
+pub struct GameRng {
+    seed: u64,
+    state: u64,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(DEFAULT_SEED)
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { seed, state: seed }
+    }
+
+    /// The seed it started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Where the sequence has got to, for `restore` to go back to.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Picks the sequence up again from an earlier `state`, so the numbers
+    /// drawn after it repeat.
+    pub fn restore(&mut self, state: u64) {
+        self.state = state;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`. `n` must be non-zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True with probability `p`, so 0 never and 1 always.
+    pub fn chance(&mut self, p: f32) -> bool {
+        // The top 24 bits, as many as an f32 holds exactly.
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) < p
+    }
+}
@@ -0,0 +1,221 @@
+//! Roofs that lift while someone is inside. A region object with a `roof`
+//! property is a building's interior: while a player is in it, the roof
+//! cells its group names fade to the group's `alpha`, and fade back once
+//! the last one has left. Each group eases toward its own target, so
+//! overlapping buildings, quick comings and goings and reloads all settle
+//! where they should. A roof cell in several groups shows the faintest.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    collision::{covered_cells, Footprint},
+    grid_to_position, layer_of, layer_z,
+    pathfinding::flood_fill,
+    player::Player,
+    regions::{Region, RegionEntered, RegionExited, RegionMembership},
+    render_layers::{z_index, RenderLayerSlot},
+    tint::TileTint,
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position, Tile,
+};
+
+/// Seconds a roof takes to fade all the way out or back.
+const ROOF_FADE_S: f32 = 0.3;
+/// Steps a flood-filled roof spreads from the cells it starts at.
+const ROOF_FLOOD_STEPS: u32 = 64;
+
+/// The roof over a region, from a region object's `roof` property.
+#[derive(Component, Deserialize, Clone, Debug)]
+pub struct RoofGroup {
+    /// Roof cells to fade, by column and row as in the map. Without any,
+    /// those over the region itself.
+    #[serde(default)]
+    pub cells: Vec<[i32; 2]>,
+    /// Also takes in every roof cell joined to those, so that a region
+    /// over a building's floor lifts the whole of its roof.
+    #[serde(default)]
+    pub flood: bool,
+    /// The roof's opacity while lifted.
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    /// Whether NPCs inside lift it too, and not only players.
+    #[serde(default)]
+    pub npcs: bool,
+}
+
+fn default_alpha() -> f32 {
+    0.2
+}
+
+/// Each roof group's cells and how far it has faded.
+struct RoofState {
+    cells: Vec<Vector3Int>,
+    alpha: f32,
+    target: f32,
+    lifted: f32,
+    npcs: bool,
+    /// Whether `alpha` has yet to reach the roof's tiles.
+    dirty: bool,
+}
+
+/// Roof groups by region id, so that a group keeps its opacity when the
+/// map is reloaded.
+#[derive(Default, Resource)]
+struct Roofs {
+    groups: HashMap<String, RoofState>,
+    /// Cells of groups since gone, to show in full again.
+    released: Vec<Vector3Int>,
+}
+
+pub struct RoofsPlugin;
+impl Plugin for RoofsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Roofs>()
+            .add_systems((gather_roofs, target_roofs, fade_roofs).chain());
+    }
+}
+
+/// The roof cells `group` fades, for its region at `position`.
+fn roof_cells(
+    group: &RoofGroup,
+    position: &Position,
+    footprint: Option<&Footprint>,
+    current: &CurrentBoard,
+    grid: GridKind,
+) -> Vec<Vector3Int> {
+    let roof = |v: Vector3Int| {
+        let z = layer_z(layer_of(v.z), z_index(RenderLayerSlot::Roof, 0));
+        current.wrap(Vector3Int::new(v.x, v.y, z))
+    };
+    let is_roof = |v: Vector3Int| current.tiles.contains_key(&v);
+    let start: Vec<Vector3Int> = match group.cells.is_empty() {
+        true => covered_cells(position.v, footprint),
+        false => (group.cells.iter())
+            .map(|[col, row]| grid_to_position(grid, *col, *row, position.v.z))
+            .collect(),
+    };
+    let start = start.into_iter().map(roof).filter(|v| is_roof(*v));
+    if !group.flood {
+        return start.collect();
+    }
+    let mut cells = HashSet::new();
+    for v in start {
+        if cells.contains(&v) {
+            continue;
+        }
+        let neighbours = |v| current.neighbours(v, grid);
+        let joined = flood_fill(v, neighbours, is_roof, ROOF_FLOOD_STEPS);
+        cells.extend(joined.into_iter().filter(|v| is_roof(*v)));
+    }
+    cells.into_iter().collect()
+}
+
+/// Works out each group's cells again whenever the board or the roof
+/// regions change, keeping how far each had faded.
+fn gather_roofs(
+    current: Res<CurrentBoard>,
+    grid: Res<GridKind>,
+    groups: Query<(&Region, &RoofGroup, &Position, Option<&Footprint>)>,
+    added: Query<(), Added<RoofGroup>>,
+    mut removed: RemovedComponents<RoofGroup>,
+    mut roofs: ResMut<Roofs>,
+) {
+    let removed = removed.iter().count() > 0;
+    if !current.is_changed() && added.is_empty() && !removed {
+        return;
+    }
+    let mut old = std::mem::take(&mut roofs.groups);
+    for (region, group, position, footprint) in groups.iter() {
+        let cells = roof_cells(group, position, footprint, &current, *grid);
+        let (alpha, target) = (old.remove(&region.id)).map_or((1., 1.), |s| (s.alpha, s.target));
+        let state = RoofState {
+            cells,
+            alpha,
+            target,
+            lifted: group.alpha.clamp(0., 1.),
+            npcs: group.npcs,
+            dirty: true,
+        };
+        roofs.groups.insert(region.id.clone(), state);
+    }
+    let gone = old.into_values().flat_map(|state| state.cells);
+    roofs.released.extend(gone);
+}
+
+/// Lifts the roofs of regions with someone inside, players only unless
+/// the group says otherwise, and lowers the rest, as they come and go.
+fn target_roofs(
+    mut entered: EventReader<RegionEntered>,
+    mut exited: EventReader<RegionExited>,
+    membership: Res<RegionMembership>,
+    players: Query<(), With<Player>>,
+    mut roofs: ResMut<Roofs>,
+) {
+    let moved = entered.iter().count() + exited.iter().count() > 0;
+    if !moved && !roofs.is_changed() {
+        return;
+    }
+    for (id, state) in roofs.bypass_change_detection().groups.iter_mut() {
+        let mut inside = membership.members_of(id);
+        let lifted = inside.any(|entity| state.npcs || players.contains(entity));
+        state.target = if lifted { state.lifted } else { 1. };
+    }
+}
+
+/// Eases each group toward its target, and gives the tiles of groups that
+/// moved the faintest opacity of any group over them.
+fn fade_roofs(
+    mut commands: Commands,
+    time: Res<Time>,
+    current: Res<CurrentBoard>,
+    tiles: Query<(), With<Tile>>,
+    mut tints: Query<&mut TileTint>,
+    mut roofs: ResMut<Roofs>,
+) {
+    let step = time.delta_seconds() / ROOF_FADE_S;
+    let roofs = roofs.bypass_change_detection();
+    for state in roofs.groups.values_mut() {
+        if state.alpha != state.target {
+            let change = (state.target - state.alpha).clamp(-step, step);
+            state.alpha += change;
+            state.dirty = true;
+        }
+    }
+    let released = std::mem::take(&mut roofs.released);
+    if released.is_empty() && !roofs.groups.values().any(|s| s.dirty) {
+        return;
+    }
+
+    let mut faintest: HashMap<Vector3Int, f32> = HashMap::new();
+    for state in roofs.groups.values() {
+        for v in &state.cells {
+            let alpha = faintest.entry(*v).or_insert(1.);
+            *alpha = alpha.min(state.alpha);
+        }
+    }
+    let dirty = (roofs.groups.values_mut()).flat_map(|state| {
+        let dirty = std::mem::take(&mut state.dirty);
+        let state: &RoofState = state;
+        state.cells.iter().copied().filter(move |_| dirty)
+    });
+    for v in released.into_iter().chain(dirty) {
+        let alpha = faintest.get(&v).copied().unwrap_or(1.);
+        let Some(&entity) = current.tiles.get(&v) else { continue };
+        match tints.get_mut(entity) {
+            Ok(mut tint) => {
+                if tint.alpha != alpha {
+                    tint.alpha = alpha;
+                }
+            }
+            Err(_) if alpha < 1. && tiles.contains(entity) => {
+                commands
+                    .entity(entity)
+                    .insert(TileTint { alpha, ..default() });
+            }
+            Err(_) => {}
+        }
+    }
+}
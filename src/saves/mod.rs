@@ -0,0 +1,694 @@
+use std::{fmt, time::Duration};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::Health,
+    difficulty::DifficultySettings,
+    equipment::Equipment,
+    flags::GameFlags,
+    followers::Follower,
+    get_world_position,
+    inventory::{Currency, Inventory},
+    locale::Localization,
+    map_meta::MapMeta,
+    objects::{self, Door, MapObjectIndex},
+    persistence::MapMemory,
+    player::{MoveTween, MovementState, Player},
+    progression::{Campaign, GameStats},
+    projection::GridProjection,
+    shop::Shop,
+    simulation::SimulationPaused,
+    stats::{CurrentRun, RunRecord},
+    status::StatusEffects,
+    storage::{platform_store, unix_time, KeyValueStore, StorageError},
+    t,
+    territory::Territory,
+    timer::LevelTimer,
+    turns::ActionPoints,
+    vectors::Vector3Int,
+    AppState, GraphicsAssets, LoadMapEvent, Position,
+};
+
+/// Number of save slots offered in the menu.
+pub const SAVE_SLOTS: usize = 3;
+/// Where the slots are kept: a directory under the working directory, or a
+/// key prefix in the browser.
+pub const SAVE_DIR: &str = "saves";
+
+const MENU_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const MENU_FONT_SIZE: f32 = 20.;
+
+/// What the menu lists for a slot, read without loading the game.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveHeader {
+    /// The map's asset path.
+    pub map: String,
+    /// The map's name, if its meta gives one.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Seconds played on the map.
+    pub play_time: f32,
+    /// Seconds since the Unix epoch when the slot was written.
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedPlayer {
+    pub index: usize,
+    pub position: [i32; 3],
+    pub health: Option<Health>,
+    #[serde(default)]
+    pub effects: StatusEffects,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default)]
+    pub inventory: Inventory,
+    #[serde(default)]
+    pub equipment: Equipment,
+    /// What was left of the player's turn, if turn-based mode had given
+    /// them one.
+    #[serde(default)]
+    pub action_points: Option<ActionPoints>,
+}
+
+/// A follower, by the index of the player it follows.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedFollower {
+    pub player: usize,
+    pub position: [i32; 3],
+    pub health: Option<Health>,
+}
+
+/// Everything written to a slot. Map objects such as doors are put back
+/// from the maps' memory.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub header: SaveHeader,
+    pub players: Vec<SavedPlayer>,
+    pub flags: GameFlags,
+    pub territory: Territory,
+    /// The run so far, and the cells explored on this map.
+    #[serde(default)]
+    pub run: RunRecord,
+    #[serde(default)]
+    pub explored: Vec<[i32; 2]>,
+    /// The countdown, on maps with a time limit.
+    #[serde(default)]
+    pub timer: LevelTimer,
+    /// Followers in place of those the map starts with.
+    #[serde(default)]
+    pub followers: Vec<SavedFollower>,
+    /// Every map visited as it was left, the saved one included.
+    #[serde(default)]
+    pub memory: MapMemory,
+    /// The difficulty the run was played at, if the save records it.
+    #[serde(default)]
+    pub difficulty: Option<DifficultySettings>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Storage(StorageError),
+    Format(ron::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Storage(e) => write!(f, "{}", e),
+            SaveError::Format(e) => write!(f, "could not write save: {}", e),
+            SaveError::Parse(e) => write!(f, "damaged save: {}", e),
+        }
+    }
+}
+
+/// The state of a slot on disk.
+#[derive(Clone, Debug)]
+pub enum SlotInfo {
+    Empty,
+    /// The slot holds something that does not parse.
+    Damaged,
+    Saved(SaveHeader),
+}
+
+pub fn slot_key(slot: usize) -> String {
+    format!("slot_{}.ron", slot)
+}
+
+/// Writes `save` over the slot. The store replaces the previous save whole,
+/// so a crash mid-write leaves it intact.
+pub fn write_slot(slot: usize, save: &SaveGame) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Format)?;
+    (platform_store(SAVE_DIR).set(&slot_key(slot), &text)).map_err(SaveError::Storage)
+}
+
+/// The save in `slot`, or `None` if it is empty.
+pub fn read_slot(slot: usize) -> Result<Option<SaveGame>, SaveError> {
+    let text = (platform_store(SAVE_DIR).get(&slot_key(slot))).map_err(SaveError::Storage)?;
+    text.map(|text| ron::from_str(&text).map_err(SaveError::Parse))
+        .transpose()
+}
+
+pub fn delete_slot(slot: usize) -> Result<(), SaveError> {
+    (platform_store(SAVE_DIR).remove(&slot_key(slot))).map_err(SaveError::Storage)
+}
+
+pub fn slot_info(slot: usize) -> SlotInfo {
+    match read_slot(slot) {
+        Ok(Some(save)) => SlotInfo::Saved(save.header),
+        Ok(None) => SlotInfo::Empty,
+        Err(_) => SlotInfo::Damaged,
+    }
+}
+
+/// `timestamp` as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let minutes = timestamp % 86400 / 60;
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// An action on the selected slot waiting for the player to confirm it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pending {
+    Overwrite,
+    Load,
+    Delete,
+}
+
+/// The save menu, open over a paused game.
+#[derive(Default, Resource)]
+pub struct SaveMenu {
+    open: bool,
+    selected: usize,
+    confirm: Option<Pending>,
+    slots: Vec<SlotInfo>,
+    /// The outcome of the last action, shown under the slots.
+    message: String,
+    /// The name of the map being played, read when the menu opens.
+    map_name: Option<String>,
+}
+
+impl SaveMenu {
+    /// Whether the menu is open and not waiting on a confirmation.
+    pub fn is_idle(&self) -> bool {
+        self.open && self.confirm.is_none()
+    }
+
+    /// Closes the menu, leaving the game paused for whatever replaces it.
+    pub fn close(&mut self) {
+        self.open = false;
+        self.confirm = None;
+        self.message.clear();
+    }
+
+    fn refresh(&mut self) {
+        self.slots = (0..SAVE_SLOTS).map(slot_info).collect();
+    }
+
+    fn text(&self, locale: &Localization) -> String {
+        let mut text = t!(locale, "saves.title") + "\n";
+        if let Some(name) = &self.map_name {
+            text += &t!(locale, "saves.map", name = name);
+            text += "\n";
+        }
+        text += "\n";
+        for (i, slot) in self.slots.iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            let label = match slot {
+                SlotInfo::Empty => t!(locale, "saves.empty"),
+                SlotInfo::Damaged => t!(locale, "saves.damaged"),
+                SlotInfo::Saved(header) => format!(
+                    "{}  {:.0}s  {}",
+                    header.name.as_ref().unwrap_or(&header.map),
+                    header.play_time,
+                    format_timestamp(header.timestamp)
+                ),
+            };
+            text += &format!("{} {}. {}\n", marker, i + 1, label);
+        }
+        text += "\n";
+        text += &t!(
+            locale,
+            match self.confirm {
+                Some(Pending::Overwrite) => "saves.confirm.overwrite",
+                Some(Pending::Load) => "saves.confirm.load",
+                Some(Pending::Delete) => "saves.confirm.delete",
+                None => "saves.help",
+            }
+        );
+        if !self.message.is_empty() {
+            text += "\n\n";
+            text += &self.message;
+        }
+        text
+    }
+}
+
+#[derive(Component)]
+struct SaveMenuText;
+
+/// What a save keeps of the run besides players, followers and maps.
+#[derive(SystemParam)]
+struct RunState<'w> {
+    stats: Res<'w, GameStats>,
+    run: Res<'w, CurrentRun>,
+    timer: Res<'w, LevelTimer>,
+    flags: Res<'w, GameFlags>,
+    territory: Res<'w, Territory>,
+    difficulty: Res<'w, DifficultySettings>,
+}
+
+/// A save being loaded, applied once its map is back in play.
+#[derive(Resource)]
+struct PendingSave {
+    save: SaveGame,
+    /// Set on entering the game again, after the map has placed players.
+    ready: bool,
+}
+
+pub struct SavesPlugin;
+impl Plugin for SavesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveMenu>()
+            .add_systems(
+                (toggle_menu, navigate_menu, draw_menu)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(close_menu.in_schedule(OnExit(AppState::Game)))
+            .add_system(arm_pending_save.in_schedule(OnEnter(AppState::Game)))
+            // After new objects get their sprites, so that followers it
+            // despawns are not drawn afterwards.
+            .add_system(
+                apply_pending_save
+                    .after(objects::spawn_object_renderer)
+                    .in_set(OnUpdate(AppState::Game)),
+            );
+    }
+}
+
+fn toggle_menu(
+    keys: Res<Input<KeyCode>>,
+    mut menu: ResMut<SaveMenu>,
+    mut paused: ResMut<SimulationPaused>,
+    meta: Res<MapMeta>,
+) {
+    let close = menu.open && menu.confirm.is_none() && keys.just_pressed(KeyCode::Escape);
+    if !keys.just_pressed(KeyCode::F5) && !close {
+        return;
+    }
+    menu.open = !menu.open;
+    menu.confirm = None;
+    menu.message.clear();
+    if menu.open {
+        menu.refresh();
+        menu.map_name = meta.name.clone();
+    }
+    paused.0 = menu.open;
+}
+
+fn close_menu(
+    mut commands: Commands,
+    mut menu: ResMut<SaveMenu>,
+    mut paused: ResMut<SimulationPaused>,
+    panels: Query<Entity, (With<SaveMenuText>, Without<Parent>)>,
+) {
+    menu.open = false;
+    menu.confirm = None;
+    paused.0 = false;
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn navigate_menu(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut menu: ResMut<SaveMenu>,
+    mut paused: ResMut<SimulationPaused>,
+    mut campaign: ResMut<Campaign>,
+    state: RunState,
+    players: Query<(
+        &Player,
+        &Position,
+        Option<&Health>,
+        Option<&StatusEffects>,
+        Option<&Currency>,
+        Option<&Inventory>,
+        Option<&Equipment>,
+        Option<&ActionPoints>,
+    )>,
+    followers: Query<(&Follower, &Position, Option<&Health>), Without<Player>>,
+    mut memory: ResMut<MapMemory>,
+    objects: Query<(&MapObjectIndex, Option<&Door>, Option<&Shop>)>,
+    mut loads: EventWriter<LoadMapEvent>,
+    locale: Res<Localization>,
+) {
+    if !menu.open {
+        return;
+    }
+    let selected = menu.selected;
+
+    if let Some(pending) = menu.confirm {
+        if keys.just_pressed(KeyCode::N) || keys.just_pressed(KeyCode::Escape) {
+            menu.confirm = None;
+            return;
+        }
+        if !keys.just_pressed(KeyCode::Y) {
+            return;
+        }
+        menu.confirm = None;
+        match pending {
+            Pending::Overwrite => {
+                let name = menu.map_name.clone();
+                menu.message = save(
+                    selected, &campaign, name, &state, &players, &followers, &memory, &objects,
+                    &locale,
+                );
+            }
+            Pending::Delete => {
+                let slot = selected + 1;
+                menu.message = match delete_slot(selected) {
+                    Ok(()) => t!(locale, "saves.deleted", slot = slot),
+                    Err(e) => t!(locale, "saves.delete_failed", slot = slot, error = e),
+                };
+            }
+            Pending::Load => match read_slot(selected) {
+                Ok(None) => menu.message = t!(locale, "saves.nothing"),
+                Ok(Some(save)) => {
+                    let Some(index) = campaign.maps.iter().position(|m| *m == save.header.map)
+                    else {
+                        menu.message = t!(locale, "saves.not_in_campaign", map = save.header.map);
+                        return;
+                    };
+                    info!("Loading slot {}.", selected + 1);
+                    campaign.current = index;
+                    memory.restore(&save.memory);
+                    loads.send(LoadMapEvent {
+                        name: save.header.map.clone(),
+                    });
+                    commands.insert_resource(PendingSave { save, ready: false });
+                    menu.open = false;
+                    paused.0 = false;
+                    return;
+                }
+                Err(e) => {
+                    menu.message = t!(locale, "saves.load_failed", slot = selected + 1, error = e)
+                }
+            },
+        }
+        menu.refresh();
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Up) {
+        menu.selected = (selected + SAVE_SLOTS - 1) % SAVE_SLOTS;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        menu.selected = (selected + 1) % SAVE_SLOTS;
+    }
+    let digits = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+    if let Some(i) = (digits.iter().take(SAVE_SLOTS)).position(|k| keys.just_pressed(*k)) {
+        menu.selected = i;
+    }
+
+    let slot = menu.slots.get(menu.selected).cloned();
+    if keys.just_pressed(KeyCode::S) {
+        match slot {
+            Some(SlotInfo::Empty) => {
+                let selected = menu.selected;
+                let name = menu.map_name.clone();
+                menu.message = save(
+                    selected, &campaign, name, &state, &players, &followers, &memory, &objects,
+                    &locale,
+                );
+                menu.refresh();
+            }
+            _ => menu.confirm = Some(Pending::Overwrite),
+        }
+    } else if keys.just_pressed(KeyCode::L) {
+        match slot {
+            Some(SlotInfo::Saved(_)) => menu.confirm = Some(Pending::Load),
+            _ => menu.message = t!(locale, "saves.nothing"),
+        }
+    } else if keys.any_just_pressed([KeyCode::X, KeyCode::Delete]) {
+        match slot {
+            Some(SlotInfo::Empty) | None => {}
+            _ => menu.confirm = Some(Pending::Delete),
+        }
+    }
+}
+
+/// Writes the game to `slot`, returning the message to show.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn save(
+    slot: usize,
+    campaign: &Campaign,
+    name: Option<String>,
+    state: &RunState,
+    players: &Query<(
+        &Player,
+        &Position,
+        Option<&Health>,
+        Option<&StatusEffects>,
+        Option<&Currency>,
+        Option<&Inventory>,
+        Option<&Equipment>,
+        Option<&ActionPoints>,
+    )>,
+    followers: &Query<(&Follower, &Position, Option<&Health>), Without<Player>>,
+    memory: &MapMemory,
+    objects: &Query<(&MapObjectIndex, Option<&Door>, Option<&Shop>)>,
+    locale: &Localization,
+) -> String {
+    let save = SaveGame {
+        header: SaveHeader {
+            map: campaign.current_map().to_string(),
+            name,
+            play_time: state.stats.time.elapsed_secs(),
+            timestamp: unix_time(),
+        },
+        players: (players.iter())
+            .map(
+                |(player, position, health, effects, currency, inventory, equipment, points)| {
+                    SavedPlayer {
+                        index: player.index,
+                        position: [position.v.x, position.v.y, position.v.z],
+                        health: health.copied(),
+                        effects: effects.cloned().unwrap_or_default(),
+                        currency: currency.copied().unwrap_or_default(),
+                        inventory: inventory.cloned().unwrap_or_default(),
+                        equipment: equipment.cloned().unwrap_or_default(),
+                        action_points: points.copied(),
+                    }
+                },
+            )
+            .collect(),
+        flags: state.flags.snapshot(),
+        territory: state.territory.clone(),
+        run: state.run.record.clone(),
+        explored: state.run.explored_cells(),
+        timer: state.timer.clone(),
+        followers: (followers.iter())
+            .filter_map(|(follower, position, health)| {
+                let (player, ..) = players.get(follower.target).ok()?;
+                Some(SavedFollower {
+                    player: player.index,
+                    position: [position.v.x, position.v.y, position.v.z],
+                    health: health.copied(),
+                })
+            })
+            .collect(),
+        memory: memory.snapshot(objects.iter()),
+        difficulty: Some(*state.difficulty),
+    };
+    match write_slot(slot, &save) {
+        Ok(()) => {
+            info!("Saved to {}/{}.", SAVE_DIR, slot_key(slot));
+            t!(locale, "saves.saved", slot = slot + 1)
+        }
+        Err(e) => {
+            warn!("Could not save to {}/{}: {}", SAVE_DIR, slot_key(slot), e);
+            t!(locale, "saves.save_failed", slot = slot + 1, error = e)
+        }
+    }
+}
+
+fn draw_menu(
+    mut commands: Commands,
+    menu: Res<SaveMenu>,
+    assets: Res<GraphicsAssets>,
+    mut texts: Query<&mut Text, With<SaveMenuText>>,
+    panels: Query<Entity, (With<SaveMenuText>, Without<Parent>)>,
+    locale: Res<Localization>,
+) {
+    if !menu.is_changed() && !locale.is_changed() {
+        return;
+    }
+    if !menu.open {
+        for entity in panels.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if let Some(mut text) = texts.iter_mut().next() {
+        text.sections[0].value = menu.text(&locale);
+        return;
+    }
+
+    commands
+        .spawn((
+            SaveMenuText,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: MENU_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                SaveMenuText,
+                TextBundle::from_section(
+                    menu.text(&locale),
+                    TextStyle {
+                        font: assets.font.clone(),
+                        font_size: MENU_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
+
+fn arm_pending_save(pending: Option<ResMut<PendingSave>>) {
+    if let Some(mut pending) = pending {
+        pending.ready = true;
+    }
+}
+
+/// Puts players, followers, flags, territory, the run, the countdown and
+/// the difficulty back as saved, once the saved map has loaded.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn apply_pending_save(
+    mut commands: Commands,
+    pending: Option<Res<PendingSave>>,
+    projection: Res<GridProjection>,
+    mut flags: ResMut<GameFlags>,
+    mut territory: ResMut<Territory>,
+    mut stats: ResMut<GameStats>,
+    mut run: ResMut<CurrentRun>,
+    mut timer: ResMut<LevelTimer>,
+    mut difficulty: ResMut<DifficultySettings>,
+    mut players: Query<(
+        Entity,
+        &Player,
+        &mut Position,
+        &mut MovementState,
+        Option<&mut MoveTween>,
+        Option<&mut Transform>,
+    )>,
+    mut followers: Query<
+        (Entity, &mut Follower, &mut Position, Option<&mut Transform>),
+        Without<Player>,
+    >,
+) {
+    let Some(pending) = pending.filter(|p| p.ready) else { return };
+    let save = &pending.save;
+
+    for (entity, player, mut position, mut state, tween, transform) in players.iter_mut() {
+        let Some(saved) = save.players.iter().find(|p| p.index == player.index) else { continue };
+        let [x, y, z] = saved.position;
+        position.v = Vector3Int::new(x, y, z);
+        state.cancel_step();
+        state.slide = None;
+        let v = get_world_position(&position, &projection);
+        if let Some(mut tween) = tween {
+            *tween = MoveTween::at(v);
+        }
+        if let Some(mut transform) = transform {
+            transform.translation = v;
+        }
+        let mut entity = commands.entity(entity);
+        if let Some(health) = saved.health {
+            entity.insert(health);
+        }
+        if let Some(points) = saved.action_points {
+            entity.insert(points);
+        }
+        entity.insert((
+            saved.effects.clone(),
+            saved.currency,
+            saved.inventory.clone(),
+            saved.equipment.clone(),
+        ));
+    }
+
+    // The map's followers take the saved ones' places in turn. Any left
+    // over had been lost by the time of saving.
+    let mut placed: Vec<_> = followers.iter_mut().collect();
+    placed.sort_by_key(|(entity, ..)| *entity);
+    let mut placed = placed.into_iter();
+    for saved in save.followers.iter() {
+        let Some((entity, mut follower, mut position, transform)) = placed.next() else {
+            warn!("The map has fewer followers than were saved.");
+            break;
+        };
+        let [x, y, z] = saved.position;
+        position.v = Vector3Int::new(x, y, z);
+        if let Some((target, ..)) = players.iter().find(|(_, p, ..)| p.index == saved.player) {
+            follower.target = target;
+        }
+        if let Some(mut transform) = transform {
+            transform.translation = get_world_position(&position, &projection);
+        }
+        if let Some(health) = saved.health {
+            commands.entity(entity).insert(health);
+        }
+    }
+    for (entity, ..) in placed {
+        commands.entity(entity).despawn_recursive();
+    }
+    *flags = save.flags.snapshot();
+    *territory = save.territory.clone();
+    run.restore(save.run.clone(), &save.header.map, &save.explored);
+    timer.restore(&save.timer, &save.header.map);
+    if let Some(saved) = save.difficulty.filter(|d| *d != *difficulty) {
+        *difficulty = saved;
+    }
+    stats.time.reset();
+    stats
+        .time
+        .tick(Duration::from_secs_f32(save.header.play_time.max(0.)));
+
+    commands.remove_resource::<PendingSave>();
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    collision::{CollisionFlags, CollisionMap},
+    player::Player,
+    vectors::Vector3Int,
+    CurrentBoard, Position,
+};
+
+/// Asks for the sound registered under `key` to be played.
+pub struct PlaySfxEvent {
+    pub key: String,
+    /// The cell the sound comes from, for it to be muffled by walls between
+    /// it and the players. `None` plays it as it is.
+    pub position: Option<Vector3Int>,
+}
+
+/// Sounds by key, as declared by the map.
+#[derive(Default, Resource)]
+pub struct SfxLibrary {
+    sounds: HashMap<String, Handle<AudioSource>>,
+}
+
+impl SfxLibrary {
+    pub fn insert(&mut self, key: String, sound: Handle<AudioSource>) {
+        self.sounds.insert(key, sound);
+    }
+
+    pub fn clear(&mut self) {
+        self.sounds.clear();
+    }
+}
+
+/// How walls between a sound and the players quieten it.
+#[derive(Resource, Clone, Debug)]
+pub struct SfxOcclusionConfig {
+    /// The share of its volume a sound keeps through each blocked cell.
+    pub blocker_volume: f32,
+    /// The quietest a sound gets however many walls are in the way.
+    pub min_volume: f32,
+    /// Seconds the walls counted from a cell are reused for, so a burst of
+    /// sounds from one place is only traced once.
+    pub cache_seconds: f32,
+    /// Appended to a sound's key for a muffled variant, played instead
+    /// when the map has one and anything is in the way.
+    pub muffled_suffix: String,
+}
+
+impl Default for SfxOcclusionConfig {
+    fn default() -> Self {
+        SfxOcclusionConfig {
+            blocker_volume: 0.5,
+            min_volume: 0.1,
+            cache_seconds: 0.3,
+            muffled_suffix: "_muffled".to_string(),
+        }
+    }
+}
+
+impl SfxOcclusionConfig {
+    /// The volume of a sound heard through `blockers` blocked cells.
+    pub fn volume(&self, blockers: u32) -> f32 {
+        (self.blocker_volume.powi(blockers as i32))
+            .max(self.min_volume)
+            .min(1.)
+    }
+}
+
+/// Walls counted between each source cell and the players, with where the
+/// players stood and the time they were counted at.
+#[derive(Default, Resource)]
+struct OcclusionCache {
+    blockers: HashMap<Vector3Int, (Vec<Vector3Int>, u32, f32)>,
+}
+
+pub struct SfxPlugin;
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlaySfxEvent>()
+            .init_resource::<SfxLibrary>()
+            .init_resource::<SfxOcclusionConfig>()
+            .init_resource::<OcclusionCache>()
+            .add_system(play_sfx);
+    }
+}
+
+/// Cells blocking sight on the straight line from `listener` to `source`, not
+/// counting either end, across wrapping edges. Walls are looked up on the
+/// source's layer.
+pub fn count_blockers(
+    listener: Vector3Int,
+    source: Vector3Int,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+) -> u32 {
+    let start = Vector3Int::new(listener.x, listener.y, source.z);
+    let delta = current.delta(start, source);
+    let end = Vector3Int::new(start.x + delta.x, start.y + delta.y, source.z);
+    let line = start.line_to(end);
+    (line.iter().skip(1).take(line.len().saturating_sub(2)))
+        .filter(|v| collision.blocks(current.wrap(**v), CollisionFlags::BLOCK_SIGHT))
+        .count() as u32
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_sfx(
+    mut events: EventReader<PlaySfxEvent>,
+    library: Res<SfxLibrary>,
+    audio: Res<Audio>,
+    players: Query<&Position, With<Player>>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    config: Res<SfxOcclusionConfig>,
+    time: Res<Time>,
+    mut cache: ResMut<OcclusionCache>,
+) {
+    let now = time.elapsed_seconds();
+    cache
+        .blockers
+        .retain(|_, (_, _, at)| now - *at < config.cache_seconds);
+    let listeners: Vec<Vector3Int> = players.iter().map(|p| p.v).collect();
+
+    for event in events.iter() {
+        // Heard by whichever player has the clearest line to it.
+        let blockers = match event.position.filter(|_| !listeners.is_empty()) {
+            Some(source) => match cache.blockers.get(&source) {
+                Some((heard_from, blockers, _)) if *heard_from == listeners => *blockers,
+                _ => {
+                    let blockers = (listeners.iter())
+                        .map(|listener| count_blockers(*listener, source, &current, &collision))
+                        .min()
+                        .unwrap_or(0);
+                    cache
+                        .blockers
+                        .insert(source, (listeners.clone(), blockers, now));
+                    blockers
+                }
+            },
+            None => 0,
+        };
+
+        let muffled = format!("{}{}", event.key, config.muffled_suffix);
+        let sound = match library.sounds.get(&muffled) {
+            Some(sound) if blockers > 0 => Some(sound),
+            _ => library.sounds.get(&event.key),
+        };
+        match sound {
+            Some(sound) => {
+                let volume = config.volume(blockers);
+                audio.play_with_settings(sound.clone(), PlaybackSettings::ONCE.with_volume(volume));
+            }
+            None => debug!("No sound loaded for `{}`.", event.key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{vectors::GridRect, BoardBounds, Wrap};
+
+    fn cell(x: i32) -> Vector3Int {
+        Vector3Int::new(x, 0, 0)
+    }
+
+    /// Walls on `cells`.
+    fn walls(cells: &[Vector3Int]) -> CollisionMap {
+        let mut collision = CollisionMap::default();
+        for v in cells {
+            collision.block(*v);
+        }
+        collision
+    }
+
+    #[test]
+    fn each_wall_between_muffles_once() {
+        let current = CurrentBoard::default();
+        let count = |cells: &[Vector3Int]| count_blockers(cell(0), cell(6), &current, &walls(cells));
+
+        assert_eq!(count(&[]), 0);
+        assert_eq!(count(&[cell(2)]), 1);
+        assert_eq!(count(&[cell(2), cell(4)]), 2);
+        // Off the line, a wall makes no difference.
+        assert_eq!(count(&[cell(2), Vector3Int::new(3, 1, 0)]), 1);
+    }
+
+    #[test]
+    fn walls_on_either_end_are_not_counted() {
+        let current = CurrentBoard::default();
+        let collision = walls(&[cell(0), cell(6)]);
+        assert_eq!(count_blockers(cell(0), cell(6), &current, &collision), 0);
+    }
+
+    #[test]
+    fn lines_cross_wrapping_edges() {
+        let bounds = BoardBounds {
+            rect: GridRect::new(0, 0, 10, 1),
+            wrap: Wrap { x: true, y: false },
+        };
+        let current = CurrentBoard {
+            bounds,
+            ..default()
+        };
+        // From column 1 to 8 the short way is left, over the edge through
+        // columns 0 and 9, so a wall in column 4 is not between them.
+        let count = |cells: &[Vector3Int]| count_blockers(cell(1), cell(8), &current, &walls(cells));
+        assert_eq!(count(&[cell(9)]), 1);
+        assert_eq!(count(&[cell(4)]), 0);
+        assert_eq!(count(&[cell(0), cell(9)]), 2);
+    }
+
+    #[test]
+    fn volume_halves_per_wall_down_to_the_floor() {
+        let config = SfxOcclusionConfig {
+            blocker_volume: 0.5,
+            min_volume: 0.2,
+            ..default()
+        };
+        let volumes: Vec<f32> = (0..5).map(|blockers| config.volume(blockers)).collect();
+        assert_eq!(volumes, [1., 0.5, 0.25, 0.2, 0.2]);
+    }
+}
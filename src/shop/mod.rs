@@ -0,0 +1,387 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    collision::{covers, Footprint},
+    inventory::{Currency, Inventory},
+    locale::Localization,
+    player::{BumpEvent, Player},
+    simulation::{SimulationPaused, SimulationSet},
+    t, AppState, GraphicsAssets, Position,
+};
+
+const PANEL_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const PANEL_FONT_SIZE: f32 = 20.;
+const SELECTED_COLOR: Color = Color::YELLOW;
+/// Keys that trade the selected row, and that leave the shop.
+pub const TRADE_KEYS: [KeyCode; 2] = [KeyCode::Return, KeyCode::NumpadEnter];
+pub const CLOSE_KEY: KeyCode = KeyCode::Escape;
+
+/// One line of a shop's stock: `quantity` of `item` at `price` coins each.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StockEntry {
+    pub item: String,
+    pub price: u32,
+    pub quantity: u32,
+}
+
+/// Trades with players who bump into it. It sells its stock and buys back
+/// the items it stocks, which go back on its shelves.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Shop {
+    pub stock: Vec<StockEntry>,
+}
+
+impl Shop {
+    /// How many of each line are left, in stock order.
+    pub fn quantities(&self) -> Vec<u32> {
+        self.stock.iter().map(|entry| entry.quantity).collect()
+    }
+
+    /// Puts back quantities from `quantities`, line by line. Lines it has
+    /// no quantity for keep theirs.
+    pub fn restock(&mut self, quantities: &[u32]) {
+        for (entry, quantity) in self.stock.iter_mut().zip(quantities) {
+            entry.quantity = *quantity;
+        }
+    }
+
+    fn entry_for(&mut self, item: &str) -> Option<&mut StockEntry> {
+        self.stock.iter_mut().find(|entry| entry.item == item)
+    }
+}
+
+/// What shops pay for items, as a share of what they sell them for.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ShopConfig {
+    pub sell_ratio: f32,
+}
+
+impl Default for ShopConfig {
+    fn default() -> Self {
+        ShopConfig { sell_ratio: 0.5 }
+    }
+}
+
+impl ShopConfig {
+    pub fn sell_price(&self, price: u32) -> u32 {
+        (price as f32 * self.sell_ratio.max(0.)).floor() as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tab {
+    #[default]
+    Buy,
+    Sell,
+}
+
+/// The shop a player is trading with, open over a paused game.
+#[derive(Default, Resource)]
+pub struct ShopPanel {
+    /// The shop and the player at it.
+    open: Option<(Entity, Entity)>,
+    tab: Tab,
+    selected: usize,
+    /// The outcome of the last trade, shown under the rows.
+    message: String,
+}
+
+impl ShopPanel {
+    /// The tab showing, while a shop is open.
+    pub fn tab(&self) -> Option<Tab> {
+        self.open.map(|_| self.tab)
+    }
+}
+
+#[derive(Component)]
+struct ShopPanelRoot;
+
+/// A row of the panel, selected on hover and traded on click.
+#[derive(Component)]
+struct ShopRow(usize);
+
+pub struct ShopPlugin;
+impl Plugin for ShopPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShopConfig>()
+            .init_resource::<ShopPanel>()
+            .add_system(
+                open_shops
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (navigate_shop, draw_shop)
+                    .chain()
+                    .in_set(OnUpdate(AppState::Game)),
+            )
+            .add_system(close_shop.in_schedule(OnExit(AppState::Game)));
+    }
+}
+
+/// Opens the shop a player bumps into.
+#[allow(clippy::type_complexity)]
+fn open_shops(
+    mut bumps: EventReader<BumpEvent>,
+    players: Query<(), (With<Player>, With<Inventory>, With<Currency>)>,
+    shops: Query<(Entity, &Position, Option<&Footprint>), With<Shop>>,
+    mut panel: ResMut<ShopPanel>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    for bump in bumps.iter().filter(|b| players.contains(b.entity)) {
+        let shop = (shops.iter()).find(|(_, p, footprint)| covers(p.v, *footprint, bump.at));
+        if let Some((shop, ..)) = shop.filter(|_| panel.open.is_none()) {
+            *panel = ShopPanel {
+                open: Some((shop, bump.entity)),
+                ..default()
+            };
+            paused.0 = true;
+        }
+    }
+}
+
+fn close_shop(
+    mut commands: Commands,
+    mut panel: ResMut<ShopPanel>,
+    mut paused: ResMut<SimulationPaused>,
+    panels: Query<Entity, With<ShopPanelRoot>>,
+) {
+    if panel.open.take().is_some() {
+        paused.0 = false;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The rows of the open tab: the shop's stock to buy, or what the player
+/// carries to sell.
+fn rows(panel: &ShopPanel, shop: &Shop, inventory: &Inventory) -> Vec<String> {
+    match panel.tab {
+        Tab::Buy => (shop.stock.iter()).map(|e| e.item.clone()).collect(),
+        Tab::Sell => inventory.iter().map(|(id, _)| id.to_string()).collect(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn navigate_shop(
+    keys: Res<Input<KeyCode>>,
+    mut panel: ResMut<ShopPanel>,
+    mut paused: ResMut<SimulationPaused>,
+    clicks: Query<(&Interaction, &ShopRow), Changed<Interaction>>,
+    mut shops: Query<&mut Shop>,
+    mut players: Query<(&mut Inventory, &mut Currency)>,
+    config: Res<ShopConfig>,
+    locale: Res<Localization>,
+) {
+    let Some((shop, player)) = panel.open else { return };
+    let (Ok(mut shop), Ok((mut inventory, mut currency))) =
+        (shops.get_mut(shop), players.get_mut(player))
+    else {
+        // The shop or its customer is gone.
+        panel.open = None;
+        paused.0 = false;
+        return;
+    };
+    if keys.just_pressed(CLOSE_KEY) {
+        panel.open = None;
+        paused.0 = false;
+        return;
+    }
+
+    if keys.any_just_pressed([KeyCode::Left, KeyCode::Right]) {
+        panel.tab = match panel.tab {
+            Tab::Buy => Tab::Sell,
+            Tab::Sell => Tab::Buy,
+        };
+        panel.selected = 0;
+        panel.message.clear();
+    }
+    let rows = rows(&panel, &shop, &inventory);
+    let count = rows.len().max(1);
+    if keys.just_pressed(KeyCode::Up) {
+        panel.selected = (panel.selected + count - 1) % count;
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        panel.selected = (panel.selected + 1) % count;
+    }
+    let mut trade = keys.any_just_pressed(TRADE_KEYS);
+    for (interaction, ShopRow(row)) in clicks.iter() {
+        match interaction {
+            Interaction::Hovered if panel.selected != *row => panel.selected = *row,
+            Interaction::Clicked => {
+                panel.selected = *row;
+                trade = true;
+            }
+            _ => {}
+        }
+    }
+    if !trade {
+        return;
+    }
+
+    let Some(item) = rows.get(panel.selected) else {
+        panel.message = t!(locale, "shop.empty");
+        return;
+    };
+    let name = t!(locale, &format!("item.{}", item));
+    panel.message = match panel.tab {
+        Tab::Buy => {
+            let Some(entry) = shop.entry_for(item) else { return };
+            if entry.quantity == 0 {
+                t!(locale, "shop.sold_out", item = name)
+            } else if currency.0 < entry.price {
+                t!(
+                    locale,
+                    "shop.too_poor",
+                    item = name,
+                    price = entry.price,
+                    coins = currency.0
+                )
+            } else if !inventory.add(item, 1) {
+                t!(locale, "shop.full", item = name)
+            } else {
+                currency.0 -= entry.price;
+                entry.quantity -= 1;
+                info!("Bought a {} for {}.", item, entry.price);
+                t!(locale, "shop.bought", item = name, price = entry.price)
+            }
+        }
+        Tab::Sell => match shop.entry_for(item) {
+            None => t!(locale, "shop.not_wanted", item = name),
+            Some(entry) => {
+                let price = config.sell_price(entry.price);
+                inventory.remove(item, 1);
+                currency.0 += price;
+                entry.quantity += 1;
+                info!("Sold a {} for {}.", item, price);
+                // The last one sold leaves its row.
+                panel.selected = panel.selected.min(inventory.iter().count().max(1) - 1);
+                t!(locale, "shop.sold", item = name, price = price)
+            }
+        },
+    };
+}
+
+fn row_text(
+    panel: &ShopPanel,
+    item: &str,
+    shop: &Shop,
+    inventory: &Inventory,
+    config: &ShopConfig,
+    locale: &Localization,
+) -> String {
+    let name = t!(locale, &format!("item.{}", item));
+    let entry = shop.stock.iter().find(|e| e.item == item);
+    match (panel.tab, entry) {
+        (Tab::Buy, Some(entry)) => t!(
+            locale,
+            "shop.stock",
+            item = name,
+            quantity = entry.quantity,
+            price = entry.price
+        ),
+        (Tab::Sell, Some(entry)) => t!(
+            locale,
+            "shop.offer",
+            item = name,
+            count = inventory.count(item),
+            price = config.sell_price(entry.price)
+        ),
+        (_, None) => t!(
+            locale,
+            "shop.no_offer",
+            item = name,
+            count = inventory.count(item)
+        ),
+    }
+}
+
+/// Rebuilds the panel whenever it or what it shows changes.
+#[allow(clippy::too_many_arguments)]
+fn draw_shop(
+    mut commands: Commands,
+    panel: Res<ShopPanel>,
+    assets: Res<GraphicsAssets>,
+    shops: Query<Ref<Shop>>,
+    players: Query<(Ref<Inventory>, Ref<Currency>)>,
+    panels: Query<Entity, With<ShopPanelRoot>>,
+    config: Res<ShopConfig>,
+    locale: Res<Localization>,
+) {
+    let open = (panel.open)
+        .and_then(|(shop, player)| Some((shops.get(shop).ok()?, players.get(player).ok()?)));
+    let changed = open.as_ref().is_some_and(|(shop, (inventory, currency))| {
+        shop.is_changed() || inventory.is_changed() || currency.is_changed()
+    });
+    if !panel.is_changed() && !locale.is_changed() && !changed {
+        return;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some((shop, (inventory, currency))) = open else { return };
+
+    let style = |color| TextStyle {
+        font: assets.font.clone(),
+        font_size: PANEL_FONT_SIZE,
+        color,
+    };
+    let (buy, sell) = (t!(locale, "shop.buy"), t!(locale, "shop.sell"));
+    let tabs = match panel.tab {
+        Tab::Buy => format!("[{}]   {}", buy, sell),
+        Tab::Sell => format!("{}   [{}]", buy, sell),
+    };
+    let mut footer = t!(locale, "shop.coins", coins = currency.0) + "\n\n";
+    footer += &t!(locale, "shop.help");
+    if !panel.message.is_empty() {
+        footer += "\n\n";
+        footer += &panel.message;
+    }
+    let rows = rows(&panel, &shop, &inventory);
+
+    commands
+        .spawn((
+            ShopPanelRoot,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let title = t!(locale, "shop.title") + "\n\n" + &tabs + "\n";
+            parent.spawn(TextBundle::from_section(title, style(Color::WHITE)));
+            if rows.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    t!(locale, "shop.empty"),
+                    style(Color::WHITE),
+                ));
+            }
+            for (i, item) in rows.iter().enumerate() {
+                let text = row_text(&panel, item, &shop, &inventory, &config, &locale);
+                let (marker, color) = match i == panel.selected {
+                    true => (">", SELECTED_COLOR),
+                    false => (" ", Color::WHITE),
+                };
+                parent.spawn((
+                    ShopRow(i),
+                    Interaction::default(),
+                    TextBundle::from_section(format!("{} {}", marker, text), style(color)),
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                "\n".to_string() + &footer,
+                style(Color::WHITE),
+            ));
+        });
+}
@@ -0,0 +1,161 @@
+//! Gameplay runs on fixed ticks of `CoreSchedule::FixedUpdate`, apart from
+//! the render frame rate, so that a sequence of inputs on given ticks always
+//! ends in the same world state, whether the game draws at 30 or 240 frames
+//! a second. `--replay` checks exactly that against a recorded session.
+//!
+//! Systems that change `Position`, health, flags or timers go in a
+//! `SimulationSet` and read `FixedTime::period` rather than `Time`. Visual
+//! systems (tweens, the camera, particles) stay on `Update` and use
+//! `tick_alpha` to draw between the last two ticks.
+
+use bevy::{
+    ecs::{event::Event, schedule::ExecutorKind},
+    input::InputSystem,
+    prelude::*,
+};
+
+use crate::{input, AppState};
+
+/// Simulation ticks per second unless a `SimulationPlugin` says otherwise.
+pub const DEFAULT_TICK_RATE: f32 = 20.;
+
+/// The stages of a tick, run in order.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    /// Advances the clock and gathers the tick's input.
+    Input,
+    /// Players, NPCs and platforms move.
+    Act,
+    /// Steps finish and occupancy catches up with what moved.
+    Resolve,
+    /// Hazards, combat, triggers and everything else reacting to moves.
+    React,
+    /// Checks the tick's outcome and consumes its input.
+    End,
+}
+
+/// While set, ticks still run their input and end stages but nothing in
+/// the world moves, as while a menu is open.
+#[derive(Default, Resource)]
+pub struct SimulationPaused(pub bool);
+
+/// Set on ticks where the world steps back through its history instead of
+/// forward, as while rewind is held. Ticks run as if paused.
+#[derive(Default, Resource)]
+pub struct Rewinding(pub bool);
+
+fn running(paused: Res<SimulationPaused>, rewinding: Res<Rewinding>) -> bool {
+    !paused.0 && !rewinding.0
+}
+
+/// How far the render frame is between the last tick and the next, from 0
+/// to 1.
+pub fn tick_alpha(fixed: &FixedTime) -> f32 {
+    fixed.accumulated().as_secs_f32() / fixed.period.as_secs_f32()
+}
+
+pub trait SimulationApp {
+    /// Adds an event that is kept for two ticks rather than two frames, so
+    /// that systems on later ticks see it however many frames pass between.
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self;
+}
+
+impl SimulationApp for App {
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self {
+        if !self.world.contains_resource::<Events<T>>() {
+            self.init_resource::<Events<T>>().add_system(
+                Events::<T>::update_system
+                    .in_set(SimulationSet::Input)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+        }
+        self
+    }
+}
+
+pub struct SimulationPlugin {
+    /// Ticks per second.
+    pub tick_rate: f32,
+}
+
+impl Default for SimulationPlugin {
+    fn default() -> Self {
+        SimulationPlugin {
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        use SimulationSet::*;
+
+        app.insert_resource(FixedTime::new_from_secs(1. / self.tick_rate))
+            .init_resource::<SimulationPaused>()
+            .init_resource::<Rewinding>()
+            .init_resource::<input::PendingPresses>()
+            .init_resource::<input::InputSuppressed>()
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                // Systems whose order is left open still run in the same
+                // order on every tick.
+                schedule
+                    .set_executor_kind(ExecutorKind::SingleThreaded)
+                    .configure_sets((Input, Act, Resolve, React, End).chain())
+                    .configure_set(Act.run_if(in_state(AppState::Game)).run_if(running))
+                    .configure_set(Resolve.run_if(in_state(AppState::Game)).run_if(running))
+                    .configure_set(React.run_if(in_state(AppState::Game)).run_if(running));
+            })
+            .add_system(
+                input::latch_presses
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(
+                input::clear_presses
+                    .in_set(End)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{headless_game, run_ticks, walk, Hashes};
+
+    const TICKS: u64 = 100;
+
+    /// The world hash after each of `TICKS` ticks, drawing `fps` frames a
+    /// second.
+    fn play(fps: f64) -> Vec<u64> {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / fps));
+        app.insert_resource(walk());
+        let frames = run_ticks(&mut app, TICKS);
+        // As many frames as the ticks last, give or take the ones a tick
+        // falls across.
+        let expected = TICKS as f64 * fps / DEFAULT_TICK_RATE as f64;
+        assert!(
+            (frames as f64 - expected).abs() <= 2.,
+            "{} frames for {} ticks at {} fps",
+            frames,
+            TICKS,
+            fps
+        );
+        let mut hashes = app.world.remove_resource::<Hashes>().unwrap().0;
+        hashes.truncate(TICKS as usize);
+        hashes
+    }
+
+    #[test]
+    fn scripted_ticks_end_the_same_at_any_frame_rate() {
+        let at_60 = play(60.);
+        assert_eq!(at_60.len(), TICKS as usize);
+        // The player went somewhere.
+        assert_ne!(at_60.first(), at_60.last());
+        for fps in [30., 144., 240.] {
+            assert!(play(fps) == at_60, "ticks at {} fps went differently", fps);
+        }
+    }
+}
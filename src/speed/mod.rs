@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{editor::EditorState, turns::TurnBased, AppState, GraphicsAssets};
+
+/// Speeds Ctrl+1 to Ctrl+4 set.
+pub const SPEED_PRESETS: [f32; 4] = [0.25, 1., 2., 4.];
+/// The slowest and fastest the simulation runs at, however it is set.
+pub const MIN_SPEED: f32 = 0.1;
+pub const MAX_SPEED: f32 = 8.;
+const PRESET_KEYS: [KeyCode; 4] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+const INDICATOR_FONT_SIZE: f32 = 16.;
+/// Pixels from the top right corner of the window.
+const INDICATOR_MARGIN: f32 = 8.;
+
+/// How fast simulation ticks come compared to real time, for watching AI
+/// or replays in slow motion or fast forward. Ticks keep their length, so
+/// each plays out as it would at any speed; only more or fewer of them
+/// run each frame. Frames, input and sound go on in real time, and
+/// turn-based mode runs at normal speed whatever this is.
+#[derive(Resource, Debug)]
+pub struct SimulationSpeed {
+    factor: f32,
+    /// Time taken back from ticks that they had not yet been given, to be
+    /// made up from later frames.
+    owed: Duration,
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        SimulationSpeed {
+            factor: 1.,
+            owed: Duration::ZERO,
+        }
+    }
+}
+
+impl SimulationSpeed {
+    pub fn new(factor: f32) -> Self {
+        let mut speed = SimulationSpeed::default();
+        speed.set(factor);
+        speed
+    }
+
+    pub fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    /// Runs the simulation `factor` times as fast as real time, kept
+    /// within `MIN_SPEED` and `MAX_SPEED`.
+    pub fn set(&mut self, factor: f32) {
+        let clamped = match factor.is_finite() {
+            true => factor.clamp(MIN_SPEED, MAX_SPEED),
+            false => 1.,
+        };
+        if clamped != factor {
+            warn!("Speed {} is out of range, so {}.", factor, clamped);
+        }
+        self.factor = clamped;
+    }
+}
+
+#[derive(Component)]
+struct SpeedIndicator;
+
+pub struct SpeedPlugin;
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationSpeed>()
+            // Before the fixed schedule runs this frame's ticks.
+            .add_system(scale_ticks.in_base_set(CoreSet::PreUpdate))
+            .add_system(spawn_indicator.in_schedule(OnEnter(AppState::Game)))
+            .add_systems((choose_speed, draw_indicator).chain());
+    }
+}
+
+/// Whether Ctrl is down, for the number keys to set the speed rather than
+/// the zoom.
+pub fn speed_modifier(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::LControl, KeyCode::RControl])
+}
+
+fn choose_speed(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    // In the editor Ctrl and a number saves a camera bookmark instead.
+    if !speed_modifier(&keys) || editor.active {
+        return;
+    }
+    if let Some(i) = PRESET_KEYS.iter().position(|k| keys.just_pressed(*k)) {
+        speed.set(SPEED_PRESETS[i]);
+        info!("Simulation speed x{}.", speed.factor);
+    }
+}
+
+/// Swaps the frame's real time in the tick accumulator, which the fixed
+/// schedule adds next, for the scaled time. Where the accumulator holds
+/// too little to take it back from, the rest is owed until later frames.
+fn scale_ticks(
+    time: Res<Time>,
+    turn_based: Res<TurnBased>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut fixed: ResMut<FixedTime>,
+) {
+    let factor = match turn_based.0 {
+        true => 1.,
+        false => speed.factor,
+    };
+    if factor == 1. && speed.owed.is_zero() {
+        return;
+    }
+    let delta = time.delta();
+    let have = fixed.accumulated() + delta.mul_f32(factor);
+    let take = delta + speed.owed;
+    let period = fixed.period;
+    *fixed = FixedTime::new(period);
+    match have.checked_sub(take) {
+        Some(rest) => {
+            fixed.tick(rest);
+            speed.owed = Duration::ZERO;
+        }
+        None => speed.owed = take - have,
+    }
+}
+
+fn spawn_indicator(
+    mut commands: Commands,
+    assets: Res<GraphicsAssets>,
+    indicators: Query<(), With<SpeedIndicator>>,
+) {
+    if !indicators.is_empty() {
+        return;
+    }
+    commands.spawn((
+        SpeedIndicator,
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: INDICATOR_FONT_SIZE,
+                    color: Color::YELLOW,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(INDICATOR_MARGIN),
+                    right: Val::Px(INDICATOR_MARGIN),
+                    ..default()
+                },
+                ..default()
+            })
+        },
+    ));
+}
+
+/// Shows the speed in a corner whenever it is not normal.
+fn draw_indicator(
+    speed: Res<SimulationSpeed>,
+    turn_based: Res<TurnBased>,
+    mut indicators: Query<(&mut Text, &mut Visibility), With<SpeedIndicator>>,
+) {
+    let value = format!("x{}", speed.factor);
+    let shown = match speed.factor != 1. && !turn_based.0 {
+        true => Visibility::Inherited,
+        false => Visibility::Hidden,
+    };
+    for (mut text, mut visibility) in indicators.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+        if *visibility != shown {
+            *visibility = shown;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{headless_game, run_ticks, walk, Hashes};
+
+    const TICKS: u64 = 100;
+
+    /// The world hash after each of `TICKS` ticks at `factor` times real
+    /// time, drawing 60 frames a second, and how many frames they took.
+    fn play(factor: f32) -> (Vec<u64>, u32) {
+        let mut app = headless_game("data.json", Duration::from_secs_f64(1. / 60.));
+        app.insert_resource(walk())
+            .insert_resource(SimulationSpeed::new(factor));
+        let frames = run_ticks(&mut app, TICKS);
+        let mut hashes = app.world.remove_resource::<Hashes>().unwrap().0;
+        hashes.truncate(TICKS as usize);
+        (hashes, frames)
+    }
+
+    #[test]
+    fn ticks_go_the_same_at_any_speed() {
+        let (normal, frames) = play(1.);
+        let (fast, fast_frames) = play(2.);
+        assert!(fast == normal, "ticks at x2 went differently");
+        // Half as many frames, give or take the ones a tick falls across.
+        assert!(
+            fast_frames.abs_diff(frames / 2) <= 2,
+            "{} frames at x2, {} at x1",
+            fast_frames,
+            frames
+        );
+        for factor in [0.25, 4.] {
+            let (hashes, _) = play(factor);
+            assert!(hashes == normal, "ticks at x{} went differently", factor);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{self, DamageEvent, Health},
+    locale::Localization,
+    loot::ItemCollectedEvent,
+    player::{Player, PlayerStepCompleted},
+    progression::Campaign,
+    saves::{format_timestamp, SAVE_DIR},
+    simulation::{SimulationPaused, SimulationSet},
+    storage::{platform_store, unix_time, KeyValueStore, StorageError},
+    t,
+    vectors::Vector3Int,
+    AppState, CurrentBoard, GraphicsAssets, Position,
+};
+
+/// Where finished runs are kept, next to the save slots.
+pub const HISTORY_KEY: &str = "history.ron";
+/// Runs kept in the history; the oldest go first.
+pub const HISTORY_LIMIT: usize = 50;
+/// Runs listed on the statistics page.
+const RUNS_SHOWN: usize = 10;
+
+const PAGE_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+const PAGE_FONT_SIZE: f32 = 20.;
+
+/// Time and exploration on one map of a run.
+///
+/// Fields default when missing, so history written before a field was
+/// added still reads.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MapRecord {
+    /// The map's asset path.
+    pub map: String,
+    /// Seconds played on the map, over every attempt.
+    pub time: f32,
+    /// Distinct cells players stood on.
+    pub explored: u32,
+    /// Cells the board spans, the largest it has been.
+    pub area: u32,
+}
+
+/// Everything counted over a run of the campaign.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RunRecord {
+    /// Seconds since the Unix epoch when the run was won, 0 until then.
+    pub finished: u64,
+    pub steps: u32,
+    /// Damage dealt to anything but players, and taken by players.
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    /// Times a player's health ran out.
+    pub deaths: u32,
+    /// Items picked up, by id.
+    pub items: BTreeMap<String, u32>,
+    /// Maps in the order first played.
+    pub maps: Vec<MapRecord>,
+}
+
+impl RunRecord {
+    /// Seconds played over all maps.
+    pub fn time(&self) -> f32 {
+        self.maps.iter().map(|m| m.time).sum()
+    }
+
+    /// The share of all maps' cells explored, as a percentage.
+    pub fn explored(&self) -> f32 {
+        let area: u32 = self.maps.iter().map(|m| m.area).sum();
+        let explored: u32 = self.maps.iter().map(|m| m.explored.min(m.area)).sum();
+        explored as f32 / area.max(1) as f32 * 100.
+    }
+
+    fn map_mut(&mut self, map: &str) -> &mut MapRecord {
+        match self.maps.iter().position(|m| m.map == map) {
+            Some(i) => &mut self.maps[i],
+            None => {
+                self.maps.push(MapRecord {
+                    map: map.to_string(),
+                    ..default()
+                });
+                self.maps.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl RunRecord {
+    /// The record as shown on winning, in `locale`'s language.
+    pub fn summary(&self, locale: &Localization) -> String {
+        let mut lines = vec![t!(locale, "run.time", time = format!("{:.1}", self.time()))];
+        for map in self.maps.iter() {
+            lines.push(format!("  {}: {:.1}s", map.map, map.time));
+        }
+        lines.push(t!(locale, "run.steps", steps = self.steps));
+        let explored = format!("{:.0}", self.explored());
+        lines.push(t!(locale, "run.explored", percent = explored));
+        lines.push(t!(
+            locale,
+            "run.damage",
+            dealt = self.damage_dealt,
+            taken = self.damage_taken
+        ));
+        lines.push(t!(locale, "run.deaths", deaths = self.deaths));
+        let items: Vec<String> = (self.items.iter())
+            .map(|(id, count)| format!("{} x{}", t!(locale, &format!("item.{}", id)), count))
+            .collect();
+        lines.push(if items.is_empty() {
+            t!(locale, "run.items.none")
+        } else {
+            t!(locale, "run.items", items = items.join(", "))
+        });
+        lines.join("\n")
+    }
+}
+
+/// The run under way. Saved with each slot, and added to the history once
+/// the campaign is won.
+#[derive(Default, Resource)]
+pub struct CurrentRun {
+    pub record: RunRecord,
+    /// The map `explored` is about.
+    map: String,
+    /// Cells players have stood on in the current map, ignoring layers.
+    explored: HashSet<Vector3Int>,
+}
+
+impl CurrentRun {
+    /// The explored cells of the current map, as saved.
+    pub fn explored_cells(&self) -> Vec<[i32; 2]> {
+        self.explored.iter().map(|v| [v.x, v.y]).collect()
+    }
+
+    /// Carries on from a saved run, on the saved map.
+    pub fn restore(&mut self, record: RunRecord, map: &str, explored: &[[i32; 2]]) {
+        self.record = record;
+        self.map = map.to_string();
+        self.explored = (explored.iter())
+            .map(|[x, y]| Vector3Int::new(*x, *y, 0))
+            .collect();
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Storage(StorageError),
+    Format(ron::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Storage(e) => write!(f, "{}", e),
+            HistoryError::Format(e) => write!(f, "could not write history: {}", e),
+            HistoryError::Parse(e) => write!(f, "damaged history: {}", e),
+        }
+    }
+}
+
+/// The finished runs, oldest first. Entries that no longer read as a
+/// `RunRecord` are skipped rather than losing the rest.
+pub fn read_history() -> Result<Vec<RunRecord>, HistoryError> {
+    let text = (platform_store(SAVE_DIR).get(HISTORY_KEY)).map_err(HistoryError::Storage)?;
+    let Some(text) = text else { return Ok(Vec::new()) };
+    let entries: Vec<ron::Value> = ron::from_str(&text).map_err(HistoryError::Parse)?;
+    let total = entries.len();
+    let runs: Vec<RunRecord> = (entries.into_iter())
+        .filter_map(|entry| entry.into_rust().ok())
+        .collect();
+    if runs.len() < total {
+        warn!(
+            "Skipped {} unreadable runs in the history.",
+            total - runs.len()
+        );
+    }
+    Ok(runs)
+}
+
+/// Adds `run` to the history, dropping the oldest runs past
+/// `HISTORY_LIMIT`. A history too damaged to read starts over.
+pub fn append_history(run: RunRecord) -> Result<(), HistoryError> {
+    let mut runs = match read_history() {
+        Ok(runs) => runs,
+        Err(HistoryError::Parse(e)) => {
+            warn!("Starting a new history over a damaged one: {}", e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    runs.push(run);
+    let excess = runs.len().saturating_sub(HISTORY_LIMIT);
+    runs.drain(..excess);
+    let text = ron::ser::to_string_pretty(&runs, ron::ser::PrettyConfig::default())
+        .map_err(HistoryError::Format)?;
+    (platform_store(SAVE_DIR).set(HISTORY_KEY, &text)).map_err(HistoryError::Storage)
+}
+
+/// The statistics page, open over a paused game.
+#[derive(Default, Resource)]
+struct StatsPage {
+    open: bool,
+    text: String,
+}
+
+#[derive(Component)]
+struct StatsPageText;
+
+pub struct StatsPlugin;
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentRun>()
+            .init_resource::<StatsPage>()
+            .add_system(
+                count_run
+                    .after(combat::despawn_dead)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(finish_run.in_schedule(OnEnter(AppState::Victory)))
+            .add_systems((toggle_page, draw_page).chain());
+    }
+}
+
+/// Adds the tick's steps, damage, pickups and deaths to the run, and the
+/// tick's time and the cells players stand on to the current map.
+#[allow(clippy::too_many_arguments)]
+fn count_run(
+    mut steps: EventReader<PlayerStepCompleted>,
+    mut damage: EventReader<DamageEvent>,
+    mut collected: EventReader<ItemCollectedEvent>,
+    players: Query<(Entity, &Position, Option<&Health>), With<Player>>,
+    campaign: Res<Campaign>,
+    current: Res<CurrentBoard>,
+    fixed: Res<FixedTime>,
+    mut run: ResMut<CurrentRun>,
+    mut down: Local<HashSet<Entity>>,
+) {
+    let run = &mut *run;
+    let record = &mut run.record;
+    record.steps += steps.iter().count() as u32;
+    for event in damage.iter() {
+        if players.contains(event.entity) {
+            record.damage_taken += event.amount;
+        } else {
+            record.damage_dealt += event.amount;
+        }
+    }
+    for event in collected.iter() {
+        *record.items.entry(event.id.clone()).or_default() += event.count;
+    }
+    for (entity, _, health) in players.iter() {
+        let dead = health.is_some_and(|h| h.current == 0);
+        if !dead {
+            down.remove(&entity);
+        } else if down.insert(entity) {
+            record.deaths += 1;
+        }
+    }
+
+    let map = campaign.current_map();
+    if run.map != map {
+        run.map = map.to_string();
+        run.explored.clear();
+    }
+    for (_, position, _) in players.iter() {
+        let v = current.wrap(position.v);
+        run.explored.insert(Vector3Int::new(v.x, v.y, 0));
+    }
+    let rect = current.bounds.rect;
+    let record = run.record.map_mut(map);
+    record.time += fixed.period.as_secs_f32();
+    record.explored = run.explored.len() as u32;
+    record.area = record.area.max((rect.width * rect.height).max(0) as u32);
+}
+
+fn finish_run(mut run: ResMut<CurrentRun>) {
+    run.record.finished = unix_time();
+    match append_history(run.record.clone()) {
+        Ok(()) => info!("Run added to {}/{}.", SAVE_DIR, HISTORY_KEY),
+        Err(e) => warn!("Could not add the run to the history: {}", e),
+    }
+}
+
+/// Past runs, fastest first.
+fn history_text(locale: &Localization) -> String {
+    let mut text = t!(locale, "stats.title") + "\n\n";
+    let mut runs = match read_history() {
+        Ok(runs) => runs,
+        Err(e) => return text + &t!(locale, "stats.unreadable", error = e),
+    };
+    if runs.is_empty() {
+        text += &t!(locale, "stats.none");
+        text += "\n";
+    }
+    runs.sort_by(|a, b| a.time().total_cmp(&b.time()));
+    for (i, run) in runs.iter().take(RUNS_SHOWN).enumerate() {
+        text += &t!(
+            locale,
+            "stats.run",
+            rank = i + 1,
+            time = format!("{:.1}", run.time()),
+            steps = run.steps,
+            explored = format!("{:.0}", run.explored()),
+            deaths = run.deaths,
+            finished = format_timestamp(run.finished),
+        );
+        text += "\n";
+    }
+    text + "\n" + &t!(locale, "stats.close")
+}
+
+fn toggle_page(
+    keys: Res<Input<KeyCode>>,
+    locale: Res<Localization>,
+    mut page: ResMut<StatsPage>,
+    mut paused: ResMut<SimulationPaused>,
+) {
+    let close = page.open && keys.just_pressed(KeyCode::Escape);
+    // Shift+F7 is the event log's.
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let toggle = keys.just_pressed(KeyCode::F7) && !shift;
+    if !toggle && !close {
+        // Written again in the new language.
+        if page.open && locale.is_changed() {
+            page.text = history_text(&locale);
+        }
+        return;
+    }
+    page.open = !page.open;
+    if page.open {
+        page.text = history_text(&locale);
+    }
+    paused.0 = page.open;
+}
+
+fn draw_page(
+    mut commands: Commands,
+    page: Res<StatsPage>,
+    assets: Res<GraphicsAssets>,
+    panels: Query<Entity, (With<StatsPageText>, Without<Parent>)>,
+) {
+    if !page.is_changed() {
+        return;
+    }
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !page.open {
+        return;
+    }
+
+    commands
+        .spawn((
+            StatsPageText,
+            Interaction::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: PAGE_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                page.text.clone(),
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: PAGE_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{DamageEvent, Health},
+    elements::{Element, Resistances},
+    npc::Chaser,
+    player::MovementState,
+    simulation::{SimulationApp, SimulationSet},
+    terrain::Capabilities,
+};
+
+/// Seconds between status ticks, when durations run down and poison bites.
+pub const STATUS_INTERVAL: f32 = 1.;
+
+/// A number of status ticks.
+pub type Ticks = u32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusKind {
+    /// Deals `magnitude` damage each tick.
+    Poison,
+    /// Multiplies the time between steps by `magnitude`, and divides the
+    /// points a turn starts with by it.
+    Slow,
+    /// Divides the time between steps by `magnitude`, and multiplies the
+    /// points a turn starts with by it.
+    Haste,
+    /// No moving at all while it lasts.
+    Rooted,
+    /// Flies while it lasts, over water and pits alike.
+    Levitate,
+    /// Resists `magnitude` percent of every element's damage.
+    Warded,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    #[serde(default = "default_magnitude")]
+    pub magnitude: f32,
+    pub remaining: Ticks,
+}
+
+fn default_magnitude() -> f32 {
+    1.
+}
+
+/// How a new effect combines with one of the same kind already applied.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StackRule {
+    /// Keep the stronger magnitude and the longer duration.
+    Refresh,
+    /// Add the magnitudes, and keep the longer duration.
+    AddMagnitude,
+}
+
+/// Stacking rules by kind. Maps can override them.
+#[derive(Resource)]
+pub struct StatusRules(pub HashMap<StatusKind, StackRule>);
+
+impl Default for StatusRules {
+    fn default() -> Self {
+        StatusRules(HashMap::from([
+            (StatusKind::Poison, StackRule::AddMagnitude),
+            (StatusKind::Slow, StackRule::Refresh),
+            (StatusKind::Haste, StackRule::Refresh),
+            (StatusKind::Rooted, StackRule::Refresh),
+            (StatusKind::Levitate, StackRule::Refresh),
+            (StatusKind::Warded, StackRule::Refresh),
+        ]))
+    }
+}
+
+impl StatusRules {
+    pub fn rule(&self, kind: StatusKind) -> StackRule {
+        self.0.get(&kind).copied().unwrap_or(StackRule::Refresh)
+    }
+}
+
+/// The effects on an entity, at most one of each kind.
+#[derive(Component, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    pub fn get(&self, kind: StatusKind) -> Option<&StatusEffect> {
+        self.0.iter().find(|e| e.kind == kind)
+    }
+
+    /// Adds `effect`, combining it with an effect of its kind by `rule`.
+    pub fn apply(&mut self, effect: StatusEffect, rule: StackRule) {
+        let Some(existing) = self.0.iter_mut().find(|e| e.kind == effect.kind) else {
+            self.0.push(effect);
+            return;
+        };
+        existing.remaining = existing.remaining.max(effect.remaining);
+        existing.magnitude = match rule {
+            StackRule::Refresh => existing.magnitude.max(effect.magnitude),
+            StackRule::AddMagnitude => existing.magnitude + effect.magnitude,
+        };
+    }
+
+    /// Multiplier on the time between steps.
+    pub fn interval_scale(&self) -> f32 {
+        let slow = self.get(StatusKind::Slow).map_or(1., |e| e.magnitude);
+        let haste = self.get(StatusKind::Haste).map_or(1., |e| e.magnitude);
+        slow / haste.max(f32::EPSILON)
+    }
+
+    pub fn is_rooted(&self) -> bool {
+        self.get(StatusKind::Rooted).is_some()
+    }
+
+    /// What the effects let their bearer do.
+    pub fn capabilities(&self) -> Capabilities {
+        match self.get(StatusKind::Levitate) {
+            Some(_) => Capabilities::FLY,
+            None => Capabilities::NONE,
+        }
+    }
+
+    /// What the effects add to their bearer's resistances.
+    pub fn resistances(&self) -> Resistances {
+        self.get(StatusKind::Warded)
+            .map_or_else(Resistances::default, |e| Resistances::all(e.magnitude))
+    }
+}
+
+/// Puts `effect` on `entity`, if it has health or moves.
+pub struct ApplyStatusEvent {
+    pub entity: Entity,
+    pub effect: StatusEffect,
+}
+
+/// Sent when an effect runs out.
+pub struct StatusExpiredEvent {
+    pub entity: Entity,
+    pub kind: StatusKind,
+}
+
+/// Counts down to the next status tick.
+#[derive(Resource)]
+struct StatusClock(Timer);
+
+impl Default for StatusClock {
+    fn default() -> Self {
+        StatusClock(Timer::from_seconds(STATUS_INTERVAL, TimerMode::Repeating))
+    }
+}
+
+pub struct StatusPlugin;
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatusRules>()
+            .init_resource::<StatusClock>()
+            .add_simulation_event::<ApplyStatusEvent>()
+            .add_simulation_event::<StatusExpiredEvent>()
+            .add_systems(
+                (apply_statuses, tick_statuses, log_expired)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_statuses(
+    mut commands: Commands,
+    mut events: EventReader<ApplyStatusEvent>,
+    rules: Res<StatusRules>,
+    mut targets: Query<
+        Option<&mut StatusEffects>,
+        Or<(With<Health>, With<MovementState>, With<Chaser>)>,
+    >,
+) {
+    // Effects on entities without any yet, gathered until the commands run.
+    let mut added: HashMap<Entity, StatusEffects> = HashMap::new();
+    for event in events.iter() {
+        let Ok(effects) = targets.get_mut(event.entity) else { continue };
+        let rule = rules.rule(event.effect.kind);
+        match effects {
+            Some(mut effects) => effects.apply(event.effect, rule),
+            None => added
+                .entry(event.entity)
+                .or_default()
+                .apply(event.effect, rule),
+        }
+    }
+    for (entity, effects) in added {
+        commands.entity(entity).insert(effects);
+    }
+}
+
+/// Runs effects down on each status tick, poisoning as they go.
+fn tick_statuses(
+    fixed: Res<FixedTime>,
+    mut clock: ResMut<StatusClock>,
+    mut query: Query<(Entity, &mut StatusEffects)>,
+    mut damage: EventWriter<DamageEvent>,
+    mut expired: EventWriter<StatusExpiredEvent>,
+) {
+    if !clock.0.tick(fixed.period).just_finished() {
+        return;
+    }
+
+    for (entity, mut effects) in query.iter_mut() {
+        for effect in effects.0.iter_mut() {
+            if effect.kind == StatusKind::Poison {
+                damage.send(DamageEvent {
+                    entity,
+                    amount: effect.magnitude.round() as u32,
+                    element: Element::Poison,
+                });
+            }
+            effect.remaining = effect.remaining.saturating_sub(1);
+        }
+        effects.0.retain(|effect| {
+            if effect.remaining == 0 {
+                expired.send(StatusExpiredEvent {
+                    entity,
+                    kind: effect.kind,
+                });
+            }
+            effect.remaining > 0
+        });
+    }
+}
+
+fn log_expired(mut expired: EventReader<StatusExpiredEvent>) {
+    for event in expired.iter() {
+        debug!("{:?} wore off {:?}.", event.kind, event.entity);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+/// Small text records kept between sessions, such as save slots. Files on
+/// the desktop and `localStorage` in the browser.
+pub trait KeyValueStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StorageError>;
+    fn set(&self, key: &str, value: &str) -> Result<(), StorageError>;
+    fn remove(&self, key: &str) -> Result<(), StorageError>;
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    /// The browser refused, or has no storage to offer.
+    Web(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "{}", e),
+            StorageError::Web(reason) => write!(f, "browser storage failed: {}", reason),
+        }
+    }
+}
+
+/// One file per key in the directory `root`.
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileStore { root: root.into() }
+    }
+}
+
+impl KeyValueStore for FileStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
+        match fs::read_to_string(self.root.join(key)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(StorageError::Io(e)),
+        }
+    }
+
+    /// Writes a temporary file first and renames it over the key's, so a
+    /// crash mid-write leaves the previous value intact.
+    fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        let path = self.root.join(key);
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::create_dir_all(&self.root).map_err(StorageError::Io)?;
+        fs::write(&temp, value).map_err(StorageError::Io)?;
+        fs::rename(&temp, &path).map_err(StorageError::Io)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(StorageError::Io(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The page's `localStorage`, with every key under `prefix` so that
+/// namespaces do not collide.
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStore {
+    prefix: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStore {
+    pub fn new(namespace: &str) -> Self {
+        LocalStore {
+            prefix: format!("{}/", namespace),
+        }
+    }
+
+    fn storage() -> Result<web_sys::Storage, StorageError> {
+        let window = web_sys::window().ok_or_else(|| StorageError::Web("no window".into()))?;
+        match window.local_storage() {
+            Ok(Some(storage)) => Ok(storage),
+            Ok(None) => Err(StorageError::Web("localStorage is disabled".into())),
+            Err(e) => Err(StorageError::Web(format!("{:?}", e))),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl KeyValueStore for LocalStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
+        (Self::storage()?.get_item(&(self.prefix.clone() + key)))
+            .map_err(|e| StorageError::Web(format!("{:?}", e)))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        // A single `setItem` either replaces the value or fails, so there is
+        // no half-written state to guard against.
+        (Self::storage()?.set_item(&(self.prefix.clone() + key), value))
+            .map_err(|e| StorageError::Web(format!("{:?}", e)))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), StorageError> {
+        (Self::storage()?.remove_item(&(self.prefix.clone() + key)))
+            .map_err(|e| StorageError::Web(format!("{:?}", e)))
+    }
+}
+
+/// The store for `namespace` on this platform: a directory of that name on
+/// the desktop, or keys prefixed with it in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn platform_store(namespace: &str) -> impl KeyValueStore {
+    FileStore::new(namespace)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn platform_store(namespace: &str) -> impl KeyValueStore {
+    LocalStore::new(namespace)
+}
+
+/// Seconds since the Unix epoch. `SystemTime` panics in the browser, so ask
+/// the page for the date there.
+pub fn unix_time() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.) as u64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        (std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH))
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
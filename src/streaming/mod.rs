@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::{
+    assets::AssetGroups,
+    collision::CollisionMap,
+    grid_to_position, load_scene,
+    materials::TileMetadataRegistry,
+    objects,
+    player::Player,
+    position_to_grid,
+    prefabs::PrefabRegistry,
+    projection::GridProjection,
+    scene_tiles, spawn_collision, spawn_tile_batch,
+    vectors::{GridKind, GridRect, Vector3Int},
+    AppState, CurrentBoard, GraphicsAssets, LoadMapEvent, MapValidationReport, Position, Scene,
+    SceneHandle, Wrap,
+};
+
+/// Campaign entries ending in this name a chunked world rather than a
+/// scene.
+pub const WORLD_EXTENSION: &str = ".world.json";
+
+fn default_load_radius() -> i32 {
+    1
+}
+
+fn default_unload_radius() -> i32 {
+    2
+}
+
+/// A map too big for one scene, split into square chunks on a coarse grid.
+/// Only the chunks near players are on the board at a time.
+#[derive(serde::Deserialize, bevy::reflect::TypeUuid, Clone, Debug)]
+#[uuid = "b7d41c2e-6a38-4f0d-8e5b-3c9f2a7d1e64"]
+pub struct WorldManifest {
+    /// Cells along each side of a chunk, which is the `width` of its scene.
+    pub chunk_size: usize,
+    /// Each chunk's scene by its column and row on the chunk grid, as
+    /// `"col,row"`. Like scene rows, chunk rows count down the board.
+    ///
+    /// Players start in chunk `"0,0"`, which loads as a map of its own
+    /// would. Its tilesets, sounds, terrain and other settings serve the
+    /// whole world; other chunks only bring tiles and objects.
+    pub chunks: HashMap<String, String>,
+    /// Chunks loaded around each player's, counted in chunks.
+    #[serde(default = "default_load_radius")]
+    pub load_radius: i32,
+    /// Chunks further than this from every player's are unloaded. Kept
+    /// above `load_radius`, so walking back and forth over a seam does not
+    /// load and unload the same chunks.
+    #[serde(default = "default_unload_radius")]
+    pub unload_radius: i32,
+}
+
+impl WorldManifest {
+    fn chunk_path(&self, key: IVec2) -> Option<&str> {
+        self.chunks
+            .get(&format!("{},{}", key.x, key.y))
+            .map(|p| p.as_str())
+    }
+
+    /// The scene of the chunk players start in.
+    pub fn start(&self) -> Option<&str> {
+        self.chunk_path(IVec2::ZERO)
+    }
+}
+
+enum Chunk {
+    Loading(Handle<Scene>),
+    Loaded,
+    /// Not retried until it has been unloaded.
+    Failed,
+}
+
+/// The world being played, if the current map is one, and the state of
+/// each chunk that is not unloaded.
+#[derive(Default, Resource)]
+pub struct ChunkStreamer {
+    world: Option<WorldManifest>,
+    chunks: HashMap<IVec2, Chunk>,
+}
+
+impl ChunkStreamer {
+    /// Starts streaming `world`, once its start chunk has loaded as the map.
+    pub fn begin(&mut self, world: WorldManifest) {
+        self.world = Some(world);
+        self.chunks.clear();
+    }
+}
+
+pub struct StreamingPlugin;
+impl Plugin for StreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkStreamer>()
+            .add_system(stop_streaming)
+            .add_system(
+                start_streaming
+                    .after(load_scene)
+                    .in_schedule(OnEnter(AppState::Game)),
+            )
+            .add_system(stream_chunks.in_set(OnUpdate(AppState::Game)));
+    }
+}
+
+/// The chunk holding `v`.
+fn chunk_of(v: Vector3Int, grid: GridKind, size: i32) -> IVec2 {
+    let (col, row) = position_to_grid(grid, v);
+    IVec2::new(col.div_euclid(size), row.div_euclid(size))
+}
+
+/// The board cells chunk `key` spans, worked out as `rebuild_board` does for
+/// a whole scene.
+fn chunk_rect(key: IVec2, size: i32) -> GridRect {
+    let origin = key * size;
+    GridRect::from_corners(
+        grid_to_position(GridKind::Square, origin.x, origin.y + size - 1, 0),
+        grid_to_position(GridKind::Square, origin.x + size - 1, origin.y, 0),
+    )
+}
+
+/// Forgets the world when another map is loaded over it.
+fn stop_streaming(mut events: EventReader<LoadMapEvent>, mut streamer: ResMut<ChunkStreamer>) {
+    if events.iter().last().is_some() {
+        *streamer = ChunkStreamer::default();
+    }
+}
+
+/// Counts the start chunk, spawned by `load_scene`, as loaded.
+fn start_streaming(
+    mut streamer: ResMut<ChunkStreamer>,
+    mut current: ResMut<CurrentBoard>,
+    mut scene: ResMut<SceneHandle>,
+    mut groups: ResMut<AssetGroups>,
+) {
+    let Some(world) = streamer.world.as_ref() else { return };
+    // Nothing keeps the start chunk loaded, so that like any other chunk
+    // it is freed once unloaded.
+    let id = scene.0.id();
+    for group in groups.loaded.iter_mut() {
+        group.handles.retain(|h| h.id() != id);
+    }
+    scene.0 = Handle::default();
+
+    let size = world.chunk_size.max(1) as i32;
+    if current.bounds.wrap.any() {
+        warn!("Wrap-around is not supported in chunked worlds.");
+        current.bounds.wrap = Wrap::default();
+    }
+    current.bounds.rect = chunk_rect(IVec2::ZERO, size);
+    streamer.chunks.entry(IVec2::ZERO).or_insert(Chunk::Loaded);
+}
+
+/// Loads the chunks within `load_radius` of any player's chunk and spawns
+/// them once loaded, then unloads those beyond `unload_radius` of every
+/// player's. Everything on an unloaded chunk but players goes with it, and
+/// a chunk comes back afresh from its file.
+///
+/// There is no ground where no chunk is loaded, so players walking faster
+/// than chunks load wait at the seam until the next one arrives.
+#[allow(clippy::too_many_arguments)]
+fn stream_chunks(
+    mut commands: Commands,
+    mut streamer: ResMut<ChunkStreamer>,
+    players: Query<&Position, With<Player>>,
+    board: Query<(Entity, &Position), Without<Player>>,
+    server: Res<AssetServer>,
+    scenes: Res<Assets<Scene>>,
+    mut current: ResMut<CurrentBoard>,
+    mut collision: ResMut<CollisionMap>,
+    mut report: ResMut<MapValidationReport>,
+    prefabs: Res<PrefabRegistry>,
+    grid: Res<GridKind>,
+    graphics: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+) {
+    let ChunkStreamer {
+        world: Some(world),
+        chunks,
+    } = &mut *streamer
+    else {
+        return;
+    };
+    let size = world.chunk_size.max(1) as i32;
+    let near: Vec<IVec2> = (players.iter())
+        .map(|p| chunk_of(p.v, *grid, size))
+        .collect();
+    if near.is_empty() {
+        return;
+    }
+    let distance = |key: IVec2| {
+        (near.iter())
+            .map(|c| (key - *c).abs().max_element())
+            .min()
+            .unwrap_or(i32::MAX)
+    };
+    let mut changed = false;
+
+    let radius = world.load_radius.max(0);
+    for center in near.iter() {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let key = *center + IVec2::new(x, y);
+                if chunks.contains_key(&key) {
+                    continue;
+                }
+                if let Some(path) = world.chunk_path(key) {
+                    chunks.insert(key, Chunk::Loading(server.load(path)));
+                }
+            }
+        }
+    }
+
+    for (key, chunk) in chunks.iter_mut() {
+        let Chunk::Loading(handle) = chunk else { continue };
+        match server.get_load_state(&*handle) {
+            LoadState::Loaded => {}
+            LoadState::Failed => {
+                error!("Failed to load chunk {},{}.", key.x, key.y);
+                *chunk = Chunk::Failed;
+                continue;
+            }
+            _ => continue,
+        }
+        let Some(scene) = scenes.get(handle) else { continue };
+        if scene.width != world.chunk_size {
+            warn!(
+                "Chunk {},{} is {} cells wide rather than {}.",
+                key.x, key.y, scene.width, world.chunk_size
+            );
+        }
+        let origin = *key * size;
+        let atlas =
+            |index| (graphics.tile_atlas(index, &atlases)).map(|(atlas, i)| (atlas.clone(), i));
+        let is_valid = |i| atlas(i).is_some();
+        let names = &graphics.tile_names;
+        let tiles = scene_tiles(scene, *grid, origin, is_valid, names, &mut report);
+        spawn_tile_batch(&mut commands, tiles, atlas, &metadata.0, &projection);
+        spawn_collision(scene, *grid, origin, &mut collision);
+        let objects: Vec<_> = (scene.objects.iter())
+            .map(|object| object.offset(origin.x, origin.y))
+            .collect();
+        objects::spawn_map_objects(&mut commands, &objects, *grid, &prefabs, false);
+        info!("Loaded chunk {},{}.", key.x, key.y);
+        *chunk = Chunk::Loaded;
+        changed = true;
+    }
+
+    // Further out than it loads, so chunks at the edge of the radius stay.
+    let radius = world.unload_radius.max(world.load_radius);
+    let far: Vec<IVec2> = (chunks.keys())
+        .filter(|key| distance(**key) > radius)
+        .copied()
+        .collect();
+    for key in far {
+        if let Some(Chunk::Loaded) = chunks.remove(&key) {
+            for (entity, position) in board.iter() {
+                if chunk_of(position.v, *grid, size) == key {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            current
+                .tiles
+                .retain(|v, _| chunk_of(*v, *grid, size) != key);
+            collision.retain(|v| chunk_of(v, *grid, size) != key);
+            info!("Unloaded chunk {},{}.", key.x, key.y);
+            changed = true;
+        }
+    }
+
+    if changed {
+        let mut loaded = (chunks.iter())
+            .filter(|(_, chunk)| matches!(chunk, Chunk::Loaded))
+            .map(|(key, _)| chunk_rect(*key, size));
+        if let Some(first) = loaded.next() {
+            current.bounds.rect = loaded.fold(first, |rect, r| rect.union(r));
+        }
+    }
+}
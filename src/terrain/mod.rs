@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    equipment::StatSheet,
+    get_world_position,
+    player::{MovementState, PlayerStepCompleted},
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    sfx::PlaySfxEvent,
+    simulation::SimulationSet,
+    status::StatusEffects,
+    CurrentBoard, Position, Tile,
+};
+
+const PARTICLE_COUNT: usize = 3;
+const PARTICLE_SIZE: f32 = 2.;
+const PARTICLE_LIFETIME: f32 = 0.4;
+/// Speed in pixels per second at which particles drift up and apart.
+const PARTICLE_SPEED: f32 = 12.;
+
+/// What an actor can do about terrain, as flags that combine with `|`.
+/// Terrain may require some to be entered; an actor's own come from its
+/// prefab, its equipment and its status effects.
+///
+/// In data, a list of names such as `["swim"]`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(from = "Vec<Capability>")]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const NONE: Self = Capabilities(0);
+    /// Enters water.
+    pub const SWIM: Self = Capabilities(1);
+    /// Goes over pits and whatever terrain, stopped only by what stops
+    /// flying.
+    pub const FLY: Self = Capabilities(1 << 1);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all of `other`'s flags are set.
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// A single capability by name, for data.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Capability {
+    Swim,
+    Fly,
+}
+
+impl From<Vec<Capability>> for Capabilities {
+    fn from(capabilities: Vec<Capability>) -> Self {
+        (capabilities.into_iter())
+            .map(|capability| match capability {
+                Capability::Swim => Capabilities::SWIM,
+                Capability::Fly => Capabilities::FLY,
+            })
+            .fold(Capabilities::NONE, |a, b| a | b)
+    }
+}
+
+/// Where an actor's capabilities come from: its own, as its prefab gives
+/// them, its stat sheet, and its status effects.
+pub type CapabilitySources<'a> = (
+    Option<&'a Capabilities>,
+    Option<&'a StatSheet>,
+    Option<&'a StatusEffects>,
+);
+
+/// An actor's capabilities, from all of its sources. The stat sheet counts
+/// equipment and status effects in where there is one.
+pub fn actor_capabilities((own, sheet, effects): CapabilitySources) -> Capabilities {
+    let own = own.copied().unwrap_or_default();
+    match sheet {
+        Some(sheet) => own | sheet.capabilities,
+        None => own | effects.map_or(Capabilities::NONE, |e| e.capabilities()),
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TerrainKind {
+    Grass,
+    Water,
+    Ice,
+    Road,
+    Swamp,
+}
+
+/// What happens when a player arrives on a kind of terrain.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TerrainHooks {
+    /// Sound effect key played on arrival.
+    #[serde(default)]
+    pub sfx: Option<String>,
+    /// Colour (RGB) of the particles kicked up on arrival.
+    #[serde(default)]
+    pub particles: Option<[f32; 3]>,
+    /// Multiplier on the time the next step takes, and what a step onto it
+    /// costs routes that look for the cheapest way.
+    #[serde(default = "default_move_cost")]
+    pub move_cost: f32,
+    /// Keep moving in the same direction until off this terrain or blocked.
+    #[serde(default)]
+    pub slide: bool,
+    /// What an actor needs to enter it at all, such as `["swim"]`.
+    #[serde(default)]
+    pub requires: Capabilities,
+}
+
+fn default_move_cost() -> f32 {
+    1.
+}
+
+/// Terrain data as written in a map: which atlas indices are which terrain,
+/// and any hooks to override.
+#[derive(serde::Deserialize, Clone, Default, Debug)]
+pub struct TerrainTable {
+    #[serde(default)]
+    pub tiles: HashMap<usize, TerrainKind>,
+    #[serde(default)]
+    pub kinds: HashMap<TerrainKind, TerrainHooks>,
+}
+
+#[derive(Resource)]
+pub struct TerrainRegistry {
+    tiles: HashMap<usize, TerrainKind>,
+    hooks: HashMap<TerrainKind, TerrainHooks>,
+}
+
+impl Default for TerrainRegistry {
+    fn default() -> Self {
+        let hooks = HashMap::from([
+            (
+                TerrainKind::Grass,
+                TerrainHooks {
+                    sfx: Some("grass".to_string()),
+                    particles: Some([0.4, 0.7, 0.3]),
+                    move_cost: 1.,
+                    slide: false,
+                    requires: Capabilities::NONE,
+                },
+            ),
+            (
+                TerrainKind::Water,
+                TerrainHooks {
+                    sfx: Some("splash".to_string()),
+                    particles: Some([0.5, 0.7, 1.]),
+                    move_cost: 2.,
+                    slide: false,
+                    requires: Capabilities::SWIM,
+                },
+            ),
+            (
+                TerrainKind::Ice,
+                TerrainHooks {
+                    sfx: Some("ice".to_string()),
+                    particles: None,
+                    move_cost: 1.,
+                    slide: true,
+                    requires: Capabilities::NONE,
+                },
+            ),
+            (
+                TerrainKind::Road,
+                TerrainHooks {
+                    sfx: None,
+                    particles: None,
+                    move_cost: 1.,
+                    slide: false,
+                    requires: Capabilities::NONE,
+                },
+            ),
+            (
+                TerrainKind::Swamp,
+                TerrainHooks {
+                    sfx: Some("splash".to_string()),
+                    particles: Some([0.4, 0.35, 0.2]),
+                    move_cost: 3.,
+                    slide: false,
+                    requires: Capabilities::NONE,
+                },
+            ),
+        ]);
+
+        TerrainRegistry {
+            tiles: HashMap::new(),
+            hooks,
+        }
+    }
+}
+
+impl TerrainRegistry {
+    /// Adds a map's terrain data, replacing existing entries.
+    pub fn extend(&mut self, table: TerrainTable) {
+        self.tiles.extend(table.tiles);
+        self.hooks.extend(table.kinds);
+    }
+
+    /// The terrain of the tile with atlas index `tile`.
+    pub fn kind(&self, tile: usize) -> Option<TerrainKind> {
+        self.tiles.get(&tile).copied()
+    }
+
+    /// Hooks for the terrain of the tile with atlas index `tile`.
+    pub fn hooks(&self, tile: usize) -> Option<&TerrainHooks> {
+        self.kind(tile).and_then(|kind| self.hooks.get(&kind))
+    }
+
+    /// What entering the tile with atlas index `tile` requires.
+    pub fn requires(&self, tile: usize) -> Capabilities {
+        self.hooks(tile).map_or(Capabilities::NONE, |h| h.requires)
+    }
+}
+
+#[derive(Component)]
+struct Particle {
+    timer: Timer,
+    velocity: Vec2,
+}
+
+pub struct TerrainPlugin;
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TerrainRegistry>()
+            .add_system(
+                apply_terrain
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(update_particles);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_terrain(
+    mut commands: Commands,
+    mut steps: EventReader<PlayerStepCompleted>,
+    registry: Res<TerrainRegistry>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    mut players: Query<(&mut MovementState, &Position, CapabilitySources)>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+    projection: Res<GridProjection>,
+) {
+    for step in steps.iter() {
+        let Ok((mut state, position, sources)) = players.get_mut(step.entity) else { continue };
+        // Fliers pass over terrain as if it were not there.
+        let flying = actor_capabilities(sources).contains(Capabilities::FLY);
+        let hooks = (current.floor(step.at))
+            .filter(|_| !flying)
+            .and_then(|floor| tiles.get(floor).ok())
+            .and_then(|tile| registry.hooks(tile.i));
+
+        let Some(hooks) = hooks else {
+            state.move_cost = 1.;
+            continue;
+        };
+
+        state.move_cost = hooks.move_cost;
+        if hooks.slide {
+            state.slide = Some(state.facing);
+        }
+        if let Some(key) = &hooks.sfx {
+            sfx.send(PlaySfxEvent {
+                key: key.clone(),
+                position: Some(step.at),
+            });
+        }
+        if let Some([r, g, b]) = hooks.particles {
+            let origin = get_world_position(position, &projection).truncate();
+            for i in 0..PARTICLE_COUNT {
+                let spread = i as f32 - (PARTICLE_COUNT - 1) as f32 / 2.;
+                commands.spawn((
+                    Particle {
+                        timer: Timer::from_seconds(PARTICLE_LIFETIME, TimerMode::Once),
+                        velocity: Vec2::new(spread, 1.) * PARTICLE_SPEED,
+                    },
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgb(r, g, b),
+                            custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            origin.extend(z_for(RenderLayerSlot::Overlay, 0.)),
+                        ),
+                        ..default()
+                    },
+                ));
+            }
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    for (entity, mut particle, mut transform, mut sprite) in query.iter_mut() {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.);
+        sprite.color.set_a(particle.timer.percent_left());
+    }
+}
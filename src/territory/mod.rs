@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    layer_of, layer_z,
+    simulation::{SimulationApp, SimulationSet},
+    tint::TileTint,
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TeamId(pub u8);
+
+impl TeamId {
+    pub fn color(&self, palette: &PaletteLookup) -> Color {
+        palette.color(PaletteColor::Team(self.0))
+    }
+}
+
+/// Which team owns each cell, kept apart from `CurrentBoard` so the map
+/// itself is left as loaded. Cells are keyed by their floor, so every z
+/// within a layer shares one owner.
+#[derive(Default, Clone, Resource, Serialize, Deserialize)]
+pub struct Territory {
+    #[serde(serialize_with = "save_owners", deserialize_with = "load_owners")]
+    owners: HashMap<Vector3Int, TeamId>,
+}
+
+impl Territory {
+    pub fn owner(&self, v: Vector3Int) -> Option<TeamId> {
+        self.owners.get(&floor_key(v)).copied()
+    }
+
+    /// Cells `team` owns.
+    pub fn count(&self, team: TeamId) -> usize {
+        self.owners.values().filter(|t| **t == team).count()
+    }
+
+    /// Cells `team` owns next to a cell it does not, including cells on
+    /// the edge of the board.
+    pub fn border_positions(
+        &self,
+        team: TeamId,
+        current: &CurrentBoard,
+        grid: GridKind,
+    ) -> Vec<Vector3Int> {
+        (self.owners.iter())
+            .filter(|(_, t)| **t == team)
+            .map(|(v, _)| *v)
+            .filter(|v| {
+                current
+                    .neighbours(*v, grid)
+                    .any(|n| self.owner(n) != Some(team))
+            })
+            .collect()
+    }
+
+    /// Every claimed cell and its owner.
+    pub fn claims(&self) -> impl Iterator<Item = (Vector3Int, TeamId)> + '_ {
+        self.owners.iter().map(|(v, t)| (*v, *t))
+    }
+
+    /// Forgets every claim, as when a new map is loaded.
+    pub fn clear(&mut self) {
+        self.owners.clear();
+    }
+}
+
+/// The cell `v` stands on.
+fn floor_key(v: Vector3Int) -> Vector3Int {
+    Vector3Int::new(v.x, v.y, layer_z(layer_of(v.z), 0))
+}
+
+// JSON keys must be strings, so claims are saved as a list.
+fn save_owners<S: Serializer>(
+    owners: &HashMap<Vector3Int, TeamId>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let claims: Vec<([i32; 3], TeamId)> = (owners.iter())
+        .map(|(v, t)| ([v.x, v.y, v.z], *t))
+        .collect();
+    claims.serialize(serializer)
+}
+
+fn load_owners<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Vector3Int, TeamId>, D::Error> {
+    let claims: Vec<([i32; 3], TeamId)> = Vec::deserialize(deserializer)?;
+    Ok((claims.into_iter())
+        .map(|([x, y, z], t)| (Vector3Int::new(x, y, z), t))
+        .collect())
+}
+
+/// Gives the cell at `position` to `team`, or frees it when `team` is
+/// `None`. Cells without a floor cannot be claimed.
+pub struct ClaimTileEvent {
+    pub position: Vector3Int,
+    pub team: Option<TeamId>,
+}
+
+pub struct TerritoryPlugin;
+impl Plugin for TerritoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Territory>()
+            .add_simulation_event::<ClaimTileEvent>()
+            .add_system(
+                apply_claims
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(tint_territory);
+    }
+}
+
+fn apply_claims(
+    mut events: EventReader<ClaimTileEvent>,
+    current: Res<CurrentBoard>,
+    mut territory: ResMut<Territory>,
+) {
+    for event in events.iter() {
+        let v = floor_key(current.wrap(event.position));
+        match event.team {
+            Some(team) if current.has_ground(v) => {
+                territory.owners.insert(v, team);
+            }
+            Some(_) => debug!("Cannot claim {:?}, which has no floor.", v),
+            None => {
+                territory.owners.remove(&v);
+            }
+        }
+    }
+}
+
+/// Overlays each owned floor tile with its team's colour, redone whenever
+/// the claims or the board change.
+fn tint_territory(
+    mut commands: Commands,
+    territory: Res<Territory>,
+    current: Res<CurrentBoard>,
+    palette: Res<PaletteLookup>,
+    mut tints: Query<&mut TileTint>,
+) {
+    if !territory.is_changed() && !current.is_changed() && !palette.is_changed() {
+        return;
+    }
+
+    for mut tint in tints.iter_mut().filter(|t| t.overlay.is_some()) {
+        tint.overlay = None;
+    }
+    for (v, team) in territory.claims() {
+        let Some(&entity) = current.tiles.get(&v) else { continue };
+        let overlay = Some(team.color(&palette));
+        match tints.get_mut(entity) {
+            Ok(mut tint) => tint.overlay = overlay,
+            Err(_) => {
+                commands.entity(entity).insert(TileTint {
+                    overlay,
+                    ..default()
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! The whole game run headless, for tests that play through ticks: no
+//! window, no GPU and frames of whatever length the test picks.
+
+use std::time::Duration;
+
+use bevy::{
+    log::LogPlugin,
+    prelude::*,
+    render::{pipelined_rendering::PipelinedRenderingPlugin, settings::WgpuSettings, RenderPlugin},
+    time::{TimeSystem, TimeUpdateStrategy},
+    utils::Instant,
+    winit::WinitPlugin,
+};
+
+use crate::{
+    input::{Action, RemoteInput},
+    progression::Campaign,
+    replay::{advance_clock, LogicalClock},
+    simulation::SimulationSet,
+    world_hash::WorldHash,
+    AppState, GamePlugin, LoadMapEvent,
+};
+
+/// Frames to wait for a map to load before giving up.
+pub(crate) const LOAD_FRAMES: u32 = 600;
+
+/// Presses (or releases) of one player's action, by the tick they happen
+/// on, fed in as remote input is.
+#[derive(Resource, Default)]
+pub(crate) struct Script(pub Vec<(u64, usize, Action, bool)>);
+
+/// How long each frame lasts.
+#[derive(Resource)]
+struct FrameLength(Duration);
+
+/// The world hash at the end of each tick, by tick.
+#[derive(Resource, Default)]
+pub(crate) struct Hashes(pub Vec<u64>);
+
+/// The game playing `map`, each frame `frame` long. Nothing has run yet.
+pub(crate) fn headless_game(map: &str, frame: Duration) -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                ..default()
+            })
+            .set(RenderPlugin {
+                wgpu_settings: WgpuSettings {
+                    backends: None,
+                    ..default()
+                },
+            })
+            .disable::<WinitPlugin>()
+            .disable::<PipelinedRenderingPlugin>()
+            .disable::<LogPlugin>(),
+    )
+    .insert_resource(TimeUpdateStrategy::ManualInstant(Instant::now()))
+    .insert_resource(FrameLength(frame))
+    .insert_resource(Campaign {
+        maps: vec![map.to_string()],
+        current: 0,
+    })
+    .init_resource::<RemoteInput>()
+    .init_resource::<Script>()
+    .init_resource::<Hashes>()
+    .add_plugin(GamePlugin)
+    .add_system(advance_time.in_base_set(CoreSet::First).before(TimeSystem))
+    .add_systems(
+        (
+            play_script
+                .in_set(SimulationSet::Input)
+                .after(advance_clock),
+            record_hash.in_set(SimulationSet::End),
+        )
+            .in_schedule(CoreSchedule::FixedUpdate),
+    );
+    app
+}
+
+/// Walks the first player round the start of the first map, dashing and
+/// turning mid-step.
+pub(crate) fn walk() -> Script {
+    use Action::*;
+    Script(vec![
+        (1, 0, MoveRight, true),
+        (13, 0, MoveRight, false),
+        (14, 0, MoveDown, true),
+        (20, 0, MoveLeft, true),
+        (21, 0, MoveDown, false),
+        (35, 0, Dash, true),
+        (36, 0, Dash, false),
+        (50, 0, MoveLeft, false),
+        (51, 0, MoveUp, true),
+        (52, 0, MoveUp, false),
+        (53, 0, MoveUp, true),
+        (80, 0, MoveRight, true),
+        (90, 0, MoveUp, false),
+        (95, 0, MoveRight, false),
+    ])
+}
+
+/// Runs frames until the map has loaded and `ticks` ticks have run on it,
+/// returning how many frames the ticks took. Panics if the map never
+/// loads.
+pub(crate) fn run_ticks(app: &mut App, ticks: u64) -> u32 {
+    for _ in 0..LOAD_FRAMES {
+        if app.world.resource::<State<AppState>>().0 != AppState::Loading {
+            break;
+        }
+        app.update();
+    }
+    assert_eq!(
+        app.world.resource::<State<AppState>>().0,
+        AppState::Game,
+        "the map did not load"
+    );
+    let mut frames = 0;
+    while app.world.resource::<LogicalClock>().tick < ticks {
+        app.update();
+        frames += 1;
+    }
+    frames
+}
+
+/// Loads `map` through the usual transition, as leaving by an exit does,
+/// and plays a tick on it. Panics if it never loads.
+pub(crate) fn travel(app: &mut App, map: &str) {
+    app.world.send_event(LoadMapEvent {
+        name: map.to_string(),
+    });
+    // The old map stays until the screen is covered.
+    for _ in 0..LOAD_FRAMES {
+        if app.world.resource::<State<AppState>>().0 == AppState::Loading {
+            break;
+        }
+        app.update();
+    }
+    let tick = app.world.resource::<LogicalClock>().tick;
+    run_ticks(app, tick + 1);
+}
+
+/// Moves the time each frame is given on by exactly one frame, whatever
+/// the time really is.
+fn advance_time(frame: Res<FrameLength>, mut strategy: ResMut<TimeUpdateStrategy>) {
+    if let TimeUpdateStrategy::ManualInstant(instant) = &mut *strategy {
+        *instant += frame.0;
+    }
+}
+
+fn play_script(clock: Res<LogicalClock>, script: Res<Script>, mut remote: ResMut<RemoteInput>) {
+    for (tick, player, action, down) in &script.0 {
+        if *tick == clock.tick {
+            remote.set(*player, *action, *down);
+        }
+    }
+}
+
+fn record_hash(state: Res<State<AppState>>, world: WorldHash, mut hashes: ResMut<Hashes>) {
+    if state.0 != AppState::Loading {
+        hashes.0.push(world.hash());
+    }
+}
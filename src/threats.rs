@@ -0,0 +1,271 @@
+//! The danger overlay of turn-based mode: every cell an enemy could step
+//! into or strike on its next turn, with the points it will have, tinted
+//! while T has it on, or just the hovered enemy's cells in a colour of
+//! their own.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    board::{BoardQuery, TileChangedEvent},
+    collision::{covered_cells, CollisionMap, Footprint, Occupier},
+    editor::{EditorState, HoveredTile},
+    npc::{Hostile, RangedAi},
+    objects::Door,
+    pathfinding::find_path_budgeted,
+    projectiles::clear_shot,
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    terrain::{actor_capabilities, CapabilitySources},
+    turns::{TurnBased, TurnConfig},
+    vectors::{GridKind, Vector3Int},
+    AppState, CurrentBoard, Position,
+};
+
+/// Enemies whose reach is worked out each frame. The rest keep what they
+/// last reached until a later frame gets to them.
+const THREATS_PER_FRAME: usize = 8;
+/// Over the trail, under the path preview.
+const THREAT_Z: f32 = 2.;
+
+/// The cells each enemy could step into or strike on its next turn.
+#[derive(Default, Resource)]
+pub struct ThreatMap {
+    /// Whether every enemy's cells are shown, toggled with T.
+    pub shown: bool,
+    reach: HashMap<Entity, Vec<Vector3Int>>,
+    /// Enemies whose cells are out of date, longest waiting first.
+    stale: VecDeque<Entity>,
+}
+
+impl ThreatMap {
+    /// The cells `entity` threatens, as last worked out.
+    pub fn reach(&self, entity: Entity) -> &[Vector3Int] {
+        self.reach.get(&entity).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every cell any enemy threatens.
+    pub fn union(&self) -> HashSet<Vector3Int> {
+        self.reach.values().flatten().copied().collect()
+    }
+}
+
+/// A sprite tinting one threatened cell. Kept around hidden when not
+/// needed and reused for later cells.
+#[derive(Component)]
+struct ThreatTile;
+
+pub struct ThreatsPlugin;
+impl Plugin for ThreatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ThreatMap>().add_systems(
+            (toggle_threats, update_threats, draw_threats)
+                .chain()
+                .in_set(OnUpdate(AppState::Game)),
+        );
+    }
+}
+
+fn toggle_threats(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    mut threats: ResMut<ThreatMap>,
+) {
+    if !keys.just_pressed(KeyCode::T) || editor.active {
+        return;
+    }
+    threats.shown = !threats.shown;
+    info!(
+        "Enemy ranges {}.",
+        if threats.shown { "shown" } else { "hidden" }
+    );
+}
+
+/// What working out an enemy's cells reads of it.
+type HostileItem<'a> = (
+    Entity,
+    &'a Position,
+    Option<&'a Footprint>,
+    CapabilitySources<'a>,
+    Option<&'a RangedAi>,
+);
+
+/// The cells an enemy could step into on its turn, each cell of its
+/// footprint there, and every cell it could strike from any of them with
+/// the points it has left: those next to it, or those an archer has a
+/// clear shot at within its range.
+fn threatened_cells(
+    (entity, position, footprint, sources, ranged): HostileItem,
+    board: &BoardQuery,
+    collision: &CollisionMap,
+    grid: GridKind,
+    config: &TurnConfig,
+) -> Vec<Vector3Int> {
+    let current = board.current();
+    let (_, sheet, effects) = sources;
+    let capabilities = actor_capabilities(sources);
+    // Rooted enemies still strike from where they stand.
+    let points = config.max_points(sheet, effects);
+    let budget = match effects.is_some_and(|e| e.is_rooted()) {
+        true => 0.,
+        false => points,
+    };
+    // One-way cells only lead on the way they face.
+    let anchors = find_path_budgeted(
+        position.v,
+        |from| current.neighbours(from, grid),
+        |from, to| {
+            board.fits(entity, to, footprint, capabilities)
+                && board.allows_step(from, to, footprint)
+        },
+        |_, to| board.step_cost(to, footprint, capabilities),
+        budget,
+    );
+    let mut cells = HashSet::new();
+    for (anchor, cost) in anchors {
+        let covered = covered_cells(anchor, footprint);
+        cells.extend(covered.iter().copied());
+        // Striking takes the points for an attack on top of the way there.
+        if cost + config.attack_cost > points {
+            continue;
+        }
+        for cell in covered {
+            let Some(archer) = ranged else {
+                cells.extend(current.neighbours(cell, grid));
+                continue;
+            };
+            let near = board.cells_near(cell, archer.max_range.max(0) as u32);
+            cells.extend(near.into_iter().filter(|t| {
+                current.distance(grid, cell, *t) >= archer.min_range
+                    && clear_shot(cell, *t, current, collision)
+            }));
+        }
+    }
+    cells.retain(|c| current.has_ground(*c));
+    cells.into_iter().collect()
+}
+
+/// Keeps each enemy's cells up to date in turn-based mode, working them
+/// out again for every enemy whenever anything moves, a door opens or the
+/// board changes, a few enemies a frame.
+#[allow(clippy::too_many_arguments)]
+fn update_threats(
+    turn_based: Res<TurnBased>,
+    config: Res<TurnConfig>,
+    board: BoardQuery,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    grid: Res<GridKind>,
+    hostiles: Query<HostileItem, Hostile>,
+    moved: Query<(), (With<Occupier>, Changed<Position>)>,
+    doors: Query<(), Changed<Door>>,
+    mut tiles_changed: EventReader<TileChangedEvent>,
+    mut threats: ResMut<ThreatMap>,
+) {
+    let board_changed = tiles_changed.iter().count() > 0 || current.is_changed();
+    if !turn_based.0 {
+        if !threats.reach.is_empty() || !threats.stale.is_empty() {
+            threats.reach.clear();
+            threats.stale.clear();
+        }
+        return;
+    }
+
+    // Only new cells count as changes, so the overlay is not redrawn every
+    // frame.
+    let t = threats.bypass_change_detection();
+    let before = t.reach.len();
+    t.reach.retain(|entity, _| hostiles.contains(*entity));
+    t.stale.retain(|entity| hostiles.contains(*entity));
+    let mut changed = t.reach.len() != before;
+
+    let everyone = board_changed || config.is_changed() || !moved.is_empty() || !doors.is_empty();
+    for (entity, ..) in hostiles.iter() {
+        let stale = everyone || !t.reach.contains_key(&entity);
+        if stale && !t.stale.contains(&entity) {
+            t.stale.push_back(entity);
+        }
+    }
+
+    for _ in 0..THREATS_PER_FRAME {
+        let Some(entity) = t.stale.pop_front() else { break };
+        let Ok(hostile) = hostiles.get(entity) else { continue };
+        let cells = threatened_cells(hostile, &board, &collision, *grid, &config);
+        t.reach.insert(entity, cells);
+        changed = true;
+    }
+    if changed {
+        threats.set_changed();
+    }
+}
+
+/// Tints the cells of the hovered enemy, or of every enemy while shown,
+/// taking sprites from the pool and adding to it when it runs short.
+#[allow(clippy::too_many_arguments)]
+fn draw_threats(
+    mut commands: Commands,
+    threats: Res<ThreatMap>,
+    turn_based: Res<TurnBased>,
+    editor: Res<EditorState>,
+    hovered: Res<HoveredTile>,
+    palette: Res<PaletteLookup>,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+    hostiles: Query<(Entity, &Position, Option<&Footprint>), Hostile>,
+    mut sprites: Query<(&mut Sprite, &mut Transform, &mut Visibility), With<ThreatTile>>,
+) {
+    let changed = threats.is_changed() || hovered.is_changed() || palette.is_changed();
+    if !changed && !turn_based.is_changed() && !editor.is_changed() {
+        return;
+    }
+
+    // The enemy with any of its cells under the cursor, on whatever layer.
+    let focus = (hovered.0)
+        .filter(|_| turn_based.0 && !editor.active)
+        .and_then(|cell| {
+            hostiles.iter().find_map(|(entity, position, footprint)| {
+                let on = |c: &Vector3Int| current.wrap(Vector3Int::new(cell.x, cell.y, c.z)) == *c;
+                (covered_cells(position.v, footprint).iter().any(on)).then_some(entity)
+            })
+        });
+    let (cells, color) = match focus {
+        Some(entity) => (
+            threats.reach(entity).to_vec(),
+            palette.color(PaletteColor::ThreatFocus),
+        ),
+        None if threats.shown && turn_based.0 && !editor.active => (
+            threats.union().into_iter().collect(),
+            palette.color(PaletteColor::Threat),
+        ),
+        None => (Vec::new(), Color::NONE),
+    };
+
+    let z = z_for(RenderLayerSlot::Overlay, THREAT_Z);
+    let mut places = (cells.iter()).map(|v| projection.world(*v).truncate().extend(z));
+    for (mut sprite, mut transform, mut visibility) in sprites.iter_mut() {
+        match places.next() {
+            Some(place) => {
+                sprite.color = color;
+                transform.translation = place;
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    for place in places {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(projection.tile_size()),
+                    ..default()
+                },
+                transform: Transform::from_translation(place),
+                ..default()
+            },
+            ThreatTile,
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use std::{collections::HashMap, fmt, fs, io};
+
+use serde_json::Value;
+
+/// A cell of a scene layer as written in the map: a map value (a tile
+/// index plus one, with 0 for none), or a tile's name in a tileset's
+/// `names`. With a `palette`, numbers pick its names instead, from 1.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MapTile {
+    Value(usize),
+    Name(String),
+}
+
+impl fmt::Display for MapTile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapTile::Value(value) => write!(f, "{}", value),
+            MapTile::Name(name) => write!(f, "`{}`", name),
+        }
+    }
+}
+
+/// Tile names from the `names` of the map's tilesets, by atlas index, so
+/// maps naming their tiles survive a sheet being laid out afresh.
+#[derive(Default, Debug)]
+pub struct TileRegistry {
+    names: HashMap<String, usize>,
+}
+
+impl TileRegistry {
+    /// Registers `names`, indices within a sheet whose tiles start at
+    /// `first`. Later sheets win over earlier ones giving the same name.
+    pub fn add_sheet(&mut self, first: usize, names: &HashMap<String, usize>) {
+        for (name, index) in names {
+            self.names.insert(name.clone(), first + index);
+        }
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.names.clear();
+    }
+}
+
+/// What `remap` could not name.
+#[derive(Debug, Default)]
+pub struct RemapReport {
+    pub named: usize,
+    /// Tile indices in the map that the table has no name for, kept as
+    /// numbers.
+    pub unnamed: Vec<usize>,
+}
+
+/// Rewrites the numeric `layers` and `overlays` of `map` to name their
+/// tiles by `table`, tile names and the atlas indices they stand for.
+/// Cells the table has no name for keep their number. Maps with a
+/// `palette` are left alone, as their numbers are not indices.
+pub fn remap(map: &mut Value, table: &HashMap<String, usize>) -> RemapReport {
+    let mut report = RemapReport::default();
+    if map.get("palette").is_some() {
+        return report;
+    }
+    let by_index: HashMap<usize, &str> = (table.iter())
+        .map(|(name, index)| (*index, name.as_str()))
+        .collect();
+    for key in ["layers", "overlays"] {
+        let Some(Value::Array(layers)) = map.get_mut(key) else { continue };
+        let cells = layers.iter_mut().filter_map(Value::as_array_mut).flatten();
+        for cell in cells {
+            // Map values are indices plus one, with 0 for none.
+            let Some(index) = cell.as_u64().filter(|v| *v > 0).map(|v| v as usize - 1) else {
+                continue;
+            };
+            match by_index.get(&index) {
+                Some(name) => {
+                    *cell = Value::from(*name);
+                    report.named += 1;
+                }
+                None => report.unnamed.push(index),
+            }
+        }
+    }
+    report.unnamed.sort_unstable();
+    report.unnamed.dedup();
+    report
+}
+
+/// Reads a table of tile names and indices, laid out as a tileset's
+/// `names`.
+pub fn load_table(path: &str) -> io::Result<HashMap<String, usize>> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(io::Error::from)
+}
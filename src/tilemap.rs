@@ -0,0 +1,279 @@
+//! Boards drawn as `bevy_ecs_tilemap` tilemaps rather than a sprite a
+//! tile, for maps too large for sprites, with the `ecs_tilemap` feature.
+//! Only the drawing changes: tiles stay entities on `CurrentBoard` with
+//! their `Position` and `Tile`, which everything else reads and writes as
+//! before. Each is drawn by a tile of the tilemap for its z-index, atlas
+//! and chunk of the board. Changing a tile's index changes the texture
+//! index of the tile drawing it, or moves it to another tilemap if its new
+//! index is in another atlas.
+//!
+//! A tilemap draws every tile the size of its cell from a grid of the same
+//! size, so only orthogonal boards are drawn this way. Even on those, tiles
+//! with a material, a `render_size` or an anchor, and placeholders, are
+//! still drawn as sprites, as are the ghosts across wrapping edges. Tints
+//! carry over as tile colours. Each z-index has tilemaps of its own at its
+//! z, so the ground, overlay and roof bands stack as their sprites do.
+//! Tiles are never flipped, so neither are the tiles drawing them.
+
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::RenderApp,
+    sprite::{Anchor, Mesh2dHandle},
+};
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::{
+    materials::{sprite_shape, ScrollingMaterial, TileMetadataRegistry},
+    projection::GridProjection,
+    tint::TileTint,
+    vectors::Vector3Int,
+    GraphicsAssets, Position, Tile,
+};
+
+/// Cells along each side of a tilemap. The board is split into tilemaps
+/// this size, so that they grow with it and streamed chunks need no more.
+const CHUNK_SIZE: i32 = 64;
+
+/// Which tilemap draws a tile: the one for its z-index, atlas and chunk.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TilemapKey {
+    z: i32,
+    atlas: Handle<TextureAtlas>,
+    chunk: IVec2,
+}
+
+impl TilemapKey {
+    fn new(v: Vector3Int, atlas: &Handle<TextureAtlas>) -> Self {
+        TilemapKey {
+            z: v.z,
+            atlas: atlas.clone(),
+            chunk: IVec2::new(v.x.div_euclid(CHUNK_SIZE), v.y.div_euclid(CHUNK_SIZE)),
+        }
+    }
+}
+
+/// The tile drawing a board tile, at `pos` in the tilemap for `key`.
+struct DrawnTile {
+    key: TilemapKey,
+    pos: TilePos,
+    entity: Entity,
+}
+
+#[derive(Resource, Default)]
+struct Tilemaps {
+    maps: HashMap<TilemapKey, Entity>,
+    /// By board tile.
+    tiles: HashMap<Entity, DrawnTile>,
+}
+
+impl Tilemaps {
+    /// Takes the tile drawing board tile `entity` out of its tilemap.
+    fn take(
+        &mut self,
+        entity: Entity,
+        commands: &mut Commands,
+        storages: &mut Query<&mut TileStorage>,
+    ) {
+        let Some(drawn) = self.tiles.remove(&entity) else { return };
+        let tilemap = self.maps.get(&drawn.key);
+        if let Some(mut storage) = tilemap.and_then(|t| storages.get_mut(*t).ok()) {
+            storage.remove(&drawn.pos);
+        }
+        commands.entity(drawn.entity).despawn();
+    }
+}
+
+pub struct TilemapRenderPlugin;
+impl Plugin for TilemapRenderPlugin {
+    fn build(&self, app: &mut App) {
+        // Tilemaps are set up in the renderer's own app, so with none, as
+        // in headless runs, there is nothing to draw them and nothing to
+        // add.
+        if app.get_sub_app(RenderApp).is_err() {
+            return;
+        }
+        app.add_plugin(TilemapPlugin)
+            .init_resource::<Tilemaps>()
+            .add_system(draw_tilemaps)
+            .add_system(tint_tilemaps.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+/// Whether tile `i` is drawn in a tilemap rather than as a sprite: when
+/// `found` in an atlas, on an orthogonal board, and filling its cell as a
+/// sprite would.
+pub fn draws(
+    i: usize,
+    found: bool,
+    metadata: &TileMetadataRegistry,
+    projection: &GridProjection,
+) -> bool {
+    let GridProjection::Orthogonal { pitch } = *projection else { return false };
+    let cell = Vec2::splat(pitch);
+    let (size, anchor) = sprite_shape(&metadata.0, i, cell);
+    found && metadata.scrolling(i).is_none() && size == cell && matches!(anchor, Anchor::Center)
+}
+
+/// A board tile, and whatever draws it other than a tilemap.
+type BoardTile<'a> = (
+    Entity,
+    &'a Tile,
+    &'a Position,
+    Option<&'a TileTint>,
+    Option<&'a TextureAtlasSprite>,
+    Option<&'a Sprite>,
+    Option<&'a Mesh2dHandle>,
+);
+
+/// Puts tiles into the tilemap for them, again whenever their index
+/// changes, and takes them out when they leave it or are despawned. A new
+/// map, a tileset swap or a new projection draws everything afresh.
+#[allow(clippy::too_many_arguments)]
+fn draw_tilemaps(
+    mut commands: Commands,
+    mut tilemaps: ResMut<Tilemaps>,
+    all: Query<BoardTile>,
+    changed: Query<BoardTile, Changed<Tile>>,
+    mut removed: RemovedComponents<Tile>,
+    mut storages: Query<&mut TileStorage>,
+    graphics: Res<GraphicsAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    metadata: Res<TileMetadataRegistry>,
+    projection: Res<GridProjection>,
+) {
+    let redraw = graphics.is_changed() || metadata.is_changed() || projection.is_changed();
+    if redraw {
+        for (_, tilemap) in tilemaps.maps.drain() {
+            commands.entity(tilemap).despawn();
+        }
+        for (_, drawn) in tilemaps.tiles.drain() {
+            commands.entity(drawn.entity).despawn();
+        }
+    }
+    for entity in removed.iter() {
+        tilemaps.take(entity, &mut commands, &mut storages);
+    }
+
+    // Tilemaps spawned this frame, with the tiles put in them so far.
+    let mut fresh: HashMap<Entity, (TilemapKey, TileStorage)> = HashMap::new();
+    let all = redraw.then(|| all.iter()).into_iter().flatten();
+    let changed = (!redraw).then(|| changed.iter()).into_iter().flatten();
+    for (entity, tile, position, tint, sheet, sprite, mesh) in all.chain(changed) {
+        let found = graphics.tile_atlas(tile.i, &atlases);
+        let drawable = draws(tile.i, found.is_some(), &metadata, &projection);
+        // Left to the scene renderer or the materials plugin.
+        let Some((atlas, index)) = found.filter(|_| drawable) else {
+            tilemaps.take(entity, &mut commands, &mut storages);
+            continue;
+        };
+        let key = TilemapKey::new(position.v, atlas);
+        let texture_index = TileTextureIndex(index as u32);
+        if let Some(drawn) = (tilemaps.tiles.get(&entity)).filter(|d| d.key == key) {
+            commands.entity(drawn.entity).insert(texture_index);
+            continue;
+        }
+        tilemaps.take(entity, &mut commands, &mut storages);
+
+        let tilemap = *tilemaps.maps.entry(key.clone()).or_insert_with(|| {
+            let tilemap = commands.spawn_empty().id();
+            let size = TilemapSize {
+                x: CHUNK_SIZE as u32,
+                y: CHUNK_SIZE as u32,
+            };
+            fresh.insert(tilemap, (key.clone(), TileStorage::empty(size)));
+            tilemap
+        });
+        let pos = TilePos::new(
+            position.v.x.rem_euclid(CHUNK_SIZE) as u32,
+            position.v.y.rem_euclid(CHUNK_SIZE) as u32,
+        );
+        let drawing = commands
+            .spawn(TileBundle {
+                position: pos,
+                tilemap_id: TilemapId(tilemap),
+                texture_index,
+                color: TileColor(tint.map_or(Color::WHITE, TileTint::color)),
+                ..default()
+            })
+            .id();
+        match fresh.get_mut(&tilemap) {
+            Some((_, storage)) => storage.set(&pos, drawing),
+            None => {
+                if let Ok(mut storage) = storages.get_mut(tilemap) {
+                    storage.set(&pos, drawing);
+                }
+            }
+        }
+        tilemaps.tiles.insert(
+            entity,
+            DrawnTile {
+                key,
+                pos,
+                entity: drawing,
+            },
+        );
+        if sheet.is_some() || sprite.is_some() || mesh.is_some() {
+            commands.entity(entity).remove::<(
+                TextureAtlasSprite,
+                Handle<TextureAtlas>,
+                Sprite,
+                Mesh2dHandle,
+                Handle<ScrollingMaterial>,
+            )>();
+        }
+    }
+
+    for (tilemap, (key, storage)) in fresh {
+        let Some(atlas) = atlases.get(&key.atlas) else { continue };
+        (commands.entity(tilemap)).insert(tilemap_bundle(&key, atlas, storage, &projection));
+    }
+}
+
+/// The tilemap for `key`, drawing from `atlas` with its tiles scaled to
+/// the cells of `projection`.
+fn tilemap_bundle(
+    key: &TilemapKey,
+    atlas: &TextureAtlas,
+    storage: TileStorage,
+    projection: &GridProjection,
+) -> TilemapBundle {
+    let first = atlas.textures.first().copied().unwrap_or_default();
+    let size = first.size();
+    // Tilesets with spacing leave the same gap between every tile.
+    let gap = (atlas.textures.get(1))
+        .filter(|next| next.min.y == first.min.y)
+        .map_or(0., |next| next.min.x - first.max.x);
+    let corner = Vector3Int::new(key.chunk.x * CHUNK_SIZE, key.chunk.y * CHUNK_SIZE, key.z);
+    let scale = projection.tile_size() / size.max(Vec2::ONE);
+    TilemapBundle {
+        grid_size: size.into(),
+        map_type: TilemapType::Square,
+        size: storage.size,
+        storage,
+        texture: TilemapTexture::Single(atlas.texture.clone()),
+        tile_size: size.into(),
+        spacing: TilemapSpacing { x: gap, y: gap },
+        transform: Transform::from_translation(projection.world(corner))
+            .with_scale(scale.extend(1.)),
+        ..default()
+    }
+}
+
+/// Colours the tiles drawing tinted board tiles, as `apply_tile_tints`
+/// colours sprites.
+fn tint_tilemaps(
+    tints: Query<(Entity, &TileTint), Changed<TileTint>>,
+    tilemaps: Res<Tilemaps>,
+    mut colors: Query<&mut TileColor>,
+) {
+    for (entity, tint) in tints.iter() {
+        let Some(drawn) = tilemaps.tiles.get(&entity) else { continue };
+        let Ok(mut color) = colors.get_mut(drawn.entity) else { continue };
+        let tinted = tint.color();
+        if color.0 != tinted {
+            color.0 = tinted;
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::{
+    materials::{retexture, ScrollingMaterial},
+    AppState, GraphicsAssets, Scene, SceneHandle, SPRITE_SHEET,
+};
+
+/// Redraws everything drawn from the built-in sheet with another image of
+/// the same layout, such as a winter version, without respawning anything.
+/// Paths are under `assets/`, which may be written out or left off.
+pub struct SwapTilesetEvent {
+    pub texture_path: String,
+}
+
+/// Which image the built-in sheet is drawn from, and the one being loaded
+/// to replace it.
+#[derive(Resource)]
+pub struct TilesetVariant {
+    current: String,
+    pending: Option<(String, Handle<Image>)>,
+    /// Chosen at launch with `--tileset`, over any map's own variant.
+    pub preferred: Option<String>,
+}
+
+impl Default for TilesetVariant {
+    fn default() -> Self {
+        TilesetVariant {
+            current: SPRITE_SHEET.to_string(),
+            pending: None,
+            preferred: None,
+        }
+    }
+}
+
+impl TilesetVariant {
+    pub fn preferring(path: &str) -> Self {
+        TilesetVariant {
+            preferred: Some(asset_path(path).to_string()),
+            ..default()
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+}
+
+fn asset_path(path: &str) -> &str {
+    path.strip_prefix("assets/").unwrap_or(path)
+}
+
+pub struct TilesetSwapPlugin;
+impl Plugin for TilesetSwapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TilesetVariant>()
+            .add_event::<SwapTilesetEvent>()
+            .add_system(request_map_variant.in_set(OnUpdate(AppState::Loading)))
+            // Before anything is spawned this frame, so nothing is left
+            // holding the old sheet.
+            .add_systems(
+                (load_variant, swap_tileset)
+                    .chain()
+                    .in_base_set(CoreSet::PreUpdate),
+            );
+    }
+}
+
+/// Switches to the variant the loading map asks for, or back to the
+/// built-in sheet, once its scene is in.
+fn request_map_variant(
+    scene: Res<SceneHandle>,
+    scenes: Res<Assets<Scene>>,
+    variant: Res<TilesetVariant>,
+    mut swaps: EventWriter<SwapTilesetEvent>,
+    mut requested: Local<Option<Handle<Scene>>>,
+) {
+    if requested.as_ref() == Some(&scene.0) {
+        return;
+    }
+    let Some(loaded) = scenes.get(&scene.0) else { return };
+    *requested = Some(scene.0.clone());
+
+    let wanted = (variant.preferred.as_deref())
+        .or(loaded.tileset_variant.as_deref())
+        .unwrap_or(SPRITE_SHEET);
+    let pending = variant.pending.as_ref().map(|(path, _)| path.as_str());
+    if asset_path(wanted) != pending.unwrap_or(&variant.current) {
+        swaps.send(SwapTilesetEvent {
+            texture_path: wanted.to_string(),
+        });
+    }
+}
+
+/// Starts loading the latest image asked for. An earlier one still loading
+/// is dropped.
+fn load_variant(
+    server: Res<AssetServer>,
+    mut swaps: EventReader<SwapTilesetEvent>,
+    mut variant: ResMut<TilesetVariant>,
+) {
+    let Some(swap) = swaps.iter().last() else { return };
+    let path = asset_path(&swap.texture_path).to_string();
+    if path == variant.current {
+        variant.pending = None;
+        return;
+    }
+    info!("Loading tileset `{}`.", path);
+    let image = server.load(path.as_str());
+    variant.pending = Some((path, image));
+}
+
+/// Once the image has loaded, gives the built-in sheet's atlas a copy
+/// drawn from it and points every sprite, tileset and material at that.
+/// Until then, and if it fails, the old sheet stays.
+fn swap_tileset(
+    server: Res<AssetServer>,
+    images: Res<Assets<Image>>,
+    mut variant: ResMut<TilesetVariant>,
+    graphics: Option<ResMut<GraphicsAssets>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ScrollingMaterial>>,
+    mut sprites: Query<&mut Handle<TextureAtlas>>,
+) {
+    let Some((path, image)) = variant.pending.clone() else { return };
+    // Inserted by a startup system.
+    let Some(mut graphics) = graphics else { return };
+    match server.get_load_state(&image) {
+        LoadState::Loaded => {}
+        LoadState::Failed | LoadState::Unloaded => {
+            warn!(
+                "Could not load tileset `{}`; keeping `{}`.",
+                path, variant.current
+            );
+            variant.pending = None;
+            return;
+        }
+        _ => return,
+    }
+    let (Some(old), Some(loaded)) = (atlases.get(&graphics.sprite_texture), images.get(&image))
+    else {
+        return;
+    };
+    if loaded.size() != old.size {
+        warn!(
+            "Tileset `{}` is {} but `{}` is {}; keeping it.",
+            path,
+            loaded.size(),
+            variant.current,
+            old.size
+        );
+        variant.pending = None;
+        return;
+    }
+
+    // The same rects, so every index still finds its tile.
+    let mut swapped = TextureAtlas::new_empty(image.clone(), old.size);
+    for rect in old.textures.iter() {
+        swapped.add_texture(*rect);
+    }
+    let old_image = old.texture.clone();
+    let old = graphics.sprite_texture.clone();
+    let new = atlases.add(swapped);
+    for mut handle in sprites.iter_mut() {
+        if *handle == old {
+            *handle = new.clone();
+        }
+    }
+    for (_, atlas) in graphics.atlases.iter_mut() {
+        if *atlas == old {
+            *atlas = new.clone();
+        }
+    }
+    graphics.sprite_texture = new;
+    retexture(&mut materials, &old_image, &image);
+
+    info!("Switched tileset to `{}`.", path);
+    variant.current = path;
+    variant.pending = None;
+}
@@ -0,0 +1,195 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    load_scene,
+    map_meta::MapMeta,
+    progression::Campaign,
+    simulation::{SimulationApp, SimulationSet},
+    turns::TurnQueue,
+    AppState, LoadMapEvent,
+};
+
+/// Seconds left below which the HUD flashes the countdown.
+pub const TIMER_WARNING: f32 = 10.;
+
+/// The countdown of a map with a `time_limit_s`. Only counts while the
+/// game runs, so menus and the pause hold it.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LevelTimer {
+    /// The map counted down on.
+    map: String,
+    /// Seconds the map allows, or `None` for maps without a limit.
+    limit: Option<f32>,
+    remaining: f32,
+    /// Seconds still to pass before the countdown carries on.
+    frozen: f32,
+    expired: bool,
+}
+
+impl LevelTimer {
+    /// Seconds left, on maps with a limit.
+    pub fn remaining(&self) -> Option<f32> {
+        self.limit.map(|_| self.remaining)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen > 0.
+    }
+
+    fn is_running(&self) -> bool {
+        self.limit.is_some() && !self.expired
+    }
+
+    /// Starts counting down from `limit` on `map`. The same map coming back
+    /// with the same limit, as when it is reloaded, carries on instead,
+    /// unless time ran out on it.
+    fn begin(&mut self, map: &str, limit: Option<f32>) {
+        if self.map == map && self.limit == limit && !self.expired {
+            return;
+        }
+        *self = LevelTimer {
+            map: map.to_string(),
+            limit,
+            remaining: limit.unwrap_or(0.).max(0.),
+            ..default()
+        };
+    }
+
+    /// Carries on from a saved countdown, if it was saved on `map`. Saves
+    /// from before timers keep the one the map started.
+    pub fn restore(&mut self, saved: &LevelTimer, map: &str) {
+        if saved.map == map {
+            *self = saved.clone();
+        }
+    }
+
+    /// Counts `seconds` off, after any freeze. Returns whether that ran
+    /// the time out.
+    fn count(&mut self, seconds: f32) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        let frozen = self.frozen.min(seconds);
+        self.frozen -= frozen;
+        self.remaining = (self.remaining - (seconds - frozen)).max(0.);
+        self.expired = self.remaining <= 0.;
+        self.expired
+    }
+}
+
+/// How the countdown runs.
+#[derive(Resource, Debug)]
+pub struct LevelTimerConfig {
+    /// Seconds each round of turn-based mode takes off, or `None` to keep
+    /// counting real time there too.
+    pub seconds_per_turn: Option<f32>,
+    /// Whether running out of time restarts the map.
+    pub restart_on_expire: bool,
+}
+
+impl Default for LevelTimerConfig {
+    fn default() -> Self {
+        LevelTimerConfig {
+            seconds_per_turn: Some(1.),
+            restart_on_expire: true,
+        }
+    }
+}
+
+/// Adds `delta` seconds to the countdown, which may be negative, and holds
+/// it still for the next `freeze` seconds.
+pub struct ModifyTimerEvent {
+    pub delta: f32,
+    pub freeze: f32,
+}
+
+/// Sent once when the countdown reaches zero.
+pub struct TimeExpiredEvent;
+
+/// Sends `ModifyTimerEvent` when the trigger it is on fires, as for
+/// pickups that buy more time.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ModifiesTimer {
+    pub delta: f32,
+    pub freeze: f32,
+}
+
+pub struct TimerPlugin;
+impl Plugin for TimerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelTimer>()
+            .init_resource::<LevelTimerConfig>()
+            .add_simulation_event::<ModifyTimerEvent>()
+            .add_simulation_event::<TimeExpiredEvent>()
+            .add_system(
+                start_timer
+                    .after(load_scene)
+                    .in_schedule(OnEnter(AppState::Game)),
+            )
+            .add_systems(
+                (count_down, restart_on_expire)
+                    .chain()
+                    .distributive_run_if(in_state(AppState::Game))
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+fn start_timer(meta: Res<MapMeta>, campaign: Res<Campaign>, mut timer: ResMut<LevelTimer>) {
+    // Chunked worlds take theirs from the start chunk.
+    let limit = meta.time_limit_s;
+    timer.begin(campaign.current_map(), limit);
+    if let Some(limit) = limit {
+        info!("{:.0} seconds to finish the level.", limit);
+    }
+}
+
+/// Applies the tick's changes, then counts the tick off, or the rounds
+/// begun in turn-based mode.
+fn count_down(
+    mut modify: EventReader<ModifyTimerEvent>,
+    fixed: Res<FixedTime>,
+    queue: Res<TurnQueue>,
+    config: Res<LevelTimerConfig>,
+    mut timer: ResMut<LevelTimer>,
+    mut expired: EventWriter<TimeExpiredEvent>,
+    mut rounds_seen: Local<u32>,
+) {
+    for event in modify.iter() {
+        if !timer.is_running() {
+            continue;
+        }
+        timer.remaining = (timer.remaining + event.delta).max(0.);
+        timer.frozen += event.freeze.max(0.);
+    }
+
+    // Rounds start over from 0 each time turn-based mode is switched on.
+    let round = queue.round();
+    let rounds = round.checked_sub(*rounds_seen).unwrap_or(round);
+    *rounds_seen = round;
+    let seconds = match config.seconds_per_turn {
+        Some(per_turn) if queue.is_active() => rounds as f32 * per_turn,
+        _ => fixed.period.as_secs_f32(),
+    };
+    if timer.count(seconds) {
+        info!("Out of time!");
+        expired.send(TimeExpiredEvent);
+    }
+}
+
+/// With no game over to go to, running out of time starts the map again.
+fn restart_on_expire(
+    mut expired: EventReader<TimeExpiredEvent>,
+    config: Res<LevelTimerConfig>,
+    campaign: Res<Campaign>,
+    mut loads: EventWriter<LoadMapEvent>,
+) {
+    if expired.iter().last().is_some() && config.restart_on_expire {
+        loads.send(LoadMapEvent {
+            name: campaign.current_map().to_string(),
+        });
+    }
+}
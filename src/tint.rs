@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+/// Colours laid over a tile's sprite by separate systems, combined in a
+/// fixed order so that none of them overwrites another:
+///
+/// 1. `overlay` (a team colour, a highlight) is blended over white by its
+///    alpha, so a faint overlay barely changes the tile;
+/// 2. `heat`, the visit heatmap, is blended over that the same way;
+/// 3. then `fire`, on tiles burning;
+/// 4. the result is multiplied by `light`, such as the time of day;
+/// 5. and that by `fog`, so hidden tiles stay dark whatever lies under
+///    them;
+/// 6. lastly its opacity is scaled by `alpha`, as roofs fade out.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct TileTint {
+    pub overlay: Option<Color>,
+    pub heat: Option<Color>,
+    pub fire: Option<Color>,
+    pub light: Color,
+    pub fog: Color,
+    pub alpha: f32,
+}
+
+impl Default for TileTint {
+    fn default() -> Self {
+        TileTint {
+            overlay: None,
+            heat: None,
+            fire: None,
+            light: Color::WHITE,
+            fog: Color::WHITE,
+            alpha: 1.,
+        }
+    }
+}
+
+impl TileTint {
+    /// The sprite colour the layers combine into.
+    pub fn color(&self) -> Color {
+        let blend = |under: Vec4, over: Option<Color>| {
+            over.map_or(under, |color| {
+                let color = Vec4::from(color.as_rgba_f32());
+                under.lerp(color.truncate().extend(1.), color.w)
+            })
+        };
+        let tinted = blend(blend(blend(Vec4::ONE, self.overlay), self.heat), self.fire);
+        let light = Vec4::from(self.light.as_rgba_f32());
+        let fog = Vec4::from(self.fog.as_rgba_f32());
+        Color::from(tinted * light * fog * Vec4::new(1., 1., 1., self.alpha))
+    }
+}
+
+pub struct TintPlugin;
+impl Plugin for TintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_tile_tints.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+/// Colours tinted sprites, again whenever the tint changes or the sprite
+/// is redrawn with a new tile.
+#[allow(clippy::type_complexity)]
+pub fn apply_tile_tints(
+    mut tiles: Query<
+        (&TileTint, &mut TextureAtlasSprite),
+        Or<(Changed<TileTint>, Changed<TextureAtlasSprite>)>,
+    >,
+) {
+    for (tint, mut sprite) in tiles.iter_mut() {
+        let color = tint.color();
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, fmt::Write};
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    layer_of, layer_z,
+    marks::{self, Mark, MarkSprites, Marks},
+    player::{PlayerStepCompleted, PlayerStepStarted},
+    projection::GridProjection,
+    render_layers::{z_for, RenderLayerSlot},
+    simulation::SimulationSet,
+    storage::{platform_store, KeyValueStore},
+    tint::TileTint,
+    vectors::Vector3Int,
+    CurrentBoard, LoadMapEvent,
+};
+
+/// Written to the working directory, or kept under this key in the browser.
+pub const HEATMAP_DIR: &str = ".";
+pub const HEATMAP_KEY: &str = "heatmap.csv";
+/// Over blast rings, under the path preview, within the overlay band.
+const TRAIL_Z: f32 = 1.;
+/// Dash size as a fraction of a tile, across and along the way walked.
+const DASH_SCALE: Vec2 = Vec2::new(0.15, 0.35);
+
+/// What is drawn of where players have walked. F11 cycles through them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailMode {
+    #[default]
+    Off,
+    /// A fading dash on each of the latest cells stepped on, pointing the
+    /// way walked.
+    Breadcrumbs,
+    /// Floor tiles tinted by how often they were stepped on.
+    Heatmap,
+}
+
+impl TrailMode {
+    fn next(self) -> Self {
+        match self {
+            TrailMode::Off => TrailMode::Breadcrumbs,
+            TrailMode::Breadcrumbs => TrailMode::Heatmap,
+            TrailMode::Heatmap => TrailMode::Off,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct TrailConfig {
+    pub mode: TrailMode,
+    /// Cells the breadcrumb trail keeps, newest first.
+    pub length: usize,
+    /// Seconds a breadcrumb takes to fade away.
+    pub fade_seconds: f32,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        TrailConfig {
+            mode: TrailMode::Off,
+            length: 20,
+            fade_seconds: 3.,
+        }
+    }
+}
+
+/// The latest cells players stepped on, oldest first.
+#[derive(Default, Resource)]
+struct Breadcrumbs(Marks);
+
+/// Steps onto each floor cell of the current map while the heatmap is on.
+#[derive(Default, Resource)]
+pub struct VisitCounts {
+    counts: HashMap<Vector3Int, u32>,
+}
+
+impl VisitCounts {
+    /// Each cell, with its layer for z, and the steps onto it.
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3Int, u32)> + '_ {
+        self.counts.iter().map(|(v, count)| (*v, *count))
+    }
+
+    /// One `x,y,layer,visits` row per cell, sorted by layer, then row, then
+    /// column, under a header.
+    pub fn to_csv(&self) -> String {
+        let mut cells: Vec<(Vector3Int, u32)> = self.iter().collect();
+        cells.sort_by_key(|(v, _)| (v.z, v.y, v.x));
+        let mut csv = String::from("x,y,layer,visits\n");
+        for (v, count) in cells {
+            let _ = writeln!(csv, "{},{},{},{}", v.x, v.y, v.z, count);
+        }
+        csv
+    }
+}
+
+/// A sprite drawing one breadcrumb.
+#[derive(Component)]
+struct TrailDot;
+
+pub struct TrailPlugin;
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrailConfig>()
+            .init_resource::<Breadcrumbs>()
+            .init_resource::<VisitCounts>()
+            .add_system(
+                record_steps
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems((toggle_trail, clear_on_load, draw_breadcrumbs, tint_heatmap).chain())
+            .add_system(export_heatmap.run_if(export_requested));
+    }
+}
+
+fn toggle_trail(keys: Res<Input<KeyCode>>, mut config: ResMut<TrailConfig>) {
+    if keys.just_pressed(KeyCode::F11) {
+        config.mode = config.mode.next();
+        info!("Trail: {:?}.", config.mode);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_steps(
+    mut started: EventReader<PlayerStepStarted>,
+    mut steps: EventReader<PlayerStepCompleted>,
+    time: Res<Time>,
+    config: Res<TrailConfig>,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+    mut breadcrumbs: ResMut<Breadcrumbs>,
+    mut visits: ResMut<VisitCounts>,
+    mut ways: Local<HashMap<Entity, Vector3Int>>,
+) {
+    for step in started.iter() {
+        ways.insert(step.entity, step.dir);
+    }
+    breadcrumbs.0.capacity = config.length;
+    for step in steps.iter() {
+        let v = current.wrap(step.at);
+        match config.mode {
+            TrailMode::Off => {}
+            TrailMode::Breadcrumbs => {
+                let way = ways.get(&step.entity).copied().unwrap_or_default();
+                breadcrumbs.0.stamp(Mark {
+                    at: (projection.world(v).truncate())
+                        .extend(z_for(RenderLayerSlot::Overlay, TRAIL_Z)),
+                    rotation: marks::facing(way, &projection),
+                    stamped: time.elapsed_seconds(),
+                });
+            }
+            TrailMode::Heatmap => {
+                *visits
+                    .counts
+                    .entry(Vector3Int::new(v.x, v.y, layer_of(v.z)))
+                    .or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Neither the trail nor the heatmap carries over to another map.
+fn clear_on_load(
+    mut loads: EventReader<LoadMapEvent>,
+    mut breadcrumbs: ResMut<Breadcrumbs>,
+    mut visits: ResMut<VisitCounts>,
+) {
+    if loads.iter().last().is_some() {
+        breadcrumbs.0.clear();
+        visits.counts.clear();
+    }
+}
+
+/// Draws each breadcrumb, faded by its age.
+fn draw_breadcrumbs(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<TrailConfig>,
+    palette: Res<PaletteLookup>,
+    projection: Res<GridProjection>,
+    mut breadcrumbs: ResMut<Breadcrumbs>,
+    mut dashes: MarkSprites<TrailDot>,
+) {
+    let now = time.elapsed_seconds();
+    if config.mode != TrailMode::Breadcrumbs {
+        breadcrumbs.0.clear();
+    }
+    breadcrumbs.0.expire(now, config.fade_seconds);
+
+    let color = palette.color(PaletteColor::Trail);
+    let size = projection.tile_size() * DASH_SCALE;
+    let fade = Some(config.fade_seconds);
+    marks::draw_marks(
+        &mut commands,
+        &breadcrumbs.0,
+        now,
+        fade,
+        color,
+        &mut dashes,
+        |color, transform| {
+            let sprite = Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            };
+            (
+                SpriteBundle {
+                    sprite,
+                    transform,
+                    ..default()
+                },
+                TrailDot,
+            )
+        },
+    );
+}
+
+/// Tints each visited floor tile by its share of the most visited one's
+/// steps, redone whenever the counts, the mode or the board change.
+fn tint_heatmap(
+    mut commands: Commands,
+    config: Res<TrailConfig>,
+    visits: Res<VisitCounts>,
+    current: Res<CurrentBoard>,
+    palette: Res<PaletteLookup>,
+    mut tints: Query<&mut TileTint>,
+) {
+    let changed = config.is_changed() || visits.is_changed() || current.is_changed();
+    if !changed && !palette.is_changed() {
+        return;
+    }
+
+    for mut tint in tints.iter_mut().filter(|t| t.heat.is_some()) {
+        tint.heat = None;
+    }
+    if config.mode != TrailMode::Heatmap {
+        return;
+    }
+    let most = visits.counts.values().copied().max().unwrap_or(0).max(1);
+    let hot = palette.color(PaletteColor::Heat);
+    for (v, count) in visits.iter() {
+        let Some(entity) = current.floor(Vector3Int::new(v.x, v.y, layer_z(v.z, 0))) else {
+            continue;
+        };
+        let heat = Some(hot.with_a(hot.a() * count as f32 / most as f32));
+        match tints.get_mut(entity) {
+            Ok(mut tint) => tint.heat = heat,
+            Err(_) => {
+                commands
+                    .entity(entity)
+                    .insert(TileTint { heat, ..default() });
+            }
+        }
+    }
+}
+
+fn export_requested(keys: Res<Input<KeyCode>>) -> bool {
+    keys.just_pressed(KeyCode::F12)
+}
+
+fn export_heatmap(visits: Res<VisitCounts>) {
+    match platform_store(HEATMAP_DIR).set(HEATMAP_KEY, &visits.to_csv()) {
+        Ok(()) => info!("Wrote {} cells to {}.", visits.counts.len(), HEATMAP_KEY),
+        Err(e) => warn!("Could not write {}: {}", HEATMAP_KEY, e),
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{input::InputSuppressed, AppState, BoardLoadedEvent, LoadMapEvent};
+
+/// Above every other UI, menus included.
+const OVERLAY_Z: i32 = 100;
+
+/// How the screen is covered and uncovered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransitionStyle {
+    /// The whole screen fades to black and back.
+    #[default]
+    Fade,
+    /// Black closes in from every edge to the centre, and opens out again.
+    Iris,
+    /// Black sweeps across from the left, and off to the right.
+    Wipe,
+}
+
+impl TransitionStyle {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fade" => Some(TransitionStyle::Fade),
+            "iris" => Some(TransitionStyle::Iris),
+            "wipe" => Some(TransitionStyle::Wipe),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct TransitionConfig {
+    pub style: TransitionStyle,
+    /// Seconds covering the screen takes, and again uncovering it.
+    pub duration: f32,
+}
+
+impl Default for TransitionConfig {
+    fn default() -> Self {
+        TransitionConfig {
+            style: TransitionStyle::Fade,
+            duration: 0.3,
+        }
+    }
+}
+
+/// What a transition hides the switch to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransitionCause {
+    /// The map `name` replacing the current board.
+    LoadMap(String),
+    State(AppState),
+}
+
+/// The screen has started to cover, for music and the like to follow.
+pub struct TransitionStarted {
+    pub cause: TransitionCause,
+}
+
+/// The screen is fully covered. Map loads tear the old board down now.
+pub struct TransitionCovered {
+    pub cause: TransitionCause,
+}
+
+/// The screen is uncovered again.
+pub struct TransitionFinished {
+    pub cause: TransitionCause,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Phase {
+    #[default]
+    Idle,
+    Covering,
+    /// Fully covered, until the new board is loaded.
+    Holding,
+    Revealing,
+}
+
+/// The transition under way, and those asked for while it runs. Each one
+/// plays in full after the last rather than on top of it.
+#[derive(Resource, Default, Debug)]
+pub struct ScreenTransition {
+    phase: Phase,
+    current: Option<TransitionCause>,
+    queue: VecDeque<TransitionCause>,
+    /// How much of the screen is covered, from 0 to 1.
+    coverage: f32,
+}
+
+impl ScreenTransition {
+    /// Queues a transition for `cause`, unless the same one is already
+    /// waiting its turn or still covering the screen.
+    pub fn request(&mut self, cause: TransitionCause) {
+        let covering = self.phase == Phase::Covering && self.current.as_ref() == Some(&cause);
+        if !covering && self.queue.back() != Some(&cause) {
+            self.queue.push_back(cause);
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.phase != Phase::Idle || !self.queue.is_empty()
+    }
+}
+
+/// The overlay's panels, laid out each frame from the coverage.
+#[derive(Component, Clone, Copy)]
+enum Panel {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Component)]
+struct Overlay;
+
+pub struct TransitionsPlugin;
+impl Plugin for TransitionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransitionConfig>()
+            .init_resource::<ScreenTransition>()
+            .add_event::<TransitionStarted>()
+            .add_event::<TransitionCovered>()
+            .add_event::<TransitionFinished>()
+            .add_startup_system(spawn_overlay)
+            .add_systems(
+                (request_map_loads, advance_transition, draw_overlay)
+                    .chain()
+                    .in_base_set(CoreSet::PreUpdate),
+            );
+    }
+}
+
+fn spawn_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Overlay,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::all(Val::Percent(100.)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(OVERLAY_Z),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for panel in [Panel::Left, Panel::Right, Panel::Top, Panel::Bottom] {
+                parent.spawn((
+                    panel,
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            ..default()
+                        },
+                        background_color: Color::BLACK.into(),
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+/// Every map load plays behind a transition.
+fn request_map_loads(
+    mut loads: EventReader<LoadMapEvent>,
+    mut transition: ResMut<ScreenTransition>,
+) {
+    for event in loads.iter() {
+        transition.request(TransitionCause::LoadMap(event.name.clone()));
+    }
+}
+
+/// Moves the transition on by a frame: covering, holding until whatever it
+/// hides is done, then uncovering and starting the next one queued.
+#[allow(clippy::too_many_arguments)]
+fn advance_transition(
+    time: Res<Time>,
+    config: Res<TransitionConfig>,
+    mut transition: ResMut<ScreenTransition>,
+    mut loaded: EventReader<BoardLoadedEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut suppressed: ResMut<InputSuppressed>,
+    mut started: EventWriter<TransitionStarted>,
+    mut covered: EventWriter<TransitionCovered>,
+    mut finished: EventWriter<TransitionFinished>,
+) {
+    let step = match config.duration {
+        duration if duration > 0. => time.delta_seconds() / duration,
+        _ => 1.,
+    };
+    let board_loaded = loaded.iter().count() > 0;
+    let transition = &mut *transition;
+
+    if transition.phase == Phase::Idle {
+        if let Some(cause) = transition.queue.pop_front() {
+            started.send(TransitionStarted {
+                cause: cause.clone(),
+            });
+            transition.current = Some(cause);
+            transition.phase = Phase::Covering;
+        }
+    }
+    match transition.phase {
+        Phase::Idle => {}
+        Phase::Covering => {
+            transition.coverage = (transition.coverage + step).min(1.);
+            if transition.coverage >= 1. {
+                let cause = transition.current.clone().unwrap();
+                transition.phase = match cause {
+                    TransitionCause::LoadMap(_) => Phase::Holding,
+                    TransitionCause::State(state) => {
+                        next_state.set(state);
+                        Phase::Revealing
+                    }
+                };
+                covered.send(TransitionCovered { cause });
+            }
+        }
+        Phase::Holding => {
+            if board_loaded {
+                transition.phase = Phase::Revealing;
+            }
+        }
+        Phase::Revealing => {
+            transition.coverage = (transition.coverage - step).max(0.);
+            if transition.coverage <= 0. {
+                transition.phase = Phase::Idle;
+                let cause = transition.current.take().unwrap();
+                finished.send(TransitionFinished { cause });
+            }
+        }
+    }
+    suppressed.0 = transition.is_active();
+}
+
+fn draw_overlay(
+    config: Res<TransitionConfig>,
+    transition: Res<ScreenTransition>,
+    mut overlays: Query<&mut Visibility, With<Overlay>>,
+    mut panels: Query<(&Panel, &mut Style, &mut BackgroundColor)>,
+) {
+    let coverage = transition.coverage;
+    for mut visibility in overlays.iter_mut() {
+        *visibility = match transition.phase {
+            Phase::Idle => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
+    }
+    if transition.phase == Phase::Idle {
+        return;
+    }
+
+    let percent = |fraction: f32| Val::Percent(fraction * 100.);
+    let revealing = transition.phase == Phase::Revealing;
+    for (panel, mut style, mut color) in panels.iter_mut() {
+        let mut alpha = 1.;
+        // Left, top, width and height, as fractions of the screen.
+        let (left, top, width, height) = match (config.style, panel) {
+            (TransitionStyle::Fade, Panel::Left) => {
+                alpha = coverage;
+                (0., 0., 1., 1.)
+            }
+            (TransitionStyle::Wipe, Panel::Left) if revealing => (1. - coverage, 0., coverage, 1.),
+            (TransitionStyle::Wipe, Panel::Left) => (0., 0., coverage, 1.),
+            (TransitionStyle::Iris, Panel::Left) => (0., 0., coverage / 2., 1.),
+            (TransitionStyle::Iris, Panel::Right) => (1. - coverage / 2., 0., coverage / 2., 1.),
+            (TransitionStyle::Iris, Panel::Top) => (0., 0., 1., coverage / 2.),
+            (TransitionStyle::Iris, Panel::Bottom) => (0., 1. - coverage / 2., 1., coverage / 2.),
+            _ => (0., 0., 0., 0.),
+        };
+        style.position = UiRect {
+            left: percent(left),
+            top: percent(top),
+            ..default()
+        };
+        style.size = Size::new(percent(width), percent(height));
+        color.0 = Color::rgba(0., 0., 0., alpha);
+    }
+}
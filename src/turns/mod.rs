@@ -0,0 +1,659 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use bevy::{
+    asset::HandleId,
+    ecs::system::SystemParam,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    collision::CollisionMap,
+    combat::{self, DiedEvent},
+    equipment::StatSheet,
+    player::Player,
+    simulation::{SimulationApp, SimulationSet},
+    status::StatusEffects,
+    terrain::{actor_capabilities, CapabilitySources},
+    AppState, Position,
+};
+
+/// Initiative of players, who act before NPCs that have none.
+pub const PLAYER_INITIATIVE: i32 = 10;
+/// How many actors the turn strip shows, starting with the current one.
+const TURN_STRIP_LENGTH: usize = 6;
+const PORTRAIT_SIZE: f32 = 24.;
+/// The frame around each portrait, lit for the actor whose turn it is.
+const PORTRAIT_BORDER: f32 = 2.;
+/// The height of the bar under the current actor's portrait showing the
+/// points it has left.
+const POINTS_BAR_HEIGHT: f32 = 3.;
+
+/// Where an actor comes in each round of turn-based mode. Higher goes
+/// first.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Initiative {
+    pub value: i32,
+}
+
+/// Whether the game is turn-based, with one actor acting per tick in
+/// initiative order, rather than everyone acting on their own timers.
+/// Toggled with F4.
+#[derive(Default, Resource, Debug)]
+pub struct TurnBased(pub bool);
+
+/// What a turn in turn-based mode allows.
+#[derive(Resource, Clone, Debug)]
+pub struct TurnConfig {
+    /// Points each actor starts its turn with, before haste and slow.
+    pub action_points: f32,
+    /// What an attack costs, a bump into a creature or a block included.
+    pub attack_cost: f32,
+}
+
+impl Default for TurnConfig {
+    fn default() -> Self {
+        TurnConfig {
+            action_points: 4.,
+            attack_cost: 2.,
+        }
+    }
+}
+
+impl TurnConfig {
+    /// The points a turn starts with for an actor with `sheet` and
+    /// `effects`: divided by the scale of its time between steps, so haste
+    /// gives more and slow fewer, and rounded down to whole points.
+    pub fn max_points(&self, sheet: Option<&StatSheet>, effects: Option<&StatusEffects>) -> f32 {
+        // The stat sheet counts status effects in with equipment.
+        let scale = sheet.map_or_else(
+            || effects.map_or(1., |e| e.interval_scale()),
+            |s| s.interval_scale,
+        );
+        (self.action_points / scale.max(f32::EPSILON)).floor()
+    }
+
+    /// The least any action costs an actor with `sources`: a step onto the
+    /// cheapest ground there is, or an attack.
+    fn cheapest(&self, sources: CapabilitySources, collision: &CollisionMap) -> f32 {
+        let step = collision.min_step_cost(actor_capabilities(sources));
+        step.min(self.attack_cost)
+    }
+}
+
+/// What an actor has left to spend on its turn in turn-based mode. A step
+/// costs what its cell costs to enter, an attack `TurnConfig::attack_cost`.
+/// The turn goes on until the actor passes, or cannot pay for the cheapest
+/// action there is. Filled up as each turn starts.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionPoints {
+    pub max: f32,
+    pub current: f32,
+}
+
+impl ActionPoints {
+    pub fn full(max: f32) -> Self {
+        ActionPoints { max, current: max }
+    }
+
+    pub fn can_afford(&self, cost: f32) -> bool {
+        cost.max(0.) <= self.current
+    }
+
+    /// Gives up whatever is left, ending the turn.
+    pub fn pass(&mut self) {
+        self.current = 0.;
+    }
+}
+
+/// Pays `cost` out of `points`, if there is enough. With no points to pay
+/// out of, as in real time, everything is free. Points that cannot pay are
+/// left untouched, so that an actor doing nothing on its turn is seen to.
+pub fn pay_points(points: &mut Option<Mut<ActionPoints>>, cost: f32) -> bool {
+    match points {
+        Some(points) if !points.can_afford(cost) => false,
+        Some(points) => {
+            points.current -= cost.max(0.);
+            true
+        }
+        None => true,
+    }
+}
+
+/// Whose turn it is, and what a turn allows.
+#[derive(SystemParam)]
+pub struct Turns<'w> {
+    pub queue: Res<'w, TurnQueue>,
+    pub config: Res<'w, TurnConfig>,
+}
+
+/// Sent when an actor's turn begins in turn-based mode.
+pub struct TurnStarted {
+    pub entity: Entity,
+}
+
+/// Sent when an actor's turn is over: it has spent its points or passed,
+/// or is gone.
+pub struct TurnEnded {
+    pub entity: Entity,
+}
+
+/// The order actors act in this round, rebuilt from their initiative at
+/// the start of each. Actors spawned mid-round join the rest of the round
+/// in initiative order, and those defeated drop out.
+#[derive(Default, Resource)]
+pub struct TurnQueue {
+    active: bool,
+    order: Vec<Entity>,
+    /// Index into `order` of the actor whose turn it is.
+    current: usize,
+    /// Whether `TurnStarted` has been sent for the current actor.
+    started: bool,
+    round: u32,
+}
+
+impl TurnQueue {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The actor whose turn it is.
+    pub fn current(&self) -> Option<Entity> {
+        self.order.get(self.current).copied()
+    }
+
+    /// The actors still to act this round, starting with the current one.
+    pub fn upcoming(&self) -> &[Entity] {
+        &self.order[self.current.min(self.order.len())..]
+    }
+
+    /// Rounds begun since turn-based mode was switched on.
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    /// Whether `entity` may act this tick: always in real time, and only on
+    /// its own turn otherwise.
+    pub fn may_act(&self, entity: Entity) -> bool {
+        !self.active || self.current() == Some(entity)
+    }
+}
+
+/// Sorts actors by initiative, breaking ties by cell, bottom row first and
+/// then left to right, and lastly by entity, so that the same world always
+/// gives the same order.
+type TurnKey = (Reverse<i32>, i32, i32, i32, Entity);
+
+fn turn_key(entity: Entity, initiative: &Initiative, position: &Position) -> TurnKey {
+    let v = position.v;
+    (Reverse(initiative.value), v.y, v.x, v.z, entity)
+}
+
+/// Portraits for the turn strip, cut out of sprite sheets on first use.
+#[derive(Default, Resource)]
+struct Portraits(HashMap<(HandleId, usize), Handle<Image>>);
+
+impl Portraits {
+    /// An image of sprite `index` of `atlas`, once the sheet has loaded.
+    fn get(
+        &mut self,
+        atlas: &Handle<TextureAtlas>,
+        index: usize,
+        atlases: &Assets<TextureAtlas>,
+        images: &mut Assets<Image>,
+    ) -> Option<Handle<Image>> {
+        if let Some(portrait) = self.0.get(&(atlas.id(), index)) {
+            return Some(portrait.clone());
+        }
+        let sheet = atlases.get(atlas)?;
+        let rect = *sheet.textures.get(index)?;
+        let image = images.get(&sheet.texture)?;
+
+        let size = image.texture_descriptor.size;
+        let bytes = image.data.len() / (size.width * size.height).max(1) as usize;
+        let (x, y) = (rect.min.x as usize, rect.min.y as usize);
+        let (width, height) = (rect.width() as usize, rect.height() as usize);
+        let mut data = Vec::with_capacity(width * height * bytes);
+        for row in y..y + height {
+            let start = (row * size.width as usize + x) * bytes;
+            data.extend_from_slice(image.data.get(start..start + width * bytes)?);
+        }
+        let portrait = Image::new(
+            Extent3d {
+                width: width as u32,
+                height: height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            image.texture_descriptor.format,
+        );
+        let portrait = images.add(portrait);
+        self.0.insert((atlas.id(), index), portrait.clone());
+        Some(portrait)
+    }
+}
+
+/// The column of portraits down the right edge of the screen.
+#[derive(Component)]
+struct TurnStrip;
+
+pub struct TurnsPlugin;
+impl Plugin for TurnsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TurnBased>()
+            .init_resource::<TurnConfig>()
+            .init_resource::<TurnQueue>()
+            .init_resource::<Portraits>()
+            .add_simulation_event::<TurnStarted>()
+            .add_simulation_event::<TurnEnded>()
+            .add_system(toggle_turn_based)
+            .add_system(
+                run_turns
+                    .after(combat::despawn_dead)
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(log_turns)
+            .add_system(spawn_turn_strip.in_schedule(OnEnter(AppState::Game)))
+            .add_system(draw_turn_strip);
+    }
+}
+
+fn toggle_turn_based(keys: Res<Input<KeyCode>>, mut turn_based: ResMut<TurnBased>) {
+    if keys.just_pressed(KeyCode::F4) {
+        turn_based.0 = !turn_based.0;
+        info!(
+            "Turn-based mode {}.",
+            if turn_based.0 { "on" } else { "off" }
+        );
+    }
+}
+
+/// Ends the current turn once its actor has spent its points, or passed,
+/// and starts the next with its points filled up. Players pass with a key,
+/// and NPCs by doing nothing on a tick of their turn.
+#[allow(clippy::too_many_arguments)]
+fn run_turns(
+    mut commands: Commands,
+    turn_based: Res<TurnBased>,
+    config: Res<TurnConfig>,
+    collision: Res<CollisionMap>,
+    mut queue: ResMut<TurnQueue>,
+    actors: Query<(Entity, &Initiative, &Position)>,
+    mut points: Query<(Option<&mut ActionPoints>, CapabilitySources)>,
+    players: Query<Option<&StatusEffects>, With<Player>>,
+    mut died: EventReader<DiedEvent>,
+    mut started: EventWriter<TurnStarted>,
+    mut ended: EventWriter<TurnEnded>,
+) {
+    let dead: Vec<Entity> = died.iter().map(|d| d.entity).collect();
+
+    if !turn_based.0 {
+        if queue.active {
+            if let Some(entity) = queue.current().filter(|_| queue.started) {
+                ended.send(TurnEnded { entity });
+            }
+            *queue = TurnQueue::default();
+        }
+        return;
+    }
+
+    // Only changes to the order or the turn count as changes, so the strip
+    // is not redrawn every tick.
+    let before = (queue.order.clone(), queue.current);
+    let queue_changed = !queue.active;
+    let q = queue.bypass_change_detection();
+    q.active = true;
+    // The defeated are only despawned once the tick's commands run.
+    let alive = |entity: Entity| actors.contains(entity) && !dead.contains(&entity);
+
+    if let Some(entity) = q.current() {
+        // Points are only counted once the turn has started. Those left
+        // untouched since last tick were not spent on anything.
+        let (spent, idle) = match points.get_mut(entity) {
+            Ok((Some(left), sources)) if q.started => (
+                left.current <= 0. || !left.can_afford(config.cheapest(sources, &collision)),
+                !left.is_changed(),
+            ),
+            _ => (false, q.started),
+        };
+        let done = !alive(entity)
+            || spent
+            || match players.get(entity) {
+                // Rooted players cannot act, so pass.
+                Ok(effects) => effects.is_some_and(|e| e.is_rooted()),
+                Err(_) => idle,
+            };
+        if done {
+            if q.started {
+                ended.send(TurnEnded { entity });
+            }
+            q.current += 1;
+            q.started = false;
+        }
+    }
+
+    // Those already gone drop out, keeping the current actor's place.
+    let (mut index, mut removed) = (0, 0);
+    let current = q.current;
+    q.order.retain(|entity| {
+        let keep = alive(*entity);
+        if !keep && index < current {
+            removed += 1;
+        }
+        index += 1;
+        keep
+    });
+    q.current -= removed;
+
+    let key = |entity: Entity| actors.get(entity).ok().map(|(e, i, p)| turn_key(e, i, p));
+    let mut waiting: Vec<TurnKey> = (actors.iter())
+        .filter(|(entity, ..)| !dead.contains(entity))
+        .map(|(entity, initiative, position)| turn_key(entity, initiative, position))
+        .collect();
+    waiting.sort();
+    if q.current >= q.order.len() {
+        // Everyone has had their turn, so start the next round.
+        q.order = waiting.into_iter().map(|key| key.4).collect();
+        q.current = 0;
+        q.started = false;
+        if !q.order.is_empty() {
+            q.round += 1;
+        }
+    } else {
+        // Newcomers join behind the current actor, in initiative order.
+        let first_open = q.current + usize::from(q.started);
+        let joining: Vec<TurnKey> = (waiting.into_iter())
+            .filter(|k| !q.order.contains(&k.4))
+            .collect();
+        for new in joining {
+            let at = (first_open..q.order.len())
+                .find(|i| key(q.order[*i]).is_some_and(|k| k > new))
+                .unwrap_or(q.order.len());
+            q.order.insert(at, new.4);
+        }
+    }
+
+    if let Some(entity) = q.current().filter(|_| !q.started) {
+        if let Ok((left, (_, sheet, effects))) = points.get_mut(entity) {
+            let full = ActionPoints::full(config.max_points(sheet, effects));
+            match left {
+                Some(mut left) => *left = full,
+                None => {
+                    commands.entity(entity).insert(full);
+                }
+            }
+        }
+        started.send(TurnStarted { entity });
+        q.started = true;
+    }
+    if queue_changed || before != (queue.order.clone(), queue.current) {
+        queue.set_changed();
+    }
+}
+
+fn log_turns(
+    queue: Res<TurnQueue>,
+    mut started: EventReader<TurnStarted>,
+    mut ended: EventReader<TurnEnded>,
+) {
+    for turn in ended.iter() {
+        debug!("{:?} ended their turn.", turn.entity);
+    }
+    for turn in started.iter() {
+        debug!("Round {}: {:?} to act.", queue.round(), turn.entity);
+    }
+}
+
+fn spawn_turn_strip(mut commands: Commands, strips: Query<(), With<TurnStrip>>) {
+    if !strips.is_empty() {
+        return;
+    }
+    commands.spawn((
+        TurnStrip,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(PORTRAIT_BORDER * 4.),
+                    top: Val::Px(PORTRAIT_BORDER * 4.),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                gap: Size::all(Val::Px(PORTRAIT_BORDER)),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Shows the portraits of the next few actors, the current one framed
+/// over a bar of the points it has left, whenever the queue, the palette,
+/// an actor's sprite or its points change.
+#[allow(clippy::too_many_arguments)]
+fn draw_turn_strip(
+    mut commands: Commands,
+    queue: Res<TurnQueue>,
+    palette: Res<PaletteLookup>,
+    mut portraits: ResMut<Portraits>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut images: ResMut<Assets<Image>>,
+    sprites: Query<(&TextureAtlasSprite, &Handle<TextureAtlas>)>,
+    drawn: Query<(), (With<Initiative>, Added<TextureAtlasSprite>)>,
+    points: Query<Ref<ActionPoints>>,
+    mut strips: Query<(Entity, &mut Visibility), With<TurnStrip>>,
+) {
+    // Actors spawned mid-round join the queue a frame before they are drawn.
+    let spent = points.iter().any(|p| p.is_changed());
+    if !queue.is_changed() && !palette.is_changed() && drawn.is_empty() && !spent {
+        return;
+    }
+    let Ok((strip, mut visibility)) = strips.get_single_mut() else { return };
+    *visibility = if queue.is_active() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+
+    commands.entity(strip).despawn_descendants();
+    for (i, entity) in queue.upcoming().iter().take(TURN_STRIP_LENGTH).enumerate() {
+        let frame = if i == 0 {
+            palette.color(PaletteColor::Highlight).with_a(1.)
+        } else {
+            Color::NONE
+        };
+        // Actors drawn before their sheet has loaded show an empty frame.
+        let portrait = (sprites.get(*entity).ok())
+            .and_then(|(sprite, atlas)| portraits.get(atlas, sprite.index, &atlases, &mut images));
+        let left = (points.get(*entity).ok())
+            .filter(|_| i == 0)
+            .map(|p| p.current / p.max.max(f32::EPSILON));
+        commands.entity(strip).with_children(|strip| {
+            strip
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(PORTRAIT_BORDER)),
+                        flex_direction: FlexDirection::Column,
+                        gap: Size::all(Val::Px(PORTRAIT_BORDER)),
+                        ..default()
+                    },
+                    background_color: frame.into(),
+                    ..default()
+                })
+                .with_children(|frame| {
+                    let style = Style {
+                        size: Size::all(Val::Px(PORTRAIT_SIZE)),
+                        ..default()
+                    };
+                    match portrait {
+                        Some(portrait) => {
+                            frame.spawn(ImageBundle {
+                                style,
+                                image: UiImage::new(portrait),
+                                ..default()
+                            });
+                        }
+                        None => {
+                            frame.spawn(NodeBundle { style, ..default() });
+                        }
+                    }
+                    if let Some(left) = left {
+                        frame.spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Px(PORTRAIT_SIZE * left.clamp(0., 1.)),
+                                    Val::Px(POINTS_BAR_HEIGHT),
+                                ),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        });
+                    }
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pathfinding::find_path_budgeted,
+        status::{StatusEffect, StatusKind},
+        terrain::Capabilities,
+        vectors::{Vector3Int, ORTHO_DIRECTIONS},
+    };
+
+    /// Road costing half a step east of the origin, swamp costing three
+    /// west of it, and water costing two, for swimmers, north of it.
+    fn terrain() -> CollisionMap {
+        let mut collision = CollisionMap::default();
+        for x in 1..4 {
+            collision.set_move_cost(Vector3Int::new(x, 0, 0), 0.5);
+            collision.set_move_cost(Vector3Int::new(-x, 0, 0), 3.);
+            let water = Vector3Int::new(0, x, 0);
+            collision.set_move_cost(water, 2.);
+            collision.set_requires(water, Capabilities::SWIM);
+        }
+        collision
+    }
+
+    /// Pays for each of `costs` in turn from a full turn's points, giving
+    /// what was paid for and what is left.
+    fn spend(costs: &[f32]) -> (Vec<bool>, f32) {
+        let mut world = World::new();
+        let max = TurnConfig::default().max_points(None, None);
+        let entity = world.spawn(ActionPoints::full(max)).id();
+        let paid = (costs.iter())
+            .map(|cost| {
+                let mut points = world.get_mut::<ActionPoints>(entity);
+                pay_points(&mut points, *cost)
+            })
+            .collect();
+        (paid, world.get::<ActionPoints>(entity).unwrap().current)
+    }
+
+    #[test]
+    fn steps_pay_what_their_cells_cost() {
+        let collision = terrain();
+        let config = TurnConfig::default();
+        let cost = |x, y| collision.step_cost(Vector3Int::new(x, y, 0), Capabilities::NONE);
+
+        // Three steps along the road and one off it leave 1.5 of 4: too
+        // little for the swamp or an attack, which are refused without
+        // taking anything, but enough for a step back onto the road.
+        let costs = [cost(1, 0), cost(2, 0), cost(3, 0), cost(3, 1)];
+        assert_eq!(costs, [0.5, 0.5, 0.5, 1.]);
+        let (paid, left) = spend(&[&costs[..], &[cost(-1, 0), config.attack_cost, 0.5]].concat());
+        assert_eq!(paid, [true, true, true, true, false, false, true]);
+        assert_eq!(left, 1.);
+
+        // Two steps along the road, one south onto open ground and an
+        // attack spend all 4 exactly, leaving nothing for even a road step.
+        let costs = [cost(1, 0), cost(2, 0), cost(0, -1), config.attack_cost];
+        let (paid, left) = spend(&[&costs[..], &[0.5]].concat());
+        assert_eq!(paid, [true, true, true, true, false]);
+        assert_eq!(left, 0.);
+    }
+
+    #[test]
+    fn turns_end_when_the_cheapest_action_is_out_of_reach() {
+        let collision = terrain();
+        let config = TurnConfig::default();
+        let walker = (None, None, None);
+        let flier = (Some(&Capabilities::FLY), None, None);
+        // The road is the cheapest thing to do for walkers, but fliers pay
+        // 1 a step everywhere.
+        assert_eq!(config.cheapest(walker, &collision), 0.5);
+        assert_eq!(config.cheapest(flier, &collision), 1.);
+        let points = ActionPoints {
+            max: 4.,
+            current: 0.75,
+        };
+        assert!(points.can_afford(config.cheapest(walker, &collision)));
+        assert!(!points.can_afford(config.cheapest(flier, &collision)));
+    }
+
+    #[test]
+    fn haste_and_slow_scale_points_down_to_whole_ones() {
+        let config = TurnConfig::default();
+        let effects = |kind, magnitude| {
+            StatusEffects(vec![StatusEffect {
+                kind,
+                magnitude,
+                remaining: 3,
+            }])
+        };
+        let points = |effects: StatusEffects| config.max_points(None, Some(&effects));
+        assert_eq!(config.max_points(None, None), 4.);
+        assert_eq!(points(effects(StatusKind::Haste, 2.)), 8.);
+        assert_eq!(points(effects(StatusKind::Haste, 1.5)), 6.);
+        // 4 / 1.5 is 2.67.
+        assert_eq!(points(effects(StatusKind::Slow, 1.5)), 2.);
+        assert_eq!(points(effects(StatusKind::Slow, 3.)), 1.);
+        // The sheet counts effects in already, so they are not counted twice.
+        let sheet = StatSheet {
+            interval_scale: 0.5,
+            ..default()
+        };
+        let slowed = effects(StatusKind::Slow, 3.);
+        assert_eq!(config.max_points(Some(&sheet), Some(&slowed)), 8.);
+    }
+
+    #[test]
+    fn reach_is_what_points_pay_for_over_the_terrain() {
+        let collision = terrain();
+        let reach = |capabilities| {
+            find_path_budgeted(
+                Vector3Int::default(),
+                |v| ORTHO_DIRECTIONS.map(|dir| v + dir),
+                |_, to| !collision.is_blocked_for(to, capabilities),
+                |_, to| collision.step_cost(to, capabilities),
+                TurnConfig::default().action_points,
+            )
+        };
+        let cost_of = |reached: &[(Vector3Int, f32)], x, y| {
+            let v = Vector3Int::new(x, y, 0);
+            let found = reached.iter().find(|(cell, _)| *cell == v);
+            found.map(|(_, cost)| *cost)
+        };
+
+        let walker = reach(Capabilities::NONE);
+        assert_eq!(cost_of(&walker, 3, 0), Some(1.5));
+        assert_eq!(cost_of(&walker, 3, 2), Some(3.5));
+        assert_eq!(cost_of(&walker, -1, 0), Some(3.));
+        assert_eq!(cost_of(&walker, -2, 0), None);
+        assert_eq!(cost_of(&walker, 0, 1), None);
+
+        // Swimmers pay 2 a cell of water, and 4 for two.
+        let swimmer = reach(Capabilities::SWIM);
+        assert_eq!(cost_of(&swimmer, 0, 2), Some(4.));
+        assert_eq!(cost_of(&swimmer, 0, 3), None);
+    }
+}
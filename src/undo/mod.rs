@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    board::BoardCommands,
+    collision::{covered_cells, Footprint},
+    combat::{DamageEvent, Health},
+    flags::GameFlags,
+    get_world_position,
+    input::{Action, ActionInput},
+    objects::{Door, ObjectSprite, SetsFlag, Trigger},
+    player::{MoveTween, MovementState, PlayerStepStarted},
+    projection::GridProjection,
+    puzzles::BlockPushedEvent,
+    simulation::SimulationSet,
+    vectors::Vector3Int,
+    AppState, Position,
+};
+
+pub const DEFAULT_UNDO_DEPTH: usize = 100;
+
+/// A change that undo knows how to revert.
+///
+/// Pressure plate flags are not recorded: they follow from where blocks and
+/// players stand, so restoring positions restores them too.
+pub enum UndoRecord {
+    Moved {
+        entity: Entity,
+        from: Vector3Int,
+    },
+    /// Closing the door again also clears the flag it set.
+    DoorOpened {
+        entity: Entity,
+    },
+    /// Tiles an editor tool changed, with the index each had before, or
+    /// `None` where there was no tile.
+    TilesPainted {
+        before: Vec<(Vector3Int, Option<usize>)>,
+    },
+    /// Only rewinding records these; undo clears its history on damage.
+    HealthChanged {
+        entity: Entity,
+        before: u32,
+    },
+}
+
+/// Groups of changes, newest last, reverted a whole group at a time.
+///
+/// Changes that cannot be reverted, such as taking damage or firing a
+/// trigger (which may consume an item), clear the history instead.
+#[derive(Resource)]
+pub struct UndoHistory {
+    pub depth: usize,
+    groups: VecDeque<Vec<UndoRecord>>,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        UndoHistory {
+            depth: DEFAULT_UNDO_DEPTH,
+            groups: VecDeque::new(),
+        }
+    }
+}
+
+impl UndoHistory {
+    /// Starts a new group for the records that follow.
+    pub fn begin(&mut self) {
+        if self.groups.back().is_some_and(|g| g.is_empty()) {
+            return;
+        }
+        self.groups.push_back(Vec::new());
+        while self.groups.len() > self.depth.max(1) {
+            self.groups.pop_front();
+        }
+    }
+
+    pub fn record(&mut self, record: UndoRecord) {
+        if self.groups.is_empty() {
+            self.begin();
+        }
+        if let Some(group) = self.groups.back_mut() {
+            group.push(record);
+        }
+    }
+
+    /// Removes the newest non-empty group.
+    pub fn pop(&mut self) -> Option<Vec<UndoRecord>> {
+        while let Some(group) = self.groups.pop_back() {
+            if !group.is_empty() {
+                return Some(group);
+            }
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.iter().all(|g| g.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+}
+
+pub struct UndoPlugin;
+impl Plugin for UndoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UndoHistory>()
+            .add_system(clear_history.in_schedule(OnEnter(AppState::Game)))
+            .add_systems(
+                (record_moves, record_doors, undo_last)
+                    .chain()
+                    .in_set(SimulationSet::React)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            // Fired triggers are only seen as removed components until the
+            // end of the frame, which a tick may not reach.
+            .add_system(flush_history.in_set(OnUpdate(AppState::Game)));
+    }
+}
+
+fn clear_history(mut history: ResMut<UndoHistory>) {
+    history.clear();
+}
+
+/// Everything a single input moves (players and the blocks they push) is
+/// one group.
+fn record_moves(
+    mut steps: EventReader<PlayerStepStarted>,
+    mut pushes: EventReader<BlockPushedEvent>,
+    mut history: ResMut<UndoHistory>,
+) {
+    let mut moves: Vec<(Entity, Vector3Int)> = steps.iter().map(|s| (s.entity, s.from)).collect();
+    moves.extend(pushes.iter().map(|p| (p.entity, p.from)));
+    if moves.is_empty() {
+        return;
+    }
+
+    history.begin();
+    for (entity, from) in moves {
+        history.record(UndoRecord::Moved { entity, from });
+    }
+}
+
+/// Opening a door is its own group.
+fn record_doors(doors: Query<(Entity, &Door), Changed<Door>>, mut history: ResMut<UndoHistory>) {
+    for (entity, door) in doors.iter() {
+        if door.open {
+            history.begin();
+            history.record(UndoRecord::DoorOpened { entity });
+        }
+    }
+}
+
+fn flush_history(
+    mut damage: EventReader<DamageEvent>,
+    mut fired: RemovedComponents<Trigger>,
+    mut history: ResMut<UndoHistory>,
+) {
+    let damaged = damage.iter().count() > 0;
+    let triggered = fired.iter().count() > 0;
+    if (damaged || triggered) && !history.is_empty() {
+        warn!("Undo history cleared by a change that cannot be undone.");
+        history.clear();
+    }
+}
+
+/// Everything an `UndoRecord` can touch, to revert records with.
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+pub struct UndoTargets<'w, 's> {
+    movers: Query<
+        'w,
+        's,
+        (
+            &'static mut Position,
+            Option<&'static mut Transform>,
+            Option<&'static mut MoveTween>,
+            Option<&'static mut MovementState>,
+        ),
+        Without<Door>,
+    >,
+    doors: Query<
+        'w,
+        's,
+        (
+            &'static mut Door,
+            &'static Position,
+            Option<&'static Footprint>,
+            &'static ObjectSprite,
+            &'static mut TextureAtlasSprite,
+            Option<&'static SetsFlag>,
+        ),
+    >,
+    healths: Query<'w, 's, &'static mut Health>,
+    flags: ResMut<'w, GameFlags>,
+    board: BoardCommands<'w, 's>,
+    projection: Res<'w, GridProjection>,
+}
+
+impl<'w, 's> UndoTargets<'w, 's> {
+    /// Puts back what `record` changed. Records of entities since
+    /// despawned are skipped.
+    pub fn revert(&mut self, record: UndoRecord) {
+        match record {
+            UndoRecord::Moved { entity, from } => {
+                let Ok((mut position, transform, tween, state)) = self.movers.get_mut(entity)
+                else {
+                    return;
+                };
+                position.v = from;
+                let v = get_world_position(&position, &self.projection);
+                if let Some(mut transform) = transform {
+                    transform.translation = v;
+                }
+                if let Some(mut tween) = tween {
+                    *tween = MoveTween::at(v);
+                }
+                if let Some(mut state) = state {
+                    state.cancel_step();
+                }
+            }
+            UndoRecord::DoorOpened { entity } => {
+                let Ok((mut door, position, footprint, closed, mut sprite, sets)) =
+                    self.doors.get_mut(entity)
+                else {
+                    return;
+                };
+                door.open = false;
+                sprite.index = closed.0;
+                for cell in covered_cells(position.v, footprint) {
+                    self.board.block(cell);
+                }
+                if let Some(SetsFlag(flag)) = sets {
+                    self.flags.set(flag, false);
+                }
+            }
+            UndoRecord::TilesPainted { before } => {
+                for (v, index) in before {
+                    self.board.paint(v, index);
+                }
+            }
+            UndoRecord::HealthChanged { entity, before } => {
+                if let Ok(mut health) = self.healths.get_mut(entity) {
+                    health.current = before;
+                }
+            }
+        }
+    }
+}
+
+fn undo_last(input: ActionInput, mut history: ResMut<UndoHistory>, mut targets: UndoTargets) {
+    if !input.any_just_pressed(Action::Undo) {
+        return;
+    }
+    let Some(group) = history.pop() else {
+        info!("Nothing to undo.");
+        return;
+    };
+
+    for record in group.into_iter().rev() {
+        targets.revert(record);
+    }
+}
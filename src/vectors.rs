@@ -3,6 +3,8 @@ use std::{
     ops::{Add, AddAssign, Div, Mul, Sub, SubAssign},
 };
 
+use bevy::prelude::{Resource, Vec2};
+
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Vector3Int {
     pub x: i32,
@@ -15,12 +17,39 @@ impl Vector3Int {
     pub const DOWN: Vector3Int = Vector3Int { x: 0, y: -1, z: 0 };
     pub const LEFT: Vector3Int = Vector3Int { x: -1, y: 0, z: 0 };
     pub const RIGHT: Vector3Int = Vector3Int { x: 1, y: 0, z: 0 };
-    pub fn new(x: i32, y: i32, z: i32) -> Vector3Int {
+    pub const fn new(x: i32, y: i32, z: i32) -> Vector3Int {
         Vector3Int { x, y, z }
     }
     pub fn manhattan(&self, other: Vector3Int) -> i32 {
         (self.x - other.x).abs() + (self.y - other.y).abs()
     }
+    /// Steps between two hex cells in axial coordinates (x = q, y = r).
+    pub fn hex_distance(&self, other: Vector3Int) -> i32 {
+        let (dq, dr) = (self.x - other.x, self.y - other.y);
+        (dq.abs() + dr.abs() + (dq + dr).abs()) / 2
+    }
+    /// The cells a straight line from here to `other` passes through, both
+    /// ends included, by Bresenham's algorithm. Keeps this cell's z.
+    pub fn line_to(&self, other: Vector3Int) -> Vec<Vector3Int> {
+        let (dx, dy) = ((other.x - self.x).abs(), -(other.y - self.y).abs());
+        let (sx, sy) = ((other.x - self.x).signum(), (other.y - self.y).signum());
+        let (mut x, mut y) = (self.x, self.y);
+        let mut error = dx + dy;
+        let mut cells = vec![*self];
+        while (x, y) != (other.x, other.y) {
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+            cells.push(Vector3Int::new(x, y, self.z));
+        }
+        cells
+    }
 }
 
 impl Add for Vector3Int {
@@ -97,3 +126,204 @@ pub const ORTHO_DIRECTIONS: [Vector3Int; 4] = [
     Vector3Int::LEFT,
     Vector3Int::RIGHT,
 ];
+
+/// A `width` by `height` block of cells with its min corner at (x, y).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GridRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl GridRect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> GridRect {
+        GridRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The smallest rect covering both corner cells, in either order.
+    pub fn from_corners(a: Vector3Int, b: Vector3Int) -> GridRect {
+        GridRect::new(
+            a.x.min(b.x),
+            a.y.min(b.y),
+            (a.x - b.x).abs() + 1,
+            (a.y - b.y).abs() + 1,
+        )
+    }
+
+    pub fn contains(&self, v: Vector3Int) -> bool {
+        (self.x..self.x + self.width).contains(&v.x)
+            && (self.y..self.y + self.height).contains(&v.y)
+    }
+
+    /// The cells both rects cover, if any.
+    pub fn intersection(&self, other: GridRect) -> Option<GridRect> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let top = (self.y + self.height).min(other.y + other.height);
+        (right > x && top > y).then(|| GridRect::new(x, y, right - x, top - y))
+    }
+
+    /// The smallest rect covering both.
+    pub fn union(&self, other: GridRect) -> GridRect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let top = (self.y + self.height).max(other.y + other.height);
+        GridRect::new(x, y, right - x, top - y)
+    }
+
+    /// The cells covered once offset by `origin`, all on `origin.z`.
+    pub fn cells(&self, origin: Vector3Int) -> impl Iterator<Item = Vector3Int> {
+        let rect = *self;
+        (0..rect.height).flat_map(move |dy| {
+            (0..rect.width).map(move |dx| {
+                Vector3Int::new(origin.x + rect.x + dx, origin.y + rect.y + dy, origin.z)
+            })
+        })
+    }
+}
+
+/// The six neighbours of an axial hex cell, with r running up the board.
+pub const HEX_DIRECTIONS: [Vector3Int; 6] = [
+    Vector3Int { x: 1, y: 0, z: 0 },
+    Vector3Int { x: 0, y: 1, z: 0 },
+    Vector3Int { x: -1, y: 1, z: 0 },
+    Vector3Int { x: -1, y: 0, z: 0 },
+    Vector3Int { x: 0, y: -1, z: 0 },
+    Vector3Int { x: 1, y: -1, z: 0 },
+];
+
+#[derive(serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HexOrientation {
+    /// Rows of hexes, each shifted half a hex from the last.
+    PointyTop,
+    /// Columns of hexes, each shifted half a hex from the last.
+    FlatTop,
+}
+
+impl HexOrientation {
+    /// The neighbour directions clockwise, starting from the left for
+    /// pointy-top hexes and from the top-left for flat-top ones.
+    pub fn clockwise(self) -> [Vector3Int; 6] {
+        let [e, ne, nw, w, sw, se] = HEX_DIRECTIONS;
+        match self {
+            HexOrientation::PointyTop => [w, nw, ne, e, se, sw],
+            // Flat-top hexes have their neighbours a sixth of a turn round.
+            HexOrientation::FlatTop => [nw, ne, e, se, sw, w],
+        }
+    }
+
+    /// Centre of an axial position for hexes of circumradius `size`.
+    pub fn hex_to_world(self, hex: Vec2, size: f32) -> Vec2 {
+        let sqrt3 = 3f32.sqrt();
+        match self {
+            HexOrientation::PointyTop => {
+                Vec2::new(sqrt3 * (hex.x + hex.y / 2.), 1.5 * hex.y) * size
+            }
+            HexOrientation::FlatTop => Vec2::new(1.5 * hex.x, sqrt3 * (hex.y + hex.x / 2.)) * size,
+        }
+    }
+
+    /// The (fractional) axial position at a world point.
+    pub fn world_to_hex(self, world: Vec2, size: f32) -> Vec2 {
+        let sqrt3 = 3f32.sqrt();
+        let p = world / size;
+        match self {
+            HexOrientation::PointyTop => {
+                let r = p.y / 1.5;
+                Vec2::new(p.x / sqrt3 - r / 2., r)
+            }
+            HexOrientation::FlatTop => {
+                let q = p.x / 1.5;
+                Vec2::new(q, p.y / sqrt3 - q / 2.)
+            }
+        }
+    }
+
+    /// The axial cell for an offset column and row, with rows counting up,
+    /// so that a rectangle of offset cells stays a rectangle on screen.
+    pub fn offset_to_axial(self, col: i32, row: i32) -> (i32, i32) {
+        match self {
+            HexOrientation::PointyTop => (col - row.div_euclid(2), row),
+            HexOrientation::FlatTop => (col, row - col.div_euclid(2)),
+        }
+    }
+
+    /// The offset column and row of an axial cell, undoing
+    /// `offset_to_axial`.
+    pub fn axial_to_offset(self, q: i32, r: i32) -> (i32, i32) {
+        match self {
+            HexOrientation::PointyTop => (q + r.div_euclid(2), r),
+            HexOrientation::FlatTop => (q, r + q.div_euclid(2)),
+        }
+    }
+}
+
+/// The hex cell containing a fractional axial position.
+pub fn hex_round(hex: Vec2) -> (i32, i32) {
+    let (q, r, s) = (hex.x, hex.y, -hex.x - hex.y);
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    // Rounding can break q + r + s = 0; fix whichever moved the most.
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+/// One of the four straight directions on the board, as written in map data.
+#[derive(serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// The step one cell in this direction.
+    pub fn step(self) -> Vector3Int {
+        match self {
+            Direction::Up => Vector3Int::UP,
+            Direction::Down => Vector3Int::DOWN,
+            Direction::Left => Vector3Int::LEFT,
+            Direction::Right => Vector3Int::RIGHT,
+        }
+    }
+}
+
+/// The shape of board cells, which decides their neighbours.
+#[derive(serde::Deserialize, Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GridKind {
+    #[default]
+    Square,
+    /// Hex cells in axial coordinates (x = q, y = r).
+    Hex { orientation: HexOrientation },
+}
+
+impl GridKind {
+    pub fn directions(&self) -> &'static [Vector3Int] {
+        match self {
+            GridKind::Square => &ORTHO_DIRECTIONS,
+            GridKind::Hex { .. } => &HEX_DIRECTIONS,
+        }
+    }
+
+    /// Steps between two cells, ignoring z.
+    pub fn distance(&self, a: Vector3Int, b: Vector3Int) -> i32 {
+        match self {
+            GridKind::Square => a.manhattan(b),
+            GridKind::Hex { .. } => a.hex_distance(b),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    accessibility::{PaletteColor, PaletteLookup},
+    collision::{CollisionFlags, CollisionMap},
+    difficulty::DifficultyModifiers,
+    editor::EditorState,
+    equipment::StatSheet,
+    fov, map_meta,
+    player::Player,
+    projectiles::clear_shot,
+    tint::TileTint,
+    vectors::{GridKind, Vector3Int},
+    CurrentBoard, Position,
+};
+
+/// Cells a player sees in every direction before equipment.
+pub const PLAYER_VISION: u32 = 6;
+
+/// Whether tiles out of every player's sight are darkened. Off in the
+/// editor whatever this says.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct FogOfWar(pub bool);
+
+impl Default for FogOfWar {
+    fn default() -> Self {
+        FogOfWar(true)
+    }
+}
+
+/// How sight past walls is worked out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SightMethod {
+    /// Enemies see along straight lines, which walls can look past at
+    /// some angles and not others, and players see every cell in range.
+    #[default]
+    Lines,
+    /// Symmetric shadowcasting, for the fog and enemies alike, so that
+    /// whoever is seen sees back. Lines still, on hex grids.
+    Shadowcasting,
+}
+
+#[derive(Resource, Clone, Debug, Default)]
+pub struct VisionConfig {
+    pub method: SightMethod,
+}
+
+impl VisionConfig {
+    /// Whether sight is by shadowcasting on a `grid` board.
+    pub fn shadowcasting(&self, grid: GridKind) -> bool {
+        self.method == SightMethod::Shadowcasting && grid == GridKind::Square
+    }
+}
+
+pub struct VisionPlugin;
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FogOfWar>()
+            .init_resource::<VisionConfig>()
+            .add_systems(
+                // So that tiles the ambient light just tinted are fogged,
+                // not tinted over.
+                (apply_system_buffers, update_fog)
+                    .chain()
+                    .after(map_meta::apply_ambient),
+            );
+    }
+}
+
+/// Whether `v` is within `vision` cells of `from`, on any layer.
+pub fn in_sight(
+    current: &CurrentBoard,
+    grid: GridKind,
+    from: Vector3Int,
+    vision: u32,
+    v: Vector3Int,
+) -> bool {
+    let flat = |v: Vector3Int| Vector3Int::new(v.x, v.y, 0);
+    current.distance(grid, flat(from), flat(v)) <= vision as i32
+}
+
+/// Whether `from` sees `to` past whatever blocks sight, by the chosen
+/// method. Shadowcasting sees the same both ways; lines may not.
+pub fn sees(
+    config: &VisionConfig,
+    grid: GridKind,
+    current: &CurrentBoard,
+    collision: &CollisionMap,
+    from: Vector3Int,
+    to: Vector3Int,
+) -> bool {
+    if !config.shadowcasting(grid) {
+        return clear_shot(from, to, current, collision);
+    }
+    let to = from + current.delta(from, Vector3Int::new(to.x, to.y, from.z));
+    let blocks = |v: Vector3Int| collision.blocks(current.wrap(v), CollisionFlags::BLOCK_SIGHT);
+    fov::sees(from, to, blocks)
+}
+
+/// Fogs every tile further from each player than their `StatSheet` and
+/// the difficulty let them see, or out of their sight when shadowcasting,
+/// redone whenever a player moves or their sight changes, and whenever the
+/// board or, when shadowcasting, what blocks sight does.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn update_fog(
+    mut commands: Commands,
+    fog: Res<FogOfWar>,
+    config: Res<VisionConfig>,
+    editor: Res<EditorState>,
+    current: Res<CurrentBoard>,
+    collision: Res<CollisionMap>,
+    palette: Res<PaletteLookup>,
+    grid: Res<GridKind>,
+    modifiers: Res<DifficultyModifiers>,
+    players: Query<(&Position, &StatSheet), With<Player>>,
+    moved: Query<(), (With<Player>, Or<(Changed<Position>, Changed<StatSheet>)>)>,
+    mut tints: Query<&mut TileTint>,
+) {
+    let shadowcasting = config.shadowcasting(*grid);
+    let blocked = config.is_changed() || (shadowcasting && collision.is_changed());
+    let changed = fog.is_changed() || editor.is_changed() || current.is_changed() || blocked;
+    if !changed && !palette.is_changed() && !modifiers.is_changed() && moved.is_empty() {
+        return;
+    }
+
+    let hidden = palette.color(PaletteColor::Fog);
+    let lit = !fog.0 || editor.active;
+    let flat = |v: Vector3Int| current.wrap(Vector3Int::new(v.x, v.y, 0));
+    // Every cell in range that some player sees past walls, on any layer.
+    let mut visible = HashSet::new();
+    if shadowcasting && !lit {
+        for (position, sheet) in players.iter() {
+            let vision = modifiers.vision(sheet.vision);
+            let blocks = |v| collision.blocks(current.wrap(v), CollisionFlags::BLOCK_SIGHT);
+            let cells = fov::compute(position.v, vision, blocks).into_iter();
+            let in_range = cells.filter(|v| in_sight(&current, *grid, position.v, vision, *v));
+            visible.extend(in_range.map(flat));
+        }
+    }
+    for (v, entity) in current.tiles.iter() {
+        let seen = lit
+            || match shadowcasting {
+                true => visible.contains(&flat(*v)),
+                false => players.iter().any(|(position, sheet)| {
+                    let vision = modifiers.vision(sheet.vision);
+                    in_sight(&current, *grid, position.v, vision, *v)
+                }),
+            };
+        let color = if seen { Color::WHITE } else { hidden };
+        match tints.get_mut(*entity) {
+            Ok(mut tint) => {
+                if tint.fog != color {
+                    tint.fog = color;
+                }
+            }
+            Err(_) if !seen => {
+                commands.entity(*entity).insert(TileTint {
+                    fog: color,
+                    ..default()
+                });
+            }
+            Err(_) => {}
+        }
+    }
+}
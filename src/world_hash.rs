@@ -0,0 +1,268 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::Occupancy, combat::Health, player::Player, replay::LogicalClock, CurrentBoard,
+    Position, Tile,
+};
+
+/// Ticks between the hashes a host sends its client to check against.
+#[cfg(feature = "net")]
+pub const DESYNC_CHECK_INTERVAL: u64 = 60;
+
+/// A tick's replicated hash, from the side running the simulation.
+/// Received by the other side, it is checked against the hash of its own
+/// world.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DesyncCheckEvent {
+    pub tick: u64,
+    pub hash: u64,
+}
+
+/// What `world_hash` covers of each actor: anything placed on the board
+/// that is not a tile.
+pub type HashedActor<'a> = (&'a Position, Option<&'a Health>);
+
+/// A hash of the board: each cell's tile, each cell stood on, and where
+/// every actor is and how healthy. Each of those is hashed on its own and
+/// the hashes summed, wrapping, so the order entities and cells are stored
+/// in does not matter, and two identical actors on one cell do not cancel
+/// out as they would XORed. Hashed with fixed keys, so a build gives the
+/// same hash for the same world on every run and on every machine.
+pub fn world_hash(
+    board: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    occupancy: &Occupancy,
+    actors: &Query<HashedActor, Without<Tile>>,
+) -> u64 {
+    let occupied_hashes = (occupancy.cells()).map(|v| hash_one((1u8, v.x, v.y, v.z)));
+    let actor_hashes = actors.iter().map(|(position, health)| {
+        let health = health.map(|h| (h.current, h.max));
+        let v = position.v;
+        hash_one((2u8, v.x, v.y, v.z, health))
+    });
+    (occupied_hashes.chain(actor_hashes)).fold(tiles_hash(board, tiles), u64::wrapping_add)
+}
+
+/// A hash of what a client mirrors of its host's world: each cell's tile,
+/// each cell a player stands on and where every player is, combined as
+/// `world_hash` combines them. Creatures, blocks and health are left out,
+/// since only tiles and players are sent over, so the host's and client's
+/// hashes agree while the two are in step.
+pub fn replicated_hash(
+    board: &CurrentBoard,
+    tiles: &Query<&Tile>,
+    occupancy: &Occupancy,
+    players: &Query<(Entity, &Position), With<Player>>,
+) -> u64 {
+    let occupied_hashes = (occupancy.cells())
+        .filter(|v| occupancy.get(*v).is_some_and(|e| players.contains(e)))
+        .map(|v| hash_one((1u8, v.x, v.y, v.z)));
+    let player_hashes = (players.iter()).map(|(_, p)| hash_one((3u8, p.v.x, p.v.y, p.v.z)));
+    (occupied_hashes.chain(player_hashes)).fold(tiles_hash(board, tiles), u64::wrapping_add)
+}
+
+fn tiles_hash(board: &CurrentBoard, tiles: &Query<&Tile>) -> u64 {
+    (board.tiles.iter())
+        .filter_map(|(v, entity)| {
+            let tile = tiles.get(*entity).ok()?;
+            Some(hash_one((0u8, v.x, v.y, v.z, tile.i as u64)))
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+fn hash_one(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Everything `world_hash` reads, for systems that need the hash.
+#[derive(SystemParam)]
+pub struct WorldHash<'w, 's> {
+    current: Res<'w, CurrentBoard>,
+    occupancy: Res<'w, Occupancy>,
+    tiles: Query<'w, 's, &'static Tile>,
+    actors: Query<'w, 's, HashedActor<'static>, Without<Tile>>,
+    players: Query<'w, 's, (Entity, &'static Position), With<Player>>,
+}
+
+impl<'w, 's> WorldHash<'w, 's> {
+    pub fn hash(&self) -> u64 {
+        world_hash(&self.current, &self.tiles, &self.occupancy, &self.actors)
+    }
+
+    pub fn replicated(&self) -> u64 {
+        replicated_hash(&self.current, &self.tiles, &self.occupancy, &self.players)
+    }
+}
+
+pub struct WorldHashPlugin;
+impl Plugin for WorldHashPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DesyncCheckEvent>().add_system(log_hash);
+    }
+}
+
+/// Debug key: F1 logs the world hash at the current tick.
+fn log_hash(keys: Res<Input<KeyCode>>, clock: Res<LogicalClock>, hash: WorldHash) {
+    if keys.just_pressed(KeyCode::F1) {
+        info!("World hash at tick {}: {:#018x}.", clock.tick, hash.hash());
+    }
+}
+
+/// How the hashes received have compared with this world's so far.
+#[cfg(feature = "net")]
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct SyncStatus {
+    /// Hashes checked.
+    pub checks: u32,
+    /// Times this world fell out of step.
+    pub desyncs: u32,
+    pub desynced: bool,
+}
+
+/// Checks the hashes received against this world's replicated hash,
+/// warning on falling out of step and again on coming back into it. Added
+/// by whichever layer receives them, once what arrived with them is
+/// applied.
+#[cfg(feature = "net")]
+pub fn check_desync(
+    mut checks: EventReader<DesyncCheckEvent>,
+    hash: WorldHash,
+    mut status: ResMut<SyncStatus>,
+) {
+    let Some(check) = checks.iter().last() else { return };
+    let local = hash.replicated();
+    let desynced = local != check.hash;
+    if desynced && !status.desynced {
+        warn!(
+            "Out of sync at tick {}: hash {:#018x} here, {:#018x} there.",
+            check.tick, local, check.hash
+        );
+        status.desyncs += 1;
+    } else if !desynced && status.desynced {
+        info!("Back in sync at tick {}.", check.tick);
+    }
+    status.checks += 1;
+    status.desynced = desynced;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::vectors::Vector3Int;
+
+    /// Tiles along a row and two actors standing on them, spawned in
+    /// reverse when `reversed`, as loading the same map again may.
+    fn world(reversed: bool) -> World {
+        let mut world = World::new();
+        let mut current = CurrentBoard::default();
+        let mut occupancy = Occupancy::default();
+        let mut tiles: Vec<(Vector3Int, usize)> = (0..6)
+            .map(|x| (Vector3Int::new(x, 0, 0), 1 + x as usize % 2))
+            .collect();
+        let mut actors = vec![(Vector3Int::new(1, 0, 4), 3), (Vector3Int::new(4, 0, 4), 5)];
+        if reversed {
+            tiles.reverse();
+            actors.reverse();
+        }
+        for (v, i) in tiles {
+            current.tiles.insert(v, world.spawn(Tile { i }).id());
+        }
+        for (v, health) in actors {
+            let entity = world.spawn((Position { v }, Health::new(health))).id();
+            occupancy.insert(entity, [v]);
+        }
+        world.insert_resource(current);
+        world.insert_resource(occupancy);
+        world
+    }
+
+    fn hash(world: &mut World) -> u64 {
+        let mut state: SystemState<WorldHash> = SystemState::new(world);
+        state.get(world).hash()
+    }
+
+    #[test]
+    fn the_same_world_hashes_the_same() {
+        let mut built = world(false);
+        let first = hash(&mut built);
+        assert_eq!(hash(&mut built), first);
+        // Whatever order its entities and cells were stored in.
+        assert_eq!(hash(&mut world(true)), first);
+    }
+
+    /// The hash of the world after `change`.
+    fn hash_after(change: fn(&mut World)) -> u64 {
+        let mut world = world(false);
+        change(&mut world);
+        hash(&mut world)
+    }
+
+    #[test]
+    fn any_change_to_one_cell_changes_the_hash() {
+        let first = hash(&mut world(false));
+        let tile_swapped = hash_after(|world| {
+            let entity = world.resource::<CurrentBoard>().tiles[&Vector3Int::new(2, 0, 0)];
+            world.get_mut::<Tile>(entity).unwrap().i = 7;
+        });
+        assert_ne!(tile_swapped, first);
+        let actor_moved = hash_after(|world| {
+            let mut actors = world.query::<&mut Position>();
+            actors.iter_mut(world).next().unwrap().v.x += 1;
+        });
+        assert_ne!(actor_moved, first);
+        let actor_hurt = hash_after(|world| {
+            let mut actors = world.query::<&mut Health>();
+            actors.iter_mut(world).next().unwrap().current -= 1;
+        });
+        assert_ne!(actor_hurt, first);
+        let cell_stood_on = hash_after(|world| {
+            let entity = world.spawn_empty().id();
+            let mut occupancy = world.resource_mut::<Occupancy>();
+            occupancy.insert(entity, [Vector3Int::new(5, 0, 4)]);
+        });
+        assert_ne!(cell_stood_on, first);
+    }
+
+    #[test]
+    fn the_replicated_hash_follows_only_what_clients_are_sent() {
+        let mut world = world(false);
+        let v = Vector3Int::new(0, 0, 4);
+        let player = world.spawn((Player { index: 0 }, Position { v })).id();
+        let replicated = |world: &mut World| {
+            let mut state: SystemState<WorldHash> = SystemState::new(world);
+            state.get(world).replicated()
+        };
+        let first = replicated(&mut world);
+
+        // Creatures moving and getting hurt are not sent to clients.
+        let mut creatures = world.query_filtered::<(&mut Position, &mut Health), Without<Player>>();
+        for (mut position, mut health) in creatures.iter_mut(&mut world) {
+            position.v.y += 1;
+            health.current -= 1;
+        }
+        let creature = world.spawn_empty().id();
+        let mut occupancy = world.resource_mut::<Occupancy>();
+        occupancy.insert(creature, [Vector3Int::new(5, 0, 4)]);
+        assert_eq!(replicated(&mut world), first);
+
+        world.resource_mut::<Occupancy>().insert(player, [v]);
+        assert_ne!(replicated(&mut world), first);
+        world.resource_mut::<Occupancy>().remove(player);
+        world.get_mut::<Position>(player).unwrap().v.x += 1;
+        assert_ne!(replicated(&mut world), first);
+        world.get_mut::<Position>(player).unwrap().v.x -= 1;
+        let entity = world.resource::<CurrentBoard>().tiles[&Vector3Int::new(2, 0, 0)];
+        world.get_mut::<Tile>(entity).unwrap().i = 7;
+        assert_ne!(replicated(&mut world), first);
+    }
+}
@@ -0,0 +1,221 @@
+use bevy::{
+    prelude::*, sprite::MaterialMesh2dBundle, sprite::Mesh2dHandle, transform::TransformSystem,
+};
+
+use crate::{
+    flash, materials::ScrollingMaterial, projection::GridProjection, tint, vectors::Vector3Int,
+    CurrentBoard, Position, Wrap,
+};
+
+/// How far past the board's edges, in cells, ghosts are drawn. A little
+/// over half a screen at the default zoom, so the camera on a player at
+/// the edge sees the far side.
+const GHOST_MARGIN: i32 = 24;
+
+/// A copy of a board sprite drawn `offset` cells away, across a wrapping
+/// edge, so the far side of the board shows past the seam.
+#[derive(Component)]
+pub struct Ghost {
+    source: Entity,
+    offset: Vector3Int,
+}
+
+pub struct WrapPlugin;
+impl Plugin for WrapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (spawn_ghosts, sync_ghosts)
+                .chain()
+                .in_base_set(CoreSet::PostUpdate)
+                .after(tint::apply_tile_tints)
+                // Ghosts flash along with their source.
+                .after(flash::flash_sprites)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// The offsets, in whole boards, of each copy of the board to draw.
+fn ghost_offsets(current: &CurrentBoard) -> Vec<Vector3Int> {
+    let rect = current.bounds.rect;
+    let Wrap { x, y } = current.bounds.wrap;
+    let xs: &[i32] = if x { &[-1, 0, 1] } else { &[0] };
+    let ys: &[i32] = if y { &[-1, 0, 1] } else { &[0] };
+    (ys.iter())
+        .flat_map(|dy| xs.iter().map(move |dx| (*dx, *dy)))
+        .filter(|offset| *offset != (0, 0))
+        .map(|(dx, dy)| Vector3Int::new(dx * rect.width, dy * rect.height, 0))
+        .collect()
+}
+
+/// The drawable parts of a sprite, whichever way it is drawn.
+type Drawable<'a> = (
+    Option<Ref<'a, TextureAtlasSprite>>,
+    Option<&'a Handle<TextureAtlas>>,
+    Option<Ref<'a, Sprite>>,
+    Option<&'a Handle<Image>>,
+    Option<&'a Mesh2dHandle>,
+    Option<&'a Handle<ScrollingMaterial>>,
+);
+
+/// Which of the three ways of drawing a sprite is in use.
+fn draw_kind(drawable: &Drawable) -> (bool, bool, bool) {
+    let (atlas_sprite, _, sprite, _, _, material) = drawable;
+    (atlas_sprite.is_some(), sprite.is_some(), material.is_some())
+}
+
+/// Gives every sprite on the board its ghosts when a wrapping board loads,
+/// and newly drawn sprites theirs after that.
+#[allow(clippy::type_complexity)]
+fn spawn_ghosts(
+    mut commands: Commands,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+    mut last: Local<Wrap>,
+    ghosts: Query<Entity, With<Ghost>>,
+    sources: Query<(Entity, &Transform, Drawable), (With<Position>, Without<Ghost>)>,
+    added: Query<
+        Entity,
+        (
+            With<Position>,
+            Without<Ghost>,
+            Or<(
+                Added<TextureAtlasSprite>,
+                Added<Sprite>,
+                Added<Handle<ScrollingMaterial>>,
+            )>,
+        ),
+    >,
+) {
+    let wrap = current.bounds.wrap;
+    let spawn_for: Vec<Entity> = if wrap != *last {
+        *last = wrap;
+        for ghost in ghosts.iter() {
+            commands.entity(ghost).despawn();
+        }
+        sources.iter().map(|(entity, ..)| entity).collect()
+    } else {
+        added.iter().collect()
+    };
+    if !wrap.any() {
+        return;
+    }
+
+    let offsets = ghost_offsets(&current);
+    for (source, transform, drawable) in sources.iter_many(spawn_for) {
+        let (atlas_sprite, atlas, sprite, image, mesh, material) = drawable;
+        for offset in offsets.iter().copied() {
+            let shift = projection.cell_to_world(Vec2::new(offset.x as f32, offset.y as f32));
+            let transform = Transform {
+                translation: transform.translation + shift.extend(0.),
+                ..*transform
+            };
+            let mut ghost = commands.spawn(Ghost { source, offset });
+            if let (Some(sprite), Some(atlas)) = (&atlas_sprite, atlas) {
+                ghost.insert(SpriteSheetBundle {
+                    sprite: (*sprite).clone(),
+                    texture_atlas: atlas.clone(),
+                    transform,
+                    ..default()
+                });
+            } else if let Some(sprite) = &sprite {
+                ghost.insert(SpriteBundle {
+                    sprite: (*sprite).clone(),
+                    texture: image.cloned().unwrap_or_default(),
+                    transform,
+                    ..default()
+                });
+            } else if let (Some(mesh), Some(material)) = (mesh, material) {
+                ghost.insert(MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+/// Keeps ghosts where their source is drawn, plus their offset, and hides
+/// those too far past the edge to be seen. Ghosts whose source is gone or
+/// drawn another way are despawned; `spawn_ghosts` replaces the latter.
+#[allow(clippy::type_complexity)]
+fn sync_ghosts(
+    mut commands: Commands,
+    current: Res<CurrentBoard>,
+    projection: Res<GridProjection>,
+    sources: Query<(&Position, &Transform, &Visibility, Drawable), Without<Ghost>>,
+    mut ghosts: Query<(
+        Entity,
+        &Ghost,
+        &mut Transform,
+        &mut Visibility,
+        Option<&mut TextureAtlasSprite>,
+        Option<&mut Handle<TextureAtlas>>,
+        Option<&mut Sprite>,
+        Option<&mut Handle<ScrollingMaterial>>,
+    )>,
+) {
+    let rect = current.bounds.rect;
+    // Cells past each edge, or zero inside the board.
+    let outside = |c: i32, min: i32, size: i32| (min - c).max(c - (min + size - 1)).max(0);
+
+    for (entity, ghost, mut transform, mut visibility, atlas_sprite, atlas, sprite, material) in
+        ghosts.iter_mut()
+    {
+        let Ok((position, source, source_visibility, drawable)) = sources.get(ghost.source) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        let kind = (atlas_sprite.is_some(), sprite.is_some(), material.is_some());
+        if kind != draw_kind(&drawable) {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let offset = ghost.offset;
+        let shift = projection.cell_to_world(Vec2::new(offset.x as f32, offset.y as f32));
+        let moved = Transform {
+            translation: source.translation + shift.extend(0.),
+            ..*source
+        };
+        if *transform != moved {
+            *transform = moved;
+        }
+
+        let cell = position.v + offset;
+        let near = outside(cell.x, rect.x, rect.width) <= GHOST_MARGIN
+            && outside(cell.y, rect.y, rect.height) <= GHOST_MARGIN;
+        let shown = if near {
+            *source_visibility
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+
+        let (source_atlas_sprite, source_atlas, source_sprite, _, _, source_material) = drawable;
+        if let (Some(mut ghost), Some(source)) = (atlas_sprite, source_atlas_sprite) {
+            if source.is_changed() {
+                *ghost = source.clone();
+            }
+        }
+        if let (Some(mut ghost), Some(source)) = (atlas, source_atlas) {
+            if *ghost != *source {
+                *ghost = source.clone();
+            }
+        }
+        if let (Some(mut ghost), Some(source)) = (sprite, source_sprite) {
+            if source.is_changed() {
+                *ghost = source.clone();
+            }
+        }
+        if let (Some(mut ghost), Some(source)) = (material, source_material) {
+            if *ghost != *source {
+                *ghost = source.clone();
+            }
+        }
+    }
+}
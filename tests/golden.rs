@@ -0,0 +1,411 @@
+//! Renders small fixture maps headless at a fixed camera and compares each
+//! frame with its golden image in `assets/golden`, within a per-pixel
+//! tolerance. A frame that differs is written beside its golden as
+//! `<name>.actual.png`, with `<name>.diff.png` marking the pixels out of
+//! tolerance in red, and fails the test.
+//!
+//! `cargo test --test golden --features golden-tests`
+//!
+//! With `GOLDEN_BLESS=1` the frames are written as the goldens instead;
+//! look over the new images before committing them. A missing golden is a
+//! failure, with the frame written as its `<name>.actual.png`.
+//! Without a GPU adapter the test is skipped and passes, unless
+//! `GOLDEN_REQUIRE_ADAPTER=1` is set. CI without a GPU can still run it on
+//! a software adapter, such as Mesa's lavapipe with `WGPU_BACKEND=vulkan`.
+
+use std::{
+    env, fs,
+    num::NonZeroU32,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    log::LogPlugin,
+    prelude::*,
+    render::{
+        camera::{CameraUpdateSystem, RenderTarget},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        pipelined_rendering::PipelinedRenderingPlugin,
+        render_asset::RenderAssets,
+        renderer::{RenderDevice, RenderQueue},
+        texture::{CompressedImageFormats, ImageType},
+        RenderApp, RenderSet,
+    },
+    transform::TransformSystem,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use futures_lite::future;
+use map_test::prelude::*;
+use wgpu::{
+    Backends, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+};
+
+const GOLDEN_DIR: &str = "assets/golden";
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 192;
+/// The camera looks at the cell players start on, as close as the game's
+/// own camera starts.
+const CAMERA_SCALE: f32 = 0.5;
+/// Frames into the game the frame is captured, by which time the board
+/// has loaded and been drawn.
+const CAPTURE_FRAME: u32 = 30;
+/// Frames after that to wait for the frame to be read back.
+const READ_BACK_FRAMES: u32 = 10;
+/// How far a pixel may be from the golden's, in any channel, before it
+/// counts as differing.
+const TOLERANCE: u8 = 8;
+/// How many pixels may differ before a frame fails, for the odd edge an
+/// adapter rasterizes a pixel over.
+const MAX_DIFFERING: usize = 16;
+
+/// One golden image: a map as drawn, and what is changed on it first.
+#[derive(Resource, Clone, Copy)]
+struct Case {
+    name: &'static str,
+    map: &'static str,
+    /// Without it the players are hidden, leaving the board alone.
+    player: bool,
+    /// Board `x, y` of tiles drawn flipped horizontally. Maps cannot flip
+    /// tiles themselves, so the sprites are flipped here.
+    flipped: Option<(i32, i32)>,
+}
+
+const CASES: [Case; 3] = [
+    Case {
+        name: "square",
+        map: "golden/square.json",
+        player: false,
+        flipped: None,
+    },
+    Case {
+        name: "square_player_flipped",
+        map: "golden/square.json",
+        player: true,
+        // A path corner next to the start, which looks different flipped.
+        flipped: Some((-1, 1)),
+    },
+    Case {
+        name: "iso",
+        map: "golden/iso.json",
+        player: false,
+        flipped: None,
+    },
+];
+
+/// The image the camera draws to, and its pixels once read back. Shared
+/// with the render world, and kept after the app is gone.
+#[derive(Resource, Clone, ExtractResource)]
+struct Capture {
+    image: Handle<Image>,
+    /// Whether this frame is the one read back.
+    take: bool,
+    pixels: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+#[test]
+fn frames_match_their_goldens() {
+    let bless = env::var_os("GOLDEN_BLESS").is_some();
+    if !has_adapter() {
+        assert!(
+            env::var_os("GOLDEN_REQUIRE_ADAPTER").is_none(),
+            "no GPU adapter found"
+        );
+        println!("No GPU adapter found; skipping the golden images.");
+        return;
+    }
+
+    let mut failed = Vec::new();
+    for (i, case) in CASES.into_iter().enumerate() {
+        // Only one logger can be installed per process.
+        let pixels = render(case, i == 0);
+        let golden = golden_path(case.name, "png");
+        if bless {
+            save_png(&golden, pixels);
+            println!("{}: wrote {}.", case.name, golden.display());
+            continue;
+        }
+        let Some(expected) = load_png(&golden) else {
+            save_png(&golden_path(case.name, "actual.png"), pixels);
+            eprintln!("{}: no golden at {}.", case.name, golden.display());
+            failed.push(case.name);
+            continue;
+        };
+
+        let (differing, diff) = compare(&pixels, &expected);
+        if differing <= MAX_DIFFERING {
+            println!("{}: matches ({} pixels differ).", case.name, differing);
+            continue;
+        }
+        save_png(&golden_path(case.name, "actual.png"), pixels);
+        save_png(&golden_path(case.name, "diff.png"), diff);
+        eprintln!(
+            "{}: {} pixels differ; see {}.",
+            case.name,
+            differing,
+            golden_path(case.name, "diff.png").display()
+        );
+        failed.push(case.name);
+    }
+    assert!(
+        failed.is_empty(),
+        "not matching their goldens: {}",
+        failed.join(", ")
+    );
+}
+
+/// Whether wgpu finds an adapter on the backends the game would use.
+fn has_adapter() -> bool {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or(Backends::all());
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..default()
+    });
+    future::block_on(instance.request_adapter(&default())).is_some()
+}
+
+/// Plays `case`'s map headless until `CAPTURE_FRAME`, returning the frame
+/// as RGBA.
+fn render(case: Case, log: bool) -> Vec<u8> {
+    let mut plugins = DefaultPlugins
+        .set(ImagePlugin::default_nearest())
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .disable::<WinitPlugin>()
+        // Rendering on its own thread outlives the app, and panics as it
+        // is torn down between cases.
+        .disable::<PipelinedRenderingPlugin>();
+    if !log {
+        plugins = plugins.disable::<LogPlugin>();
+    }
+
+    let mut app = App::new();
+    app.add_plugins(plugins)
+        .add_plugin(ScheduleRunnerPlugin)
+        .insert_resource(Campaign {
+            maps: vec![case.map.to_string()],
+            current: 0,
+        })
+        .add_plugin(GamePlugin)
+        .add_plugin(ExtractResourcePlugin::<Capture>::default())
+        .insert_resource(case)
+        .add_system(stage_case.in_set(OnUpdate(AppState::Game)))
+        .add_system(count_frames.in_set(OnUpdate(AppState::Game)))
+        .add_system(
+            aim_camera
+                .in_base_set(CoreSet::PostUpdate)
+                .before(CameraUpdateSystem)
+                .before(TransformSystem::TransformPropagate),
+        );
+    app.sub_app_mut(RenderApp)
+        .add_system(read_back.in_set(RenderSet::Cleanup));
+
+    let image = (app.world.resource_mut::<Assets<Image>>()).add(target_image());
+    let pixels = Arc::new(Mutex::new(None));
+    app.insert_resource(Capture {
+        image,
+        take: false,
+        pixels: pixels.clone(),
+    })
+    .run();
+    let pixels = pixels.lock().unwrap().take();
+    pixels.expect("the frame was never read back")
+}
+
+fn target_image() -> Image {
+    let size = Extent3d {
+        width: WIDTH,
+        height: HEIGHT,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.texture_descriptor.usage = TextureUsages::RENDER_ATTACHMENT
+        | TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_SRC
+        | TextureUsages::COPY_DST;
+    image
+}
+
+/// Hides the players or flips tiles, as the case asks.
+fn stage_case(
+    case: Res<Case>,
+    mut players: Query<&mut Visibility, With<Player>>,
+    mut tiles: Query<(&Position, &mut TextureAtlasSprite), With<Tile>>,
+) {
+    if !case.player {
+        for mut visibility in players.iter_mut() {
+            *visibility = Visibility::Hidden;
+        }
+    }
+    let Some((x, y)) = case.flipped else { return };
+    for (position, mut sprite) in tiles.iter_mut() {
+        if (position.v.x, position.v.y) == (x, y) && !sprite.flip_x {
+            sprite.flip_x = true;
+        }
+    }
+}
+
+/// Points every camera at the capture image from the fixed spot, after the
+/// game's own camera systems have moved it. The UI is left out, since the
+/// goldens are of the board.
+fn aim_camera(
+    mut commands: Commands,
+    capture: Res<Capture>,
+    mut cameras: Query<(
+        Entity,
+        &mut Camera,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+) {
+    for (entity, mut camera, mut transform, mut projection) in cameras.iter_mut() {
+        let aimed = matches!(&camera.target, RenderTarget::Image(image) if *image == capture.image);
+        if !aimed {
+            camera.target = RenderTarget::Image(capture.image.clone());
+            let no_ui = UiCameraConfig { show_ui: false };
+            commands.entity(entity).insert(no_ui);
+        }
+        transform.translation.x = 0.;
+        transform.translation.y = 0.;
+        projection.scale = CAMERA_SCALE;
+    }
+}
+
+fn count_frames(
+    mut frames: Local<u32>,
+    mut capture: ResMut<Capture>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frames += 1;
+    capture.take = *frames == CAPTURE_FRAME;
+    let read = capture.pixels.lock().unwrap().is_some();
+    if read || *frames > CAPTURE_FRAME + READ_BACK_FRAMES {
+        exit.send(AppExit);
+    }
+}
+
+/// Copies the capture image to a buffer once drawn, on the frame asked
+/// for, and waits for it to be read back.
+fn read_back(
+    capture: Res<Capture>,
+    images: Res<RenderAssets<Image>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    if !capture.take {
+        return;
+    }
+    let Some(image) = images.get(&capture.image) else { return };
+    let (width, height) = (image.size.x as u32, image.size.y as u32);
+    // Rows in the buffer are padded out to wgpu's alignment.
+    let row = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row = row.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("golden_read_back"),
+        size: (padded_row * height) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("golden_read_back"),
+    });
+    encoder.copy_texture_to_buffer(
+        image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, |result| {
+        result.expect("could not read the frame back");
+    });
+    device.wgpu_device().poll(Maintain::Wait);
+    let pixels = (slice.get_mapped_range().chunks(padded_row as usize))
+        .flat_map(|padded| padded[..row as usize].to_vec())
+        .collect();
+    *capture.pixels.lock().unwrap() = Some(pixels);
+}
+
+fn golden_path(name: &str, extension: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(GOLDEN_DIR);
+    dir.join(format!("{name}.{extension}"))
+}
+
+/// The golden's pixels as RGBA, if it exists and is the size rendered.
+fn load_png(path: &PathBuf) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    let image = Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+    );
+    let image = image.map_err(|e| warn!("Could not read {}: {}", path.display(), e));
+    let image = image.ok()?;
+    let size = image.texture_descriptor.size;
+    let rgba = image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb;
+    if !rgba || (size.width, size.height) != (WIDTH, HEIGHT) {
+        eprintln!("{} is not a {WIDTH}x{HEIGHT} RGBA image.", path.display());
+        return None;
+    }
+    Some(image.data)
+}
+
+fn save_png(path: &PathBuf, pixels: Vec<u8>) {
+    let size = Extent3d {
+        width: WIDTH,
+        height: HEIGHT,
+        depth_or_array_layers: 1,
+    };
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let image = Image::new(size, TextureDimension::D2, pixels, format);
+    let saved = (image.try_into_dynamic())
+        .map_err(|e| format!("{e:?}"))
+        .and_then(|image| image.save(path).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        eprintln!("Could not write {}: {}", path.display(), e);
+    }
+}
+
+/// How many pixels of `frame` are out of tolerance from `golden`'s, and an
+/// image of where: those in red, over the golden faded.
+fn compare(frame: &[u8], golden: &[u8]) -> (usize, Vec<u8>) {
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(golden.len());
+    for (actual, expected) in frame.chunks(4).zip(golden.chunks(4)) {
+        let off = (actual.iter().zip(expected)).any(|(a, e)| a.abs_diff(*e) > TOLERANCE);
+        if off {
+            differing += 1;
+            diff.extend([255, 0, 0, 255]);
+        } else {
+            diff.extend(expected[..3].iter().map(|c| c / 4));
+            diff.push(255);
+        }
+    }
+    (differing, diff)
+}
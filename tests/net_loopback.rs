@@ -0,0 +1,126 @@
+//! Runs a host and a client of the demo map headless in one process, over
+//! loopback, and checks that both end up with the same board once the
+//! host's bomb has gone off, and that the client never fell out of sync.
+//!
+//! `cargo test --test net_loopback --features net`
+#![cfg(feature = "net")]
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    log::LogPlugin,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use map_test::{
+    net::{self, BoardSnapshot, ClientLink, NetClient, NetHost, NetPlugin, SyncStatus},
+    simulation::SimulationPaused,
+    AppState, CurrentBoard, GamePlugin, Tile,
+};
+
+/// Seconds into the game each side hashes its board. The host stops its
+/// simulation once hashed, and the client waits for its last messages.
+const HOST_HASH_AT: f32 = 6.;
+const CLIENT_HASH_AT: f32 = 7.;
+/// Seconds into the game both sides exit.
+const RUN_TIME: f32 = 8.;
+
+/// The board's hash, once taken, and on the client how its checks against
+/// the host went. Shared, since the app is gone by the time it has run.
+#[derive(Resource)]
+struct BoardHash {
+    hash: Arc<Mutex<Option<u64>>>,
+    sync: Arc<Mutex<Option<SyncStatus>>>,
+    at: f32,
+}
+
+#[test]
+fn host_and_client_converge() {
+    let (accept, port) = net::listen("127.0.0.1:0").expect("could not listen on loopback");
+
+    let host = thread::spawn(move || run(NetHost::new(accept), HOST_HASH_AT, true));
+    // Let the host reach the game before the client joins.
+    thread::sleep(Duration::from_millis(500));
+    let link = ClientLink::connect(("127.0.0.1", port)).expect("could not join the host");
+    let client = thread::spawn(move || run(NetClient::new(link), CLIENT_HASH_AT, false));
+
+    let (host, _) = host.join().expect("the host panicked");
+    let (client, sync) = client.join().expect("the client panicked");
+    assert_eq!(host, client, "the boards differ");
+    let sync = sync.expect("the client kept no sync status");
+    assert!(sync.checks > 0, "the client checked no hashes");
+    assert_eq!(sync.desyncs, 0, "the client fell out of sync: {sync:?}");
+}
+
+/// Plays headless with `session` until `RUN_TIME`, returning the hash of
+/// the board at `hash_at` and any sync status at the end.
+fn run(session: impl Resource, hash_at: f32, log: bool) -> (u64, Option<SyncStatus>) {
+    let mut plugins = DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .set(RenderPlugin {
+            wgpu_settings: WgpuSettings {
+                backends: None,
+                ..default()
+            },
+        })
+        .disable::<WinitPlugin>();
+    // Only one logger can be installed per process.
+    if !log {
+        plugins = plugins.disable::<LogPlugin>();
+    }
+
+    let hash = Arc::new(Mutex::new(None));
+    let sync = Arc::new(Mutex::new(None));
+    App::new()
+        .add_plugins(plugins)
+        .add_plugin(ScheduleRunnerPlugin)
+        .insert_resource(session)
+        .add_plugin(GamePlugin)
+        .add_plugin(NetPlugin)
+        .insert_resource(BoardHash {
+            hash: hash.clone(),
+            sync: sync.clone(),
+            at: hash_at,
+        })
+        .add_system(hash_board.in_set(OnUpdate(AppState::Game)))
+        .run();
+    let hash = *hash.lock().unwrap();
+    let sync = *sync.lock().unwrap();
+    (hash.expect("the game never started"), sync)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hash_board(
+    time: Res<Time>,
+    mut started: Local<Option<f32>>,
+    board: Res<BoardHash>,
+    current: Res<CurrentBoard>,
+    tiles: Query<&Tile>,
+    sync: Option<Res<SyncStatus>>,
+    mut paused: ResMut<SimulationPaused>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let now = time.elapsed_seconds();
+    let elapsed = now - *started.get_or_insert(now);
+    let mut hash = board.hash.lock().unwrap();
+    if hash.is_none() && elapsed > board.at {
+        *hash = Some(BoardSnapshot::from_board(&current, &tiles).hash());
+        // Platforms would otherwise move on before the client hashes.
+        paused.0 = true;
+    }
+    if elapsed > RUN_TIME {
+        *board.sync.lock().unwrap() = sync.map(|s| *s);
+        exit.send(AppExit);
+    }
+}